@@ -1,6 +1,8 @@
 import json
 import os
+import socket
 from planoai.utils import convert_legacy_listeners
+from planoai.prompt_templates import validate_prompt_templates
 from jinja2 import Environment, FileSystemLoader
 import yaml
 from jsonschema import validate
@@ -13,7 +15,10 @@ SUPPORTED_PROVIDERS_WITH_BASE_URL = [
     "ollama",
     "qwen",
     "amazon_bedrock",
+    "vertex_ai",
+    "chatgpt",
     "arch",
+    "custom",
 ]
 
 SUPPORTED_PROVIDERS_WITHOUT_BASE_URL = [
@@ -33,6 +38,25 @@ SUPPORTED_PROVIDERS = (
     SUPPORTED_PROVIDERS_WITHOUT_BASE_URL + SUPPORTED_PROVIDERS_WITH_BASE_URL
 )
 
+# hosts of the predefined clusters of the providers without base_url
+PUBLIC_PROVIDER_HOSTS = {
+    "deepseek": "api.deepseek.com",
+    "groq": "api.groq.com",
+    "mistral": "api.mistral.ai",
+    "openai": "api.openai.com",
+    "gemini": "generativelanguage.googleapis.com",
+    "anthropic": "api.anthropic.com",
+    "together_ai": "api.together.xyz",
+    "xai": "api.x.ai",
+    "moonshotai": "api.moonshot.ai",
+    "zhipu": "open.bigmodel.cn",
+}
+
+# the Codex backend of ChatGPT is only served from chatgpt.com
+CHATGPT_BASE_URL = "https://chatgpt.com"
+
+FUNCTION_CALLING_ENDPOINT_NAME = "function_calling"
+
 
 def get_endpoint_and_port(endpoint, protocol):
     endpoint_tokens = endpoint.split(":")
@@ -48,6 +72,89 @@ def get_endpoint_and_port(endpoint, protocol):
         return endpoint, port
 
 
+def vertex_ai_base_url(model_provider, model_name):
+    project_id = model_provider.get("project_id")
+    region = model_provider.get("region")
+    if project_id is None or region is None:
+        raise Exception(
+            f"Provider 'vertex_ai' requires either 'base_url' or 'project_id' and 'region' to be set for model {model_name}"
+        )
+    # the global endpoint has no region in its host
+    if region == "global":
+        host = "aiplatform.googleapis.com"
+    else:
+        host = f"{region}-aiplatform.googleapis.com"
+    return f"https://{host}/v1/projects/{project_id}/locations/{region}"
+
+
+def bypasses_proxy(host, no_proxy):
+    """Whether a no_proxy entry matches the host, entries also match their subdomains"""
+    host = host.lower()
+    for entry in no_proxy:
+        entry = entry.strip().lstrip(".").lower()
+        if entry == "*" or host == entry or host.endswith("." + entry):
+            return True
+    return False
+
+
+def egress_proxy_url(model_provider, host, egress_proxy):
+    """Proxy the provider's traffic to host goes through, None when it connects directly"""
+    if model_provider.get("proxy_url"):
+        return model_provider["proxy_url"]
+    proxy_url = egress_proxy.get("proxy_url")
+    if not proxy_url or bypasses_proxy(host, egress_proxy.get("no_proxy", [])):
+        return None
+    return proxy_url
+
+
+def proxy_address(proxy_url):
+    """Address of an http proxy for the endpoint metadata of Envoy's http_11_proxy
+    transport socket, which takes an ip, so the host is resolved here"""
+    parsed = urlparse(proxy_url)
+    if parsed.scheme != "http" or not parsed.hostname:
+        raise Exception(f"proxy_url must look like http://host:port, got {proxy_url}")
+    if parsed.username or parsed.password:
+        raise Exception(f"proxy_url {proxy_url} can't carry credentials")
+    try:
+        address = socket.gethostbyname(parsed.hostname)
+    except OSError as e:
+        raise Exception(f"Failed to resolve the proxy {parsed.hostname}: {e}")
+    return {"address": address, "port": parsed.port or 80}
+
+
+def validate_upstream_tls(model_provider):
+    """Checks the tls block of a provider before it is rendered into its Envoy cluster"""
+    tls = model_provider.get("tls")
+    if not tls:
+        return
+    name = model_provider.get("name")
+    if urlparse(model_provider.get("base_url", "")).scheme != "https":
+        raise Exception(f"Model provider {name} has tls but no https base_url")
+    if bool(tls.get("client_cert")) != bool(tls.get("client_key")):
+        raise Exception(
+            f"Model provider {name} needs both tls.client_cert and tls.client_key"
+        )
+    if tls.get("subject_alt_names") and not tls.get("ca_cert"):
+        raise Exception(
+            f"Model provider {name} needs tls.ca_cert to verify tls.subject_alt_names"
+        )
+    for san in tls.get("subject_alt_names", []):
+        if "://" not in san:
+            raise Exception(
+                f"Model provider {name} tls.subject_alt_names must be URIs, e.g. spiffe://example.org/vllm, got {san}"
+            )
+    for key in ["client_cert", "client_key", "ca_cert"]:
+        path = tls.get(key)
+        if not path:
+            continue
+        if not os.path.isabs(path):
+            raise Exception(
+                f"Model provider {name} tls.{key} must be an absolute path, got {path}"
+            )
+        if not os.path.isfile(path):
+            raise Exception(f"Model provider {name} tls.{key} {path} does not exist")
+
+
 def validate_and_render_schema():
     ENVOY_CONFIG_TEMPLATE_FILE = os.getenv(
         "ENVOY_CONFIG_TEMPLATE_FILE", "envoy.template.yaml"
@@ -112,6 +219,12 @@ def validate_and_render_schema():
                 f"Duplicate agent id {agent_id}, please provide unique id for each agent"
             )
         agent_id_keys.add(agent_id)
+        # retrieval filters are run by brightstaff, which calls the vector store directly
+        if agent.get("type") == "retrieval":
+            continue
+        # stdio filters are child processes of brightstaff, not reached through envoy
+        if agent.get("transport") == "stdio":
+            continue
         agent_endpoint = agent.get("url")
 
         if agent_id and agent_endpoint:
@@ -132,6 +245,34 @@ def validate_and_render_schema():
                     "protocol": protocol,
                 }
 
+    # register the url of a self-hosted function calling model as an endpoint
+    function_calling = config_yaml.get("function_calling", {})
+    function_calling_url = function_calling.get("url")
+    if function_calling_url:
+        urlparse_result = urlparse(function_calling_url)
+        if not (urlparse_result.scheme and urlparse_result.hostname):
+            raise Exception(
+                f"Invalid function_calling url {function_calling_url}, expected scheme://host[:port]"
+            )
+        protocol = urlparse_result.scheme
+        port = urlparse_result.port
+        if port is None:
+            port = 80 if protocol == "http" else 443
+        endpoints[FUNCTION_CALLING_ENDPOINT_NAME] = {
+            "endpoint": urlparse_result.hostname,
+            "port": port,
+            "protocol": protocol,
+        }
+        function_calling["endpoint"] = FUNCTION_CALLING_ENDPOINT_NAME
+        if urlparse_result.path not in ("", "/") and "path" not in function_calling:
+            function_calling["path"] = urlparse_result.path
+
+    function_calling_endpoint = function_calling.get("endpoint")
+    if function_calling_endpoint and function_calling_endpoint not in endpoints:
+        raise Exception(
+            f"Unknown function_calling endpoint {function_calling_endpoint}, please add it in endpoints section in your arch_config.yaml file"
+        )
+
     # override the inferred clusters with the ones defined in the config
     for name, endpoint_details in endpoints.items():
         inferred_clusters[name] = endpoint_details
@@ -158,6 +299,10 @@ def validate_and_render_schema():
 
     arch_tracing = config_yaml.get("tracing", {})
 
+    egress_proxy = config_yaml.get("egress_proxy", {})
+    if egress_proxy.get("proxy_url"):
+        proxy_address(egress_proxy["proxy_url"])
+
     llms_with_endpoint = []
     llms_with_endpoint_cluster_names = set()
     updated_model_providers = []
@@ -204,6 +349,13 @@ def validate_and_render_schema():
                 )
             provider = model_name_tokens[0]
 
+            # Vertex AI is served from regional endpoints, the project and region are part
+            # of every path
+            if provider == "vertex_ai" and model_provider.get("base_url") is None:
+                model_provider["base_url"] = vertex_ai_base_url(model_provider, model_name)
+            if provider == "chatgpt" and model_provider.get("base_url") is None:
+                model_provider["base_url"] = CHATGPT_BASE_URL
+
             # Validate azure_openai and ollama provider requires base_url
             if (provider in SUPPORTED_PROVIDERS_WITH_BASE_URL) and model_provider.get(
                 "base_url"
@@ -227,6 +379,19 @@ def validate_and_render_schema():
                     f"Please provide provider interface as part of model name {model_name} using the format <provider>/<model_id>. For example, use 'openai/gpt-3.5-turbo' instead of 'gpt-3.5-turbo' "
                 )
 
+            public_host = PUBLIC_PROVIDER_HOSTS.get(provider)
+            if (
+                public_host
+                and model_provider.get("base_url") is None
+                and (
+                    egress_proxy_url(model_provider, public_host, egress_proxy)
+                    or model_provider.get("connection_pool")
+                )
+            ):
+                # proxied and pooled traffic gets its own cluster, the predefined ones
+                # connect directly with the default connection settings
+                model_provider["base_url"] = f"https://{public_host}"
+
             if model_id in model_name_keys:
                 raise Exception(
                     f"Duplicate model_id {model_id}, please provide unique model_id for each model_provider"
@@ -255,6 +420,7 @@ def validate_and_render_schema():
                 del model_provider["provider"]
             updated_model_providers.append(model_provider)
 
+            validate_upstream_tls(model_provider)
             if model_provider.get("base_url", None):
                 base_url = model_provider["base_url"]
                 urlparse_result = urlparse(base_url)
@@ -285,10 +451,37 @@ def validate_and_render_schema():
                     provider + "_" + endpoint
                 )  # make name unique by appending endpoint
                 model_provider["cluster_name"] = cluster_name
+                proxy_url = egress_proxy_url(model_provider, endpoint, egress_proxy)
+                if proxy_url:
+                    model_provider["proxy"] = proxy_address(proxy_url)
                 # Only add if cluster_name is not already present to avoid duplicates
                 if cluster_name not in llms_with_endpoint_cluster_names:
                     llms_with_endpoint.append(model_provider)
                     llms_with_endpoint_cluster_names.add(cluster_name)
+                elif any(
+                    llm["cluster_name"] == cluster_name
+                    and (
+                        llm.get("tls") != model_provider.get("tls")
+                        or llm.get("proxy") != model_provider.get("proxy")
+                        or llm.get("connection_pool")
+                        != model_provider.get("connection_pool")
+                    )
+                    for llm in llms_with_endpoint
+                ):
+                    raise Exception(
+                        f"Model providers of {endpoint} share a cluster and must have the same tls, proxy and connection_pool"
+                    )
+
+    data_residency = config_yaml.get("data_residency", {})
+    residency_policies = list(data_residency.get("tenants", {}).items())
+    if data_residency.get("default"):
+        residency_policies.append(("default", data_residency["default"]))
+    for tenant, policy in residency_policies:
+        for provider_name in policy.get("providers", []):
+            if provider_name not in model_provider_name_set:
+                raise Exception(
+                    f"Data residency policy of {tenant} allows unknown model_provider {provider_name}"
+                )
 
     if len(model_usage_name_keys) > 0:
         routing_model_provider = config_yaml.get("routing", {}).get(
@@ -354,6 +547,25 @@ def validate_and_render_schema():
                 raise Exception(
                     f"Model alias 2 - '{alias_name}' targets '{target}' which is not defined as a model. Available models: {', '.join(sorted(model_name_keys))}"
                 )
+            splits = alias_config.get("splits", [])
+            for split in splits:
+                if split.get("target") not in model_name_keys:
+                    raise Exception(
+                        f"Model alias '{alias_name}' splits traffic to '{split.get('target')}' which is not defined as a model. Available models: {', '.join(sorted(model_name_keys))}"
+                    )
+            if sum(split.get("weight", 0) for split in splits) > 100:
+                raise Exception(
+                    f"Model alias '{alias_name}' splits more than 100% of its traffic"
+                )
+            shadow = alias_config.get("shadow")
+            if shadow and shadow.get("target") not in model_name_keys:
+                raise Exception(
+                    f"Model alias '{alias_name}' mirrors traffic to '{shadow.get('target')}' which is not defined as a model. Available models: {', '.join(sorted(model_name_keys))}"
+                )
+
+    prompt_template_errors = validate_prompt_templates(config_yaml)
+    if prompt_template_errors:
+        raise Exception("\n".join(prompt_template_errors))
 
     arch_config_string = yaml.dump(config_yaml)
     arch_llm_config_string = yaml.dump(config_yaml)
@@ -390,6 +602,11 @@ def validate_and_render_schema():
         "local_llms": llms_with_endpoint,
         "agent_orchestrator": agent_orchestrator,
         "listeners": listeners,
+        "model_listeners": [
+            listener
+            for listener in listeners
+            if listener.get("type") == "model_listener"
+        ],
     }
 
     rendered = template.render(data)
@@ -5,7 +5,11 @@ import time
 import sys
 
 import yaml
-from planoai.utils import convert_legacy_listeners, getLogger
+from planoai.utils import (
+    convert_legacy_listeners,
+    get_llm_provider_tls_files,
+    getLogger,
+)
 from planoai.consts import (
     PLANO_DOCKER_IMAGE,
     PLANO_DOCKER_NAME,
@@ -70,6 +74,7 @@ def start_arch(arch_config_file, env, log_timeout=120, foreground=False):
             arch_config_file,
             env,
             gateway_ports,
+            get_llm_provider_tls_files(arch_config_file),
         )
         if return_code != 0:
             log.info("Failed to start plano gateway: " + str(return_code))
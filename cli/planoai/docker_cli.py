@@ -44,6 +44,7 @@ def docker_start_plano_detached(
     arch_config_file: str,
     env: dict,
     gateway_ports: list[int],
+    tls_files: list[str],
 ) -> str:
     env_args = [item for key, value in env.items() for item in ["-e", f"{key}={value}"]]
 
@@ -60,6 +61,8 @@ def docker_start_plano_detached(
     volume_mappings = [
         f"{arch_config_file}:/app/arch_config.yaml:ro",
     ]
+    # upstream tls files are mounted at the same path the config refers to
+    volume_mappings += [f"{path}:{path}:ro" for path in tls_files]
     volume_mappings_args = [
         item for volume in volume_mappings for item in ("-v", volume)
     ]
@@ -115,7 +118,10 @@ def stream_gateway_logs(follow, service="plano"):
         log.info(f"Failed to stream logs: {str(e)}")
 
 
-def docker_validate_plano_schema(arch_config_file):
+def docker_validate_plano_schema(arch_config_file, tls_files: list[str]):
+    tls_volume_args = [
+        item for path in tls_files for item in ("-v", f"{path}:{path}:ro")
+    ]
     result = subprocess.run(
         [
             "docker",
@@ -123,6 +129,7 @@ def docker_validate_plano_schema(arch_config_file):
             "--rm",
             "-v",
             f"{arch_config_file}:/app/arch_config.yaml:ro",
+            *tls_volume_args,
             "--entrypoint",
             "python",
             PLANO_DOCKER_IMAGE,
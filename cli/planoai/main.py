@@ -5,6 +5,7 @@ import subprocess
 import multiprocessing
 import importlib.metadata
 import json
+import yaml
 from planoai import targets
 from planoai.docker_cli import (
     docker_validate_plano_schema,
@@ -14,12 +15,14 @@ from planoai.docker_cli import (
 from planoai.utils import (
     getLogger,
     get_llm_provider_access_keys,
+    get_llm_provider_tls_files,
     has_ingress_listener,
     load_env_file_to_dict,
     stream_access_logs,
     find_config_file,
     find_repo_root,
 )
+from planoai.prompt_templates import parse_template, validate_prompt_templates
 from planoai.core import (
     start_arch,
     stop_docker_container,
@@ -144,7 +147,9 @@ def up(file, path, foreground):
         validation_return_code,
         validation_stdout,
         validation_stderr,
-    ) = docker_validate_plano_schema(arch_config_file)
+    ) = docker_validate_plano_schema(
+        arch_config_file, get_llm_provider_tls_files(arch_config_file)
+    )
     if validation_return_code != 0:
         log.info(f"Error: Validation failed. Exiting")
         log.info(f"Validation stdout: {validation_stdout}")
@@ -224,6 +229,41 @@ def generate_prompt_targets(file):
     targets.generate_prompt_targets(file)
 
 
+@click.command()
+@click.argument("file", required=False)
+@click.option(
+    "--path", default=".", help="Path to the directory containing arch_config.yaml"
+)
+def validate_templates(file, path):
+    """Validates the prompt templates of the config and lists their variables."""
+    arch_config_file = find_config_file(path, file)
+    if not os.path.exists(arch_config_file):
+        log.info(f"Error: {arch_config_file} does not exist.")
+        sys.exit(1)
+
+    with open(arch_config_file, "r") as f:
+        config_yaml = yaml.safe_load(f)
+
+    templates = config_yaml.get("prompt_templates") or {}
+    for name, template_config in templates.items():
+        label = name
+        if template_config.get("version"):
+            label = f"{name}@{template_config['version']}"
+        try:
+            variables = parse_template(template_config.get("template", ""))
+            print(f"{label}: variables {', '.join(variables) or '(none)'}")
+        except ValueError:
+            # reported with the other errors below
+            pass
+
+    errors = validate_prompt_templates(config_yaml)
+    for error in errors:
+        print(f"Error: {error}")
+    if errors:
+        sys.exit(1)
+    print(f"{len(templates)} prompt templates are valid")
+
+
 @click.command()
 @click.option(
     "--debug",
@@ -304,6 +344,7 @@ main.add_command(build)
 main.add_command(logs)
 main.add_command(cli_agent)
 main.add_command(generate_prompt_targets)
+main.add_command(validate_templates)
 
 if __name__ == "__main__":
     main()
@@ -0,0 +1,73 @@
+import re
+
+# Mirrors crates/common/src/prompt_template.rs so templates are checked before plano starts
+VARIABLE_NAME = re.compile(r"^[A-Za-z0-9_.\-]+$")
+DEFAULT_FILTER = re.compile(r"""^default\(\s*("[^"]*"|'[^']*')\s*\)$""")
+SIMPLE_FILTERS = {"upper", "lower", "trim"}
+
+
+def _split_filters(placeholder):
+    parts = []
+    quote = None
+    start = 0
+    for i, c in enumerate(placeholder):
+        if quote is None and c in "\"'":
+            quote = c
+        elif quote is not None and c == quote:
+            quote = None
+        elif quote is None and c == "|":
+            parts.append(placeholder[start:i])
+            start = i + 1
+    parts.append(placeholder[start:])
+    return parts
+
+
+def parse_template(template):
+    """Returns the variables of the template, raises ValueError when it is invalid."""
+    variables = []
+    offset = 0
+    while True:
+        start = template.find("{{", offset)
+        if start == -1:
+            return variables
+        end = template.find("}}", start)
+        if end == -1:
+            raise ValueError(f"placeholder at offset {start} is not closed")
+        parts = _split_filters(template[start + 2 : end])
+        name = parts[0].strip()
+        if not name:
+            raise ValueError(f"placeholder at offset {start} has no variable")
+        if not VARIABLE_NAME.match(name):
+            raise ValueError(f"invalid variable name {name!r}")
+        for part in parts[1:]:
+            part = part.strip()
+            if part not in SIMPLE_FILTERS and not DEFAULT_FILTER.match(part):
+                raise ValueError(f"unknown filter {part!r}")
+        if name not in variables:
+            variables.append(name)
+        offset = end + 2
+
+
+def validate_prompt_templates(config_yaml):
+    """Returns the errors of the prompt templates of the config and of their references."""
+    errors = []
+    templates = config_yaml.get("prompt_templates") or {}
+    for name, template_config in templates.items():
+        try:
+            parse_template(template_config.get("template", ""))
+        except ValueError as e:
+            errors.append(f"Prompt template '{name}': {e}")
+
+    references = [
+        (f"Model alias '{alias_name}'", alias_config.get("prompt_template"))
+        for alias_name, alias_config in (config_yaml.get("model_aliases") or {}).items()
+    ] + [
+        (f"Prompt target '{target.get('name')}'", target.get("prompt_template"))
+        for target in config_yaml.get("prompt_targets") or []
+    ]
+    for owner, template_name in references:
+        if template_name and template_name not in templates:
+            errors.append(
+                f"{owner} uses prompt template '{template_name}' which is not defined in prompt_templates"
+            )
+    return errors
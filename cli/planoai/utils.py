@@ -129,10 +129,10 @@ def convert_legacy_listeners(
     model_provider_set = False
     for listener in listeners:
         if listener.get("type") == "model_listener":
+            # all model listeners share the root level model_providers, only the
+            # first one carries them so that they are validated and rendered once
             if model_provider_set:
-                raise ValueError(
-                    "Currently only one listener can have model_providers set"
-                )
+                continue
             listener["model_providers"] = model_providers or []
             model_provider_set = True
             llm_gateway_listener = listener
@@ -201,6 +201,26 @@ def get_llm_provider_access_keys(arch_config_file):
     return access_key_list
 
 
+def get_llm_provider_tls_files(arch_config_file):
+    """Certificate and key files of the providers' upstream tls, mounted into the gateway"""
+    with open(arch_config_file, "r") as file:
+        arch_config_yaml = yaml.safe_load(file.read())
+
+    providers = (
+        arch_config_yaml.get("model_providers")
+        or arch_config_yaml.get("llm_providers")
+        or []
+    )
+    tls_files = []
+    for provider in providers:
+        tls = provider.get("tls") or {}
+        for key in ["client_cert", "client_key", "ca_cert"]:
+            path = tls.get(key)
+            if path and path not in tls_files:
+                tls_files.append(path)
+    return tls_files
+
+
 def load_env_file_to_dict(file_path):
     env_dict = {}
 
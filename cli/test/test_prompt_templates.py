@@ -0,0 +1,38 @@
+import pytest
+from planoai.prompt_templates import parse_template, validate_prompt_templates
+
+
+def test_parse_template_variables():
+    assert parse_template(
+        "You help the {{ team }} team, tier {{ tier | default('free') | upper }}. {{team}}"
+    ) == ["team", "tier"]
+
+
+@pytest.mark.parametrize(
+    "template,error",
+    [
+        ("Hi {{ name", "not closed"),
+        ("Hi {{ }}", "has no variable"),
+        ("{{ first name }}", "invalid variable name"),
+        ("{{ name | title }}", "unknown filter"),
+        ("{{ name | default(free) }}", "unknown filter"),
+    ],
+)
+def test_parse_template_errors(template, error):
+    with pytest.raises(ValueError, match=error):
+        parse_template(template)
+
+
+def test_validate_prompt_templates_references():
+    config = {
+        "prompt_templates": {"support": {"template": "Help {{ team }}", "version": "v2"}},
+        "model_aliases": {
+            "support.default": {"target": "gpt-4o", "prompt_template": "support"},
+            "sales.default": {"target": "gpt-4o", "prompt_template": "sales"},
+        },
+        "prompt_targets": [{"name": "weather", "prompt_template": "weather"}],
+    }
+    assert validate_prompt_templates(config) == [
+        "Model alias 'sales.default' uses prompt template 'sales' which is not defined in prompt_templates",
+        "Prompt target 'weather' uses prompt template 'weather' which is not defined in prompt_templates",
+    ]
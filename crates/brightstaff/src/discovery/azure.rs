@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{get_json, DiscoveredModel, DiscoveryError, ModelDiscovery};
+use crate::utils::egress_proxy::egress_client;
+
+/// Last api version of the data plane serving the deployments listing
+const DEPLOYMENTS_API_VERSION: &str = "2022-12-01";
+
+#[derive(Debug, Deserialize)]
+struct DeploymentsResponse {
+    data: Vec<Deployment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Deployment {
+    id: String,
+    status: Option<String>,
+}
+
+/// Lists the deployments of an Azure OpenAI resource. Resources that no longer serve
+/// the deployments listing fall back to the deployments configured as providers.
+pub struct AzureDeploymentsDiscovery {
+    client: reqwest::Client,
+    provider: String,
+    url: String,
+    access_key: Option<String>,
+    configured_deployments: Vec<String>,
+}
+
+impl AzureDeploymentsDiscovery {
+    pub fn new(
+        provider: &str,
+        base_url: &str,
+        access_key: Option<String>,
+        configured_deployments: Vec<String>,
+    ) -> Self {
+        AzureDeploymentsDiscovery {
+            client: egress_client(),
+            provider: provider.to_string(),
+            url: format!(
+                "{}/openai/deployments?api-version={}",
+                base_url, DEPLOYMENTS_API_VERSION
+            ),
+            access_key,
+            configured_deployments,
+        }
+    }
+
+    /// Deployments still being created or that failed can't serve requests
+    fn models(&self, response: DeploymentsResponse) -> Vec<DiscoveredModel> {
+        response
+            .data
+            .into_iter()
+            .filter(|deployment| {
+                deployment
+                    .status
+                    .as_deref()
+                    .is_none_or(|s| s == "succeeded")
+            })
+            .map(|deployment| self.model(deployment.id))
+            .collect()
+    }
+
+    fn model(&self, deployment: String) -> DiscoveredModel {
+        DiscoveredModel {
+            id: deployment,
+            provider: self.provider.clone(),
+            context_window: None,
+            capabilities: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl ModelDiscovery for AzureDeploymentsDiscovery {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(access_key) = &self.access_key {
+            request = request.header("api-key", access_key);
+        }
+        match get_json(&self.provider, request).await {
+            Ok(response) => Ok(self.models(response)),
+            Err(DiscoveryError::Status { status: 404, .. }) => Ok(self
+                .configured_deployments
+                .iter()
+                .cloned()
+                .map(|deployment| self.model(deployment))
+                .collect()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deployments_response() {
+        let discovery = AzureDeploymentsDiscovery::new(
+            "azure_openai",
+            "https://contoso.openai.azure.com:443",
+            None,
+            vec!["gpt-4o".to_string()],
+        );
+        assert_eq!(
+            discovery.url,
+            "https://contoso.openai.azure.com:443/openai/deployments?api-version=2022-12-01"
+        );
+
+        let response: DeploymentsResponse = serde_json::from_str(
+            r#"{"data":[
+                {"id":"gpt-4o-prod","model":"gpt-4o","status":"succeeded","object":"deployment"},
+                {"id":"gpt-4o-mini","model":"gpt-4o-mini","status":"creating"}
+            ]}"#,
+        )
+        .unwrap();
+        let models = discovery.models(response);
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "gpt-4o-prod");
+        assert_eq!(models[0].provider, "azure_openai");
+    }
+
+    #[tokio::test]
+    async fn test_configured_deployments_when_listing_is_unavailable() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/openai/deployments")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "api-version".to_string(),
+                DEPLOYMENTS_API_VERSION.to_string(),
+            ))
+            .match_header("api-key", "secret")
+            .with_status(404)
+            .create_async()
+            .await;
+        let discovery = AzureDeploymentsDiscovery::new(
+            "azure_openai",
+            &server.url(),
+            Some("secret".to_string()),
+            vec!["gpt-4o".to_string()],
+        );
+        let models = discovery.list_models().await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "gpt-4o");
+    }
+}
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{get_json, DiscoveredModel, DiscoveryError, ModelDiscovery};
+use crate::utils::egress_proxy::egress_client;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListFoundationModelsResponse {
+    model_summaries: Vec<FoundationModelSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundationModelSummary {
+    model_id: String,
+    #[serde(default)]
+    input_modalities: Vec<String>,
+    #[serde(default)]
+    output_modalities: Vec<String>,
+    response_streaming_supported: Option<bool>,
+    model_lifecycle: Option<ModelLifecycle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelLifecycle {
+    status: String,
+}
+
+/// Lists foundation models with the Bedrock `ListFoundationModels` api, authenticated
+/// with a Bedrock api key
+pub struct BedrockDiscovery {
+    client: reqwest::Client,
+    provider: String,
+    url: String,
+    access_key: Option<String>,
+}
+
+impl BedrockDiscovery {
+    pub fn new(provider: &str, base_url: &str, access_key: Option<String>) -> Self {
+        BedrockDiscovery {
+            client: egress_client(),
+            provider: provider.to_string(),
+            url: format!("{}/foundation-models", base_url),
+            access_key,
+        }
+    }
+
+    /// Legacy models can't be invoked by accounts that didn't use them before, so
+    /// they're left out
+    fn models(&self, response: ListFoundationModelsResponse) -> Vec<DiscoveredModel> {
+        response
+            .model_summaries
+            .into_iter()
+            .filter(|model| {
+                model
+                    .model_lifecycle
+                    .as_ref()
+                    .is_none_or(|lifecycle| lifecycle.status == "ACTIVE")
+            })
+            .map(|model| DiscoveredModel {
+                capabilities: capabilities(&model),
+                id: model.model_id,
+                provider: self.provider.clone(),
+                context_window: None,
+            })
+            .collect()
+    }
+}
+
+fn capabilities(model: &FoundationModelSummary) -> Vec<String> {
+    let mut capabilities = Vec::new();
+    if model.input_modalities.iter().any(|m| m == "IMAGE") {
+        capabilities.push("vision".to_string());
+    }
+    if model.output_modalities.iter().any(|m| m == "EMBEDDING") {
+        capabilities.push("embeddings".to_string());
+    }
+    if model.output_modalities.iter().any(|m| m == "IMAGE") {
+        capabilities.push("image_generation".to_string());
+    }
+    if model.response_streaming_supported == Some(true) {
+        capabilities.push("streaming".to_string());
+    }
+    capabilities
+}
+
+/// Models are listed by the control plane (`bedrock.<region>`) while providers are
+/// configured with the runtime endpoint (`bedrock-runtime.<region>`)
+pub fn control_plane_url(base_url: &str) -> String {
+    base_url.replacen("bedrock-runtime.", "bedrock.", 1)
+}
+
+#[async_trait]
+impl ModelDiscovery for BedrockDiscovery {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(access_key) = &self.access_key {
+            request = request.bearer_auth(access_key);
+        }
+        Ok(self.models(get_json(&self.provider, request).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_foundation_models_response() {
+        let base_url = control_plane_url("https://bedrock-runtime.us-west-2.amazonaws.com:443");
+        assert_eq!(base_url, "https://bedrock.us-west-2.amazonaws.com:443");
+        let discovery = BedrockDiscovery::new("amazon_bedrock", &base_url, None);
+        assert_eq!(
+            discovery.url,
+            "https://bedrock.us-west-2.amazonaws.com:443/foundation-models"
+        );
+
+        let response: ListFoundationModelsResponse = serde_json::from_str(
+            r#"{"modelSummaries":[
+                {"modelId":"anthropic.claude-3-5-sonnet-20241022-v2:0","providerName":"Anthropic",
+                 "inputModalities":["TEXT","IMAGE"],"outputModalities":["TEXT"],
+                 "responseStreamingSupported":true,"modelLifecycle":{"status":"ACTIVE"}},
+                {"modelId":"amazon.titan-embed-text-v2:0",
+                 "inputModalities":["TEXT"],"outputModalities":["EMBEDDING"],
+                 "modelLifecycle":{"status":"ACTIVE"}},
+                {"modelId":"anthropic.claude-v2","inputModalities":["TEXT"],"outputModalities":["TEXT"],
+                 "modelLifecycle":{"status":"LEGACY"}}
+            ]}"#,
+        )
+        .unwrap();
+        let models = discovery.models(response);
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].capabilities, vec!["vision", "streaming"]);
+        assert_eq!(models[1].id, "amazon.titan-embed-text-v2:0");
+        assert_eq!(models[1].capabilities, vec!["embeddings"]);
+    }
+}
@@ -0,0 +1,516 @@
+use async_trait::async_trait;
+use common::configuration::{LlmProvider, LlmProviderType, ModelDiscoveryConfig};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{watch, Mutex};
+use tracing::{info, warn};
+
+pub mod azure;
+pub mod bedrock;
+pub mod ollama;
+pub mod openai;
+pub mod openrouter;
+
+pub const DEFAULT_DISCOVERY_INTERVAL: Duration = Duration::from_secs(300);
+pub const DEFAULT_DISCOVERY_JITTER: Duration = Duration::from_secs(30);
+const LIST_MODELS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Model offered by a provider
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiscoveredModel {
+    pub id: String,
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>,
+    /// e.g. `tools`, `vision`, `embeddings`, when the provider reports them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum DiscoveryError {
+    #[error("failed to list models of {provider}: {source}")]
+    Http {
+        provider: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{provider} returned {status} listing models: {body}")]
+    Status {
+        provider: String,
+        status: u16,
+        body: String,
+    },
+    #[error("unexpected list models response from {provider}: {source}")]
+    Parse {
+        provider: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Lists the models of one provider
+#[async_trait]
+pub trait ModelDiscovery: Send + Sync {
+    /// Name the discovered models are registered under, e.g. `openai`
+    fn provider(&self) -> &str;
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError>;
+}
+
+/// Sends a list models request and parses the json response
+async fn get_json<T: DeserializeOwned>(
+    provider: &str,
+    request: reqwest::RequestBuilder,
+) -> Result<T, DiscoveryError> {
+    let http_error = |source| DiscoveryError::Http {
+        provider: provider.to_string(),
+        source,
+    };
+    let response = request
+        .timeout(LIST_MODELS_TIMEOUT)
+        .send()
+        .await
+        .map_err(http_error)?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(http_error)?;
+    if !status.is_success() {
+        return Err(DiscoveryError::Status {
+            provider: provider.to_string(),
+            status: status.as_u16(),
+            body: String::from_utf8_lossy(&body).to_string(),
+        });
+    }
+    serde_json::from_slice(&body).map_err(|source| DiscoveryError::Parse {
+        provider: provider.to_string(),
+        source,
+    })
+}
+
+/// Base url of the upstream of a provider, from its `base_url` or the public url of
+/// providers that don't need one
+pub fn upstream_base_url(provider: &LlmProvider) -> Option<String> {
+    let Some(endpoint) = &provider.endpoint else {
+        return openai::default_base_url(&provider.provider_interface).map(str::to_string);
+    };
+    let protocol = provider.protocol.as_deref().unwrap_or(match provider.port {
+        Some(80) => "http",
+        _ => "https",
+    });
+    let authority = match provider.port {
+        Some(port) => format!("{}:{}", endpoint, port),
+        None => endpoint.clone(),
+    };
+    Some(format!(
+        "{}://{}{}",
+        protocol,
+        authority,
+        provider
+            .base_url_path_prefix
+            .as_deref()
+            .unwrap_or_default()
+            .trim_end_matches('/')
+    ))
+}
+
+/// Adapter listing the models of the upstream shared by `providers`
+fn discovery_source(
+    name: &str,
+    base_url: &str,
+    providers: &[&LlmProvider],
+) -> Option<Arc<dyn ModelDiscovery>> {
+    let provider = providers[0];
+    let access_key = provider.access_key.clone();
+    let source: Arc<dyn ModelDiscovery> = match provider.provider_interface {
+        LlmProviderType::AzureOpenAI => Arc::new(azure::AzureDeploymentsDiscovery::new(
+            name,
+            base_url,
+            access_key,
+            providers
+                .iter()
+                .filter_map(|provider| provider.model.clone())
+                .collect(),
+        )),
+        LlmProviderType::AmazonBedrock => Arc::new(bedrock::BedrockDiscovery::new(
+            name,
+            &bedrock::control_plane_url(base_url),
+            access_key,
+        )),
+        LlmProviderType::Ollama => {
+            Arc::new(ollama::OllamaDiscovery::new(name, base_url, access_key))
+        }
+        LlmProviderType::Arch
+        | LlmProviderType::Anthropic
+        | LlmProviderType::Gemini
+        | LlmProviderType::VertexAI
+        | LlmProviderType::ChatGPT => return None,
+        _ if provider.endpoint.as_deref() == Some(openrouter::OPENROUTER_HOST) => Arc::new(
+            openrouter::OpenRouterDiscovery::new(name, base_url, access_key),
+        ),
+        _ => Arc::new(openai::OpenAiCompatibleDiscovery::new(
+            name,
+            &format!("{}/v1/models", base_url),
+            access_key,
+        )),
+    };
+    Some(source)
+}
+
+/// Base url and access key of an upstream
+type UpstreamKey = (String, Option<String>);
+
+/// One discovery source per distinct upstream of the configured providers. Providers
+/// configured per model share their upstream, so it is only listed once.
+pub fn discovery_sources(
+    providers: &[LlmProvider],
+    config: &ModelDiscoveryConfig,
+) -> Vec<Arc<dyn ModelDiscovery>> {
+    let mut upstreams: Vec<(UpstreamKey, Vec<&LlmProvider>)> = Vec::new();
+    for provider in providers {
+        if let Some(enabled) = &config.providers {
+            if !enabled.contains(&provider.name) {
+                continue;
+            }
+        }
+        // the gateway presents the client certificate, discovery calls the upstream directly
+        if provider
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.client_cert.is_some())
+        {
+            continue;
+        }
+        let Some(base_url) = upstream_base_url(provider) else {
+            continue;
+        };
+        let key = (base_url, provider.access_key.clone());
+        match upstreams.iter_mut().find(|(upstream, _)| *upstream == key) {
+            Some((_, providers)) => providers.push(provider),
+            None => upstreams.push((key, vec![provider])),
+        }
+    }
+
+    let mut names = HashSet::new();
+    upstreams
+        .into_iter()
+        .filter_map(|((base_url, _), providers)| {
+            let interface = providers[0].provider_interface.to_string();
+            let name = if names.contains(&interface) {
+                providers[0].name.clone()
+            } else {
+                interface
+            };
+            let source = discovery_source(&name, &base_url, &providers)?;
+            names.insert(name);
+            Some(source)
+        })
+        .collect()
+}
+
+/// Change in the models of a provider between two refreshes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ModelEvent {
+    ModelAdded {
+        model: DiscoveredModel,
+    },
+    ModelRemoved {
+        model: DiscoveredModel,
+    },
+    ModelChanged {
+        before: DiscoveredModel,
+        after: DiscoveredModel,
+    },
+}
+
+/// Models known per provider
+#[derive(Debug, Default)]
+pub struct ModelRegistry {
+    providers: HashMap<String, BTreeMap<String, DiscoveredModel>>,
+}
+
+impl ModelRegistry {
+    /// Replaces the models of a provider and returns what changed
+    pub fn apply(&mut self, provider: &str, models: Vec<DiscoveredModel>) -> Vec<ModelEvent> {
+        let mut previous = self.providers.remove(provider).unwrap_or_default();
+        let mut current = BTreeMap::new();
+        let mut events = Vec::new();
+
+        for model in models {
+            match previous.remove(&model.id) {
+                None => events.push(ModelEvent::ModelAdded {
+                    model: model.clone(),
+                }),
+                Some(before) if before != model => events.push(ModelEvent::ModelChanged {
+                    before,
+                    after: model.clone(),
+                }),
+                Some(_) => {}
+            }
+            current.insert(model.id.clone(), model);
+        }
+        events.extend(
+            previous
+                .into_values()
+                .map(|model| ModelEvent::ModelRemoved { model }),
+        );
+
+        self.providers.insert(provider.to_string(), current);
+        events
+    }
+
+    pub fn models(&self) -> Vec<DiscoveredModel> {
+        let mut providers: Vec<_> = self.providers.iter().collect();
+        providers.sort_by_key(|(provider, _)| *provider);
+        providers
+            .into_iter()
+            .flat_map(|(_, models)| models.values().cloned())
+            .collect()
+    }
+}
+
+/// State published to subscribers after every refresh that changed something
+#[derive(Debug, Clone, Default)]
+pub struct DiscoverySnapshot {
+    pub models: Vec<DiscoveredModel>,
+    /// Events of the refresh that produced this snapshot
+    pub events: Vec<ModelEvent>,
+}
+
+/// Refreshes the model list of every provider on an interval with jitter, so that
+/// providers sharing an interval don't all get called at once
+pub struct DiscoveryScheduler {
+    sources: Vec<Arc<dyn ModelDiscovery>>,
+    interval: Duration,
+    jitter: Duration,
+    registry: Mutex<ModelRegistry>,
+    snapshot: watch::Sender<DiscoverySnapshot>,
+}
+
+impl DiscoveryScheduler {
+    pub fn new(
+        sources: Vec<Arc<dyn ModelDiscovery>>,
+        interval: Duration,
+        jitter: Duration,
+    ) -> Self {
+        DiscoveryScheduler {
+            sources,
+            interval,
+            jitter,
+            registry: Mutex::new(ModelRegistry::default()),
+            snapshot: watch::channel(DiscoverySnapshot::default()).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<DiscoverySnapshot> {
+        self.snapshot.subscribe()
+    }
+
+    /// Lists the models of a provider and publishes the changes. A failed listing keeps
+    /// the models of the previous refresh.
+    pub async fn refresh(
+        &self,
+        source: &dyn ModelDiscovery,
+    ) -> Result<Vec<ModelEvent>, DiscoveryError> {
+        let models = source.list_models().await?;
+        let mut registry = self.registry.lock().await;
+        let events = registry.apply(source.provider(), models);
+        if !events.is_empty() {
+            for event in &events {
+                info!(
+                    "model discovery: {}",
+                    serde_json::to_string(event).unwrap_or_default()
+                );
+            }
+            self.snapshot.send_replace(DiscoverySnapshot {
+                models: registry.models(),
+                events: events.clone(),
+            });
+        }
+        Ok(events)
+    }
+
+    /// Spawns one refresh loop per provider
+    pub fn start(self: Arc<Self>) -> Vec<tokio::task::JoinHandle<()>> {
+        self.sources
+            .iter()
+            .cloned()
+            .map(|source| {
+                let scheduler = self.clone();
+                tokio::spawn(async move {
+                    loop {
+                        if let Err(err) = scheduler.refresh(source.as_ref()).await {
+                            warn!("model discovery failed: {}", err);
+                        }
+                        tokio::time::sleep(scheduler.next_delay()).await;
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn next_delay(&self) -> Duration {
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            return self.interval;
+        }
+        self.interval + Duration::from_millis(rand::random_range(0..=jitter_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(id: &str, context_window: Option<u64>) -> DiscoveredModel {
+        DiscoveredModel {
+            id: id.to_string(),
+            provider: "openai".to_string(),
+            context_window,
+            capabilities: Vec::new(),
+        }
+    }
+
+    struct StaticDiscovery {
+        models: std::sync::Mutex<Vec<DiscoveredModel>>,
+    }
+
+    #[async_trait]
+    impl ModelDiscovery for StaticDiscovery {
+        fn provider(&self) -> &str {
+            "openai"
+        }
+
+        async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+            Ok(self.models.lock().unwrap().clone())
+        }
+    }
+
+    fn provider(name: &str, interface: LlmProviderType) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            provider_interface: interface,
+            access_key: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_upstream_base_url() {
+        assert_eq!(
+            upstream_base_url(&provider("openai/gpt-4o", LlmProviderType::OpenAI)).as_deref(),
+            Some("https://api.openai.com")
+        );
+        let mut ollama = provider("ollama/llama3", LlmProviderType::Ollama);
+        assert!(upstream_base_url(&ollama).is_none());
+        ollama.endpoint = Some("host.docker.internal".to_string());
+        ollama.port = Some(11434);
+        ollama.protocol = Some("http".to_string());
+        assert_eq!(
+            upstream_base_url(&ollama).as_deref(),
+            Some("http://host.docker.internal:11434")
+        );
+    }
+
+    #[test]
+    fn test_discovery_sources() {
+        let mut openrouter = provider("openrouter/gpt-4o", LlmProviderType::OpenAI);
+        openrouter.endpoint = Some(openrouter::OPENROUTER_HOST.to_string());
+        openrouter.port = Some(443);
+        openrouter.base_url_path_prefix = Some("/api".to_string());
+        let mut ollama = provider("ollama/llama3", LlmProviderType::Ollama);
+        ollama.endpoint = Some("localhost".to_string());
+        ollama.port = Some(11434);
+
+        let providers = vec![
+            provider("openai/gpt-4o", LlmProviderType::OpenAI),
+            provider("openai/o3", LlmProviderType::OpenAI),
+            provider("claude", LlmProviderType::Anthropic),
+            provider("mistral/large", LlmProviderType::Mistral),
+            openrouter,
+            ollama,
+        ];
+        let sources = discovery_sources(&providers, &ModelDiscoveryConfig::default());
+        let names: Vec<&str> = sources.iter().map(|source| source.provider()).collect();
+        // providers sharing an upstream are listed once, a second upstream of the same
+        // interface is named after its provider
+        assert_eq!(
+            names,
+            vec!["openai", "mistral", "openrouter/gpt-4o", "ollama"]
+        );
+
+        let config = ModelDiscoveryConfig {
+            providers: Some(vec!["mistral/large".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(discovery_sources(&providers, &config).len(), 1);
+    }
+
+    #[test]
+    fn test_registry_diff() {
+        let mut registry = ModelRegistry::default();
+        let events = registry.apply("openai", vec![model("gpt-4o", None), model("o3", None)]);
+        assert_eq!(events.len(), 2);
+
+        let events = registry.apply(
+            "openai",
+            vec![model("gpt-4o", Some(128000)), model("gpt-5", None)],
+        );
+        assert_eq!(
+            events,
+            vec![
+                ModelEvent::ModelChanged {
+                    before: model("gpt-4o", None),
+                    after: model("gpt-4o", Some(128000)),
+                },
+                ModelEvent::ModelAdded {
+                    model: model("gpt-5", None)
+                },
+                ModelEvent::ModelRemoved {
+                    model: model("o3", None)
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&events[1]).unwrap(),
+            serde_json::json!({"event": "model_added", "model": {"id": "gpt-5", "provider": "openai"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_notifies_subscribers_on_change() {
+        let source = Arc::new(StaticDiscovery {
+            models: std::sync::Mutex::new(vec![model("gpt-4o", None)]),
+        });
+        let scheduler = DiscoveryScheduler::new(
+            vec![source.clone()],
+            Duration::from_secs(60),
+            Duration::ZERO,
+        );
+        let mut subscriber = scheduler.subscribe();
+
+        scheduler.refresh(source.as_ref()).await.unwrap();
+        assert!(subscriber.has_changed().unwrap());
+        assert_eq!(subscriber.borrow_and_update().models.len(), 1);
+
+        // nothing changed, nothing published
+        assert!(scheduler.refresh(source.as_ref()).await.unwrap().is_empty());
+        assert!(!subscriber.has_changed().unwrap());
+
+        source.models.lock().unwrap().clear();
+        scheduler.refresh(source.as_ref()).await.unwrap();
+        let snapshot = subscriber.borrow_and_update().clone();
+        assert!(snapshot.models.is_empty());
+        assert_eq!(
+            snapshot.events,
+            vec![ModelEvent::ModelRemoved {
+                model: model("gpt-4o", None)
+            }]
+        );
+        assert_eq!(scheduler.next_delay(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{get_json, DiscoveredModel, DiscoveryError, ModelDiscovery};
+use crate::utils::egress_proxy::egress_client;
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<LocalModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalModel {
+    name: String,
+    #[serde(default)]
+    details: ModelDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModelDetails {
+    family: Option<String>,
+    families: Option<Vec<String>>,
+}
+
+/// Lists the models pulled into an Ollama server with `GET /api/tags`
+pub struct OllamaDiscovery {
+    client: reqwest::Client,
+    provider: String,
+    url: String,
+    access_key: Option<String>,
+}
+
+impl OllamaDiscovery {
+    pub fn new(provider: &str, base_url: &str, access_key: Option<String>) -> Self {
+        OllamaDiscovery {
+            client: egress_client(),
+            provider: provider.to_string(),
+            url: format!("{}/api/tags", base_url),
+            access_key,
+        }
+    }
+
+    fn models(&self, response: TagsResponse) -> Vec<DiscoveredModel> {
+        response
+            .models
+            .into_iter()
+            .map(|model| DiscoveredModel {
+                capabilities: capabilities(&model.details),
+                id: model.name,
+                provider: self.provider.clone(),
+                context_window: None,
+            })
+            .collect()
+    }
+}
+
+/// Tags only carry the model families, vision models include a clip or mllama projector
+/// and embedding models are bert based
+fn capabilities(details: &ModelDetails) -> Vec<String> {
+    let families: Vec<&str> = details
+        .families
+        .iter()
+        .flatten()
+        .chain(details.family.iter())
+        .map(String::as_str)
+        .collect();
+    let mut capabilities = Vec::new();
+    if families
+        .iter()
+        .any(|family| matches!(*family, "clip" | "mllama"))
+    {
+        capabilities.push("vision".to_string());
+    }
+    if families.iter().any(|family| family.ends_with("bert")) {
+        capabilities.push("embeddings".to_string());
+    }
+    capabilities
+}
+
+#[async_trait]
+impl ModelDiscovery for OllamaDiscovery {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(access_key) = &self.access_key {
+            request = request.bearer_auth(access_key);
+        }
+        Ok(self.models(get_json(&self.provider, request).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_response() {
+        let discovery = OllamaDiscovery::new("ollama", "http://localhost:11434", None);
+        assert_eq!(discovery.url, "http://localhost:11434/api/tags");
+
+        let response: TagsResponse = serde_json::from_str(
+            r#"{"models":[
+                {"name":"llama3.2:latest","model":"llama3.2:latest","details":{"family":"llama","families":["llama"],"parameter_size":"3.2B"}},
+                {"name":"llava:7b","details":{"family":"llama","families":["llama","clip"]}},
+                {"name":"nomic-embed-text:latest","details":{"family":"nomic-bert","families":["nomic-bert"]}},
+                {"name":"custom:latest"}
+            ]}"#,
+        )
+        .unwrap();
+        let models = discovery.models(response);
+        assert_eq!(models[0].id, "llama3.2:latest");
+        assert!(models[0].capabilities.is_empty());
+        assert_eq!(models[1].capabilities, vec!["vision"]);
+        assert_eq!(models[2].capabilities, vec!["embeddings"]);
+        assert!(models[3].capabilities.is_empty());
+    }
+}
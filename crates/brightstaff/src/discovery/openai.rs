@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use common::configuration::LlmProviderType;
+use serde::Deserialize;
+
+use super::{get_json, DiscoveredModel, DiscoveryError, ModelDiscovery};
+use crate::utils::egress_proxy::egress_client;
+
+#[derive(Debug, Deserialize)]
+struct ListModelsResponse {
+    data: Vec<ListedModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListedModel {
+    id: String,
+    /// Reported by groq
+    context_window: Option<u64>,
+    /// Reported by together ai
+    context_length: Option<u64>,
+}
+
+/// Lists models with `GET /v1/models` of an OpenAI compatible api
+pub struct OpenAiCompatibleDiscovery {
+    client: reqwest::Client,
+    provider: String,
+    url: String,
+    access_key: Option<String>,
+}
+
+impl OpenAiCompatibleDiscovery {
+    pub fn new(provider: &str, url: &str, access_key: Option<String>) -> Self {
+        OpenAiCompatibleDiscovery {
+            client: egress_client(),
+            provider: provider.to_string(),
+            url: url.to_string(),
+            access_key,
+        }
+    }
+
+    fn models(&self, response: ListModelsResponse) -> Vec<DiscoveredModel> {
+        response
+            .data
+            .into_iter()
+            .map(|model| DiscoveredModel {
+                id: model.id,
+                provider: self.provider.clone(),
+                context_window: model.context_window.or(model.context_length),
+                capabilities: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ModelDiscovery for OpenAiCompatibleDiscovery {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(access_key) = &self.access_key {
+            request = request.bearer_auth(access_key);
+        }
+        Ok(self.models(get_json(&self.provider, request).await?))
+    }
+}
+
+/// Public base url of providers that don't need a `base_url` configured
+pub fn default_base_url(provider_interface: &LlmProviderType) -> Option<&'static str> {
+    match provider_interface {
+        LlmProviderType::OpenAI => Some("https://api.openai.com"),
+        LlmProviderType::Groq => Some("https://api.groq.com/openai"),
+        LlmProviderType::Mistral => Some("https://api.mistral.ai"),
+        LlmProviderType::Deepseek => Some("https://api.deepseek.com"),
+        LlmProviderType::XAI => Some("https://api.x.ai"),
+        LlmProviderType::TogetherAI => Some("https://api.together.xyz"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_models_response() {
+        let discovery = OpenAiCompatibleDiscovery::new("groq", "http://localhost/v1/models", None);
+        let response: ListModelsResponse = serde_json::from_str(
+            r#"{"object":"list","data":[
+                {"id":"llama-3.3-70b","object":"model","context_window":131072},
+                {"id":"whisper","object":"model"}
+            ]}"#,
+        )
+        .unwrap();
+        let models = discovery.models(response);
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].context_window, Some(131072));
+        assert_eq!(models[1].provider, "groq");
+        assert!(serde_json::from_str::<ListModelsResponse>("{}").is_err());
+    }
+}
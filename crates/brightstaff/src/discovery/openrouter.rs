@@ -0,0 +1,134 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{get_json, DiscoveredModel, DiscoveryError, ModelDiscovery};
+use crate::utils::egress_proxy::egress_client;
+
+pub const OPENROUTER_HOST: &str = "openrouter.ai";
+
+/// Request parameters that map to a capability of the model
+const PARAMETER_CAPABILITIES: &[(&str, &str)] = &[
+    ("tools", "tools"),
+    ("reasoning", "reasoning"),
+    ("structured_outputs", "structured_outputs"),
+];
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<OpenRouterModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterModel {
+    id: String,
+    context_length: Option<u64>,
+    architecture: Option<Architecture>,
+    #[serde(default)]
+    supported_parameters: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Architecture {
+    #[serde(default)]
+    input_modalities: Vec<String>,
+    #[serde(default)]
+    output_modalities: Vec<String>,
+}
+
+/// Lists the OpenRouter catalog with `GET /v1/models`, which also reports the context
+/// length, modalities and supported parameters of every model
+pub struct OpenRouterDiscovery {
+    client: reqwest::Client,
+    provider: String,
+    url: String,
+    access_key: Option<String>,
+}
+
+impl OpenRouterDiscovery {
+    /// `base_url` is the OpenRouter api base, e.g. `https://openrouter.ai:443/api`
+    pub fn new(provider: &str, base_url: &str, access_key: Option<String>) -> Self {
+        OpenRouterDiscovery {
+            client: egress_client(),
+            provider: provider.to_string(),
+            url: format!("{}/v1/models", base_url),
+            access_key,
+        }
+    }
+
+    fn models(&self, response: ModelsResponse) -> Vec<DiscoveredModel> {
+        response
+            .data
+            .into_iter()
+            .map(|model| DiscoveredModel {
+                capabilities: capabilities(&model),
+                id: model.id,
+                provider: self.provider.clone(),
+                context_window: model.context_length,
+            })
+            .collect()
+    }
+}
+
+fn capabilities(model: &OpenRouterModel) -> Vec<String> {
+    let mut capabilities = Vec::new();
+    if let Some(architecture) = &model.architecture {
+        if architecture.input_modalities.iter().any(|m| m == "image") {
+            capabilities.push("vision".to_string());
+        }
+        if architecture.output_modalities.iter().any(|m| m == "image") {
+            capabilities.push("image_generation".to_string());
+        }
+    }
+    for (parameter, capability) in PARAMETER_CAPABILITIES {
+        if model.supported_parameters.iter().any(|p| p == parameter) {
+            capabilities.push(capability.to_string());
+        }
+    }
+    capabilities
+}
+
+#[async_trait]
+impl ModelDiscovery for OpenRouterDiscovery {
+    fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    async fn list_models(&self) -> Result<Vec<DiscoveredModel>, DiscoveryError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(access_key) = &self.access_key {
+            request = request.bearer_auth(access_key);
+        }
+        Ok(self.models(get_json(&self.provider, request).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_response() {
+        let discovery =
+            OpenRouterDiscovery::new("openrouter", "https://openrouter.ai:443/api", None);
+        assert_eq!(discovery.url, "https://openrouter.ai:443/api/v1/models");
+
+        let response: ModelsResponse = serde_json::from_str(
+            r#"{"data":[
+                {"id":"openai/gpt-4o","context_length":128000,
+                 "architecture":{"input_modalities":["text","image","file"],"output_modalities":["text"]},
+                 "supported_parameters":["tools","tool_choice","structured_outputs","temperature"]},
+                {"id":"deepseek/deepseek-r1","context_length":163840,
+                 "supported_parameters":["reasoning","include_reasoning"]}
+            ]}"#,
+        )
+        .unwrap();
+        let models = discovery.models(response);
+        assert_eq!(models[0].id, "openai/gpt-4o");
+        assert_eq!(models[0].context_window, Some(128000));
+        assert_eq!(
+            models[0].capabilities,
+            vec!["vision", "tools", "structured_outputs"]
+        );
+        assert_eq!(models[1].capabilities, vec!["reasoning"]);
+    }
+}
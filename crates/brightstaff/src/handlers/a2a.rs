@@ -0,0 +1,717 @@
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use bytes::Bytes;
+use common::configuration::{Agent, Listener};
+use common::consts::{A2A_PATH, ARCH_AGENT_LISTENER_NAME_HEADER, TRACE_PARENT_HEADER};
+use common::traces::{
+    generate_random_span_id, parse_traceparent, SpanBuilder, SpanKind, TraceCollector,
+};
+use hermesllm::apis::openai::{ChatCompletionsRequest, Message, MessageContent, Role};
+use hermesllm::apis::OpenAIApi;
+use hermesllm::clients::SupportedAPIsFromClient;
+use hermesllm::ProviderRequestType;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::header::{self, HeaderMap};
+use hyper::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::agent_chat_completions::AgentFilterChainError;
+use super::agent_selector::AgentSelector;
+use super::jsonrpc::{JsonRpcError, JsonRpcId, JSON_RPC_VERSION};
+use super::pipeline_processor::PipelineProcessor;
+use super::response_handler::ResponseHandler;
+use crate::router::plano_orchestrator::OrchestratorService;
+use crate::tracing::{http, operation_component, OperationNameBuilder};
+
+pub const A2A_MESSAGE_SEND: &str = "message/send";
+pub const A2A_MESSAGE_STREAM: &str = "message/stream";
+const A2A_PROTOCOL_VERSION: &str = "0.2.6";
+
+// JSON-RPC error codes used by A2A
+const JSON_RPC_PARSE_ERROR: i32 = -32700;
+const JSON_RPC_INVALID_PARAMS: i32 = -32602;
+const JSON_RPC_METHOD_NOT_FOUND: i32 = -32601;
+const JSON_RPC_INTERNAL_ERROR: i32 = -32603;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct A2aRequest {
+    pub id: JsonRpcId,
+    pub method: String,
+    pub params: Option<MessageSendParams>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageSendParams {
+    pub message: A2aMessage,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum A2aRole {
+    User,
+    Agent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum A2aPart {
+    Text { text: String },
+    Data { data: Value },
+    File { file: Value },
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct A2aMessage {
+    pub role: A2aRole,
+    pub parts: Vec<A2aPart>,
+    pub message_id: String,
+    pub context_id: Option<String>,
+    pub task_id: Option<String>,
+    #[serde(default = "message_kind")]
+    pub kind: String,
+}
+
+fn message_kind() -> String {
+    "message".to_string()
+}
+
+impl A2aMessage {
+    fn agent_text(text: String, task_id: &str, context_id: &str) -> Self {
+        A2aMessage {
+            role: A2aRole::Agent,
+            parts: vec![A2aPart::Text { text }],
+            message_id: Uuid::new_v4().to_string(),
+            context_id: Some(context_id.to_string()),
+            task_id: Some(task_id.to_string()),
+            kind: message_kind(),
+        }
+    }
+
+    /// Text of the message, data parts are passed to the agents as json
+    fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                A2aPart::Text { text } => Some(text.clone()),
+                A2aPart::Data { data } => Some(data.to_string()),
+                A2aPart::File { .. } => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskState {
+    Submitted,
+    Working,
+    Completed,
+    Failed,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub state: TaskState,
+    pub message: Option<A2aMessage>,
+    pub timestamp: String,
+}
+
+impl TaskStatus {
+    fn new(state: TaskState, message: Option<A2aMessage>) -> Self {
+        TaskStatus {
+            state,
+            message,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Artifact {
+    pub artifact_id: String,
+    pub name: Option<String>,
+    pub parts: Vec<A2aPart>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct A2aTask {
+    pub id: String,
+    pub context_id: String,
+    pub status: TaskStatus,
+    pub artifacts: Vec<Artifact>,
+    pub history: Vec<A2aMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatusUpdateEvent {
+    pub task_id: String,
+    pub context_id: String,
+    pub status: TaskStatus,
+    #[serde(rename = "final")]
+    pub is_final: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskArtifactUpdateEvent {
+    pub task_id: String,
+    pub context_id: String,
+    pub artifact: Artifact,
+    pub last_chunk: bool,
+}
+
+/// Results of the A2A methods, tagged with `kind` as in the spec
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum A2aResult {
+    Task(A2aTask),
+    StatusUpdate(TaskStatusUpdateEvent),
+    ArtifactUpdate(TaskArtifactUpdateEvent),
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize)]
+struct A2aResponse {
+    jsonrpc: String,
+    id: Option<JsonRpcId>,
+    result: Option<A2aResult>,
+    error: Option<JsonRpcError>,
+}
+
+impl A2aResponse {
+    fn result(id: JsonRpcId, result: A2aResult) -> Self {
+        A2aResponse {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Option<JsonRpcId>, code: i32, message: String) -> Self {
+        A2aResponse {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+        }
+    }
+}
+
+/// Task being executed for an A2A request
+struct A2aTaskContext {
+    task_id: String,
+    context_id: String,
+    listener: Listener,
+    agents: Vec<Agent>,
+    request_headers: HeaderMap,
+    model: String,
+}
+
+impl A2aTaskContext {
+    fn status_update(&self, state: TaskState, text: Option<String>, is_final: bool) -> A2aResult {
+        A2aResult::StatusUpdate(TaskStatusUpdateEvent {
+            task_id: self.task_id.clone(),
+            context_id: self.context_id.clone(),
+            status: TaskStatus::new(
+                state,
+                text.map(|t| A2aMessage::agent_text(t, &self.task_id, &self.context_id)),
+            ),
+            is_final,
+        })
+    }
+
+    fn artifact(&self, text: String) -> Artifact {
+        Artifact {
+            artifact_id: format!("{}-response", self.task_id),
+            name: Some("response".to_string()),
+            parts: vec![A2aPart::Text { text }],
+        }
+    }
+}
+
+/// Agent card describing the listener, its agents are advertised as skills
+pub async fn a2a_agent_card(
+    request: Request<hyper::body::Incoming>,
+    listeners: Arc<RwLock<Vec<Listener>>>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let listener_name = request
+        .headers()
+        .get(ARCH_AGENT_LISTENER_NAME_HEADER)
+        .and_then(|name| name.to_str().ok());
+    let listener = listeners
+        .read()
+        .await
+        .iter()
+        .find(|l| Some(l.name.as_str()) == listener_name)
+        .cloned();
+    let Some(listener) = listener else {
+        return ResponseHandler::create_error_response(
+            StatusCode::NOT_FOUND,
+            &format!("listener not found: {}", listener_name.unwrap_or("unknown")),
+        );
+    };
+
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    let card = build_agent_card(&listener, host);
+
+    let mut response = Response::new(ResponseHandler::create_full_body(card.to_string()));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+fn build_agent_card(listener: &Listener, host: &str) -> Value {
+    let skills: Vec<Value> = listener
+        .agents
+        .iter()
+        .flatten()
+        .map(|agent| {
+            serde_json::json!({
+                "id": agent.id,
+                "name": agent.id,
+                "description": agent.description.clone().unwrap_or_default(),
+                "tags": [],
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "protocolVersion": A2A_PROTOCOL_VERSION,
+        "name": listener.name,
+        "description": format!("Plano agent listener {}", listener.name),
+        "url": format!("http://{}{}", host, A2A_PATH),
+        "version": env!("CARGO_PKG_VERSION"),
+        "capabilities": {"streaming": true},
+        "defaultInputModes": ["text/plain", "application/json"],
+        "defaultOutputModes": ["text/plain"],
+        "skills": skills,
+    })
+}
+
+/// JSON-RPC endpoint for A2A, `message/send` returns the completed task and
+/// `message/stream` streams status updates for every agent of the chain as SSE
+pub async fn a2a_handler(
+    request: Request<hyper::body::Incoming>,
+    orchestrator_service: Arc<OrchestratorService>,
+    agents_list: Arc<RwLock<Option<Vec<Agent>>>>,
+    listeners: Arc<RwLock<Vec<Listener>>>,
+    trace_collector: Arc<TraceCollector>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let request_headers = request.headers().clone();
+    let body = request.collect().await?.to_bytes();
+
+    let a2a_request: A2aRequest = match serde_json::from_slice(&body) {
+        Ok(a2a_request) => a2a_request,
+        Err(err) => {
+            warn!("Failed to parse A2A request: {}", err);
+            return Ok(json_rpc_response(&A2aResponse::error(
+                None,
+                JSON_RPC_PARSE_ERROR,
+                err.to_string(),
+            )));
+        }
+    };
+    let id = a2a_request.id.clone();
+
+    let streaming = match a2a_request.method.as_str() {
+        A2A_MESSAGE_SEND => false,
+        A2A_MESSAGE_STREAM => true,
+        method => {
+            return Ok(json_rpc_response(&A2aResponse::error(
+                Some(id),
+                JSON_RPC_METHOD_NOT_FOUND,
+                format!("method not supported: {}", method),
+            )));
+        }
+    };
+    let Some(params) = a2a_request.params else {
+        return Ok(json_rpc_response(&A2aResponse::error(
+            Some(id),
+            JSON_RPC_INVALID_PARAMS,
+            "params.message is required".to_string(),
+        )));
+    };
+
+    let listener_name = request_headers
+        .get(ARCH_AGENT_LISTENER_NAME_HEADER)
+        .and_then(|name| name.to_str().ok());
+    let agent_selector = AgentSelector::new(orchestrator_service);
+    let listener = {
+        let listeners = listeners.read().await;
+        agent_selector
+            .find_listener(listener_name, &listeners)
+            .await
+    };
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            return Ok(json_rpc_response(&A2aResponse::error(
+                Some(id),
+                JSON_RPC_INVALID_PARAMS,
+                err.to_string(),
+            )));
+        }
+    };
+
+    let task_context = A2aTaskContext {
+        task_id: params
+            .message
+            .task_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        context_id: params
+            .message
+            .context_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        agents: agents_list.read().await.clone().unwrap_or_default(),
+        model: params
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get("model"))
+            .and_then(Value::as_str)
+            .unwrap_or(&listener.name)
+            .to_string(),
+        listener,
+        request_headers,
+    };
+
+    info!(
+        "A2A {} task {} on listener {}",
+        a2a_request.method, task_context.task_id, task_context.listener.name
+    );
+
+    if !streaming {
+        let result = run_agent_chain(
+            &task_context,
+            &agent_selector,
+            &params.message,
+            &trace_collector,
+            None,
+        )
+        .await;
+        let response = match result {
+            Ok(text) => A2aResponse::result(
+                id,
+                A2aResult::Task(A2aTask {
+                    id: task_context.task_id.clone(),
+                    context_id: task_context.context_id.clone(),
+                    status: TaskStatus::new(TaskState::Completed, None),
+                    artifacts: vec![task_context.artifact(text)],
+                    history: vec![params.message],
+                }),
+            ),
+            Err(err) => {
+                warn!("A2A task {} failed: {}", task_context.task_id, err);
+                A2aResponse::error(Some(id), JSON_RPC_INTERNAL_ERROR, err.to_string())
+            }
+        };
+        return Ok(json_rpc_response(&response));
+    }
+
+    let (tx, rx) = mpsc::channel::<A2aResult>(16);
+    tokio::spawn(async move {
+        let _ = tx
+            .send(task_context.status_update(TaskState::Submitted, None, false))
+            .await;
+        let result = run_agent_chain(
+            &task_context,
+            &agent_selector,
+            &params.message,
+            &trace_collector,
+            Some(&tx),
+        )
+        .await;
+        let final_events = match result {
+            Ok(text) => vec![
+                A2aResult::ArtifactUpdate(TaskArtifactUpdateEvent {
+                    task_id: task_context.task_id.clone(),
+                    context_id: task_context.context_id.clone(),
+                    artifact: task_context.artifact(text),
+                    last_chunk: true,
+                }),
+                task_context.status_update(TaskState::Completed, None, true),
+            ],
+            Err(err) => {
+                warn!("A2A task {} failed: {}", task_context.task_id, err);
+                vec![task_context.status_update(TaskState::Failed, Some(err.to_string()), true)]
+            }
+        };
+        for event in final_events {
+            if tx.send(event).await.is_err() {
+                warn!("A2A stream receiver dropped");
+                break;
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(move |event| {
+        let response = A2aResponse::result(id.clone(), event);
+        let data = format!(
+            "data: {}\n\n",
+            serde_json::to_string(&response).unwrap_or_default()
+        );
+        Ok::<_, hyper::Error>(Frame::data(Bytes::from(data)))
+    });
+    let mut response = Response::new(BoxBody::new(StreamBody::new(stream)));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "text/event-stream".parse().unwrap());
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+    Ok(response)
+}
+
+/// Run the A2A message through agent selection, the filter chains and the terminal
+/// agents, the same way `/agents/v1/chat/completions` does. Every step is reported on
+/// `events` as a working status update; the final agent output is returned.
+async fn run_agent_chain(
+    task_context: &A2aTaskContext,
+    agent_selector: &AgentSelector,
+    message: &A2aMessage,
+    trace_collector: &Arc<TraceCollector>,
+    events: Option<&mpsc::Sender<A2aResult>>,
+) -> Result<String, AgentFilterChainError> {
+    let report = |text: String| async move {
+        if let Some(events) = events {
+            let event = task_context.status_update(TaskState::Working, Some(text), false);
+            if events.send(event).await.is_err() {
+                debug!("A2A stream receiver dropped");
+            }
+        }
+    };
+
+    let mut pipeline_processor = PipelineProcessor::default();
+    let response_handler = ResponseHandler::new();
+    let agent_map = agent_selector.create_agent_map(&task_context.agents);
+
+    let trace_parent = task_context
+        .request_headers
+        .get(TRACE_PARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let (trace_id, parent_span_id) = trace_parent
+        .as_deref()
+        .map(parse_traceparent)
+        .unwrap_or((String::new(), None));
+
+    let mut current_messages = vec![Message {
+        role: Role::User,
+        content: MessageContent::Text(message.text()),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }];
+    let client_request = ProviderRequestType::ChatCompletionsRequest(ChatCompletionsRequest {
+        model: task_context.model.clone(),
+        messages: current_messages.clone(),
+        ..Default::default()
+    });
+
+    let selected_agents = agent_selector
+        .select_agents(&current_messages, &task_context.listener, trace_parent)
+        .await?;
+    report(format!(
+        "selected agents: {}",
+        selected_agents
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    ))
+    .await;
+
+    let mut response_text = String::new();
+    for (agent_index, selected_agent) in selected_agents.iter().enumerate() {
+        let agent_name = selected_agent.id.clone();
+        let agent_start_time = SystemTime::now();
+        let agent_start_instant = Instant::now();
+        let span_id = generate_random_span_id();
+        report(format!("invoking agent {}", agent_name)).await;
+
+        let chat_history = pipeline_processor
+            .process_filter_chain(
+                &current_messages,
+                selected_agent,
+                &agent_map,
+                &task_context.request_headers,
+                None,
+                Some(trace_collector),
+                trace_id.clone(),
+                span_id.clone(),
+            )
+            .await?;
+        let agent = agent_map.get(&agent_name).ok_or_else(|| {
+            super::pipeline_processor::PipelineError::AgentNotFound(agent_name.clone())
+        })?;
+        let llm_response = pipeline_processor
+            .invoke_agent(
+                &chat_history,
+                client_request.clone(),
+                agent,
+                &task_context.request_headers,
+                trace_id.clone(),
+                span_id.clone(),
+                &SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+            )
+            .await?;
+        response_text = response_handler.collect_full_response(llm_response).await?;
+
+        let operation_name = OperationNameBuilder::new()
+            .with_method("POST")
+            .with_path(format!("/agents{}", A2A_PATH))
+            .with_target(&agent_name)
+            .build();
+        let mut span_builder = SpanBuilder::new(&operation_name)
+            .with_span_id(span_id)
+            .with_kind(SpanKind::Internal)
+            .with_start_time(agent_start_time)
+            .with_end_time(SystemTime::now())
+            .with_attribute(http::METHOD, "POST")
+            .with_attribute(http::TARGET, format!("/agents{}", A2A_PATH))
+            .with_attribute("agent.name", agent_name.clone())
+            .with_attribute("a2a.task_id", task_context.task_id.clone())
+            .with_attribute(
+                "agent.sequence",
+                format!("{}/{}", agent_index + 1, selected_agents.len()),
+            )
+            .with_attribute(
+                "duration_ms",
+                format!(
+                    "{:.2}",
+                    agent_start_instant.elapsed().as_secs_f64() * 1000.0
+                ),
+            );
+        if !trace_id.is_empty() {
+            span_builder = span_builder.with_trace_id(trace_id.clone());
+        }
+        if let Some(parent_id) = parent_span_id.clone() {
+            span_builder = span_builder.with_parent_span_id(parent_id);
+        }
+        trace_collector.record_span(operation_component::AGENT, span_builder.build());
+
+        if agent_index + 1 < selected_agents.len() {
+            report(format!("agent {} completed", agent_name)).await;
+            let last_message = current_messages.pop().unwrap();
+            current_messages.push(Message {
+                role: Role::Assistant,
+                content: MessageContent::Text(response_text.clone()),
+                name: Some(agent_name.clone()),
+                tool_calls: None,
+                tool_call_id: None,
+            });
+            current_messages.push(last_message);
+        }
+    }
+
+    Ok(response_text)
+}
+
+fn json_rpc_response(response: &A2aResponse) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = serde_json::to_string(response).unwrap_or_default();
+    let mut response = Response::new(ResponseHandler::create_full_body(body));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::configuration::AgentFilterChain;
+
+    #[test]
+    fn test_parse_message_send_request() {
+        let body = r#"{
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "message/send",
+            "params": {
+                "message": {
+                    "role": "user",
+                    "parts": [{"kind": "text", "text": "book a flight"}, {"kind": "data", "data": {"to": "SFO"}}],
+                    "messageId": "9229e770-767c-417b-a0b0-f0741243c589"
+                },
+                "metadata": {"model": "gpt-4o"}
+            }
+        }"#;
+        let request: A2aRequest = serde_json::from_str(body).unwrap();
+        assert_eq!(request.method, A2A_MESSAGE_SEND);
+        let message = request.params.unwrap().message;
+        assert_eq!(message.role, A2aRole::User);
+        assert_eq!(message.text(), "book a flight\n{\"to\":\"SFO\"}");
+    }
+
+    #[test]
+    fn test_status_update_serialization() {
+        let event = A2aResult::StatusUpdate(TaskStatusUpdateEvent {
+            task_id: "task-1".to_string(),
+            context_id: "ctx-1".to_string(),
+            status: TaskStatus::new(TaskState::Working, None),
+            is_final: false,
+        });
+        let value = serde_json::to_value(A2aResponse::result(JsonRpcId::Number(1), event)).unwrap();
+        assert_eq!(value["result"]["kind"], "status-update");
+        assert_eq!(value["result"]["taskId"], "task-1");
+        assert_eq!(value["result"]["status"]["state"], "working");
+        assert_eq!(value["result"]["final"], false);
+        assert!(value.get("error").is_none());
+    }
+
+    #[test]
+    fn test_build_agent_card() {
+        let listener = Listener {
+            name: "travel".to_string(),
+            router: None,
+            agents: Some(vec![AgentFilterChain {
+                id: "flight_agent".to_string(),
+                default: None,
+                description: Some("books flights".to_string()),
+                filter_chain: None,
+                stream_filter_chain: None,
+            }]),
+            port: 8001,
+            allowed_apis: None,
+            default_provider: None,
+            passthrough_auth: None,
+            embedding_router: None,
+            oidc: None,
+        };
+        let card = build_agent_card(&listener, "localhost:8001");
+        assert_eq!(card["url"], "http://localhost:8001/a2a");
+        assert_eq!(card["skills"][0]["id"], "flight_agent");
+        assert_eq!(card["capabilities"]["streaming"], true);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// Tokens are minted again this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetime of tokens minted without an `expires_in`
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Token response of the GCP metadata server and OAuth2 token endpoints
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessTokenError {
+    #[error("failed to get an access token from {url}: {reason}")]
+    Request { url: String, reason: String },
+}
+
+/// Process wide cache of the access tokens of providers authenticated with a service account
+pub fn access_tokens() -> &'static AccessTokens {
+    static TOKENS: OnceLock<AccessTokens> = OnceLock::new();
+    TOKENS.get_or_init(AccessTokens::default)
+}
+
+/// Access tokens by token url, minted by the GCP metadata server or a token sidecar that
+/// runs the service account JWT assertion flow
+#[derive(Default)]
+pub struct AccessTokens {
+    client: reqwest::Client,
+    tokens: Mutex<HashMap<String, CachedToken>>,
+}
+
+impl AccessTokens {
+    /// Cached token of the url, minted again when it is about to expire
+    pub async fn token(&self, url: &str) -> Result<String, AccessTokenError> {
+        // held while minting so concurrent requests wait for the same token
+        let mut tokens = self.tokens.lock().await;
+        if let Some(token) = tokens.get(url) {
+            if Instant::now() + EXPIRY_MARGIN < token.expires_at {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let token = self.mint(url).await?;
+        info!("minted an access token from {}", url);
+        let access_token = token.access_token.clone();
+        tokens.insert(url.to_string(), token);
+        Ok(access_token)
+    }
+
+    async fn mint(&self, url: &str) -> Result<CachedToken, AccessTokenError> {
+        let error = |reason: String| AccessTokenError::Request {
+            url: url.to_string(),
+            reason,
+        };
+        let response = self
+            .client
+            .get(url)
+            // required by the GCP metadata server, ignored by sidecars
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+        if !response.status().is_success() {
+            return Err(error(format!("status {}", response.status())));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+        parse_token(&body, Instant::now()).map_err(|err| error(err.to_string()))
+    }
+}
+
+fn parse_token(body: &[u8], now: Instant) -> Result<CachedToken, serde_json::Error> {
+    let response: TokenResponse = serde_json::from_slice(body)?;
+    let lifetime = response
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+    Ok(CachedToken {
+        access_token: response.access_token,
+        expires_at: now + lifetime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_token() {
+        let now = Instant::now();
+        let token = parse_token(
+            br#"{"access_token":"ya29.token","expires_in":3599,"token_type":"Bearer"}"#,
+            now,
+        )
+        .unwrap();
+        assert_eq!(token.access_token, "ya29.token");
+        assert_eq!(token.expires_at, now + Duration::from_secs(3599));
+
+        let token = parse_token(br#"{"access_token":"sidecar"}"#, now).unwrap();
+        assert_eq!(token.expires_at, now + DEFAULT_TOKEN_LIFETIME);
+
+        assert!(parse_token(br#"{"error":"invalid_grant"}"#, now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_token_is_reused() {
+        let tokens = AccessTokens::default();
+        tokens.tokens.lock().await.insert(
+            "http://token-sidecar/token".to_string(),
+            CachedToken {
+                access_token: "cached".to_string(),
+                expires_at: Instant::now() + Duration::from_secs(600),
+            },
+        );
+        assert_eq!(
+            tokens.token("http://token-sidecar/token").await.unwrap(),
+            "cached"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use hyper::header::{self, HeaderMap};
+
+/// Bearer token of the debug and admin endpoints, `overrides.admin_token`. Without one
+/// configured no request is authorized.
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn new(token: Option<&str>) -> Self {
+        AdminToken(token.filter(|token| !token.is_empty()).map(str::to_string))
+    }
+
+    /// Whether the request carries the admin token as a bearer token
+    pub fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(expected) = &self.0 else {
+            return false;
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+    }
+}
+
+/// Compares without returning at the first differing byte, so the time taken doesn't
+/// tell how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn bearer(token: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static(token));
+        headers
+    }
+
+    #[test]
+    fn test_requires_the_configured_bearer_token() {
+        let admin_token = AdminToken::new(Some("secret"));
+        assert!(admin_token.is_authorized(&bearer("Bearer secret")));
+        assert!(!admin_token.is_authorized(&bearer("Bearer secreT")));
+        assert!(!admin_token.is_authorized(&bearer("Bearer secret2")));
+        assert!(!admin_token.is_authorized(&bearer("secret")));
+        assert!(!admin_token.is_authorized(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_nothing_is_authorized_without_a_token() {
+        assert!(!AdminToken::new(None).is_authorized(&bearer("Bearer ")));
+        assert!(!AdminToken::new(Some("")).is_authorized(&bearer("Bearer ")));
+    }
+}
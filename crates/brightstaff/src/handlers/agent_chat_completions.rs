@@ -2,10 +2,13 @@ use std::sync::Arc;
 use std::time::{Instant, SystemTime};
 
 use bytes::Bytes;
-use common::consts::TRACE_PARENT_HEADER;
+use common::consts::{
+    ARCH_AGENT_LISTENER_NAME_HEADER, ARCH_AGENT_ROUTING_HEADER, TRACE_PARENT_HEADER,
+};
 use common::traces::{generate_random_span_id, parse_traceparent, SpanBuilder, SpanKind};
-use hermesllm::apis::OpenAIMessage;
-use hermesllm::clients::SupportedAPIsFromClient;
+use hermesllm::apis::openai::{MessageContent, Role};
+use hermesllm::apis::{OpenAIApi, OpenAIMessage};
+use hermesllm::clients::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
 use hermesllm::providers::request::ProviderRequest;
 use hermesllm::ProviderRequestType;
 use http_body_util::combinators::BoxBody;
@@ -16,8 +19,16 @@ use tracing::{debug, info, warn};
 
 use super::agent_selector::{AgentSelectionError, AgentSelector};
 use super::pipeline_processor::{PipelineError, PipelineProcessor};
-use super::response_handler::ResponseHandler;
+use super::response_handler::{normalize_response, ResponseHandler};
+use super::retrieval::{self, with_citations};
+use super::session_memory::{summary_message, SessionSummarizer};
+use super::sessions::{assistant_text, session_id_from_headers};
+use super::stream_filter::create_filtered_streaming_response;
+use super::utils::{keep_alive_response, KeepAlive};
+use crate::router::embedding_router::RoutingDecision;
 use crate::router::plano_orchestrator::OrchestratorService;
+use crate::state::agent_session::AgentSessionStore;
+use crate::state::StateStorageError;
 use crate::tracing::{http, operation_component, OperationNameBuilder};
 
 /// Main errors for agent chat completions
@@ -33,110 +44,262 @@ pub enum AgentFilterChainError {
     RequestParsing(#[from] serde_json::Error),
     #[error("HTTP error: {0}")]
     Http(#[from] hyper::Error),
+    #[error("Session storage error: {0}")]
+    Session(#[from] StateStorageError),
+    #[error("agent session {0} belongs to another listener")]
+    SessionListener(String),
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn agent_chat(
     request: Request<hyper::body::Incoming>,
     orchestrator_service: Arc<OrchestratorService>,
-    _: String,
+    llm_provider_url: String,
     agents_list: Arc<tokio::sync::RwLock<Option<Vec<common::configuration::Agent>>>>,
     listeners: Arc<tokio::sync::RwLock<Vec<common::configuration::Listener>>>,
     trace_collector: Arc<common::traces::TraceCollector>,
+    session_store: Option<Arc<dyn AgentSessionStore>>,
+    session_summarizer: Option<Arc<SessionSummarizer>>,
+    keep_alive: Option<KeepAlive>,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
-    match handle_agent_chat(
-        request,
-        orchestrator_service,
-        agents_list,
-        listeners,
-        trace_collector,
-    )
-    .await
+    let (parts, body) = request.into_parts();
+    let body = body.collect().await?.to_bytes();
+    // streaming clients are answered right away and kept alive while the filter chain runs
+    let keep_alive = keep_alive.filter(|_| is_streaming_body(&body));
+    let chat = async move {
+        match handle_agent_chat(
+            Request::from_parts(parts, body),
+            orchestrator_service,
+            llm_provider_url,
+            agents_list,
+            listeners,
+            trace_collector,
+            session_store,
+            session_summarizer,
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(err) => agent_chat_error_response(err),
+        }
+    };
+    match keep_alive {
+        Some(keep_alive) => Ok(keep_alive_response(chat, keep_alive)),
+        None => Ok(chat.await),
+    }
+}
+
+fn is_streaming_body(body: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body.get("stream").and_then(serde_json::Value::as_bool))
+        .unwrap_or(false)
+}
+
+fn agent_chat_error_response(err: AgentFilterChainError) -> Response<BoxBody<Bytes, hyper::Error>> {
+    // Check if this is a client error from the pipeline that should be cascaded
+    if let AgentFilterChainError::Pipeline(PipelineError::ClientError {
+        agent,
+        status,
+        body,
+    }) = &err
     {
-        Ok(response) => Ok(response),
-        Err(err) => {
-            // Check if this is a client error from the pipeline that should be cascaded
-            if let AgentFilterChainError::Pipeline(PipelineError::ClientError {
-                agent,
-                status,
-                body,
-            }) = &err
-            {
-                warn!(
-                    "Client error from agent '{}' (HTTP {}): {}",
-                    agent, status, body
-                );
+        warn!(
+            "Client error from agent '{}' (HTTP {}): {}",
+            agent, status, body
+        );
 
-                // Create error response with the original status code and body
-                let error_json = serde_json::json!({
-                    "error": "ClientError",
-                    "agent": agent,
-                    "status": status,
-                    "agent_response": body
-                });
+        // Create error response with the original status code and body
+        let error_json = serde_json::json!({
+            "error": "ClientError",
+            "agent": agent,
+            "status": status,
+            "agent_response": body
+        });
 
-                let json_string = error_json.to_string();
-                let mut response = Response::new(ResponseHandler::create_full_body(json_string));
-                *response.status_mut() =
-                    hyper::StatusCode::from_u16(*status).unwrap_or(hyper::StatusCode::BAD_REQUEST);
-                response.headers_mut().insert(
-                    hyper::header::CONTENT_TYPE,
-                    "application/json".parse().unwrap(),
-                );
-                return Ok(response);
-            }
+        let json_string = error_json.to_string();
+        let mut response = Response::new(ResponseHandler::create_full_body(json_string));
+        *response.status_mut() =
+            hyper::StatusCode::from_u16(*status).unwrap_or(hyper::StatusCode::BAD_REQUEST);
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            "application/json".parse().unwrap(),
+        );
+        return response;
+    }
 
-            // Print detailed error information with full error chain for other errors
-            let mut error_chain = Vec::new();
-            let mut current_error: &dyn std::error::Error = &err;
+    // Agents that cannot be reached are an upstream failure, not ours
+    if let AgentFilterChainError::Pipeline(
+        PipelineError::SessionInitFailed { agent, .. } | PipelineError::Transport { agent, .. },
+    ) = &err
+    {
+        warn!("Agent '{}' unavailable: {}", agent, err);
+        let error_json = serde_json::json!({
+            "error": {
+                "type": "AgentUnavailable",
+                "agent": agent,
+                "message": err.to_string(),
+            }
+        });
+        let mut response = ResponseHandler::create_json_error_response(&error_json);
+        *response.status_mut() = hyper::StatusCode::BAD_GATEWAY;
+        return response;
+    }
 
-            // Collect the full error chain
-            loop {
-                error_chain.push(current_error.to_string());
-                match current_error.source() {
-                    Some(source) => current_error = source,
-                    None => break,
-                }
+    // Filters whose tool schema no longer matches the calls we send are misconfigured
+    // upstream, the client can't fix the request
+    if let AgentFilterChainError::Pipeline(PipelineError::InvalidToolArguments {
+        agent,
+        tool,
+        ..
+    }) = &err
+    {
+        warn!("{}", err);
+        let error_json = serde_json::json!({
+            "error": {
+                "type": "InvalidToolArguments",
+                "agent": agent,
+                "tool": tool,
+                "message": err.to_string(),
             }
+        });
+        let mut response = ResponseHandler::create_json_error_response(&error_json);
+        *response.status_mut() = hyper::StatusCode::BAD_GATEWAY;
+        return response;
+    }
 
-            // Log the complete error chain
-            warn!("Agent chat error chain: {:#?}", error_chain);
-            warn!("Root error: {:?}", err);
+    // Sessions are only continued on the listener they were created for
+    if let AgentFilterChainError::SessionListener(_) = &err {
+        warn!("{}", err);
+        let error_json = serde_json::json!({
+            "error": {
+                "type": "SessionListenerMismatch",
+                "message": err.to_string(),
+            }
+        });
+        let mut response = ResponseHandler::create_json_error_response(&error_json);
+        *response.status_mut() = hyper::StatusCode::FORBIDDEN;
+        return response;
+    }
 
-            // Create structured error response as JSON
+    // Calls that were not approved are reported as such, not as gateway errors
+    if let AgentFilterChainError::Pipeline(pipeline_error) = &err {
+        let approval = match pipeline_error {
+            PipelineError::ApprovalRejected {
+                agent, approval_id, ..
+            } => Some((
+                "ApprovalRejected",
+                hyper::StatusCode::FORBIDDEN,
+                agent,
+                approval_id,
+            )),
+            PipelineError::ApprovalTimeout { agent, approval_id } => Some((
+                "ApprovalTimeout",
+                hyper::StatusCode::GATEWAY_TIMEOUT,
+                agent,
+                approval_id,
+            )),
+            _ => None,
+        };
+        if let Some((error_type, status, agent, approval_id)) = approval {
+            info!("{}", pipeline_error);
             let error_json = serde_json::json!({
                 "error": {
-                    "type": "AgentFilterChainError",
-                    "message": err.to_string(),
-                    "error_chain": error_chain,
-                    "debug_info": format!("{:?}", err)
+                    "type": error_type,
+                    "agent": agent,
+                    "approval_id": approval_id,
+                    "message": pipeline_error.to_string(),
                 }
             });
+            let mut response = ResponseHandler::create_json_error_response(&error_json);
+            *response.status_mut() = status;
+            return response;
+        }
+    }
+
+    // Print detailed error information with full error chain for other errors
+    let mut error_chain = Vec::new();
+    let mut current_error: &dyn std::error::Error = &err;
 
-            // Log the error for debugging
-            info!("Structured error info: {}", error_json);
+    // Collect the full error chain
+    loop {
+        error_chain.push(current_error.to_string());
+        match current_error.source() {
+            Some(source) => current_error = source,
+            None => break,
+        }
+    }
 
-            // Return JSON error response
-            Ok(ResponseHandler::create_json_error_response(&error_json))
+    // Log the complete error chain
+    warn!("Agent chat error chain: {:#?}", error_chain);
+    warn!("Root error: {:?}", err);
+
+    // Create structured error response as JSON
+    let error_json = serde_json::json!({
+        "error": {
+            "type": "AgentFilterChainError",
+            "message": err.to_string(),
+            "error_chain": error_chain,
+            "debug_info": format!("{:?}", err)
         }
+    });
+
+    // Log the error for debugging
+    info!("Structured error info: {}", error_json);
+
+    // Return JSON error response
+    ResponseHandler::create_json_error_response(&error_json)
+}
+
+/// Converts a chat completions response of the pipeline to the api of the client
+fn to_client_api(
+    response: reqwest::Response,
+    api_type: &SupportedAPIsFromClient,
+) -> reqwest::Response {
+    normalize_response(
+        response,
+        api_type,
+        &SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+    )
+}
+
+/// Report the embedding routing decision to the client in the agent routing header
+fn with_routing_decision(
+    mut response: Response<BoxBody<Bytes, hyper::Error>>,
+    decision: Option<&RoutingDecision>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    if let Some(value) = decision
+        .and_then(|decision| serde_json::to_string(decision).ok())
+        .and_then(|json| hyper::header::HeaderValue::from_str(&json).ok())
+    {
+        response
+            .headers_mut()
+            .insert(ARCH_AGENT_ROUTING_HEADER, value);
     }
+    response
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_agent_chat(
-    request: Request<hyper::body::Incoming>,
+    request: Request<Bytes>,
     orchestrator_service: Arc<OrchestratorService>,
+    llm_provider_url: String,
     agents_list: Arc<tokio::sync::RwLock<Option<Vec<common::configuration::Agent>>>>,
     listeners: Arc<tokio::sync::RwLock<Vec<common::configuration::Listener>>>,
     trace_collector: Arc<common::traces::TraceCollector>,
+    session_store: Option<Arc<dyn AgentSessionStore>>,
+    session_summarizer: Option<Arc<SessionSummarizer>>,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, AgentFilterChainError> {
     // Initialize services
     let agent_selector = AgentSelector::new(orchestrator_service);
-    let mut pipeline_processor = PipelineProcessor::default();
+    let mut pipeline_processor =
+        PipelineProcessor::default().with_llm_provider_url(llm_provider_url);
     let response_handler = ResponseHandler::new();
 
     // Extract listener name from headers
     let listener_name = request
         .headers()
-        .get("x-arch-agent-listener-name")
+        .get(ARCH_AGENT_LISTENER_NAME_HEADER)
         .and_then(|name| name.to_str().ok());
 
     // Find the appropriate listener
@@ -158,7 +321,7 @@ async fn handle_agent_chat(
         .unwrap()
         .to_string();
     let request_headers = request.headers().clone();
-    let chat_request_bytes = request.collect().await?.to_bytes();
+    let chat_request_bytes = request.into_body();
 
     debug!(
         "Received request body (raw utf8): {}",
@@ -184,7 +347,44 @@ async fn handle_agent_chat(
         }
     };
 
-    let message: Vec<OpenAIMessage> = client_request.get_messages();
+    let new_messages: Vec<OpenAIMessage> = client_request.get_messages();
+
+    // With a session, prior turns are restored so clients only send the new ones
+    let session = session_store
+        .clone()
+        .zip(session_id_from_headers(&request_headers));
+    let message: Vec<OpenAIMessage> = match &session {
+        Some((store, session_id)) => {
+            // summarized sessions start with their summary, followed by the recent turns
+            let mut history = match store.get(session_id).await {
+                Ok(stored)
+                    if stored
+                        .listener
+                        .as_deref()
+                        .is_some_and(|name| name != listener.name) =>
+                {
+                    return Err(AgentFilterChainError::SessionListener(session_id.clone()));
+                }
+                Ok(stored) => stored
+                    .summary
+                    .as_deref()
+                    .map(summary_message)
+                    .into_iter()
+                    .chain(stored.messages)
+                    .collect(),
+                Err(StateStorageError::NotFound(_)) => Vec::new(),
+                Err(err) => return Err(err.into()),
+            };
+            info!(
+                "Restored {} message(s) from agent session {}",
+                history.len(),
+                session_id
+            );
+            history.extend(new_messages.iter().cloned());
+            history
+        }
+        None => new_messages.clone(),
+    };
 
     // Extract trace parent for routing
     let trace_parent = request_headers
@@ -211,8 +411,8 @@ async fn handle_agent_chat(
     let selection_start_time = SystemTime::now();
     let selection_start_instant = Instant::now();
 
-    let selected_agents = agent_selector
-        .select_agents(&message, &listener, trace_parent.clone())
+    let (selected_agents, routing_decision) = agent_selector
+        .select_agents_with_decision(&message, &listener, trace_parent.clone())
         .await?;
 
     // Record agent selection span
@@ -246,6 +446,19 @@ async fn handle_agent_chat(
             format!("{:.2}", selection_elapsed.as_secs_f64() * 1000.0),
         );
 
+    if let Some(decision) = routing_decision.as_ref() {
+        selection_span_builder = selection_span_builder
+            .with_attribute("selection.router", decision.router.clone())
+            .with_attribute("selection.model", decision.model.clone())
+            .with_attribute("selection.score", format!("{:.4}", decision.score))
+            .with_attribute("selection.threshold", format!("{:.4}", decision.threshold))
+            .with_attribute("selection.fallback", decision.fallback.to_string())
+            .with_attribute(
+                "selection.candidates",
+                serde_json::to_string(&decision.candidates).unwrap_or_default(),
+            );
+    }
+
     if !trace_id.is_empty() {
         selection_span_builder = selection_span_builder.with_trace_id(trace_id.clone());
     }
@@ -287,14 +500,29 @@ async fn handle_agent_chat(
                 selected_agent,
                 &agent_map,
                 &request_headers,
+                client_request.metadata().as_ref(),
                 Some(&trace_collector),
                 trace_id.clone(),
                 span_id.clone(),
             )
             .await?;
+        // only the chunks retrieved for the last agent are cited in the response
+        let citations = pipeline_processor.take_citations();
 
         // Get agent details and invoke
         let agent = agent_map.get(&agent_name).unwrap();
+        let stream_filter_chain = selected_agent
+            .stream_filter_chain
+            .as_ref()
+            .filter(|chain| !chain.is_empty());
+        // the pipeline works on chat completions, only the response of the last agent is
+        // returned to the client as is
+        let response_api = if is_last_agent && citations.is_empty() && stream_filter_chain.is_none()
+        {
+            api_type.clone()
+        } else {
+            SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions)
+        };
 
         debug!("Invoking agent: {}", agent_name);
 
@@ -306,6 +534,7 @@ async fn handle_agent_chat(
                 &request_headers,
                 trace_id.clone(),
                 span_id.clone(),
+                &response_api,
             )
             .await?;
 
@@ -352,10 +581,86 @@ async fn handle_agent_chat(
                 "Completed agent chain, returning response from last agent: {}",
                 agent_name
             );
-            return response_handler
+            let llm_response = if citations.is_empty() {
+                llm_response
+            } else {
+                with_citations(llm_response, &retrieval::citations(&citations))
+                    .await
+                    .map_err(PipelineError::from)?
+            };
+            if let Some(stream_filter_chain) = stream_filter_chain {
+                let stream_filters = stream_filter_chain
+                    .iter()
+                    .map(|name| {
+                        agent_map
+                            .get(name)
+                            .cloned()
+                            .ok_or_else(|| PipelineError::AgentNotFound(name.clone()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                info!(
+                    "Streaming response of agent {} through {} stream filter(s)",
+                    agent_name,
+                    stream_filters.len()
+                );
+                let response = create_filtered_streaming_response(
+                    llm_response,
+                    stream_filters,
+                    std::mem::take(&mut pipeline_processor),
+                    request_headers.clone(),
+                    trace_id.clone(),
+                    parent_span_id.clone().unwrap_or_default(),
+                )?;
+                let response =
+                    response.map(|body| reqwest::Body::wrap_stream(body.into_data_stream()));
+                let response = response_handler
+                    .create_streaming_response(to_client_api(
+                        reqwest::Response::from(response),
+                        &api_type,
+                    ))
+                    .await?;
+                return Ok(with_routing_decision(response, routing_decision.as_ref()));
+            }
+            let llm_response = if response_api == api_type {
+                llm_response
+            } else {
+                to_client_api(llm_response, &api_type)
+            };
+            // Record the turn in the session once the response has been streamed.
+            // Responses going through stream filters above are not recorded.
+            if let Some((store, session_id)) = session {
+                let (response, response_text) = response_handler
+                    .create_streaming_response_with_capture(llm_response)
+                    .await?;
+                let listener_name = listener.name.clone();
+                tokio::spawn(async move {
+                    let Ok(response_text) = response_text.await else {
+                        return;
+                    };
+                    let mut turn = new_messages;
+                    turn.push(OpenAIMessage {
+                        role: Role::Assistant,
+                        content: MessageContent::Text(assistant_text(&response_text)),
+                        name: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    });
+                    if let Err(err) = store.append(&session_id, Some(&listener_name), turn).await {
+                        warn!("Failed to update agent session {}: {}", session_id, err);
+                        return;
+                    }
+                    if let Some(summarizer) = session_summarizer {
+                        if let Err(err) = summarizer.summarize(&*store, &session_id, false).await {
+                            warn!("Failed to summarize agent session {}: {}", session_id, err);
+                        }
+                    }
+                });
+                return Ok(with_routing_decision(response, routing_decision.as_ref()));
+            }
+            let response = response_handler
                 .create_streaming_response(llm_response)
-                .await
-                .map_err(AgentFilterChainError::from);
+                .await?;
+            return Ok(with_routing_decision(response, routing_decision.as_ref()));
         }
 
         // For intermediate agents, collect the full response and pass to next agent
@@ -7,6 +7,7 @@ use common::configuration::{
 use hermesllm::apis::openai::Message;
 use tracing::{debug, warn};
 
+use crate::router::embedding_router::RoutingDecision;
 use crate::router::plano_orchestrator::OrchestratorService;
 
 /// Errors that can occur during agent selection
@@ -22,8 +23,13 @@ pub enum AgentSelectionError {
     McpError(String),
     #[error("Orchestration service error: {0}")]
     OrchestrationError(String),
+    #[error("Embedding router error: {0}")]
+    EmbeddingRouterError(String),
 }
 
+/// Listener `router` value selecting embedding based agent routing
+pub const EMBEDDING_ROUTER: &str = "embedding";
+
 /// Service for selecting agents based on orchestration preferences and listener configuration
 pub struct AgentSelector {
     orchestrator_service: Arc<OrchestratorService>,
@@ -110,6 +116,19 @@ impl AgentSelector {
         listener: &Listener,
         trace_parent: Option<String>,
     ) -> Result<Vec<AgentFilterChain>, AgentSelectionError> {
+        self.select_agents_with_decision(messages, listener, trace_parent)
+            .await
+            .map(|(agents, _)| agents)
+    }
+
+    /// Select agents like `select_agents`, also returning the routing decision when
+    /// the listener routes on embeddings
+    pub async fn select_agents_with_decision(
+        &self,
+        messages: &[Message],
+        listener: &Listener,
+        trace_parent: Option<String>,
+    ) -> Result<(Vec<AgentFilterChain>, Option<RoutingDecision>), AgentSelectionError> {
         let agents = listener
             .agents
             .as_ref()
@@ -118,9 +137,63 @@ impl AgentSelector {
         // If only one agent, skip orchestration
         if agents.len() == 1 {
             debug!("Only one agent available, skipping orchestration");
-            return Ok(vec![agents[0].clone()]);
+            return Ok((vec![agents[0].clone()], None));
+        }
+
+        if listener.router.as_deref() == Some(EMBEDDING_ROUTER) {
+            return self
+                .select_agent_by_embedding(messages, listener, agents, trace_parent)
+                .await;
         }
 
+        self.select_agents_by_orchestration(messages, listener, agents, trace_parent)
+            .await
+            .map(|agents| (agents, None))
+    }
+
+    async fn select_agent_by_embedding(
+        &self,
+        messages: &[Message],
+        listener: &Listener,
+        agents: &[AgentFilterChain],
+        trace_parent: Option<String>,
+    ) -> Result<(Vec<AgentFilterChain>, Option<RoutingDecision>), AgentSelectionError> {
+        let config = listener.embedding_router.as_ref().ok_or_else(|| {
+            AgentSelectionError::EmbeddingRouterError(format!(
+                "listener {} uses the embedding router without embedding_router settings",
+                listener.name
+            ))
+        })?;
+        let router = self
+            .orchestrator_service
+            .embedding_router()
+            .ok_or_else(|| {
+                AgentSelectionError::EmbeddingRouterError(
+                    "embedding router not enabled".to_string(),
+                )
+            })?;
+        let default_agent = self.get_default_agent(agents, &listener.name)?;
+
+        match router
+            .route(messages, agents, &default_agent, config, trace_parent)
+            .await
+            .map_err(|err| AgentSelectionError::EmbeddingRouterError(err.to_string()))?
+        {
+            Some((agent, decision)) => Ok((vec![agent], Some(decision))),
+            None => {
+                debug!("No user text to route on, using default agent");
+                Ok((vec![default_agent], None))
+            }
+        }
+    }
+
+    async fn select_agents_by_orchestration(
+        &self,
+        messages: &[Message],
+        listener: &Listener,
+        agents: &[AgentFilterChain],
+        trace_parent: Option<String>,
+    ) -> Result<Vec<AgentFilterChain>, AgentSelectionError> {
         let usage_preferences = self
             .convert_agent_description_to_orchestration_preferences(agents)
             .await;
@@ -186,7 +259,8 @@ mod tests {
             id: name.to_string(),
             description: Some(description.to_string()),
             default: Some(is_default),
-            filter_chain: Some(vec![name.to_string()]),
+            filter_chain: Some(vec![name.into()]),
+            stream_filter_chain: None,
         }
     }
 
@@ -196,6 +270,11 @@ mod tests {
             agents: Some(agents),
             port: 8080,
             router: None,
+            allowed_apis: None,
+            default_provider: None,
+            passthrough_auth: None,
+            embedding_router: None,
+            oidc: None,
         }
     }
 
@@ -206,6 +285,17 @@ mod tests {
             url: "http://localhost:8080".to_string(),
             tool: None,
             transport: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
         }
     }
 
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use common::configuration::Agent;
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::{Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::admin::AdminToken;
+use super::pipeline_processor::PipelineError;
+use super::response_handler::ResponseHandler;
+
+/// How long a call waits for an approval when the agent does not configure it
+pub const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Process wide pending approvals, pipelines wait on them while the decision comes in
+/// on a separate request.
+pub fn approval_registry() -> &'static ApprovalRegistry {
+    static APPROVALS: OnceLock<ApprovalRegistry> = OnceLock::new();
+    APPROVALS.get_or_init(ApprovalRegistry::default)
+}
+
+/// Call waiting for a decision, as reported to webhooks and on /agents/approvals
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingApproval {
+    pub approval_id: String,
+    pub agent: String,
+    pub tool: Option<String>,
+    /// What the agent is about to be called with
+    pub payload: serde_json::Value,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApprovalDecision {
+    pub approved: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApprovalEvent<'a> {
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    #[serde(flatten)]
+    approval: &'a PendingApproval,
+}
+
+#[derive(Debug, Default)]
+pub struct ApprovalRegistry {
+    pending: Mutex<HashMap<String, (PendingApproval, oneshot::Sender<ApprovalDecision>)>>,
+}
+
+impl ApprovalRegistry {
+    pub fn request(
+        &self,
+        agent: &str,
+        tool: Option<&str>,
+        payload: serde_json::Value,
+        timeout: Duration,
+    ) -> (PendingApproval, oneshot::Receiver<ApprovalDecision>) {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let approval = PendingApproval {
+            approval_id: Uuid::new_v4().to_string(),
+            agent: agent.to_string(),
+            tool: tool.map(str::to_string),
+            payload,
+            created_at,
+            expires_at: created_at + timeout.as_secs() as i64,
+        };
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(approval.approval_id.clone(), (approval.clone(), tx));
+        (approval, rx)
+    }
+
+    /// Deliver a decision to the waiting call, false when there is none
+    pub fn resolve(&self, approval_id: &str, decision: ApprovalDecision) -> bool {
+        match self.pending.lock().unwrap().remove(approval_id) {
+            Some((_, tx)) => tx.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, approval_id: &str) -> Option<PendingApproval> {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(approval_id)
+            .map(|(approval, _)| approval.clone())
+    }
+
+    pub fn list(&self) -> Vec<PendingApproval> {
+        let mut approvals: Vec<PendingApproval> = self
+            .pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(approval, _)| approval.clone())
+            .collect();
+        approvals.sort_by_key(|approval| approval.created_at);
+        approvals
+    }
+
+    fn remove(&self, approval_id: &str) {
+        self.pending.lock().unwrap().remove(approval_id);
+    }
+}
+
+/// Block a call to an agent marked `requires_approval` until it is approved.
+/// The pending approval is posted to the agent's webhook and listed on
+/// /agents/approvals; rejections and timeouts fail the call.
+pub async fn await_approval(
+    client: &reqwest::Client,
+    agent: &Agent,
+    payload: serde_json::Value,
+) -> Result<(), PipelineError> {
+    if !agent.requires_approval.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let timeout = agent
+        .approval_timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_APPROVAL_TIMEOUT);
+    let registry = approval_registry();
+    let (approval, decision) = registry.request(&agent.id, agent.tool.as_deref(), payload, timeout);
+    info!(
+        "call to agent {} waiting for approval {}",
+        agent.id, approval.approval_id
+    );
+
+    if let Some(webhook) = agent.approval_webhook.as_ref() {
+        let event = ApprovalEvent {
+            event_type: "approval.pending",
+            approval: &approval,
+        };
+        if let Err(err) = client
+            .post(webhook)
+            .json(&event)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            warn!(
+                "failed to notify approval webhook of agent {}: {}",
+                agent.id, err
+            );
+        }
+    }
+
+    match tokio::time::timeout(timeout, decision).await {
+        Ok(Ok(decision)) if decision.approved => {
+            info!("approval {} granted", approval.approval_id);
+            Ok(())
+        }
+        Ok(Ok(decision)) => Err(PipelineError::ApprovalRejected {
+            agent: agent.id.clone(),
+            approval_id: approval.approval_id,
+            reason: decision.reason.unwrap_or_default(),
+        }),
+        Ok(Err(_)) | Err(_) => {
+            registry.remove(&approval.approval_id);
+            Err(PipelineError::ApprovalTimeout {
+                agent: agent.id.clone(),
+                approval_id: approval.approval_id,
+            })
+        }
+    }
+}
+
+/// Handles `/agents/approvals` and `/agents/approvals/{id}`: GET lists pending
+/// approvals or returns one, POST with `{"approved": bool, "reason": ...}` decides it.
+/// Requires the admin token as a bearer token.
+pub async fn agent_approvals<B>(
+    request: Request<B>,
+    approval_id: Option<&str>,
+    admin_token: &AdminToken,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error>
+where
+    B: hyper::body::Body<Data = Bytes, Error = hyper::Error>,
+{
+    if !admin_token.is_authorized(request.headers()) {
+        return Ok(ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        ));
+    }
+    let registry = approval_registry();
+    let not_found = |approval_id: &str| {
+        ResponseHandler::create_json_response(
+            StatusCode::NOT_FOUND,
+            &serde_json::json!({ "error": format!("no pending approval {}", approval_id) }),
+        )
+    };
+
+    match (request.method().clone(), approval_id) {
+        (Method::GET, None) => Ok(ResponseHandler::create_json_response(
+            StatusCode::OK,
+            &serde_json::json!({ "object": "list", "data": registry.list() }),
+        )),
+        (Method::GET, Some(approval_id)) => Ok(match registry.get(approval_id) {
+            Some(approval) => ResponseHandler::create_json_response(StatusCode::OK, &approval),
+            None => not_found(approval_id),
+        }),
+        (Method::POST, Some(approval_id)) => {
+            let body = request.collect().await?.to_bytes();
+            let decision: ApprovalDecision = match serde_json::from_slice(&body) {
+                Ok(decision) => decision,
+                Err(err) => {
+                    return Ok(ResponseHandler::create_bad_request(&format!(
+                        "invalid approval decision: {}",
+                        err
+                    )))
+                }
+            };
+            let approved = decision.approved;
+            if !registry.resolve(approval_id, decision) {
+                return Ok(not_found(approval_id));
+            }
+            info!(
+                "approval {} {}",
+                approval_id,
+                if approved { "approved" } else { "rejected" }
+            );
+            Ok(ResponseHandler::create_json_response(
+                StatusCode::OK,
+                &serde_json::json!({ "approval_id": approval_id, "approved": approved }),
+            ))
+        }
+        _ => Ok(ResponseHandler::create_error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(requires_approval: bool, timeout_ms: u64) -> Agent {
+        Agent {
+            id: "payments".to_string(),
+            transport: None,
+            tool: Some("refund".to_string()),
+            url: "http://localhost".to_string(),
+            agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: Some(requires_approval),
+            approval_webhook: None,
+            approval_timeout_ms: Some(timeout_ms),
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
+        }
+    }
+
+    async fn wait_for_pending(agent: &str) -> PendingApproval {
+        loop {
+            if let Some(approval) = approval_registry()
+                .list()
+                .into_iter()
+                .find(|approval| approval.agent == agent)
+            {
+                return approval;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approval_granted_and_rejected() {
+        let client = reqwest::Client::new();
+        let agent = agent(true, 5_000);
+
+        let call = tokio::spawn({
+            let client = client.clone();
+            let agent = agent.clone();
+            async move { await_approval(&client, &agent, serde_json::json!({"amount": 10})).await }
+        });
+        let pending = wait_for_pending("payments").await;
+        assert_eq!(pending.tool.as_deref(), Some("refund"));
+        assert!(approval_registry().resolve(
+            &pending.approval_id,
+            ApprovalDecision {
+                approved: true,
+                reason: None
+            }
+        ));
+        assert!(call.await.unwrap().is_ok());
+
+        let call = tokio::spawn({
+            let client = client.clone();
+            let agent = agent.clone();
+            async move { await_approval(&client, &agent, serde_json::json!({})).await }
+        });
+        let pending = wait_for_pending("payments").await;
+        approval_registry().resolve(
+            &pending.approval_id,
+            ApprovalDecision {
+                approved: false,
+                reason: Some("too large".to_string()),
+            },
+        );
+        match call.await.unwrap() {
+            Err(PipelineError::ApprovalRejected { reason, .. }) => assert_eq!(reason, "too large"),
+            other => panic!("Expected rejection, got {:?}", other),
+        }
+        assert!(!approval_registry().resolve(
+            &pending.approval_id,
+            ApprovalDecision {
+                approved: true,
+                reason: None
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_approvals_require_the_admin_token() {
+        let client = reqwest::Client::new();
+        let mut agent = agent(true, 5_000);
+        agent.id = "admin-only".to_string();
+        let call =
+            tokio::spawn(
+                async move { await_approval(&client, &agent, serde_json::json!({})).await },
+            );
+        let pending = wait_for_pending("admin-only").await;
+        let admin_token = AdminToken::new(Some("secret"));
+        let decide = |authorization: Option<&str>| {
+            let mut request = Request::post(format!("/agents/approvals/{}", pending.approval_id));
+            if let Some(authorization) = authorization {
+                request = request.header(hyper::header::AUTHORIZATION, authorization);
+            }
+            request
+                .body(ResponseHandler::create_full_body(r#"{"approved":true}"#))
+                .unwrap()
+        };
+
+        for request in [
+            decide(None),
+            decide(Some("Bearer wrong")),
+            Request::get("/agents/approvals")
+                .body(ResponseHandler::create_full_body(""))
+                .unwrap(),
+        ] {
+            let response = agent_approvals(request, Some(&pending.approval_id), &admin_token)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(approval_registry().get(&pending.approval_id).is_some());
+
+        let response = agent_approvals(
+            decide(Some("Bearer secret")),
+            Some(&pending.approval_id),
+            &admin_token,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(call.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_approval_times_out_and_is_skipped_when_not_required() {
+        let client = reqwest::Client::new();
+        let mut timing_out = agent(true, 10);
+        timing_out.id = "timing-out".to_string();
+        let result = await_approval(&client, &timing_out, serde_json::json!({})).await;
+        assert!(matches!(result, Err(PipelineError::ApprovalTimeout { .. })));
+        assert!(approval_registry()
+            .list()
+            .iter()
+            .all(|approval| approval.agent != "timing-out"));
+
+        let result = await_approval(&client, &agent(false, 10), serde_json::json!({})).await;
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use common::configuration::ConversationArchiveConfig;
+use hermesllm::fixtures::REDACTED;
+use http_body_util::combinators::BoxBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Response, StatusCode};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::warn;
+
+use crate::handlers::admin::AdminToken;
+use crate::handlers::response_handler::ResponseHandler;
+
+const DEFAULT_PERCENTAGE: f64 = 100.0;
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+/// Expired files of a directory archive are looked for at most this often
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// llm conversations as the client saw them, the request it sent with the completion
+/// it received, kept for debugging and compliance review
+pub struct ConversationArchive {
+    percentage: f64,
+    retention: Duration,
+    max_entries: usize,
+    admin_token: AdminToken,
+    redactions: Vec<Regex>,
+    directory: Option<PathBuf>,
+    recent: Mutex<VecDeque<ArchivedConversation>>,
+    last_prune: Mutex<Option<Instant>>,
+}
+
+/// One archived request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchivedConversation {
+    pub request_id: String,
+    /// Unix time in seconds
+    pub archived_at: u64,
+    pub model: String,
+    pub provider: String,
+    pub status: u16,
+    pub streaming: bool,
+    /// Body the client sent
+    pub request: Value,
+    /// Text of the response, none when it failed before completing
+    pub completion: Option<String>,
+}
+
+impl ConversationArchive {
+    /// Invalid redaction patterns are skipped
+    pub fn new(config: &ConversationArchiveConfig, admin_token: AdminToken) -> Self {
+        let redactions = config
+            .redact
+            .iter()
+            .flatten()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    warn!("ignoring invalid redaction pattern {}: {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+        ConversationArchive {
+            percentage: config.percentage.unwrap_or(DEFAULT_PERCENTAGE),
+            retention: config
+                .retention_seconds
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RETENTION),
+            max_entries: config.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES).max(1),
+            admin_token,
+            redactions,
+            directory: config.directory.as_ref().map(PathBuf::from),
+            recent: Mutex::new(VecDeque::new()),
+            last_prune: Mutex::new(None),
+        }
+    }
+
+    /// Whether this request is archived
+    pub fn sample(&self) -> bool {
+        self.percentage > 0.0 && rand::random_range(0.0..100.0) < self.percentage
+    }
+
+    /// Starts a conversation with the request body, a body that isn't json is kept as text
+    pub fn conversation(
+        &self,
+        request_id: &str,
+        model: &str,
+        provider: &str,
+        status: u16,
+        streaming: bool,
+        request: &[u8],
+    ) -> ArchivedConversation {
+        let request = serde_json::from_slice(request)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(request).into_owned()));
+        ArchivedConversation {
+            request_id: request_id.to_string(),
+            archived_at: 0,
+            model: model.to_string(),
+            provider: provider.to_string(),
+            status,
+            streaming,
+            request,
+            completion: None,
+        }
+    }
+
+    /// Stores the conversation once the completion is sent, without it when the response
+    /// fails
+    pub async fn archive_when_complete(
+        &self,
+        mut conversation: ArchivedConversation,
+        completion: oneshot::Receiver<String>,
+    ) {
+        conversation.completion = completion.await.ok();
+        self.archive(conversation);
+    }
+
+    /// Redacts and stores a conversation
+    pub fn archive(&self, mut conversation: ArchivedConversation) {
+        conversation.archived_at = now();
+        self.redact_value(&mut conversation.request);
+        if let Some(completion) = conversation.completion.as_mut() {
+            *completion = self.redact(completion);
+        }
+
+        let Some(directory) = &self.directory else {
+            let mut recent = self.recent.lock().unwrap();
+            self.expire(&mut recent);
+            if recent.len() >= self.max_entries {
+                recent.pop_front();
+            }
+            recent.push_back(conversation);
+            return;
+        };
+        let Some(path) = conversation_path(directory, &conversation.request_id) else {
+            warn!(
+                "[PLANO_REQ_ID:{}] | CONVERSATION_ARCHIVE | request id can't be used as a file name",
+                conversation.request_id
+            );
+            return;
+        };
+        let written = std::fs::create_dir_all(directory).and_then(|_| {
+            let json = serde_json::to_vec(&conversation).map_err(std::io::Error::other)?;
+            std::fs::write(&path, json)
+        });
+        if let Err(err) = written {
+            warn!(
+                "[PLANO_REQ_ID:{}] | CONVERSATION_ARCHIVE | failed to write {}: {}",
+                conversation.request_id,
+                path.display(),
+                err
+            );
+        }
+        self.prune_directory(directory);
+    }
+
+    /// The archived conversation of a request, if it hasn't expired
+    pub fn get(&self, request_id: &str) -> Option<ArchivedConversation> {
+        let conversation = match &self.directory {
+            Some(directory) => {
+                let path = conversation_path(directory, request_id)?;
+                let json = std::fs::read(path).ok()?;
+                serde_json::from_slice::<ArchivedConversation>(&json).ok()?
+            }
+            None => {
+                let mut recent = self.recent.lock().unwrap();
+                self.expire(&mut recent);
+                recent
+                    .iter()
+                    .rev()
+                    .find(|conversation| conversation.request_id == request_id)?
+                    .clone()
+            }
+        };
+        (!self.expired(conversation.archived_at)).then_some(conversation)
+    }
+
+    fn redact(&self, content: &str) -> String {
+        self.redactions
+            .iter()
+            .fold(content.to_string(), |content, regex| {
+                regex.replace_all(&content, REDACTED).into_owned()
+            })
+    }
+
+    /// Redacts the strings of a json value in place
+    fn redact_value(&self, value: &mut Value) {
+        if self.redactions.is_empty() {
+            return;
+        }
+        match value {
+            Value::String(text) => *text = self.redact(text),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_value(value)),
+            Value::Object(object) => object
+                .values_mut()
+                .for_each(|value| self.redact_value(value)),
+            _ => {}
+        }
+    }
+
+    fn expired(&self, archived_at: u64) -> bool {
+        now().saturating_sub(archived_at) > self.retention.as_secs()
+    }
+
+    fn expire(&self, recent: &mut VecDeque<ArchivedConversation>) {
+        while recent
+            .front()
+            .is_some_and(|conversation| self.expired(conversation.archived_at))
+        {
+            recent.pop_front();
+        }
+    }
+
+    /// Removes the files past the retention, and the oldest ones beyond max_entries
+    fn prune_directory(&self, directory: &Path) {
+        {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            if last_prune.is_some_and(|last_prune| last_prune.elapsed() < PRUNE_INTERVAL) {
+                return;
+            }
+            *last_prune = Some(Instant::now());
+        }
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, PathBuf)> = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        files.sort();
+        let excess = files.len().saturating_sub(self.max_entries);
+        for (index, (modified, path)) in files.iter().enumerate() {
+            let expired = modified
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed > self.retention);
+            if index < excess || expired {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// File of a conversation, none for request ids that could leave the directory
+fn conversation_path(directory: &Path, request_id: &str) -> Option<PathBuf> {
+    let safe = !request_id.is_empty()
+        && !request_id.starts_with('.')
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    safe.then(|| directory.join(format!("{}.json", request_id)))
+}
+
+/// Handles `GET /v1/debug/conversations?request_id=`: the archived conversation of the
+/// request. Requires the admin token as a bearer token.
+pub fn archived_conversation(
+    archive: Option<&ConversationArchive>,
+    headers: &HeaderMap,
+    query: Option<&str>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let Some(archive) = archive else {
+        return ResponseHandler::create_error_response(
+            StatusCode::NOT_FOUND,
+            "conversation archive is not configured",
+        );
+    };
+    if !archive.admin_token.is_authorized(headers) {
+        return ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        );
+    }
+    let Some(request_id) = query.and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("request_id="))
+    }) else {
+        return ResponseHandler::create_error_response(
+            StatusCode::BAD_REQUEST,
+            "request_id is required",
+        );
+    };
+    let Some(conversation) = archive.get(request_id) else {
+        return ResponseHandler::create_error_response(
+            StatusCode::NOT_FOUND,
+            "no archived conversation for this request",
+        );
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(
+            serde_json::to_string(&conversation).unwrap_or_default(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn archive(directory: Option<&Path>) -> ConversationArchive {
+        ConversationArchive::new(
+            &ConversationArchiveConfig {
+                directory: directory.map(|directory| directory.display().to_string()),
+                max_entries: Some(2),
+                redact: Some(vec![r"\d{3}-\d{2}-\d{4}".to_string()]),
+                ..Default::default()
+            },
+            AdminToken::new(Some("secret")),
+        )
+    }
+
+    fn conversation(archive: &ConversationArchive, request_id: &str) -> ArchivedConversation {
+        let mut conversation = archive.conversation(
+            request_id,
+            "gpt-4o",
+            "openai/gpt-4o",
+            200,
+            true,
+            br#"{"messages":[{"role":"user","content":"my ssn is 123-45-6789"}]}"#,
+        );
+        conversation.completion = Some("noted 123-45-6789".to_string());
+        conversation
+    }
+
+    #[test]
+    fn test_memory_archive_redacts_and_drops_the_oldest() {
+        let archive = archive(None);
+        for request_id in ["req-1", "req-2", "req-3"] {
+            archive.archive(conversation(&archive, request_id));
+        }
+        assert!(archive.get("req-1").is_none());
+
+        let archived = archive.get("req-3").unwrap();
+        assert_eq!(
+            archived.request["messages"][0]["content"],
+            "my ssn is [REDACTED]"
+        );
+        assert_eq!(archived.completion.as_deref(), Some("noted [REDACTED]"));
+    }
+
+    #[tokio::test]
+    async fn test_directory_archive_waits_for_the_completion() {
+        let dir = std::env::temp_dir().join(format!("archive-{}", uuid::Uuid::new_v4()));
+        let archive = archive(Some(&dir));
+        let (sender, receiver) = oneshot::channel();
+        let mut pending = conversation(&archive, "req-1");
+        pending.completion = None;
+        sender.send("done".to_string()).unwrap();
+        archive.archive_when_complete(pending, receiver).await;
+
+        assert!(dir.join("req-1.json").exists());
+        assert_eq!(
+            archive.get("req-1").unwrap().completion.as_deref(),
+            Some("done")
+        );
+        assert!(archive.get("../req-1").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_requires_admin_token() {
+        let archive = archive(None);
+        archive.archive(conversation(&archive, "req-1"));
+        let query = Some("request_id=req-1");
+
+        let response = archived_conversation(Some(&archive), &HeaderMap::new(), query);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        let response = archived_conversation(Some(&archive), &headers, query);
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = archived_conversation(Some(&archive), &headers, Some("request_id=req-2"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
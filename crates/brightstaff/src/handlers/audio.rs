@@ -0,0 +1,202 @@
+use bytes::Bytes;
+use common::configuration::ModelAlias;
+use common::consts::{
+    ARCH_IS_STREAMING_HEADER, ARCH_PROVIDER_HINT_HEADER, ARCH_REQUEST_ID_HEADER,
+    TRACE_PARENT_HEADER,
+};
+use common::traces::{parse_traceparent, SpanBuilder, SpanKind, TraceCollector};
+use hermesllm::apis::openai_audio::{multipart_model_field, AudioApi, SpeechRequest};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{self};
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::handlers::llm::resolve_model_alias;
+use crate::handlers::utils::{create_streaming_response, ObservableStreamProcessor};
+use crate::tracing::{http, llm, operation_component, OperationNameBuilder};
+
+fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn bad_request(message: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(message));
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+}
+
+/// Reads the requested model from an audio request without parsing the audio payload
+fn requested_model(audio_api: AudioApi, content_type: &str, body: &[u8]) -> Option<String> {
+    match audio_api {
+        AudioApi::Transcriptions => multipart_model_field(content_type, body),
+        AudioApi::Speech => serde_json::from_slice::<SpeechRequest>(body)
+            .ok()
+            .map(|speech_request| speech_request.model),
+    }
+}
+
+/// Forwards /v1/audio/transcriptions and /v1/audio/speech to the llm gateway.
+/// The model is only read to select the provider, the llm gateway rewrites it to the
+/// upstream model.
+pub async fn audio_passthrough(
+    request: Request<hyper::body::Incoming>,
+    audio_api: AudioApi,
+    full_qualified_llm_provider_url: String,
+    model_aliases: Arc<Option<HashMap<String, ModelAlias>>>,
+    trace_collector: Arc<TraceCollector>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let request_path = request.uri().path().to_string();
+    let mut request_headers = request.headers().clone();
+    let request_id = request_headers
+        .get(ARCH_REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+    let traceparent = request_headers
+        .get(TRACE_PARENT_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+    let content_type = request_headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let body = request.collect().await?.to_bytes();
+
+    let Some(model_from_request) = requested_model(audio_api, &content_type, &body) else {
+        return Ok(bad_request(format!(
+            "Missing model in {} request",
+            audio_api.endpoint()
+        )));
+    };
+    let resolved_model = resolve_model_alias(&model_from_request, &model_aliases);
+
+    debug!(
+        "[PLANO_REQ_ID:{}] | AUDIO_REQUEST | path={} model={} resolved_model={} bytes={}",
+        request_id,
+        request_path,
+        model_from_request,
+        resolved_model,
+        body.len()
+    );
+
+    let Ok(provider_hint) = header::HeaderValue::from_str(&resolved_model) else {
+        return Ok(bad_request(format!(
+            "Invalid model name {}",
+            resolved_model
+        )));
+    };
+    request_headers.insert(ARCH_PROVIDER_HINT_HEADER, provider_hint);
+    request_headers.insert(
+        header::HeaderName::from_static(ARCH_IS_STREAMING_HEADER),
+        header::HeaderValue::from_static("false"),
+    );
+    request_headers.remove(header::CONTENT_LENGTH);
+
+    let request_start_time = std::time::Instant::now();
+    let request_start_system_time = std::time::SystemTime::now();
+
+    let llm_response = match reqwest::Client::new()
+        .post(full_qualified_llm_provider_url)
+        .headers(request_headers)
+        .body(body)
+        .send()
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => {
+            warn!(
+                "[PLANO_REQ_ID:{}] | AUDIO_REQUEST_FAILED | {}",
+                request_id, err
+            );
+            let mut internal_error =
+                Response::new(full(format!("Failed to send request: {}", err)));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(internal_error);
+        }
+    };
+
+    let upstream_status = llm_response.status();
+    let mut response = Response::builder().status(upstream_status);
+    let headers = response.headers_mut().unwrap();
+    for (header_name, header_value) in llm_response.headers().iter() {
+        headers.insert(header_name, header_value.clone());
+    }
+
+    let operation_name = OperationNameBuilder::new()
+        .with_method("POST")
+        .with_path(&request_path)
+        .with_target(&resolved_model)
+        .build();
+    let mut span_builder = SpanBuilder::new(&operation_name)
+        .with_kind(SpanKind::Client)
+        .with_start_time(request_start_system_time)
+        .with_attribute(http::METHOD, "POST")
+        .with_attribute(http::STATUS_CODE, upstream_status.as_u16().to_string())
+        .with_attribute(http::TARGET, request_path.clone())
+        .with_attribute(http::REQUEST_ID, request_id.clone())
+        .with_attribute(llm::MODEL_NAME, resolved_model.clone())
+        .with_attribute(llm::IS_STREAMING, "false");
+    if let Some(traceparent) = traceparent {
+        let (trace_id, parent_span_id) = parse_traceparent(&traceparent);
+        span_builder = span_builder.with_trace_id(&trace_id);
+        if let Some(parent) = parent_span_id {
+            span_builder = span_builder.with_parent_span_id(&parent);
+        }
+    }
+
+    let processor = ObservableStreamProcessor::new(
+        trace_collector,
+        operation_component::LLM,
+        span_builder.build(),
+        request_start_time,
+    );
+    let streaming_response = create_streaming_response(llm_response.bytes_stream(), processor, 16);
+
+    match response.body(streaming_response.body) {
+        Ok(response) => Ok(response),
+        Err(err) => {
+            let mut internal_error =
+                Response::new(full(format!("Failed to create response: {}", err)));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(internal_error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_model() {
+        let multipart =
+            "--b\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n--b--\r\n";
+        assert_eq!(
+            requested_model(
+                AudioApi::Transcriptions,
+                "multipart/form-data; boundary=b",
+                multipart.as_bytes()
+            ),
+            Some("whisper-1".to_string())
+        );
+        assert_eq!(
+            requested_model(
+                AudioApi::Speech,
+                "application/json",
+                br#"{"model":"tts-1","input":"hello","voice":"alloy"}"#
+            ),
+            Some("tts-1".to_string())
+        );
+        assert_eq!(
+            requested_model(AudioApi::Speech, "application/json", b"{}"),
+            None
+        );
+    }
+}
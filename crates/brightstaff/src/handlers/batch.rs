@@ -0,0 +1,237 @@
+use bytes::Bytes;
+use common::configuration::{LlmProvider, LlmProviderType};
+use common::consts::{
+    ARCH_IS_STREAMING_HEADER, ARCH_PROVIDER_HINT_HEADER, ARCH_REQUEST_ID_HEADER,
+    TRACE_PARENT_HEADER,
+};
+use common::traces::{parse_traceparent, SpanBuilder, SpanKind, TraceCollector};
+use futures::StreamExt;
+use hermesllm::apis::openai_batch::BatchApi;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, BodyStream, Full};
+use hyper::header::{self};
+use hyper::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::handlers::utils::{create_streaming_response, ObservableStreamProcessor};
+use crate::tracing::{http, llm, operation_component, OperationNameBuilder};
+
+fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+/// Provider for batch and file requests. These carry no model, so the client's provider
+/// hint is used when present, then the first Anthropic provider for message batches, then
+/// the default provider, then the first OpenAI provider.
+pub(crate) fn select_batch_provider(
+    providers: &[LlmProvider],
+    hint: Option<&str>,
+    batch_api: BatchApi,
+) -> Option<String> {
+    if let Some(hint) = hint {
+        if let Some(provider) = providers
+            .iter()
+            .find(|p| p.name == hint || p.model.as_deref() == Some(hint))
+        {
+            return Some(provider.name.clone());
+        }
+    }
+    if batch_api == BatchApi::MessageBatches {
+        if let Some(provider) = providers
+            .iter()
+            .find(|p| p.provider_interface == LlmProviderType::Anthropic)
+        {
+            return Some(provider.name.clone());
+        }
+    }
+    providers
+        .iter()
+        .find(|p| p.default.unwrap_or(false))
+        .or_else(|| {
+            providers
+                .iter()
+                .find(|p| p.provider_interface == LlmProviderType::OpenAI)
+        })
+        .map(|p| p.name.clone())
+}
+
+/// Forwards /v1/batches, /v1/files and /v1/messages/batches requests to the llm gateway. Request and response
+/// bodies are streamed, so large batch input files are never buffered here.
+pub async fn batch_passthrough(
+    request: Request<hyper::body::Incoming>,
+    full_qualified_llm_provider_url: String,
+    llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
+    trace_collector: Arc<TraceCollector>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let (parts, body) = request.into_parts();
+    let method = parts.method;
+    let request_path = parts.uri.path().to_string();
+    let batch_api = BatchApi::from_endpoint(&request_path).unwrap_or(BatchApi::Batches);
+    let mut request_headers = parts.headers;
+    let request_id = request_headers
+        .get(ARCH_REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+    let traceparent = request_headers
+        .get(TRACE_PARENT_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    let provider_hint = request_headers
+        .get(ARCH_PROVIDER_HINT_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+    let provider_name = {
+        let providers = llm_providers.read().await;
+        select_batch_provider(&providers, provider_hint.as_deref(), batch_api)
+    };
+    let Some(provider_name) = provider_name else {
+        let mut bad_request = Response::new(full("No provider available for batch requests"));
+        *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(bad_request);
+    };
+
+    debug!(
+        "[PLANO_REQ_ID:{}] | BATCH_REQUEST | {} {} provider={}",
+        request_id, method, full_qualified_llm_provider_url, provider_name
+    );
+
+    if let Ok(value) = header::HeaderValue::from_str(&provider_name) {
+        request_headers.insert(ARCH_PROVIDER_HINT_HEADER, value);
+    }
+    request_headers.insert(
+        header::HeaderName::from_static(ARCH_IS_STREAMING_HEADER),
+        header::HeaderValue::from_static("false"),
+    );
+
+    let body_stream = BodyStream::new(body).filter_map(|frame| async move {
+        match frame {
+            Ok(frame) => frame.into_data().ok().map(Ok::<_, hyper::Error>),
+            Err(err) => Some(Err(err)),
+        }
+    });
+
+    let request_start_time = std::time::Instant::now();
+    let request_start_system_time = std::time::SystemTime::now();
+
+    let mut upstream_request = reqwest::Client::new()
+        .request(method.clone(), full_qualified_llm_provider_url)
+        .headers(request_headers);
+    // only uploads and batch creation carry a body, polling is plain GET
+    if method == Method::POST {
+        upstream_request = upstream_request.body(reqwest::Body::wrap_stream(body_stream));
+    }
+
+    let llm_response = match upstream_request.send().await {
+        Ok(res) => res,
+        Err(err) => {
+            warn!(
+                "[PLANO_REQ_ID:{}] | BATCH_REQUEST_FAILED | {}",
+                request_id, err
+            );
+            let mut internal_error =
+                Response::new(full(format!("Failed to send request: {}", err)));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(internal_error);
+        }
+    };
+
+    let upstream_status = llm_response.status();
+    let mut response = Response::builder().status(upstream_status);
+    let headers = response.headers_mut().unwrap();
+    for (header_name, header_value) in llm_response.headers().iter() {
+        headers.insert(header_name, header_value.clone());
+    }
+
+    let operation_name = OperationNameBuilder::new()
+        .with_method(method.as_str())
+        .with_path(&request_path)
+        .with_target(&provider_name)
+        .build();
+    let mut span_builder = SpanBuilder::new(&operation_name)
+        .with_kind(SpanKind::Client)
+        .with_start_time(request_start_system_time)
+        .with_attribute(http::METHOD, method.as_str())
+        .with_attribute(http::STATUS_CODE, upstream_status.as_u16().to_string())
+        .with_attribute(http::TARGET, request_path.clone())
+        .with_attribute(http::REQUEST_ID, request_id.clone())
+        .with_attribute(llm::PROVIDER, provider_name.clone());
+    if let Some(traceparent) = traceparent {
+        let (trace_id, parent_span_id) = parse_traceparent(&traceparent);
+        span_builder = span_builder.with_trace_id(&trace_id);
+        if let Some(parent) = parent_span_id {
+            span_builder = span_builder.with_parent_span_id(&parent);
+        }
+    }
+    let processor = ObservableStreamProcessor::new(
+        trace_collector,
+        operation_component::LLM,
+        span_builder.build(),
+        request_start_time,
+    );
+    let streaming_response = create_streaming_response(llm_response.bytes_stream(), processor, 16);
+
+    match response.body(streaming_response.body) {
+        Ok(response) => Ok(response),
+        Err(err) => {
+            let mut internal_error =
+                Response::new(full(format!("Failed to create response: {}", err)));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(internal_error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(name: &str, interface: LlmProviderType, default: bool) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            provider_interface: interface,
+            model: Some(name.split('/').next_back().unwrap().to_string()),
+            default: Some(default),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_batch_provider() {
+        let providers = vec![
+            provider(
+                "anthropic/claude-sonnet-4",
+                LlmProviderType::Anthropic,
+                false,
+            ),
+            provider("openai/gpt-4o-mini", LlmProviderType::OpenAI, false),
+            provider("groq/llama-3.1-8b-instant", LlmProviderType::Groq, false),
+        ];
+
+        assert_eq!(
+            select_batch_provider(&providers, Some("llama-3.1-8b-instant"), BatchApi::Batches)
+                .as_deref(),
+            Some("groq/llama-3.1-8b-instant")
+        );
+        assert_eq!(
+            select_batch_provider(&providers, None, BatchApi::Batches).as_deref(),
+            Some("openai/gpt-4o-mini")
+        );
+        assert_eq!(
+            select_batch_provider(&providers, None, BatchApi::MessageBatches).as_deref(),
+            Some("anthropic/claude-sonnet-4")
+        );
+
+        let mut with_default = providers.clone();
+        with_default[0].default = Some(true);
+        assert_eq!(
+            select_batch_provider(&with_default, Some("unknown"), BatchApi::Batches).as_deref(),
+            Some("anthropic/claude-sonnet-4")
+        );
+    }
+}
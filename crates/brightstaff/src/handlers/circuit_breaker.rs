@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use common::configuration::AgentCircuitBreaker;
+use tracing::{info, warn};
+
+/// Process wide circuit breaker state, pipeline processors are created per request
+/// so agent health has to outlive them.
+pub fn agent_circuit_breakers() -> &'static CircuitBreakerRegistry {
+    static CIRCUIT_BREAKERS: OnceLock<CircuitBreakerRegistry> = OnceLock::new();
+    CIRCUIT_BREAKERS.get_or_init(CircuitBreakerRegistry::default)
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct CircuitBreakerRegistry {
+    circuits: Mutex<HashMap<String, CircuitState>>,
+}
+
+impl CircuitBreakerRegistry {
+    /// Whether a call to the agent may go through. Once the cooldown of an open circuit
+    /// has passed a single trial call is allowed, its outcome closes or re-opens it.
+    pub fn allow(&self, agent_id: &str, config: &AgentCircuitBreaker) -> bool {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(state) = circuits.get_mut(agent_id) else {
+            return true;
+        };
+        match state.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= Duration::from_millis(config.cooldown_ms) => {
+                info!(
+                    "circuit half open for agent {}, allowing trial call",
+                    agent_id
+                );
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    pub fn record_success(&self, agent_id: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        if let Some(state) = circuits.remove(agent_id) {
+            if state.opened_at.is_some() {
+                info!("circuit closed for agent {}", agent_id);
+            }
+        }
+    }
+
+    pub fn record_failure(&self, agent_id: &str, config: &AgentCircuitBreaker) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let state = circuits.entry(agent_id.to_string()).or_default();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= config.failure_threshold {
+            if state.opened_at.is_none() {
+                warn!(
+                    "circuit opened for agent {} after {} consecutive failures",
+                    agent_id, state.consecutive_failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(cooldown_ms: u64) -> AgentCircuitBreaker {
+        AgentCircuitBreaker {
+            failure_threshold: 2,
+            cooldown_ms,
+            skip_when_open: None,
+        }
+    }
+
+    #[test]
+    fn test_circuit_opens_after_threshold_and_closes_on_success() {
+        let registry = CircuitBreakerRegistry::default();
+        let config = config(60_000);
+
+        assert!(registry.allow("agent", &config));
+        registry.record_failure("agent", &config);
+        assert!(registry.allow("agent", &config));
+        registry.record_failure("agent", &config);
+        assert!(!registry.allow("agent", &config));
+        assert!(registry.allow("other-agent", &config));
+
+        registry.record_success("agent");
+        assert!(registry.allow("agent", &config));
+    }
+
+    #[test]
+    fn test_circuit_half_opens_after_cooldown() {
+        let registry = CircuitBreakerRegistry::default();
+        let config = config(0);
+
+        registry.record_failure("agent", &config);
+        registry.record_failure("agent", &config);
+        // cooldown elapsed, one trial call goes through and re-arms the circuit
+        assert!(registry.allow("agent", &config));
+        registry.record_failure("agent", &config);
+        assert!(registry.circuits.lock().unwrap()["agent"]
+            .opened_at
+            .is_some());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use common::configuration::{DataResidencyConfig, LlmProvider, ResidencyPolicy};
+use common::traces::{Attribute, AttributeValue};
+use hyper::header::HeaderMap;
+
+use crate::tracing::llm;
+
+/// Data residency policies of the tenants, checked once the request is routed
+#[derive(Debug, Clone)]
+pub struct DataResidency {
+    tenant_header: String,
+    tenants: HashMap<String, ResidencyPolicy>,
+    default: Option<ResidencyPolicy>,
+}
+
+/// Outcome of the policy for a routed request, recorded on the llm span for audit
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidencyDecision {
+    pub tenant: Option<String>,
+    pub provider: String,
+    pub provider_residency: Option<String>,
+    pub allowed: bool,
+}
+
+impl DataResidency {
+    pub fn new(config: &DataResidencyConfig, tenant_header: &str) -> Self {
+        DataResidency {
+            tenant_header: tenant_header.to_ascii_lowercase(),
+            tenants: config.tenants.clone().unwrap_or_default(),
+            default: config.default.clone(),
+        }
+    }
+
+    /// Decision for the provider the request was routed to, None when no policy applies.
+    /// Models without a configured provider have no residency, so policies restricting
+    /// residencies deny them.
+    pub fn check(
+        &self,
+        headers: &HeaderMap,
+        model_name: &str,
+        provider: Option<&LlmProvider>,
+    ) -> Option<ResidencyDecision> {
+        let tenant = headers
+            .get(&self.tenant_header)
+            .and_then(|value| value.to_str().ok())
+            .map(|tenant| tenant.trim().to_string());
+        let policy = tenant
+            .as_deref()
+            .and_then(|tenant| self.tenants.get(tenant))
+            .or(self.default.as_ref())?;
+
+        let provider_name = provider.map_or(model_name, |provider| provider.name.as_str());
+        let provider_residency = provider.and_then(|provider| provider.residency.clone());
+        let residency_allowed = policy.residencies.as_ref().is_none_or(|residencies| {
+            provider_residency
+                .as_ref()
+                .is_some_and(|residency| residencies.contains(residency))
+        });
+        let provider_allowed = policy.providers.as_ref().is_none_or(|providers| {
+            providers
+                .iter()
+                .any(|allowed| allowed == provider_name || allowed == model_name)
+        });
+        Some(ResidencyDecision {
+            tenant,
+            provider: provider_name.to_string(),
+            provider_residency,
+            allowed: residency_allowed && provider_allowed,
+        })
+    }
+}
+
+impl ResidencyDecision {
+    /// Body of the 403 of a denied request
+    pub fn message(&self) -> String {
+        format!(
+            "data residency policy of tenant {} doesn't allow provider {} (residency {})",
+            self.tenant.as_deref().unwrap_or("default"),
+            self.provider,
+            self.provider_residency.as_deref().unwrap_or("unknown")
+        )
+    }
+
+    pub fn attributes(&self) -> Vec<Attribute> {
+        let attribute = |key: &str, value: &str| Attribute {
+            key: key.to_string(),
+            value: AttributeValue {
+                string_value: Some(value.to_string()),
+            },
+        };
+        let mut attributes = vec![attribute(
+            llm::RESIDENCY_DECISION,
+            if self.allowed { "allowed" } else { "denied" },
+        )];
+        if let Some(tenant) = &self.tenant {
+            attributes.push(attribute(llm::RESIDENCY_TENANT, tenant));
+        }
+        if let Some(residency) = &self.provider_residency {
+            attributes.push(attribute(llm::RESIDENCY, residency));
+        }
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::consts::ARCH_TENANT_ID_HEADER;
+    use hyper::header::HeaderValue;
+
+    fn provider(name: &str, residency: Option<&str>) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            residency: residency.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let data_residency = DataResidency::new(
+            &DataResidencyConfig {
+                tenants: Some(HashMap::from([(
+                    "acme-eu".to_string(),
+                    ResidencyPolicy {
+                        residencies: Some(vec!["eu".to_string()]),
+                        providers: None,
+                    },
+                )])),
+                default: None,
+            },
+            ARCH_TENANT_ID_HEADER,
+        );
+        let eu = provider("mistral/mistral-large", Some("eu"));
+        let us = provider("openai/gpt-4o", Some("us"));
+
+        let mut headers = HeaderMap::new();
+        // no policy for requests without a tenant
+        assert_eq!(data_residency.check(&headers, "gpt-4o", Some(&us)), None);
+
+        headers.insert("x-arch-tenant-id", HeaderValue::from_static("acme-eu"));
+        let allowed = data_residency
+            .check(&headers, "mistral-large", Some(&eu))
+            .unwrap();
+        assert!(allowed.allowed);
+        let denied = data_residency.check(&headers, "gpt-4o", Some(&us)).unwrap();
+        assert!(!denied.allowed);
+        assert_eq!(
+            denied.message(),
+            "data residency policy of tenant acme-eu doesn't allow provider openai/gpt-4o (residency us)"
+        );
+        // unknown providers have no residency
+        assert!(
+            !data_residency
+                .check(&headers, "llama3", None)
+                .unwrap()
+                .allowed
+        );
+    }
+
+    #[test]
+    fn test_provider_allowlist_of_default_policy() {
+        let data_residency = DataResidency::new(
+            &DataResidencyConfig {
+                default: Some(ResidencyPolicy {
+                    residencies: None,
+                    providers: Some(vec!["azure/gpt-4o".to_string()]),
+                }),
+                ..Default::default()
+            },
+            ARCH_TENANT_ID_HEADER,
+        );
+        let headers = HeaderMap::new();
+        let azure = provider("azure/gpt-4o", None);
+        let openai = provider("openai/gpt-4o", None);
+        assert!(
+            data_residency
+                .check(&headers, "gpt-4o", Some(&azure))
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            !data_residency
+                .check(&headers, "gpt-4o", Some(&openai))
+                .unwrap()
+                .allowed
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::configuration::{CalloutFailureMode, DlpConfig};
+use hermesllm::apis::openai::{ContentPart, MessageContent};
+use hermesllm::clients::SupportedAPIsFromClient;
+use hermesllm::{ProviderRequest, ProviderRequestType};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::utils::egress_proxy::egress_client;
+
+pub const DEFAULT_DLP_TIMEOUT: Duration = Duration::from_secs(1);
+pub const DEFAULT_DLP_CACHE_TTL: Duration = Duration::from_secs(300);
+pub const DEFAULT_DLP_CACHE_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanDirection {
+    Request,
+    Response,
+}
+
+/// Texts sent to the service, the ones with a cached verdict are left out
+#[derive(Debug, Serialize)]
+pub struct ScanRequest<'a> {
+    pub request_id: &'a str,
+    pub direction: ScanDirection,
+    pub texts: Vec<&'a str>,
+}
+
+/// Verdict of the service on one text
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DlpVerdict {
+    Allow,
+    /// The text is replaced by `text`
+    Redact {
+        text: String,
+    },
+    Block {
+        reason: Option<String>,
+    },
+}
+
+/// Answer of the service, a verdict per text in the order they were sent
+#[derive(Debug, Deserialize)]
+pub struct ScanResponse {
+    pub verdicts: Vec<DlpVerdict>,
+}
+
+#[derive(Debug, Error)]
+pub enum DlpError {
+    #[error("dlp scan failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("dlp scan returned {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("dlp scan returned {got} verdicts for {sent} texts")]
+    VerdictCount { sent: usize, got: usize },
+}
+
+/// What happens to the request or response after the scan
+#[derive(Debug, PartialEq)]
+pub enum DlpOutcome {
+    Allow,
+    Redacted,
+    Block { status: StatusCode, message: String },
+}
+
+/// Sends the text of llm requests, and of non streaming responses, to a DLP service.
+/// Verdicts are cached by the hash of the text, so the history of a conversation is
+/// only scanned once.
+pub struct DlpScanner {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    failure_mode: CalloutFailureMode,
+    scan_responses: bool,
+    cache_ttl: Duration,
+    cache_size: usize,
+    verdicts: Mutex<HashMap<[u8; 32], (DlpVerdict, Instant)>>,
+}
+
+impl DlpScanner {
+    pub fn new(config: &DlpConfig) -> Self {
+        DlpScanner {
+            client: egress_client(),
+            url: config.url.clone(),
+            timeout: config
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_DLP_TIMEOUT),
+            failure_mode: config.failure_mode.unwrap_or_default(),
+            scan_responses: config.scan_responses.unwrap_or(true),
+            cache_ttl: config
+                .cache_ttl_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_DLP_CACHE_TTL),
+            cache_size: config.cache_size.unwrap_or(DEFAULT_DLP_CACHE_SIZE),
+            verdicts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn scans_responses(&self) -> bool {
+        self.scan_responses
+    }
+
+    /// Scans the text of the messages of `request` and redacts it in place
+    pub async fn scan_request(
+        &self,
+        request_id: &str,
+        request: &mut ProviderRequestType,
+    ) -> DlpOutcome {
+        let mut messages = request.get_messages();
+        let texts = messages
+            .iter_mut()
+            .flat_map(|message| match &mut message.content {
+                MessageContent::Text(text) => vec![text],
+                MessageContent::Parts(parts) => parts
+                    .iter_mut()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text),
+                        _ => None,
+                    })
+                    .collect(),
+            })
+            .collect();
+        let outcome = self.scan(request_id, ScanDirection::Request, texts).await;
+        // messages are only set again when needed, converting them back can lose details
+        if outcome == DlpOutcome::Redacted {
+            request.set_messages(&messages);
+        }
+        outcome
+    }
+
+    /// Scans the text of a non streaming response in the format of the client api and
+    /// redacts it in `body`. Bodies that aren't json have nothing to scan.
+    pub async fn scan_response(
+        &self,
+        request_id: &str,
+        client_api: &SupportedAPIsFromClient,
+        body: &mut Bytes,
+    ) -> DlpOutcome {
+        let Ok(mut response) = serde_json::from_slice::<Value>(body) else {
+            return DlpOutcome::Allow;
+        };
+        let outcome = self
+            .scan(
+                request_id,
+                ScanDirection::Response,
+                response_texts(client_api, &mut response),
+            )
+            .await;
+        if outcome == DlpOutcome::Redacted {
+            *body = Bytes::from(response.to_string());
+        }
+        outcome
+    }
+
+    async fn scan(
+        &self,
+        request_id: &str,
+        direction: ScanDirection,
+        mut texts: Vec<&mut String>,
+    ) -> DlpOutcome {
+        texts.retain(|text| !text.trim().is_empty());
+        let keys: Vec<[u8; 32]> = texts
+            .iter()
+            .map(|text| content_hash(direction, text))
+            .collect();
+        let mut verdicts: Vec<Option<DlpVerdict>> =
+            keys.iter().map(|key| self.cached_verdict(key)).collect();
+
+        let unscanned: Vec<usize> = (0..texts.len())
+            .filter(|&index| verdicts[index].is_none())
+            .collect();
+        if !unscanned.is_empty() {
+            let scan_request = ScanRequest {
+                request_id,
+                direction,
+                texts: unscanned
+                    .iter()
+                    .map(|&index| texts[index].as_str())
+                    .collect(),
+            };
+            match self.call(&scan_request).await {
+                Ok(scanned) => {
+                    let mut cache = self.verdicts.lock().unwrap();
+                    self.make_room(&mut cache, scanned.len());
+                    for (index, verdict) in unscanned.into_iter().zip(scanned) {
+                        cache.insert(keys[index], (verdict.clone(), Instant::now()));
+                        verdicts[index] = Some(verdict);
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "[PLANO_REQ_ID:{}] | DLP | {:?} {} ({:?})",
+                        request_id, direction, err, self.failure_mode
+                    );
+                    return match self.failure_mode {
+                        CalloutFailureMode::FailOpen => DlpOutcome::Allow,
+                        CalloutFailureMode::FailClosed => DlpOutcome::Block {
+                            status: StatusCode::SERVICE_UNAVAILABLE,
+                            message: "dlp scanner unavailable".to_string(),
+                        },
+                    };
+                }
+            }
+        }
+
+        let mut outcome = DlpOutcome::Allow;
+        for (text, verdict) in texts.into_iter().zip(verdicts.into_iter().flatten()) {
+            match verdict {
+                DlpVerdict::Allow => {}
+                DlpVerdict::Redact { text: redacted } => {
+                    *text = redacted;
+                    outcome = DlpOutcome::Redacted;
+                }
+                DlpVerdict::Block { reason } => {
+                    info!(
+                        "[PLANO_REQ_ID:{}] | DLP | {:?} blocked",
+                        request_id, direction
+                    );
+                    return DlpOutcome::Block {
+                        status: StatusCode::FORBIDDEN,
+                        message: reason.unwrap_or_else(|| "blocked by dlp policy".to_string()),
+                    };
+                }
+            }
+        }
+        if outcome == DlpOutcome::Redacted {
+            info!(
+                "[PLANO_REQ_ID:{}] | DLP | {:?} redacted",
+                request_id, direction
+            );
+        }
+        outcome
+    }
+
+    async fn call(&self, request: &ScanRequest<'_>) -> Result<Vec<DlpVerdict>, DlpError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(request)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(DlpError::Status {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        let verdicts = response.json::<ScanResponse>().await?.verdicts;
+        if verdicts.len() != request.texts.len() {
+            return Err(DlpError::VerdictCount {
+                sent: request.texts.len(),
+                got: verdicts.len(),
+            });
+        }
+        Ok(verdicts)
+    }
+
+    fn cached_verdict(&self, key: &[u8; 32]) -> Option<DlpVerdict> {
+        let cache = self.verdicts.lock().unwrap();
+        let (verdict, scanned_at) = cache.get(key)?;
+        (scanned_at.elapsed() < self.cache_ttl).then(|| verdict.clone())
+    }
+
+    /// Drops the expired verdicts when `incoming` more don't fit, and all of them when
+    /// that isn't enough
+    fn make_room(&self, cache: &mut HashMap<[u8; 32], (DlpVerdict, Instant)>, incoming: usize) {
+        if cache.len() + incoming <= self.cache_size {
+            return;
+        }
+        cache.retain(|_, (_, scanned_at)| scanned_at.elapsed() < self.cache_ttl);
+        if cache.len() + incoming > self.cache_size {
+            cache.clear();
+        }
+    }
+}
+
+fn content_hash(direction: ScanDirection, text: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([direction as u8]);
+    hasher.update(text.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Text of the generated content of a response: the message of each choice for chat
+/// completions, text blocks for messages and output text for the responses api
+fn response_texts<'a>(
+    client_api: &SupportedAPIsFromClient,
+    response: &'a mut Value,
+) -> Vec<&'a mut String> {
+    match client_api {
+        SupportedAPIsFromClient::OpenAIChatCompletions(_) => {
+            array_items(response.get_mut("choices"))
+                .filter_map(|choice| match choice.pointer_mut("/message/content") {
+                    Some(Value::String(content)) => Some(content),
+                    _ => None,
+                })
+                .collect()
+        }
+        SupportedAPIsFromClient::AnthropicMessagesAPI(_) => {
+            array_items(response.get_mut("content"))
+                .filter_map(|block| block_text(block, "text"))
+                .collect()
+        }
+        SupportedAPIsFromClient::OpenAIResponsesAPI(_) => array_items(response.get_mut("output"))
+            .flat_map(|item| array_items(item.get_mut("content")))
+            .filter_map(|block| block_text(block, "output_text"))
+            .collect(),
+    }
+}
+
+fn array_items(value: Option<&mut Value>) -> impl Iterator<Item = &mut Value> {
+    value
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flat_map(|items| items.iter_mut())
+}
+
+/// Text of a content block of the given type
+fn block_text<'a>(block: &'a mut Value, block_type: &str) -> Option<&'a mut String> {
+    if block.get("type").and_then(Value::as_str) != Some(block_type) {
+        return None;
+    }
+    match block.get_mut("text") {
+        Some(Value::String(text)) => Some(text),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::{ChatCompletionsRequest, OpenAIApi};
+
+    fn chat_request(content: &str) -> ProviderRequestType {
+        let request: ChatCompletionsRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "you are a helpful assistant"},
+                {"role": "user", "content": content}
+            ]
+        }))
+        .unwrap();
+        ProviderRequestType::ChatCompletionsRequest(request)
+    }
+
+    fn scanner(url: &str, failure_mode: Option<CalloutFailureMode>) -> DlpScanner {
+        DlpScanner::new(&DlpConfig {
+            url: url.to_string(),
+            failure_mode,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_redacts_request_and_caches_verdicts() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/scan")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "request_id": "req-1",
+                "direction": "request",
+                "texts": ["you are a helpful assistant", "my card is 4111 1111 1111 1111"]
+            })))
+            .with_body(
+                r#"{"verdicts":[{"action":"allow"},{"action":"redact","text":"my card is [CARD]"}]}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let dlp = scanner(&format!("{}/scan", server.url()), None);
+
+        for _ in 0..2 {
+            let mut request = chat_request("my card is 4111 1111 1111 1111");
+            assert_eq!(
+                dlp.scan_request("req-1", &mut request).await,
+                DlpOutcome::Redacted
+            );
+            assert_eq!(
+                request.get_recent_user_message().as_deref(),
+                Some("my card is [CARD]")
+            );
+        }
+        // the second request was answered from the cache
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_blocks_response() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/scan")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "direction": "response",
+                "texts": ["the api key is sk-123"]
+            })))
+            .with_body(r#"{"verdicts":[{"action":"block","reason":"secret in response"}]}"#)
+            .create_async()
+            .await;
+        let dlp = scanner(&format!("{}/scan", server.url()), None);
+
+        let mut body = Bytes::from(
+            r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"the api key is sk-123"}}]}"#,
+        );
+        let outcome = dlp
+            .scan_response(
+                "req-1",
+                &SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+                &mut body,
+            )
+            .await;
+        assert_eq!(
+            outcome,
+            DlpOutcome::Block {
+                status: StatusCode::FORBIDDEN,
+                message: "secret in response".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_modes() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/scan")
+            .with_status(500)
+            .expect(2)
+            .create_async()
+            .await;
+        let url = format!("{}/scan", server.url());
+
+        let mut request = chat_request("hello");
+        let outcome = scanner(&url, Some(CalloutFailureMode::FailOpen))
+            .scan_request("req-1", &mut request)
+            .await;
+        assert_eq!(outcome, DlpOutcome::Allow);
+
+        let outcome = scanner(&url, None)
+            .scan_request("req-1", &mut request)
+            .await;
+        assert!(matches!(
+            outcome,
+            DlpOutcome::Block {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_response_texts() {
+        let mut messages = serde_json::json!({
+            "content": [
+                {"type": "text", "text": "hello"},
+                {"type": "tool_use", "id": "t1", "name": "lookup", "input": {}}
+            ]
+        });
+        let texts = response_texts(
+            &SupportedAPIsFromClient::AnthropicMessagesAPI(
+                hermesllm::apis::anthropic::AnthropicApi::Messages,
+            ),
+            &mut messages,
+        );
+        assert_eq!(texts, vec!["hello"]);
+
+        let mut responses = serde_json::json!({
+            "output": [{"type": "message", "content": [{"type": "output_text", "text": "hi"}]}]
+        });
+        let texts = response_texts(
+            &SupportedAPIsFromClient::OpenAIResponsesAPI(OpenAIApi::Responses),
+            &mut responses,
+        );
+        assert_eq!(texts, vec!["hi"]);
+    }
+}
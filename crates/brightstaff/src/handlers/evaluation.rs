@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::configuration::{EvaluationConfig, LlmProvider, ModelAlias};
+use common::consts::{ARCH_PROVIDER_HINT_HEADER, CHAT_COMPLETIONS_PATH};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::header;
+use hyper::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::handlers::admin::AdminToken;
+use crate::handlers::llm::{find_provider, resolve_model_alias};
+use crate::handlers::response_handler::ResponseHandler;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_MODELS: usize = 10;
+
+const JUDGE_PROMPT: &str = "You compare the answers of several models to the same prompt. Score every answer from 0 to 10 for how well it answers the prompt. Reply with json only, in the form {\"scores\": [{\"answer\": <number of the answer>, \"score\": <0 to 10>, \"rationale\": \"<one sentence>\"}]}.";
+
+/// Body of `POST /v1/debug/evaluate`
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvaluationRequest {
+    /// Sent as a single user message when `messages` isn't set
+    pub prompt: Option<String>,
+    /// Chat completions messages
+    pub messages: Option<Vec<Value>>,
+    /// Model aliases, models or provider names to compare
+    pub models: Vec<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f64>,
+    /// Score the answers with a judge model
+    pub judge: Option<JudgeRequest>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JudgeRequest {
+    /// Defaults to the judge_model of the config
+    pub model: Option<String>,
+    /// What makes a good answer, added to the instructions of the judge
+    pub criteria: Option<String>,
+}
+
+/// Answer of one model
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct EvaluationResult {
+    /// As requested
+    pub model: String,
+    /// Model the alias resolved to
+    pub resolved_model: String,
+    pub completion: Option<String>,
+    pub latency_ms: u64,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// USD, when the provider has pricing configured and the response reported usage
+    pub cost: Option<f64>,
+    pub error: Option<String>,
+    /// From 0 to 10, set when a judge scored the answer
+    pub score: Option<f64>,
+    pub rationale: Option<String>,
+}
+
+/// Sends one prompt to several models through llm_gateway, so every provider gets the
+/// request in its own api, and compares what they answered
+pub struct Evaluator {
+    client: reqwest::Client,
+    url: String,
+    admin_token: AdminToken,
+    judge_model: Option<String>,
+    timeout: Duration,
+    max_models: usize,
+}
+
+/// Completion text with token usage of a chat completions response
+struct Completion {
+    text: String,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+impl Evaluator {
+    pub fn new(config: &EvaluationConfig, llm_provider_url: &str, admin_token: AdminToken) -> Self {
+        Evaluator {
+            client: reqwest::Client::new(),
+            url: format!("{}{}", llm_provider_url, CHAT_COMPLETIONS_PATH),
+            admin_token,
+            judge_model: config.judge_model.clone(),
+            timeout: config
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_TIMEOUT),
+            max_models: config.max_models.unwrap_or(DEFAULT_MAX_MODELS).max(1),
+        }
+    }
+
+    /// Asks every model at the same time, then the judge if one was requested
+    pub async fn evaluate(
+        &self,
+        request: &EvaluationRequest,
+        model_aliases: &Arc<Option<HashMap<String, ModelAlias>>>,
+        llm_providers: &Arc<RwLock<Vec<LlmProvider>>>,
+    ) -> Result<Vec<EvaluationResult>, String> {
+        if request.models.is_empty() || request.models.len() > self.max_models {
+            return Err(format!("models must list 1 to {} models", self.max_models));
+        }
+        let messages = match (&request.messages, &request.prompt) {
+            (Some(messages), _) if !messages.is_empty() => messages.clone(),
+            (_, Some(prompt)) => vec![json!({"role": "user", "content": prompt})],
+            _ => return Err("prompt or messages is required".to_string()),
+        };
+        let judge_model = match &request.judge {
+            Some(judge) => Some(
+                judge
+                    .model
+                    .clone()
+                    .or_else(|| self.judge_model.clone())
+                    .ok_or("judge.model is required, no judge_model is configured")?,
+            ),
+            None => None,
+        };
+
+        let calls = request.models.iter().map(|model| async {
+            let resolved_model = resolve_model_alias(model, model_aliases);
+            let mut body = json!({
+                "model": resolved_model,
+                "messages": messages,
+                "stream": false,
+            });
+            if let Some(max_tokens) = request.max_tokens {
+                body["max_tokens"] = json!(max_tokens);
+            }
+            if let Some(temperature) = request.temperature {
+                body["temperature"] = json!(temperature);
+            }
+            let start = Instant::now();
+            let completion = self.complete(&resolved_model, &body).await;
+            let mut result = EvaluationResult {
+                model: model.clone(),
+                latency_ms: start.elapsed().as_millis() as u64,
+                ..Default::default()
+            };
+            match completion {
+                Ok(completion) => {
+                    let pricing = find_provider(llm_providers, &resolved_model)
+                        .await
+                        .and_then(|provider| provider.pricing);
+                    result.cost = match (pricing, completion.input_tokens, completion.output_tokens)
+                    {
+                        (Some(pricing), Some(input), Some(output)) => {
+                            Some(pricing.cost(input, output))
+                        }
+                        _ => None,
+                    };
+                    result.completion = Some(completion.text);
+                    result.input_tokens = completion.input_tokens;
+                    result.output_tokens = completion.output_tokens;
+                }
+                Err(err) => result.error = Some(err),
+            }
+            result.resolved_model = resolved_model;
+            result
+        });
+        let mut results = futures::future::join_all(calls).await;
+
+        if let Some(judge_model) = judge_model {
+            let criteria = request
+                .judge
+                .as_ref()
+                .and_then(|judge| judge.criteria.as_deref());
+            let body = judge_request(&judge_model, &messages, &results, criteria);
+            match self.complete(&judge_model, &body).await {
+                Ok(verdict) => {
+                    for (answer, score, rationale) in parse_scores(&verdict.text) {
+                        if let Some(result) = results.get_mut(answer) {
+                            result.score = Some(score);
+                            result.rationale = Some(rationale);
+                        }
+                    }
+                }
+                Err(err) => {
+                    for result in results.iter_mut().filter(|result| result.error.is_none()) {
+                        result.rationale = Some(format!("judge failed: {}", err));
+                    }
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    async fn complete(&self, model: &str, body: &Value) -> Result<Completion, String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(ARCH_PROVIDER_HINT_HEADER, model)
+            .timeout(self.timeout)
+            .json(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = response.status();
+        let text = response.text().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{} returned {}: {}", model, status, text));
+        }
+        let response: Value = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+        let usage = |key: &str| response["usage"][key].as_u64();
+        Ok(Completion {
+            text: response["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            input_tokens: usage("prompt_tokens"),
+            output_tokens: usage("completion_tokens"),
+        })
+    }
+}
+
+/// Chat completions request asking the judge to score the answers, numbered in the order
+/// of the results. Failed calls are left out.
+fn judge_request(
+    judge_model: &str,
+    messages: &[Value],
+    results: &[EvaluationResult],
+    criteria: Option<&str>,
+) -> Value {
+    let mut instructions = JUDGE_PROMPT.to_string();
+    if let Some(criteria) = criteria {
+        instructions.push_str(&format!("\n\nA good answer: {}", criteria));
+    }
+    let mut transcript = String::from("Prompt:\n");
+    for message in messages {
+        let role = message["role"].as_str().unwrap_or("user");
+        let content = match &message["content"] {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        transcript.push_str(&format!("{}: {}\n", role, content));
+    }
+    for (answer, result) in results.iter().enumerate() {
+        if let Some(completion) = &result.completion {
+            transcript.push_str(&format!("\nAnswer {}:\n{}\n", answer, completion));
+        }
+    }
+    json!({
+        "model": judge_model,
+        "messages": [
+            {"role": "system", "content": instructions},
+            {"role": "user", "content": transcript},
+        ],
+        "temperature": 0,
+        "stream": false,
+    })
+}
+
+/// Answer number, score and rationale of each answer the judge scored. The json may be
+/// wrapped in prose or a code fence.
+fn parse_scores(verdict: &str) -> Vec<(usize, f64, String)> {
+    let json = match (verdict.find('{'), verdict.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &verdict[start..=end],
+        _ => return Vec::new(),
+    };
+    let Ok(verdict) = serde_json::from_str::<Value>(json) else {
+        return Vec::new();
+    };
+    verdict["scores"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|score| {
+            Some((
+                score["answer"].as_u64()? as usize,
+                score["score"].as_f64()?.clamp(0.0, 10.0),
+                score["rationale"].as_str().unwrap_or_default().to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Handles `POST /v1/debug/evaluate`: the answers of the models side by side. Requires the
+/// admin token as a bearer token.
+pub async fn evaluate(
+    request: Request<hyper::body::Incoming>,
+    evaluator: Option<Arc<Evaluator>>,
+    model_aliases: Arc<Option<HashMap<String, ModelAlias>>>,
+    llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let Some(evaluator) = evaluator else {
+        return Ok(ResponseHandler::create_error_response(
+            StatusCode::NOT_FOUND,
+            "evaluation is not configured",
+        ));
+    };
+    if !evaluator.admin_token.is_authorized(request.headers()) {
+        return Ok(ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        ));
+    }
+    let body = request.collect().await?.to_bytes();
+    let evaluation: EvaluationRequest = match serde_json::from_slice(&body) {
+        Ok(evaluation) => evaluation,
+        Err(err) => {
+            return Ok(ResponseHandler::create_error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("invalid evaluation request: {}", err),
+            ))
+        }
+    };
+    let results = match evaluator
+        .evaluate(&evaluation, &model_aliases, &llm_providers)
+        .await
+    {
+        Ok(results) => results,
+        Err(err) => {
+            return Ok(ResponseHandler::create_error_response(
+                StatusCode::BAD_REQUEST,
+                &err,
+            ))
+        }
+    };
+    info!(
+        "evaluated {} models{}",
+        results.len(),
+        if evaluation.judge.is_some() {
+            " with a judge"
+        } else {
+            ""
+        }
+    );
+    let body = json!({ "object": "evaluation", "results": results });
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_completion(content: &str) -> String {
+        json!({
+            "id": "1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "m",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_scores() {
+        let verdict = "```json\n{\"scores\": [{\"answer\": 0, \"score\": 8, \"rationale\": \"clear\"}, {\"answer\": 1, \"score\": 12}]}\n```";
+        assert_eq!(
+            parse_scores(verdict),
+            vec![(0, 8.0, "clear".to_string()), (1, 10.0, String::new())]
+        );
+        assert!(parse_scores("no json here").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_with_judge() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", CHAT_COMPLETIONS_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "gpt-4o")
+            .with_body(chat_completion("Paris"))
+            .create_async()
+            .await;
+        server
+            .mock("POST", CHAT_COMPLETIONS_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "claude-sonnet-4")
+            .with_status(429)
+            .with_body("rate limited")
+            .create_async()
+            .await;
+        server
+            .mock("POST", CHAT_COMPLETIONS_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "judge")
+            .with_body(chat_completion(
+                r#"{"scores": [{"answer": 0, "score": 9, "rationale": "correct"}]}"#,
+            ))
+            .create_async()
+            .await;
+
+        let evaluator = Evaluator::new(
+            &EvaluationConfig {
+                judge_model: Some("judge".to_string()),
+                ..Default::default()
+            },
+            &server.url(),
+            AdminToken::new(Some("secret")),
+        );
+        let model_aliases = Arc::new(Some(HashMap::from([(
+            "smart".to_string(),
+            ModelAlias {
+                target: "gpt-4o".to_string(),
+                splits: None,
+                sticky_header: None,
+                shadow: None,
+                prompt_template: None,
+                system_prompt_policy: None,
+                semantic_cache: None,
+            },
+        )])));
+        let request = EvaluationRequest {
+            prompt: Some("Capital of France?".to_string()),
+            messages: None,
+            models: vec!["smart".to_string(), "claude-sonnet-4".to_string()],
+            max_tokens: None,
+            temperature: None,
+            judge: Some(JudgeRequest::default()),
+        };
+        let results = evaluator
+            .evaluate(&request, &model_aliases, &Arc::new(RwLock::new(Vec::new())))
+            .await
+            .unwrap();
+
+        assert_eq!(results[0].model, "smart");
+        assert_eq!(results[0].resolved_model, "gpt-4o");
+        assert_eq!(results[0].completion.as_deref(), Some("Paris"));
+        assert_eq!(results[0].input_tokens, Some(10));
+        assert_eq!(results[0].score, Some(9.0));
+        assert!(results[1].error.as_deref().unwrap().contains("429"));
+        assert_eq!(results[1].score, None);
+    }
+}
@@ -0,0 +1,228 @@
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::configuration::FaultInjectionConfig;
+use hyper::header;
+use hyper::StatusCode;
+use tokio_stream::{Stream, StreamExt};
+
+/// Environment variable that turns on the fault injection configured on providers, so a
+/// config copied to production doesn't inject failures there
+pub const FAULT_INJECTION_ENV: &str = "FAULT_INJECTION_ENABLED";
+
+const DEFAULT_TRUNCATE_AFTER_CHUNKS: usize = 3;
+/// Event whose json ends in the middle of an object
+const MALFORMED_EVENT: &str =
+    "data: {\"id\":\"fault-injection\",\"choices\":[{\"delta\":{\"content\":\n\n";
+
+type BodyStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+/// Whether brightstaff was started with fault injection on
+pub fn fault_injection_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var(FAULT_INJECTION_ENV)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(false)
+    })
+}
+
+/// Faults picked for one attempt of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    pub latency: Option<Duration>,
+    /// Status the request is answered with instead of being sent
+    pub status: Option<StatusCode>,
+    pub truncate_after_chunks: Option<usize>,
+    pub malformed_json: bool,
+}
+
+impl Faults {
+    pub fn sample(config: &FaultInjectionConfig) -> Self {
+        Self::sample_with(config, rand::random::<f64>)
+    }
+
+    fn sample_with(config: &FaultInjectionConfig, mut roll: impl FnMut() -> f64) -> Self {
+        let mut hit = |rate: Option<f64>| rate.is_some_and(|rate| rate > 0.0 && roll() < rate);
+        let status = if hit(config.rate_limit_rate) {
+            Some(StatusCode::TOO_MANY_REQUESTS)
+        } else if hit(config.server_error_rate) {
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        } else {
+            None
+        };
+        Faults {
+            latency: config
+                .added_latency_ms
+                .filter(|latency| *latency > 0)
+                .map(Duration::from_millis),
+            status,
+            truncate_after_chunks: hit(config.truncated_stream_rate).then(|| {
+                config
+                    .truncate_after_chunks
+                    .unwrap_or(DEFAULT_TRUNCATE_AFTER_CHUNKS)
+            }),
+            malformed_json: hit(config.malformed_json_rate),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Faults::default()
+    }
+
+    /// What was injected, for logs
+    pub fn describe(&self) -> String {
+        let mut faults = Vec::new();
+        if let Some(latency) = self.latency {
+            faults.push(format!("latency={}ms", latency.as_millis()));
+        }
+        if let Some(status) = self.status {
+            faults.push(format!("status={}", status.as_u16()));
+        }
+        if let Some(chunks) = self.truncate_after_chunks {
+            faults.push(format!("truncate_after_chunks={}", chunks));
+        }
+        if self.malformed_json {
+            faults.push("malformed_json".to_string());
+        }
+        faults.join(" ")
+    }
+
+    /// The error response the provider is replaced with, if one was picked
+    pub fn error_response(&self, provider: &str) -> Option<reqwest::Response> {
+        let status = self.status?;
+        let body = serde_json::json!({
+            "error": {
+                "type": "fault_injection",
+                "message": format!("{} failure injected for provider {}", status.as_u16(), provider),
+            }
+        });
+        let mut response = hyper::Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json");
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            response = response.header(header::RETRY_AFTER, "1");
+        }
+        let response = response.body(Bytes::from(body.to_string())).ok()?;
+        Some(reqwest::Response::from(response))
+    }
+
+    /// Whether the faults change the response body
+    pub fn changes_body(&self) -> bool {
+        self.truncate_after_chunks.is_some() || self.malformed_json
+    }
+
+    /// The response body with the stream cut off or its json broken. A malformed non
+    /// streaming body is the first half of the response.
+    pub fn inject_into_body(&self, body: BodyStream, is_streaming: bool) -> BodyStream {
+        let mut body = body;
+        if is_streaming {
+            if let Some(chunks) = self.truncate_after_chunks {
+                body = Box::pin(body.take(chunks));
+            }
+            if self.malformed_json {
+                let malformed =
+                    tokio_stream::once(Ok(Bytes::from_static(MALFORMED_EVENT.as_bytes())));
+                body = Box::pin(malformed.chain(body));
+            }
+            return body;
+        }
+        if !self.malformed_json {
+            return body;
+        }
+        Box::pin(futures::stream::once(async move {
+            let mut complete = Vec::new();
+            let mut body = body;
+            while let Some(chunk) = body.next().await {
+                complete.extend_from_slice(&chunk?);
+            }
+            complete.truncate(complete.len() / 2);
+            Ok(Bytes::from(complete))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(chunks: &[&'static str]) -> BodyStream {
+        let chunks: Vec<reqwest::Result<Bytes>> = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect();
+        Box::pin(tokio_stream::iter(chunks))
+    }
+
+    async fn collect(mut body: BodyStream) -> String {
+        let mut collected = Vec::new();
+        while let Some(chunk) = body.next().await {
+            collected.extend_from_slice(&chunk.unwrap());
+        }
+        String::from_utf8(collected).unwrap()
+    }
+
+    #[test]
+    fn test_sample() {
+        let config = FaultInjectionConfig {
+            rate_limit_rate: Some(0.5),
+            server_error_rate: Some(0.5),
+            added_latency_ms: Some(200),
+            malformed_json_rate: Some(0.1),
+            ..Default::default()
+        };
+        // 429 missed, 500 hit, malformed json missed
+        let mut rolls = [0.7, 0.2, 0.3].into_iter();
+        let faults = Faults::sample_with(&config, || rolls.next().unwrap());
+        assert_eq!(
+            faults,
+            Faults {
+                latency: Some(Duration::from_millis(200)),
+                status: Some(StatusCode::INTERNAL_SERVER_ERROR),
+                truncate_after_chunks: None,
+                malformed_json: false,
+            }
+        );
+        assert_eq!(faults.describe(), "latency=200ms status=500");
+
+        let none = Faults::sample(&FaultInjectionConfig::default());
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_error_response() {
+        let faults = Faults {
+            status: Some(StatusCode::TOO_MANY_REQUESTS),
+            ..Default::default()
+        };
+        let response = faults.error_response("openai/gpt-4o").unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        assert!(Faults::default().error_response("openai/gpt-4o").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_inject_into_body() {
+        let truncated = Faults {
+            truncate_after_chunks: Some(2),
+            ..Default::default()
+        };
+        let stream = body(&["data: 1\n\n", "data: 2\n\n", "data: [DONE]\n\n"]);
+        assert_eq!(
+            collect(truncated.inject_into_body(stream, true)).await,
+            "data: 1\n\ndata: 2\n\n"
+        );
+
+        let malformed = Faults {
+            malformed_json: true,
+            ..Default::default()
+        };
+        let json = body(&["{\"id\":\"chatcmpl-1\",", "\"choices\":[]}"]);
+        let json = collect(malformed.inject_into_body(json, false)).await;
+        assert_eq!(json, "{\"id\":\"chatcmpl-");
+        assert!(serde_json::from_str::<serde_json::Value>(&json).is_err());
+    }
+}
@@ -1093,6 +1093,7 @@ impl ArchFunctionHandler {
                 audio: None,
                 function_call: None,
                 tool_calls: None,
+                reasoning_content: None,
             }
         } else if !response_dict.required_functions.is_empty() {
             if !use_agent_orchestrator {
@@ -1104,6 +1105,7 @@ impl ArchFunctionHandler {
                     audio: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 }
             } else {
                 ResponseMessage {
@@ -1114,6 +1116,7 @@ impl ArchFunctionHandler {
                     audio: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 }
             }
         } else if !response_dict.tool_calls.is_empty() {
@@ -1139,6 +1142,7 @@ impl ArchFunctionHandler {
                                 audio: None,
                                 function_call: None,
                                 tool_calls: Some(response_dict.tool_calls.clone()),
+                                reasoning_content: None,
                             }
                         } else {
                             error!("Invalid tool call - {}", verification.error_message);
@@ -1150,6 +1154,7 @@ impl ArchFunctionHandler {
                                 audio: None,
                                 function_call: None,
                                 tool_calls: None,
+                                reasoning_content: None,
                             }
                         }
                     } else {
@@ -1162,6 +1167,7 @@ impl ArchFunctionHandler {
                             audio: None,
                             function_call: None,
                             tool_calls: None,
+                            reasoning_content: None,
                         }
                     }
                 } else {
@@ -1181,6 +1187,7 @@ impl ArchFunctionHandler {
                         audio: None,
                         function_call: None,
                         tool_calls: Some(response_dict.tool_calls.clone()),
+                        reasoning_content: None,
                     }
                 }
             } else {
@@ -1196,6 +1203,7 @@ impl ArchFunctionHandler {
                     audio: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 }
             }
         } else {
@@ -1208,6 +1216,7 @@ impl ArchFunctionHandler {
                 audio: None,
                 function_call: None,
                 tool_calls: None,
+                reasoning_content: None,
             }
         };
 
@@ -1229,6 +1238,7 @@ impl ArchFunctionHandler {
                 message: model_message,
                 finish_reason: Some(FinishReason::Stop),
                 logprobs: None,
+                stop_sequence: None,
             }],
             usage: Usage {
                 prompt_tokens: 0,
@@ -1236,10 +1246,12 @@ impl ArchFunctionHandler {
                 total_tokens: 0,
                 prompt_tokens_details: None,
                 completion_tokens_details: None,
+                provider_extensions: Default::default(),
             },
             system_fingerprint: None,
             service_tier: None,
             metadata: Some(metadata),
+            extensions: Default::default(),
         };
 
         info!("[response arch-fc]: {:?}", chat_completion_response);
@@ -50,6 +50,17 @@ mod tests {
                 url: "http://localhost:8081".to_string(),
                 tool: None,
                 transport: None,
+                timeout_ms: None,
+                max_retries: None,
+                retry_backoff_ms: None,
+                circuit_breaker: None,
+                requires_approval: None,
+                approval_webhook: None,
+                approval_timeout_ms: None,
+                retrieval: None,
+                api: None,
+                tools_refresh_interval_ms: None,
+                stdio: None,
             },
             Agent {
                 id: "terminal-agent".to_string(),
@@ -57,15 +68,24 @@ mod tests {
                 url: "http://localhost:8082".to_string(),
                 tool: None,
                 transport: None,
+                timeout_ms: None,
+                max_retries: None,
+                retry_backoff_ms: None,
+                circuit_breaker: None,
+                requires_approval: None,
+                approval_webhook: None,
+                approval_timeout_ms: None,
+                retrieval: None,
+                api: None,
+                tools_refresh_interval_ms: None,
+                stdio: None,
             },
         ];
 
         let agent_pipeline = AgentFilterChain {
             id: "terminal-agent".to_string(),
-            filter_chain: Some(vec![
-                "filter-agent".to_string(),
-                "terminal-agent".to_string(),
-            ]),
+            filter_chain: Some(vec!["filter-agent".into(), "terminal-agent".into()]),
+            stream_filter_chain: None,
             description: Some("Test pipeline".to_string()),
             default: Some(true),
         };
@@ -75,6 +95,11 @@ mod tests {
             agents: Some(vec![agent_pipeline.clone()]),
             port: 8080,
             router: None,
+            allowed_apis: None,
+            default_provider: None,
+            passthrough_auth: None,
+            embedding_router: None,
+            oidc: None,
         };
 
         let listeners = vec![listener];
@@ -106,6 +131,7 @@ mod tests {
         let test_pipeline = AgentFilterChain {
             id: "terminal-agent".to_string(),
             filter_chain: Some(vec![]), // Empty filter chain - no network calls needed
+            stream_filter_chain: None,
             description: None,
             default: None,
         };
@@ -118,6 +144,7 @@ mod tests {
                 &agent_map,
                 &headers,
                 None,
+                None,
                 String::new(),
                 String::new(),
             )
@@ -1,5 +1,7 @@
+use common::consts::BRIGHT_STAFF_SERVICE_NAME;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use uuid::Uuid;
 
 pub const JSON_RPC_VERSION: &str = "2.0";
 pub const TOOL_CALL_METHOD: &str = "tools/call";
@@ -47,3 +49,26 @@ pub struct JsonRpcResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<JsonRpcError>,
 }
+
+/// Request opening an MCP session, answered with the capabilities of the server
+pub fn mcp_initialize_request() -> JsonRpcRequest {
+    let mut params = HashMap::new();
+    params.insert(
+        "protocolVersion".to_string(),
+        serde_json::Value::String("2024-11-05".to_string()),
+    );
+    params.insert("capabilities".to_string(), serde_json::json!({}));
+    params.insert(
+        "clientInfo".to_string(),
+        serde_json::json!({
+            "name": BRIGHT_STAFF_SERVICE_NAME,
+            "version": "1.0.0"
+        }),
+    );
+    JsonRpcRequest {
+        jsonrpc: JSON_RPC_VERSION.to_string(),
+        id: JsonRpcId::String(Uuid::new_v4().to_string()),
+        method: MCP_INITIALIZE.to_string(),
+        params: Some(params),
+    }
+}
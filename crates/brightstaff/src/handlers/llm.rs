@@ -1,31 +1,62 @@
 use bytes::Bytes;
-use common::configuration::{LlmProvider, ModelAlias};
+use common::compression::ContentEncoding;
+use common::configuration::{ContentCaptureMode, LlmProvider, LlmProviderType, ModelAlias};
 use common::consts::{
-    ARCH_IS_STREAMING_HEADER, ARCH_PROVIDER_HINT_HEADER, REQUEST_ID_HEADER, TRACE_PARENT_HEADER,
+    ARCH_IS_STREAMING_HEADER, ARCH_MALFORMED_RESPONSE_HEADER, ARCH_PROVIDER_HINT_HEADER,
+    ARCH_RATELIMIT_CLAIMS_HEADER, ARCH_REQUEST_ID_HEADER, ARCH_SEMANTIC_CACHE_HEADER,
+    ARCH_SSE_TAP_HEADER, ARCH_TRAFFIC_SPLIT_HEADER, ARCH_UPSTREAM_ACCESS_TOKEN_HEADER,
+    TRACE_PARENT_HEADER,
 };
-use common::traces::TraceCollector;
+use common::traces::{Attribute, AttributeValue, TraceCollector};
+use hermesllm::apis::anthropic::{McpServer, MessagesRequest};
 use hermesllm::apis::openai_responses::InputParam;
 use hermesllm::clients::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
+use hermesllm::providers::error::UpstreamError;
 use hermesllm::{ProviderRequest, ProviderRequestType};
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Full};
 use hyper::header::{self};
 use hyper::{Request, Response, StatusCode};
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, RwLock};
+use tokio_stream::{Stream, StreamExt};
 use tracing::{debug, info, warn};
 
+use crate::handlers::access_token::access_tokens;
+use crate::handlers::archive::ConversationArchive;
+use crate::handlers::data_residency::{DataResidency, ResidencyDecision};
+use crate::handlers::dlp::{DlpOutcome, DlpScanner};
+use crate::handlers::fault_injection::{fault_injection_enabled, Faults};
+use crate::handlers::jwt::ValidatedClaims;
+use crate::handlers::mcp_connector::{run_mcp_tool_loop, McpConnectorError, McpConnectorPolicy};
+use crate::handlers::prompt_templates::PromptTemplates;
+use crate::handlers::provenance::{Provenance, ProvenancePolicy};
+use crate::handlers::provider_queue::{provider_queues, RequestPriorities};
+use crate::handlers::rate_limit_retry::{RateLimitRetries, RateLimitRetry};
+use crate::handlers::ratelimit_jwt::RatelimitJwt;
+use crate::handlers::request_callout::{CalloutOutcome, RequestCallout};
 use crate::handlers::router_chat::router_chat_get_upstream_model;
+use crate::handlers::semantic_cache::{CacheLookup, CachedResponse, SemanticCache};
+use crate::handlers::shadow::{
+    run_shadow, sample_shadow, shadow_request_body, shadow_request_headers, ShadowRequest,
+    DEFAULT_SHADOW_TIMEOUT,
+};
+use crate::handlers::sse_tap::{FixtureSource, SseTap, SseTapProcessor};
 use crate::handlers::utils::{
-    create_streaming_response, truncate_message, ObservableStreamProcessor,
+    create_streaming_response_with_keep_alive, truncate_message, CompressionProcessor, KeepAlive,
+    ObservableStreamProcessor, PendingCacheFill, PendingUsage,
 };
 use crate::router::llm_router::RouterService;
+use crate::router::traffic_split::select_split;
 use crate::state::response_state_processor::ResponsesStateProcessor;
 use crate::state::{
     extract_input_items, retrieve_and_combine_input, StateStorage, StateStorageError,
 };
-use crate::tracing::operation_component;
+use crate::tracing::{llm, operation_component, ContentCapturePolicy, GenAiResponseRecorder};
+use crate::usage::{UsageExporter, UsageRecord};
+use crate::utils::connection_pool::llm_client;
 
 fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
     Full::new(chunk.into())
@@ -33,6 +64,7 @@ fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
         .boxed()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn llm_chat(
     request: Request<hyper::body::Incoming>,
     router_service: Arc<RouterService>,
@@ -41,11 +73,29 @@ pub async fn llm_chat(
     llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
     trace_collector: Arc<TraceCollector>,
     state_storage: Option<Arc<dyn StateStorage>>,
+    content_capture: Arc<ContentCapturePolicy>,
+    usage_exporter: Option<Arc<UsageExporter>>,
+    malformed_response_retries: u32,
+    compress_responses: bool,
+    stream_keep_alive: Option<KeepAlive>,
+    rate_limit_retry: Option<RateLimitRetry>,
+    request_priorities: Arc<RequestPriorities>,
+    sse_tap: Option<Arc<SseTap>>,
+    request_callout: Option<Arc<RequestCallout>>,
+    ratelimit_jwt: Option<Arc<RatelimitJwt>>,
+    prompt_templates: Arc<PromptTemplates>,
+    semantic_cache: Arc<SemanticCache>,
+    data_residency: Option<Arc<DataResidency>>,
+    dlp: Option<Arc<DlpScanner>>,
+    provenance_policy: Option<ProvenancePolicy>,
+    conversation_archive: Option<Arc<ConversationArchive>>,
+    mcp_connector: Option<Arc<McpConnectorPolicy>>,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
     let request_path = request.uri().path().to_string();
     let request_headers = request.headers().clone();
+    let validated_claims = request.extensions().get::<ValidatedClaims>().cloned();
     let request_id = request_headers
-        .get(REQUEST_ID_HEADER)
+        .get(ARCH_REQUEST_ID_HEADER)
         .and_then(|h| h.to_str().ok())
         .map(|s| s.to_string())
         .unwrap_or_else(|| "unknown".to_string());
@@ -61,13 +111,40 @@ pub async fn llm_chat(
             format!("00-{}-0000000000000000-01", trace_id)
         });
 
+    let tenant = usage_exporter.as_ref().and_then(|exporter| {
+        request_headers
+            .get(exporter.tenant_header())
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string())
+    });
+
     let mut request_headers = request_headers;
+    // ratelimit selectors of the end user come from a validated JWT only, clients can't
+    // set them
+    request_headers.remove(ARCH_RATELIMIT_CLAIMS_HEADER);
+    if let Some(ratelimit_jwt) = &ratelimit_jwt {
+        match ratelimit_jwt
+            .selectors(&request_headers, validated_claims.as_ref())
+            .await
+        {
+            Ok(Some(selectors)) => {
+                request_headers.insert(ARCH_RATELIMIT_CLAIMS_HEADER, selectors);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!("[PLANO_REQ_ID:{}] | RATELIMIT_JWT | {}", request_id, err);
+                let mut unauthorized = Response::new(full(err.to_string()));
+                *unauthorized.status_mut() = StatusCode::UNAUTHORIZED;
+                return Ok(unauthorized);
+            }
+        }
+    }
     let chat_request_bytes = request.collect().await?.to_bytes();
 
     debug!(
         "[PLANO_REQ_ID:{}] | REQUEST_BODY (UTF8): {}",
         request_id,
-        String::from_utf8_lossy(&chat_request_bytes)
+        content_capture.log_body(&chat_request_bytes)
     );
 
     let mut client_request = match ProviderRequestType::try_from((
@@ -90,6 +167,18 @@ pub async fn llm_chat(
         }
     };
 
+    // The callout sees the model the client asked for, aliases it picks are resolved below
+    if let Some(request_callout) = &request_callout {
+        if let CalloutOutcome::Reject { status, message } = request_callout
+            .process(&request_id, &request_path, &mut client_request)
+            .await
+        {
+            let mut rejected = Response::new(full(message));
+            *rejected.status_mut() = status;
+            return Ok(rejected);
+        }
+    }
+
     // === v1/responses state management: Extract input items early ===
     let mut original_input_items = Vec::new();
     let client_api = SupportedAPIsFromClient::from_endpoint(request_path.as_str());
@@ -98,27 +187,156 @@ pub async fn llm_chat(
         Some(SupportedAPIsFromClient::OpenAIResponsesAPI(_))
     );
 
+    // The DLP scan sees the messages as the callout left them, before templates are applied
+    let mut request_dlp_outcome = None;
+    if let Some(dlp) = &dlp {
+        match dlp.scan_request(&request_id, &mut client_request).await {
+            DlpOutcome::Block { status, message } => {
+                return Ok(dlp_blocked(status, message, client_api.as_ref()));
+            }
+            outcome => request_dlp_outcome = Some(outcome),
+        }
+    }
+
     // Model alias resolution: update model field in client_request immediately
     // This ensures all downstream objects use the resolved model
     let model_from_request = client_request.model().to_string();
     let temperature = client_request.get_temperature();
     let is_streaming_request = client_request.is_streaming();
-    let resolved_model = resolve_model_alias(&model_from_request, &model_aliases);
+    // Aliases with traffic splits send a share of the requests to other models
+    let traffic_split = model_aliases
+        .as_ref()
+        .as_ref()
+        .and_then(|aliases| aliases.get(&model_from_request))
+        .and_then(|alias| select_split(&model_from_request, alias, &request_headers));
+    let resolved_model = match &traffic_split {
+        Some(split) => {
+            info!(
+                "[PLANO_REQ_ID:{}] | TRAFFIC_SPLIT | alias={} split={} target={}",
+                request_id, split.alias, split.label, split.target
+            );
+            split.target.clone()
+        }
+        None => resolve_model_alias(&model_from_request, &model_aliases),
+    };
+    let shadow_traffic = model_aliases
+        .as_ref()
+        .as_ref()
+        .and_then(|aliases| aliases.get(&model_from_request))
+        .and_then(|alias| alias.shadow.clone())
+        .filter(sample_shadow);
+
+    // The alias owns the system prompt when it names a template
+    let prompt_template_name = model_aliases
+        .as_ref()
+        .as_ref()
+        .and_then(|aliases| aliases.get(&model_from_request))
+        .and_then(|alias| alias.prompt_template.as_deref());
+    let mut prompt_template = None;
+    if let Some(name) = prompt_template_name {
+        match prompt_templates.apply(name, &model_from_request, &mut client_request) {
+            Ok(label) => {
+                debug!(
+                    "[PLANO_REQ_ID:{}] | PROMPT_TEMPLATE | applied {:?}",
+                    request_id, label
+                );
+                prompt_template = label;
+            }
+            Err(err) => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] | PROMPT_TEMPLATE | {} failed: {}",
+                    request_id, name, err
+                );
+                let mut bad_request =
+                    Response::new(full(format!("prompt template {}: {}", name, err)));
+                *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(bad_request);
+            }
+        }
+    }
+    // Policies of providers are applied by llm_gateway, once the request is converted
+    if let Some(policy) = model_aliases
+        .as_ref()
+        .as_ref()
+        .and_then(|aliases| aliases.get(&model_from_request))
+        .and_then(|alias| alias.system_prompt_policy.as_ref())
+    {
+        client_request.apply_system_prompt(policy.mode, &policy.content);
+    }
+
+    // Similar prompts to an alias with a semantic cache are answered from the cache.
+    // Responses api requests are left out as their responses are stored by id.
+    let mut cache_key = None;
+    if !is_responses_api_client && semantic_cache.applies(&model_from_request, &request_headers) {
+        match semantic_cache
+            .lookup(
+                &model_from_request,
+                &request_path,
+                &client_request,
+                &request_headers,
+                &traceparent,
+            )
+            .await
+        {
+            Some(CacheLookup::Hit {
+                response,
+                similarity,
+            }) => {
+                info!(
+                    "[PLANO_REQ_ID:{}] | SEMANTIC_CACHE | hit for {}, similarity={:.3}",
+                    request_id, model_from_request, similarity
+                );
+                return Ok(cached_response(response));
+            }
+            Some(CacheLookup::Miss(key)) => cache_key = Some(key),
+            None => {}
+        }
+    }
 
     // Extract tool names and user message preview for span attributes
     let tool_names = client_request.get_tool_names();
+    // Message content only reaches the span as far as the capture mode of this request allows
+    let capture_mode = content_capture.sample();
     let user_message_preview = client_request
         .get_recent_user_message()
-        .map(|msg| truncate_message(&msg, 50));
+        .filter(|_| capture_mode != ContentCaptureMode::Off)
+        .map(|msg| truncate_message(&content_capture.redact(&msg), 50));
+    let captured_prompt = (capture_mode == ContentCaptureMode::Full).then(|| {
+        content_capture
+            .scrub(&serde_json::to_string(&client_request.get_messages()).unwrap_or_default())
+    });
 
     client_request.set_model(resolved_model.clone());
-    if client_request.remove_metadata_key("archgw_preference_config") {
-        debug!(
-            "[PLANO_REQ_ID:{}] Removed archgw_preference_config from metadata",
+
+    // mcp_servers are resolved by the gateway (see mcp_connector) so that any upstream
+    // can honor them, the field itself is never forwarded
+    let mcp_servers = match &mut client_request {
+        ProviderRequestType::MessagesRequest(messages_request) => messages_request
+            .mcp_servers
+            .take()
+            .filter(|servers| !servers.is_empty()),
+        _ => None,
+    };
+    if mcp_servers.is_some() && mcp_connector.is_none() {
+        warn!(
+            "[PLANO_REQ_ID:{}] | FAILURE | mcp_servers without overrides.mcp_connector",
             request_id
         );
+        let mut bad_request = Response::new(full("mcp_servers are not enabled on this gateway"));
+        *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(bad_request);
+    }
+    if mcp_servers.is_some() && is_streaming_request {
+        warn!(
+            "[PLANO_REQ_ID:{}] | FAILURE | streaming is not supported with mcp_servers",
+            request_id
+        );
+        let mut bad_request = Response::new(full(
+            "streaming is not supported for requests with mcp_servers",
+        ));
+        *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(bad_request);
     }
-
     // === v1/responses state management: Determine upstream API and combine input if needed ===
     // Do this BEFORE routing since routing consumes the request
     // Only process state if state_storage is configured
@@ -221,6 +439,47 @@ pub async fn llm_chat(
 
     let model_name = routing_result.model_name;
 
+    // Tenants restricted to some providers or residencies are checked against the
+    // provider the request was routed to, before anything is sent to it
+    let residency_decision = match &data_residency {
+        Some(data_residency) => data_residency.check(
+            &request_headers,
+            &model_name,
+            find_provider(&llm_providers, &model_name).await.as_ref(),
+        ),
+        None => None,
+    };
+    if let Some(decision) = residency_decision
+        .as_ref()
+        .filter(|decision| !decision.allowed)
+    {
+        warn!(
+            "[PLANO_REQ_ID:{}] | DATA_RESIDENCY | {}",
+            request_id,
+            decision.message()
+        );
+        let mut denied_span = build_llm_span(
+            &traceparent,
+            &request_id,
+            &request_path,
+            &resolved_model,
+            &model_name,
+            StatusCode::FORBIDDEN.as_u16(),
+            is_streaming_request,
+            std::time::SystemTime::now(),
+            None,
+            None,
+            None,
+            temperature,
+            &llm_providers,
+        )
+        .await;
+        denied_span.end_time_unix_nano = denied_span.start_time_unix_nano.clone();
+        denied_span.attributes.extend(decision.attributes());
+        trace_collector.record_span(operation_component::LLM, denied_span);
+        return Ok(residency_denied(decision, client_api.as_ref()));
+    }
+
     debug!(
         "[PLANO_REQ_ID:{}] | ARCH_ROUTER URL | {}, Resolved Model: {}",
         request_id, full_qualified_llm_provider_url, model_name
@@ -235,44 +494,354 @@ pub async fn llm_chat(
         header::HeaderName::from_static(ARCH_IS_STREAMING_HEADER),
         header::HeaderValue::from_str(&is_streaming_request.to_string()).unwrap(),
     );
+    // llm_gateway labels the request metrics with the split
+    request_headers.remove(ARCH_TRAFFIC_SPLIT_HEADER);
+    if let Some(split) = &traffic_split {
+        if let Ok(label) = header::HeaderValue::from_str(&split.label) {
+            request_headers.insert(ARCH_TRAFFIC_SPLIT_HEADER, label);
+        }
+    }
     // remove content-length header if it exists
     request_headers.remove(header::CONTENT_LENGTH);
 
+    if let Some((mcp_servers, mcp_connector)) = mcp_servers.zip(mcp_connector) {
+        return Ok(mcp_connector_chat(
+            &client_request_bytes_for_upstream,
+            &mcp_servers,
+            &mcp_connector,
+            &full_qualified_llm_provider_url,
+            &request_headers,
+            &request_id,
+        )
+        .await);
+    }
+
+    // Shadow requests run next to the primary request and never reach the client, the
+    // primary completion is handed over once it's done for the comparison
+    let mut primary_completion = None;
+    if let Some(shadow) = shadow_traffic {
+        let body = shadow_request_body(&client_request_bytes_for_upstream, &shadow.target);
+        let headers = shadow_request_headers(&request_headers, &shadow.target);
+        if let (Some(body), Some(headers)) = (body, headers) {
+            let provider_timeout_ms = find_provider(&llm_providers, &shadow.target)
+                .await
+                .and_then(|provider| provider.timeout_ms);
+            let timeout = shadow
+                .timeout_ms
+                .or(provider_timeout_ms)
+                .map(std::time::Duration::from_millis)
+                .unwrap_or(DEFAULT_SHADOW_TIMEOUT);
+            info!(
+                "[PLANO_REQ_ID:{}] | SHADOW | mirroring {} to {}",
+                request_id, model_name, shadow.target
+            );
+            let (sender, receiver) = oneshot::channel();
+            primary_completion = Some(sender);
+            tokio::spawn(run_shadow(
+                ShadowRequest {
+                    request_id: request_id.clone(),
+                    traceparent: traceparent.clone(),
+                    request_path: request_path.clone(),
+                    primary_model: model_name.clone(),
+                    target: shadow.target,
+                    url: full_qualified_llm_provider_url.clone(),
+                    headers,
+                    body,
+                    timeout,
+                },
+                receiver,
+                trace_collector.clone(),
+            ));
+        }
+    }
+
+    let provider = find_provider(&llm_providers, &model_name).await;
+    let provider_name = provider
+        .as_ref()
+        .map(|provider| provider.name.clone())
+        .unwrap_or_else(|| model_name.clone());
+
+    // Providers with a concurrency limit queue requests by priority, the slot is held
+    // until the response is streamed to the client
+    let provider_slot = match provider
+        .as_ref()
+        .and_then(|provider| provider.concurrency.as_ref())
+    {
+        Some(limit) => {
+            let priority = request_priorities.priority(&request_headers);
+            match provider_queues()
+                .acquire(&request_id, &provider_name, limit, priority)
+                .await
+            {
+                Ok(slot) => Some(slot),
+                Err(shed) => {
+                    let mut too_many_requests =
+                        Response::new(full(format!("{}: {}", provider_name, shed)));
+                    *too_many_requests.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    return Ok(too_many_requests);
+                }
+            }
+        }
+        None => None,
+    };
+
     // Capture start time right before sending request to upstream
     let request_start_time = std::time::Instant::now();
     let request_start_system_time = std::time::SystemTime::now();
 
-    let llm_response = match reqwest::Client::new()
-        .post(full_qualified_llm_provider_url)
-        .headers(request_headers)
-        .body(client_request_bytes_for_upstream)
-        .send()
-        .await
+    // llm_gateway flags responses it couldn't parse nor repair, those are sent again, and
+    // 429s with a Retry-After are held and sent again when rate limit retries are on
+    let mut rate_limit_retries =
+        rate_limit_retry.map(|config| RateLimitRetries::new(config, &provider_name));
+    let mut retry_slot = None;
+    let client = llm_client();
+    let request_body = Bytes::from(client_request_bytes_for_upstream);
+    // llm_gateway answers uncompressed, the response is compressed for the client below
+    let mut upstream_headers = request_headers.clone();
+    upstream_headers.remove(header::ACCEPT_ENCODING);
+    // sampled streams come back from llm_gateway with the raw upstream chunks next to
+    // their translation, clients can't ask for that themselves
+    upstream_headers.remove(ARCH_SSE_TAP_HEADER);
+    // providers authenticated with a service account get a fresh access token, llm_gateway
+    // can't call the token endpoint itself
+    upstream_headers.remove(ARCH_UPSTREAM_ACCESS_TOKEN_HEADER);
+    if let Some(token_url) = provider
+        .as_ref()
+        .and_then(|provider| provider.access_token_url())
     {
-        Ok(res) => res,
-        Err(err) => {
-            let err_msg = format!("Failed to send request: {}", err);
-            let mut internal_error = Response::new(full(err_msg));
-            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-            return Ok(internal_error);
+        let access_token = match access_tokens().token(token_url).await {
+            Ok(token) => header::HeaderValue::from_str(&token).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match access_token {
+            Ok(access_token) => {
+                upstream_headers.insert(ARCH_UPSTREAM_ACCESS_TOKEN_HEADER, access_token);
+            }
+            Err(err) => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] | ACCESS_TOKEN | provider={} {}",
+                    request_id, provider_name, err
+                );
+                let mut bad_gateway = Response::new(full(format!(
+                    "Failed to get an access token for provider {}",
+                    provider_name
+                )));
+                *bad_gateway.status_mut() = StatusCode::BAD_GATEWAY;
+                return Ok(bad_gateway);
+            }
+        }
+    }
+    let mut sse_tap_recorder = None;
+    if let Some(tap) = sse_tap.filter(|tap| is_streaming_request && tap.sample()) {
+        upstream_headers.insert(ARCH_SSE_TAP_HEADER, header::HeaderValue::from_static("1"));
+        let fixture = provider.as_ref().map(|provider| FixtureSource {
+            client_api: request_path.clone(),
+            provider: provider.to_provider_id(),
+            request: request_body.clone(),
+        });
+        sse_tap_recorder = Some(tap.record(&request_id, &resolved_model, fixture));
+    }
+    let fault_injection = provider
+        .as_ref()
+        .and_then(|provider| provider.fault_injection.as_ref())
+        .filter(|_| fault_injection_enabled());
+    let mut faults;
+    let mut attempt = 0;
+    let llm_response = loop {
+        // every attempt gets its own faults, so retries can get through
+        faults = fault_injection.map(Faults::sample).unwrap_or_default();
+        if !faults.is_empty() {
+            warn!(
+                "[PLANO_REQ_ID:{}] | FAULT_INJECTION | provider={} {}",
+                request_id,
+                provider_name,
+                faults.describe()
+            );
+        }
+        if let Some(latency) = faults.latency {
+            tokio::time::sleep(latency).await;
+        }
+        let injected_error = faults.error_response(&provider_name);
+        let llm_response = match injected_error {
+            Some(injected_error) => injected_error,
+            None => match client
+                .post(&full_qualified_llm_provider_url)
+                .headers(upstream_headers.clone())
+                .body(request_body.clone())
+                .send()
+                .await
+            {
+                Ok(res) => res,
+                Err(err) => {
+                    let err_msg = format!("Failed to send request: {}", err);
+                    let mut internal_error = Response::new(full(err_msg));
+                    *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    return Ok(internal_error);
+                }
+            },
+        };
+        // the retry slot is taken until the retry is answered
+        drop(retry_slot.take());
+        if llm_response.status() == StatusCode::TOO_MANY_REQUESTS {
+            if let Some(retries) = rate_limit_retries.as_mut() {
+                retry_slot = retries.wait(&request_id, llm_response.headers()).await;
+                if retry_slot.is_some() {
+                    continue;
+                }
+            }
         }
+        if attempt >= malformed_response_retries
+            || !llm_response
+                .headers()
+                .contains_key(ARCH_MALFORMED_RESPONSE_HEADER)
+        {
+            break llm_response;
+        }
+        attempt += 1;
+        warn!(
+            "[PLANO_REQ_ID:{}] | MALFORMED_RESPONSE | model={} retry {}/{}",
+            request_id, model_name, attempt, malformed_response_retries
+        );
     };
 
     // copy over the headers and status code from the original response
     let response_headers = llm_response.headers().clone();
     let upstream_status = llm_response.status();
+    let mut provenance = provenance_policy.map(|_| Provenance {
+        provider: provider_name.clone(),
+        model: provider
+            .as_ref()
+            .and_then(|provider| provider.model.clone())
+            .unwrap_or_else(|| model_name.clone()),
+        decisions: Vec::new(),
+    });
+    if let Some(provenance) = provenance.as_mut() {
+        if let Some(split) = &traffic_split {
+            provenance.decision("traffic_split", split.label.clone());
+        }
+        if let Some(label) = &prompt_template {
+            provenance.decision("prompt_template", label.clone());
+        }
+        if residency_decision.is_some() {
+            provenance.decision("residency", "allowed");
+        }
+        match request_dlp_outcome {
+            Some(DlpOutcome::Redacted) => provenance.decision("dlp_request", "redacted"),
+            Some(_) => provenance.decision("dlp_request", "allowed"),
+            None => {}
+        }
+        if cache_key.is_some() {
+            provenance.decision("semantic_cache", "miss");
+        }
+    }
+
+    // Non streaming responses are held until the DLP service has scanned them and the
+    // provenance is added, streamed tokens reach the client as they are generated
+    let holds_body = !is_streaming_request
+        && upstream_status.is_success()
+        && !response_headers.contains_key(header::CONTENT_ENCODING);
+    let scan_response = dlp
+        .as_ref()
+        .filter(|dlp| holds_body && dlp.scans_responses());
+    let provenance_body = holds_body && provenance_policy.is_some_and(|policy| policy.body);
+    let mut response_rewritten = false;
+    let upstream_body: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>> =
+        match &client_api {
+            Some(client_api) if scan_response.is_some() || provenance_body => {
+                let mut body = match llm_response.bytes().await {
+                    Ok(body) => body,
+                    Err(err) => {
+                        let err_msg = format!("Failed to read response: {}", err);
+                        let mut internal_error = Response::new(full(err_msg));
+                        *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                        return Ok(internal_error);
+                    }
+                };
+                if let Some(dlp) = scan_response {
+                    let outcome = dlp.scan_response(&request_id, client_api, &mut body).await;
+                    if let DlpOutcome::Block { status, message } = outcome {
+                        return Ok(dlp_blocked(status, message, Some(client_api)));
+                    }
+                    response_rewritten = outcome == DlpOutcome::Redacted;
+                    if let Some(provenance) = provenance.as_mut() {
+                        provenance.decision(
+                            "dlp_response",
+                            if response_rewritten {
+                                "redacted"
+                            } else {
+                                "allowed"
+                            },
+                        );
+                    }
+                }
+                if let Some(provenance) = provenance.as_ref().filter(|_| provenance_body) {
+                    response_rewritten |= provenance.append_to_body(&mut body);
+                }
+                Box::pin(tokio_stream::once(Ok(body)))
+            }
+            _ => Box::pin(llm_response.bytes_stream()),
+        };
+    let upstream_body = if faults.changes_body() && upstream_status.is_success() {
+        response_rewritten = true;
+        faults.inject_into_body(upstream_body, is_streaming_request)
+    } else {
+        upstream_body
+    };
     let mut response = Response::builder().status(upstream_status);
     let headers = response.headers_mut().unwrap();
     for (header_name, header_value) in response_headers.iter() {
         headers.insert(header_name, header_value.clone());
     }
+    if response_rewritten {
+        headers.remove(header::CONTENT_LENGTH);
+    }
+    if let Some(provenance) = &provenance {
+        provenance.insert_headers(headers);
+    }
+    let upstream_encoding = response_headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !ContentEncoding::is_identity(value))
+        .and_then(ContentEncoding::from_header);
+    let client_encoding = request_headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(ContentEncoding::negotiate)
+        .filter(|_| compress_responses && upstream_status.is_success());
+    if upstream_encoding.is_some() || client_encoding.is_some() {
+        headers.remove(header::CONTENT_ENCODING);
+        headers.remove(header::CONTENT_LENGTH);
+        if let Some(encoding) = client_encoding {
+            headers.insert(
+                header::CONTENT_ENCODING,
+                header::HeaderValue::from_static(encoding.as_str()),
+            );
+        }
+    }
+    // the processors below see the decoded response
+    let response_encoding = response_headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .filter(|_| upstream_encoding.is_none());
+    if cache_key.is_some() {
+        headers.insert(
+            ARCH_SEMANTIC_CACHE_HEADER,
+            header::HeaderValue::from_static("miss"),
+        );
+    }
 
     // Build LLM span with actual status code using constants
-    let byte_stream = llm_response.bytes_stream();
+    let byte_stream = upstream_body.map(move |chunk| {
+        // the provider slot is released once the stream is done
+        let _held = &provider_slot;
+        chunk
+    });
 
     // Build the LLM span (will be finalized after streaming completes)
-    let llm_span = build_llm_span(
+    let mut llm_span = build_llm_span(
         &traceparent,
+        &request_id,
         &request_path,
         &resolved_model,
         &model_name,
@@ -281,18 +850,126 @@ pub async fn llm_chat(
         request_start_system_time,
         tool_names,
         user_message_preview,
+        captured_prompt,
         temperature,
         &llm_providers,
     )
     .await;
+    if let Some(split) = &traffic_split {
+        llm_span.attributes.push(Attribute {
+            key: llm::MODEL_ALIAS.to_string(),
+            value: AttributeValue {
+                string_value: Some(split.alias.clone()),
+            },
+        });
+        llm_span.attributes.push(Attribute {
+            key: llm::TRAFFIC_SPLIT.to_string(),
+            value: AttributeValue {
+                string_value: Some(split.label.clone()),
+            },
+        });
+    }
+
+    if let Some(label) = prompt_template {
+        llm_span.attributes.push(Attribute {
+            key: llm::PROMPT_TEMPLATE.to_string(),
+            value: AttributeValue {
+                string_value: Some(label),
+            },
+        });
+    }
+    if let Some(decision) = &residency_decision {
+        llm_span.attributes.extend(decision.attributes());
+    }
+    if cache_key.is_some() {
+        llm_span.attributes.push(Attribute {
+            key: llm::SEMANTIC_CACHE.to_string(),
+            value: AttributeValue {
+                string_value: Some("miss".to_string()),
+            },
+        });
+    }
 
     // Create base processor for metrics and tracing
-    let base_processor = ObservableStreamProcessor::new(
+    let mut base_processor = ObservableStreamProcessor::new(
         trace_collector,
         operation_component::LLM,
         llm_span,
         request_start_time,
-    );
+    )
+    .with_gen_ai_recorder(GenAiResponseRecorder::new(
+        is_streaming_request,
+        (capture_mode == ContentCaptureMode::Full).then_some(content_capture),
+    ));
+    if let Some(sender) = primary_completion {
+        base_processor = base_processor.with_completion_tap(is_streaming_request, sender);
+    }
+    if let Some(archive) = conversation_archive.filter(|archive| archive.sample()) {
+        let provider = find_provider(&llm_providers, &model_name)
+            .await
+            .map(|provider| provider.name)
+            .unwrap_or_else(|| model_name.clone());
+        let conversation = archive.conversation(
+            &request_id,
+            &resolved_model,
+            &provider,
+            upstream_status.as_u16(),
+            is_streaming_request,
+            &chat_request_bytes,
+        );
+        let (sender, receiver) = oneshot::channel();
+        base_processor = base_processor.with_completion_tap(is_streaming_request, sender);
+        tokio::spawn(async move {
+            archive.archive_when_complete(conversation, receiver).await;
+        });
+    }
+    // only successful responses are cached
+    if let Some(key) = cache_key.filter(|_| upstream_status.is_success()) {
+        let header_value = |name: header::HeaderName| {
+            response_headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        base_processor = base_processor.with_cache_fill(PendingCacheFill {
+            cache: semantic_cache,
+            key,
+            content_type: header_value(header::CONTENT_TYPE),
+            content_encoding: response_encoding.clone(),
+            pricing: find_provider(&llm_providers, &model_name)
+                .await
+                .and_then(|provider| provider.pricing),
+            body: Vec::new(),
+        });
+    }
+
+    if let Some(exporter) = usage_exporter {
+        let provider = find_provider(&llm_providers, &model_name).await;
+        base_processor = base_processor.with_usage(PendingUsage {
+            record: UsageRecord {
+                request_id: request_id.clone(),
+                timestamp: String::new(),
+                tenant,
+                provider: provider
+                    .as_ref()
+                    .map(|provider| provider.name.clone())
+                    .unwrap_or_else(|| model_name.clone()),
+                model: resolved_model.clone(),
+                input_tokens: None,
+                output_tokens: None,
+                cost: None,
+                latency_ms: 0,
+                status: upstream_status.as_u16(),
+                streaming: is_streaming_request,
+            },
+            pricing: provider.and_then(|provider| provider.pricing),
+            exporter,
+        });
+    }
+
+    // error responses aren't event streams
+    let keep_alive =
+        stream_keep_alive.filter(|_| is_streaming_request && upstream_status.is_success());
 
     // === v1/responses state management: Wrap with ResponsesStateProcessor ===
     // Only wrap if we need to manage state (client is ResponsesAPI AND upstream is NOT ResponsesAPI AND state_storage is configured)
@@ -301,12 +978,6 @@ pub async fn llm_chat(
         original_input_items.is_empty(),
         state_storage,
     ) {
-        // Extract Content-Encoding header to handle decompression for state parsing
-        let content_encoding = response_headers
-            .get("content-encoding")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string());
-
         // Wrap with state management processor to store state after response completes
         let state_processor = ResponsesStateProcessor::new(
             base_processor,
@@ -316,13 +987,31 @@ pub async fn llm_chat(
             model_name.clone(),
             is_streaming_request,
             false, // Not OpenAI upstream since should_manage_state is true
-            content_encoding,
+            response_encoding,
             request_id.clone(),
         );
-        create_streaming_response(byte_stream, state_processor, 16)
+        create_streaming_response_with_keep_alive(
+            byte_stream,
+            CompressionProcessor::new(
+                SseTapProcessor::new(state_processor, sse_tap_recorder),
+                upstream_encoding,
+                client_encoding,
+            ),
+            16,
+            keep_alive,
+        )
     } else {
         // Use base processor without state management
-        create_streaming_response(byte_stream, base_processor, 16)
+        create_streaming_response_with_keep_alive(
+            byte_stream,
+            CompressionProcessor::new(
+                SseTapProcessor::new(base_processor, sse_tap_recorder),
+                upstream_encoding,
+                client_encoding,
+            ),
+            16,
+            keep_alive,
+        )
     };
 
     match response.body(streaming_response.body) {
@@ -336,9 +1025,121 @@ pub async fn llm_chat(
     }
 }
 
+/// 403 of a request the data residency policy of its tenant denied, in the error format
+/// of the client api
+fn residency_denied(
+    decision: &ResidencyDecision,
+    client_api: Option<&SupportedAPIsFromClient>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let error = UpstreamError {
+        status: StatusCode::FORBIDDEN.as_u16(),
+        message: decision.message(),
+        error_type: None,
+        code: Some("data_residency_violation".to_string()),
+        param: None,
+    };
+    let body = match client_api {
+        Some(client_api) => error.to_client_body(client_api),
+        None => error.message.into_bytes(),
+    };
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(full(body))
+        .unwrap()
+}
+
+/// Request or response the DLP service blocked, in the error format of the client api
+fn dlp_blocked(
+    status: StatusCode,
+    message: String,
+    client_api: Option<&SupportedAPIsFromClient>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let error = UpstreamError {
+        status: status.as_u16(),
+        message,
+        error_type: None,
+        code: Some("dlp_violation".to_string()),
+        param: None,
+    };
+    let body = match client_api {
+        Some(client_api) => error.to_client_body(client_api),
+        None => error.message.into_bytes(),
+    };
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(full(body))
+        .unwrap()
+}
+
+/// Replays a cached response to the client
+fn cached_response(cached: CachedResponse) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(ARCH_SEMANTIC_CACHE_HEADER, "hit");
+    if let Some(content_type) = cached.content_type {
+        response = response.header(header::CONTENT_TYPE, content_type);
+    }
+    if let Some(content_encoding) = cached.content_encoding {
+        response = response.header(header::CONTENT_ENCODING, content_encoding);
+    }
+    response.body(full(cached.body)).unwrap()
+}
+
+/// Runs a Messages request with mcp_servers through the MCP tool loop and returns the
+/// final, non streaming response.
+async fn mcp_connector_chat(
+    request_bytes: &[u8],
+    mcp_servers: &[McpServer],
+    policy: &McpConnectorPolicy,
+    llm_url: &str,
+    request_headers: &header::HeaderMap,
+    request_id: &str,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let result = match serde_json::from_slice::<MessagesRequest>(request_bytes) {
+        Ok(messages_request) => {
+            run_mcp_tool_loop(
+                messages_request,
+                mcp_servers,
+                policy,
+                llm_url,
+                request_headers,
+                request_id,
+            )
+            .await
+        }
+        Err(err) => Err(err.into()),
+    };
+
+    match result.and_then(|response| Ok(serde_json::to_vec(&response)?)) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(full(body))
+            .unwrap(),
+        Err(err) => {
+            warn!(
+                "[PLANO_REQ_ID:{}] | FAILURE | MCP connector: {}",
+                request_id, err
+            );
+            let status = match &err {
+                McpConnectorError::UpstreamError { status, .. } => {
+                    StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY)
+                }
+                McpConnectorError::NotAllowed { .. } => StatusCode::BAD_REQUEST,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            let mut error_response = Response::new(full(err.client_message()));
+            *error_response.status_mut() = status;
+            error_response
+        }
+    }
+}
+
 /// Resolves model aliases by looking up the requested model in the model_aliases map.
 /// Returns the target model if an alias is found, otherwise returns the original model.
-fn resolve_model_alias(
+pub(crate) fn resolve_model_alias(
     model_from_request: &str,
     model_aliases: &Arc<Option<HashMap<String, ModelAlias>>>,
 ) -> String {
@@ -358,6 +1159,7 @@ fn resolve_model_alias(
 #[allow(clippy::too_many_arguments)]
 async fn build_llm_span(
     traceparent: &str,
+    request_id: &str,
     request_path: &str,
     resolved_model: &str,
     model_name: &str,
@@ -366,11 +1168,14 @@ async fn build_llm_span(
     start_time: std::time::SystemTime,
     tool_names: Option<Vec<String>>,
     user_message_preview: Option<String>,
+    captured_prompt: Option<String>,
     temperature: Option<f32>,
     llm_providers: &Arc<RwLock<Vec<LlmProvider>>>,
 ) -> common::traces::Span {
-    use crate::tracing::{http, llm, OperationNameBuilder};
-    use common::traces::{parse_traceparent, SpanBuilder, SpanKind};
+    use crate::tracing::{gen_ai, gen_ai_system, http, OperationNameBuilder};
+    use common::traces::{parse_traceparent, Event, SpanBuilder, SpanKind};
+
+    let (provider_interface, _) = get_provider_info(llm_providers, model_name).await;
 
     // Calculate the upstream path based on provider configuration
     let upstream_path = get_upstream_path(
@@ -407,8 +1212,12 @@ async fn build_llm_span(
         .with_attribute(http::STATUS_CODE, status_code.to_string())
         .with_attribute(http::TARGET, request_path.to_string())
         .with_attribute(http::UPSTREAM_TARGET, upstream_path)
+        .with_attribute(http::REQUEST_ID, request_id.to_string())
         .with_attribute(llm::MODEL_NAME, resolved_model.to_string())
-        .with_attribute(llm::IS_STREAMING, is_streaming.to_string());
+        .with_attribute(llm::IS_STREAMING, is_streaming.to_string())
+        .with_attribute(gen_ai::SYSTEM, gen_ai_system(&provider_interface))
+        .with_attribute(gen_ai::OPERATION_NAME, gen_ai::OPERATION_CHAT)
+        .with_attribute(gen_ai::REQUEST_MODEL, resolved_model.to_string());
 
     // Only set parent span ID if it exists (not a root span)
     if let Some(parent) = parent_span_id {
@@ -417,7 +1226,9 @@ async fn build_llm_span(
 
     // Add optional attributes
     if let Some(temp) = temperature {
-        span_builder = span_builder.with_attribute(llm::TEMPERATURE, temp.to_string());
+        span_builder = span_builder
+            .with_attribute(llm::TEMPERATURE, temp.to_string())
+            .with_attribute(gen_ai::REQUEST_TEMPERATURE, temp.to_string());
     }
 
     if let Some(tools) = tool_names {
@@ -433,7 +1244,16 @@ async fn build_llm_span(
         span_builder = span_builder.with_attribute(llm::USER_MESSAGE_PREVIEW, preview);
     }
 
-    span_builder.build()
+    let mut span = span_builder.build();
+    if let Some(prompt) = captured_prompt {
+        let mut event = Event::new(
+            gen_ai::CONTENT_PROMPT.to_string(),
+            span.start_time_unix_nano.parse().unwrap_or_default(),
+        );
+        event.add_attribute(gen_ai::PROMPT.to_string(), prompt);
+        span.events = Some(vec![event]);
+    }
+    span
 }
 
 /// Calculates the upstream path for the provider based on the model name.
@@ -446,7 +1266,11 @@ async fn get_upstream_path(
     resolved_model: &str,
     is_streaming: bool,
 ) -> String {
-    let (provider_id, base_url_path_prefix) = get_provider_info(llm_providers, model_name).await;
+    // the provider id depends on more than the interface, e.g. native or compatible Gemini
+    let (provider_id, base_url_path_prefix) = match find_provider(llm_providers, model_name).await {
+        Some(provider) => (provider.to_provider_id(), provider.base_url_path_prefix),
+        None => (LlmProviderType::OpenAI.to_provider_id(), None),
+    };
 
     // Calculate the upstream path using the proper API
     let client_api = SupportedAPIsFromClient::from_endpoint(request_path)
@@ -461,33 +1285,34 @@ async fn get_upstream_path(
     )
 }
 
-/// Helper function to get provider info (ProviderId and base_url_path_prefix)
+/// Helper function to get provider info (provider interface and base_url_path_prefix)
 async fn get_provider_info(
     llm_providers: &Arc<RwLock<Vec<LlmProvider>>>,
     model_name: &str,
-) -> (hermesllm::ProviderId, Option<String>) {
-    let providers_lock = llm_providers.read().await;
-
-    // First, try to find by model name or provider name
-    let provider = providers_lock.iter().find(|p| {
-        p.model.as_ref().map(|m| m == model_name).unwrap_or(false) || p.name == model_name
-    });
-
-    if let Some(provider) = provider {
-        let provider_id = provider.provider_interface.to_provider_id();
-        let prefix = provider.base_url_path_prefix.clone();
-        return (provider_id, prefix);
+) -> (LlmProviderType, Option<String>) {
+    match find_provider(llm_providers, model_name).await {
+        Some(provider) => (provider.provider_interface, provider.base_url_path_prefix),
+        None => {
+            // Last resort: use OpenAI as hardcoded fallback
+            warn!("No default provider found, falling back to OpenAI");
+            (LlmProviderType::OpenAI, None)
+        }
     }
+}
 
-    let default_provider = providers_lock.iter().find(|p| p.default.unwrap_or(false));
+/// Provider serving the model, by model or provider name, else the default provider
+pub(crate) async fn find_provider(
+    llm_providers: &Arc<RwLock<Vec<LlmProvider>>>,
+    model_name: &str,
+) -> Option<LlmProvider> {
+    let providers_lock = llm_providers.read().await;
 
-    if let Some(provider) = default_provider {
-        let provider_id = provider.provider_interface.to_provider_id();
-        let prefix = provider.base_url_path_prefix.clone();
-        (provider_id, prefix)
-    } else {
-        // Last resort: use OpenAI as hardcoded fallback
-        warn!("No default provider found, falling back to OpenAI");
-        (hermesllm::ProviderId::OpenAI, None)
-    }
+    // First, try to find by model name or provider name
+    providers_lock
+        .iter()
+        .find(|p| {
+            p.model.as_ref().map(|m| m == model_name).unwrap_or(false) || p.name == model_name
+        })
+        .or_else(|| providers_lock.iter().find(|p| p.default.unwrap_or(false)))
+        .cloned()
 }
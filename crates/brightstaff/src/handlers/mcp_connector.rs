@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+
+use common::configuration::McpConnectorConfig;
+use common::consts::BRIGHT_STAFF_SERVICE_NAME;
+use hermesllm::apis::anthropic::{
+    McpServer, MessagesContentBlock, MessagesMessage, MessagesMessageContent, MessagesRequest,
+    MessagesResponse, MessagesRole, MessagesStopReason, MessagesTool, ToolResultContent,
+};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::handlers::jsonrpc::{
+    JsonRpcId, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, JSON_RPC_VERSION,
+    MCP_INITIALIZE, MCP_INITIALIZE_NOTIFICATION, TOOL_CALL_METHOD,
+};
+
+pub const MCP_TOOLS_LIST_METHOD: &str = "tools/list";
+const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+const MCP_SESSION_ID_HEADER: &str = "mcp-session-id";
+
+/// Upper bound on model <-> tool round trips for a single request
+pub const MAX_MCP_TOOL_TURNS: usize = 8;
+
+/// Errors raised while emulating the MCP connector
+#[derive(Debug, thiserror::Error)]
+pub enum McpConnectorError {
+    #[error("HTTP request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Failed to parse response: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("MCP server '{server}' returned an error: {message}")]
+    ServerError { server: String, message: String },
+    #[error("No result in response from MCP server '{0}'")]
+    NoResultInResponse(String),
+    #[error("Upstream LLM returned HTTP {status}: {body}")]
+    UpstreamError { status: u16, body: String },
+    #[error("MCP tool loop did not finish after {0} turns")]
+    TooManyTurns(usize),
+    #[error("MCP server '{server}' is not allowed: {reason}")]
+    NotAllowed { server: String, reason: String },
+}
+
+impl McpConnectorError {
+    /// Message returned to the client. What MCP servers answer is only logged, the
+    /// client gets the server that failed.
+    pub fn client_message(&self) -> String {
+        match self {
+            McpConnectorError::RequestFailed(_) => "MCP server request failed".to_string(),
+            McpConnectorError::ServerError { server, .. }
+            | McpConnectorError::NoResultInResponse(server) => {
+                format!("MCP server '{}' failed", server)
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// MCP servers a request may name, from `overrides.mcp_connector`
+#[derive(Debug, Clone)]
+pub struct McpConnectorPolicy {
+    allowed_hosts: Vec<String>,
+}
+
+impl McpConnectorPolicy {
+    pub fn new(config: &McpConnectorConfig) -> Self {
+        Self {
+            allowed_hosts: config
+                .allowed_hosts
+                .iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Servers are only reached over https on one of the allowed hosts
+    pub fn check(&self, server: &McpServer) -> Result<(), McpConnectorError> {
+        let not_allowed = |reason: &str| McpConnectorError::NotAllowed {
+            server: server.name.clone(),
+            reason: reason.to_string(),
+        };
+        let url = reqwest::Url::parse(&server.url).map_err(|_| not_allowed("invalid url"))?;
+        if url.scheme() != "https" {
+            return Err(not_allowed("url must use https"));
+        }
+        match url.host_str() {
+            Some(host)
+                if self
+                    .allowed_hosts
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(host)) =>
+            {
+                Ok(())
+            }
+            _ => Err(not_allowed("host is not in mcp_connector.allowed_hosts")),
+        }
+    }
+}
+
+/// Tool definition as returned by `tools/list`
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpToolDefinition {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "inputSchema", default)]
+    pub input_schema: Value,
+}
+
+/// Page of tools returned by `tools/list`
+#[derive(Debug, Deserialize)]
+pub(crate) struct McpToolsListResult {
+    #[serde(default)]
+    pub tools: Vec<McpToolDefinition>,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct McpToolCallResult {
+    #[serde(default)]
+    content: Vec<Value>,
+    #[serde(rename = "isError")]
+    is_error: Option<bool>,
+}
+
+/// Minimal streamable HTTP MCP client, one per server listed in `mcp_servers`
+pub struct McpHttpClient {
+    client: reqwest::Client,
+    server_name: String,
+    url: String,
+    authorization_token: Option<String>,
+    session_id: Option<String>,
+}
+
+impl McpHttpClient {
+    pub fn new(server: &McpServer) -> Self {
+        Self {
+            // redirects could lead the gateway off the allowed hosts
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap_or_default(),
+            server_name: server.name.clone(),
+            url: server.url.clone(),
+            authorization_token: server.authorization_token.clone(),
+            session_id: None,
+        }
+    }
+
+    fn build_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json, text/event-stream"),
+        );
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        if let Some(token) = &self.authorization_token {
+            if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token)) {
+                headers.insert(header::AUTHORIZATION, value);
+            }
+        }
+        if let Some(sid) = self.session_id.as_deref() {
+            if let Ok(value) = HeaderValue::from_str(sid) {
+                headers.insert(MCP_SESSION_ID_HEADER, value);
+            }
+        }
+        headers
+    }
+
+    /// Run the initialize handshake, servers that are stateless may not return a session id
+    pub async fn initialize(&mut self) -> Result<(), McpConnectorError> {
+        let mut params = HashMap::new();
+        params.insert(
+            "protocolVersion".to_string(),
+            Value::String(MCP_PROTOCOL_VERSION.to_string()),
+        );
+        params.insert("capabilities".to_string(), serde_json::json!({}));
+        params.insert(
+            "clientInfo".to_string(),
+            serde_json::json!({"name": BRIGHT_STAFF_SERVICE_NAME, "version": "1.0.0"}),
+        );
+
+        let response = self.send_raw(MCP_INITIALIZE, Some(params)).await?;
+        self.session_id = response
+            .headers()
+            .get(MCP_SESSION_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        self.parse_response(response).await?;
+
+        let notification = JsonRpcNotification {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            method: MCP_INITIALIZE_NOTIFICATION.to_string(),
+            params: None,
+        };
+        self.client
+            .post(&self.url)
+            .headers(self.build_headers())
+            .body(serde_json::to_string(&notification)?)
+            .send()
+            .await?;
+
+        info!(
+            "initialized MCP server '{}', session: {:?}",
+            self.server_name, self.session_id
+        );
+        Ok(())
+    }
+
+    /// List every tool of the server, following pagination cursors
+    pub async fn list_tools(&self) -> Result<Vec<McpToolDefinition>, McpConnectorError> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.take().map(|c| {
+                let mut params = HashMap::new();
+                params.insert("cursor".to_string(), Value::String(c));
+                params
+            });
+            let result: McpToolsListResult = self.call(MCP_TOOLS_LIST_METHOD, params).await?;
+            tools.extend(result.tools);
+            match result.next_cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => break,
+            }
+        }
+        Ok(tools)
+    }
+
+    /// Call a tool, returns the text content and whether the server flagged it as an error
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Value,
+    ) -> Result<(String, bool), McpConnectorError> {
+        let mut params = HashMap::new();
+        params.insert("name".to_string(), Value::String(name.to_string()));
+        params.insert("arguments".to_string(), arguments);
+        let result: McpToolCallResult = self.call(TOOL_CALL_METHOD, Some(params)).await?;
+        Ok((
+            tool_result_text(&result.content),
+            result.is_error.unwrap_or(false),
+        ))
+    }
+
+    async fn send_raw(
+        &self,
+        method: &str,
+        params: Option<HashMap<String, Value>>,
+    ) -> Result<reqwest::Response, McpConnectorError> {
+        let request = JsonRpcRequest {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            id: JsonRpcId::String(Uuid::new_v4().to_string()),
+            method: method.to_string(),
+            params,
+        };
+        debug!(
+            "sending MCP request '{}' to server '{}'",
+            method, self.server_name
+        );
+        Ok(self
+            .client
+            .post(&self.url)
+            .headers(self.build_headers())
+            .body(serde_json::to_string(&request)?)
+            .send()
+            .await?)
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Option<HashMap<String, Value>>,
+    ) -> Result<T, McpConnectorError> {
+        let response = self.send_raw(method, params).await?;
+        let result = self.parse_response(response).await?;
+        Ok(serde_json::from_value(Value::Object(
+            result.into_iter().collect(),
+        ))?)
+    }
+
+    async fn parse_response(
+        &self,
+        response: reqwest::Response,
+    ) -> Result<HashMap<String, Value>, McpConnectorError> {
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            debug!(
+                "MCP server '{}' returned HTTP {}: {}",
+                self.server_name,
+                status,
+                String::from_utf8_lossy(&body)
+            );
+            return Err(McpConnectorError::ServerError {
+                server: self.server_name.clone(),
+                message: format!("HTTP {}", status),
+            });
+        }
+
+        let rpc_response = parse_json_rpc_body(&body)?;
+        if let Some(error) = rpc_response.error {
+            return Err(McpConnectorError::ServerError {
+                server: self.server_name.clone(),
+                message: error.message,
+            });
+        }
+        rpc_response
+            .result
+            .ok_or_else(|| McpConnectorError::NoResultInResponse(self.server_name.clone()))
+    }
+}
+
+/// Parse a JSON-RPC response that is either plain JSON or an SSE stream. For SSE the
+/// last `data:` payload that is a response (not a server notification) is used.
+fn parse_json_rpc_body(body: &[u8]) -> Result<JsonRpcResponse, serde_json::Error> {
+    let text = String::from_utf8_lossy(body);
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('{') {
+        return serde_json::from_str(trimmed);
+    }
+
+    let mut last_error = None;
+    for data in text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim)
+        .filter(|data| !data.is_empty())
+        .rev()
+    {
+        match serde_json::from_str::<JsonRpcResponse>(data) {
+            Ok(response) => return Ok(response),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| serde::de::Error::custom("no JSON-RPC response found in SSE body")))
+}
+
+fn tool_result_text(content: &[Value]) -> String {
+    content
+        .iter()
+        .filter_map(|item| match item.get("type").and_then(Value::as_str) {
+            Some("text") => item.get("text").and_then(Value::as_str).map(str::to_string),
+            _ => Some(item.to_string()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Tools of the connected MCP servers, flattened into a single namespace
+pub struct McpConnector {
+    clients: Vec<McpHttpClient>,
+    /// exposed tool name -> (client index, tool name on the server)
+    tool_index: HashMap<String, (usize, String)>,
+    tools: Vec<MessagesTool>,
+}
+
+impl McpConnector {
+    /// Connect to every enabled server and collect its allowed tools. Servers the policy
+    /// doesn't allow fail the request before any of them is contacted.
+    pub async fn connect(
+        servers: &[McpServer],
+        policy: &McpConnectorPolicy,
+    ) -> Result<Self, McpConnectorError> {
+        for server in servers {
+            policy.check(server)?;
+        }
+        let mut server_tools = Vec::new();
+        for server in servers {
+            let tool_configuration = server.tool_configuration.as_ref();
+            if tool_configuration.and_then(|c| c.enabled) == Some(false) {
+                debug!("skipping disabled MCP server '{}'", server.name);
+                continue;
+            }
+            let mut client = McpHttpClient::new(server);
+            client.initialize().await?;
+            let allowed_tools = tool_configuration.and_then(|c| c.allowed_tools.as_ref());
+            let tools = client
+                .list_tools()
+                .await?
+                .into_iter()
+                .filter(|tool| allowed_tools.is_none_or(|allowed| allowed.contains(&tool.name)))
+                .collect();
+            server_tools.push((client, tools));
+        }
+        Ok(Self::from_server_tools(server_tools))
+    }
+
+    /// Build the tool index. Tool names that collide across servers are prefixed
+    /// with the server name so the model can address each of them.
+    fn from_server_tools(server_tools: Vec<(McpHttpClient, Vec<McpToolDefinition>)>) -> Self {
+        let mut name_counts: HashMap<&str, usize> = HashMap::new();
+        for (_, tools) in &server_tools {
+            for tool in tools {
+                *name_counts.entry(tool.name.as_str()).or_default() += 1;
+            }
+        }
+        let name_counts: HashMap<String, usize> = name_counts
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+
+        let mut clients = Vec::new();
+        let mut tool_index = HashMap::new();
+        let mut exposed_tools = Vec::new();
+        for (index, (client, tools)) in server_tools.into_iter().enumerate() {
+            for tool in tools {
+                let exposed_name = if name_counts.get(&tool.name).copied().unwrap_or(0) > 1 {
+                    format!("{}_{}", client.server_name, tool.name)
+                } else {
+                    tool.name.clone()
+                };
+                let input_schema = if tool.input_schema.is_null() {
+                    serde_json::json!({"type": "object", "properties": {}})
+                } else {
+                    tool.input_schema
+                };
+                exposed_tools.push(MessagesTool {
+                    name: exposed_name.clone(),
+                    description: tool.description,
+                    input_schema,
+                });
+                tool_index.insert(exposed_name, (index, tool.name));
+            }
+            clients.push(client);
+        }
+
+        Self {
+            clients,
+            tool_index,
+            tools: exposed_tools,
+        }
+    }
+
+    pub fn tools(&self) -> &[MessagesTool] {
+        &self.tools
+    }
+
+    pub fn owns_tool(&self, name: &str) -> bool {
+        self.tool_index.contains_key(name)
+    }
+
+    /// Execute a tool call, failures are reported back to the model as error results
+    pub async fn call_tool(&self, name: &str, input: Value) -> (String, bool) {
+        let Some((index, server_tool_name)) = self.tool_index.get(name) else {
+            return (format!("unknown MCP tool '{}'", name), true);
+        };
+        match self.clients[*index]
+            .call_tool(server_tool_name, input)
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("MCP tool '{}' failed: {}", name, err);
+                (err.to_string(), true)
+            }
+        }
+    }
+}
+
+/// Emulate Anthropic's MCP connector for any upstream: the tools of `mcp_servers` are
+/// offered to the model, MCP tool calls are executed by the gateway and the request is
+/// replayed until the model stops calling them. Executed calls are returned to the client
+/// as `mcp_tool_use` / `mcp_tool_result` blocks ahead of the final content. When the
+/// model also asks for client side tools the response is returned as is.
+pub async fn run_mcp_tool_loop(
+    mut request: MessagesRequest,
+    mcp_servers: &[McpServer],
+    policy: &McpConnectorPolicy,
+    llm_url: &str,
+    request_headers: &HeaderMap,
+    request_id: &str,
+) -> Result<MessagesResponse, McpConnectorError> {
+    let connector = McpConnector::connect(mcp_servers, policy).await?;
+    info!(
+        "[PLANO_REQ_ID:{}] MCP_CONNECTOR: servers={}, tools={}",
+        request_id,
+        mcp_servers.len(),
+        connector.tools().len()
+    );
+
+    request.mcp_servers = None;
+    request.stream = Some(false);
+    let mut tools = request.tools.take().unwrap_or_default();
+    tools.extend(connector.tools().iter().cloned());
+    request.tools = Some(tools);
+
+    let client = reqwest::Client::new();
+    let mut executed_blocks = Vec::new();
+    for turn in 0..MAX_MCP_TOOL_TURNS {
+        let llm_response = client
+            .post(llm_url)
+            .headers(request_headers.clone())
+            .body(serde_json::to_vec(&request)?)
+            .send()
+            .await?;
+        let status = llm_response.status();
+        let body = llm_response.bytes().await?;
+        if !status.is_success() {
+            return Err(McpConnectorError::UpstreamError {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).to_string(),
+            });
+        }
+        let mut response: MessagesResponse = serde_json::from_slice(&body)?;
+
+        let tool_calls: Vec<(String, String, Value)> = response
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                MessagesContentBlock::ToolUse {
+                    id, name, input, ..
+                } => Some((id.clone(), name.clone(), input.clone())),
+                _ => None,
+            })
+            .collect();
+        let only_mcp_calls = !tool_calls.is_empty()
+            && tool_calls
+                .iter()
+                .all(|(_, name, _)| connector.owns_tool(name));
+
+        if response.stop_reason != MessagesStopReason::ToolUse || !only_mcp_calls {
+            executed_blocks.append(&mut response.content);
+            response.content = executed_blocks;
+            return Ok(response);
+        }
+
+        debug!(
+            "[PLANO_REQ_ID:{}] MCP_CONNECTOR: turn={}, tool_calls={}",
+            request_id,
+            turn,
+            tool_calls.len()
+        );
+
+        let mut tool_results = Vec::new();
+        for (id, name, input) in tool_calls {
+            let (text, is_error) = connector.call_tool(&name, input.clone()).await;
+            executed_blocks.push(MessagesContentBlock::McpToolUse {
+                id: id.clone(),
+                name,
+                input,
+            });
+            executed_blocks.push(MessagesContentBlock::McpToolResult {
+                tool_use_id: id.clone(),
+                is_error: Some(is_error),
+                content: vec![MessagesContentBlock::Text {
+                    text: text.clone(),
+                    cache_control: None,
+                }],
+            });
+            tool_results.push(MessagesContentBlock::ToolResult {
+                tool_use_id: id,
+                is_error: Some(is_error),
+                content: ToolResultContent::Text(text),
+                cache_control: None,
+            });
+        }
+
+        request.messages.push(MessagesMessage {
+            role: MessagesRole::Assistant,
+            content: MessagesMessageContent::Blocks(response.content),
+        });
+        request.messages.push(MessagesMessage {
+            role: MessagesRole::User,
+            content: MessagesMessageContent::Blocks(tool_results),
+        });
+    }
+
+    Err(McpConnectorError::TooManyTurns(MAX_MCP_TOOL_TURNS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::anthropic::McpServerType;
+
+    fn tool(name: &str) -> McpToolDefinition {
+        McpToolDefinition {
+            name: name.to_string(),
+            description: Some(format!("{} tool", name)),
+            input_schema: Value::Null,
+        }
+    }
+
+    fn client(name: &str) -> McpHttpClient {
+        McpHttpClient::new(&McpServer {
+            name: name.to_string(),
+            server_type: McpServerType::Url,
+            url: format!("http://{}.example.com/mcp", name),
+            authorization_token: Some("secret".to_string()),
+            tool_configuration: None,
+        })
+    }
+
+    #[test]
+    fn test_connector_flattens_tools_and_prefixes_collisions() {
+        let connector = McpConnector::from_server_tools(vec![
+            (client("github"), vec![tool("search"), tool("create_issue")]),
+            (client("jira"), vec![tool("search")]),
+        ]);
+
+        let names: Vec<&str> = connector.tools().iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["github_search", "create_issue", "jira_search"]);
+        assert!(connector.owns_tool("create_issue"));
+        assert!(!connector.owns_tool("search"));
+        assert_eq!(
+            connector.tool_index.get("jira_search"),
+            Some(&(1, "search".to_string()))
+        );
+        assert_eq!(connector.tools()[1].input_schema["type"], "object");
+    }
+
+    #[test]
+    fn test_policy_allows_only_https_urls_of_allowed_hosts() {
+        let policy = McpConnectorPolicy::new(&McpConnectorConfig {
+            allowed_hosts: vec!["MCP.example.com".to_string()],
+        });
+        let server = |url: &str| McpServer {
+            name: "tools".to_string(),
+            server_type: McpServerType::Url,
+            url: url.to_string(),
+            authorization_token: None,
+            tool_configuration: None,
+        };
+
+        assert!(policy.check(&server("https://mcp.example.com/mcp")).is_ok());
+        for url in [
+            "http://mcp.example.com/mcp",
+            "https://169.254.169.254/latest/meta-data",
+            "https://mcp.example.com.evil.com/mcp",
+            "not a url",
+        ] {
+            let err = policy.check(&server(url)).unwrap_err();
+            assert!(
+                matches!(err, McpConnectorError::NotAllowed { .. }),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn test_client_message_hides_server_responses() {
+        let err = McpConnectorError::ServerError {
+            server: "github".to_string(),
+            message: "internal details".to_string(),
+        };
+        assert_eq!(err.client_message(), "MCP server 'github' failed");
+    }
+
+    #[test]
+    fn test_client_headers_carry_token_and_session() {
+        let mut client = client("github");
+        client.session_id = Some("abc".to_string());
+        let headers = client.build_headers();
+        assert_eq!(headers.get(header::AUTHORIZATION).unwrap(), "Bearer secret");
+        assert_eq!(headers.get(MCP_SESSION_ID_HEADER).unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_parse_json_rpc_body() {
+        let json = br#"{"jsonrpc":"2.0","id":"1","result":{"tools":[]}}"#;
+        assert!(parse_json_rpc_body(json).unwrap().result.is_some());
+
+        let sse = b"event: message\ndata: {\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\"}\n\nevent: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":\"1\",\"result\":{\"content\":[]}}\n\n";
+        let response = parse_json_rpc_body(sse).unwrap();
+        assert!(response.result.unwrap().contains_key("content"));
+
+        assert!(parse_json_rpc_body(b"event: ping\n\n").is_err());
+    }
+
+    #[test]
+    fn test_tool_result_text() {
+        let content = vec![
+            serde_json::json!({"type": "text", "text": "first"}),
+            serde_json::json!({"type": "text", "text": "second"}),
+        ];
+        assert_eq!(tool_result_text(&content), "first\nsecond");
+    }
+}
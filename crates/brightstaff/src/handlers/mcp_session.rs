@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info};
+
+/// Sessions unused for this long are dropped and re-initialized on next use
+pub const DEFAULT_MCP_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+/// Upper bound on cached sessions, the least recently used one is evicted beyond it
+pub const DEFAULT_MAX_MCP_SESSIONS: usize = 1024;
+
+/// Process wide MCP session cache shared by all pipeline processors, so sessions
+/// outlive the request that created them.
+pub fn mcp_session_cache() -> Arc<McpSessionCache> {
+    static MCP_SESSIONS: OnceLock<Arc<McpSessionCache>> = OnceLock::new();
+    MCP_SESSIONS
+        .get_or_init(|| Arc::new(McpSessionCache::default()))
+        .clone()
+}
+
+#[derive(Debug)]
+struct McpSession {
+    session_id: String,
+    last_used: Instant,
+}
+
+/// Counters of the session lifecycle, reported on MCP spans
+#[derive(Debug, Default)]
+pub struct McpSessionStats {
+    pub created: AtomicU64,
+    pub expired: AtomicU64,
+    pub evicted: AtomicU64,
+    pub reinitialized: AtomicU64,
+}
+
+/// MCP session ids by agent id with an idle TTL and an LRU cap
+#[derive(Debug)]
+pub struct McpSessionCache {
+    sessions: Mutex<HashMap<String, McpSession>>,
+    ttl: Duration,
+    max_sessions: usize,
+    pub stats: McpSessionStats,
+}
+
+impl Default for McpSessionCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MCP_SESSION_TTL, DEFAULT_MAX_MCP_SESSIONS)
+    }
+}
+
+impl McpSessionCache {
+    pub fn new(ttl: Duration, max_sessions: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+            max_sessions,
+            stats: McpSessionStats::default(),
+        }
+    }
+
+    /// Session of the agent, None when there is none or it has been idle past the TTL
+    pub fn get(&self, agent_id: &str) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(agent_id)?;
+        if session.last_used.elapsed() >= self.ttl {
+            info!(
+                "MCP session {} for agent {} expired",
+                session.session_id, agent_id
+            );
+            sessions.remove(agent_id);
+            self.stats.expired.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        session.last_used = Instant::now();
+        Some(session.session_id.clone())
+    }
+
+    pub fn insert(&self, agent_id: String, session_id: String) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if !sessions.contains_key(&agent_id) && sessions.len() >= self.max_sessions {
+            let lru_agent = sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(agent_id, _)| agent_id.clone());
+            if let Some(lru_agent) = lru_agent {
+                debug!("evicting MCP session of agent {}", lru_agent);
+                sessions.remove(&lru_agent);
+                self.stats.evicted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        sessions.insert(
+            agent_id,
+            McpSession {
+                session_id,
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    /// Drop the session if it is still the given one, another request may have
+    /// already replaced it
+    pub fn invalidate(&self, agent_id: &str, session_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions
+            .get(agent_id)
+            .is_some_and(|session| session.session_id == session_id)
+        {
+            sessions.remove(agent_id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_expires_after_ttl() {
+        let cache = McpSessionCache::new(Duration::ZERO, 10);
+        cache.insert("agent".to_string(), "session-1".to_string());
+        assert_eq!(cache.get("agent"), None);
+        assert_eq!(cache.stats.expired.load(Ordering::Relaxed), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_session_is_evicted() {
+        let cache = McpSessionCache::new(DEFAULT_MCP_SESSION_TTL, 2);
+        cache.insert("agent-1".to_string(), "session-1".to_string());
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert("agent-2".to_string(), "session-2".to_string());
+        std::thread::sleep(Duration::from_millis(2));
+        // touch agent-1 so agent-2 becomes the least recently used
+        assert_eq!(cache.get("agent-1"), Some("session-1".to_string()));
+        cache.insert("agent-3".to_string(), "session-3".to_string());
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("agent-2"), None);
+        assert_eq!(cache.stats.evicted.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_invalidate_only_removes_matching_session() {
+        let cache = McpSessionCache::default();
+        cache.insert("agent".to_string(), "session-2".to_string());
+        cache.invalidate("agent", "session-1");
+        assert_eq!(cache.get("agent"), Some("session-2".to_string()));
+        cache.invalidate("agent", "session-2");
+        assert_eq!(cache.get("agent"), None);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+use common::configuration::Agent;
+use futures::future::join_all;
+use serde_json::Value;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use super::mcp_connector::McpToolDefinition;
+use super::pipeline_processor::PipelineProcessor;
+
+/// How often the tools of the MCP filters are listed again when no filter sets
+/// `tools_refresh_interval_ms`
+pub const DEFAULT_TOOLS_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MIN_TOOLS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Process wide tool schemas of the MCP filters shared by all pipeline processors, so
+/// tool calls are checked against the schemas listed in the background.
+pub fn mcp_tool_schemas() -> Arc<McpToolSchemas> {
+    static MCP_TOOL_SCHEMAS: OnceLock<Arc<McpToolSchemas>> = OnceLock::new();
+    MCP_TOOL_SCHEMAS
+        .get_or_init(|| Arc::new(McpToolSchemas::default()))
+        .clone()
+}
+
+/// Input schemas of the tools of every listed MCP filter, by filter id and tool name
+#[derive(Debug, Default)]
+pub struct McpToolSchemas {
+    filters: RwLock<HashMap<String, HashMap<String, Value>>>,
+}
+
+/// Change to the tools of a filter that breaks calls built for the previous schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    ToolRemoved {
+        tool: String,
+    },
+    RequiredPropertyAdded {
+        tool: String,
+        property: String,
+    },
+    PropertyRemoved {
+        tool: String,
+        property: String,
+    },
+    PropertyTypeChanged {
+        tool: String,
+        property: String,
+        from: Value,
+        to: Value,
+    },
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::ToolRemoved { tool } => write!(f, "tool '{}' was removed", tool),
+            SchemaChange::RequiredPropertyAdded { tool, property } => {
+                write!(f, "tool '{}' now requires '{}'", tool, property)
+            }
+            SchemaChange::PropertyRemoved { tool, property } => {
+                write!(f, "tool '{}' no longer accepts '{}'", tool, property)
+            }
+            SchemaChange::PropertyTypeChanged {
+                tool,
+                property,
+                from,
+                to,
+            } => write!(
+                f,
+                "'{}' of tool '{}' changed type from {} to {}",
+                property, tool, from, to
+            ),
+        }
+    }
+}
+
+impl McpToolSchemas {
+    /// Caches the tools listed by the filter and returns the incompatible changes to the
+    /// tools cached before, none on the first listing
+    pub fn update(&self, agent_id: &str, tools: Vec<McpToolDefinition>) -> Vec<SchemaChange> {
+        let listed: HashMap<String, Value> = tools
+            .into_iter()
+            .map(|tool| (tool.name, tool.input_schema))
+            .collect();
+        let mut filters = self.filters.write().unwrap();
+        let changes = match filters.get(agent_id) {
+            Some(cached) => {
+                let mut changes = Vec::new();
+                for (tool, schema) in cached {
+                    match listed.get(tool) {
+                        Some(listed_schema) => {
+                            changes.extend(incompatible_changes(tool, schema, listed_schema))
+                        }
+                        None => changes.push(SchemaChange::ToolRemoved { tool: tool.clone() }),
+                    }
+                }
+                changes
+            }
+            None => Vec::new(),
+        };
+        filters.insert(agent_id.to_string(), listed);
+        changes
+    }
+
+    /// Checks the arguments of a call against the cached schema of the tool. Calls to
+    /// filters that haven't been listed yet aren't checked.
+    pub fn validate_arguments(
+        &self,
+        agent_id: &str,
+        tool: &str,
+        arguments: &Value,
+    ) -> Result<(), Vec<String>> {
+        let filters = self.filters.read().unwrap();
+        let Some(tools) = filters.get(agent_id) else {
+            return Ok(());
+        };
+        let Some(schema) = tools.get(tool) else {
+            return Err(vec![format!("tool '{}' isn't listed by the filter", tool)]);
+        };
+        let mut violations = Vec::new();
+        validate(schema, arguments, "arguments", &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// Changes between two schemas of a tool that make arguments valid for the old one
+/// invalid for the new one, looking at the top level properties
+fn incompatible_changes(tool: &str, old: &Value, new: &Value) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    let old_required = required_properties(old);
+    for property in required_properties(new) {
+        if !old_required.contains(&property) {
+            changes.push(SchemaChange::RequiredPropertyAdded {
+                tool: tool.to_string(),
+                property: property.to_string(),
+            });
+        }
+    }
+    let (Some(old_properties), Some(new_properties)) = (
+        old.get("properties").and_then(Value::as_object),
+        new.get("properties").and_then(Value::as_object),
+    ) else {
+        return changes;
+    };
+    for (property, old_schema) in old_properties {
+        match new_properties.get(property) {
+            Some(new_schema) => {
+                let old_type = old_schema.get("type");
+                let new_type = new_schema.get("type");
+                if let (Some(from), Some(to)) = (old_type, new_type) {
+                    if from != to {
+                        changes.push(SchemaChange::PropertyTypeChanged {
+                            tool: tool.to_string(),
+                            property: property.clone(),
+                            from: from.clone(),
+                            to: to.clone(),
+                        });
+                    }
+                }
+            }
+            None => changes.push(SchemaChange::PropertyRemoved {
+                tool: tool.to_string(),
+                property: property.clone(),
+            }),
+        }
+    }
+    changes
+}
+
+fn required_properties(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// Checks the value against the parts of JSON schema tools describe their arguments
+/// with: `type`, `enum`, `properties`, `required`, `additionalProperties` and `items`
+fn validate(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    if !schema.is_object() {
+        return;
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            violations.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                types.join(" or "),
+                json_type(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violations.push(format!(
+                "{}: {} isn't one of {}",
+                path,
+                value,
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            for property in required_properties(schema) {
+                if !object.contains_key(property) {
+                    violations.push(format!(
+                        "{}.{}: required property is missing",
+                        path, property
+                    ));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional_allowed =
+                schema.get("additionalProperties") != Some(&Value::Bool(false));
+            for (key, property_value) in object {
+                let property_path = format!("{}.{}", path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property_schema) => {
+                        validate(property_schema, property_value, &property_path, violations)
+                    }
+                    None if !additional_allowed => {
+                        violations.push(format!("{}: property isn't allowed", property_path))
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate(
+                        item_schema,
+                        item,
+                        &format!("{}[{}]", path, index),
+                        violations,
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        // types this check doesn't know are left to the filter
+        _ => true,
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Lists the tools of every MCP filter at startup and then on an interval, caching their
+/// schemas for the pipeline processors and warning when a filter changes them in a way
+/// that breaks the calls the gateway sends.
+pub struct McpToolSchemaRefresher {
+    filters: Vec<Agent>,
+    schemas: Arc<McpToolSchemas>,
+    pipeline_processor: PipelineProcessor,
+    interval: Duration,
+}
+
+impl McpToolSchemaRefresher {
+    /// None when there are no MCP filters
+    pub fn new(filters: &[Agent]) -> Option<Self> {
+        Self::with_pipeline_processor(filters, PipelineProcessor::default())
+    }
+
+    fn with_pipeline_processor(
+        filters: &[Agent],
+        pipeline_processor: PipelineProcessor,
+    ) -> Option<Self> {
+        let filters: Vec<Agent> = filters
+            .iter()
+            .filter(|filter| filter.agent_type.as_deref().unwrap_or("mcp") == "mcp")
+            .cloned()
+            .collect();
+        if filters.is_empty() {
+            return None;
+        }
+        // filters are listed together, as often as the most demanding one asks
+        let interval = filters
+            .iter()
+            .filter_map(|filter| filter.tools_refresh_interval_ms)
+            .map(Duration::from_millis)
+            .min()
+            .unwrap_or(DEFAULT_TOOLS_REFRESH_INTERVAL);
+        Some(McpToolSchemaRefresher {
+            filters,
+            schemas: pipeline_processor.tool_schemas(),
+            pipeline_processor,
+            interval: interval.max(MIN_TOOLS_REFRESH_INTERVAL),
+        })
+    }
+
+    /// Lists the tools of every filter at the same time. Filters that can't be listed
+    /// keep their cached schemas. Returns the incompatible changes found.
+    pub async fn refresh(&self) -> Vec<(String, SchemaChange)> {
+        let listings = join_all(
+            self.filters
+                .iter()
+                .map(|filter| self.pipeline_processor.list_mcp_tools(filter)),
+        )
+        .await;
+        let mut changes = Vec::new();
+        for (filter, listing) in self.filters.iter().zip(listings) {
+            match listing {
+                Ok(tools) => {
+                    debug!(
+                        "MCP_TOOLS_REFRESH | filter={} tools={}",
+                        filter.id,
+                        tools.len()
+                    );
+                    for change in self.schemas.update(&filter.id, tools) {
+                        warn!(
+                            "MCP filter {} changed its tools incompatibly: {}",
+                            filter.id, change
+                        );
+                        changes.push((filter.id.clone(), change));
+                    }
+                }
+                Err(err) => warn!("MCP_TOOLS_REFRESH | filter={} failed: {}", filter.id, err),
+            }
+        }
+        changes
+    }
+
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        info!(
+            "listing the tools of {} MCP filters every {:?}",
+            self.filters.len(),
+            self.interval
+        );
+        tokio::spawn(async move {
+            let mut ticker = interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.refresh().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jsonrpc::{JSON_RPC_VERSION, MCP_INITIALIZE, MCP_INITIALIZE_NOTIFICATION};
+    use mockito::{Matcher, Server};
+    use serde_json::json;
+
+    fn tool(name: &str, input_schema: Value) -> McpToolDefinition {
+        McpToolDefinition {
+            name: name.to_string(),
+            description: None,
+            input_schema,
+        }
+    }
+
+    fn messages_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "messages": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"role": {"type": "string", "enum": ["system", "user", "assistant", "tool"]}},
+                        "required": ["role"]
+                    }
+                },
+                "top_k": {"type": "integer"}
+            },
+            "required": ["messages"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_validate_arguments() {
+        let schemas = McpToolSchemas::default();
+        assert!(schemas
+            .validate_arguments("query_rewriter", "rewrite_query", &json!({}))
+            .is_ok());
+
+        schemas.update(
+            "query_rewriter",
+            vec![tool("rewrite_query", messages_schema())],
+        );
+        assert!(schemas
+            .validate_arguments(
+                "query_rewriter",
+                "rewrite_query",
+                &json!({"messages": [{"role": "user", "content": "hi"}], "top_k": 3.0})
+            )
+            .is_ok());
+        let mut violations = schemas
+            .validate_arguments(
+                "query_rewriter",
+                "rewrite_query",
+                &json!({"messages": [{"role": "developer"}, {}], "top_k": "3", "chunk": {}}),
+            )
+            .unwrap_err();
+        violations.sort();
+        assert_eq!(
+            violations,
+            vec![
+                "arguments.chunk: property isn't allowed",
+                r#"arguments.messages[0].role: "developer" isn't one of ["system","user","assistant","tool"]"#,
+                "arguments.messages[1].role: required property is missing",
+                "arguments.top_k: expected integer, got string",
+            ]
+        );
+        assert_eq!(
+            schemas
+                .validate_arguments("query_rewriter", "summarize", &json!({}))
+                .unwrap_err(),
+            vec!["tool 'summarize' isn't listed by the filter"]
+        );
+    }
+
+    #[test]
+    fn test_update_reports_incompatible_changes() {
+        let schemas = McpToolSchemas::default();
+        assert!(schemas
+            .update(
+                "query_rewriter",
+                vec![
+                    tool("rewrite_query", messages_schema()),
+                    tool("summarize", json!({"type": "object"})),
+                ],
+            )
+            .is_empty());
+
+        let changed = json!({
+            "type": "object",
+            "properties": {
+                "messages": {"type": "string"},
+                "language": {"type": "string"},
+                "style": {"type": "string"}
+            },
+            "required": ["messages", "language"]
+        });
+        let mut changes = schemas.update("query_rewriter", vec![tool("rewrite_query", changed)]);
+        changes.sort_by_key(|change| change.to_string());
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::PropertyTypeChanged {
+                    tool: "rewrite_query".to_string(),
+                    property: "messages".to_string(),
+                    from: json!("array"),
+                    to: json!("string"),
+                },
+                SchemaChange::PropertyRemoved {
+                    tool: "rewrite_query".to_string(),
+                    property: "top_k".to_string(),
+                },
+                SchemaChange::RequiredPropertyAdded {
+                    tool: "rewrite_query".to_string(),
+                    property: "language".to_string(),
+                },
+                SchemaChange::ToolRemoved {
+                    tool: "summarize".to_string()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let tools_list = json!({
+            "jsonrpc": JSON_RPC_VERSION,
+            "id": "1",
+            "result": {"tools": [{"name": "rewrite_query", "inputSchema": messages_schema()}]}
+        });
+        let mut server = Server::new_async().await;
+        let _initialize = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": MCP_INITIALIZE})))
+            .with_status(200)
+            .with_header("mcp-session-id", "session-1")
+            .create_async()
+            .await;
+        let _initialized = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(
+                json!({"method": MCP_INITIALIZE_NOTIFICATION}),
+            ))
+            .with_status(202)
+            .create_async()
+            .await;
+        let list = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": "tools/list"})))
+            .with_status(200)
+            .with_body(format!("event: message\ndata: {}\n\n", tools_list))
+            .create_async()
+            .await;
+
+        let filters: Vec<Agent> = serde_json::from_value(json!([
+            {"id": "query_rewriter", "url": server.url(), "tools_refresh_interval_ms": 60000},
+            {"id": "docs_retrieval", "url": "http://qdrant:6333", "type": "retrieval"}
+        ]))
+        .unwrap();
+        let refresher = McpToolSchemaRefresher::with_pipeline_processor(
+            &filters,
+            PipelineProcessor::new(server.url()),
+        )
+        .unwrap();
+        assert_eq!(refresher.filters.len(), 1);
+        assert_eq!(refresher.interval, Duration::from_secs(60));
+
+        assert!(refresher.refresh().await.is_empty());
+        list.assert_async().await;
+        assert!(refresher
+            .schemas
+            .validate_arguments(
+                "query_rewriter",
+                "rewrite_query",
+                &json!({"messages": "hi"})
+            )
+            .is_err());
+
+        assert!(McpToolSchemaRefresher::new(&filters[1..]).is_none());
+    }
+}
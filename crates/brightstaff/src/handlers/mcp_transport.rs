@@ -0,0 +1,762 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use common::configuration::{Agent, McpTransport, RestartPolicy, StdioServerConfig};
+use common::consts::ARCH_UPSTREAM_HOST_HEADER;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use hyper::header;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::{oneshot, watch};
+use tracing::{debug, info, warn};
+
+use super::jsonrpc::{
+    mcp_initialize_request, JsonRpcId, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+    JSON_RPC_VERSION, MCP_INITIALIZE_NOTIFICATION,
+};
+
+/// Delay before the first restart of a stdio server or reconnect of an SSE stream,
+/// doubled on every failure in a row
+const DEFAULT_RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+/// How long a server has to answer the initialize request once it's reachable
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Path of the SSE stream when the url of the filter doesn't have one
+const DEFAULT_SSE_PATH: &str = "/sse";
+
+#[derive(Debug, thiserror::Error)]
+pub enum McpTransportError {
+    #[error("no stdio settings for a filter with the stdio transport")]
+    MissingStdioConfig,
+    #[error("server is unavailable: {0}")]
+    Unavailable(String),
+    #[error("server was stopped: {0}")]
+    Stopped(String),
+    #[error("connection closed before the response arrived")]
+    ConnectionClosed,
+    #[error("failed to write the request: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HTTP request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("request rejected with HTTP {status}: {body}")]
+    Rejected { status: u16, body: String },
+    #[error("failed to encode the request: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+impl McpTransportError {
+    /// Failures of a server that is restarting or reconnecting, the call may succeed later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            McpTransportError::Unavailable(_)
+            | McpTransportError::ConnectionClosed
+            | McpTransportError::Io(_) => true,
+            McpTransportError::RequestFailed(err) => err.is_connect() || err.is_timeout(),
+            McpTransportError::Rejected { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// Checks that every filter using the stdio transport says how to start its server
+pub fn validate_mcp_transports(filters: &[Agent]) -> Result<(), String> {
+    for filter in filters {
+        if filter.transport == Some(McpTransport::Stdio) && filter.stdio.is_none() {
+            return Err(format!(
+                "filter '{}': {}",
+                filter.id,
+                McpTransportError::MissingStdioConfig
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Process wide connections to the MCP filters served over stdio or SSE, shared by all
+/// pipeline processors so servers and streams outlive the request that started them.
+pub fn mcp_connections() -> Arc<McpConnections> {
+    static MCP_CONNECTIONS: OnceLock<Arc<McpConnections>> = OnceLock::new();
+    MCP_CONNECTIONS
+        .get_or_init(|| Arc::new(McpConnections::default()))
+        .clone()
+}
+
+/// Connection of every stdio and SSE filter by filter id, opened on first use
+#[derive(Default)]
+pub struct McpConnections {
+    connections: Mutex<HashMap<String, Arc<McpConnection>>>,
+}
+
+impl McpConnections {
+    /// The connection to the filter, its server is started or its stream opened on first
+    /// use. SSE streams go through Envoy at `url`.
+    pub fn connect(
+        &self,
+        agent: &Agent,
+        url: &str,
+        client: &reqwest::Client,
+    ) -> Result<Arc<McpConnection>, McpTransportError> {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(connection) = connections.get(&agent.id) {
+            return Ok(connection.clone());
+        }
+        let connection = Arc::new(McpConnection::new(&agent.id, url, client.clone()));
+        match agent.transport.unwrap_or_default() {
+            McpTransport::Stdio => {
+                let config = agent
+                    .stdio
+                    .clone()
+                    .ok_or(McpTransportError::MissingStdioConfig)?;
+                tokio::spawn(connection.clone().run_stdio(config));
+            }
+            McpTransport::Sse => {
+                tokio::spawn(connection.clone().run_sse(sse_path(&agent.url)));
+            }
+            // served by the pipeline processor, one request at a time
+            McpTransport::StreamableHttp => {
+                return Err(McpTransportError::Unavailable(
+                    "streamable HTTP filters don't keep a connection".to_string(),
+                ))
+            }
+        }
+        connections.insert(agent.id.clone(), connection.clone());
+        Ok(connection)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConnectionState {
+    /// Starting the server or opening the stream, then initializing the session
+    Connecting,
+    /// Answering requests, with the process id or endpoint of the session
+    Ready(String),
+    /// Waiting to restart or reconnect after a failure
+    Down(String),
+    /// Given up on by the restart policy
+    Stopped(String),
+}
+
+/// Where the requests of a connection are written
+enum Outbox {
+    /// stdin of the running server
+    Stdio(ChildStdin),
+    /// Endpoint announced on the SSE stream, requests are posted to it through Envoy
+    Sse(String),
+}
+
+/// Session with an MCP server whose responses arrive on a stream shared by every request,
+/// the stdout of a child process or an SSE stream, and are matched to their request by
+/// id. The stream is reopened, and the server restarted, when it ends.
+pub struct McpConnection {
+    agent_id: String,
+    url: String,
+    client: reqwest::Client,
+    pending: Mutex<HashMap<String, oneshot::Sender<JsonRpcResponse>>>,
+    outbox: tokio::sync::Mutex<Option<Outbox>>,
+    state: watch::Sender<ConnectionState>,
+}
+
+impl McpConnection {
+    fn new(agent_id: &str, url: &str, client: reqwest::Client) -> Self {
+        McpConnection {
+            agent_id: agent_id.to_string(),
+            url: url.to_string(),
+            client,
+            pending: Mutex::new(HashMap::new()),
+            outbox: tokio::sync::Mutex::new(None),
+            state: watch::Sender::new(ConnectionState::Connecting),
+        }
+    }
+
+    /// Sends the request once the session is initialized and waits for its response.
+    /// Returns the session the request was sent on with the response.
+    pub async fn request(
+        &self,
+        request: &JsonRpcRequest,
+    ) -> Result<(String, JsonRpcResponse), McpTransportError> {
+        let mut state = self.state.subscribe();
+        let state = state
+            .wait_for(|state| *state != ConnectionState::Connecting)
+            .await
+            .map_err(|_| McpTransportError::ConnectionClosed)?
+            .clone();
+        let session = match state {
+            ConnectionState::Ready(session) => session,
+            ConnectionState::Down(reason) => return Err(McpTransportError::Unavailable(reason)),
+            ConnectionState::Stopped(reason) => return Err(McpTransportError::Stopped(reason)),
+            ConnectionState::Connecting => unreachable!("waited for the connection"),
+        };
+        let response = self.exchange(request).await?;
+        Ok((session, response))
+    }
+
+    async fn exchange(
+        &self,
+        request: &JsonRpcRequest,
+    ) -> Result<JsonRpcResponse, McpTransportError> {
+        let id = request_key(&request.id);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+        if let Err(err) = self.send(request).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        receiver
+            .await
+            .map_err(|_| McpTransportError::ConnectionClosed)
+    }
+
+    async fn send(&self, message: &impl Serialize) -> Result<(), McpTransportError> {
+        let body = serde_json::to_string(message)?;
+        let mut outbox = self.outbox.lock().await;
+        let endpoint = match outbox.as_mut() {
+            Some(Outbox::Stdio(stdin)) => {
+                stdin.write_all(body.as_bytes()).await?;
+                stdin.write_all(b"\n").await?;
+                stdin.flush().await?;
+                return Ok(());
+            }
+            Some(Outbox::Sse(endpoint)) => endpoint.clone(),
+            None => return Err(McpTransportError::Unavailable("not connected".to_string())),
+        };
+        // posts don't need to be serialized like writes to stdin
+        drop(outbox);
+
+        let response = self
+            .client
+            .post(format!("{}{}", self.url, endpoint))
+            .header(ARCH_UPSTREAM_HOST_HEADER, &self.agent_id)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(McpTransportError::Rejected {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Hands a message of the server to the request waiting for it. Requests and
+    /// notifications of the server aren't supported and are dropped.
+    fn dispatch(&self, message: &str) {
+        let value: Value = match serde_json::from_str(message) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!(
+                    "MCP server {} sent a message that isn't JSON: {}",
+                    self.agent_id, err
+                );
+                return;
+            }
+        };
+        if let Some(method) = value.get("method") {
+            debug!("MCP server {} sent {}, ignoring it", self.agent_id, method);
+            return;
+        }
+        match serde_json::from_value::<JsonRpcResponse>(value) {
+            Ok(response) => {
+                let sender = self
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .remove(&request_key(&response.id));
+                match sender {
+                    Some(sender) => {
+                        let _ = sender.send(response);
+                    }
+                    None => debug!(
+                        "MCP server {} answered an unknown request {:?}",
+                        self.agent_id, response.id
+                    ),
+                }
+            }
+            Err(err) => warn!(
+                "MCP server {} sent an invalid response: {}",
+                self.agent_id, err
+            ),
+        }
+    }
+
+    /// Stops writing to the stream that ended and fails the requests still waiting on it
+    async fn disconnect(&self) {
+        self.outbox.lock().await.take();
+        self.pending.lock().unwrap().clear();
+    }
+
+    async fn initialize(&self) -> Result<(), McpTransportError> {
+        let response =
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, self.exchange(&mcp_initialize_request()))
+                .await
+                .map_err(|_| {
+                    McpTransportError::Unavailable("initialize timed out".to_string())
+                })??;
+        if let Some(error) = response.error {
+            return Err(McpTransportError::Unavailable(format!(
+                "initialize failed: {}",
+                error.message
+            )));
+        }
+        self.send(&JsonRpcNotification {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            method: MCP_INITIALIZE_NOTIFICATION.to_string(),
+            params: None,
+        })
+        .await
+    }
+
+    /// Runs the server of the filter, restarting it as its restart policy says
+    async fn run_stdio(self: Arc<Self>, config: StdioServerConfig) {
+        let policy = config.restart.unwrap_or_default();
+        let mut restarts = 0;
+        loop {
+            self.state.send_replace(ConnectionState::Connecting);
+            let exit = self.run_process(&config).await;
+            self.disconnect().await;
+            if exit.was_ready {
+                restarts = 0;
+            }
+
+            let restart = match policy {
+                RestartPolicy::Always => true,
+                RestartPolicy::OnFailure => !exit.success,
+                RestartPolicy::Never => false,
+            };
+            if !restart || config.max_restarts.is_some_and(|max| restarts >= max) {
+                warn!("MCP server {} stopped: {}", self.agent_id, exit.reason);
+                self.state
+                    .send_replace(ConnectionState::Stopped(exit.reason));
+                return;
+            }
+            let backoff = restart_backoff(config.restart_backoff_ms, restarts);
+            warn!(
+                "MCP server {} {}, restarting it in {}ms",
+                self.agent_id,
+                exit.reason,
+                backoff.as_millis()
+            );
+            self.state.send_replace(ConnectionState::Down(exit.reason));
+            tokio::time::sleep(backoff).await;
+            restarts += 1;
+        }
+    }
+
+    /// Runs one process of the server until it exits
+    async fn run_process(&self, config: &StdioServerConfig) -> ProcessExit {
+        let mut command = Command::new(&config.command);
+        command
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &config.cwd {
+            command.current_dir(cwd);
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                return ProcessExit {
+                    reason: format!("failed to start {}: {}", config.command, err),
+                    success: false,
+                    was_ready: false,
+                }
+            }
+        };
+        let session = format!("pid-{}", child.id().unwrap_or_default());
+        *self.outbox.lock().await = child.stdin.take().map(Outbox::Stdio);
+        if let Some(stderr) = child.stderr.take() {
+            let agent_id = self.agent_id.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    info!("MCP server {}: {}", agent_id, line);
+                }
+            });
+        }
+
+        let stdout = child.stdout.take();
+        let reader = async {
+            if let Some(stdout) = stdout {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if !line.trim().is_empty() {
+                        self.dispatch(&line);
+                    }
+                }
+            }
+        };
+        tokio::pin!(reader);
+
+        let was_ready = tokio::select! {
+            _ = &mut reader => false,
+            initialized = self.initialize() => match initialized {
+                Ok(()) => {
+                    info!("MCP server {} started ({})", self.agent_id, session);
+                    self.state.send_replace(ConnectionState::Ready(session));
+                    reader.await;
+                    true
+                }
+                Err(err) => {
+                    warn!("MCP server {} failed to initialize: {}", self.agent_id, err);
+                    let _ = child.start_kill();
+                    false
+                }
+            },
+        };
+
+        match child.wait().await {
+            Ok(status) => ProcessExit {
+                reason: format!("exited with {}", status),
+                success: status.success(),
+                was_ready,
+            },
+            Err(err) => ProcessExit {
+                reason: format!("failed to wait for the process: {}", err),
+                success: false,
+                was_ready,
+            },
+        }
+    }
+
+    /// Keeps the SSE stream of the filter open, reopening it whenever it ends
+    async fn run_sse(self: Arc<Self>, path: String) {
+        let mut failures = 0;
+        loop {
+            self.state.send_replace(ConnectionState::Connecting);
+            let (reason, was_ready) = self.run_sse_stream(&path).await;
+            self.disconnect().await;
+            if was_ready {
+                failures = 0;
+            }
+            let backoff = restart_backoff(None, failures);
+            warn!(
+                "SSE stream of MCP server {} ended ({}), reopening it in {}ms",
+                self.agent_id,
+                reason,
+                backoff.as_millis()
+            );
+            self.state.send_replace(ConnectionState::Down(reason));
+            tokio::time::sleep(backoff).await;
+            failures += 1;
+        }
+    }
+
+    /// Reads one SSE stream until it ends, returning why it ended and whether the session
+    /// was initialized on it
+    async fn run_sse_stream(&self, path: &str) -> (String, bool) {
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .header(ARCH_UPSTREAM_HOST_HEADER, &self.agent_id)
+            .header(header::ACCEPT, "text/event-stream")
+            .send()
+            .await;
+        let response = match response {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                return (
+                    format!("stream answered HTTP {}", response.status().as_u16()),
+                    false,
+                )
+            }
+            Err(err) => return (format!("failed to open the stream: {}", err), false),
+        };
+
+        let (endpoint_sender, endpoint_receiver) = oneshot::channel();
+        let reader = async {
+            let mut endpoint_sender = Some(endpoint_sender);
+            let mut events = response.bytes_stream().eventsource();
+            while let Some(event) = events.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(err) => return format!("failed to read the stream: {}", err),
+                };
+                match event.event.as_str() {
+                    "endpoint" => {
+                        let endpoint = endpoint_path(path, &event.data);
+                        debug!(
+                            "MCP server {} announced endpoint {}",
+                            self.agent_id, endpoint
+                        );
+                        *self.outbox.lock().await = Some(Outbox::Sse(endpoint.clone()));
+                        if let Some(sender) = endpoint_sender.take() {
+                            let _ = sender.send(endpoint);
+                        }
+                    }
+                    "message" | "" => self.dispatch(&event.data),
+                    other => debug!(
+                        "MCP server {} sent a {} event, ignoring it",
+                        self.agent_id, other
+                    ),
+                }
+            }
+            "stream closed".to_string()
+        };
+        tokio::pin!(reader);
+
+        let handshake = async {
+            let endpoint = endpoint_receiver
+                .await
+                .map_err(|_| McpTransportError::ConnectionClosed)?;
+            self.initialize().await.map(|_| endpoint)
+        };
+        tokio::select! {
+            reason = &mut reader => (reason, false),
+            initialized = handshake => match initialized {
+                Ok(endpoint) => {
+                    info!("MCP server {} connected ({})", self.agent_id, endpoint);
+                    self.state.send_replace(ConnectionState::Ready(endpoint));
+                    (reader.await, true)
+                }
+                Err(err) => (format!("failed to initialize: {}", err), false),
+            },
+        }
+    }
+}
+
+struct ProcessExit {
+    reason: String,
+    success: bool,
+    /// The server initialized its session before it exited
+    was_ready: bool,
+}
+
+fn request_key(id: &JsonRpcId) -> String {
+    match id {
+        JsonRpcId::String(id) => id.clone(),
+        JsonRpcId::Number(id) => id.to_string(),
+    }
+}
+
+fn restart_backoff(base_ms: Option<u64>, failures: u32) -> Duration {
+    let base = base_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_RESTART_BACKOFF);
+    base.saturating_mul(1 << failures.min(16))
+        .min(MAX_RESTART_BACKOFF)
+}
+
+/// Path of the SSE stream, the path of the filter url or `/sse`
+fn sse_path(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .map(|url| url.path().to_string())
+        .filter(|path| path != "/")
+        .unwrap_or_else(|| DEFAULT_SSE_PATH.to_string())
+}
+
+/// Path and query of the endpoint announced on the stream. Servers announce it relative
+/// to the stream or as an absolute url, requests go through Envoy either way.
+fn endpoint_path(stream_path: &str, endpoint: &str) -> String {
+    let joined = reqwest::Url::parse("http://mcp.local")
+        .and_then(|base| base.join(stream_path))
+        .and_then(|stream| stream.join(endpoint));
+    match joined {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        },
+        Err(_) => endpoint.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jsonrpc::TOOL_CALL_METHOD;
+
+    fn tool_call(id: &str) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            id: JsonRpcId::String(id.to_string()),
+            method: TOOL_CALL_METHOD.to_string(),
+            params: None,
+        }
+    }
+
+    #[test]
+    fn test_endpoint_path() {
+        assert_eq!(
+            endpoint_path("/sse", "/messages?session_id=abc"),
+            "/messages?session_id=abc"
+        );
+        assert_eq!(
+            endpoint_path("/mcp/sse", "messages?session_id=abc"),
+            "/mcp/messages?session_id=abc"
+        );
+        assert_eq!(
+            endpoint_path("/sse", "http://fetch:8000/messages/?session_id=abc"),
+            "/messages/?session_id=abc"
+        );
+        assert_eq!(sse_path("http://fetch:8000"), "/sse");
+        assert_eq!(sse_path("http://fetch:8000/mcp/sse"), "/mcp/sse");
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        assert_eq!(restart_backoff(None, 0), Duration::from_millis(500));
+        assert_eq!(restart_backoff(Some(100), 3), Duration::from_millis(800));
+        assert_eq!(restart_backoff(None, 40), MAX_RESTART_BACKOFF);
+    }
+
+    #[test]
+    fn test_validate_mcp_transports() {
+        let filters: Vec<Agent> = serde_json::from_value(serde_json::json!([
+            {"id": "fetch", "transport": "stdio", "stdio": {"command": "uvx", "args": ["mcp-server-fetch"]}},
+            {"id": "query_rewriter", "url": "http://query-rewriter:10501", "transport": "sse"}
+        ]))
+        .unwrap();
+        assert!(validate_mcp_transports(&filters).is_ok());
+
+        let filters: Vec<Agent> = serde_json::from_value(serde_json::json!([
+            {"id": "fetch", "transport": "stdio"}
+        ]))
+        .unwrap();
+        assert_eq!(
+            validate_mcp_transports(&filters).unwrap_err(),
+            "filter 'fetch': no stdio settings for a filter with the stdio transport"
+        );
+    }
+
+    /// Shell script answering every request of the session with its id, like a stdio MCP
+    /// server would
+    fn echo_server(restart: &str, max_restarts: u32) -> Agent {
+        let script = r#"while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":"\([^"]*\)".*/\1/p')
+  case "$line" in
+    *'"method":"exit"'*) exit 3 ;;
+    *'"method":"notifications/'*) ;;
+    *) printf '{"jsonrpc":"2.0","id":"%s","result":{"echo":"%s"}}\n' "$id" "$id" ;;
+  esac
+done"#;
+        serde_json::from_value(serde_json::json!({
+            "id": "echo",
+            "transport": "stdio",
+            "stdio": {
+                "command": "sh",
+                "args": ["-c", script],
+                "restart": restart,
+                "max_restarts": max_restarts,
+                "restart_backoff_ms": 10
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stdio_server_answers_and_is_restarted() {
+        let connections = McpConnections::default();
+        let connection = connections
+            .connect(&echo_server("on_failure", 1), "", &reqwest::Client::new())
+            .unwrap();
+
+        let (session, response) = connection.request(&tool_call("call-1")).await.unwrap();
+        assert!(session.starts_with("pid-"));
+        assert_eq!(
+            response.result.unwrap().get("echo"),
+            Some(&Value::String("call-1".to_string()))
+        );
+
+        // the server exits with an error instead of answering, and is restarted
+        let mut exit = tool_call("exit-1");
+        exit.method = "exit".to_string();
+        assert!(matches!(
+            connection.request(&exit).await,
+            Err(McpTransportError::ConnectionClosed)
+        ));
+        let mut state = connection.state.subscribe();
+        state
+            .wait_for(
+                |state| matches!(state, ConnectionState::Ready(restarted) if *restarted != session),
+            )
+            .await
+            .unwrap();
+        let (restarted_session, _) = connection.request(&tool_call("call-2")).await.unwrap();
+        assert_ne!(restarted_session, session);
+    }
+
+    #[tokio::test]
+    async fn test_stdio_server_that_cant_start_is_stopped() {
+        let mut agent = echo_server("on_failure", 1);
+        agent.stdio.as_mut().unwrap().command = "/nonexistent/mcp-server".to_string();
+        let connection = McpConnections::default()
+            .connect(&agent, "", &reqwest::Client::new())
+            .unwrap();
+
+        let mut state = connection.state.subscribe();
+        state
+            .wait_for(|state| matches!(state, ConnectionState::Stopped(_)))
+            .await
+            .unwrap();
+        let err = connection.request(&tool_call("call-1")).await.unwrap_err();
+        assert!(matches!(err, McpTransportError::Stopped(_)));
+        assert!(!err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_sse_requests_are_posted_and_answered_on_the_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let messages = server
+            .mock("POST", "/messages")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "session_id".to_string(),
+                "abc".to_string(),
+            ))
+            .match_header(ARCH_UPSTREAM_HOST_HEADER, "fetch")
+            .with_status(202)
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        let _stream = server
+            .mock("GET", "/sse")
+            .match_header(ARCH_UPSTREAM_HOST_HEADER, "fetch")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body("event: endpoint\ndata: /messages?session_id=abc\n\n")
+            .create_async()
+            .await;
+
+        let connection = McpConnection::new("fetch", &server.url(), reqwest::Client::new());
+        // mockito can't hold the stream open, it ends before the initialize response
+        let (reason, was_ready) = connection.run_sse_stream("/sse").await;
+        assert_eq!(reason, "stream closed");
+        assert!(!was_ready);
+        assert!(matches!(
+            connection.outbox.lock().await.as_ref(),
+            Some(Outbox::Sse(endpoint)) if endpoint == "/messages?session_id=abc"
+        ));
+
+        // responses read from the stream go to the request with their id
+        connection.state.send_replace(ConnectionState::Ready(
+            "/messages?session_id=abc".to_string(),
+        ));
+        let call = tool_call("call-1");
+        let (answered, _) = tokio::join!(connection.request(&call), async {
+            while connection.pending.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            connection.dispatch(r#"{"jsonrpc":"2.0","method":"notifications/progress"}"#);
+            connection.dispatch(r#"{"jsonrpc":"2.0","id":"call-1","result":{"content":[]}}"#);
+        });
+        let (session, response) = answered.unwrap();
+        assert_eq!(session, "/messages?session_id=abc");
+        assert!(response.result.unwrap().contains_key("content"));
+        messages.assert_async().await;
+
+        connection.disconnect().await;
+        assert!(matches!(
+            connection.request(&tool_call("call-2")).await,
+            Err(McpTransportError::Unavailable(_))
+        ));
+    }
+}
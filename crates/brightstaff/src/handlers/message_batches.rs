@@ -0,0 +1,534 @@
+//! Anthropic message batches
+//!
+//! `/v1/messages/batches` is passed through to Anthropic providers. When the selected provider
+//! has no batch api the batch is emulated: its requests are sent through the messages api of
+//! the llm gateway, which translates them for the provider of each model, and the status and
+//! results of the batch are kept in memory until the batch is deleted.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use bytes::Bytes;
+use chrono::{SecondsFormat, Utc};
+use common::configuration::{LlmProvider, LlmProviderType};
+use common::consts::{ARCH_PROVIDER_HINT_HEADER, MESSAGES_PATH, TRACE_PARENT_HEADER};
+use common::traces::TraceCollector;
+use futures::StreamExt;
+use hermesllm::apis::anthropic::MessagesResponse;
+use hermesllm::apis::anthropic_batch::{
+    MessageBatch, MessageBatchCreateRequest, MessageBatchRequest, MessageBatchRequestCounts,
+    MessageBatchResult, MessageBatchResultType, MessageBatchStatus,
+};
+use hermesllm::apis::openai_batch::BatchApi;
+use hermesllm::MESSAGE_BATCHES_PATH;
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::header;
+use hyper::{Method, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::batch::{batch_passthrough, select_batch_provider};
+use super::response_handler::ResponseHandler;
+
+/// Requests of an emulated batch sent to the provider at the same time
+pub const EMULATED_BATCH_CONCURRENCY: usize = 8;
+
+struct EmulatedBatch {
+    batch: MessageBatch,
+    results: Vec<MessageBatchResult>,
+    canceled: Arc<AtomicBool>,
+}
+
+fn emulated_batches() -> &'static Mutex<HashMap<String, EmulatedBatch>> {
+    static BATCHES: OnceLock<Mutex<HashMap<String, EmulatedBatch>>> = OnceLock::new();
+    BATCHES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+#[derive(Serialize)]
+struct MessageBatchList {
+    data: Vec<MessageBatch>,
+    has_more: bool,
+    first_id: Option<String>,
+    last_id: Option<String>,
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Anthropic error body
+fn error_body(error_type: &str, message: impl Into<String>) -> Value {
+    json!({"type": "error", "error": {"type": error_type, "message": message.into()}})
+}
+
+fn error_response(
+    status: StatusCode,
+    error_type: &str,
+    message: impl Into<String>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    ResponseHandler::create_json_response(status, &error_body(error_type, message))
+}
+
+/// Starts an emulated batch and returns it, the requests run in the background
+pub fn create_emulated_batch(
+    requests: Vec<MessageBatchRequest>,
+    messages_url: String,
+    traceparent: Option<String>,
+) -> MessageBatch {
+    let created_at = Utc::now();
+    let batch = MessageBatch {
+        id: format!("msgbatch_{}", Uuid::new_v4().simple()),
+        obj_type: "message_batch".to_string(),
+        processing_status: MessageBatchStatus::InProgress,
+        request_counts: MessageBatchRequestCounts {
+            processing: requests.len() as u64,
+            ..Default::default()
+        },
+        created_at: created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        expires_at: Some(
+            (created_at + chrono::Duration::hours(24)).to_rfc3339_opts(SecondsFormat::Micros, true),
+        ),
+        ended_at: None,
+        cancel_initiated_at: None,
+        archived_at: None,
+        results_url: None,
+    };
+    let canceled = Arc::new(AtomicBool::new(false));
+    emulated_batches().lock().unwrap().insert(
+        batch.id.clone(),
+        EmulatedBatch {
+            batch: batch.clone(),
+            results: Vec::with_capacity(requests.len()),
+            canceled: canceled.clone(),
+        },
+    );
+    info!(
+        "emulating message batch {} with {} requests",
+        batch.id,
+        requests.len()
+    );
+    tokio::spawn(run_emulated_batch(
+        batch.id.clone(),
+        requests,
+        messages_url,
+        traceparent,
+        canceled,
+    ));
+    batch
+}
+
+async fn run_emulated_batch(
+    batch_id: String,
+    requests: Vec<MessageBatchRequest>,
+    messages_url: String,
+    traceparent: Option<String>,
+    canceled: Arc<AtomicBool>,
+) {
+    let client = reqwest::Client::new();
+    futures::stream::iter(requests)
+        .map(|request| {
+            let client = &client;
+            let messages_url = &messages_url;
+            let traceparent = traceparent.as_deref();
+            let canceled = &canceled;
+            async move {
+                if canceled.load(Ordering::Relaxed) {
+                    return MessageBatchResult {
+                        custom_id: request.custom_id,
+                        result: MessageBatchResultType::Canceled,
+                    };
+                }
+                send_batch_request(client, messages_url, traceparent, request).await
+            }
+        })
+        .buffer_unordered(EMULATED_BATCH_CONCURRENCY)
+        .for_each(|result| {
+            record_result(&batch_id, result);
+            async {}
+        })
+        .await;
+
+    if let Some(emulated) = emulated_batches().lock().unwrap().get_mut(&batch_id) {
+        emulated.batch.processing_status = MessageBatchStatus::Ended;
+        emulated.batch.ended_at = Some(now());
+        emulated.batch.results_url = Some(format!("{}/{}/results", MESSAGE_BATCHES_PATH, batch_id));
+        info!(
+            "emulated message batch {} ended: {:?}",
+            batch_id, emulated.batch.request_counts
+        );
+    }
+}
+
+async fn send_batch_request(
+    client: &reqwest::Client,
+    messages_url: &str,
+    traceparent: Option<&str>,
+    request: MessageBatchRequest,
+) -> MessageBatchResult {
+    let mut params = request.params;
+    params.stream = Some(false);
+    let mut upstream_request = client
+        .post(messages_url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(ARCH_PROVIDER_HINT_HEADER, &params.model)
+        .json(&params);
+    if let Some(traceparent) = traceparent {
+        upstream_request = upstream_request.header(TRACE_PARENT_HEADER, traceparent);
+    }
+
+    let result = match upstream_request.send().await {
+        Ok(response) if response.status().is_success() => {
+            match response.json::<MessagesResponse>().await {
+                Ok(message) => MessageBatchResultType::Succeeded {
+                    message: Box::new(message),
+                },
+                Err(err) => MessageBatchResultType::Errored {
+                    error: error_body("api_error", err.to_string()),
+                },
+            }
+        }
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            MessageBatchResultType::Errored {
+                error: serde_json::from_str::<Value>(&body)
+                    .ok()
+                    .filter(|error| error.get("error").is_some())
+                    .unwrap_or_else(|| {
+                        error_body("api_error", format!("HTTP {}: {}", status, body))
+                    }),
+            }
+        }
+        Err(err) => MessageBatchResultType::Errored {
+            error: error_body("api_error", err.to_string()),
+        },
+    };
+    MessageBatchResult {
+        custom_id: request.custom_id,
+        result,
+    }
+}
+
+fn record_result(batch_id: &str, result: MessageBatchResult) {
+    let mut batches = emulated_batches().lock().unwrap();
+    let Some(emulated) = batches.get_mut(batch_id) else {
+        return;
+    };
+    let counts = &mut emulated.batch.request_counts;
+    counts.processing = counts.processing.saturating_sub(1);
+    match result.result {
+        MessageBatchResultType::Succeeded { .. } => counts.succeeded += 1,
+        MessageBatchResultType::Errored { .. } => counts.errored += 1,
+        MessageBatchResultType::Canceled => counts.canceled += 1,
+        MessageBatchResultType::Expired => counts.expired += 1,
+    }
+    emulated.results.push(result);
+}
+
+pub fn emulated_batch(batch_id: &str) -> Option<MessageBatch> {
+    emulated_batches()
+        .lock()
+        .unwrap()
+        .get(batch_id)
+        .map(|emulated| emulated.batch.clone())
+}
+
+/// Requests of the batch that haven't been sent yet are canceled
+pub fn cancel_emulated_batch(batch_id: &str) -> Option<MessageBatch> {
+    let mut batches = emulated_batches().lock().unwrap();
+    let emulated = batches.get_mut(batch_id)?;
+    if !emulated.batch.is_ended() {
+        emulated.canceled.store(true, Ordering::Relaxed);
+        emulated.batch.processing_status = MessageBatchStatus::Canceling;
+        emulated.batch.cancel_initiated_at = Some(now());
+    }
+    Some(emulated.batch.clone())
+}
+
+/// Results of an ended batch as JSONL
+pub fn emulated_batch_results(batch_id: &str) -> Option<Result<String, MessageBatch>> {
+    let batches = emulated_batches().lock().unwrap();
+    let emulated = batches.get(batch_id)?;
+    if !emulated.batch.is_ended() {
+        return Some(Err(emulated.batch.clone()));
+    }
+    Some(Ok(emulated
+        .results
+        .iter()
+        .filter_map(|result| serde_json::to_string(result).ok())
+        .map(|line| line + "\n")
+        .collect()))
+}
+
+fn list_emulated_batches() -> MessageBatchList {
+    let mut data: Vec<MessageBatch> = emulated_batches()
+        .lock()
+        .unwrap()
+        .values()
+        .map(|emulated| emulated.batch.clone())
+        .collect();
+    // most recent first, as listed by anthropic
+    data.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    MessageBatchList {
+        first_id: data.first().map(|batch| batch.id.clone()),
+        last_id: data.last().map(|batch| batch.id.clone()),
+        data,
+        has_more: false,
+    }
+}
+
+/// Handles `/v1/messages/batches` and its sub resources
+pub async fn message_batches(
+    request: Request<hyper::body::Incoming>,
+    llm_provider_url: String,
+    llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
+    trace_collector: Arc<TraceCollector>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let segments: Vec<String> = path
+        .strip_prefix(MESSAGE_BATCHES_PATH)
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let is_emulated = segments
+        .first()
+        .is_some_and(|batch_id| emulated_batch(batch_id).is_some());
+    let provider_supports_batches = {
+        let providers = llm_providers.read().await;
+        let hint = request
+            .headers()
+            .get(ARCH_PROVIDER_HINT_HEADER)
+            .and_then(|h| h.to_str().ok());
+        select_batch_provider(&providers, hint, BatchApi::MessageBatches)
+            .and_then(|name| providers.iter().find(|p| p.name == name))
+            .is_some_and(|provider| provider.provider_interface == LlmProviderType::Anthropic)
+    };
+    if !is_emulated && provider_supports_batches {
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or(path);
+        return batch_passthrough(
+            request,
+            format!("{}{}", llm_provider_url, path_and_query),
+            llm_providers,
+            trace_collector,
+        )
+        .await;
+    }
+
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let response = match (&method, segments.as_slice()) {
+        (&Method::POST, []) => {
+            let traceparent = request
+                .headers()
+                .get(TRACE_PARENT_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string);
+            let body = request.collect().await?.to_bytes();
+            match serde_json::from_slice::<MessageBatchCreateRequest>(&body) {
+                Ok(create) if !create.requests.is_empty() => {
+                    let batch = create_emulated_batch(
+                        create.requests,
+                        format!("{}{}", llm_provider_url, MESSAGES_PATH),
+                        traceparent,
+                    );
+                    ResponseHandler::create_json_response(StatusCode::OK, &batch)
+                }
+                Ok(_) => error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    "requests: the batch has no requests",
+                ),
+                Err(err) => error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    err.to_string(),
+                ),
+            }
+        }
+        (&Method::GET, []) => {
+            ResponseHandler::create_json_response(StatusCode::OK, &list_emulated_batches())
+        }
+        (&Method::GET, [batch_id]) => match emulated_batch(batch_id) {
+            Some(batch) => ResponseHandler::create_json_response(StatusCode::OK, &batch),
+            None => batch_not_found(batch_id),
+        },
+        (&Method::POST, [batch_id, "cancel"]) => match cancel_emulated_batch(batch_id) {
+            Some(batch) => ResponseHandler::create_json_response(StatusCode::OK, &batch),
+            None => batch_not_found(batch_id),
+        },
+        (&Method::GET, [batch_id, "results"]) => match emulated_batch_results(batch_id) {
+            Some(Ok(results)) => {
+                let mut response = Response::new(ResponseHandler::create_full_body(results));
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_TYPE, "application/x-jsonl".parse().unwrap());
+                response
+            }
+            Some(Err(batch)) => error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("message batch {} has not ended yet", batch.id),
+            ),
+            None => batch_not_found(batch_id),
+        },
+        (&Method::DELETE, [batch_id]) => {
+            let mut batches = emulated_batches().lock().unwrap();
+            match batches
+                .get(*batch_id)
+                .map(|emulated| emulated.batch.is_ended())
+            {
+                Some(true) => {
+                    batches.remove(*batch_id);
+                    ResponseHandler::create_json_response(
+                        StatusCode::OK,
+                        &json!({"id": batch_id, "type": "message_batch_deleted"}),
+                    )
+                }
+                Some(false) => error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    format!("message batch {} must end before it is deleted", batch_id),
+                ),
+                None => batch_not_found(batch_id),
+            }
+        }
+        _ => {
+            warn!("unsupported message batch request: {} {}", method, path);
+            error_response(
+                StatusCode::NOT_FOUND,
+                "not_found_error",
+                format!("{} {} not found", method, path),
+            )
+        }
+    };
+    Ok(response)
+}
+
+fn batch_not_found(batch_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "not_found_error",
+        format!("message batch {} not found", batch_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn batch_request(custom_id: &str, model: &str) -> MessageBatchRequest {
+        serde_json::from_value(json!({
+            "custom_id": custom_id,
+            "params": {
+                "model": model,
+                "max_tokens": 64,
+                "messages": [{"role": "user", "content": "Hello"}]
+            }
+        }))
+        .unwrap()
+    }
+
+    async fn wait_until_ended(batch_id: &str) -> MessageBatch {
+        for _ in 0..100 {
+            let batch = emulated_batch(batch_id).unwrap();
+            if batch.is_ended() {
+                return batch;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("message batch {} did not end", batch_id);
+    }
+
+    #[tokio::test]
+    async fn test_emulated_batch_fans_out_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let succeeded = server
+            .mock("POST", MESSAGES_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "gpt-4o-mini")
+            .with_body(
+                r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"Hi"}],"model":"gpt-4o-mini","stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":5,"output_tokens":1}}"#,
+            )
+            .create_async()
+            .await;
+        let errored = server
+            .mock("POST", MESSAGES_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "unknown-model")
+            .with_status(400)
+            .with_body(
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"unknown model"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let batch = create_emulated_batch(
+            vec![
+                batch_request("a", "gpt-4o-mini"),
+                batch_request("b", "unknown-model"),
+            ],
+            format!("{}{}", server.url(), MESSAGES_PATH),
+            None,
+        );
+        assert_eq!(batch.request_counts.processing, 2);
+        assert_eq!(batch.results_url, None);
+
+        let batch = wait_until_ended(&batch.id).await;
+        succeeded.assert_async().await;
+        errored.assert_async().await;
+        assert_eq!(batch.request_counts.succeeded, 1);
+        assert_eq!(batch.request_counts.errored, 1);
+        assert_eq!(
+            batch.results_url,
+            Some(format!("{}/{}/results", MESSAGE_BATCHES_PATH, batch.id))
+        );
+
+        let results = emulated_batch_results(&batch.id).unwrap().unwrap();
+        let mut results: Vec<MessageBatchResult> = results
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        results.sort_by(|a, b| a.custom_id.cmp(&b.custom_id));
+        assert!(matches!(
+            results[0].result,
+            MessageBatchResultType::Succeeded { .. }
+        ));
+        match &results[1].result {
+            MessageBatchResultType::Errored { error } => {
+                assert_eq!(error["error"]["type"], "invalid_request_error")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_canceled_batch_skips_pending_requests() {
+        let batch = create_emulated_batch(
+            (0..50)
+                .map(|i| batch_request(&format!("req-{}", i), "gpt-4o-mini"))
+                .collect(),
+            // nothing listens here, sent requests error out
+            "http://127.0.0.1:9/v1/messages".to_string(),
+            None,
+        );
+        let canceling = cancel_emulated_batch(&batch.id).unwrap();
+        assert!(canceling.cancel_initiated_at.is_some());
+
+        let batch = wait_until_ended(&batch.id).await;
+        let counts = batch.request_counts;
+        assert_eq!(counts.processing, 0);
+        assert!(counts.canceled > 0);
+        assert_eq!(counts.canceled + counts.errored, 50);
+    }
+}
@@ -1,12 +1,47 @@
+pub mod a2a;
+pub mod access_token;
+pub mod admin;
 pub mod agent_chat_completions;
 pub mod agent_selector;
+pub mod approvals;
+pub mod archive;
+pub mod audio;
+pub mod batch;
+pub mod circuit_breaker;
+pub mod data_residency;
+pub mod dlp;
+pub mod evaluation;
+pub mod fault_injection;
 pub mod function_calling;
 pub mod jsonrpc;
+pub mod jwt;
 pub mod llm;
+pub mod mcp_connector;
+pub mod mcp_session;
+pub mod mcp_tools;
+pub mod mcp_transport;
+pub mod message_batches;
 pub mod models;
+pub mod oidc;
 pub mod pipeline_processor;
+pub mod prompt_templates;
+pub mod provenance;
+pub mod provider_queue;
+pub mod rate_limit_retry;
+pub mod ratelimit_jwt;
+pub mod readiness;
+pub mod realtime;
+pub mod request_callout;
 pub mod response_handler;
+pub mod retrieval;
 pub mod router_chat;
+pub mod semantic_cache;
+pub mod session_memory;
+pub mod sessions;
+pub mod shadow;
+pub mod sse_tap;
+pub mod stream_filter;
+pub mod tools;
 pub mod utils;
 
 #[cfg(test)]
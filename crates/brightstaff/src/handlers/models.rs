@@ -1,19 +1,111 @@
 use bytes::Bytes;
-use common::configuration::{IntoModels, LlmProvider};
-use hermesllm::apis::openai::Models;
+use chrono::{DateTime, SecondsFormat};
+use common::configuration::{IntoModels, LlmProvider, ModelAlias};
+use hermesllm::apis::anthropic::{ModelInfo, ModelsList};
+use hermesllm::apis::openai::{ModelDetail, Models};
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::header::HeaderMap;
 use hyper::{Response, StatusCode};
+use serde::Serialize;
 use serde_json;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::watch;
 
+use crate::discovery::DiscoverySnapshot;
+
+/// Header every Anthropic client sends, its requests are answered in the Anthropic format
+const ANTHROPIC_VERSION_HEADER: &str = "anthropic-version";
+
+/// Lists the configured providers, followed by the discovered models that aren't
+/// configured, as `<provider>/<model>`, and the model aliases whose target is one of
+/// them. Anthropic clients get the list in the format of Anthropic's models api.
 pub async fn list_models(
     llm_providers: Arc<tokio::sync::RwLock<Vec<LlmProvider>>>,
+    discovered_models: Option<watch::Receiver<DiscoverySnapshot>>,
+    model_aliases: Arc<Option<HashMap<String, ModelAlias>>>,
+    headers: &HeaderMap,
 ) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let prov = llm_providers.read().await;
-    let providers = prov.clone();
-    let openai_models: Models = providers.into_models();
+    let providers = llm_providers.read().await.clone();
+    let snapshot = discovered_models.map(|discovered_models| discovered_models.borrow().clone());
+    let openai_models = available_models(providers, snapshot.as_ref(), (*model_aliases).as_ref());
+
+    if headers.contains_key(ANTHROPIC_VERSION_HEADER) {
+        json_response(&anthropic_models(openai_models))
+    } else {
+        json_response(&openai_models)
+    }
+}
+
+fn available_models(
+    providers: Vec<LlmProvider>,
+    snapshot: Option<&DiscoverySnapshot>,
+    model_aliases: Option<&HashMap<String, ModelAlias>>,
+) -> Models {
+    let mut openai_models: Models = providers.into_models();
 
-    match serde_json::to_string(&openai_models) {
+    if let Some(snapshot) = snapshot {
+        for model in &snapshot.models {
+            let id = format!("{}/{}", model.provider, model.id);
+            if openai_models.data.iter().any(|detail| detail.id == id) {
+                continue;
+            }
+            openai_models.data.push(ModelDetail {
+                id,
+                object: Some("model".to_string()),
+                created: 0,
+                owned_by: model.provider.clone(),
+            });
+        }
+    }
+
+    if let Some(model_aliases) = model_aliases {
+        let mut aliases: Vec<_> = model_aliases
+            .iter()
+            .filter(|(alias, config)| {
+                // aliases are only listed when they route somewhere
+                let routable = |id: &str| openai_models.data.iter().any(|detail| detail.id == id);
+                routable(&config.target) && !routable(alias)
+            })
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        aliases.sort();
+        openai_models
+            .data
+            .extend(aliases.into_iter().map(|alias| ModelDetail {
+                id: alias,
+                object: Some("model".to_string()),
+                created: 0,
+                owned_by: "alias".to_string(),
+            }));
+    }
+
+    openai_models
+}
+
+fn anthropic_models(openai_models: Models) -> ModelsList {
+    let data: Vec<ModelInfo> = openai_models
+        .data
+        .into_iter()
+        .map(|detail| ModelInfo {
+            display_name: detail.id.clone(),
+            id: detail.id,
+            obj_type: "model".to_string(),
+            created_at: DateTime::from_timestamp(detail.created as i64, 0)
+                .unwrap_or_default()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+        .collect();
+    ModelsList {
+        first_id: data.first().map(|model| model.id.clone()),
+        last_id: data.last().map(|model| model.id.clone()),
+        data,
+        has_more: false,
+    }
+}
+
+fn json_response(models: &impl Serialize) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match serde_json::to_string(models) {
         Ok(json) => {
             let body = Full::new(Bytes::from(json))
                 .map_err(|never| match never {})
@@ -38,3 +130,98 @@ pub async fn list_models(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::DiscoveredModel;
+
+    fn provider(name: &str) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn alias(target: &str) -> ModelAlias {
+        serde_json::from_value(serde_json::json!({ "target": target })).unwrap()
+    }
+
+    fn ids(models: &Models) -> Vec<&str> {
+        models
+            .data
+            .iter()
+            .map(|detail| detail.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_available_models() {
+        let snapshot = DiscoverySnapshot {
+            models: vec![
+                DiscoveredModel {
+                    id: "gpt-4o".to_string(),
+                    provider: "openai".to_string(),
+                    context_window: None,
+                    capabilities: Vec::new(),
+                },
+                DiscoveredModel {
+                    id: "gpt-4.1".to_string(),
+                    provider: "openai".to_string(),
+                    context_window: None,
+                    capabilities: Vec::new(),
+                },
+            ],
+            events: Vec::new(),
+        };
+        let aliases = HashMap::from([
+            ("fast".to_string(), alias("openai/gpt-4.1")),
+            ("smart".to_string(), alias("anthropic/claude-sonnet-4")),
+            ("gone".to_string(), alias("openai/gpt-3.5-turbo")),
+        ]);
+        let models = available_models(
+            vec![
+                provider("openai/gpt-4o"),
+                provider("anthropic/claude-sonnet-4"),
+            ],
+            Some(&snapshot),
+            Some(&aliases),
+        );
+        assert_eq!(
+            ids(&models),
+            vec![
+                "openai/gpt-4o",
+                "anthropic/claude-sonnet-4",
+                "openai/gpt-4.1",
+                "fast",
+                "smart"
+            ]
+        );
+        assert_eq!(models.data[3].owned_by, "alias");
+    }
+
+    #[test]
+    fn test_anthropic_models() {
+        let models = available_models(
+            vec![
+                provider("anthropic/claude-sonnet-4"),
+                provider("openai/gpt-4o"),
+            ],
+            None,
+            None,
+        );
+        let list = anthropic_models(models);
+        assert_eq!(
+            list.data[0],
+            ModelInfo {
+                id: "anthropic/claude-sonnet-4".to_string(),
+                obj_type: "model".to_string(),
+                display_name: "anthropic/claude-sonnet-4".to_string(),
+                created_at: "1970-01-01T00:00:00Z".to_string(),
+            }
+        );
+        assert_eq!(list.first_id.as_deref(), Some("anthropic/claude-sonnet-4"));
+        assert_eq!(list.last_id.as_deref(), Some("openai/gpt-4o"));
+        assert!(!list.has_more);
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use common::configuration::{Listener, OidcConfig};
+use common::consts::{ARCH_AGENT_LISTENER_NAME_HEADER, ARCH_LISTENER_NAME_HEADER};
+use hermesllm::clients::SupportedAPIsFromClient;
+use hermesllm::providers::error::UpstreamError;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{self, HeaderName, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+use tracing::warn;
+
+use crate::handlers::jwt::{bearer_jwt, claim_value, JwtValidator, ValidatedClaims};
+
+/// Bearer JWT validation of the requests to a listener
+pub struct ListenerOidc {
+    validator: JwtValidator,
+    claim_headers: Vec<(String, HeaderName)>,
+}
+
+impl ListenerOidc {
+    /// Fails on a jwks_url that isn't a url or a claim header that isn't a header name
+    pub fn new(config: &OidcConfig) -> Result<Self, String> {
+        reqwest::Url::parse(&config.validation.jwks_url)
+            .map_err(|err| format!("invalid jwks_url {}: {}", config.validation.jwks_url, err))?;
+        let claim_headers = config
+            .claim_headers
+            .iter()
+            .flatten()
+            .map(|(claim, header)| {
+                let header = HeaderName::try_from(header.as_str())
+                    .map_err(|_| format!("invalid header {} of claim {}", header, claim))?;
+                Ok((claim.clone(), header))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ListenerOidc {
+            validator: JwtValidator::new(&config.validation),
+            claim_headers,
+        })
+    }
+
+    /// Validates the token of the request, copies its claims to the claim headers and keeps
+    /// them in the request extensions. Invalid or missing tokens get a 401 in the error
+    /// format of the api the client called.
+    pub async fn authenticate<B>(
+        &self,
+        request: &mut Request<B>,
+    ) -> Result<(), Response<BoxBody<Bytes, hyper::Error>>> {
+        let claims = match bearer_jwt(request.headers()) {
+            Some(token) => self
+                .validator
+                .validate(token)
+                .await
+                .map_err(|err| err.to_string()),
+            None => Err("missing bearer token".to_string()),
+        };
+        let claims = match claims {
+            Ok(claims) => claims,
+            Err(message) => {
+                warn!(
+                    "OIDC | {} {} | {}",
+                    request.method(),
+                    request.uri(),
+                    message
+                );
+                return Err(unauthorized(request.uri().path(), message));
+            }
+        };
+
+        let headers = request.headers_mut();
+        for (claim, header) in &self.claim_headers {
+            headers.remove(header);
+            if let Some(value) =
+                claim_value(&claims, claim).and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                headers.insert(header.clone(), value);
+            }
+        }
+        request.extensions_mut().insert(ValidatedClaims(claims));
+        Ok(())
+    }
+}
+
+/// OIDC validation of the listeners that have it, by listener name
+#[derive(Default)]
+pub struct ListenerAuth {
+    listeners: HashMap<String, ListenerOidc>,
+}
+
+impl ListenerAuth {
+    /// Fails on the first listener with an invalid oidc config
+    pub fn new(listeners: &[Listener]) -> Result<Self, String> {
+        let listeners = listeners
+            .iter()
+            .filter_map(|listener| {
+                let oidc = listener.oidc.as_ref()?;
+                Some(
+                    ListenerOidc::new(oidc)
+                        .map(|oidc| (listener.name.clone(), oidc))
+                        .map_err(|err| format!("listener '{}' oidc: {}", listener.name, err)),
+                )
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ListenerAuth { listeners })
+    }
+
+    /// Validation of the listener the request came in on, named by the header envoy sets
+    pub fn for_request<B>(&self, request: &Request<B>) -> Option<&ListenerOidc> {
+        if self.listeners.is_empty() {
+            return None;
+        }
+        [ARCH_LISTENER_NAME_HEADER, ARCH_AGENT_LISTENER_NAME_HEADER]
+            .iter()
+            .find_map(|name| request.headers().get(*name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|name| self.listeners.get(name))
+    }
+}
+
+/// 401 in the error format of the client api, agent and other paths get the OpenAI one
+fn unauthorized(path: &str, message: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let client_api = path
+        .strip_prefix("/agents")
+        .and_then(SupportedAPIsFromClient::from_endpoint)
+        .or_else(|| SupportedAPIsFromClient::from_endpoint(path))
+        .unwrap_or(SupportedAPIsFromClient::OpenAIChatCompletions(
+            hermesllm::apis::openai::OpenAIApi::ChatCompletions,
+        ));
+    let error = UpstreamError {
+        status: StatusCode::UNAUTHORIZED.as_u16(),
+        message,
+        error_type: None,
+        code: Some("invalid_token".to_string()),
+        param: None,
+    };
+    let mut response = Response::new(
+        Full::new(Bytes::from(error.to_client_body(&client_api)))
+            .map_err(|never| match never {})
+            .boxed(),
+    );
+    *response.status_mut() = StatusCode::UNAUTHORIZED;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Bearer error=\"invalid_token\""),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jwt::tests::{test_bearer, test_validation_config, test_validator};
+    use serde_json::{json, Value};
+
+    fn listener_oidc() -> ListenerOidc {
+        let mut oidc = ListenerOidc::new(&OidcConfig {
+            validation: test_validation_config(),
+            claim_headers: Some(HashMap::from([(
+                "org_id".to_string(),
+                "x-arch-tenant-id".to_string(),
+            )])),
+        })
+        .unwrap();
+        oidc.validator = test_validator();
+        oidc
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_sets_claim_headers() {
+        let oidc = listener_oidc();
+        let mut request = Request::post("/v1/chat/completions").body(()).unwrap();
+        *request.headers_mut() = test_bearer(json!({
+            "sub": "alice",
+            "org_id": "acme",
+            "iss": "https://auth.example.com/",
+            "aud": "plano",
+            "exp": 4102444800u64
+        }));
+        // clients can't pick their own tenant
+        request
+            .headers_mut()
+            .insert("x-arch-tenant-id", HeaderValue::from_static("other"));
+
+        oidc.authenticate(&mut request).await.unwrap();
+        assert_eq!(request.headers()["x-arch-tenant-id"], "acme");
+        let ValidatedClaims(claims) = request.extensions().get::<ValidatedClaims>().unwrap();
+        assert_eq!(claims["sub"], "alice");
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_is_rejected_in_client_format() {
+        let oidc = listener_oidc();
+        let mut request = Request::post("/v1/messages").body(()).unwrap();
+
+        let response = oidc.authenticate(&mut request).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "authentication_error");
+        assert_eq!(body["error"]["message"], "missing bearer token");
+    }
+
+    #[test]
+    fn test_listener_of_request() {
+        let listener = |name: &str, oidc: Option<OidcConfig>| Listener {
+            name: name.to_string(),
+            router: None,
+            agents: None,
+            port: 0,
+            allowed_apis: None,
+            default_provider: None,
+            passthrough_auth: None,
+            embedding_router: None,
+            oidc,
+        };
+        let auth = ListenerAuth::new(&[
+            listener("public", None),
+            listener(
+                "internal",
+                Some(OidcConfig {
+                    validation: test_validation_config(),
+                    claim_headers: None,
+                }),
+            ),
+        ])
+        .unwrap();
+
+        let request = Request::post("/v1/chat/completions")
+            .header(ARCH_LISTENER_NAME_HEADER, "public")
+            .body(())
+            .unwrap();
+        assert!(auth.for_request(&request).is_none());
+
+        let request = Request::post("/agents/v1/chat/completions")
+            .header(ARCH_AGENT_LISTENER_NAME_HEADER, "internal")
+            .body(())
+            .unwrap();
+        assert!(auth.for_request(&request).is_some());
+    }
+
+    #[test]
+    fn test_invalid_config_is_an_error() {
+        let err = ListenerOidc::new(&OidcConfig {
+            validation: test_validation_config(),
+            claim_headers: Some(HashMap::from([(
+                "org_id".to_string(),
+                "x arch tenant".to_string(),
+            )])),
+        })
+        .err()
+        .unwrap();
+        assert!(err.contains("org_id"));
+
+        let mut validation = test_validation_config();
+        validation.jwks_url = "auth.example.com/jwks.json".to_string();
+        assert!(ListenerOidc::new(&OidcConfig {
+            validation,
+            claim_headers: None,
+        })
+        .is_err());
+    }
+}
@@ -1,23 +1,43 @@
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use common::configuration::{Agent, AgentFilterChain};
-use common::consts::{
-    ARCH_UPSTREAM_HOST_HEADER, BRIGHT_STAFF_SERVICE_NAME, ENVOY_RETRY_HEADER, TRACE_PARENT_HEADER,
+use common::condition::{Condition, ConditionError};
+use common::configuration::{
+    Agent, AgentApi, AgentFilterChain, FilterStep, Listener, McpTransport,
 };
+use common::consts::{ARCH_UPSTREAM_HOST_HEADER, ENVOY_RETRY_HEADER, TRACE_PARENT_HEADER};
 use common::traces::{generate_random_span_id, SpanBuilder, SpanKind};
-use hermesllm::apis::openai::Message;
+use hermesllm::apis::anthropic::AnthropicApi;
+use hermesllm::apis::openai::{Message, Role};
+use hermesllm::apis::{ApiDefinition, OpenAIApi};
+use hermesllm::clients::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
 use hermesllm::{ProviderRequest, ProviderRequestType};
 use hyper::header::HeaderMap;
-use std::time::{Instant, SystemTime};
+use serde_json::Value;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, info, warn};
 
 use crate::tracing::operation_component::{self};
 use crate::tracing::{http, OperationNameBuilder};
 
+use crate::handlers::approvals::await_approval;
+use crate::handlers::circuit_breaker::agent_circuit_breakers;
 use crate::handlers::jsonrpc::{
-    JsonRpcId, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, JSON_RPC_VERSION,
-    MCP_INITIALIZE, MCP_INITIALIZE_NOTIFICATION, TOOL_CALL_METHOD,
+    mcp_initialize_request, JsonRpcId, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+    JSON_RPC_VERSION, MCP_INITIALIZE_NOTIFICATION, TOOL_CALL_METHOD,
 };
+use crate::handlers::mcp_connector::{
+    McpToolDefinition, McpToolsListResult, MCP_TOOLS_LIST_METHOD,
+};
+use crate::handlers::mcp_session::{mcp_session_cache, McpSessionCache};
+use crate::handlers::mcp_tools::{mcp_tool_schemas, McpToolSchemas};
+use crate::handlers::mcp_transport::{mcp_connections, McpConnections, McpTransportError};
+use crate::handlers::response_handler::normalize_response;
+use crate::handlers::retrieval::{
+    add_context, retrieval_query, RetrievalError, RetrievedChunk, Retriever,
+};
+use crate::handlers::stream_filter::StreamChunk;
 use uuid::Uuid;
 
 /// Errors that can occur during pipeline processing
@@ -51,23 +71,172 @@ pub enum PipelineError {
         status: u16,
         body: String,
     },
+    #[error("Agent '{agent}' timed out after {timeout_ms}ms")]
+    Timeout { agent: String, timeout_ms: u64 },
+    #[error("Circuit open for agent '{0}'")]
+    CircuitOpen(String),
+    #[error("Failed to initialize MCP session with agent '{agent}': {reason}")]
+    SessionInitFailed { agent: String, reason: String },
+    #[error("Call to agent '{agent}' rejected (approval {approval_id}): {reason}")]
+    ApprovalRejected {
+        agent: String,
+        approval_id: String,
+        reason: String,
+    },
+    #[error("Call to agent '{agent}' was not approved in time (approval {approval_id})")]
+    ApprovalTimeout { agent: String, approval_id: String },
+    #[error("Retrieval filter '{agent}' failed: {source}")]
+    Retrieval {
+        agent: String,
+        source: RetrievalError,
+    },
+    #[error("Request can't be sent in the API of agent '{agent}': {reason}")]
+    UnsupportedAgentApi { agent: String, reason: String },
+    #[error("Invalid filter condition {condition:?}: {source}")]
+    InvalidCondition {
+        condition: String,
+        source: ConditionError,
+    },
+    #[error(
+        "Arguments of tool '{tool}' don't match the schema listed by agent '{agent}': {reason}"
+    )]
+    InvalidToolArguments {
+        agent: String,
+        tool: String,
+        reason: String,
+    },
+    #[error("MCP transport of agent '{agent}' failed: {source}")]
+    Transport {
+        agent: String,
+        source: McpTransportError,
+    },
+}
+
+impl PipelineError {
+    /// Transient failures that are retried and count towards the circuit breaker
+    fn is_retryable(&self) -> bool {
+        match self {
+            PipelineError::RequestFailed(err) => err.is_connect() || err.is_timeout(),
+            PipelineError::ServerError { .. } | PipelineError::Timeout { .. } => true,
+            PipelineError::Retrieval { source, .. } => source.is_retryable(),
+            PipelineError::Transport { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+/// Parses the conditions of the filter chains of the listeners, failing on the first
+/// invalid one
+pub fn validate_filter_conditions(listeners: &[Listener]) -> Result<(), String> {
+    let steps = listeners
+        .iter()
+        .flat_map(|listener| listener.agents.iter().flatten())
+        .flat_map(|agent| agent.filter_chain.iter().flatten());
+    for step in steps {
+        if let FilterStep::Conditional(conditional) = step {
+            Condition::parse(&conditional.when)
+                .map_err(|err| format!("condition {:?}: {}", conditional.when, err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Value of a variable of filter chain conditions: `last_user_message`, `last_message`,
+/// `message_count`, `header.<name>` or `metadata.<key>`, with nested keys separated by
+/// dots
+fn condition_variable(
+    name: &str,
+    messages: &[Message],
+    request_headers: &HeaderMap,
+    metadata: Option<&HashMap<String, Value>>,
+) -> Option<Value> {
+    if let Some(header) = name.strip_prefix("header.") {
+        let value = request_headers.get(header)?.to_str().ok()?;
+        return Some(Value::String(value.to_string()));
+    }
+    if let Some(path) = name.strip_prefix("metadata.") {
+        let mut keys = path.split('.');
+        let mut value = metadata?.get(keys.next()?)?;
+        for key in keys {
+            value = value.get(key)?;
+        }
+        return Some(value.clone());
+    }
+    match name {
+        "last_user_message" => messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| Value::String(message.content.to_string())),
+        "last_message" => messages
+            .last()
+            .map(|message| Value::String(message.content.to_string())),
+        "message_count" => Some(Value::from(messages.len())),
+        _ => None,
+    }
+}
+
+/// Upstream api of a terminal agent with the path requests are sent to
+fn agent_upstream_api(api: AgentApi) -> (SupportedUpstreamAPIs, &'static str) {
+    match api {
+        AgentApi::ChatCompletions => (
+            SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+            OpenAIApi::ChatCompletions.endpoint(),
+        ),
+        AgentApi::Messages => (
+            SupportedUpstreamAPIs::AnthropicMessagesAPI(AnthropicApi::Messages),
+            AnthropicApi::Messages.endpoint(),
+        ),
+    }
+}
+
+/// Delay before retry `attempt` (0 based), doubled on every attempt
+fn retry_backoff(agent: &Agent, attempt: u32) -> Duration {
+    let base_ms = agent.retry_backoff_ms.unwrap_or(DEFAULT_RETRY_BACKOFF_MS);
+    Duration::from_millis(base_ms.saturating_mul(1 << attempt.min(10)))
+}
+
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 100;
+/// Attempts at initializing an MCP session before the call fails, an agent that is
+/// restarting is often reachable again within a few hundred milliseconds
+const MCP_SESSION_INIT_ATTEMPTS: u32 = 3;
+
+/// Response of an MCP filter to a JSON-RPC request, as its transport delivered it
+enum McpReply {
+    /// Streamable HTTP response, with its SSE formatted body
+    Http(reqwest::StatusCode, bytes::Bytes),
+    /// Response read from the stdout of a stdio server or the stream of an SSE server
+    Message(JsonRpcResponse),
 }
 
 /// Service for processing agent pipelines
 pub struct PipelineProcessor {
     client: reqwest::Client,
     url: String,
-    agent_id_session_map: HashMap<String, String>,
+    /// Serves the embeddings of retrieval filters
+    llm_provider_url: String,
+    agent_id_session_map: Arc<McpSessionCache>,
+    /// Connections to the MCP filters served over stdio or SSE
+    mcp_connections: Arc<McpConnections>,
+    /// Tool schemas of the MCP filters, tool call arguments are checked against them
+    tool_schemas: Arc<McpToolSchemas>,
+    /// Chunks added to the conversation by retrieval filters, cited in the response
+    citations: Vec<RetrievedChunk>,
 }
 
 const ENVOY_API_ROUTER_ADDRESS: &str = "http://localhost:11000";
+const LLM_PROVIDER_ADDRESS: &str = "http://localhost:12001";
 
 impl Default for PipelineProcessor {
     fn default() -> Self {
         Self {
             client: reqwest::Client::new(),
             url: ENVOY_API_ROUTER_ADDRESS.to_string(),
-            agent_id_session_map: HashMap::new(),
+            llm_provider_url: LLM_PROVIDER_ADDRESS.to_string(),
+            agent_id_session_map: mcp_session_cache(),
+            mcp_connections: mcp_connections(),
+            tool_schemas: mcp_tool_schemas(),
+            citations: Vec::new(),
         }
     }
 }
@@ -77,10 +246,29 @@ impl PipelineProcessor {
         Self {
             client: reqwest::Client::new(),
             url,
-            agent_id_session_map: HashMap::new(),
+            llm_provider_url: LLM_PROVIDER_ADDRESS.to_string(),
+            agent_id_session_map: Arc::new(McpSessionCache::default()),
+            mcp_connections: Arc::new(McpConnections::default()),
+            tool_schemas: Arc::new(McpToolSchemas::default()),
+            citations: Vec::new(),
         }
     }
 
+    pub fn with_llm_provider_url(mut self, llm_provider_url: String) -> Self {
+        self.llm_provider_url = llm_provider_url;
+        self
+    }
+
+    pub fn tool_schemas(&self) -> Arc<McpToolSchemas> {
+        self.tool_schemas.clone()
+    }
+
+    /// Chunks retrieved since the last call, in the order they were added to the
+    /// conversation
+    pub fn take_citations(&mut self) -> Vec<RetrievedChunk> {
+        std::mem::take(&mut self.citations)
+    }
+
     /// Record a span for filter execution
     #[allow(clippy::too_many_arguments)]
     fn record_filter_span(
@@ -189,7 +377,8 @@ impl PipelineProcessor {
         collector.record_span(operation_component::AGENT_FILTER, span);
     }
 
-    /// Process the filter chain of agents (all except the terminal agent)
+    /// Process the filter chain of agents (all except the terminal agent). Conditions of
+    /// conditional steps see the conversation as the filters before them left it.
     #[allow(clippy::too_many_arguments)]
     pub async fn process_filter_chain(
         &mut self,
@@ -197,6 +386,7 @@ impl PipelineProcessor {
         agent_filter_chain: &AgentFilterChain,
         agent_map: &HashMap<String, Agent>,
         request_headers: &HeaderMap,
+        metadata: Option<&HashMap<String, Value>>,
         trace_collector: Option<&std::sync::Arc<common::traces::TraceCollector>>,
         trace_id: String,
         parent_span_id: String,
@@ -209,33 +399,58 @@ impl PipelineProcessor {
             _ => return Ok(chat_history_updated),
         };
 
-        for agent_name in filter_chain {
-            debug!("Processing filter agent: {}", agent_name);
+        for step in filter_chain {
+            let filters: &[String] = match step {
+                FilterStep::Filter(agent_name) => std::slice::from_ref(agent_name),
+                FilterStep::Conditional(conditional) => {
+                    let condition = Condition::parse(&conditional.when).map_err(|source| {
+                        PipelineError::InvalidCondition {
+                            condition: conditional.when.clone(),
+                            source,
+                        }
+                    })?;
+                    let holds = condition.evaluate(&|name| {
+                        condition_variable(name, &chat_history_updated, request_headers, metadata)
+                    });
+                    debug!(
+                        "filter condition {:?} {}",
+                        conditional.when,
+                        if holds { "holds" } else { "doesn't hold" }
+                    );
+                    if holds {
+                        &conditional.then
+                    } else {
+                        conditional.otherwise.as_deref().unwrap_or_default()
+                    }
+                }
+            };
+
+            for agent_name in filters {
+                debug!("Processing filter agent: {}", agent_name);
 
-            let agent = agent_map
-                .get(agent_name)
-                .ok_or_else(|| PipelineError::AgentNotFound(agent_name.clone()))?;
+                let agent = agent_map
+                    .get(agent_name)
+                    .ok_or_else(|| PipelineError::AgentNotFound(agent_name.clone()))?;
 
-            let tool_name = agent.tool.as_deref().unwrap_or(&agent.id);
+                let tool_name = agent.tool.as_deref().unwrap_or(&agent.id);
 
-            info!(
-                "executing filter: {}/{}, url: {}, type: {}, conversation length: {}",
-                agent_name,
-                tool_name,
-                agent.url,
-                agent.agent_type.as_deref().unwrap_or("mcp"),
-                chat_history.len()
-            );
+                info!(
+                    "executing filter: {}/{}, url: {}, type: {}, conversation length: {}",
+                    agent_name,
+                    tool_name,
+                    agent.url,
+                    agent.agent_type.as_deref().unwrap_or("mcp"),
+                    chat_history.len()
+                );
 
-            let start_time = SystemTime::now();
-            let start_instant = Instant::now();
+                let start_time = SystemTime::now();
+                let start_instant = Instant::now();
 
-            // Generate filter span ID before execution so MCP spans can use it as parent
-            let filter_span_id = generate_random_span_id();
+                // Generate filter span ID before execution so MCP spans can use it as parent
+                let filter_span_id = generate_random_span_id();
 
-            if agent.agent_type.as_deref().unwrap_or("mcp") == "mcp" {
-                chat_history_updated = self
-                    .execute_mcp_filter(
+                match self
+                    .execute_filter_with_policy(
                         &chat_history_updated,
                         agent,
                         request_headers,
@@ -243,47 +458,141 @@ impl PipelineProcessor {
                         trace_id.clone(),
                         filter_span_id.clone(),
                     )
-                    .await?;
-            } else {
-                chat_history_updated = self
-                    .execute_http_filter(
-                        &chat_history_updated,
+                    .await?
+                {
+                    Some(updated) => chat_history_updated = updated,
+                    None => {
+                        warn!("circuit open for filter '{}', skipping it", agent_name);
+                        continue;
+                    }
+                }
+
+                let end_time = SystemTime::now();
+                let elapsed = start_instant.elapsed();
+
+                info!(
+                    "Filter '{}' completed in {:.2}ms, updated conversation length: {}",
+                    agent_name,
+                    elapsed.as_secs_f64() * 1000.0,
+                    chat_history_updated.len()
+                );
+
+                // Record span for this filter execution
+                if let Some(collector) = trace_collector {
+                    self.record_filter_span(
+                        collector,
+                        agent_name,
+                        tool_name,
+                        start_time,
+                        end_time,
+                        elapsed,
+                        trace_id.clone(),
+                        parent_span_id.clone(),
+                        filter_span_id,
+                    );
+                }
+            }
+        }
+
+        Ok(chat_history_updated)
+    }
+
+    /// Execute a filter agent honoring its timeout, retry and circuit breaker settings.
+    /// Returns None when the circuit is open and the agent is configured to be skipped.
+    async fn execute_filter_with_policy(
+        &mut self,
+        messages: &[Message],
+        agent: &Agent,
+        request_headers: &HeaderMap,
+        trace_collector: Option<&std::sync::Arc<common::traces::TraceCollector>>,
+        trace_id: String,
+        filter_span_id: String,
+    ) -> Result<Option<Vec<Message>>, PipelineError> {
+        if let Some(circuit_breaker) = agent.circuit_breaker.as_ref() {
+            if !agent_circuit_breakers().allow(&agent.id, circuit_breaker) {
+                if circuit_breaker.skip_when_open.unwrap_or(false) {
+                    return Ok(None);
+                }
+                return Err(PipelineError::CircuitOpen(agent.id.clone()));
+            }
+        }
+
+        await_approval(
+            &self.client,
+            agent,
+            serde_json::json!({ "messages": messages }),
+        )
+        .await?;
+
+        let max_retries = agent.max_retries.unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let agent_type = agent.agent_type.as_deref().unwrap_or("mcp");
+            let call = async {
+                if agent_type == "retrieval" {
+                    self.execute_retrieval_filter(messages, agent, &trace_id, &filter_span_id)
+                        .await
+                } else if agent_type == "mcp" {
+                    self.execute_mcp_filter(
+                        messages,
                         agent,
                         request_headers,
                         trace_collector,
                         trace_id.clone(),
                         filter_span_id.clone(),
                     )
-                    .await?;
-            }
-
-            let end_time = SystemTime::now();
-            let elapsed = start_instant.elapsed();
-
-            info!(
-                "Filter '{}' completed in {:.2}ms, updated conversation length: {}",
-                agent_name,
-                elapsed.as_secs_f64() * 1000.0,
-                chat_history_updated.len()
-            );
+                    .await
+                } else {
+                    self.execute_http_filter(
+                        messages,
+                        agent,
+                        request_headers,
+                        trace_collector,
+                        trace_id.clone(),
+                        filter_span_id.clone(),
+                    )
+                    .await
+                }
+            };
+            let result = match agent.timeout_ms {
+                Some(timeout_ms) => tokio::time::timeout(Duration::from_millis(timeout_ms), call)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(PipelineError::Timeout {
+                            agent: agent.id.clone(),
+                            timeout_ms,
+                        })
+                    }),
+                None => call.await,
+            };
 
-            // Record span for this filter execution
-            if let Some(collector) = trace_collector {
-                self.record_filter_span(
-                    collector,
-                    agent_name,
-                    tool_name,
-                    start_time,
-                    end_time,
-                    elapsed,
-                    trace_id.clone(),
-                    parent_span_id.clone(),
-                    filter_span_id,
-                );
+            match result {
+                Ok(messages) => {
+                    agent_circuit_breakers().record_success(&agent.id);
+                    return Ok(Some(messages));
+                }
+                Err(err) if err.is_retryable() => {
+                    if let Some(circuit_breaker) = agent.circuit_breaker.as_ref() {
+                        agent_circuit_breakers().record_failure(&agent.id, circuit_breaker);
+                    }
+                    if attempt >= max_retries {
+                        return Err(err);
+                    }
+                    let backoff = retry_backoff(agent, attempt);
+                    warn!(
+                        "filter agent {} failed (attempt {}/{}): {}, retrying in {}ms",
+                        agent.id,
+                        attempt + 1,
+                        max_retries + 1,
+                        err,
+                        backoff.as_millis()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
             }
         }
-
-        Ok(chat_history_updated)
     }
 
     /// Build common MCP headers for requests
@@ -329,7 +638,12 @@ impl PipelineProcessor {
         if let Some(sid) = session_id {
             headers.insert(
                 "mcp-session-id",
-                hyper::header::HeaderValue::from_str(sid).unwrap(),
+                hyper::header::HeaderValue::from_str(sid).map_err(|_| {
+                    PipelineError::SessionInitFailed {
+                        agent: agent_id.to_string(),
+                        reason: format!("invalid mcp-session-id {:?}", sid),
+                    }
+                })?,
             );
         }
 
@@ -415,7 +729,15 @@ impl PipelineProcessor {
     ) -> Result<JsonRpcRequest, PipelineError> {
         let mut arguments = HashMap::new();
         arguments.insert("messages".to_string(), serde_json::to_value(messages)?);
+        self.build_tool_call_request_with_arguments(tool_name, arguments)
+    }
 
+    /// Build a tools/call JSON-RPC request with the given tool arguments
+    fn build_tool_call_request_with_arguments(
+        &self,
+        tool_name: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> Result<JsonRpcRequest, PipelineError> {
         let mut params = HashMap::new();
         params.insert("name".to_string(), serde_json::to_value(tool_name)?);
         params.insert("arguments".to_string(), serde_json::to_value(arguments)?);
@@ -428,6 +750,30 @@ impl PipelineProcessor {
         })
     }
 
+    /// Check the arguments of a tools/call request against the schema the agent listed
+    /// for the tool, so a mismatch fails here instead of as an opaque `isError` result
+    fn validate_tool_call(
+        &self,
+        json_rpc_request: &JsonRpcRequest,
+        agent_id: &str,
+        tool_name: &str,
+    ) -> Result<(), PipelineError> {
+        let Some(arguments) = json_rpc_request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("arguments"))
+        else {
+            return Ok(());
+        };
+        self.tool_schemas
+            .validate_arguments(agent_id, tool_name, arguments)
+            .map_err(|violations| PipelineError::InvalidToolArguments {
+                agent: agent_id.to_string(),
+                tool: tool_name.to_string(),
+                reason: violations.join("; "),
+            })
+    }
+
     /// Send request to a specific agent and return the response content
     async fn execute_mcp_filter(
         &mut self,
@@ -438,48 +784,28 @@ impl PipelineProcessor {
         trace_id: String,
         filter_span_id: String,
     ) -> Result<Vec<Message>, PipelineError> {
-        // Get or create MCP session
-        let mcp_session_id = if let Some(session_id) = self.agent_id_session_map.get(&agent.id) {
-            session_id.clone()
-        } else {
-            let session_id = self
-                .get_new_session_id(&agent.id, trace_id.clone(), filter_span_id.clone())
-                .await;
-            self.agent_id_session_map
-                .insert(agent.id.clone(), session_id.clone());
-            session_id
-        };
-
-        info!(
-            "Using MCP session ID {} for agent {}",
-            mcp_session_id, agent.id
-        );
-
         // Build JSON-RPC request
         let tool_name = agent.tool.as_deref().unwrap_or(&agent.id);
         let json_rpc_request = self.build_tool_call_request(tool_name, messages)?;
+        self.validate_tool_call(&json_rpc_request, &agent.id, tool_name)?;
 
         // Generate span ID for this MCP tool call (child of filter span)
         let mcp_span_id = generate_random_span_id();
 
-        // Build headers
-        let agent_headers = self.build_mcp_headers(
-            request_headers,
-            &agent.id,
-            Some(&mcp_session_id),
-            trace_id.clone(),
-            mcp_span_id.clone(),
-        )?;
-
         // Send request with tracing
         let start_time = SystemTime::now();
         let start_instant = Instant::now();
 
-        let response = self
-            .send_mcp_request(&json_rpc_request, agent_headers, &agent.id)
+        let (mcp_session_id, reply) = self
+            .send_mcp_tool_call(
+                &json_rpc_request,
+                agent,
+                request_headers,
+                trace_id.clone(),
+                filter_span_id.clone(),
+                mcp_span_id.clone(),
+            )
             .await?;
-        let http_status = response.status();
-        let response_bytes = response.bytes().await?;
 
         let end_time = SystemTime::now();
         let elapsed = start_instant.elapsed();
@@ -490,7 +816,33 @@ impl PipelineProcessor {
             attrs.insert("mcp.method", "tools/call".to_string());
             attrs.insert("mcp.tool_name", tool_name.to_string());
             attrs.insert("mcp.session_id", mcp_session_id.clone());
-            attrs.insert("http.status_code", http_status.as_u16().to_string());
+            attrs.insert(
+                "mcp.transport",
+                agent.transport.unwrap_or_default().to_string(),
+            );
+            let session_stats = &self.agent_id_session_map.stats;
+            attrs.insert(
+                "mcp.sessions.created",
+                session_stats.created.load(Ordering::Relaxed).to_string(),
+            );
+            attrs.insert(
+                "mcp.sessions.expired",
+                session_stats.expired.load(Ordering::Relaxed).to_string(),
+            );
+            attrs.insert(
+                "mcp.sessions.evicted",
+                session_stats.evicted.load(Ordering::Relaxed).to_string(),
+            );
+            attrs.insert(
+                "mcp.sessions.reinitialized",
+                session_stats
+                    .reinitialized
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            );
+            if let McpReply::Http(http_status, _) = &reply {
+                attrs.insert("http.status_code", http_status.as_u16().to_string());
+            }
 
             self.record_agent_filter_span(
                 collector,
@@ -506,58 +858,7 @@ impl PipelineProcessor {
             );
         }
 
-        // Handle HTTP errors
-        if !http_status.is_success() {
-            let error_body = String::from_utf8_lossy(&response_bytes).to_string();
-            return Err(if http_status.is_client_error() {
-                PipelineError::ClientError {
-                    agent: agent.id.clone(),
-                    status: http_status.as_u16(),
-                    body: error_body,
-                }
-            } else {
-                PipelineError::ServerError {
-                    agent: agent.id.clone(),
-                    status: http_status.as_u16(),
-                    body: error_body,
-                }
-            });
-        }
-
-        info!(
-            "Response from agent {}: {}",
-            agent.id,
-            String::from_utf8_lossy(&response_bytes)
-        );
-
-        // Parse SSE response
-        let data_chunk = self.parse_sse_response(&response_bytes, &agent.id)?;
-        let response: JsonRpcResponse = serde_json::from_str(&data_chunk)?;
-        let response_result = response
-            .result
-            .ok_or_else(|| PipelineError::NoResultInResponse(agent.id.clone()))?;
-
-        // Check if error field is set in response result
-        if response_result
-            .get("isError")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
-            let error_message = response_result
-                .get("content")
-                .and_then(|v| v.as_array())
-                .and_then(|arr| arr.first())
-                .and_then(|v| v.get("text"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("unknown_error")
-                .to_string();
-
-            return Err(PipelineError::ClientError {
-                agent: agent.id.clone(),
-                status: hyper::StatusCode::BAD_REQUEST.as_u16(),
-                body: error_message,
-            });
-        }
+        let response_result = self.parse_tool_call_result(reply, &agent.id)?;
 
         // Extract structured content and parse messages
         let response_json = response_result
@@ -576,104 +877,490 @@ impl PipelineProcessor {
         Ok(messages)
     }
 
-    /// Build an initialize JSON-RPC request
-    fn build_initialize_request(&self) -> JsonRpcRequest {
-        JsonRpcRequest {
-            jsonrpc: JSON_RPC_VERSION.to_string(),
-            id: JsonRpcId::String(Uuid::new_v4().to_string()),
-            method: MCP_INITIALIZE.to_string(),
-            params: Some({
-                let mut params = HashMap::new();
-                params.insert(
-                    "protocolVersion".to_string(),
-                    serde_json::Value::String("2024-11-05".to_string()),
-                );
-                params.insert("capabilities".to_string(), serde_json::json!({}));
-                params.insert(
-                    "clientInfo".to_string(),
-                    serde_json::json!({
-                        "name": BRIGHT_STAFF_SERVICE_NAME,
-                        "version": "1.0.0"
-                    }),
-                );
-                params
-            }),
-        }
-    }
-
-    /// Send initialized notification after session creation
-    async fn send_initialized_notification(
+    /// Get the MCP session of the agent, initializing a new one on first use
+    async fn get_or_create_session(
         &self,
         agent_id: &str,
-        session_id: &str,
         trace_id: String,
         parent_span_id: String,
-    ) -> Result<(), PipelineError> {
-        let initialized_notification = JsonRpcNotification {
-            jsonrpc: JSON_RPC_VERSION.to_string(),
-            method: MCP_INITIALIZE_NOTIFICATION.to_string(),
-            params: None,
+    ) -> Result<String, PipelineError> {
+        let session_id = if let Some(session_id) = self.agent_id_session_map.get(agent_id) {
+            session_id
+        } else {
+            let session_id = self
+                .get_new_session_id(agent_id, trace_id, parent_span_id)
+                .await?;
+            self.agent_id_session_map
+                .insert(agent_id.to_string(), session_id.clone());
+            self.agent_id_session_map
+                .stats
+                .created
+                .fetch_add(1, Ordering::Relaxed);
+            session_id
         };
 
-        let notification_body = serde_json::to_string(&initialized_notification)?;
-        debug!("Sending initialized notification for agent {}", agent_id);
-
-        let headers = self.build_mcp_headers(
-            &HeaderMap::new(),
-            agent_id,
-            Some(session_id),
-            trace_id.clone(),
-            parent_span_id.clone(),
-        )?;
-
-        let response = self
-            .client
-            .post(format!("{}/mcp", self.url))
-            .headers(headers)
-            .body(notification_body)
-            .send()
-            .await?;
-
-        info!(
-            "Initialized notification response status: {}",
-            response.status()
-        );
-
-        Ok(())
+        info!("Using MCP session ID {} for agent {}", session_id, agent_id);
+        Ok(session_id)
     }
 
-    async fn get_new_session_id(
+    /// Send a request over the agent's MCP session. Over streamable HTTP, servers answer
+    /// 404 for sessions they no longer know (expired or restarted), the session is then
+    /// re-initialized once and the call repeated.
+    async fn send_mcp_tool_call(
         &self,
-        agent_id: &str,
+        json_rpc_request: &JsonRpcRequest,
+        agent: &Agent,
+        request_headers: &HeaderMap,
         trace_id: String,
         parent_span_id: String,
-    ) -> String {
-        info!("Initializing MCP session for agent {}", agent_id);
+        call_span_id: String,
+    ) -> Result<(String, McpReply), PipelineError> {
+        if agent.transport.unwrap_or_default() != McpTransport::StreamableHttp {
+            return self.send_mcp_message(json_rpc_request, agent).await;
+        }
 
-        let initialize_request = self.build_initialize_request();
-        let headers = self
-            .build_mcp_headers(
-                &HeaderMap::new(),
+        let agent_id = agent.id.as_str();
+        let mut session_id = self
+            .get_or_create_session(agent_id, trace_id.clone(), parent_span_id.clone())
+            .await?;
+        let mut reinitialized = false;
+        loop {
+            let headers = self.build_mcp_headers(
+                request_headers,
                 agent_id,
-                None,
+                Some(&session_id),
                 trace_id.clone(),
-                parent_span_id.clone(),
-            )
-            .expect("Failed to build headers for initialization");
-
-        let response = self
-            .send_mcp_request(&initialize_request, headers, agent_id)
-            .await
-            .expect("Failed to initialize MCP session");
+                call_span_id.clone(),
+            )?;
+            let response = self
+                .send_mcp_request(json_rpc_request, headers, agent_id)
+                .await?;
+            let http_status = response.status();
+
+            if http_status == reqwest::StatusCode::NOT_FOUND && !reinitialized {
+                warn!(
+                    "MCP session {} not found on agent {}, re-initializing",
+                    session_id, agent_id
+                );
+                self.agent_id_session_map.invalidate(agent_id, &session_id);
+                self.agent_id_session_map
+                    .stats
+                    .reinitialized
+                    .fetch_add(1, Ordering::Relaxed);
+                session_id = self
+                    .get_or_create_session(agent_id, trace_id.clone(), parent_span_id.clone())
+                    .await?;
+                reinitialized = true;
+                continue;
+            }
 
-        info!("Initialize response status: {}", response.status());
+            let response_bytes = response.bytes().await?;
+            return Ok((session_id, McpReply::Http(http_status, response_bytes)));
+        }
+    }
 
-        let session_id = response
-            .headers()
+    /// Send a request to a filter served over stdio or SSE, its response is read from the
+    /// stream of the filter's connection, which is opened on first use
+    async fn send_mcp_message(
+        &self,
+        json_rpc_request: &JsonRpcRequest,
+        agent: &Agent,
+    ) -> Result<(String, McpReply), PipelineError> {
+        let transport_error = |source| PipelineError::Transport {
+            agent: agent.id.clone(),
+            source,
+        };
+        let connection = self
+            .mcp_connections
+            .connect(agent, &self.url, &self.client)
+            .map_err(transport_error)?;
+        let (session, response) = connection
+            .request(json_rpc_request)
+            .await
+            .map_err(transport_error)?;
+        Ok((session, McpReply::Message(response)))
+    }
+
+    /// Tools of an MCP filter as listed by `tools/list`, following pagination cursors
+    pub async fn list_mcp_tools(
+        &self,
+        agent: &Agent,
+    ) -> Result<Vec<McpToolDefinition>, PipelineError> {
+        let trace_id = Uuid::new_v4().simple().to_string();
+        let span_id = generate_random_span_id();
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let request = JsonRpcRequest {
+                jsonrpc: JSON_RPC_VERSION.to_string(),
+                id: JsonRpcId::String(Uuid::new_v4().to_string()),
+                method: MCP_TOOLS_LIST_METHOD.to_string(),
+                params: cursor
+                    .take()
+                    .map(|cursor| HashMap::from([("cursor".to_string(), Value::String(cursor))])),
+            };
+            let (_, reply) = self
+                .send_mcp_tool_call(
+                    &request,
+                    agent,
+                    &HeaderMap::new(),
+                    trace_id.clone(),
+                    span_id.clone(),
+                    span_id.clone(),
+                )
+                .await?;
+            let result = self.parse_tool_call_result(reply, &agent.id)?;
+            let page: McpToolsListResult =
+                serde_json::from_value(Value::Object(result.into_iter().collect()))?;
+            tools.extend(page.tools);
+            match page.next_cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => break,
+            }
+        }
+        Ok(tools)
+    }
+
+    /// Validate a tools/call response and return its JSON-RPC result
+    fn parse_tool_call_result(
+        &self,
+        reply: McpReply,
+        agent_id: &str,
+    ) -> Result<HashMap<String, serde_json::Value>, PipelineError> {
+        let response = match reply {
+            McpReply::Http(http_status, response_bytes) => {
+                self.parse_http_reply(http_status, &response_bytes, agent_id)?
+            }
+            McpReply::Message(response) => response,
+        };
+        let response_result = response
+            .result
+            .ok_or_else(|| PipelineError::NoResultInResponse(agent_id.to_string()))?;
+
+        // Check if error field is set in response result
+        if response_result
+            .get("isError")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            let error_message = response_result
+                .get("content")
+                .and_then(|v| v.as_array())
+                .and_then(|arr| arr.first())
+                .and_then(|v| v.get("text"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown_error")
+                .to_string();
+
+            return Err(PipelineError::ClientError {
+                agent: agent_id.to_string(),
+                status: hyper::StatusCode::BAD_REQUEST.as_u16(),
+                body: error_message,
+            });
+        }
+
+        Ok(response_result)
+    }
+
+    /// Check the HTTP status of a streamable HTTP response and parse its JSON-RPC response
+    fn parse_http_reply(
+        &self,
+        http_status: reqwest::StatusCode,
+        response_bytes: &[u8],
+        agent_id: &str,
+    ) -> Result<JsonRpcResponse, PipelineError> {
+        // Handle HTTP errors
+        if !http_status.is_success() {
+            let error_body = String::from_utf8_lossy(response_bytes).to_string();
+            return Err(if http_status.is_client_error() {
+                PipelineError::ClientError {
+                    agent: agent_id.to_string(),
+                    status: http_status.as_u16(),
+                    body: error_body,
+                }
+            } else {
+                PipelineError::ServerError {
+                    agent: agent_id.to_string(),
+                    status: http_status.as_u16(),
+                    body: error_body,
+                }
+            });
+        }
+
+        info!(
+            "Response from agent {}: {}",
+            agent_id,
+            String::from_utf8_lossy(response_bytes)
+        );
+
+        // Parse SSE response
+        let data_chunk = self.parse_sse_response(response_bytes, agent_id)?;
+        Ok(serde_json::from_str(&data_chunk)?)
+    }
+
+    /// Run a stream filter agent over one chunk of the terminal agent response and
+    /// return the transformed content. MCP filters are called with a `chunk` argument and
+    /// answer with `structuredContent.result`; HTTP filters receive the chunk as body and
+    /// answer with `{"content": ...}`.
+    pub async fn execute_stream_filter(
+        &mut self,
+        chunk: &StreamChunk,
+        agent: &Agent,
+        request_headers: &HeaderMap,
+        trace_id: String,
+        parent_span_id: String,
+    ) -> Result<String, PipelineError> {
+        let timeout = agent.timeout_ms.map(Duration::from_millis);
+        let call = async {
+            if agent.agent_type.as_deref().unwrap_or("mcp") == "mcp" {
+                self.execute_mcp_stream_filter(
+                    chunk,
+                    agent,
+                    request_headers,
+                    trace_id,
+                    parent_span_id,
+                )
+                .await
+            } else {
+                self.execute_http_stream_filter(chunk, agent, request_headers, trace_id)
+                    .await
+            }
+        };
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(PipelineError::Timeout {
+                        agent: agent.id.clone(),
+                        timeout_ms: timeout.as_millis() as u64,
+                    })
+                }),
+            None => call.await,
+        }
+    }
+
+    async fn execute_mcp_stream_filter(
+        &mut self,
+        chunk: &StreamChunk,
+        agent: &Agent,
+        request_headers: &HeaderMap,
+        trace_id: String,
+        parent_span_id: String,
+    ) -> Result<String, PipelineError> {
+        let tool_name = agent.tool.as_deref().unwrap_or(&agent.id);
+        let mut arguments = HashMap::new();
+        arguments.insert("chunk".to_string(), serde_json::to_value(chunk)?);
+        let json_rpc_request = self.build_tool_call_request_with_arguments(tool_name, arguments)?;
+        self.validate_tool_call(&json_rpc_request, &agent.id, tool_name)?;
+
+        let (_, reply) = self
+            .send_mcp_tool_call(
+                &json_rpc_request,
+                agent,
+                request_headers,
+                trace_id,
+                parent_span_id.clone(),
+                parent_span_id,
+            )
+            .await?;
+
+        let response_result = self.parse_tool_call_result(reply, &agent.id)?;
+        response_result
+            .get("structuredContent")
+            .and_then(|v| v.get("result"))
+            .and_then(|v| v.as_str())
+            .map(|content| content.to_string())
+            .ok_or_else(|| PipelineError::NoStructuredContentInResponse(agent.id.clone()))
+    }
+
+    async fn execute_http_stream_filter(
+        &self,
+        chunk: &StreamChunk,
+        agent: &Agent,
+        request_headers: &HeaderMap,
+        trace_id: String,
+    ) -> Result<String, PipelineError> {
+        let mut agent_headers = request_headers.clone();
+        agent_headers.remove(hyper::header::CONTENT_LENGTH);
+        agent_headers.remove(TRACE_PARENT_HEADER);
+        if !trace_id.is_empty() {
+            let trace_parent = format!("00-{}-{}-01", trace_id, generate_random_span_id());
+            agent_headers.insert(
+                TRACE_PARENT_HEADER,
+                hyper::header::HeaderValue::from_str(&trace_parent).unwrap(),
+            );
+        }
+        agent_headers.insert(
+            ARCH_UPSTREAM_HOST_HEADER,
+            hyper::header::HeaderValue::from_str(&agent.id)
+                .map_err(|_| PipelineError::AgentNotFound(agent.id.clone()))?,
+        );
+
+        let response = self
+            .client
+            .post(&agent.url)
+            .headers(agent_headers)
+            .json(chunk)
+            .send()
+            .await?;
+        let http_status = response.status();
+        let response_bytes = response.bytes().await?;
+        if !http_status.is_success() {
+            let error_body = String::from_utf8_lossy(&response_bytes).to_string();
+            return Err(if http_status.is_client_error() {
+                PipelineError::ClientError {
+                    agent: agent.id.clone(),
+                    status: http_status.as_u16(),
+                    body: error_body,
+                }
+            } else {
+                PipelineError::ServerError {
+                    agent: agent.id.clone(),
+                    status: http_status.as_u16(),
+                    body: error_body,
+                }
+            });
+        }
+
+        let response: serde_json::Value = serde_json::from_slice(&response_bytes)?;
+        response
+            .get("content")
+            .and_then(|v| v.as_str())
+            .map(|content| content.to_string())
+            .ok_or_else(|| PipelineError::NoContentInResponse(agent.id.clone()))
+    }
+
+    /// Send initialized notification after session creation
+    async fn send_initialized_notification(
+        &self,
+        agent_id: &str,
+        session_id: &str,
+        trace_id: String,
+        parent_span_id: String,
+    ) -> Result<(), PipelineError> {
+        let initialized_notification = JsonRpcNotification {
+            jsonrpc: JSON_RPC_VERSION.to_string(),
+            method: MCP_INITIALIZE_NOTIFICATION.to_string(),
+            params: None,
+        };
+
+        let notification_body = serde_json::to_string(&initialized_notification)?;
+        debug!("Sending initialized notification for agent {}", agent_id);
+
+        let headers = self.build_mcp_headers(
+            &HeaderMap::new(),
+            agent_id,
+            Some(session_id),
+            trace_id.clone(),
+            parent_span_id.clone(),
+        )?;
+
+        let response = self
+            .client
+            .post(format!("{}/mcp", self.url))
+            .headers(headers)
+            .body(notification_body)
+            .send()
+            .await?;
+
+        info!(
+            "Initialized notification response status: {}",
+            response.status()
+        );
+
+        Ok(())
+    }
+
+    /// Initialize a new MCP session with the agent, retrying transient failures
+    async fn get_new_session_id(
+        &self,
+        agent_id: &str,
+        trace_id: String,
+        parent_span_id: String,
+    ) -> Result<String, PipelineError> {
+        let mut attempt = 0;
+        loop {
+            match self
+                .initialize_session(agent_id, trace_id.clone(), parent_span_id.clone())
+                .await
+            {
+                Ok(session_id) => return Ok(session_id),
+                Err(err) if err.is_retryable() && attempt + 1 < MCP_SESSION_INIT_ATTEMPTS => {
+                    let backoff = Duration::from_millis(DEFAULT_RETRY_BACKOFF_MS << attempt);
+                    warn!(
+                        "MCP session initialization for agent {} failed (attempt {}/{}): {}, retrying in {}ms",
+                        agent_id,
+                        attempt + 1,
+                        MCP_SESSION_INIT_ATTEMPTS,
+                        err,
+                        backoff.as_millis()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    warn!(
+                        "MCP session initialization for agent {} failed: {}",
+                        agent_id, err
+                    );
+                    return Err(match err {
+                        PipelineError::SessionInitFailed { .. } => err,
+                        err => PipelineError::SessionInitFailed {
+                            agent: agent_id.to_string(),
+                            reason: err.to_string(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    async fn initialize_session(
+        &self,
+        agent_id: &str,
+        trace_id: String,
+        parent_span_id: String,
+    ) -> Result<String, PipelineError> {
+        info!("Initializing MCP session for agent {}", agent_id);
+
+        let initialize_request = mcp_initialize_request();
+        let headers = self.build_mcp_headers(
+            &HeaderMap::new(),
+            agent_id,
+            None,
+            trace_id.clone(),
+            parent_span_id.clone(),
+        )?;
+
+        let response = self
+            .send_mcp_request(&initialize_request, headers, agent_id)
+            .await?;
+
+        let http_status = response.status();
+        info!("Initialize response status: {}", http_status);
+        if http_status.is_server_error() {
+            return Err(PipelineError::ServerError {
+                agent: agent_id.to_string(),
+                status: http_status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        if !http_status.is_success() {
+            return Err(PipelineError::SessionInitFailed {
+                agent: agent_id.to_string(),
+                reason: format!("initialize returned HTTP {}", http_status.as_u16()),
+            });
+        }
+
+        let session_id = response
+            .headers()
             .get("mcp-session-id")
             .and_then(|v| v.to_str().ok())
-            .expect("No mcp-session-id in response")
-            .to_string();
+            .map(str::to_string)
+            .ok_or_else(|| PipelineError::SessionInitFailed {
+                agent: agent_id.to_string(),
+                reason: "no mcp-session-id in initialize response".to_string(),
+            })?;
 
         info!(
             "Created new MCP session for agent {}: {}",
@@ -687,10 +1374,9 @@ impl PipelineProcessor {
             trace_id.clone(),
             parent_span_id.clone(),
         )
-        .await
-        .expect("Failed to send initialized notification");
+        .await?;
 
-        session_id
+        Ok(session_id)
     }
 
     /// Execute a HTTP-based filter agent
@@ -816,7 +1502,53 @@ impl PipelineProcessor {
         Ok(messages)
     }
 
-    /// Send request to terminal agent and return the raw response for streaming
+    /// Run the built-in retrieval filter: the chunks closest to the last user message are
+    /// added to the conversation and kept to be cited in the response
+    async fn execute_retrieval_filter(
+        &mut self,
+        messages: &[Message],
+        agent: &Agent,
+        trace_id: &str,
+        filter_span_id: &str,
+    ) -> Result<Vec<Message>, PipelineError> {
+        let Some(config) = agent.retrieval.as_ref() else {
+            return Err(PipelineError::Retrieval {
+                agent: agent.id.clone(),
+                source: RetrievalError::MissingSettings,
+            });
+        };
+        let Some(query) = retrieval_query(messages) else {
+            debug!(
+                "no user message to retrieve context for in filter {}",
+                agent.id
+            );
+            return Ok(messages.to_vec());
+        };
+
+        let trace_parent =
+            (!trace_id.is_empty()).then(|| format!("00-{}-{}-01", trace_id, filter_span_id));
+        let chunks = Retriever::new(self.client.clone(), &self.llm_provider_url)
+            .retrieve(&agent.url, config, &query, trace_parent.as_deref())
+            .await
+            .map_err(|source| PipelineError::Retrieval {
+                agent: agent.id.clone(),
+                source,
+            })?;
+
+        info!(
+            "retrieval filter {} added {} chunk(s) from {:?}",
+            agent.id,
+            chunks.len(),
+            config.vector_store
+        );
+        let messages = add_context(messages, &chunks);
+        self.citations.extend(chunks);
+        Ok(messages)
+    }
+
+    /// Send request to terminal agent and return the response for streaming. The request
+    /// is sent in the API the agent serves and its response converted to `response_api`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn invoke_agent(
         &self,
         messages: &[Message],
@@ -825,12 +1557,27 @@ impl PipelineProcessor {
         request_headers: &HeaderMap,
         trace_id: String,
         agent_span_id: String,
+        response_api: &SupportedAPIsFromClient,
     ) -> Result<reqwest::Response, PipelineError> {
+        await_approval(
+            &self.client,
+            terminal_agent,
+            serde_json::json!({ "messages": messages }),
+        )
+        .await?;
+
         // let mut request = original_request.clone();
         original_request.set_messages(messages);
 
-        let request_body = ProviderRequestType::to_bytes(&original_request).unwrap();
-        // let request_body = serde_json::to_string(&request)?;
+        let (agent_api, agent_path) = agent_upstream_api(terminal_agent.api.unwrap_or_default());
+        let agent_request =
+            ProviderRequestType::try_from((original_request, &agent_api)).map_err(|err| {
+                PipelineError::UnsupportedAgentApi {
+                    agent: terminal_agent.id.clone(),
+                    reason: err.message,
+                }
+            })?;
+        let request_body = ProviderRequestType::to_bytes(&agent_request).unwrap();
         debug!("Sending request to terminal agent {}", terminal_agent.id);
 
         let mut agent_headers = request_headers.clone();
@@ -857,21 +1604,83 @@ impl PipelineProcessor {
             hyper::header::HeaderValue::from_str("3").unwrap(),
         );
 
-        let response = self
-            .client
-            .post(format!("{}/v1/chat/completions", self.url))
-            .headers(agent_headers)
-            .body(request_body)
-            .send()
-            .await?;
+        if let Some(circuit_breaker) = terminal_agent.circuit_breaker.as_ref() {
+            if !agent_circuit_breakers().allow(&terminal_agent.id, circuit_breaker) {
+                return Err(PipelineError::CircuitOpen(terminal_agent.id.clone()));
+            }
+        }
 
-        Ok(response)
+        // Only the response headers are covered by the timeout and retries,
+        // the body is streamed back to the client once the agent has answered.
+        let max_retries = terminal_agent.max_retries.unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let send = self
+                .client
+                .post(format!("{}{}", self.url, agent_path))
+                .headers(agent_headers.clone())
+                .body(request_body.clone())
+                .send();
+            let result = match terminal_agent.timeout_ms {
+                Some(timeout_ms) => {
+                    match tokio::time::timeout(Duration::from_millis(timeout_ms), send).await {
+                        Ok(result) => result.map_err(PipelineError::from),
+                        Err(_) => Err(PipelineError::Timeout {
+                            agent: terminal_agent.id.clone(),
+                            timeout_ms,
+                        }),
+                    }
+                }
+                None => send.await.map_err(PipelineError::from),
+            };
+
+            let error = match result {
+                Ok(response) if !response.status().is_server_error() => {
+                    agent_circuit_breakers().record_success(&terminal_agent.id);
+                    return Ok(normalize_response(response, response_api, &agent_api));
+                }
+                Ok(response) if attempt >= max_retries => {
+                    if let Some(circuit_breaker) = terminal_agent.circuit_breaker.as_ref() {
+                        agent_circuit_breakers()
+                            .record_failure(&terminal_agent.id, circuit_breaker);
+                    }
+                    return Ok(response);
+                }
+                Ok(response) => format!("HTTP {}", response.status()),
+                Err(err) if err.is_retryable() && attempt < max_retries => err.to_string(),
+                Err(err) => {
+                    if let Some(circuit_breaker) = terminal_agent.circuit_breaker.as_ref() {
+                        if err.is_retryable() {
+                            agent_circuit_breakers()
+                                .record_failure(&terminal_agent.id, circuit_breaker);
+                        }
+                    }
+                    return Err(err);
+                }
+            };
+
+            if let Some(circuit_breaker) = terminal_agent.circuit_breaker.as_ref() {
+                agent_circuit_breakers().record_failure(&terminal_agent.id, circuit_breaker);
+            }
+            let backoff = retry_backoff(terminal_agent, attempt);
+            warn!(
+                "terminal agent {} failed (attempt {}/{}): {}, retrying in {}ms",
+                terminal_agent.id,
+                attempt + 1,
+                max_retries + 1,
+                error,
+                backoff.as_millis()
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::configuration::ConditionalFilters;
     use hermesllm::apis::openai::{Message, MessageContent, Role};
     use mockito::Server;
     use std::collections::HashMap;
@@ -889,7 +1698,8 @@ mod tests {
     fn create_test_pipeline(agents: Vec<&str>) -> AgentFilterChain {
         AgentFilterChain {
             id: "test-agent".to_string(),
-            filter_chain: Some(agents.iter().map(|s| s.to_string()).collect()),
+            filter_chain: Some(agents.iter().map(|s| FilterStep::from(*s)).collect()),
+            stream_filter_chain: None,
             description: None,
             default: None,
         }
@@ -912,6 +1722,7 @@ mod tests {
                 &agent_map,
                 &request_headers,
                 None,
+                None,
                 String::new(),
                 String::new(),
             )
@@ -921,6 +1732,83 @@ mod tests {
         matches!(result.unwrap_err(), PipelineError::AgentNotFound(_));
     }
 
+    #[tokio::test]
+    async fn test_conditional_filter_steps() {
+        let mut processor = PipelineProcessor::default();
+        let agent_map = HashMap::new();
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert("x-tier", "free".parse().unwrap());
+        let messages = vec![create_test_message(Role::User, "where is my order?")];
+
+        let mut pipeline = create_test_pipeline(vec![]);
+        pipeline.filter_chain = Some(vec![FilterStep::Conditional(ConditionalFilters {
+            when: r#"last_user_message contains "refund" || header.x-tier == "gold""#.to_string(),
+            then: vec!["refund-filter".to_string()],
+            otherwise: None,
+        })]);
+        // the condition doesn't hold and there is no else branch, no filter runs
+        let result = processor
+            .process_filter_chain(
+                &messages,
+                &pipeline,
+                &agent_map,
+                &request_headers,
+                None,
+                None,
+                String::new(),
+                String::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+
+        if let Some(FilterStep::Conditional(conditional)) = pipeline
+            .filter_chain
+            .as_mut()
+            .and_then(|steps| steps.first_mut())
+        {
+            conditional.otherwise = Some(vec!["faq-filter".to_string()]);
+        }
+        let result = processor
+            .process_filter_chain(
+                &messages,
+                &pipeline,
+                &agent_map,
+                &request_headers,
+                None,
+                None,
+                String::new(),
+                String::new(),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(PipelineError::AgentNotFound(agent)) if agent == "faq-filter")
+        );
+    }
+
+    #[test]
+    fn test_condition_variable() {
+        let messages = vec![
+            create_test_message(Role::User, "hi"),
+            create_test_message(Role::Assistant, "hello"),
+        ];
+        let metadata = HashMap::from([(
+            "customer".to_string(),
+            serde_json::json!({ "tier": "gold" }),
+        )]);
+        let variable =
+            |name: &str| condition_variable(name, &messages, &HeaderMap::new(), Some(&metadata));
+        assert_eq!(variable("last_user_message"), Some(Value::from("hi")));
+        assert_eq!(variable("last_message"), Some(Value::from("hello")));
+        assert_eq!(variable("message_count"), Some(Value::from(2)));
+        assert_eq!(
+            variable("metadata.customer.tier"),
+            Some(Value::from("gold"))
+        );
+        assert_eq!(variable("metadata.customer.region"), None);
+        assert_eq!(variable("header.x-tier"), None);
+    }
+
     #[tokio::test]
     async fn test_execute_filter_http_status_error() {
         let mut server = Server::new_async().await;
@@ -942,6 +1830,17 @@ mod tests {
             tool: None,
             url: server_url,
             agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
         };
 
         let messages = vec![create_test_message(Role::User, "Hello")];
@@ -967,6 +1866,78 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_execute_filter_retries_server_errors_and_opens_circuit() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/mcp")
+            .with_status(503)
+            .with_body("unavailable")
+            .expect(2)
+            .create();
+
+        let server_url = server.url();
+        let mut processor = PipelineProcessor::new(server_url.clone());
+        processor
+            .agent_id_session_map
+            .insert("agent-retry".to_string(), "session-1".to_string());
+
+        let agent = Agent {
+            id: "agent-retry".to_string(),
+            transport: None,
+            tool: None,
+            url: server_url,
+            agent_type: None,
+            timeout_ms: Some(5_000),
+            max_retries: Some(1),
+            retry_backoff_ms: Some(1),
+            circuit_breaker: Some(common::configuration::AgentCircuitBreaker {
+                failure_threshold: 2,
+                cooldown_ms: 60_000,
+                skip_when_open: Some(true),
+            }),
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
+        };
+
+        let messages = vec![create_test_message(Role::User, "Hello")];
+        let request_headers = HeaderMap::new();
+
+        let result = processor
+            .execute_filter_with_policy(
+                &messages,
+                &agent,
+                &request_headers,
+                None,
+                "trace-123".to_string(),
+                "span-123".to_string(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(PipelineError::ServerError { status: 503, .. })
+        ));
+        mock.assert();
+
+        // both attempts failed, the circuit is open and the filter is skipped
+        let result = processor
+            .execute_filter_with_policy(
+                &messages,
+                &agent,
+                &request_headers,
+                None,
+                "trace-123".to_string(),
+                "span-123".to_string(),
+            )
+            .await;
+        assert!(matches!(result, Ok(None)));
+    }
+
     #[tokio::test]
     async fn test_execute_filter_http_client_error() {
         let mut server = Server::new_async().await;
@@ -988,6 +1959,17 @@ mod tests {
             tool: None,
             url: server_url,
             agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
         };
 
         let messages = vec![create_test_message(Role::User, "Ping")];
@@ -1047,6 +2029,17 @@ mod tests {
             tool: None,
             url: server_url,
             agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
         };
 
         let messages = vec![create_test_message(Role::User, "Hi")];
@@ -1071,4 +2064,252 @@ mod tests {
             _ => panic!("Expected client error when isError flag is set"),
         }
     }
+
+    #[tokio::test]
+    async fn test_execute_filter_rejects_arguments_not_matching_the_tool_schema() {
+        let mut server = Server::new_async().await;
+        let call = server.mock("POST", "/mcp").expect(0).create();
+
+        let server_url = server.url();
+        let mut processor = PipelineProcessor::new(server_url.clone());
+        processor.tool_schemas().update(
+            "query_rewriter",
+            vec![McpToolDefinition {
+                name: "rewrite_query".to_string(),
+                description: None,
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {"messages": {"type": "array"}, "language": {"type": "string"}},
+                    "required": ["messages", "language"]
+                }),
+            }],
+        );
+        let agent: Agent = serde_json::from_value(serde_json::json!({
+            "id": "query_rewriter",
+            "url": server_url,
+            "tool": "rewrite_query"
+        }))
+        .unwrap();
+
+        let result = processor
+            .execute_mcp_filter(
+                &[create_test_message(Role::User, "Hello")],
+                &agent,
+                &HeaderMap::new(),
+                None,
+                "trace-123".to_string(),
+                "span-123".to_string(),
+            )
+            .await;
+
+        match result {
+            Err(PipelineError::InvalidToolArguments {
+                agent,
+                tool,
+                reason,
+            }) => {
+                assert_eq!(agent, "query_rewriter");
+                assert_eq!(tool, "rewrite_query");
+                assert_eq!(reason, "arguments.language: required property is missing");
+            }
+            other => panic!("Expected invalid tool arguments, got {:?}", other),
+        }
+        call.assert();
+    }
+
+    #[tokio::test]
+    async fn test_execute_filter_over_stdio() {
+        let script = r#"while read -r line; do
+  case "$line" in
+    *'"method":"notifications/'*) ;;
+    *)
+      id=$(printf '%s' "$line" | sed -n 's/.*"id":"\([^"]*\)".*/\1/p')
+      printf '{"jsonrpc":"2.0","id":"%s","result":{"structuredContent":{"result":[{"role":"user","content":"rewritten"}]}}}\n' "$id"
+      ;;
+  esac
+done"#;
+        let agent: Agent = serde_json::from_value(serde_json::json!({
+            "id": "query_rewriter",
+            "transport": "stdio",
+            "stdio": {"command": "sh", "args": ["-c", script]}
+        }))
+        .unwrap();
+        let mut processor = PipelineProcessor::new("http://localhost:1".to_string());
+
+        let messages = processor
+            .execute_mcp_filter(
+                &[create_test_message(Role::User, "Hello")],
+                &agent,
+                &HeaderMap::new(),
+                None,
+                "trace-123".to_string(),
+                "span-123".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content.to_string(), "rewritten");
+        assert!(processor.agent_id_session_map.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_execute_filter_reinitializes_expired_session() {
+        let rpc_body = serde_json::json!({
+            "jsonrpc": JSON_RPC_VERSION,
+            "id": "1",
+            "result": {
+                "structuredContent": {
+                    "result": [{"role": "user", "content": "rewritten"}]
+                }
+            }
+        });
+        let sse_body = format!("event: message\ndata: {}\n\n", rpc_body);
+
+        let mut server = Server::new_async().await;
+        let expired = server
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", "expired-session")
+            .with_status(404)
+            .expect(1)
+            .create();
+        let initialize = server
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("mcp-session-id", "fresh-session")
+            .with_body("event: message\ndata: {}\n\n")
+            .expect(1)
+            .create();
+        let _fresh = server
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", "fresh-session")
+            .with_status(200)
+            .with_body(sse_body)
+            .create();
+
+        let server_url = server.url();
+        let mut processor = PipelineProcessor::new(server_url.clone());
+        processor
+            .agent_id_session_map
+            .insert("agent-4".to_string(), "expired-session".to_string());
+
+        let agent = Agent {
+            id: "agent-4".to_string(),
+            transport: None,
+            tool: None,
+            url: server_url,
+            agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
+        };
+
+        let messages = vec![create_test_message(Role::User, "Hi")];
+        let result = processor
+            .execute_mcp_filter(
+                &messages,
+                &agent,
+                &HeaderMap::new(),
+                None,
+                "trace-404".to_string(),
+                "span-404".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+        expired.assert();
+        initialize.assert();
+        assert_eq!(
+            processor.agent_id_session_map.get("agent-4"),
+            Some("fresh-session".to_string())
+        );
+        assert_eq!(
+            processor
+                .agent_id_session_map
+                .stats
+                .reinitialized
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_initialization_failure_returns_error() {
+        let mut server = Server::new_async().await;
+        let initialize = server
+            .mock("POST", "/mcp")
+            .match_header("mcp-session-id", mockito::Matcher::Missing)
+            .with_status(503)
+            .expect(MCP_SESSION_INIT_ATTEMPTS as usize)
+            .create();
+
+        let server_url = server.url();
+        let mut processor = PipelineProcessor::new(server_url.clone());
+        let agent = Agent {
+            id: "agent-5".to_string(),
+            transport: None,
+            tool: None,
+            url: server_url.clone(),
+            agent_type: None,
+            timeout_ms: None,
+            max_retries: None,
+            retry_backoff_ms: None,
+            circuit_breaker: None,
+            requires_approval: None,
+            approval_webhook: None,
+            approval_timeout_ms: None,
+            retrieval: None,
+            api: None,
+            tools_refresh_interval_ms: None,
+            stdio: None,
+        };
+
+        let messages = vec![create_test_message(Role::User, "Hi")];
+        let result = processor
+            .execute_mcp_filter(
+                &messages,
+                &agent,
+                &HeaderMap::new(),
+                None,
+                "trace-init".to_string(),
+                "span-init".to_string(),
+            )
+            .await;
+
+        match result {
+            Err(PipelineError::SessionInitFailed { agent, reason }) => {
+                assert_eq!(agent, "agent-5");
+                assert!(reason.contains("503"));
+            }
+            other => panic!("Expected session init failure, got {:?}", other),
+        }
+        initialize.assert();
+        assert!(processor.agent_id_session_map.is_empty());
+
+        // a 200 without a session id is not retried
+        let mut server = Server::new_async().await;
+        let initialize = server
+            .mock("POST", "/mcp")
+            .with_status(200)
+            .with_body("event: message\ndata: {}\n\n")
+            .expect(1)
+            .create();
+        let processor = PipelineProcessor::new(server.url());
+        let err = processor
+            .get_new_session_id("agent-5", "trace".to_string(), "span".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::SessionInitFailed { .. }));
+        initialize.assert();
+    }
 }
@@ -0,0 +1,159 @@
+use common::configuration::PromptTemplateConfig;
+use common::prompt_template::{parse_templates, LabeledTemplate, TemplateError};
+use hermesllm::apis::openai::{Message, MessageContent, Role};
+use hermesllm::{ProviderRequest, ProviderRequestType};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Variable holding the model the client asked for, metadata keys can't override it
+pub const MODEL_VARIABLE: &str = "model";
+
+/// Prompt templates of the configuration, parsed once at startup
+#[derive(Default)]
+pub struct PromptTemplates {
+    templates: HashMap<String, LabeledTemplate>,
+}
+
+impl PromptTemplates {
+    pub fn new(configs: &HashMap<String, PromptTemplateConfig>) -> Result<Self, String> {
+        Ok(PromptTemplates {
+            templates: parse_templates(configs)?,
+        })
+    }
+
+    /// Renders template `name` with the metadata of `request` and makes it the system
+    /// prompt of the request. Returns the `name@version` label of the template, or None
+    /// when there is no such template.
+    pub fn apply(
+        &self,
+        name: &str,
+        requested_model: &str,
+        request: &mut ProviderRequestType,
+    ) -> Result<Option<String>, TemplateError> {
+        let Some(labeled) = self.templates.get(name) else {
+            return Ok(None);
+        };
+        let mut variables = metadata_variables(request.metadata());
+        variables.insert(MODEL_VARIABLE.to_string(), requested_model.to_string());
+        let system_prompt = labeled.template.render(&variables)?;
+        set_system_prompt(request, system_prompt);
+        Ok(Some(labeled.label.clone()))
+    }
+}
+
+/// Request metadata as template variables, values that aren't strings as json
+pub fn metadata_variables(metadata: &Option<HashMap<String, Value>>) -> HashMap<String, String> {
+    metadata
+        .iter()
+        .flatten()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// Replaces the system prompt of the request, or adds one
+fn set_system_prompt(request: &mut ProviderRequestType, system_prompt: String) {
+    // setting messages on responses requests flattens their input, the system prompt
+    // has a field of its own there
+    if let ProviderRequestType::ResponsesAPIRequest(request) = request {
+        request.instructions = Some(system_prompt);
+        return;
+    }
+    let mut messages = request.get_messages();
+    messages.retain(|message| message.role != Role::System);
+    messages.insert(
+        0,
+        Message {
+            role: Role::System,
+            content: MessageContent::Text(system_prompt),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        },
+    );
+    request.set_messages(&messages);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::ChatCompletionsRequest;
+
+    fn templates() -> PromptTemplates {
+        let configs = HashMap::from([(
+            "support".to_string(),
+            PromptTemplateConfig {
+                template: "You support the {{ team }} team as {{ model }}, tier {{ tier | default(\"free\") }}."
+                    .to_string(),
+                version: Some("v3".to_string()),
+            },
+        )]);
+        PromptTemplates::new(&configs).unwrap()
+    }
+
+    fn chat_request(metadata: Value) -> ProviderRequestType {
+        let request: ChatCompletionsRequest = serde_json::from_value(serde_json::json!({
+            "model": "support.default",
+            "messages": [
+                {"role": "system", "content": "client prompt"},
+                {"role": "user", "content": "hi"}
+            ],
+            "metadata": metadata
+        }))
+        .unwrap();
+        ProviderRequestType::ChatCompletionsRequest(request)
+    }
+
+    #[test]
+    fn test_apply_replaces_system_prompt() {
+        let mut request = chat_request(serde_json::json!({"team": "search", "tier": 2}));
+        let label = templates()
+            .apply("support", "support.default", &mut request)
+            .unwrap();
+        assert_eq!(label.as_deref(), Some("support@v3"));
+        let messages = request.get_messages();
+        assert_eq!(messages.len(), 2);
+        match &messages[0].content {
+            MessageContent::Text(text) => assert_eq!(
+                text,
+                "You support the search team as support.default, tier 2."
+            ),
+            other => panic!("unexpected content {:?}", other),
+        }
+        assert_eq!(messages[1].role, Role::User);
+    }
+
+    #[test]
+    fn test_apply_missing_variable_and_template() {
+        let templates = templates();
+        let mut request = chat_request(serde_json::json!({}));
+        assert_eq!(
+            templates.apply("support", "support.default", &mut request),
+            Err(TemplateError::MissingVariable("team".to_string()))
+        );
+        assert_eq!(
+            templates.apply("other", "support.default", &mut request),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        let configs = HashMap::from([(
+            "broken".to_string(),
+            PromptTemplateConfig {
+                template: "Hi {{ name".to_string(),
+                version: None,
+            },
+        )]);
+        assert!(PromptTemplates::new(&configs)
+            .err()
+            .unwrap()
+            .starts_with("prompt template broken"));
+    }
+}
@@ -0,0 +1,131 @@
+use bytes::Bytes;
+use common::configuration::ProvenanceConfig;
+use common::consts::{
+    ARCH_GATEWAY_VERSION_HEADER, ARCH_POLICY_DECISIONS_HEADER, ARCH_SERVED_MODEL_HEADER,
+    ARCH_SERVED_PROVIDER_HEADER,
+};
+use hyper::header::{HeaderMap, HeaderValue};
+use serde_json::{json, Map, Value};
+
+/// Field added to non streaming json responses when provenance is also wanted in the body
+pub const PROVENANCE_BODY_FIELD: &str = "arch_provenance";
+
+/// Where the provenance of llm responses is exposed
+#[derive(Debug, Clone, Copy)]
+pub struct ProvenancePolicy {
+    pub body: bool,
+}
+
+impl From<&ProvenanceConfig> for ProvenancePolicy {
+    fn from(config: &ProvenanceConfig) -> Self {
+        ProvenancePolicy {
+            body: config.body_field.unwrap_or(false),
+        }
+    }
+}
+
+/// Which provider and model served a request and the policies that shaped it, for
+/// downstream audits
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    pub provider: String,
+    pub model: String,
+    /// Policy name to its decision, in the order they were taken
+    pub decisions: Vec<(&'static str, String)>,
+}
+
+impl Provenance {
+    pub fn decision(&mut self, policy: &'static str, decision: impl Into<String>) {
+        self.decisions.push((policy, decision.into()));
+    }
+
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        let mut insert = |name: &'static str, value: &str| {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        };
+        insert(ARCH_SERVED_PROVIDER_HEADER, &self.provider);
+        insert(ARCH_SERVED_MODEL_HEADER, &self.model);
+        insert(ARCH_GATEWAY_VERSION_HEADER, env!("CARGO_PKG_VERSION"));
+        if !self.decisions.is_empty() {
+            let decisions = self
+                .decisions
+                .iter()
+                .map(|(policy, decision)| format!("{}={}", policy, decision))
+                .collect::<Vec<_>>()
+                .join(", ");
+            insert(ARCH_POLICY_DECISIONS_HEADER, &decisions);
+        }
+    }
+
+    /// Adds the provenance to a json object body, other bodies are left as they are.
+    /// Returns whether the body changed.
+    pub fn append_to_body(&self, body: &mut Bytes) -> bool {
+        let Ok(Value::Object(mut response)) = serde_json::from_slice::<Value>(body) else {
+            return false;
+        };
+        let decisions: Map<String, Value> = self
+            .decisions
+            .iter()
+            .map(|(policy, decision)| (policy.to_string(), Value::String(decision.clone())))
+            .collect();
+        response.insert(
+            PROVENANCE_BODY_FIELD.to_string(),
+            json!({
+                "provider": self.provider,
+                "model": self.model,
+                "gateway_version": env!("CARGO_PKG_VERSION"),
+                "decisions": decisions,
+            }),
+        );
+        *body = Bytes::from(Value::Object(response).to_string());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provenance() -> Provenance {
+        let mut provenance = Provenance {
+            provider: "openai/gpt-4o".to_string(),
+            model: "gpt-4o".to_string(),
+            decisions: Vec::new(),
+        };
+        provenance.decision("traffic_split", "canary");
+        provenance.decision("dlp_request", "redacted");
+        provenance
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut headers = HeaderMap::new();
+        provenance().insert_headers(&mut headers);
+        assert_eq!(headers[ARCH_SERVED_PROVIDER_HEADER], "openai/gpt-4o");
+        assert_eq!(headers[ARCH_SERVED_MODEL_HEADER], "gpt-4o");
+        assert_eq!(
+            headers[ARCH_POLICY_DECISIONS_HEADER],
+            "traffic_split=canary, dlp_request=redacted"
+        );
+        assert!(headers.contains_key(ARCH_GATEWAY_VERSION_HEADER));
+    }
+
+    #[test]
+    fn test_append_to_body() {
+        let mut body = Bytes::from(r#"{"id":"c1","choices":[]}"#);
+        assert!(provenance().append_to_body(&mut body));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], "c1");
+        assert_eq!(body[PROVENANCE_BODY_FIELD]["provider"], "openai/gpt-4o");
+        assert_eq!(
+            body[PROVENANCE_BODY_FIELD]["decisions"]["traffic_split"],
+            "canary"
+        );
+
+        let mut not_json = Bytes::from("upstream error");
+        assert!(!provenance().append_to_body(&mut not_json));
+        assert_eq!(not_json, "upstream error");
+    }
+}
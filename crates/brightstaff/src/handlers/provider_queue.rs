@@ -0,0 +1,499 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::configuration::{ProviderConcurrency, RequestPriority, RequestPriorityConfig};
+use common::consts::{ARCH_PRIORITY_HEADER, ARCH_TENANT_ID_HEADER};
+use http_body_util::combinators::BoxBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::handlers::response_handler::ResponseHandler;
+
+const DEFAULT_MAX_QUEUE_WAIT_MS: u64 = 30_000;
+
+/// Upper bounds of the queue wait time buckets, in milliseconds
+const WAIT_TIME_BUCKETS_MS: [u64; 10] = [1, 10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Resolves the priority of llm requests from their headers
+#[derive(Debug, Clone)]
+pub struct RequestPriorities {
+    header: String,
+    tenant_header: String,
+    tenants: HashMap<String, RequestPriority>,
+    default: RequestPriority,
+}
+
+impl Default for RequestPriorities {
+    fn default() -> Self {
+        RequestPriorities::new(&RequestPriorityConfig::default(), ARCH_TENANT_ID_HEADER)
+    }
+}
+
+impl RequestPriorities {
+    pub fn new(config: &RequestPriorityConfig, tenant_header: &str) -> Self {
+        RequestPriorities {
+            header: config
+                .header
+                .as_deref()
+                .unwrap_or(ARCH_PRIORITY_HEADER)
+                .to_ascii_lowercase(),
+            tenant_header: tenant_header.to_ascii_lowercase(),
+            tenants: config.tenants.clone().unwrap_or_default(),
+            default: config.default.unwrap_or_default(),
+        }
+    }
+
+    /// Priority from the priority header, else from the tenant of the request, else the default
+    pub fn priority(&self, headers: &HeaderMap) -> RequestPriority {
+        let header_value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        if let Some(priority) = header_value(&self.header).and_then(|value| value.parse().ok()) {
+            return priority;
+        }
+        header_value(&self.tenant_header)
+            .and_then(|tenant| self.tenants.get(tenant.trim()))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Process wide queues of the providers with a concurrency limit
+pub fn provider_queues() -> &'static ProviderQueues {
+    static QUEUES: OnceLock<ProviderQueues> = OnceLock::new();
+    QUEUES.get_or_init(ProviderQueues::default)
+}
+
+/// Why a request didn't get a slot, it is answered with 429
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Shed {
+    #[error("the provider is at its concurrency limit and its queue is full")]
+    QueueFull,
+    #[error("the request was shed from the queue for a higher priority request")]
+    Displaced,
+    #[error("no slot of the provider was free within the queue wait time")]
+    Timeout,
+}
+
+#[derive(Debug, Default)]
+pub struct ProviderQueues {
+    providers: Mutex<HashMap<String, Arc<ProviderQueue>>>,
+}
+
+impl ProviderQueues {
+    fn queue(&self, provider: &str, limit: &ProviderConcurrency) -> Arc<ProviderQueue> {
+        self.providers
+            .lock()
+            .unwrap()
+            .entry(provider.to_string())
+            .or_insert_with(|| Arc::new(ProviderQueue::new(limit)))
+            .clone()
+    }
+
+    /// Waits for a slot of the provider. The slot is taken until the returned guard is
+    /// dropped, once the response is streamed to the client.
+    pub async fn acquire(
+        &self,
+        request_id: &str,
+        provider: &str,
+        limit: &ProviderConcurrency,
+        priority: RequestPriority,
+    ) -> Result<ProviderSlot, Shed> {
+        let queue = self.queue(provider, limit);
+        let result = queue.clone().acquire(priority).await;
+        match &result {
+            Ok(slot) if slot.waited > Duration::ZERO => info!(
+                "[PLANO_REQ_ID:{}] | PROVIDER_QUEUE | provider={} priority={} waited {}ms",
+                request_id,
+                provider,
+                priority.as_str(),
+                slot.waited.as_millis()
+            ),
+            Ok(_) => {}
+            Err(shed) => warn!(
+                "[PLANO_REQ_ID:{}] | PROVIDER_QUEUE | provider={} priority={} shed: {}",
+                request_id,
+                provider,
+                priority.as_str(),
+                shed
+            ),
+        }
+        result
+    }
+
+    pub fn stats(&self) -> Vec<ProviderQueueStats> {
+        let providers = self.providers.lock().unwrap();
+        let mut stats: Vec<_> = providers
+            .iter()
+            .map(|(provider, queue)| {
+                let state = queue.state();
+                ProviderQueueStats {
+                    provider: provider.clone(),
+                    max_concurrent_requests: queue.max_concurrent_requests,
+                    in_flight: state.in_flight,
+                    queue_depth: state.waiting.len(),
+                    admitted: state.admitted,
+                    shed: state.shed,
+                    timed_out: state.timed_out,
+                    wait_time_ms: state.wait_times.clone(),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.provider.cmp(&b.provider));
+        stats
+    }
+}
+
+/// Requests in flight and waiting for one provider
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProviderQueueStats {
+    pub provider: String,
+    pub max_concurrent_requests: usize,
+    pub in_flight: usize,
+    /// Requests waiting for a slot right now
+    pub queue_depth: usize,
+    /// Requests that got a slot since startup
+    pub admitted: u64,
+    /// Requests answered with 429 because the queue was full
+    pub shed: u64,
+    /// Requests answered with 429 because they waited too long
+    pub timed_out: u64,
+    /// Time admitted requests waited for a slot, by priority
+    pub wait_time_ms: BTreeMap<RequestPriority, WaitTimeHistogram>,
+}
+
+/// Cumulative histogram of queue wait times, `counts[i]` requests waited at most
+/// `buckets_ms[i]`, `count` includes the ones that waited longer
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct WaitTimeHistogram {
+    pub buckets_ms: Vec<u64>,
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+impl Default for WaitTimeHistogram {
+    fn default() -> Self {
+        WaitTimeHistogram {
+            buckets_ms: WAIT_TIME_BUCKETS_MS.to_vec(),
+            counts: vec![0; WAIT_TIME_BUCKETS_MS.len()],
+            count: 0,
+            sum_ms: 0,
+        }
+    }
+}
+
+impl WaitTimeHistogram {
+    fn record(&mut self, wait: Duration) {
+        let wait_ms = wait.as_millis() as u64;
+        for (bound, count) in self.buckets_ms.iter().zip(self.counts.iter_mut()) {
+            if wait_ms <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_ms += wait_ms;
+    }
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: RequestPriority,
+    seq: u64,
+    admit: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    in_flight: usize,
+    next_seq: u64,
+    waiting: Vec<Waiter>,
+    admitted: u64,
+    shed: u64,
+    timed_out: u64,
+    wait_times: BTreeMap<RequestPriority, WaitTimeHistogram>,
+}
+
+impl QueueState {
+    /// Hands the slot of a finished request to the highest priority waiter, oldest first
+    fn release(&mut self) {
+        while let Some(next) = self
+            .waiting
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, waiter)| (waiter.priority, Reverse(waiter.seq)))
+            .map(|(i, _)| i)
+        {
+            let waiter = self.waiting.remove(next);
+            if waiter.admit.send(()).is_ok() {
+                return;
+            }
+        }
+        self.in_flight = self.in_flight.saturating_sub(1);
+    }
+
+    fn record_wait(&mut self, priority: RequestPriority, wait: Duration) {
+        self.admitted += 1;
+        self.wait_times.entry(priority).or_default().record(wait);
+    }
+}
+
+#[derive(Debug)]
+struct ProviderQueue {
+    max_concurrent_requests: usize,
+    max_queue_size: usize,
+    max_queue_wait: Duration,
+    state: Mutex<QueueState>,
+}
+
+impl ProviderQueue {
+    fn new(limit: &ProviderConcurrency) -> Self {
+        let max_concurrent_requests = limit.max_concurrent_requests.max(1);
+        ProviderQueue {
+            max_concurrent_requests,
+            max_queue_size: limit.max_queue_size.unwrap_or(max_concurrent_requests),
+            max_queue_wait: Duration::from_millis(
+                limit.max_queue_wait_ms.unwrap_or(DEFAULT_MAX_QUEUE_WAIT_MS),
+            ),
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap()
+    }
+
+    async fn acquire(self: Arc<Self>, priority: RequestPriority) -> Result<ProviderSlot, Shed> {
+        let start = Instant::now();
+        let (seq, receiver) = {
+            let mut state = self.state();
+            if state.in_flight < self.max_concurrent_requests && state.waiting.is_empty() {
+                state.in_flight += 1;
+                state.record_wait(priority, Duration::ZERO);
+                return Ok(ProviderSlot {
+                    queue: self.clone(),
+                    waited: Duration::ZERO,
+                });
+            }
+            if state.waiting.len() >= self.max_queue_size {
+                // a full queue sheds its lowest priority request, the newest one first
+                let lowest = state
+                    .waiting
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, waiter)| (waiter.priority, Reverse(waiter.seq)))
+                    .map(|(i, waiter)| (i, waiter.priority));
+                state.shed += 1;
+                match lowest {
+                    // dropping its sender tells the waiter it was shed
+                    Some((i, lowest)) if lowest < priority => drop(state.waiting.remove(i)),
+                    _ => return Err(Shed::QueueFull),
+                }
+            }
+            let (admit, receiver) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                admit,
+            });
+            (seq, receiver)
+        };
+
+        let mut pending = PendingAdmission {
+            queue: self.clone(),
+            seq,
+            receiver,
+            admitted: false,
+        };
+        match tokio::time::timeout(self.max_queue_wait, &mut pending.receiver).await {
+            Ok(Ok(())) => {
+                pending.admitted = true;
+                let waited = start.elapsed();
+                self.state().record_wait(priority, waited);
+                Ok(ProviderSlot {
+                    queue: self.clone(),
+                    waited,
+                })
+            }
+            Ok(Err(_)) => Err(Shed::Displaced),
+            Err(_) => {
+                drop(pending);
+                self.state().timed_out += 1;
+                Err(Shed::Timeout)
+            }
+        }
+    }
+}
+
+/// Place of a request in the queue, leaves the queue when the request stops waiting,
+/// e.g. the client went away, and passes on a slot it got but never used
+struct PendingAdmission {
+    queue: Arc<ProviderQueue>,
+    seq: u64,
+    receiver: oneshot::Receiver<()>,
+    admitted: bool,
+}
+
+impl Drop for PendingAdmission {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+        let mut state = self.queue.state();
+        if let Some(i) = state
+            .waiting
+            .iter()
+            .position(|waiter| waiter.seq == self.seq)
+        {
+            state.waiting.remove(i);
+        } else if self.receiver.try_recv().is_ok() {
+            state.release();
+        }
+    }
+}
+
+/// Slot of a provider taken by a request in flight
+#[derive(Debug)]
+pub struct ProviderSlot {
+    queue: Arc<ProviderQueue>,
+    waited: Duration,
+}
+
+impl Drop for ProviderSlot {
+    fn drop(&mut self) {
+        self.queue.state().release();
+    }
+}
+
+/// Handles `GET /v1/provider_queues/stats`: requests in flight and queued for each provider
+pub fn provider_queue_stats() -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = serde_json::json!({ "object": "list", "data": provider_queues().stats() });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn queue(max_concurrent_requests: usize, max_queue_size: usize) -> Arc<ProviderQueue> {
+        Arc::new(ProviderQueue::new(&ProviderConcurrency {
+            max_concurrent_requests,
+            max_queue_size: Some(max_queue_size),
+            max_queue_wait_ms: Some(50),
+        }))
+    }
+
+    async fn queued(queue: &ProviderQueue, depth: usize) {
+        while queue.state().waiting.len() < depth {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test]
+    fn test_priority_from_header_then_tenant() {
+        let priorities = RequestPriorities::new(
+            &RequestPriorityConfig {
+                tenants: Some(HashMap::from([(
+                    "batch-jobs".to_string(),
+                    RequestPriority::Low,
+                )])),
+                ..Default::default()
+            },
+            ARCH_TENANT_ID_HEADER,
+        );
+        let mut headers = HeaderMap::new();
+        assert_eq!(priorities.priority(&headers), RequestPriority::Normal);
+        headers.insert("x-arch-tenant-id", HeaderValue::from_static("batch-jobs"));
+        assert_eq!(priorities.priority(&headers), RequestPriority::Low);
+        headers.insert("x-arch-priority", HeaderValue::from_static("HIGH"));
+        assert_eq!(priorities.priority(&headers), RequestPriority::High);
+        // unknown priorities fall back to the tenant's
+        headers.insert("x-arch-priority", HeaderValue::from_static("urgent"));
+        assert_eq!(priorities.priority(&headers), RequestPriority::Low);
+    }
+
+    #[tokio::test]
+    async fn test_slots_go_to_the_highest_priority_waiter() {
+        let queue = queue(1, 4);
+        let slot = queue
+            .clone()
+            .acquire(RequestPriority::Normal)
+            .await
+            .unwrap();
+
+        let (order, mut admitted) = tokio::sync::mpsc::unbounded_channel();
+        for priority in [RequestPriority::Low, RequestPriority::High] {
+            let queue = queue.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                let slot = queue.acquire(priority).await.unwrap();
+                order.send(priority).unwrap();
+                drop(slot);
+            });
+        }
+        queued(&queue, 2).await;
+        drop(slot);
+
+        assert_eq!(admitted.recv().await, Some(RequestPriority::High));
+        assert_eq!(admitted.recv().await, Some(RequestPriority::Low));
+        let state = queue.state();
+        assert_eq!((state.admitted, state.shed), (3, 0));
+        assert_eq!(state.wait_times[&RequestPriority::Normal].counts[0], 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_lowest_priority() {
+        let queue = queue(1, 1);
+        let slot = queue
+            .clone()
+            .acquire(RequestPriority::Normal)
+            .await
+            .unwrap();
+
+        let low = tokio::spawn(queue.clone().acquire(RequestPriority::Low));
+        queued(&queue, 1).await;
+        // the high priority request takes the place of the low priority one
+        let high = tokio::spawn(queue.clone().acquire(RequestPriority::High));
+        assert_eq!(low.await.unwrap().unwrap_err(), Shed::Displaced);
+        assert_eq!(
+            queue
+                .clone()
+                .acquire(RequestPriority::Normal)
+                .await
+                .unwrap_err(),
+            Shed::QueueFull
+        );
+
+        drop(slot);
+        assert!(high.await.unwrap().is_ok());
+        let state = queue.state();
+        assert_eq!((state.shed, state.in_flight), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_waiting_too_long_times_out() {
+        let queue = queue(1, 1);
+        let _slot = queue.clone().acquire(RequestPriority::High).await.unwrap();
+        assert_eq!(
+            queue
+                .clone()
+                .acquire(RequestPriority::High)
+                .await
+                .unwrap_err(),
+            Shed::Timeout
+        );
+        let state = queue.state();
+        assert_eq!((state.timed_out, state.waiting.len()), (1, 0));
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::configuration::RateLimitRetryPolicy;
+use http_body_util::combinators::BoxBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use crate::handlers::response_handler::ResponseHandler;
+
+const DEFAULT_MAX_WAIT_MS: u64 = 30_000;
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_MAX_CONCURRENT_RETRIES: usize = 4;
+
+/// OpenAI and Azure send the delay in milliseconds next to Retry-After
+const RETRY_AFTER_MS_HEADER: &str = "retry-after-ms";
+
+/// Resolved rate limit retry settings
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitRetry {
+    pub max_wait: Duration,
+    pub max_retries: u32,
+    pub max_concurrent_retries: usize,
+}
+
+impl From<&RateLimitRetryPolicy> for RateLimitRetry {
+    fn from(policy: &RateLimitRetryPolicy) -> Self {
+        RateLimitRetry {
+            max_wait: Duration::from_millis(policy.max_wait_ms.unwrap_or(DEFAULT_MAX_WAIT_MS)),
+            max_retries: policy.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            max_concurrent_retries: policy
+                .max_concurrent_retries
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_RETRIES)
+                .max(1),
+        }
+    }
+}
+
+/// Delay a rate limited response asks for, from `retry-after-ms` or `Retry-After` in
+/// seconds or as an HTTP date. None when the response doesn't say when to retry.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get(RETRY_AFTER_MS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|ms| ms.is_finite() && *ms >= 0.0)
+    {
+        return Some(Duration::from_millis(ms as u64));
+    }
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.signed_duration_since(chrono::Utc::now());
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Process wide queues of held requests, one per provider
+pub fn rate_limit_queues() -> &'static RateLimitQueues {
+    static QUEUES: OnceLock<RateLimitQueues> = OnceLock::new();
+    QUEUES.get_or_init(RateLimitQueues::default)
+}
+
+#[derive(Debug)]
+struct ProviderQueue {
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    held: AtomicU64,
+    retried: AtomicU64,
+    given_up: AtomicU64,
+}
+
+impl ProviderQueue {
+    fn new(max_concurrent_retries: usize) -> Self {
+        ProviderQueue {
+            slots: Arc::new(Semaphore::new(max_concurrent_retries)),
+            waiting: AtomicUsize::new(0),
+            held: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            given_up: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimitQueues {
+    providers: Mutex<HashMap<String, Arc<ProviderQueue>>>,
+}
+
+/// Queue depth and outcomes of the requests held for one provider
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RateLimitQueueStats {
+    pub provider: String,
+    /// Requests held right now
+    pub queue_depth: usize,
+    /// Requests held since startup
+    pub held: u64,
+    /// Retries sent
+    pub retried: u64,
+    /// Requests whose 429 was returned because the wait would be too long
+    pub given_up: u64,
+}
+
+impl RateLimitQueues {
+    fn queue(&self, provider: &str, max_concurrent_retries: usize) -> Arc<ProviderQueue> {
+        self.providers
+            .lock()
+            .unwrap()
+            .entry(provider.to_string())
+            .or_insert_with(|| Arc::new(ProviderQueue::new(max_concurrent_retries)))
+            .clone()
+    }
+
+    pub fn stats(&self) -> Vec<RateLimitQueueStats> {
+        let mut stats: Vec<_> = self
+            .providers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(provider, queue)| RateLimitQueueStats {
+                provider: provider.clone(),
+                queue_depth: queue.waiting.load(Ordering::Relaxed),
+                held: queue.held.load(Ordering::Relaxed),
+                retried: queue.retried.load(Ordering::Relaxed),
+                given_up: queue.given_up.load(Ordering::Relaxed),
+            })
+            .collect();
+        stats.sort_by(|a, b| a.provider.cmp(&b.provider));
+        stats
+    }
+}
+
+/// Retries of one request, holds it for the Retry-After of each 429 until the retries
+/// or the wait budget run out
+pub struct RateLimitRetries {
+    config: RateLimitRetry,
+    provider: String,
+    queue: Arc<ProviderQueue>,
+    retries: u32,
+    deadline: Instant,
+}
+
+impl RateLimitRetries {
+    pub fn new(config: RateLimitRetry, provider: &str) -> Self {
+        Self::with_queues(config, provider, rate_limit_queues())
+    }
+
+    fn with_queues(config: RateLimitRetry, provider: &str, queues: &RateLimitQueues) -> Self {
+        RateLimitRetries {
+            config,
+            provider: provider.to_string(),
+            queue: queues.queue(provider, config.max_concurrent_retries),
+            retries: 0,
+            deadline: Instant::now() + config.max_wait,
+        }
+    }
+
+    /// Waits out the delay of a 429 and a free retry slot of the provider. The slot is
+    /// held until the returned permit is dropped, once the retry is answered. None when
+    /// the response has no Retry-After or the request can't wait that long, the 429 is
+    /// then returned to the client.
+    pub async fn wait(
+        &mut self,
+        request_id: &str,
+        headers: &HeaderMap,
+    ) -> Option<OwnedSemaphorePermit> {
+        let delay = retry_after(headers)?;
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if self.retries >= self.config.max_retries || delay > remaining {
+            self.queue.given_up.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "[PLANO_REQ_ID:{}] | RATE_LIMITED | provider={} retry_after_ms={} retries={}, returning 429",
+                request_id,
+                self.provider,
+                delay.as_millis(),
+                self.retries
+            );
+            return None;
+        }
+        self.retries += 1;
+        self.queue.held.fetch_add(1, Ordering::Relaxed);
+        self.queue.waiting.fetch_add(1, Ordering::Relaxed);
+        info!(
+            "[PLANO_REQ_ID:{}] | RATE_LIMITED | provider={} holding for {}ms, retry {}/{}",
+            request_id,
+            self.provider,
+            delay.as_millis(),
+            self.retries,
+            self.config.max_retries
+        );
+        tokio::time::sleep(delay).await;
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        let permit = tokio::time::timeout(remaining, self.queue.slots.clone().acquire_owned())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        self.queue.waiting.fetch_sub(1, Ordering::Relaxed);
+        match permit {
+            Some(permit) => {
+                self.queue.retried.fetch_add(1, Ordering::Relaxed);
+                Some(permit)
+            }
+            None => {
+                self.queue.given_up.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "[PLANO_REQ_ID:{}] | RATE_LIMITED | provider={} no retry slot within the wait budget",
+                    request_id, self.provider
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Handles `GET /v1/rate_limit_retries/stats`: requests held for each provider
+pub fn rate_limit_retry_stats() -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = serde_json::json!({ "object": "list", "data": rate_limit_queues().stats() });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn config(max_wait_ms: u64, max_retries: u32, max_concurrent_retries: usize) -> RateLimitRetry {
+        RateLimitRetry::from(&RateLimitRetryPolicy {
+            max_wait_ms: Some(max_wait_ms),
+            max_retries: Some(max_retries),
+            max_concurrent_retries: Some(max_concurrent_retries),
+        })
+    }
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(
+            retry_after(&headers("retry-after", "2")),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            retry_after(&headers("retry-after-ms", "150")),
+            Some(Duration::from_millis(150))
+        );
+        // dates in the past mean retry now
+        assert_eq!(
+            retry_after(&headers("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&headers("retry-after", "soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_holds_until_retries_run_out() {
+        let queues = RateLimitQueues::default();
+        let mut retries = RateLimitRetries::with_queues(config(1_000, 1, 1), "openai", &queues);
+        let rate_limited = headers("retry-after-ms", "10");
+
+        assert!(retries.wait("req", &rate_limited).await.is_some());
+        assert!(retries.wait("req", &rate_limited).await.is_none());
+        // no Retry-After, nothing to wait for
+        assert!(retries.wait("req", &HeaderMap::new()).await.is_none());
+
+        let stats = queues.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].queue_depth, 0);
+        assert_eq!(
+            (stats[0].held, stats[0].retried, stats[0].given_up),
+            (1, 1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_retry_after_exceeds_max_wait() {
+        let queues = RateLimitQueues::default();
+        let mut retries = RateLimitRetries::with_queues(config(100, 3, 1), "anthropic", &queues);
+        assert!(retries
+            .wait("req", &headers("retry-after", "5"))
+            .await
+            .is_none());
+        assert_eq!(queues.stats()[0].held, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retries_of_a_provider_queue_for_a_slot() {
+        let queues = RateLimitQueues::default();
+        let rate_limited = headers("retry-after-ms", "0");
+        let mut first = RateLimitRetries::with_queues(config(50, 1, 1), "openai", &queues);
+        let mut second = RateLimitRetries::with_queues(config(50, 1, 1), "openai", &queues);
+
+        let permit = first.wait("req-1", &rate_limited).await;
+        assert!(permit.is_some());
+        // the only slot is taken until the first retry is answered
+        assert!(second.wait("req-2", &rate_limited).await.is_none());
+        drop(permit);
+
+        let stats = queues.stats();
+        assert_eq!((stats[0].retried, stats[0].given_up), (1, 1));
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use common::configuration::RatelimitJwtConfig;
+use hyper::header::{HeaderMap, HeaderValue};
+use serde_json::{Map, Value};
+
+use crate::handlers::jwt::{bearer_jwt, claim_value, JwtError, JwtValidator, ValidatedClaims};
+
+/// Turns the claims of the bearer JWT of llm requests into ratelimit selectors, which
+/// llm_gateway applies next to the selector header
+pub struct RatelimitJwt {
+    validator: JwtValidator,
+    claims: HashMap<String, String>,
+}
+
+impl RatelimitJwt {
+    pub fn new(config: &RatelimitJwtConfig) -> Self {
+        RatelimitJwt {
+            validator: JwtValidator::new(&config.validation),
+            claims: config.claims.clone(),
+        }
+    }
+
+    /// Selectors of the validated claims, as the value of the claims header. Claims the
+    /// listener already validated are used as they are. None when the request has no
+    /// bearer JWT, e.g. it carries a provider api key, or the token has none of the
+    /// configured claims.
+    pub async fn selectors(
+        &self,
+        headers: &HeaderMap,
+        validated: Option<&ValidatedClaims>,
+    ) -> Result<Option<HeaderValue>, JwtError> {
+        let selectors = match validated {
+            Some(ValidatedClaims(claims)) => claim_selectors(&self.claims, claims),
+            None => {
+                let Some(token) = bearer_jwt(headers) else {
+                    return Ok(None);
+                };
+                claim_selectors(&self.claims, &self.validator.validate(token).await?)
+            }
+        };
+        if selectors.is_empty() {
+            return Ok(None);
+        }
+        Ok(HeaderValue::from_str(&Value::Object(selectors).to_string()).ok())
+    }
+}
+
+/// Selector key to value of the configured claims the token has
+fn claim_selectors(
+    claims_config: &HashMap<String, String>,
+    claims: &Map<String, Value>,
+) -> Map<String, Value> {
+    claims_config
+        .iter()
+        .filter_map(|(claim, selector)| {
+            claim_value(claims, claim).map(|value| (selector.clone(), Value::String(value)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jwt::tests::{test_bearer, test_validation_config, test_validator};
+    use serde_json::json;
+
+    fn ratelimit_jwt() -> RatelimitJwt {
+        let mut ratelimit_jwt = RatelimitJwt::new(&RatelimitJwtConfig {
+            validation: test_validation_config(),
+            claims: HashMap::from([
+                ("sub".to_string(), "user_id".to_string()),
+                ("org".to_string(), "org_id".to_string()),
+            ]),
+        });
+        ratelimit_jwt.validator = test_validator();
+        ratelimit_jwt
+    }
+
+    #[tokio::test]
+    async fn test_claims_become_selectors() {
+        let jwt = ratelimit_jwt();
+        let headers = test_bearer(json!({
+            "sub": "alice",
+            "org": 42,
+            "iss": "https://auth.example.com/",
+            "aud": "plano",
+            "exp": 4102444800u64
+        }));
+
+        let selectors = jwt.selectors(&headers, None).await.unwrap().unwrap();
+        let selectors: Value = serde_json::from_slice(selectors.as_bytes()).unwrap();
+        assert_eq!(selectors, json!({"user_id": "alice", "org_id": "42"}));
+
+        // claims the listener validated aren't validated again
+        let validated = ValidatedClaims(json!({"sub": "bob"}).as_object().unwrap().clone());
+        let selectors = jwt
+            .selectors(&HeaderMap::new(), Some(&validated))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selectors, r#"{"user_id":"bob"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tokens_are_rejected() {
+        let jwt = ratelimit_jwt();
+        let expired = test_bearer(json!({
+            "sub": "alice",
+            "iss": "https://auth.example.com/",
+            "aud": "plano",
+            "exp": 1000000000u64
+        }));
+        assert!(jwt.selectors(&expired, None).await.is_err());
+        assert!(jwt
+            .selectors(&HeaderMap::new(), None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
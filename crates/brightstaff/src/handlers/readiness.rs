@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::configuration::LlmProvider;
+use http_body_util::combinators::BoxBody;
+use hyper::header;
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::{watch, RwLock};
+
+use crate::discovery::DiscoverySnapshot;
+use crate::handlers::response_handler::ResponseHandler;
+use crate::state::StateStorage;
+
+/// A readiness probe shouldn't hang on an unreachable database
+const STATE_STORAGE_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One dependency check of `/readyz`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ReadinessCheck {
+    fn new(name: &'static str, failure: Option<String>) -> Self {
+        ReadinessCheck {
+            name,
+            ready: failure.is_none(),
+            detail: failure,
+        }
+    }
+}
+
+/// Checks of the dependencies llm requests need. The config was parsed at startup, a
+/// running brightstaff always has one.
+pub async fn readiness_checks(
+    llm_providers: &RwLock<Vec<LlmProvider>>,
+    discovered_models: Option<&watch::Receiver<DiscoverySnapshot>>,
+    state_storage: Option<&Arc<dyn StateStorage>>,
+    draining: bool,
+) -> Vec<ReadinessCheck> {
+    let mut checks = vec![ReadinessCheck::new(
+        "shutdown",
+        draining.then(|| "draining connections".to_string()),
+    )];
+
+    let configured = llm_providers.read().await.len();
+    let discovered = discovered_models.map_or(0, |models| models.borrow().models.len());
+    checks.push(ReadinessCheck::new(
+        "model_registry",
+        (configured + discovered == 0).then(|| "no models configured or discovered".to_string()),
+    ));
+
+    if let Some(state_storage) = state_storage {
+        let failure =
+            match tokio::time::timeout(STATE_STORAGE_PING_TIMEOUT, state_storage.ping()).await {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(err.to_string()),
+                Err(_) => Some("timed out".to_string()),
+            };
+        checks.push(ReadinessCheck::new("state_storage", failure));
+    }
+    checks
+}
+
+/// 200 when every check passes, 503 otherwise, with the checks in the body
+pub async fn readiness(
+    llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
+    discovered_models: Option<watch::Receiver<DiscoverySnapshot>>,
+    state_storage: Option<Arc<dyn StateStorage>>,
+    shutdown: watch::Receiver<bool>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let draining = *shutdown.borrow();
+    let checks = readiness_checks(
+        &llm_providers,
+        discovered_models.as_ref(),
+        state_storage.as_ref(),
+        draining,
+    )
+    .await;
+    let ready = checks.iter().all(|check| check.ready);
+    let body = serde_json::json!({ "ready": ready, "checks": checks });
+    Response::builder()
+        .status(if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        })
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::memory::MemoryConversationalStorage;
+
+    #[tokio::test]
+    async fn test_readiness_checks() {
+        let llm_providers = RwLock::new(vec![LlmProvider {
+            name: "openai/gpt-4o".to_string(),
+            ..Default::default()
+        }]);
+        let state_storage: Arc<dyn StateStorage> = Arc::new(MemoryConversationalStorage::new());
+        let checks = readiness_checks(&llm_providers, None, Some(&state_storage), false).await;
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|check| check.ready));
+
+        let checks = readiness_checks(&RwLock::new(Vec::new()), None, None, true).await;
+        assert_eq!(
+            checks,
+            vec![
+                ReadinessCheck {
+                    name: "shutdown",
+                    ready: false,
+                    detail: Some("draining connections".to_string()),
+                },
+                ReadinessCheck {
+                    name: "model_registry",
+                    ready: false,
+                    detail: Some("no models configured or discovered".to_string()),
+                },
+            ]
+        );
+    }
+}
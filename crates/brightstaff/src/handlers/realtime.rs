@@ -0,0 +1,634 @@
+use bytes::Bytes;
+use common::configuration::{LlmProvider, LlmProviderType};
+use common::consts::{
+    ARCH_REQUEST_ID_HEADER, OPENAI_ORGANIZATION_HEADER, OPENAI_PROJECT_HEADER, REALTIME_PATH,
+    TRACE_PARENT_HEADER,
+};
+use common::traces::{parse_traceparent, SpanBuilder, SpanKind, TraceCollector};
+use futures::{SinkExt, StreamExt};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::RwLock;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info, warn};
+
+use crate::tracing::{http, llm, operation_component, OperationNameBuilder};
+
+const OPENAI_REALTIME_HOST: &str = "api.openai.com";
+const AZURE_REALTIME_PATH: &str = "/openai/realtime";
+const AZURE_REALTIME_API_VERSION: &str = "2024-10-01-preview";
+const GEMINI_LIVE_HOST: &str = "generativelanguage.googleapis.com";
+const GEMINI_LIVE_PATH: &str =
+    "/ws/google.ai.generativelanguage.v1beta.GenerativeService.BidiGenerateContent";
+
+fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(message));
+    *response.status_mut() = status;
+    response
+}
+
+/// Wire protocol spoken by the upstream realtime endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RealtimeFlavor {
+    OpenAI,
+    /// The OpenAI events, served per deployment and authenticated with an `api-key` header
+    AzureOpenAI,
+    GeminiLive,
+}
+
+impl RealtimeFlavor {
+    fn for_provider(provider: &LlmProvider) -> Option<Self> {
+        match provider.provider_interface {
+            LlmProviderType::OpenAI => Some(RealtimeFlavor::OpenAI),
+            LlmProviderType::AzureOpenAI => Some(RealtimeFlavor::AzureOpenAI),
+            LlmProviderType::Gemini => Some(RealtimeFlavor::GeminiLive),
+            _ => None,
+        }
+    }
+}
+
+/// Token usage accumulated over a realtime session from the upstream server events
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RealtimeUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub responses: u64,
+}
+
+impl RealtimeUsage {
+    /// Inspect a server event and add any usage it reports
+    pub fn observe(&mut self, flavor: RealtimeFlavor, event: &str) {
+        let Ok(event) = serde_json::from_str::<Value>(event) else {
+            return;
+        };
+        let token_count = |value: &Value, key: &str| value.get(key).and_then(Value::as_u64);
+
+        match flavor {
+            // usage is reported once per response on the response.done event
+            RealtimeFlavor::OpenAI | RealtimeFlavor::AzureOpenAI => {
+                if event.get("type").and_then(Value::as_str) != Some("response.done") {
+                    return;
+                }
+                if let Some(usage) = event.pointer("/response/usage") {
+                    self.input_tokens += token_count(usage, "input_tokens").unwrap_or(0);
+                    self.output_tokens += token_count(usage, "output_tokens").unwrap_or(0);
+                    self.responses += 1;
+                }
+            }
+            RealtimeFlavor::GeminiLive => {
+                if let Some(usage) = event.get("usageMetadata") {
+                    self.input_tokens += token_count(usage, "promptTokenCount").unwrap_or(0);
+                    self.output_tokens += token_count(usage, "responseTokenCount")
+                        .or_else(|| token_count(usage, "candidatesTokenCount"))
+                        .unwrap_or(0);
+                    self.responses += 1;
+                }
+            }
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// Find the provider for the requested realtime model, falling back to the default provider
+fn select_realtime_provider(providers: &[LlmProvider], model: Option<&str>) -> Option<LlmProvider> {
+    model
+        .and_then(|model| {
+            providers
+                .iter()
+                .find(|p| p.model.as_deref() == Some(model) || p.name == model)
+        })
+        .or_else(|| providers.iter().find(|p| p.default.unwrap_or(false)))
+        .cloned()
+}
+
+/// Build the upstream websocket url for the provider, None for Azure providers without an
+/// endpoint as every Azure resource has its own host
+pub fn realtime_upstream_url(
+    provider: &LlmProvider,
+    flavor: RealtimeFlavor,
+    model: &str,
+) -> Option<String> {
+    let host = match (provider.endpoint.as_deref(), flavor) {
+        (Some(endpoint), _) => endpoint,
+        (None, RealtimeFlavor::OpenAI) => OPENAI_REALTIME_HOST,
+        (None, RealtimeFlavor::GeminiLive) => GEMINI_LIVE_HOST,
+        (None, RealtimeFlavor::AzureOpenAI) => return None,
+    };
+    let scheme = if provider.port == Some(80) {
+        "ws"
+    } else {
+        "wss"
+    };
+    let authority = match provider.port {
+        Some(port) if port != 80 && port != 443 => format!("{}:{}", host, port),
+        _ => host.to_string(),
+    };
+    let prefix = provider
+        .base_url_path_prefix
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+
+    let url = match flavor {
+        RealtimeFlavor::OpenAI => format!(
+            "{}://{}{}{}?model={}",
+            scheme, authority, prefix, REALTIME_PATH, model
+        ),
+        // the model of an Azure provider is its deployment
+        RealtimeFlavor::AzureOpenAI => format!(
+            "{}://{}{}{}?api-version={}&deployment={}",
+            scheme, authority, prefix, AZURE_REALTIME_PATH, AZURE_REALTIME_API_VERSION, model
+        ),
+        // Gemini Live authenticates with the key query parameter
+        RealtimeFlavor::GeminiLive => format!(
+            "{}://{}{}{}?key={}",
+            scheme,
+            authority,
+            prefix,
+            GEMINI_LIVE_PATH,
+            provider.access_key.as_deref().unwrap_or_default()
+        ),
+    };
+    Some(url)
+}
+
+/// Subprotocols offered by the client, passed on to the upstream so the one it picks can
+/// be echoed back. The OpenAI browser protocol that carries an api key is dropped, the
+/// upstream is authenticated with the provider's key.
+fn offered_subprotocols(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| {
+            !protocol.is_empty() && !protocol.starts_with("openai-insecure-api-key.")
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_websocket_upgrade(request: &Request<hyper::body::Incoming>) -> bool {
+    let has_token = |name: header::HeaderName, token: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| {
+                v.split(',')
+                    .any(|part| part.trim().eq_ignore_ascii_case(token))
+            })
+            .unwrap_or(false)
+    };
+    has_token(header::UPGRADE, "websocket") && has_token(header::CONNECTION, "upgrade")
+}
+
+/// Proxy a realtime websocket session to the selected provider.
+/// The wasm filters can't hold a websocket open, so the client connection is terminated
+/// here: the provider is selected from the `model` query parameter, credentials are
+/// injected on the upstream handshake and frames are relayed both ways while usage is
+/// metered from the server events. A single span is recorded when the session ends.
+pub async fn realtime_proxy(
+    mut request: Request<hyper::body::Incoming>,
+    llm_providers: Arc<RwLock<Vec<LlmProvider>>>,
+    trace_collector: Arc<TraceCollector>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let request_id = request
+        .headers()
+        .get(ARCH_REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+    let traceparent = request
+        .headers()
+        .get(TRACE_PARENT_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    if !is_websocket_upgrade(&request) {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "Expected a websocket upgrade request".to_string(),
+        ));
+    }
+    let Some(accept_key) = request
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .map(|key| derive_accept_key(key.as_bytes()))
+    else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "Missing Sec-WebSocket-Key header".to_string(),
+        ));
+    };
+
+    let requested_model = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "model")
+            .map(|(_, value)| value.to_string())
+    });
+
+    let provider = {
+        let providers = llm_providers.read().await;
+        select_realtime_provider(&providers, requested_model.as_deref())
+    };
+    let Some(provider) = provider else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "No provider configured for realtime model {:?}",
+                requested_model
+            ),
+        ));
+    };
+    let Some(flavor) = RealtimeFlavor::for_provider(&provider) else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Provider {} does not support the realtime API",
+                provider.name
+            ),
+        ));
+    };
+    let Some(access_key) = provider.access_key.clone() else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "No access key configured for selected LLM Provider \"{}\"",
+                provider.name
+            ),
+        ));
+    };
+
+    let model = provider
+        .model
+        .clone()
+        .or(requested_model)
+        .unwrap_or_default();
+    let Some(upstream_url) = realtime_upstream_url(&provider, flavor, &model) else {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Provider {} needs an endpoint for the realtime API",
+                provider.name
+            ),
+        ));
+    };
+
+    let mut upstream_request = match upstream_url.as_str().into_client_request() {
+        Ok(upstream_request) => upstream_request,
+        Err(err) => {
+            return Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Invalid realtime upstream url: {}", err),
+            ));
+        }
+    };
+    let subprotocols = offered_subprotocols(request.headers());
+    if !subprotocols.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&subprotocols.join(", ")) {
+            upstream_request
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+    }
+    if flavor == RealtimeFlavor::AzureOpenAI {
+        if let Ok(value) = HeaderValue::from_str(&access_key) {
+            upstream_request.headers_mut().insert("api-key", value);
+        }
+    }
+    if flavor == RealtimeFlavor::OpenAI {
+        let headers = upstream_request.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", access_key)) {
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        headers.insert("OpenAI-Beta", HeaderValue::from_static("realtime=v1"));
+        if let Some(value) = provider
+            .organization
+            .as_deref()
+            .and_then(|org| HeaderValue::from_str(org).ok())
+        {
+            headers.insert(OPENAI_ORGANIZATION_HEADER, value);
+        }
+        if let Some(value) = provider
+            .project
+            .as_deref()
+            .and_then(|project| HeaderValue::from_str(project).ok())
+        {
+            headers.insert(OPENAI_PROJECT_HEADER, value);
+        }
+    }
+
+    // Connect upstream before accepting the client so failures surface as an http error
+    let (upstream, subprotocol) = match tokio_tungstenite::connect_async(upstream_request).await {
+        Ok((upstream, response)) => (
+            upstream,
+            response
+                .headers()
+                .get(header::SEC_WEBSOCKET_PROTOCOL)
+                .cloned(),
+        ),
+        Err(err) => {
+            warn!(
+                "[PLANO_REQ_ID:{}] REALTIME_UPSTREAM_CONNECT_FAILED: provider={} error={}",
+                request_id, provider.name, err
+            );
+            return Ok(error_response(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to connect to realtime upstream: {}", err),
+            ));
+        }
+    };
+
+    info!(
+        "[PLANO_REQ_ID:{}] REALTIME_SESSION_START: provider={} model={}",
+        request_id, provider.name, model
+    );
+
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] REALTIME_UPGRADE_FAILED: {}",
+                    request_id, err
+                );
+                return;
+            }
+        };
+        let client =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+
+        let start_time = SystemTime::now();
+        let started = Instant::now();
+        let usage = relay(client, upstream, flavor).await;
+
+        info!(
+            "[PLANO_REQ_ID:{}] REALTIME_SESSION_END: provider={} model={} input_tokens={} output_tokens={} duration_ms={}",
+            request_id,
+            provider.name,
+            model,
+            usage.input_tokens,
+            usage.output_tokens,
+            started.elapsed().as_millis()
+        );
+
+        let operation_name = OperationNameBuilder::new()
+            .with_method("GET")
+            .with_path(REALTIME_PATH)
+            .with_target(&model)
+            .build();
+        let mut span_builder = SpanBuilder::new(&operation_name)
+            .with_kind(SpanKind::Client)
+            .with_start_time(start_time)
+            .with_end_time(SystemTime::now())
+            .with_attribute(http::METHOD, "GET")
+            .with_attribute(http::TARGET, REALTIME_PATH)
+            .with_attribute(llm::MODEL_NAME, model.clone())
+            .with_attribute(llm::PROVIDER, provider.name.clone())
+            .with_attribute(llm::IS_STREAMING, "true")
+            .with_attribute(llm::PROMPT_TOKENS, usage.input_tokens.to_string())
+            .with_attribute(llm::COMPLETION_TOKENS, usage.output_tokens.to_string())
+            .with_attribute(llm::TOTAL_TOKENS, usage.total_tokens().to_string())
+            .with_attribute(llm::DURATION_MS, started.elapsed().as_millis().to_string());
+        if let Some(traceparent) = traceparent {
+            let (trace_id, parent_span_id) = parse_traceparent(&traceparent);
+            span_builder = span_builder.with_trace_id(&trace_id);
+            if let Some(parent) = parent_span_id {
+                span_builder = span_builder.with_parent_span_id(&parent);
+            }
+        }
+        trace_collector.record_span(operation_component::LLM, span_builder.build());
+    });
+
+    let mut response = Response::new(full(Bytes::new()));
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    if let Ok(value) = HeaderValue::from_str(&accept_key) {
+        headers.insert(header::SEC_WEBSOCKET_ACCEPT, value);
+    }
+    // the client fails the handshake unless the protocol the upstream picked is echoed
+    if let Some(subprotocol) = subprotocol {
+        headers.insert(header::SEC_WEBSOCKET_PROTOCOL, subprotocol);
+    }
+    Ok(response)
+}
+
+/// Relay frames between the client and upstream until either side closes
+async fn relay<C, U>(
+    client: WebSocketStream<C>,
+    upstream: WebSocketStream<U>,
+    flavor: RealtimeFlavor,
+) -> RealtimeUsage
+where
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    U: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let mut usage = RealtimeUsage::default();
+
+    let client_to_upstream = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            let is_close = message.is_close();
+            if upstream_tx.send(message).await.is_err() || is_close {
+                break;
+            }
+        }
+        let _ = upstream_tx.close().await;
+    };
+
+    let upstream_to_client = async {
+        while let Some(Ok(message)) = upstream_rx.next().await {
+            match &message {
+                Message::Text(text) => usage.observe(flavor, text.as_str()),
+                // Gemini Live sends its JSON events as binary frames
+                Message::Binary(data) => {
+                    if let Ok(text) = std::str::from_utf8(data) {
+                        usage.observe(flavor, text);
+                    }
+                }
+                _ => {}
+            }
+            let is_close = message.is_close();
+            if client_tx.send(message).await.is_err() || is_close {
+                break;
+            }
+        }
+        let _ = client_tx.close().await;
+    };
+
+    tokio::select! {
+        _ = client_to_upstream => debug!("realtime client closed the session"),
+        _ = upstream_to_client => debug!("realtime upstream closed the session"),
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(name: &str, interface: LlmProviderType, model: &str) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            provider_interface: interface,
+            model: Some(model.to_string()),
+            access_key: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_openai_usage_from_response_done() {
+        let mut usage = RealtimeUsage::default();
+        usage.observe(
+            RealtimeFlavor::OpenAI,
+            r#"{"type":"response.audio.delta","delta":"AAAA"}"#,
+        );
+        usage.observe(
+            RealtimeFlavor::OpenAI,
+            r#"{"type":"response.done","response":{"usage":{"input_tokens":120,"output_tokens":40,"total_tokens":160}}}"#,
+        );
+        usage.observe(
+            RealtimeFlavor::OpenAI,
+            r#"{"type":"response.done","response":{"usage":{"input_tokens":10,"output_tokens":5}}}"#,
+        );
+        assert_eq!(usage.input_tokens, 130);
+        assert_eq!(usage.output_tokens, 45);
+        assert_eq!(usage.responses, 2);
+        assert_eq!(usage.total_tokens(), 175);
+    }
+
+    #[test]
+    fn test_gemini_live_usage_metadata() {
+        let mut usage = RealtimeUsage::default();
+        usage.observe(
+            RealtimeFlavor::GeminiLive,
+            r#"{"serverContent":{"turnComplete":true},"usageMetadata":{"promptTokenCount":30,"responseTokenCount":12}}"#,
+        );
+        usage.observe(RealtimeFlavor::GeminiLive, "not json");
+        assert_eq!(usage.input_tokens, 30);
+        assert_eq!(usage.output_tokens, 12);
+    }
+
+    #[test]
+    fn test_realtime_upstream_url() {
+        let openai = provider(
+            "openai/gpt-4o-realtime-preview",
+            LlmProviderType::OpenAI,
+            "gpt-4o-realtime-preview",
+        );
+        assert_eq!(
+            realtime_upstream_url(&openai, RealtimeFlavor::OpenAI, "gpt-4o-realtime-preview")
+                .unwrap(),
+            "wss://api.openai.com/v1/realtime?model=gpt-4o-realtime-preview"
+        );
+
+        let gemini = provider(
+            "gemini/gemini-2.0-flash-live",
+            LlmProviderType::Gemini,
+            "gemini-2.0-flash-live",
+        );
+        assert_eq!(
+            realtime_upstream_url(&gemini, RealtimeFlavor::GeminiLive, "gemini-2.0-flash-live")
+                .unwrap(),
+            format!("wss://{}{}?key=secret", GEMINI_LIVE_HOST, GEMINI_LIVE_PATH)
+        );
+
+        let mut local = openai.clone();
+        local.endpoint = Some("localhost".to_string());
+        local.port = Some(8000);
+        assert_eq!(
+            realtime_upstream_url(&local, RealtimeFlavor::OpenAI, "gpt-4o-realtime-preview")
+                .unwrap(),
+            "wss://localhost:8000/v1/realtime?model=gpt-4o-realtime-preview"
+        );
+
+        let mut azure = provider(
+            "azure_openai/gpt-4o-realtime",
+            LlmProviderType::AzureOpenAI,
+            "gpt-4o-realtime",
+        );
+        assert_eq!(
+            RealtimeFlavor::for_provider(&azure),
+            Some(RealtimeFlavor::AzureOpenAI)
+        );
+        assert_eq!(
+            realtime_upstream_url(&azure, RealtimeFlavor::AzureOpenAI, "gpt-4o-realtime"),
+            None
+        );
+        azure.endpoint = Some("contoso.openai.azure.com".to_string());
+        assert_eq!(
+            realtime_upstream_url(&azure, RealtimeFlavor::AzureOpenAI, "gpt-4o-realtime")
+                .unwrap(),
+            format!(
+                "wss://contoso.openai.azure.com/openai/realtime?api-version={}&deployment=gpt-4o-realtime",
+                AZURE_REALTIME_API_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn test_offered_subprotocols() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(
+                "realtime, openai-insecure-api-key.sk-client, openai-beta.realtime-v1",
+            ),
+        );
+        assert_eq!(
+            offered_subprotocols(&headers),
+            vec!["realtime", "openai-beta.realtime-v1"]
+        );
+        assert!(offered_subprotocols(&HeaderMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_select_realtime_provider() {
+        let mut default = provider("openai/gpt-4o", LlmProviderType::OpenAI, "gpt-4o");
+        default.default = Some(true);
+        let providers = vec![
+            default,
+            provider(
+                "openai/gpt-4o-realtime-preview",
+                LlmProviderType::OpenAI,
+                "gpt-4o-realtime-preview",
+            ),
+        ];
+
+        let selected =
+            select_realtime_provider(&providers, Some("gpt-4o-realtime-preview")).unwrap();
+        assert_eq!(selected.name, "openai/gpt-4o-realtime-preview");
+
+        let fallback = select_realtime_provider(&providers, Some("unknown")).unwrap();
+        assert_eq!(fallback.name, "openai/gpt-4o");
+    }
+}
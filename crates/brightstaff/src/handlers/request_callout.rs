@@ -0,0 +1,280 @@
+use common::configuration::{CalloutFailureMode, RequestCalloutConfig};
+use hermesllm::apis::openai::Message;
+use hermesllm::{ProviderRequest, ProviderRequestType};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+pub const DEFAULT_CALLOUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Status of requests the service rejects without saying how
+const DEFAULT_REJECT_STATUS: StatusCode = StatusCode::FORBIDDEN;
+
+/// The request as the service sees it, messages in the OpenAI shape whatever the
+/// client api
+#[derive(Debug, Serialize)]
+pub struct CalloutRequest<'a> {
+    pub request_id: &'a str,
+    /// Path of the client api, e.g. `/v1/chat/completions`
+    pub api: &'a str,
+    pub model: &'a str,
+    pub messages: Vec<Message>,
+    pub metadata: &'a Option<HashMap<String, Value>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalloutAction {
+    #[default]
+    Continue,
+    Reject,
+}
+
+/// Answer of the service. Fields left out keep the request as it is.
+#[derive(Debug, Default, Deserialize)]
+pub struct CalloutResponse {
+    #[serde(default)]
+    pub action: CalloutAction,
+    pub model: Option<String>,
+    pub messages: Option<Vec<Message>>,
+    /// Status of a rejected request, defaults to 403
+    pub status: Option<u16>,
+    /// Body of a rejected request
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum CalloutError {
+    #[error("request callout failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("request callout returned {status}: {body}")]
+    Status { status: u16, body: String },
+}
+
+/// What happens to the request after the callout
+#[derive(Debug, PartialEq)]
+pub enum CalloutOutcome {
+    Continue,
+    Reject { status: StatusCode, message: String },
+}
+
+/// Sends every llm request to a user service that can rewrite its model and messages
+/// or reject it, before the request is routed
+pub struct RequestCallout {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    failure_mode: CalloutFailureMode,
+}
+
+impl RequestCallout {
+    pub fn new(config: &RequestCalloutConfig) -> Self {
+        RequestCallout {
+            client: reqwest::Client::new(),
+            url: config.url.clone(),
+            timeout: config
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_CALLOUT_TIMEOUT),
+            failure_mode: config.failure_mode.unwrap_or_default(),
+        }
+    }
+
+    async fn call(&self, request: &CalloutRequest<'_>) -> Result<CalloutResponse, CalloutError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(request)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(CalloutError::Status {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Applies the answer of the service to `request`. Failures of the service follow
+    /// the failure mode.
+    pub async fn process(
+        &self,
+        request_id: &str,
+        api: &str,
+        request: &mut ProviderRequestType,
+    ) -> CalloutOutcome {
+        let callout_request = CalloutRequest {
+            request_id,
+            api,
+            model: request.model(),
+            messages: request.get_messages(),
+            metadata: request.metadata(),
+        };
+        let response = match self.call(&callout_request).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] | REQUEST_CALLOUT | {} ({:?})",
+                    request_id, err, self.failure_mode
+                );
+                return match self.failure_mode {
+                    CalloutFailureMode::FailOpen => CalloutOutcome::Continue,
+                    CalloutFailureMode::FailClosed => CalloutOutcome::Reject {
+                        status: StatusCode::SERVICE_UNAVAILABLE,
+                        message: "request callout unavailable".to_string(),
+                    },
+                };
+            }
+        };
+        apply_callout_response(request_id, response, request)
+    }
+}
+
+fn apply_callout_response(
+    request_id: &str,
+    response: CalloutResponse,
+    request: &mut ProviderRequestType,
+) -> CalloutOutcome {
+    if response.action == CalloutAction::Reject {
+        let status = response
+            .status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .filter(|status| status.is_client_error() || status.is_server_error())
+            .unwrap_or(DEFAULT_REJECT_STATUS);
+        info!(
+            "[PLANO_REQ_ID:{}] | REQUEST_CALLOUT | rejected with {}",
+            request_id, status
+        );
+        return CalloutOutcome::Reject {
+            status,
+            message: response
+                .message
+                .unwrap_or_else(|| "request rejected by policy".to_string()),
+        };
+    }
+    if let Some(model) = response.model {
+        info!(
+            "[PLANO_REQ_ID:{}] | REQUEST_CALLOUT | model {} -> {}",
+            request_id,
+            request.model(),
+            model
+        );
+        request.set_model(model);
+    }
+    if let Some(messages) = response.messages {
+        request.set_messages(&messages);
+    }
+    CalloutOutcome::Continue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::ChatCompletionsRequest;
+
+    fn chat_request() -> ProviderRequestType {
+        let request: ChatCompletionsRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "my card is 4111 1111 1111 1111"}],
+            "metadata": {"team": "search"}
+        }))
+        .unwrap();
+        ProviderRequestType::ChatCompletionsRequest(request)
+    }
+
+    fn callout(url: &str, failure_mode: Option<CalloutFailureMode>) -> RequestCallout {
+        RequestCallout::new(&RequestCalloutConfig {
+            url: url.to_string(),
+            timeout_ms: None,
+            failure_mode,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_callout_rewrites_request() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/check")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "request_id": "req-1",
+                "api": "/v1/chat/completions",
+                "model": "gpt-4o",
+                "metadata": {"team": "search"}
+            })))
+            .with_body(
+                r#"{"action":"continue","model":"gpt-4o-mini","messages":[{"role":"user","content":"my card is [CARD]"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut request = chat_request();
+        let outcome = callout(&format!("{}/check", server.url()), None)
+            .process("req-1", "/v1/chat/completions", &mut request)
+            .await;
+        mock.assert_async().await;
+        assert_eq!(outcome, CalloutOutcome::Continue);
+        assert_eq!(request.model(), "gpt-4o-mini");
+        assert_eq!(
+            request.get_recent_user_message().as_deref(),
+            Some("my card is [CARD]")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_callout_rejects_request() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/check")
+            .with_body(r#"{"action":"reject","status":451,"message":"blocked by policy"}"#)
+            .create_async()
+            .await;
+
+        let outcome = callout(&format!("{}/check", server.url()), None)
+            .process("req-1", "/v1/chat/completions", &mut chat_request())
+            .await;
+        assert_eq!(
+            outcome,
+            CalloutOutcome::Reject {
+                status: StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                message: "blocked by policy".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_callout_failure_modes() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/check")
+            .with_status(500)
+            .expect(2)
+            .create_async()
+            .await;
+        let url = format!("{}/check", server.url());
+
+        let mut request = chat_request();
+        let outcome = callout(&url, Some(CalloutFailureMode::FailOpen))
+            .process("req-1", "/v1/chat/completions", &mut request)
+            .await;
+        assert_eq!(outcome, CalloutOutcome::Continue);
+        assert_eq!(request.model(), "gpt-4o");
+
+        let outcome = callout(&url, None)
+            .process("req-1", "/v1/chat/completions", &mut request)
+            .await;
+        assert!(matches!(
+            outcome,
+            CalloutOutcome::Reject {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                ..
+            }
+        ));
+    }
+}
@@ -1,12 +1,16 @@
 use bytes::Bytes;
+use hermesllm::apis::streaming_shapes::sse::{SseStreamBuffer, SseStreamBufferTrait};
+use hermesllm::apis::streaming_shapes::sse_chunk_processor::SseChunkProcessor;
 use hermesllm::apis::OpenAIApi;
 use hermesllm::clients::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
+use hermesllm::providers::response::ProviderResponseType;
 use hermesllm::SseEvent;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Full, StreamBody};
 use hyper::body::Frame;
 use hyper::{Response, StatusCode};
-use tokio::sync::mpsc;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tracing::{info, warn};
@@ -45,6 +49,22 @@ impl ResponseHandler {
         response
     }
 
+    /// Create a JSON response with the given status code
+    pub fn create_json_response<T: Serialize>(
+        status: StatusCode,
+        body: &T,
+    ) -> Response<BoxBody<Bytes, hyper::Error>> {
+        let mut response = Response::new(Self::create_full_body(
+            serde_json::to_string(body).unwrap_or_default(),
+        ));
+        *response.status_mut() = status;
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            "application/json".parse().unwrap(),
+        );
+        response
+    }
+
     /// Create a bad request response
     pub fn create_bad_request(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
         Self::create_error_response(StatusCode::BAD_REQUEST, message)
@@ -117,6 +137,65 @@ impl ResponseHandler {
             .map_err(ResponseError::from)
     }
 
+    /// Stream a response to the client like `create_streaming_response`, and send the
+    /// text of the whole response on the returned channel once the stream completes.
+    /// Nothing is sent if the stream fails or the client goes away.
+    pub async fn create_streaming_response_with_capture(
+        &self,
+        llm_response: reqwest::Response,
+    ) -> Result<
+        (
+            Response<BoxBody<Bytes, hyper::Error>>,
+            oneshot::Receiver<String>,
+        ),
+        ResponseError,
+    > {
+        let is_sse_streaming = is_sse_response(&llm_response);
+        let mut response_builder = Response::builder();
+        let headers = response_builder.headers_mut().ok_or_else(|| {
+            ResponseError::StreamError("Failed to get mutable headers".to_string())
+        })?;
+        for (header_name, header_value) in llm_response.headers().iter() {
+            headers.insert(header_name, header_value.clone());
+        }
+
+        let (tx, rx) = mpsc::channel::<Bytes>(16);
+        let (text_tx, text_rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let mut byte_stream = llm_response.bytes_stream();
+            let mut captured = Vec::new();
+
+            while let Some(item) = byte_stream.next().await {
+                let chunk = match item {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        warn!("Error receiving chunk: {:?}", err);
+                        return;
+                    }
+                };
+                captured.extend_from_slice(&chunk);
+                if tx.send(chunk).await.is_err() {
+                    warn!("Receiver dropped");
+                    return;
+                }
+            }
+
+            match Self::response_text(&captured, is_sse_streaming) {
+                Ok(text) => {
+                    let _ = text_tx.send(text);
+                }
+                Err(err) => warn!("Failed to capture response text: {}", err),
+            }
+        });
+
+        let stream = ReceiverStream::new(rx).map(|chunk| Ok::<_, hyper::Error>(Frame::data(chunk)));
+        let response = response_builder
+            .body(BoxBody::new(StreamBody::new(stream)))
+            .map_err(ResponseError::from)?;
+        Ok((response, text_rx))
+    }
+
     /// Collect the full response body as a string
     /// This is used for intermediate agents where we need to capture the full response
     /// before passing it to the next agent.
@@ -128,25 +207,30 @@ impl ResponseHandler {
         &self,
         llm_response: reqwest::Response,
     ) -> Result<String, ResponseError> {
-        use hermesllm::apis::streaming_shapes::sse::SseStreamIter;
-
-        let response_headers = llm_response.headers();
-        let is_sse_streaming = response_headers
-            .get(hyper::header::CONTENT_TYPE)
-            .is_some_and(|v| v.to_str().unwrap_or("").contains("text/event-stream"));
+        let is_sse_streaming = is_sse_response(&llm_response);
 
         let response_bytes = llm_response
             .bytes()
             .await
             .map_err(|e| ResponseError::StreamError(format!("Failed to read response: {}", e)))?;
 
+        Self::response_text(&response_bytes, is_sse_streaming)
+    }
+
+    /// Text of a response body, accumulating the content deltas of SSE responses
+    fn response_text(
+        response_bytes: &[u8],
+        is_sse_streaming: bool,
+    ) -> Result<String, ResponseError> {
+        use hermesllm::apis::streaming_shapes::sse::SseStreamIter;
+
         if is_sse_streaming {
             let client_api =
                 SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
             let upstream_api =
                 SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
 
-            let sse_iter = SseStreamIter::try_from(response_bytes.as_ref()).unwrap();
+            let sse_iter = SseStreamIter::try_from(response_bytes).unwrap();
             let mut accumulated_text = String::new();
 
             for sse_event in sse_iter {
@@ -184,6 +268,116 @@ impl ResponseHandler {
     }
 }
 
+/// Converts a response of the upstream api, e.g. an agent serving Anthropic's messages
+/// api, to the client's api with the same conversions llm_gateway applies to provider
+/// responses. Error responses and responses already in the client's api are returned as
+/// they are.
+pub fn normalize_response(
+    response: reqwest::Response,
+    client_api: &SupportedAPIsFromClient,
+    upstream_api: &SupportedUpstreamAPIs,
+) -> reqwest::Response {
+    if !response.status().is_success() || is_same_api(client_api, upstream_api) {
+        return response;
+    }
+    let is_sse_streaming = is_sse_response(&response);
+    let mut builder = hyper::Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        if name != hyper::header::CONTENT_LENGTH {
+            builder = builder.header(name, value);
+        }
+    }
+    let client_api = client_api.clone();
+    let upstream_api = upstream_api.clone();
+
+    let body = if is_sse_streaming {
+        let (tx, rx) = mpsc::channel::<Result<Bytes, ResponseError>>(16);
+        tokio::spawn(async move {
+            let mut byte_stream = response.bytes_stream();
+            let mut processor = SseChunkProcessor::new();
+            let mut buffer = match SseStreamBuffer::try_from((&client_api, &upstream_api)) {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    let _ = tx
+                        .send(Err(ResponseError::StreamError(err.to_string())))
+                        .await;
+                    return;
+                }
+            };
+            loop {
+                let chunk = byte_stream.next().await;
+                let is_end = chunk.is_none();
+                let events = match chunk {
+                    Some(Ok(chunk)) => processor.process_chunk(&chunk, &client_api, &upstream_api),
+                    Some(Err(err)) => Err(err.to_string()),
+                    // the last event may not end with a blank line
+                    None => processor.flush(&client_api, &upstream_api),
+                };
+                match events {
+                    Ok(events) => {
+                        for event in events {
+                            buffer.add_transformed_event(event);
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Failed to convert response stream: {}", err);
+                        let _ = tx.send(Err(ResponseError::StreamError(err))).await;
+                        return;
+                    }
+                }
+                let bytes = buffer.to_bytes();
+                if !bytes.is_empty() && tx.send(Ok(Bytes::from(bytes))).await.is_err() {
+                    warn!("Receiver dropped");
+                    return;
+                }
+                if is_end {
+                    return;
+                }
+            }
+        });
+        reqwest::Body::wrap_stream(ReceiverStream::new(rx))
+    } else {
+        reqwest::Body::wrap_stream(futures::stream::once(async move {
+            let bytes = response.bytes().await?;
+            let converted =
+                ProviderResponseType::try_from((&bytes[..], &client_api, &upstream_api))
+                    .and_then(|converted| serde_json::to_vec(&converted).map_err(Into::into));
+            match converted {
+                Ok(converted) => Ok::<_, reqwest::Error>(Bytes::from(converted)),
+                Err(err) => {
+                    warn!("Failed to convert response: {}", err);
+                    Ok(bytes)
+                }
+            }
+        }))
+    };
+
+    reqwest::Response::from(builder.body(body).expect("headers of a valid response"))
+}
+
+fn is_same_api(client_api: &SupportedAPIsFromClient, upstream_api: &SupportedUpstreamAPIs) -> bool {
+    matches!(
+        (client_api, upstream_api),
+        (
+            SupportedAPIsFromClient::OpenAIChatCompletions(_),
+            SupportedUpstreamAPIs::OpenAIChatCompletions(_)
+        ) | (
+            SupportedAPIsFromClient::AnthropicMessagesAPI(_),
+            SupportedUpstreamAPIs::AnthropicMessagesAPI(_)
+        ) | (
+            SupportedAPIsFromClient::OpenAIResponsesAPI(_),
+            SupportedUpstreamAPIs::OpenAIResponsesAPI(_)
+        )
+    )
+}
+
+fn is_sse_response(llm_response: &reqwest::Response) -> bool {
+    llm_response
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .is_some_and(|v| v.to_str().unwrap_or("").contains("text/event-stream"))
+}
+
 impl Default for ResponseHandler {
     fn default() -> Self {
         Self::new()
@@ -193,6 +387,7 @@ impl Default for ResponseHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hermesllm::apis::anthropic::AnthropicApi;
     use hyper::StatusCode;
 
     #[test]
@@ -257,4 +452,30 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
         assert!(response.headers().contains_key("content-type"));
     }
+    #[tokio::test]
+    async fn test_normalize_response() {
+        let message = serde_json::json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4",
+            "content": [{"type": "text", "text": "hello"}],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 3, "output_tokens": 1}
+        });
+        let response = hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(message.to_string())
+            .unwrap();
+        let response = normalize_response(
+            reqwest::Response::from(response),
+            &SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+            &SupportedUpstreamAPIs::AnthropicMessagesAPI(AnthropicApi::Messages),
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["choices"][0]["message"]["content"], "hello");
+    }
 }
@@ -0,0 +1,729 @@
+//! Built-in retrieval filter
+//!
+//! Filters with `type: retrieval` are run by brightstaff itself: the last user message is
+//! embedded with the configured embeddings model, the closest chunks are fetched from a
+//! Qdrant, pgvector or Pinecone vector store and added to the conversation as a context
+//! message ahead of that user message. The sources of the chunks are returned to the
+//! client in the reasoning content of the terminal agent response.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use bytes::Bytes;
+use common::configuration::{RetrievalConfig, VectorStoreType};
+use common::consts::{ARCH_PROVIDER_HINT_HEADER, EMBEDDINGS_PATH};
+use futures::StreamExt;
+use hermesllm::apis::openai::{Message, MessageContent, Role};
+use hyper::header;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio_postgres::NoTls;
+use tracing::{debug, warn};
+
+pub const DEFAULT_TOP_K: usize = 4;
+const DEFAULT_TEXT_FIELD: &str = "text";
+const DEFAULT_SOURCE_FIELD: &str = "source";
+
+#[derive(Debug, Error)]
+pub enum RetrievalError {
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("{service} request failed with HTTP {status}: {body}")]
+    Upstream {
+        service: &'static str,
+        status: u16,
+        body: String,
+    },
+    #[error("failed to parse {0} response")]
+    InvalidResponse(&'static str),
+    #[error("pgvector query failed: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    #[error("invalid pgvector identifier '{0}'")]
+    InvalidIdentifier(String),
+    #[error("collection is required for {0:?}")]
+    MissingCollection(VectorStoreType),
+    #[error("retrieval settings are missing")]
+    MissingSettings,
+}
+
+impl RetrievalError {
+    /// Transient failures, retried like failures of other filters
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RetrievalError::Request(err) => err.is_connect() || err.is_timeout(),
+            RetrievalError::Upstream { status, .. } => *status >= 500,
+            RetrievalError::Postgres(err) => err.is_closed(),
+            _ => false,
+        }
+    }
+}
+
+/// Chunk of a document fetched from the vector store
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RetrievedChunk {
+    pub id: String,
+    pub score: f32,
+    pub text: String,
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+pub struct Retriever {
+    client: reqwest::Client,
+    embeddings_url: String,
+}
+
+impl Retriever {
+    pub fn new(client: reqwest::Client, llm_provider_url: &str) -> Self {
+        Retriever {
+            client,
+            embeddings_url: format!("{}{}", llm_provider_url, EMBEDDINGS_PATH),
+        }
+    }
+
+    /// Fetches the chunks closest to `query` from the vector store at `url`
+    pub async fn retrieve(
+        &self,
+        url: &str,
+        config: &RetrievalConfig,
+        query: &str,
+        trace_parent: Option<&str>,
+    ) -> Result<Vec<RetrievedChunk>, RetrievalError> {
+        let embedding = self.embed(config, query, trace_parent).await?;
+        let top_k = config.top_k.unwrap_or(DEFAULT_TOP_K);
+        let mut chunks = match config.vector_store {
+            VectorStoreType::Qdrant => self.search_qdrant(url, config, &embedding, top_k).await?,
+            VectorStoreType::Pinecone => {
+                self.search_pinecone(url, config, &embedding, top_k).await?
+            }
+            VectorStoreType::Pgvector => search_pgvector(url, config, &embedding, top_k).await?,
+        };
+        if let Some(threshold) = config.score_threshold {
+            chunks.retain(|chunk| chunk.score >= threshold);
+        }
+        chunks.truncate(top_k);
+        Ok(chunks)
+    }
+
+    async fn embed(
+        &self,
+        config: &RetrievalConfig,
+        query: &str,
+        trace_parent: Option<&str>,
+    ) -> Result<Vec<f32>, RetrievalError> {
+        let mut request = self
+            .client
+            .post(&self.embeddings_url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&EmbeddingsRequest {
+                model: &config.embedding_model,
+                input: vec![query],
+            });
+        if let Some(provider) = config.embedding_provider.as_deref() {
+            request = request.header(ARCH_PROVIDER_HINT_HEADER, provider);
+        }
+        if let Some(trace_parent) = trace_parent {
+            request = request.header("traceparent", trace_parent);
+        }
+        let response: EmbeddingsResponse = send("embeddings", request).await?;
+        response
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .ok_or(RetrievalError::InvalidResponse("embeddings"))
+    }
+
+    async fn search_qdrant(
+        &self,
+        url: &str,
+        config: &RetrievalConfig,
+        embedding: &[f32],
+        top_k: usize,
+    ) -> Result<Vec<RetrievedChunk>, RetrievalError> {
+        let collection = config
+            .collection
+            .as_deref()
+            .ok_or(RetrievalError::MissingCollection(VectorStoreType::Qdrant))?;
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/collections/{}/points/search",
+                url.trim_end_matches('/'),
+                collection
+            ))
+            .json(&serde_json::json!({
+                "vector": embedding,
+                "limit": top_k,
+                "with_payload": true,
+            }));
+        if let Some(api_key) = config.api_key.as_deref() {
+            request = request.header("api-key", api_key);
+        }
+        let response: Value = send("qdrant", request).await?;
+        let points = response
+            .get("result")
+            .and_then(Value::as_array)
+            .ok_or(RetrievalError::InvalidResponse("qdrant"))?;
+        Ok(points
+            .iter()
+            .filter_map(|point| chunk_from_match(point, "payload", config))
+            .collect())
+    }
+
+    async fn search_pinecone(
+        &self,
+        url: &str,
+        config: &RetrievalConfig,
+        embedding: &[f32],
+        top_k: usize,
+    ) -> Result<Vec<RetrievedChunk>, RetrievalError> {
+        let mut body = serde_json::json!({
+            "vector": embedding,
+            "topK": top_k,
+            "includeMetadata": true,
+        });
+        if let Some(namespace) = config.collection.as_deref() {
+            body["namespace"] = Value::from(namespace);
+        }
+        let mut request = self
+            .client
+            .post(format!("{}/query", url.trim_end_matches('/')))
+            .json(&body);
+        if let Some(api_key) = config.api_key.as_deref() {
+            request = request.header("Api-Key", api_key);
+        }
+        let response: Value = send("pinecone", request).await?;
+        let matches = response
+            .get("matches")
+            .and_then(Value::as_array)
+            .ok_or(RetrievalError::InvalidResponse("pinecone"))?;
+        Ok(matches
+            .iter()
+            .filter_map(|found| chunk_from_match(found, "metadata", config))
+            .collect())
+    }
+}
+
+async fn send<T: serde::de::DeserializeOwned>(
+    service: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<T, RetrievalError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(RetrievalError::Upstream {
+            service,
+            status: status.as_u16(),
+            body: response.text().await.unwrap_or_default(),
+        });
+    }
+    response
+        .json()
+        .await
+        .map_err(|_| RetrievalError::InvalidResponse(service))
+}
+
+/// Chunk of a Qdrant point or Pinecone match, None when it carries no text
+fn chunk_from_match(
+    found: &Value,
+    fields: &str,
+    config: &RetrievalConfig,
+) -> Option<RetrievedChunk> {
+    let fields = found.get(fields)?;
+    let text = fields
+        .get(config.text_field.as_deref().unwrap_or(DEFAULT_TEXT_FIELD))?
+        .as_str()?;
+    let id = match found.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(id) => id.to_string(),
+        None => String::new(),
+    };
+    Some(RetrievedChunk {
+        id,
+        score: found
+            .get("score")
+            .and_then(Value::as_f64)
+            .unwrap_or_default() as f32,
+        text: text.to_string(),
+        source: fields
+            .get(
+                config
+                    .source_field
+                    .as_deref()
+                    .unwrap_or(DEFAULT_SOURCE_FIELD),
+            )
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// Connections to pgvector databases, filters are run by pipeline processors created
+/// per request so connections have to outlive them
+fn pgvector_clients() -> &'static tokio::sync::Mutex<HashMap<String, Arc<tokio_postgres::Client>>> {
+    static CLIENTS: OnceLock<tokio::sync::Mutex<HashMap<String, Arc<tokio_postgres::Client>>>> =
+        OnceLock::new();
+    CLIENTS.get_or_init(Default::default)
+}
+
+async fn pgvector_client(
+    connection_string: &str,
+) -> Result<Arc<tokio_postgres::Client>, RetrievalError> {
+    let mut clients = pgvector_clients().lock().await;
+    if let Some(client) = clients
+        .get(connection_string)
+        .filter(|client| !client.is_closed())
+    {
+        return Ok(client.clone());
+    }
+    let (client, connection) = tokio_postgres::connect(connection_string, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            warn!("pgvector connection error: {}", err);
+        }
+    });
+    let client = Arc::new(client);
+    clients.insert(connection_string.to_string(), client.clone());
+    Ok(client)
+}
+
+/// Quotes a table or column name, optionally schema qualified
+fn quote_identifier(name: &str) -> Result<String, RetrievalError> {
+    let valid = name.split('.').count() <= 2
+        && name.split('.').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+    if !valid {
+        return Err(RetrievalError::InvalidIdentifier(name.to_string()));
+    }
+    Ok(name
+        .split('.')
+        .map(|part| format!("\"{}\"", part))
+        .collect::<Vec<_>>()
+        .join("."))
+}
+
+/// Nearest neighbours by cosine distance, the table has `id` and `embedding` columns
+/// next to the text and source columns
+fn pgvector_query(config: &RetrievalConfig) -> Result<String, RetrievalError> {
+    let table = config
+        .collection
+        .as_deref()
+        .ok_or(RetrievalError::MissingCollection(VectorStoreType::Pgvector))?;
+    Ok(format!(
+        "SELECT id::text, {text}::text, {source}::text, (1 - (embedding <=> $1::text::vector))::float8 \
+         FROM {table} ORDER BY embedding <=> $1::text::vector LIMIT $2",
+        text = quote_identifier(config.text_field.as_deref().unwrap_or(DEFAULT_TEXT_FIELD))?,
+        source = quote_identifier(config.source_field.as_deref().unwrap_or(DEFAULT_SOURCE_FIELD))?,
+        table = quote_identifier(table)?,
+    ))
+}
+
+async fn search_pgvector(
+    connection_string: &str,
+    config: &RetrievalConfig,
+    embedding: &[f32],
+    top_k: usize,
+) -> Result<Vec<RetrievedChunk>, RetrievalError> {
+    let query = pgvector_query(config)?;
+    let vector = format!(
+        "[{}]",
+        embedding
+            .iter()
+            .map(f32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let client = pgvector_client(connection_string).await?;
+    let rows = client.query(&query, &[&vector, &(top_k as i64)]).await?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            Some(RetrievedChunk {
+                id: row.get::<_, Option<String>>(0).unwrap_or_default(),
+                text: row.get::<_, Option<String>>(1)?,
+                source: row.get(2),
+                score: row.get::<_, f64>(3) as f32,
+            })
+        })
+        .collect())
+}
+
+/// Text the retrieval is done for, the last user message
+pub fn retrieval_query(messages: &[Message]) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .find(|message| message.role == Role::User)
+        .map(|message| message.content.to_string())
+        .filter(|query| !query.trim().is_empty())
+}
+
+fn source_label(chunk: &RetrievedChunk) -> &str {
+    chunk.source.as_deref().unwrap_or(&chunk.id)
+}
+
+/// Adds the chunks as a context message right before the last user message
+pub fn add_context(messages: &[Message], chunks: &[RetrievedChunk]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    if chunks.is_empty() {
+        return messages;
+    }
+    let mut context = String::from(
+        "Use the following context to answer. Cite the sources you use by their number, e.g. [1].",
+    );
+    for (index, chunk) in chunks.iter().enumerate() {
+        context.push_str(&format!(
+            "\n\n[{}] ({})\n{}",
+            index + 1,
+            source_label(chunk),
+            chunk.text
+        ));
+    }
+    let position = messages
+        .iter()
+        .rposition(|message| message.role == Role::User)
+        .unwrap_or(messages.len());
+    messages.insert(
+        position,
+        Message {
+            role: Role::System,
+            content: MessageContent::Text(context),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        },
+    );
+    messages
+}
+
+/// Numbered sources of the chunks, matching the numbers of the context message
+pub fn citations(chunks: &[RetrievedChunk]) -> String {
+    let mut citations = String::from("Sources:");
+    for (index, chunk) in chunks.iter().enumerate() {
+        citations.push_str(&format!(
+            "\n[{}] {} (score {:.2})",
+            index + 1,
+            source_label(chunk),
+            chunk.score
+        ));
+    }
+    citations
+}
+
+/// Adds the citations to the reasoning content of the agent response: as a first chunk
+/// of streamed responses, or to the message of non streaming chat completions. Other
+/// responses are returned unchanged.
+pub async fn with_citations(
+    response: reqwest::Response,
+    citations: &str,
+) -> Result<reqwest::Response, reqwest::Error> {
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let mut builder = hyper::Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_LENGTH {
+            builder = builder.header(name, value);
+        }
+    }
+
+    let body = if content_type.starts_with("text/event-stream") {
+        let chunk = serde_json::json!({
+            "id": "retrieval",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "",
+            "choices": [{
+                "index": 0,
+                "delta": {"role": "assistant", "reasoning_content": citations},
+                "finish_reason": null,
+            }],
+        });
+        let first = Bytes::from(format!("data: {}\n\n", chunk));
+        reqwest::Body::wrap_stream(
+            futures::stream::once(async move { Ok::<_, reqwest::Error>(first) })
+                .chain(response.bytes_stream()),
+        )
+    } else if content_type.starts_with("application/json") {
+        let bytes = response.bytes().await?;
+        match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut body) => {
+                if let Some(message) = body.pointer_mut("/choices/0/message") {
+                    let reasoning = match message.get("reasoning_content").and_then(Value::as_str) {
+                        Some(existing) if !existing.is_empty() => {
+                            format!("{}\n\n{}", existing, citations)
+                        }
+                        _ => citations.to_string(),
+                    };
+                    message["reasoning_content"] = Value::from(reasoning);
+                }
+                reqwest::Body::from(body.to_string())
+            }
+            Err(_) => {
+                debug!("agent response is not json, citations not added");
+                reqwest::Body::from(bytes)
+            }
+        }
+    } else {
+        return Ok(response);
+    };
+
+    Ok(reqwest::Response::from(
+        builder.body(body).expect("headers of a valid response"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+
+    fn config(vector_store: VectorStoreType) -> RetrievalConfig {
+        RetrievalConfig {
+            vector_store,
+            collection: Some("docs".to_string()),
+            embedding_model: "text-embedding-3-small".to_string(),
+            embedding_provider: None,
+            top_k: Some(2),
+            score_threshold: Some(0.5),
+            api_key: Some("secret".to_string()),
+            text_field: None,
+            source_field: None,
+        }
+    }
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    fn chunk(id: &str, source: Option<&str>) -> RetrievedChunk {
+        RetrievedChunk {
+            id: id.to_string(),
+            score: 0.8,
+            text: format!("text of {}", id),
+            source: source.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_from_qdrant() {
+        let mut server = Server::new_async().await;
+        let embeddings = server
+            .mock("POST", EMBEDDINGS_PATH)
+            .with_body(r#"{"data":[{"index":0,"embedding":[0.1,0.2]}]}"#)
+            .create_async()
+            .await;
+        let search = server
+            .mock("POST", "/collections/docs/points/search")
+            .match_header("api-key", "secret")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"limit":2,"with_payload":true}"#.to_string(),
+            ))
+            .with_body(
+                r#"{"result":[
+                    {"id":1,"score":0.9,"payload":{"text":"refunds take 5 days","source":"refunds.md"}},
+                    {"id":"b","score":0.7,"payload":{"source":"empty.md"}},
+                    {"id":"c","score":0.3,"payload":{"text":"unrelated"}}
+                ]}"#,
+            )
+            .create_async()
+            .await;
+
+        let retriever = Retriever::new(reqwest::Client::new(), &server.url());
+        let chunks = retriever
+            .retrieve(
+                &server.url(),
+                &config(VectorStoreType::Qdrant),
+                "how long do refunds take?",
+                None,
+            )
+            .await
+            .unwrap();
+        embeddings.assert_async().await;
+        search.assert_async().await;
+        assert_eq!(
+            chunks,
+            vec![RetrievedChunk {
+                id: "1".to_string(),
+                score: 0.9,
+                text: "refunds take 5 days".to_string(),
+                source: Some("refunds.md".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_from_pinecone() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", EMBEDDINGS_PATH)
+            .with_body(r#"{"data":[{"index":0,"embedding":[0.1,0.2]}]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/query")
+            .match_header("Api-Key", "secret")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"topK":2,"namespace":"docs"}"#.to_string(),
+            ))
+            .with_body(r#"{"matches":[{"id":"a","score":0.8,"metadata":{"text":"hello"}}]}"#)
+            .create_async()
+            .await;
+
+        let retriever = Retriever::new(reqwest::Client::new(), &server.url());
+        let chunks = retriever
+            .retrieve(
+                &server.url(),
+                &config(VectorStoreType::Pinecone),
+                "hi",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].source, None);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_embeddings_error() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", EMBEDDINGS_PATH)
+            .with_status(503)
+            .create_async()
+            .await;
+        let retriever = Retriever::new(reqwest::Client::new(), &server.url());
+        let err = retriever
+            .retrieve(&server.url(), &config(VectorStoreType::Qdrant), "hi", None)
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_pgvector_query() {
+        let mut config = config(VectorStoreType::Pgvector);
+        config.collection = Some("rag.chunks".to_string());
+        assert_eq!(
+            pgvector_query(&config).unwrap(),
+            "SELECT id::text, \"text\"::text, \"source\"::text, (1 - (embedding <=> $1::text::vector))::float8 \
+             FROM \"rag\".\"chunks\" ORDER BY embedding <=> $1::text::vector LIMIT $2"
+        );
+        config.collection = Some("chunks; drop table users".to_string());
+        assert!(matches!(
+            pgvector_query(&config),
+            Err(RetrievalError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_context_before_last_user_message() {
+        let messages = vec![
+            message(Role::User, "hi"),
+            message(Role::Assistant, "hello"),
+            message(Role::User, "how long do refunds take?"),
+        ];
+        assert_eq!(
+            retrieval_query(&messages).as_deref(),
+            Some("how long do refunds take?")
+        );
+
+        let chunks = vec![chunk("1", Some("refunds.md")), chunk("2", None)];
+        let updated = add_context(&messages, &chunks);
+        assert_eq!(updated.len(), 4);
+        assert_eq!(updated[2].role, Role::System);
+        assert!(updated[2]
+            .content
+            .to_string()
+            .ends_with("[1] (refunds.md)\ntext of 1\n\n[2] (2)\ntext of 2"));
+        assert_eq!(updated[3].content.to_string(), "how long do refunds take?");
+        assert_eq!(
+            citations(&chunks),
+            "Sources:\n[1] refunds.md (score 0.80)\n[2] 2 (score 0.80)"
+        );
+        assert_eq!(add_context(&messages, &[]).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_with_citations() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/json")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"5 days [1]"}}]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/sse")
+            .with_header("content-type", "text/event-stream")
+            .with_body("data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"5 days\"}}]}\n\ndata: [DONE]\n\n")
+            .create_async()
+            .await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(format!("{}/json", server.url()))
+            .send()
+            .await
+            .unwrap();
+        let body: Value = with_citations(response, "Sources:\n[1] refunds.md")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            body["choices"][0]["message"]["reasoning_content"],
+            "Sources:\n[1] refunds.md"
+        );
+
+        let response = client
+            .post(format!("{}/sse", server.url()))
+            .send()
+            .await
+            .unwrap();
+        let body = with_citations(response, "Sources:\n[1] refunds.md")
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let events: Vec<&str> = body.split("\n\n").filter(|e| !e.is_empty()).collect();
+        assert_eq!(events.len(), 3);
+        assert!(events[0].contains(r#""reasoning_content":"Sources:\n[1] refunds.md""#));
+        assert!(events[1].contains("5 days"));
+    }
+}
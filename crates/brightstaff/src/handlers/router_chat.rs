@@ -1,5 +1,5 @@
 use common::configuration::ModelUsagePreference;
-use common::consts::REQUEST_ID_HEADER;
+use common::consts::ARCH_REQUEST_ID_HEADER;
 use common::traces::{parse_traceparent, SpanBuilder, SpanKind, TraceCollector};
 use hermesllm::clients::endpoints::SupportedUpstreamAPIs;
 use hermesllm::{ProviderRequest, ProviderRequestType};
@@ -45,7 +45,7 @@ pub async fn router_chat_get_upstream_model(
     // Clone metadata for routing before converting (which consumes client_request)
     let routing_metadata = client_request.metadata().clone();
     let request_id = request_headers
-        .get(REQUEST_ID_HEADER)
+        .get(ARCH_REQUEST_ID_HEADER)
         .and_then(|value| value.to_str().ok())
         .unwrap_or("unknown");
 
@@ -59,7 +59,8 @@ pub async fn router_chat_get_upstream_model(
             ProviderRequestType::MessagesRequest(_)
             | ProviderRequestType::BedrockConverse(_)
             | ProviderRequestType::BedrockConverseStream(_)
-            | ProviderRequestType::ResponsesAPIRequest(_),
+            | ProviderRequestType::ResponsesAPIRequest(_)
+            | ProviderRequestType::GeminiGenerateContent(_),
         ) => {
             warn!("Unexpected: got non-ChatCompletions request after converting to OpenAI format");
             return Err(RoutingError::internal_error(
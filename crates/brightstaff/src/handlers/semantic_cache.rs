@@ -0,0 +1,760 @@
+//! Semantic cache of completions
+//!
+//! Model aliases with a `semantic_cache` embed the prompt of every request and answer it
+//! with the completion of an earlier request whose prompt is similar enough, without
+//! calling the model. Prompts are only compared to prompts of the same alias, client api,
+//! tenant and listener, sent with the same parameters (streaming, tools, sampling,
+//! response format, end user...), so cached responses are only replayed to requests that
+//! would have gotten them and as they were received.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use common::configuration::{ModelAlias, SemanticCacheConfig};
+use common::consts::{
+    ARCH_LISTENER_NAME_HEADER, ARCH_PROVIDER_HINT_HEADER, ARCH_SEMANTIC_CACHE_HEADER,
+    EMBEDDINGS_PATH,
+};
+use hermesllm::{ProviderRequest, ProviderRequestType};
+use http_body_util::combinators::BoxBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::response_handler::ResponseHandler;
+use crate::utils::shutdown::{read_snapshot, write_snapshot};
+
+pub const DEFAULT_SIMILARITY_THRESHOLD: f64 = 0.95;
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_MAX_CACHE_ENTRIES: usize = 1000;
+
+/// Value of the semantic cache header that skips the cache for a request
+pub const CACHE_BYPASS: &str = "bypass";
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// Response replayed to the client
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub body: Bytes,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+}
+
+struct CacheEntry {
+    embedding: Vec<f32>,
+    response: CachedResponse,
+    created: Instant,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost: Option<f64>,
+}
+
+/// Entry as written to the snapshot taken on shutdown
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    partition: String,
+    embedding: Vec<f32>,
+    body: Vec<u8>,
+    content_type: Option<String>,
+    content_encoding: Option<String>,
+    /// Unix milliseconds, the ttl of the entry carries over the restart
+    created_at_ms: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost: Option<f64>,
+}
+
+/// Where a missed request is stored once its response is complete
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    alias: String,
+    partition: String,
+    embedding: Vec<f32>,
+}
+
+pub enum CacheLookup {
+    Hit {
+        response: CachedResponse,
+        similarity: f64,
+    },
+    Miss(CacheKey),
+}
+
+/// Hit rate and estimated savings of the cache of an alias
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SemanticCacheStats {
+    pub model: String,
+    pub lookups: u64,
+    pub hits: u64,
+    pub hit_rate: f64,
+    pub saved_input_tokens: u64,
+    pub saved_output_tokens: u64,
+    /// USD, for the providers with pricing configured
+    pub estimated_savings_usd: f64,
+}
+
+pub struct SemanticCache {
+    client: reqwest::Client,
+    embeddings_url: String,
+    configs: HashMap<String, SemanticCacheConfig>,
+    tenant_header: String,
+    entries: Mutex<HashMap<String, Vec<CacheEntry>>>,
+    stats: Mutex<HashMap<String, SemanticCacheStats>>,
+}
+
+impl SemanticCache {
+    pub fn new(
+        model_aliases: Option<&HashMap<String, ModelAlias>>,
+        llm_provider_url: &str,
+        tenant_header: &str,
+    ) -> Self {
+        let configs = model_aliases
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, alias)| Some((name.clone(), alias.semantic_cache.clone()?)))
+            .collect();
+        SemanticCache {
+            client: reqwest::Client::new(),
+            embeddings_url: format!("{}{}", llm_provider_url, EMBEDDINGS_PATH),
+            configs,
+            tenant_header: tenant_header.to_ascii_lowercase(),
+            entries: Mutex::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether requests to `alias` go through the cache, clients opt out per request
+    /// with `x-arch-semantic-cache: bypass`
+    pub fn applies(&self, alias: &str, headers: &HeaderMap) -> bool {
+        self.configs.contains_key(alias)
+            && headers
+                .get(ARCH_SEMANTIC_CACHE_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .is_none_or(|value| !value.trim().eq_ignore_ascii_case(CACHE_BYPASS))
+    }
+
+    /// Looks up the completion of a similar prompt. None when the prompt can't be
+    /// embedded, the request is then neither served from nor stored in the cache.
+    pub async fn lookup(
+        &self,
+        alias: &str,
+        request_path: &str,
+        request: &ProviderRequestType,
+        headers: &HeaderMap,
+        traceparent: &str,
+    ) -> Option<CacheLookup> {
+        let config = self.configs.get(alias)?;
+        let embedding = match self.embed(config, &prompt_text(request), traceparent).await {
+            Ok(embedding) => embedding,
+            Err(err) => {
+                warn!(
+                    "semantic cache of {} skipped, embedding failed: {}",
+                    alias, err
+                );
+                return None;
+            }
+        };
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+        };
+        let partition = format!(
+            "{}|{}|{}|{}|{}",
+            alias,
+            request_path,
+            header(&self.tenant_header),
+            header(ARCH_LISTENER_NAME_HEADER),
+            request_params(request)
+        );
+
+        let ttl = config
+            .ttl_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_CACHE_TTL);
+        let threshold = config
+            .similarity_threshold
+            .unwrap_or(DEFAULT_SIMILARITY_THRESHOLD);
+        let mut entries = self.entries.lock().unwrap();
+        let candidates = entries.entry(partition.clone()).or_default();
+        candidates.retain(|entry| entry.created.elapsed() < ttl);
+        let best = candidates
+            .iter()
+            .map(|entry| (entry, cosine_similarity(&embedding, &entry.embedding)))
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats
+            .entry(alias.to_string())
+            .or_insert_with(|| SemanticCacheStats {
+                model: alias.to_string(),
+                ..Default::default()
+            });
+        stats.lookups += 1;
+        match best {
+            Some((entry, similarity)) => {
+                stats.hits += 1;
+                stats.saved_input_tokens += entry.input_tokens;
+                stats.saved_output_tokens += entry.output_tokens;
+                stats.estimated_savings_usd += entry.cost.unwrap_or_default();
+                Some(CacheLookup::Hit {
+                    response: entry.response.clone(),
+                    similarity,
+                })
+            }
+            None => Some(CacheLookup::Miss(CacheKey {
+                alias: alias.to_string(),
+                partition,
+                embedding,
+            })),
+        }
+    }
+
+    /// Stores the complete response of a missed request
+    pub fn insert(
+        &self,
+        key: CacheKey,
+        response: CachedResponse,
+        tokens: (Option<u64>, Option<u64>),
+        cost: Option<f64>,
+    ) {
+        let max_entries = self
+            .configs
+            .get(&key.alias)
+            .and_then(|config| config.max_entries)
+            .unwrap_or(DEFAULT_MAX_CACHE_ENTRIES);
+        let mut entries = self.entries.lock().unwrap();
+        let candidates = entries.entry(key.partition).or_default();
+        candidates.push(CacheEntry {
+            embedding: key.embedding,
+            response,
+            created: Instant::now(),
+            input_tokens: tokens.0.unwrap_or_default(),
+            output_tokens: tokens.1.unwrap_or_default(),
+            cost,
+        });
+        if candidates.len() > max_entries {
+            let excess = candidates.len() - max_entries;
+            candidates.drain(..excess);
+        }
+        debug!(
+            "semantic cache of {} holds {} entries",
+            key.alias,
+            candidates.len()
+        );
+    }
+
+    /// Writes the entries to a snapshot, the cache is only held in memory
+    pub fn save(&self, path: &Path) -> io::Result<usize> {
+        let now_ms = unix_now_ms();
+        let entries = self.entries.lock().unwrap();
+        let saved: Vec<SavedEntry> = entries
+            .iter()
+            .flat_map(|(partition, candidates)| {
+                candidates.iter().map(move |entry| SavedEntry {
+                    partition: partition.clone(),
+                    embedding: entry.embedding.clone(),
+                    body: entry.response.body.to_vec(),
+                    content_type: entry.response.content_type.clone(),
+                    content_encoding: entry.response.content_encoding.clone(),
+                    created_at_ms: now_ms
+                        .saturating_sub(entry.created.elapsed().as_millis() as u64),
+                    input_tokens: entry.input_tokens,
+                    output_tokens: entry.output_tokens,
+                    cost: entry.cost,
+                })
+            })
+            .collect();
+        write_snapshot(path, &saved)?;
+        Ok(saved.len())
+    }
+
+    /// Restores the entries saved on the last shutdown, of the aliases that still have a
+    /// cache. Entries are dropped by their ttl as if there had been no restart.
+    pub fn restore(&self, path: &Path) -> io::Result<usize> {
+        let Some(saved) = read_snapshot::<Vec<SavedEntry>>(path)? else {
+            return Ok(0);
+        };
+        let now_ms = unix_now_ms();
+        let mut entries = self.entries.lock().unwrap();
+        let mut restored = 0;
+        for entry in saved {
+            let alias = entry.partition.split('|').next().unwrap_or_default();
+            if !self.configs.contains_key(alias) {
+                continue;
+            }
+            let age = Duration::from_millis(now_ms.saturating_sub(entry.created_at_ms));
+            let Some(created) = Instant::now().checked_sub(age) else {
+                continue;
+            };
+            entries
+                .entry(entry.partition)
+                .or_default()
+                .push(CacheEntry {
+                    embedding: entry.embedding,
+                    response: CachedResponse {
+                        body: Bytes::from(entry.body),
+                        content_type: entry.content_type,
+                        content_encoding: entry.content_encoding,
+                    },
+                    created,
+                    input_tokens: entry.input_tokens,
+                    output_tokens: entry.output_tokens,
+                    cost: entry.cost,
+                });
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    pub fn stats(&self) -> Vec<SemanticCacheStats> {
+        let mut stats: Vec<_> = self
+            .stats
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .map(|mut stats| {
+                if stats.lookups > 0 {
+                    stats.hit_rate = stats.hits as f64 / stats.lookups as f64;
+                }
+                stats
+            })
+            .collect();
+        stats.sort_by(|a, b| a.model.cmp(&b.model));
+        stats
+    }
+
+    async fn embed(
+        &self,
+        config: &SemanticCacheConfig,
+        text: &str,
+        traceparent: &str,
+    ) -> Result<Vec<f32>, String> {
+        let mut request = self
+            .client
+            .post(&self.embeddings_url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("traceparent", traceparent)
+            .json(&EmbeddingsRequest {
+                model: &config.embedding_model,
+                input: vec![text],
+            });
+        if let Some(provider) = config.embedding_provider.as_deref() {
+            request = request.header(ARCH_PROVIDER_HINT_HEADER, provider);
+        }
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        response
+            .json::<EmbeddingsResponse>()
+            .await
+            .map_err(|err| err.to_string())?
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .ok_or_else(|| "no embedding in response".to_string())
+    }
+}
+
+/// Handles `GET /v1/semantic_cache/stats`: hit rate and estimated savings by alias
+pub fn semantic_cache_stats(cache: &SemanticCache) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = serde_json::json!({ "object": "list", "data": cache.stats() });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap()
+}
+
+/// Text of the whole conversation, cached completions only answer the same conversation
+fn prompt_text(request: &ProviderRequestType) -> String {
+    request
+        .get_messages()
+        .iter()
+        .map(|message| format!("{:?}: {}", message.role, message.content))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Everything of the request but its prompt, which is compared by embedding
+fn request_params(request: &ProviderRequestType) -> String {
+    let Some(mut params) = request
+        .to_bytes()
+        .ok()
+        .and_then(|body| serde_json::from_slice::<serde_json::Value>(&body).ok())
+    else {
+        return String::new();
+    };
+    if let Some(params) = params.as_object_mut() {
+        for prompt_field in ["messages", "system", "input", "instructions"] {
+            params.remove(prompt_field);
+        }
+    }
+    params.to_string()
+}
+
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        dot += *x as f64 * *y as f64;
+        norm_a += *x as f64 * *x as f64;
+        norm_b += *y as f64 * *y as f64;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::clients::SupportedAPIsFromClient;
+    use mockito::{Matcher, Server};
+
+    fn cache(url: &str) -> SemanticCache {
+        let alias: ModelAlias = serde_json::from_value(serde_json::json!({
+            "target": "gpt-4o",
+            "semantic_cache": {
+                "embedding_model": "text-embedding-3-small",
+                "similarity_threshold": 0.9,
+                "max_entries": 1
+            }
+        }))
+        .unwrap();
+        let aliases = HashMap::from([("support".to_string(), alias)]);
+        SemanticCache::new(Some(&aliases), url, "x-arch-tenant-id")
+    }
+
+    fn request(text: &str) -> ProviderRequestType {
+        let body = serde_json::json!({
+            "model": "support",
+            "messages": [{"role": "user", "content": text}]
+        })
+        .to_string();
+        ProviderRequestType::try_from((
+            body.as_bytes(),
+            &SupportedAPIsFromClient::from_endpoint("/v1/chat/completions").unwrap(),
+        ))
+        .unwrap()
+    }
+
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            body: Bytes::from(body.to_string()),
+            content_type: Some("application/json".to_string()),
+            content_encoding: None,
+        }
+    }
+
+    async fn mock_embedding(server: &mut Server, text: &str, embedding: &str) {
+        server
+            .mock("POST", EMBEDDINGS_PATH)
+            .match_body(Matcher::PartialJsonString(format!(
+                r#"{{"input":["{}"]}}"#,
+                text
+            )))
+            .with_body(format!(
+                r#"{{"data":[{{"index":0,"embedding":{}}}]}}"#,
+                embedding
+            ))
+            .create_async()
+            .await;
+    }
+
+    #[test]
+    fn test_applies() {
+        let cache = cache("http://localhost");
+        let mut headers = HeaderMap::new();
+        assert!(cache.applies("support", &headers));
+        assert!(!cache.applies("gpt-4o", &headers));
+        headers.insert(ARCH_SEMANTIC_CACHE_HEADER, "Bypass".parse().unwrap());
+        assert!(!cache.applies("support", &headers));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_serves_similar_prompts() {
+        let mut server = Server::new_async().await;
+        mock_embedding(
+            &mut server,
+            "User: how do I reset my password?",
+            "[1.0, 0.0]",
+        )
+        .await;
+        mock_embedding(
+            &mut server,
+            "User: how can I reset my password?",
+            "[0.99, 0.1]",
+        )
+        .await;
+        mock_embedding(
+            &mut server,
+            "User: what is the refund policy?",
+            "[0.0, 1.0]",
+        )
+        .await;
+        let cache = cache(&server.url());
+
+        let Some(CacheLookup::Miss(key)) = cache
+            .lookup(
+                "support",
+                "/v1/chat/completions",
+                &request("how do I reset my password?"),
+                &HeaderMap::new(),
+                "",
+            )
+            .await
+        else {
+            panic!("expected a miss");
+        };
+        cache.insert(
+            key,
+            response("reset it in settings"),
+            (Some(100), Some(20)),
+            Some(0.01),
+        );
+
+        match cache
+            .lookup(
+                "support",
+                "/v1/chat/completions",
+                &request("how can I reset my password?"),
+                &HeaderMap::new(),
+                "",
+            )
+            .await
+        {
+            Some(CacheLookup::Hit {
+                response,
+                similarity,
+            }) => {
+                assert_eq!(response.body, Bytes::from("reset it in settings"));
+                assert!(similarity > 0.9);
+            }
+            _ => panic!("expected a hit"),
+        }
+        // other apis are cached apart
+        assert!(matches!(
+            cache
+                .lookup(
+                    "support",
+                    "/v1/messages",
+                    &request("how can I reset my password?"),
+                    &HeaderMap::new(),
+                    ""
+                )
+                .await,
+            Some(CacheLookup::Miss(_))
+        ));
+        assert!(matches!(
+            cache
+                .lookup(
+                    "support",
+                    "/v1/chat/completions",
+                    &request("what is the refund policy?"),
+                    &HeaderMap::new(),
+                    ""
+                )
+                .await,
+            Some(CacheLookup::Miss(_))
+        ));
+
+        assert_eq!(
+            cache.stats(),
+            vec![SemanticCacheStats {
+                model: "support".to_string(),
+                lookups: 4,
+                hits: 1,
+                hit_rate: 0.25,
+                saved_input_tokens: 100,
+                saved_output_tokens: 20,
+                estimated_savings_usd: 0.01,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_is_partitioned_by_tenant_listener_and_params() {
+        let mut server = Server::new_async().await;
+        mock_embedding(&mut server, "User: hi", "[1.0, 0.0]").await;
+        let cache = cache(&server.url());
+        let chat_request = |params: serde_json::Value| {
+            let mut body = serde_json::json!({
+                "model": "support",
+                "messages": [{"role": "user", "content": "hi"}]
+            });
+            body.as_object_mut()
+                .unwrap()
+                .extend(params.as_object().unwrap().clone());
+            ProviderRequestType::try_from((
+                body.to_string().as_bytes(),
+                &SupportedAPIsFromClient::from_endpoint("/v1/chat/completions").unwrap(),
+            ))
+            .unwrap()
+        };
+        let headers = |tenant: &str, listener: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-arch-tenant-id", tenant.parse().unwrap());
+            headers.insert(ARCH_LISTENER_NAME_HEADER, listener.parse().unwrap());
+            headers
+        };
+        let lookup = |params: serde_json::Value, headers: HeaderMap| {
+            let cache = &cache;
+            let request = chat_request(params);
+            async move {
+                cache
+                    .lookup("support", "/v1/chat/completions", &request, &headers, "")
+                    .await
+            }
+        };
+
+        let Some(CacheLookup::Miss(key)) = lookup(
+            serde_json::json!({"temperature": 0.2, "user": "alice"}),
+            headers("acme", "public"),
+        )
+        .await
+        else {
+            panic!("expected a miss");
+        };
+        cache.insert(key, response("hello alice"), (None, None), None);
+        assert!(matches!(
+            lookup(
+                serde_json::json!({"temperature": 0.2, "user": "alice"}),
+                headers("acme", "public"),
+            )
+            .await,
+            Some(CacheLookup::Hit { .. })
+        ));
+
+        for (params, headers) in [
+            (
+                serde_json::json!({"temperature": 0.2, "user": "alice"}),
+                headers("globex", "public"),
+            ),
+            (
+                serde_json::json!({"temperature": 0.2, "user": "alice"}),
+                headers("acme", "internal"),
+            ),
+            (
+                serde_json::json!({"temperature": 0.2, "user": "bob"}),
+                headers("acme", "public"),
+            ),
+            (
+                serde_json::json!({"temperature": 0.9, "user": "alice"}),
+                headers("acme", "public"),
+            ),
+            (
+                serde_json::json!({
+                    "temperature": 0.2,
+                    "user": "alice",
+                    "response_format": {"type": "json_object"}
+                }),
+                headers("acme", "public"),
+            ),
+        ] {
+            assert!(matches!(
+                lookup(params, headers).await,
+                Some(CacheLookup::Miss(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_without_embedding() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", EMBEDDINGS_PATH)
+            .with_status(500)
+            .create_async()
+            .await;
+        let cache = cache(&server.url());
+        assert!(cache
+            .lookup(
+                "support",
+                "/v1/chat/completions",
+                &request("hi"),
+                &HeaderMap::new(),
+                "",
+            )
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_insert_drops_oldest_entries() {
+        let cache = cache("http://localhost");
+        for text in ["a", "b"] {
+            let key = CacheKey {
+                alias: "support".to_string(),
+                partition: "support|/v1/chat/completions|false|".to_string(),
+                embedding: vec![1.0],
+            };
+            cache.insert(key, response(text), (None, None), None);
+        }
+        let entries = cache.entries.lock().unwrap();
+        let entries = &entries["support|/v1/chat/completions|false|"];
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].response.body, Bytes::from("b"));
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let path =
+            std::env::temp_dir().join(format!("semantic-cache-{}.json", uuid::Uuid::new_v4()));
+        let cache = cache("http://localhost");
+        for alias in ["support", "removed"] {
+            let key = CacheKey {
+                alias: alias.to_string(),
+                partition: format!("{}|/v1/chat/completions||", alias),
+                embedding: vec![1.0, 0.5],
+            };
+            cache.insert(key, response("answer"), (Some(10), Some(5)), Some(0.01));
+        }
+        assert_eq!(cache.save(&path).unwrap(), 2);
+
+        let restarted = self::cache("http://localhost");
+        assert_eq!(restarted.restore(&path).unwrap(), 1);
+        let entries = restarted.entries.lock().unwrap();
+        let entry = &entries["support|/v1/chat/completions||"][0];
+        assert_eq!(entry.embedding, vec![1.0, 0.5]);
+        assert_eq!(entry.response.body, Bytes::from("answer"));
+        assert_eq!(entry.output_tokens, 5);
+        assert!(entry.created.elapsed() < DEFAULT_CACHE_TTL);
+        assert!(!entries.contains_key("removed|/v1/chat/completions||"));
+    }
+}
@@ -0,0 +1,260 @@
+use common::configuration::SessionSummarizationConfig;
+use common::consts::{ARCH_PROVIDER_HINT_HEADER, CHAT_COMPLETIONS_PATH};
+use hermesllm::apis::openai::{
+    ChatCompletionsRequest, ChatCompletionsResponse, MessageContent, Role,
+};
+use hermesllm::apis::OpenAIMessage;
+use hyper::header;
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::state::agent_session::AgentSessionStore;
+use crate::state::StateStorageError;
+
+pub const DEFAULT_MAX_SESSION_MESSAGES: usize = 40;
+pub const DEFAULT_KEEP_SESSION_MESSAGES: usize = 10;
+pub const DEFAULT_MAX_SUMMARY_TOKENS: u32 = 512;
+
+const SUMMARIZATION_PROMPT: &str = "You maintain the memory of a conversation between a user and an assistant. Write a concise summary of the conversation below, merged with the previous summary if there is one. Keep facts, decisions, user preferences and open questions. Answer with the summary only.";
+
+#[derive(Debug, Error)]
+pub enum SummarizationError {
+    #[error("summarization request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("summarization model returned no summary")]
+    EmptySummary,
+    #[error(transparent)]
+    Storage(#[from] StateStorageError),
+}
+
+/// Keeps agent sessions short by replacing their older messages with a rolling summary
+/// written by a cheap model. Restored sessions are sent as the summary followed by the
+/// most recent messages.
+pub struct SessionSummarizer {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    max_messages: usize,
+    keep_messages: usize,
+    max_summary_tokens: u32,
+}
+
+impl SessionSummarizer {
+    pub fn new(config: &SessionSummarizationConfig, llm_provider_url: &str) -> Self {
+        SessionSummarizer {
+            client: reqwest::Client::new(),
+            url: format!("{}{}", llm_provider_url, CHAT_COMPLETIONS_PATH),
+            model: config.model.clone(),
+            max_messages: config.max_messages.unwrap_or(DEFAULT_MAX_SESSION_MESSAGES),
+            keep_messages: config
+                .keep_messages
+                .unwrap_or(DEFAULT_KEEP_SESSION_MESSAGES),
+            max_summary_tokens: config
+                .max_summary_tokens
+                .unwrap_or(DEFAULT_MAX_SUMMARY_TOKENS),
+        }
+    }
+
+    /// Number of messages to summarize, None when the session is short enough. The
+    /// kept messages start at a user message so tool results stay with their calls.
+    pub fn messages_to_summarize(&self, messages: &[OpenAIMessage], force: bool) -> Option<usize> {
+        if messages.len() <= self.keep_messages || (!force && messages.len() <= self.max_messages) {
+            return None;
+        }
+        let start = messages.len() - self.keep_messages;
+        (start..messages.len())
+            .find(|&i| messages[i].role == Role::User)
+            .filter(|&i| i > 0)
+    }
+
+    pub fn summary_request(
+        &self,
+        previous_summary: Option<&str>,
+        messages: &[OpenAIMessage],
+    ) -> ChatCompletionsRequest {
+        let mut transcript = String::new();
+        if let Some(summary) = previous_summary {
+            transcript.push_str(&format!("Previous summary:\n{}\n\n", summary));
+        }
+        transcript.push_str("Conversation:\n");
+        for message in messages {
+            let role = serde_json::to_value(&message.role)
+                .ok()
+                .and_then(|role| role.as_str().map(str::to_string))
+                .unwrap_or_default();
+            transcript.push_str(&format!("{}: {}\n", role, message.content));
+        }
+        ChatCompletionsRequest {
+            model: self.model.clone(),
+            messages: vec![
+                text_message(Role::System, SUMMARIZATION_PROMPT.to_string()),
+                text_message(Role::User, transcript),
+            ],
+            max_tokens: Some(self.max_summary_tokens),
+            stream: Some(false),
+            ..Default::default()
+        }
+    }
+
+    async fn call(&self, request: &ChatCompletionsRequest) -> Result<String, SummarizationError> {
+        let response: ChatCompletionsResponse = self
+            .client
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(ARCH_PROVIDER_HINT_HEADER, &self.model)
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .map(|summary| summary.trim().to_string())
+            .filter(|summary| !summary.is_empty())
+            .ok_or(SummarizationError::EmptySummary)
+    }
+
+    /// Summarizes the older messages of the session when it holds more than
+    /// `max_messages`, or whenever it can when `force` is set. Returns whether the
+    /// session was compacted.
+    pub async fn summarize(
+        &self,
+        store: &dyn AgentSessionStore,
+        session_id: &str,
+        force: bool,
+    ) -> Result<bool, SummarizationError> {
+        let session = store.get(session_id).await?;
+        let Some(summarized) = self.messages_to_summarize(&session.messages, force) else {
+            debug!(
+                "agent session {} has {} messages, not summarized",
+                session_id,
+                session.messages.len()
+            );
+            return Ok(false);
+        };
+        let request =
+            self.summary_request(session.summary.as_deref(), &session.messages[..summarized]);
+        let summary = self.call(&request).await?;
+        store.compact(session_id, summary, summarized).await?;
+        info!(
+            "summarized {} messages of agent session {} with {}",
+            summarized, session_id, self.model
+        );
+        Ok(true)
+    }
+}
+
+fn text_message(role: Role, text: String) -> OpenAIMessage {
+    OpenAIMessage {
+        role,
+        content: MessageContent::Text(text),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+/// Message carrying the summary of a session ahead of its remaining messages
+pub fn summary_message(summary: &str) -> OpenAIMessage {
+    text_message(
+        Role::System,
+        format!("Summary of the earlier conversation:\n{}", summary),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::agent_session::MemoryAgentSessionStore;
+
+    fn summarizer(url: &str) -> SessionSummarizer {
+        SessionSummarizer::new(
+            &SessionSummarizationConfig {
+                model: "gpt-4o-mini".to_string(),
+                max_messages: Some(4),
+                keep_messages: Some(2),
+                max_summary_tokens: None,
+            },
+            url,
+        )
+    }
+
+    fn conversation(turns: usize) -> Vec<OpenAIMessage> {
+        (0..turns)
+            .flat_map(|turn| {
+                [
+                    text_message(Role::User, format!("question {}", turn)),
+                    text_message(Role::Assistant, format!("answer {}", turn)),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_messages_to_summarize() {
+        let summarizer = summarizer("http://localhost");
+        assert_eq!(
+            summarizer.messages_to_summarize(&conversation(2), false),
+            None
+        );
+        assert_eq!(
+            summarizer.messages_to_summarize(&conversation(3), false),
+            Some(4)
+        );
+        assert_eq!(
+            summarizer.messages_to_summarize(&conversation(2), true),
+            Some(2)
+        );
+
+        // the kept messages start with the next user message
+        let mut messages = conversation(3);
+        messages.push(text_message(Role::Tool, "tool result".to_string()));
+        assert_eq!(summarizer.messages_to_summarize(&messages, false), None);
+        messages.push(text_message(Role::Assistant, "done".to_string()));
+        messages.push(text_message(Role::User, "thanks".to_string()));
+        assert_eq!(summarizer.messages_to_summarize(&messages, false), Some(8));
+    }
+
+    #[test]
+    fn test_summary_request() {
+        let summarizer = summarizer("http://localhost");
+        let request = summarizer.summary_request(Some("user likes tea"), &conversation(1));
+        assert_eq!(request.model, "gpt-4o-mini");
+        assert_eq!(request.max_tokens, Some(DEFAULT_MAX_SUMMARY_TOKENS));
+        assert_eq!(
+            request.messages[1].content.to_string(),
+            "Previous summary:\nuser likes tea\n\nConversation:\nuser: question 0\nassistant: answer 0\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summarize_compacts_session() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", CHAT_COMPLETIONS_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "gpt-4o-mini")
+            .with_body(
+                r#"{"id":"1","object":"chat.completion","created":1,"model":"gpt-4o-mini","choices":[{"index":0,"message":{"role":"assistant","content":" The user asked two questions. "},"finish_reason":"stop"}],"usage":{"prompt_tokens":1,"completion_tokens":1,"total_tokens":2}}"#,
+            )
+            .create_async()
+            .await;
+
+        let store = MemoryAgentSessionStore::default();
+        store.append("s1", None, conversation(3)).await.unwrap();
+        let summarizer = summarizer(&server.url());
+        assert!(summarizer.summarize(&store, "s1", false).await.unwrap());
+        mock.assert_async().await;
+
+        let session = store.get("s1").await.unwrap();
+        assert_eq!(
+            session.summary.as_deref(),
+            Some("The user asked two questions.")
+        );
+        assert_eq!(session.messages.len(), 2);
+        assert!(!summarizer.summarize(&store, "s1", false).await.unwrap());
+    }
+}
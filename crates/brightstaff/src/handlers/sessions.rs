@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use common::consts::{ARCH_AGENT_LISTENER_NAME_HEADER, ARCH_SESSION_ID_HEADER};
+use http_body_util::combinators::BoxBody;
+use hyper::header::HeaderMap;
+use hyper::{Method, Request, Response, StatusCode};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::admin::AdminToken;
+use super::response_handler::ResponseHandler;
+use super::session_memory::{SessionSummarizer, SummarizationError};
+use crate::state::agent_session::{AgentSession, AgentSessionStore};
+use crate::state::StateStorageError;
+
+/// Session id sent by the client, if any
+pub fn session_id_from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ARCH_SESSION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Assistant text of a collected agent response. Non streaming chat completion bodies
+/// are unwrapped to the message content, anything else is kept as is.
+pub fn assistant_text(response_text: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(response_text)
+        .ok()
+        .and_then(|body| {
+            body.pointer("/choices/0/message/content")
+                .and_then(|content| content.as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| response_text.to_string())
+}
+
+fn storage_error_response(err: StateStorageError) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let status = match err {
+        StateStorageError::NotFound(_) => StatusCode::NOT_FOUND,
+        _ => {
+            warn!("agent session storage error: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    ResponseHandler::create_json_response(status, &serde_json::json!({ "error": err.to_string() }))
+}
+
+/// Whether a request to the sessions endpoints needs the admin token. Creating a
+/// session is open to clients, reading, deleting and summarizing sessions is not.
+pub fn requires_admin_token(method: &Method, session_id: Option<&str>) -> bool {
+    !(method == Method::POST && session_id.is_none())
+}
+
+/// Handles `/agents/sessions` and `/agents/sessions/{id}`:
+/// POST creates a session, GET lists sessions or returns one with its messages,
+/// DELETE removes a session. All but POST require the admin token as a bearer token.
+pub async fn agent_sessions<B>(
+    request: Request<B>,
+    session_id: Option<&str>,
+    session_store: Option<Arc<dyn AgentSessionStore>>,
+    admin_token: &AdminToken,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    if requires_admin_token(request.method(), session_id)
+        && !admin_token.is_authorized(request.headers())
+    {
+        return ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        );
+    }
+    let Some(session_store) = session_store else {
+        return ResponseHandler::create_json_response(
+            StatusCode::NOT_IMPLEMENTED,
+            &serde_json::json!({
+                "error": "agent sessions require state_storage to be configured"
+            }),
+        );
+    };
+
+    match (request.method(), session_id) {
+        (&Method::POST, None) => {
+            let listener = request
+                .headers()
+                .get(ARCH_AGENT_LISTENER_NAME_HEADER)
+                .and_then(|name| name.to_str().ok())
+                .map(str::to_string);
+            let session = AgentSession::new(Uuid::new_v4().to_string(), listener);
+            match session_store.create(session.clone()).await {
+                Ok(()) => {
+                    info!("created agent session {}", session.session_id);
+                    ResponseHandler::create_json_response(StatusCode::CREATED, &session)
+                }
+                Err(err) => storage_error_response(err),
+            }
+        }
+        (&Method::GET, None) => match session_store.list().await {
+            Ok(sessions) => ResponseHandler::create_json_response(
+                StatusCode::OK,
+                &serde_json::json!({ "object": "list", "data": sessions }),
+            ),
+            Err(err) => storage_error_response(err),
+        },
+        (&Method::GET, Some(session_id)) => match session_store.get(session_id).await {
+            Ok(session) => ResponseHandler::create_json_response(StatusCode::OK, &session),
+            Err(err) => storage_error_response(err),
+        },
+        (&Method::DELETE, Some(session_id)) => match session_store.delete(session_id).await {
+            Ok(()) => {
+                info!("deleted agent session {}", session_id);
+                ResponseHandler::create_json_response(
+                    StatusCode::OK,
+                    &serde_json::json!({ "session_id": session_id, "deleted": true }),
+                )
+            }
+            Err(err) => storage_error_response(err),
+        },
+        _ => ResponseHandler::create_error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed",
+        ),
+    }
+}
+
+/// Handles `POST /agents/sessions/{id}/summarize`: summarizes the older messages of the
+/// session right away, whatever its length, and returns the compacted session.
+/// Requires the admin token as a bearer token.
+pub async fn summarize_agent_session<B>(
+    request: Request<B>,
+    session_id: &str,
+    session_store: Option<Arc<dyn AgentSessionStore>>,
+    session_summarizer: Option<Arc<SessionSummarizer>>,
+    admin_token: &AdminToken,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    if request.method() != Method::POST {
+        return ResponseHandler::create_error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed",
+        );
+    }
+    if !admin_token.is_authorized(request.headers()) {
+        return ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        );
+    }
+    let (Some(session_store), Some(session_summarizer)) = (session_store, session_summarizer)
+    else {
+        return ResponseHandler::create_json_response(
+            StatusCode::NOT_IMPLEMENTED,
+            &serde_json::json!({
+                "error": "session summarization requires state_storage.session_summarization to be configured"
+            }),
+        );
+    };
+
+    match session_summarizer
+        .summarize(&*session_store, session_id, true)
+        .await
+    {
+        Ok(_) => match session_store.get(session_id).await {
+            Ok(session) => ResponseHandler::create_json_response(StatusCode::OK, &session),
+            Err(err) => storage_error_response(err),
+        },
+        Err(SummarizationError::Storage(err)) => storage_error_response(err),
+        Err(err) => {
+            warn!("failed to summarize agent session {}: {}", session_id, err);
+            ResponseHandler::create_json_response(
+                StatusCode::BAD_GATEWAY,
+                &serde_json::json!({ "error": err.to_string() }),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::agent_session::MemoryAgentSessionStore;
+
+    #[test]
+    fn test_session_id_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_id_from_headers(&headers), None);
+        headers.insert(ARCH_SESSION_ID_HEADER, " ".parse().unwrap());
+        assert_eq!(session_id_from_headers(&headers), None);
+        headers.insert(ARCH_SESSION_ID_HEADER, "abc".parse().unwrap());
+        assert_eq!(session_id_from_headers(&headers), Some("abc".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_reading_sessions_requires_the_admin_token() {
+        let store: Arc<dyn AgentSessionStore> = Arc::new(MemoryAgentSessionStore::default());
+        let admin_token = AdminToken::new(Some("secret"));
+        let request = |method: Method, authorization: Option<&str>| {
+            let mut request = Request::builder().method(method).uri("/agents/sessions");
+            if let Some(authorization) = authorization {
+                request = request.header(hyper::header::AUTHORIZATION, authorization);
+            }
+            request.body(()).unwrap()
+        };
+
+        let created = agent_sessions(
+            request(Method::POST, None),
+            None,
+            Some(store.clone()),
+            &admin_token,
+        )
+        .await;
+        assert_eq!(created.status(), StatusCode::CREATED);
+
+        for (method, session_id) in [
+            (Method::GET, None),
+            (Method::GET, Some("abc")),
+            (Method::DELETE, Some("abc")),
+        ] {
+            let response = agent_sessions(
+                request(method, Some("Bearer wrong")),
+                session_id,
+                Some(store.clone()),
+                &admin_token,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = summarize_agent_session(
+            request(Method::POST, None),
+            "abc",
+            Some(store.clone()),
+            None,
+            &admin_token,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let listed = agent_sessions(
+            request(Method::GET, Some("Bearer secret")),
+            None,
+            Some(store),
+            &admin_token,
+        )
+        .await;
+        assert_eq!(listed.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_assistant_text_unwraps_chat_completion() {
+        let body = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"hello"}}]}"#;
+        assert_eq!(assistant_text(body), "hello");
+        assert_eq!(assistant_text("plain text"), "plain text");
+    }
+}
@@ -0,0 +1,245 @@
+use bytes::Bytes;
+use common::configuration::ShadowTraffic;
+use common::consts::{
+    ARCH_IS_STREAMING_HEADER, ARCH_PROVIDER_HINT_HEADER, ARCH_TRAFFIC_SPLIT_HEADER,
+};
+use common::traces::{parse_traceparent, SpanBuilder, SpanKind, TraceCollector};
+use hyper::header::{HeaderMap, HeaderValue};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::tracing::{
+    error, gen_ai, http, llm, operation_component, ContentCapturePolicy, GenAiResponseRecorder,
+    OperationNameBuilder,
+};
+
+/// Split label of mirrored requests on the llm_gateway metrics
+pub const SHADOW_LABEL: &str = "shadow";
+
+/// Deadline of a mirrored request when neither the shadow nor its provider sets one
+pub const DEFAULT_SHADOW_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Request mirrored to the shadow model of an alias
+pub struct ShadowRequest {
+    pub request_id: String,
+    pub traceparent: String,
+    pub request_path: String,
+    /// Model that served the client
+    pub primary_model: String,
+    pub target: String,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub timeout: Duration,
+}
+
+/// Whether this request to the alias is mirrored
+pub fn sample_shadow(shadow: &ShadowTraffic) -> bool {
+    shadow.percentage > 0.0 && rand::random_range(0.0..100.0) < shadow.percentage
+}
+
+/// Request body for the shadow model. Shadow requests never stream, only the final
+/// response is compared.
+pub fn shadow_request_body(body: &[u8], target: &str) -> Option<Bytes> {
+    let mut request: Value = serde_json::from_slice(body).ok()?;
+    let fields = request.as_object_mut()?;
+    fields.insert("model".to_string(), Value::String(target.to_string()));
+    fields.remove("stream");
+    fields.remove("stream_options");
+    serde_json::to_vec(&request).ok().map(Bytes::from)
+}
+
+/// Headers for the shadow request, routed by llm_gateway to the shadow model and
+/// labeled as shadow traffic on its metrics
+pub fn shadow_request_headers(request_headers: &HeaderMap, target: &str) -> Option<HeaderMap> {
+    let mut headers = request_headers.clone();
+    headers.insert(
+        ARCH_PROVIDER_HINT_HEADER,
+        HeaderValue::from_str(target).ok()?,
+    );
+    headers.insert(ARCH_IS_STREAMING_HEADER, HeaderValue::from_static("false"));
+    headers.insert(
+        ARCH_TRAFFIC_SPLIT_HEADER,
+        HeaderValue::from_static(SHADOW_LABEL),
+    );
+    Some(headers)
+}
+
+/// Jaccard similarity of the words of two completions, 1 when they use the same words
+pub fn content_similarity(primary: &str, shadow: &str) -> f64 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let primary = words(primary);
+    let shadow = words(shadow);
+    let union = primary.union(&shadow).count();
+    if union == 0 {
+        return 1.0;
+    }
+    primary.intersection(&shadow).count() as f64 / union as f64
+}
+
+/// Sends the shadow request and records its latency, usage and similarity to the
+/// primary completion on a span. Nothing of the shadow response reaches the client.
+pub async fn run_shadow(
+    request: ShadowRequest,
+    primary_completion: oneshot::Receiver<String>,
+    trace_collector: Arc<TraceCollector>,
+) {
+    let start_time = SystemTime::now();
+    let started = Instant::now();
+    let response = reqwest::Client::new()
+        .post(&request.url)
+        .headers(request.headers)
+        .timeout(request.timeout)
+        .body(request.body)
+        .send()
+        .await;
+
+    let operation_name = OperationNameBuilder::new()
+        .with_method("POST")
+        .with_path(&request.request_path)
+        .with_target(&request.target)
+        .build();
+    let (trace_id, parent_span_id) = parse_traceparent(&request.traceparent);
+    let mut span_builder = SpanBuilder::new(&operation_name)
+        .with_trace_id(&trace_id)
+        .with_kind(SpanKind::Client)
+        .with_start_time(start_time)
+        .with_attribute(http::METHOD, "POST")
+        .with_attribute(http::TARGET, request.request_path.clone())
+        .with_attribute(llm::MODEL_NAME, request.target.clone())
+        .with_attribute(llm::SHADOW_OF, request.primary_model.clone())
+        .with_attribute(gen_ai::REQUEST_MODEL, request.target.clone());
+    if let Some(parent) = parent_span_id {
+        span_builder = span_builder.with_parent_span_id(&parent);
+    }
+
+    let body = match response {
+        Ok(response) => {
+            span_builder = span_builder
+                .with_attribute(http::STATUS_CODE, response.status().as_u16().to_string());
+            match response.error_for_status() {
+                Ok(response) => response.bytes().await.map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            }
+        }
+        Err(err) => Err(err.to_string()),
+    };
+    let duration_ms = started.elapsed().as_millis();
+    span_builder = span_builder.with_attribute(llm::DURATION_MS, duration_ms.to_string());
+
+    let body = match body {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(
+                "[PLANO_REQ_ID:{}] | SHADOW | target={} failed: {}",
+                request.request_id, request.target, err
+            );
+            let span = span_builder
+                .with_attribute(error::ERROR, "true")
+                .with_attribute(error::MESSAGE, err)
+                .with_end_time(SystemTime::now())
+                .build();
+            trace_collector.record_span(operation_component::LLM, span);
+            return;
+        }
+    };
+
+    let mut recorder =
+        GenAiResponseRecorder::new(false, Some(Arc::new(ContentCapturePolicy::default())));
+    recorder.observe_chunk(&body);
+    for (key, value) in recorder.finish() {
+        span_builder = span_builder.with_attribute(key, value);
+    }
+
+    // the primary response is dropped without a completion when it fails
+    let similarity = primary_completion
+        .await
+        .ok()
+        .map(|primary| content_similarity(&primary, &recorder.completion().unwrap_or_default()));
+    if let Some(similarity) = similarity {
+        span_builder = span_builder
+            .with_attribute(llm::SHADOW_CONTENT_SIMILARITY, format!("{:.2}", similarity));
+    }
+
+    info!(
+        "[PLANO_REQ_ID:{}] | SHADOW | target={} primary={} duration_ms={} input_tokens={:?} output_tokens={:?} similarity={:?}",
+        request.request_id,
+        request.target,
+        request.primary_model,
+        duration_ms,
+        recorder.input_tokens(),
+        recorder.output_tokens(),
+        similarity
+    );
+    let span = span_builder.with_end_time(SystemTime::now()).build();
+    trace_collector.record_span(operation_component::LLM, span);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shadow_request_body() {
+        let body = shadow_request_body(
+            br#"{"model":"gpt-4o","stream":true,"stream_options":{"include_usage":true},"messages":[]}"#,
+            "claude-sonnet-4",
+        )
+        .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"model": "claude-sonnet-4", "messages": []})
+        );
+        assert_eq!(shadow_request_body(b"not json", "claude-sonnet-4"), None);
+    }
+
+    #[test]
+    fn test_shadow_request_headers() {
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(
+            ARCH_PROVIDER_HINT_HEADER,
+            HeaderValue::from_static("gpt-4o"),
+        );
+        request_headers.insert(ARCH_IS_STREAMING_HEADER, HeaderValue::from_static("true"));
+        let headers = shadow_request_headers(&request_headers, "claude-sonnet-4").unwrap();
+        assert_eq!(headers[ARCH_PROVIDER_HINT_HEADER], "claude-sonnet-4");
+        assert_eq!(headers[ARCH_IS_STREAMING_HEADER], "false");
+        assert_eq!(headers[ARCH_TRAFFIC_SPLIT_HEADER], SHADOW_LABEL);
+    }
+
+    #[test]
+    fn test_content_similarity() {
+        assert_eq!(content_similarity("", ""), 1.0);
+        assert_eq!(content_similarity("Paris.", "paris"), 1.0);
+        assert_eq!(content_similarity("Paris", "Lyon"), 0.0);
+        let similarity = content_similarity(
+            "The capital of France is Paris.",
+            "Paris is the capital of France!",
+        );
+        assert_eq!(similarity, 1.0);
+        let similarity = content_similarity("the capital is Paris", "the capital is Lyon");
+        assert!((similarity - 0.6).abs() < 1e-9, "got {}", similarity);
+    }
+
+    #[test]
+    fn test_sample_shadow() {
+        let mut shadow = ShadowTraffic {
+            target: "claude-sonnet-4".to_string(),
+            percentage: 0.0,
+            timeout_ms: None,
+        };
+        assert!(!(0..100).any(|_| sample_shadow(&shadow)));
+        shadow.percentage = 100.0;
+        assert!((0..100).all(|_| sample_shadow(&shadow)));
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use common::configuration::SseTapConfig;
+use common::consts::SSE_TAP_UPSTREAM_PREFIX;
+use hermesllm::fixtures::{Fixture, FixtureResponse};
+use hermesllm::ProviderId;
+use http_body_util::combinators::BoxBody;
+use hyper::header::{self, HeaderMap};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::handlers::admin::AdminToken;
+use crate::handlers::response_handler::ResponseHandler;
+use crate::handlers::utils::StreamProcessor;
+
+const DEFAULT_MAX_REQUESTS: usize = 20;
+const DEFAULT_MAX_BYTES_PER_REQUEST: usize = 256 * 1024;
+
+/// Recent streaming requests recorded side by side: every raw upstream chunk with the
+/// client events llm_gateway translated it into
+pub struct SseTap {
+    percentage: f64,
+    max_requests: usize,
+    max_bytes_per_request: usize,
+    admin_token: AdminToken,
+    fixture_dir: Option<PathBuf>,
+    recent: Mutex<VecDeque<Arc<Mutex<TappedStream>>>>,
+}
+
+/// What is needed besides the upstream chunks to write a recording as a replay fixture
+pub struct FixtureSource {
+    /// Path the client called
+    pub client_api: String,
+    pub provider: ProviderId,
+    /// Request body as sent to llm_gateway
+    pub request: Bytes,
+}
+
+/// A recorded stream
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TappedStream {
+    pub request_id: String,
+    pub model: String,
+    /// Unix time in milliseconds
+    pub started_at: u128,
+    pub chunks: Vec<TappedChunk>,
+    /// Recording stopped at max_bytes_per_request
+    pub truncated: bool,
+    pub complete: bool,
+    #[serde(skip)]
+    bytes: usize,
+}
+
+/// A raw upstream chunk and its translation for the client
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct TappedChunk {
+    pub upstream: String,
+    pub client: String,
+}
+
+impl SseTap {
+    pub fn new(config: &SseTapConfig, admin_token: AdminToken) -> Self {
+        SseTap {
+            percentage: config.percentage,
+            max_requests: config.max_requests.unwrap_or(DEFAULT_MAX_REQUESTS).max(1),
+            max_bytes_per_request: config
+                .max_bytes_per_request
+                .unwrap_or(DEFAULT_MAX_BYTES_PER_REQUEST),
+            admin_token,
+            fixture_dir: config.fixture_dir.as_ref().map(PathBuf::from),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Whether this streaming request is recorded
+    pub fn sample(&self) -> bool {
+        self.percentage > 0.0 && rand::random_range(0.0..100.0) < self.percentage
+    }
+
+    /// Starts recording a stream, the oldest recording is dropped when the buffer is full.
+    /// With a fixture_dir configured the complete recording is also written as a fixture.
+    pub fn record(
+        &self,
+        request_id: &str,
+        model: &str,
+        fixture: Option<FixtureSource>,
+    ) -> SseTapRecorder {
+        let stream = Arc::new(Mutex::new(TappedStream {
+            request_id: request_id.to_string(),
+            model: model.to_string(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis())
+                .unwrap_or_default(),
+            chunks: Vec::new(),
+            truncated: false,
+            complete: false,
+            bytes: 0,
+        }));
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= self.max_requests {
+            recent.pop_front();
+        }
+        recent.push_back(stream.clone());
+        SseTapRecorder {
+            stream,
+            max_bytes: self.max_bytes_per_request,
+            fixture: self.fixture_dir.clone().zip(fixture),
+        }
+    }
+
+    /// Recorded streams, newest first
+    pub fn streams(&self, request_id: Option<&str>) -> Vec<TappedStream> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|stream| stream.lock().unwrap().clone())
+            .filter(|stream| request_id.is_none_or(|id| stream.request_id == id))
+            .collect()
+    }
+}
+
+/// Appends to one recorded stream
+pub struct SseTapRecorder {
+    stream: Arc<Mutex<TappedStream>>,
+    max_bytes: usize,
+    fixture: Option<(PathBuf, FixtureSource)>,
+}
+
+impl SseTapRecorder {
+    fn upstream(&self, raw: &str) {
+        let mut stream = self.stream.lock().unwrap();
+        if Self::reserve(&mut stream, raw.len(), self.max_bytes) {
+            stream.chunks.push(TappedChunk {
+                upstream: raw.to_string(),
+                client: String::new(),
+            });
+        }
+    }
+
+    fn client(&self, events: &[u8]) {
+        let mut stream = self.stream.lock().unwrap();
+        if events.is_empty() || !Self::reserve(&mut stream, events.len(), self.max_bytes) {
+            return;
+        }
+        let events = String::from_utf8_lossy(events);
+        match stream.chunks.last_mut() {
+            Some(chunk) => chunk.client.push_str(&events),
+            // events llm_gateway sent without an upstream chunk, e.g. when flushing
+            None => stream.chunks.push(TappedChunk {
+                upstream: String::new(),
+                client: events.into_owned(),
+            }),
+        }
+    }
+
+    fn reserve(stream: &mut TappedStream, len: usize, max_bytes: usize) -> bool {
+        if stream.truncated || stream.bytes + len > max_bytes {
+            stream.truncated = true;
+            return false;
+        }
+        stream.bytes += len;
+        true
+    }
+
+    fn complete(&self) {
+        let mut stream = self.stream.lock().unwrap();
+        stream.complete = true;
+        if let Some((dir, source)) = &self.fixture {
+            Self::write_fixture(&stream, dir, source);
+        }
+    }
+
+    /// Writes the upstream side of a recording as a hermesllm replay fixture. Truncated
+    /// recordings are skipped, replaying them would not reproduce the stream.
+    fn write_fixture(stream: &TappedStream, dir: &Path, source: &FixtureSource) {
+        if stream.truncated {
+            return;
+        }
+        let chunks = stream
+            .chunks
+            .iter()
+            .filter(|chunk| !chunk.upstream.is_empty())
+            .map(|chunk| chunk.upstream.clone())
+            .collect();
+        let written = Fixture::new(
+            &stream.request_id,
+            &source.client_api,
+            &source.provider,
+            &source.request,
+            FixtureResponse::Stream {
+                status: 200,
+                chunks,
+            },
+        )
+        .and_then(|fixture| fixture.save(dir));
+        match written {
+            Ok(path) => info!(
+                "[PLANO_REQ_ID:{}] | SSE_TAP | wrote fixture {}",
+                stream.request_id,
+                path.display()
+            ),
+            Err(e) => warn!(
+                "[PLANO_REQ_ID:{}] | SSE_TAP | failed to write fixture: {}",
+                stream.request_id, e
+            ),
+        }
+    }
+}
+
+/// Takes the raw upstream chunks llm_gateway sends along as SSE comments out of tapped
+/// streams and records them with the client events. Streams that aren't tapped go
+/// through untouched.
+pub struct SseTapProcessor<P: StreamProcessor> {
+    inner: P,
+    recorder: Option<SseTapRecorder>,
+    /// Start of a line that continues in the next chunk
+    partial_line: Vec<u8>,
+}
+
+impl<P: StreamProcessor> SseTapProcessor<P> {
+    pub fn new(inner: P, recorder: Option<SseTapRecorder>) -> Self {
+        SseTapProcessor {
+            inner,
+            recorder,
+            partial_line: Vec::new(),
+        }
+    }
+
+    /// Client bytes of whole lines, recording the upstream comments among them
+    fn split_lines(&mut self, chunk: &[u8], recorder: &SseTapRecorder) -> Vec<u8> {
+        self.partial_line.extend_from_slice(chunk);
+        let Some(end) = self.partial_line.iter().rposition(|byte| *byte == b'\n') else {
+            return Vec::new();
+        };
+        let lines: Vec<u8> = self.partial_line.drain(..=end).collect();
+        let mut sent = Vec::with_capacity(lines.len());
+        // client events since the last upstream chunk
+        let mut translated = Vec::new();
+        for line in lines.split_inclusive(|byte| *byte == b'\n') {
+            match line.strip_prefix(SSE_TAP_UPSTREAM_PREFIX.as_bytes()) {
+                Some(raw) => {
+                    recorder.client(&translated);
+                    translated.clear();
+                    let raw = serde_json::from_slice::<String>(raw.trim_ascii())
+                        .unwrap_or_else(|_| String::from_utf8_lossy(raw).into_owned());
+                    recorder.upstream(&raw);
+                }
+                None => {
+                    translated.extend_from_slice(line);
+                    sent.extend_from_slice(line);
+                }
+            }
+        }
+        recorder.client(&translated);
+        sent
+    }
+}
+
+impl<P: StreamProcessor> StreamProcessor for SseTapProcessor<P> {
+    fn process_chunk(&mut self, chunk: Bytes) -> Result<Option<Bytes>, String> {
+        let Some(recorder) = self.recorder.take() else {
+            return self.inner.process_chunk(chunk);
+        };
+        let client = self.split_lines(&chunk, &recorder);
+        self.recorder = Some(recorder);
+        if client.is_empty() {
+            return Ok(None);
+        }
+        self.inner.process_chunk(Bytes::from(client))
+    }
+
+    fn on_first_bytes(&mut self) {
+        self.inner.on_first_bytes();
+    }
+
+    fn finish(&mut self) -> Option<Bytes> {
+        let mut tail = Vec::new();
+        if !self.partial_line.is_empty() {
+            let rest = std::mem::take(&mut self.partial_line);
+            if let Some(recorder) = &self.recorder {
+                recorder.client(&rest);
+            }
+            if let Ok(Some(processed)) = self.inner.process_chunk(Bytes::from(rest)) {
+                tail.extend_from_slice(&processed);
+            }
+        }
+        if let Some(inner_tail) = self.inner.finish() {
+            tail.extend_from_slice(&inner_tail);
+        }
+        (!tail.is_empty()).then(|| Bytes::from(tail))
+    }
+
+    fn keep_alive(&mut self, keep_alive: Bytes) -> Option<Bytes> {
+        self.inner.keep_alive(keep_alive)
+    }
+
+    fn on_complete(&mut self) {
+        if let Some(recorder) = &self.recorder {
+            recorder.complete();
+        }
+        self.inner.on_complete();
+    }
+
+    fn on_error(&mut self, error: &str) {
+        self.inner.on_error(error);
+    }
+}
+
+/// Handles `GET /v1/debug/sse_tap`: the recorded streams, newest first, optionally only
+/// the one of `?request_id=`. Requires the admin token as a bearer token.
+pub fn sse_tap_streams(
+    tap: Option<&SseTap>,
+    headers: &HeaderMap,
+    query: Option<&str>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let Some(tap) = tap else {
+        return ResponseHandler::create_error_response(
+            StatusCode::NOT_FOUND,
+            "sse tap is not configured",
+        );
+    };
+    if !tap.admin_token.is_authorized(headers) {
+        return ResponseHandler::create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "admin token required",
+        );
+    }
+    let request_id = query.and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("request_id="))
+    });
+    let body = serde_json::json!({ "object": "list", "data": tap.streams(request_id) });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(body.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    struct Collect(Vec<u8>);
+
+    impl StreamProcessor for Collect {
+        fn process_chunk(&mut self, chunk: Bytes) -> Result<Option<Bytes>, String> {
+            self.0.extend_from_slice(&chunk);
+            Ok(Some(chunk))
+        }
+    }
+
+    fn tap(max_bytes_per_request: usize) -> SseTap {
+        SseTap::new(
+            &SseTapConfig {
+                percentage: 100.0,
+                max_requests: Some(2),
+                max_bytes_per_request: Some(max_bytes_per_request),
+                fixture_dir: None,
+            },
+            AdminToken::new(Some("secret")),
+        )
+    }
+
+    #[test]
+    fn test_records_upstream_chunks_next_to_client_events() {
+        let tap = tap(4096);
+        let mut processor = SseTapProcessor::new(
+            Collect(Vec::new()),
+            Some(tap.record("req-1", "claude", None)),
+        );
+
+        let upstream = "event: content_block_delta\ndata: {\"delta\":{\"text\":\"Hi\"}}\n\n";
+        let tapped = format!(
+            "{}{}\ndata: {{\"choices\":[{{\"delta\":{{\"content\":\"Hi\"}}}}]}}\n\n",
+            SSE_TAP_UPSTREAM_PREFIX,
+            serde_json::to_string(upstream).unwrap()
+        );
+        // the comment arrives split over two chunks
+        let (first, second) = tapped.split_at(10);
+        assert_eq!(
+            processor
+                .process_chunk(Bytes::from(first.to_string()))
+                .unwrap(),
+            None
+        );
+        let sent = processor
+            .process_chunk(Bytes::from(second.to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            sent,
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n"
+        );
+        processor.on_complete();
+
+        let streams = tap.streams(Some("req-1"));
+        assert_eq!(streams.len(), 1);
+        assert!(streams[0].complete);
+        assert_eq!(streams[0].chunks[0].upstream, upstream);
+        assert_eq!(
+            streams[0].chunks[0].client,
+            std::str::from_utf8(&sent).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer_and_byte_limit() {
+        let tap = tap(8);
+        for id in ["req-1", "req-2", "req-3"] {
+            let recorder = tap.record(id, "gpt-4o", None);
+            recorder.upstream("1234");
+            recorder.client(b"123456789");
+        }
+        let streams = tap.streams(None);
+        assert_eq!(
+            streams
+                .iter()
+                .map(|s| s.request_id.as_str())
+                .collect::<Vec<_>>(),
+            ["req-3", "req-2"]
+        );
+        assert!(streams[0].truncated);
+        assert_eq!(streams[0].chunks[0].client, "");
+    }
+
+    #[test]
+    fn test_complete_recording_is_written_as_fixture() {
+        let dir = std::env::temp_dir().join(format!("sse-tap-{}", uuid::Uuid::new_v4()));
+        let tap = SseTap::new(
+            &SseTapConfig {
+                percentage: 100.0,
+                max_requests: None,
+                max_bytes_per_request: None,
+                fixture_dir: Some(dir.to_string_lossy().into_owned()),
+            },
+            AdminToken::new(Some("secret")),
+        );
+        let recorder = tap.record(
+            "req-1",
+            "gpt-4o",
+            Some(FixtureSource {
+                client_api: "/v1/chat/completions".to_string(),
+                provider: ProviderId::OpenAI,
+                request: Bytes::from_static(
+                    br#"{"model":"gpt-4o","stream":true,"messages":[{"role":"user","content":"hi"}]}"#,
+                ),
+            }),
+        );
+        recorder.upstream("data: {\"choices\":[]}\n\n");
+        recorder.client(b"data: {\"choices\":[]}\n\n");
+        recorder.complete();
+
+        let fixture = Fixture::load(&dir.join("req-1.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(fixture.provider, "openai");
+        assert_eq!(fixture.request["messages"][0]["content"], "hi");
+        assert_eq!(
+            fixture.response,
+            FixtureResponse::Stream {
+                status: 200,
+                chunks: vec!["data: {\"choices\":[]}\n\n".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_streams_require_admin_token() {
+        let tap = tap(4096);
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            sse_tap_streams(Some(&tap), &headers, None).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert_eq!(
+            sse_tap_streams(Some(&tap), &headers, None).status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            sse_tap_streams(None, &headers, None).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use bytes::Bytes;
+use common::configuration::Agent;
+use http_body_util::combinators::BoxBody;
+use http_body_util::StreamBody;
+use hyper::body::Frame;
+use hyper::header::HeaderMap;
+use hyper::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+
+use super::pipeline_processor::{PipelineError, PipelineProcessor};
+use super::response_handler::ResponseError;
+
+/// Number of filtered events buffered ahead of the client. When the client reads slower
+/// than the agent produces, sends block and the upstream body is no longer polled.
+const STREAM_FILTER_CHANNEL_CAPACITY: usize = 16;
+
+/// Unit of work handed to a stream filter agent. The last chunk of a stream is sent
+/// with empty content and `is_final` set so filters holding back text can flush it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamChunk {
+    pub index: usize,
+    pub content: String,
+    pub is_final: bool,
+}
+
+/// Splits a byte stream into complete SSE events
+#[derive(Debug, Default)]
+struct SseEventBuffer {
+    buffer: String,
+}
+
+impl SseEventBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer
+            .push_str(&String::from_utf8_lossy(bytes).replace("\r\n", "\n"));
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let event = self.buffer[..pos].to_string();
+            self.buffer.drain(..pos + 2);
+            if !event.trim().is_empty() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn take_remaining(&mut self) -> Option<String> {
+        let remaining = std::mem::take(&mut self.buffer);
+        (!remaining.trim().is_empty()).then_some(remaining)
+    }
+}
+
+fn event_data(event: &str) -> Option<&str> {
+    event
+        .lines()
+        .find_map(|line| line.strip_prefix("data:"))
+        .map(str::trim)
+}
+
+fn is_done_event(event: &str) -> bool {
+    event_data(event) == Some("[DONE]")
+}
+
+/// Parse a chat completion chunk event, returning the chunk and its content delta
+fn parse_content_delta(event: &str) -> Option<(Value, String)> {
+    let chunk: Value = serde_json::from_str(event_data(event)?).ok()?;
+    let content = chunk
+        .get("choices")?
+        .get(0)?
+        .get("delta")?
+        .get("content")?
+        .as_str()?
+        .to_string();
+    Some((chunk, content))
+}
+
+/// Serialize a chat completion chunk event carrying `content` as its delta
+fn event_with_content(chunk: &Value, content: &str) -> String {
+    let mut chunk = chunk.clone();
+    if let Some(delta) = chunk
+        .get_mut("choices")
+        .and_then(|choices| choices.get_mut(0))
+        .and_then(|choice| choice.get_mut("delta"))
+    {
+        delta["content"] = Value::String(content.to_string());
+    }
+    format!("data: {}\n\n", chunk)
+}
+
+fn error_event(err: &PipelineError) -> String {
+    let error = serde_json::json!({
+        "error": {
+            "type": "StreamFilterError",
+            "message": err.to_string(),
+        }
+    });
+    format!("event: error\ndata: {}\n\n", error)
+}
+
+/// Runs the stream filter agents of an agent over its SSE response
+struct StreamFilterChain {
+    filters: Vec<Agent>,
+    pipeline_processor: PipelineProcessor,
+    request_headers: HeaderMap,
+    trace_id: String,
+    parent_span_id: String,
+    index: usize,
+}
+
+impl StreamFilterChain {
+    async fn apply(&mut self, content: String, is_final: bool) -> Result<String, PipelineError> {
+        let mut content = content;
+        for filter in &self.filters {
+            let chunk = StreamChunk {
+                index: self.index,
+                content,
+                is_final,
+            };
+            content = self
+                .pipeline_processor
+                .execute_stream_filter(
+                    &chunk,
+                    filter,
+                    &self.request_headers,
+                    self.trace_id.clone(),
+                    self.parent_span_id.clone(),
+                )
+                .await?;
+        }
+        self.index += 1;
+        Ok(content)
+    }
+}
+
+/// Stream the terminal agent response to the client, passing every content delta
+/// through the stream filters in order. Filter failures end the stream with an SSE
+/// error event rather than letting unfiltered content through.
+pub fn create_filtered_streaming_response(
+    llm_response: reqwest::Response,
+    filters: Vec<Agent>,
+    pipeline_processor: PipelineProcessor,
+    request_headers: HeaderMap,
+    trace_id: String,
+    parent_span_id: String,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, ResponseError> {
+    let mut response_builder = Response::builder().status(llm_response.status());
+    let headers = response_builder
+        .headers_mut()
+        .ok_or_else(|| ResponseError::StreamError("Failed to get mutable headers".to_string()))?;
+    for (header_name, header_value) in llm_response.headers().iter() {
+        if header_name != hyper::header::CONTENT_LENGTH {
+            headers.insert(header_name, header_value.clone());
+        }
+    }
+
+    let mut filter_chain = StreamFilterChain {
+        filters,
+        pipeline_processor,
+        request_headers,
+        trace_id,
+        parent_span_id,
+        index: 0,
+    };
+    let (tx, rx) = mpsc::channel::<Bytes>(STREAM_FILTER_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut byte_stream = llm_response.bytes_stream();
+        let mut event_buffer = SseEventBuffer::default();
+        let mut last_chunk: Option<Value> = None;
+        let mut flushed = false;
+        let mut stream_ended = false;
+
+        while !stream_ended {
+            let events = match byte_stream.next().await {
+                Some(Ok(bytes)) => event_buffer.push(&bytes),
+                Some(Err(err)) => {
+                    warn!("Error receiving chunk: {:?}", err);
+                    return;
+                }
+                None => {
+                    stream_ended = true;
+                    event_buffer.take_remaining().into_iter().collect()
+                }
+            };
+
+            for event in events {
+                let mut output = String::new();
+                if let Some((chunk, content)) = parse_content_delta(&event) {
+                    match filter_chain.apply(content, false).await {
+                        Ok(filtered) => output.push_str(&event_with_content(&chunk, &filtered)),
+                        Err(err) => {
+                            warn!("stream filter failed: {}", err);
+                            let _ = tx.send(Bytes::from(error_event(&err))).await;
+                            return;
+                        }
+                    }
+                    last_chunk = Some(chunk);
+                } else {
+                    if is_done_event(&event) && !flushed {
+                        flushed = true;
+                        match filter_chain.apply(String::new(), true).await {
+                            Ok(tail) if !tail.is_empty() => {
+                                if let Some(chunk) = last_chunk.as_ref() {
+                                    output.push_str(&event_with_content(chunk, &tail));
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(err) => {
+                                warn!("stream filter failed on final chunk: {}", err);
+                                let _ = tx.send(Bytes::from(error_event(&err))).await;
+                                return;
+                            }
+                        }
+                    }
+                    output.push_str(&event);
+                    output.push_str("\n\n");
+                }
+
+                if tx.send(Bytes::from(output)).await.is_err() {
+                    debug!("client dropped filtered stream");
+                    return;
+                }
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(|chunk| Ok::<_, hyper::Error>(Frame::data(chunk)));
+    response_builder
+        .body(BoxBody::new(StreamBody::new(stream)))
+        .map_err(ResponseError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_event_buffer_handles_split_events() {
+        let mut buffer = SseEventBuffer::default();
+        assert!(buffer.push(b"data: {\"a\":").is_empty());
+        let events = buffer.push(b"1}\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec!["data: {\"a\":1}", "data: [DONE]"]);
+        assert_eq!(buffer.take_remaining(), None);
+    }
+
+    #[test]
+    fn test_parse_and_rewrite_content_delta() {
+        let event = r#"data: {"id":"c1","object":"chat.completion.chunk","choices":[{"index":0,"delta":{"content":"call 555-1234"}}]}"#;
+        let (chunk, content) = parse_content_delta(event).unwrap();
+        assert_eq!(content, "call 555-1234");
+
+        let rewritten = event_with_content(&chunk, "call [REDACTED]");
+        let (_, content) = parse_content_delta(rewritten.trim_end()).unwrap();
+        assert_eq!(content, "call [REDACTED]");
+        assert!(rewritten.ends_with("\n\n"));
+
+        assert!(parse_content_delta("data: [DONE]").is_none());
+        assert!(is_done_event("data: [DONE]"));
+        assert!(
+            parse_content_delta(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#).is_none()
+        );
+    }
+
+    #[test]
+    fn test_stream_chunk_serialization() {
+        let chunk = StreamChunk {
+            index: 3,
+            content: "hello".to_string(),
+            is_final: false,
+        };
+        assert_eq!(
+            serde_json::to_value(&chunk).unwrap(),
+            serde_json::json!({"index": 3, "content": "hello", "is_final": false})
+        );
+    }
+}
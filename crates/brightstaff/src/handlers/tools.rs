@@ -0,0 +1,281 @@
+use bytes::Bytes;
+use common::api::open_ai::ParameterType;
+use common::configuration::{Agent, PromptTarget};
+use futures::future::join_all;
+use http_body_util::combinators::BoxBody;
+use hyper::{header, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use super::pipeline_processor::PipelineProcessor;
+use super::response_handler::ResponseHandler;
+
+const PROMPT_TARGET_SOURCE: &str = "prompt_target";
+const MCP_SOURCE: &str = "mcp";
+
+/// Tool the gateway can execute, a prompt target or a tool of an MCP filter
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CatalogTool {
+    pub name: String,
+    pub description: Option<String>,
+    /// `prompt_target` or `mcp`
+    pub source: &'static str,
+    /// MCP filter serving the tool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// JSON schema of the tool arguments
+    pub input_schema: Value,
+}
+
+/// MCP filter whose tools couldn't be listed
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CatalogError {
+    pub agent: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolList {
+    object: &'static str,
+    data: Vec<CatalogTool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<CatalogError>,
+}
+
+/// Prompt targets and the tools of the MCP filters, served on `/v1/tools`
+pub struct ToolCatalog {
+    prompt_targets: Vec<PromptTarget>,
+    mcp_filters: Vec<Agent>,
+    pipeline_processor: PipelineProcessor,
+}
+
+impl ToolCatalog {
+    pub fn new(prompt_targets: &[PromptTarget], filters: &[Agent]) -> Self {
+        Self::with_pipeline_processor(prompt_targets, filters, PipelineProcessor::default())
+    }
+
+    fn with_pipeline_processor(
+        prompt_targets: &[PromptTarget],
+        filters: &[Agent],
+        pipeline_processor: PipelineProcessor,
+    ) -> Self {
+        ToolCatalog {
+            prompt_targets: prompt_targets.to_vec(),
+            mcp_filters: filters
+                .iter()
+                .filter(|filter| filter.agent_type.as_deref().unwrap_or(MCP_SOURCE) == MCP_SOURCE)
+                .cloned()
+                .collect(),
+            pipeline_processor,
+        }
+    }
+
+    /// The prompt targets followed by the tools of every MCP filter, listed at the same
+    /// time. Filters that can't be listed are returned as errors.
+    pub async fn tools(&self) -> (Vec<CatalogTool>, Vec<CatalogError>) {
+        let mut tools: Vec<CatalogTool> = self
+            .prompt_targets
+            .iter()
+            .map(|target| CatalogTool {
+                name: target.name.clone(),
+                description: Some(target.description.clone()),
+                source: PROMPT_TARGET_SOURCE,
+                agent: None,
+                input_schema: prompt_target_schema(target),
+            })
+            .collect();
+        let mut errors = Vec::new();
+
+        let listings = join_all(
+            self.mcp_filters
+                .iter()
+                .map(|filter| self.pipeline_processor.list_mcp_tools(filter)),
+        )
+        .await;
+        for (filter, listing) in self.mcp_filters.iter().zip(listings) {
+            match listing {
+                Ok(listed) => tools.extend(listed.into_iter().map(|tool| CatalogTool {
+                    name: tool.name,
+                    description: tool.description,
+                    source: MCP_SOURCE,
+                    agent: Some(filter.id.clone()),
+                    input_schema: tool.input_schema,
+                })),
+                Err(err) => {
+                    warn!(
+                        "failed to list the tools of MCP filter {}: {}",
+                        filter.id, err
+                    );
+                    errors.push(CatalogError {
+                        agent: filter.id.clone(),
+                        message: err.to_string(),
+                    });
+                }
+            }
+        }
+        (tools, errors)
+    }
+}
+
+pub async fn list_tools(catalog: &ToolCatalog) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let (data, errors) = catalog.tools().await;
+    let body = ToolList {
+        object: "list",
+        data,
+        errors,
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(ResponseHandler::create_full_body(
+            serde_json::to_string(&body).unwrap_or_default(),
+        ))
+        .unwrap()
+}
+
+/// JSON schema of the parameters of a prompt target
+fn prompt_target_schema(target: &PromptTarget) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for parameter in target.parameters.iter().flatten() {
+        let parameter_type = ParameterType::from(
+            parameter
+                .parameter_type
+                .clone()
+                .unwrap_or("str".to_string()),
+        );
+        let mut property = Map::new();
+        property.insert("type".to_string(), json!(json_schema_type(&parameter_type)));
+        property.insert("description".to_string(), json!(parameter.description));
+        if let Some(enum_values) = &parameter.enum_values {
+            property.insert("enum".to_string(), json!(enum_values));
+        }
+        if let Some(default) = &parameter.default {
+            property.insert("default".to_string(), json!(default));
+        }
+        if let Some(format) = &parameter.format {
+            property.insert("format".to_string(), json!(format));
+        }
+        properties.insert(parameter.name.clone(), Value::Object(property));
+        if parameter.required.unwrap_or(false) {
+            required.push(parameter.name.clone());
+        }
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+fn json_schema_type(parameter_type: &ParameterType) -> &'static str {
+    match parameter_type {
+        ParameterType::Int => "integer",
+        ParameterType::Float => "number",
+        ParameterType::Bool => "boolean",
+        ParameterType::String => "string",
+        ParameterType::List => "array",
+        ParameterType::Dict => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jsonrpc::{JSON_RPC_VERSION, MCP_INITIALIZE, MCP_INITIALIZE_NOTIFICATION};
+    use common::consts::ARCH_UPSTREAM_HOST_HEADER;
+    use mockito::{Matcher, Server};
+
+    #[tokio::test]
+    async fn test_tools() {
+        let tools_list = json!({
+            "jsonrpc": JSON_RPC_VERSION,
+            "id": "1",
+            "result": {
+                "tools": [{
+                    "name": "rewrite_query",
+                    "description": "Rewrites the last user message",
+                    "inputSchema": {"type": "object", "properties": {"messages": {"type": "array"}}}
+                }]
+            }
+        });
+        let mut server = Server::new_async().await;
+        let _initialize = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": MCP_INITIALIZE})))
+            .match_header(ARCH_UPSTREAM_HOST_HEADER, "query_rewriter")
+            .with_status(200)
+            .with_header("mcp-session-id", "session-1")
+            .create_async()
+            .await;
+        let _initialized = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(
+                json!({"method": MCP_INITIALIZE_NOTIFICATION}),
+            ))
+            .with_status(202)
+            .create_async()
+            .await;
+        let list = server
+            .mock("POST", "/mcp")
+            .match_body(Matcher::PartialJson(json!({"method": "tools/list"})))
+            .match_header(ARCH_UPSTREAM_HOST_HEADER, "query_rewriter")
+            .match_header("mcp-session-id", "session-1")
+            .with_status(200)
+            .with_body(format!("event: message\ndata: {}\n\n", tools_list))
+            .create_async()
+            .await;
+        let _rejected = server
+            .mock("POST", "/mcp")
+            .match_header(ARCH_UPSTREAM_HOST_HEADER, "context_builder")
+            .with_status(401)
+            .create_async()
+            .await;
+
+        let prompt_targets: Vec<PromptTarget> = serde_json::from_value(json!([{
+            "name": "reboot_device",
+            "description": "Reboot a network device",
+            "parameters": [
+                {"name": "device_id", "type": "str", "description": "Device to reboot", "required": true},
+                {"name": "delay", "type": "int", "description": "Delay in seconds"}
+            ]
+        }]))
+        .unwrap();
+        let filters: Vec<Agent> = serde_json::from_value(json!([
+            {"id": "query_rewriter", "url": server.url()},
+            {"id": "context_builder", "url": server.url(), "type": "mcp"},
+            {"id": "docs_retrieval", "url": "http://qdrant:6333", "type": "retrieval"}
+        ]))
+        .unwrap();
+        let processor = PipelineProcessor::new(server.url());
+        let catalog = ToolCatalog::with_pipeline_processor(&prompt_targets, &filters, processor);
+
+        let (tools, errors) = catalog.tools().await;
+        list.assert_async().await;
+        assert_eq!(tools.len(), 2);
+        assert_eq!(
+            tools[0].input_schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "device_id": {"type": "string", "description": "Device to reboot"},
+                    "delay": {"type": "integer", "description": "Delay in seconds"}
+                },
+                "required": ["device_id"]
+            })
+        );
+        assert_eq!(
+            tools[1],
+            CatalogTool {
+                name: "rewrite_query".to_string(),
+                description: Some("Rewrites the last user message".to_string()),
+                source: MCP_SOURCE,
+                agent: Some("query_rewriter".to_string()),
+                input_schema: json!({"type": "object", "properties": {"messages": {"type": "array"}}}),
+            }
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].agent, "context_builder");
+    }
+}
@@ -1,17 +1,88 @@
 use bytes::Bytes;
 use common::traces::{Attribute, AttributeValue, Event, Span, TraceCollector};
 use http_body_util::combinators::BoxBody;
-use http_body_util::StreamBody;
+use http_body_util::{BodyExt, StreamBody};
 use hyper::body::Frame;
+use hyper::{header, Response, StatusCode};
+use std::future::Future;
 use std::sync::Arc;
-use std::time::{Instant, SystemTime};
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tracing::warn;
 
 // Import tracing constants
-use crate::tracing::{error, llm};
+use crate::handlers::semantic_cache::{CacheKey, CachedResponse, SemanticCache};
+use crate::tracing::{error, gen_ai, llm, ContentCapturePolicy, GenAiResponseRecorder};
+use crate::usage::{UsageExporter, UsageRecord};
+use common::compression::{ContentEncoding, StreamDecoder, StreamEncoder};
+use common::configuration::{ModelPricing, StreamKeepAlive};
+
+/// SSE comment sent to streaming clients while the upstream is quiet
+pub const KEEP_ALIVE_COMMENT: &[u8] = b": keep-alive\n\n";
+const DEFAULT_KEEP_ALIVE_INTERVAL_MS: u64 = 15_000;
+
+/// Keep-alives of a streaming response
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAlive {
+    pub interval: Duration,
+    pub max_quiet: Option<Duration>,
+}
+
+impl From<&StreamKeepAlive> for KeepAlive {
+    fn from(config: &StreamKeepAlive) -> Self {
+        KeepAlive {
+            interval: Duration::from_millis(
+                config.interval_ms.unwrap_or(DEFAULT_KEEP_ALIVE_INTERVAL_MS),
+            ),
+            max_quiet: config.max_quiet_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+impl KeepAlive {
+    /// How long to wait for data before sending the next keep-alive or giving up
+    fn wait(&self, quiet_since: Instant) -> Duration {
+        match self.max_quiet {
+            Some(max_quiet) => self
+                .interval
+                .min(max_quiet.saturating_sub(quiet_since.elapsed())),
+            None => self.interval,
+        }
+    }
+
+    fn is_exceeded(&self, quiet_since: Instant) -> bool {
+        self.max_quiet
+            .is_some_and(|max_quiet| quiet_since.elapsed() >= max_quiet)
+    }
+}
+
+/// Whether the bytes sent on a stream so far end between SSE events, the only place a
+/// keep-alive comment can go without splitting an event
+struct EventBoundary {
+    /// Last bytes sent, enough to hold a blank line
+    tail: Vec<u8>,
+}
+
+impl EventBoundary {
+    fn new() -> Self {
+        EventBoundary { tail: Vec::new() }
+    }
+
+    fn observe(&mut self, bytes: &[u8]) {
+        self.tail.extend_from_slice(bytes);
+        let excess = self.tail.len().saturating_sub(4);
+        self.tail.drain(..excess);
+    }
+
+    fn is_at_boundary(&self) -> bool {
+        self.tail.is_empty()
+            || self.tail.ends_with(b"\n\n")
+            || self.tail.ends_with(b"\r\n\r\n")
+            || self.tail.ends_with(b"\r\r")
+    }
+}
 
 /// Trait for processing streaming chunks
 /// Implementors can inject custom logic during streaming (e.g., hallucination detection, logging)
@@ -22,6 +93,16 @@ pub trait StreamProcessor: Send + 'static {
     /// Called when the first bytes are received (for time-to-first-token tracking)
     fn on_first_bytes(&mut self) {}
 
+    /// Bytes sent to the client after the last chunk
+    fn finish(&mut self) -> Option<Bytes> {
+        None
+    }
+
+    /// Keep-alive sent to the client while the upstream is quiet, None to skip it
+    fn keep_alive(&mut self, keep_alive: Bytes) -> Option<Bytes> {
+        Some(keep_alive)
+    }
+
     /// Called when streaming completes successfully
     fn on_complete(&mut self) {}
 
@@ -38,6 +119,27 @@ pub struct ObservableStreamProcessor {
     chunk_count: usize,
     start_time: Instant,
     time_to_first_token: Option<u128>,
+    gen_ai: Option<GenAiResponseRecorder>,
+    usage: Option<PendingUsage>,
+    completion_tap: Option<(GenAiResponseRecorder, Vec<oneshot::Sender<String>>)>,
+    cache_fill: Option<PendingCacheFill>,
+}
+
+/// Response stored in the semantic cache once it is complete
+pub struct PendingCacheFill {
+    pub cache: Arc<SemanticCache>,
+    pub key: CacheKey,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub pricing: Option<ModelPricing>,
+    pub body: Vec<u8>,
+}
+
+/// Usage record completed with tokens, cost and latency once the response is done
+pub struct PendingUsage {
+    pub exporter: Arc<UsageExporter>,
+    pub record: UsageRecord,
+    pub pricing: Option<ModelPricing>,
 }
 
 impl ObservableStreamProcessor {
@@ -62,14 +164,64 @@ impl ObservableStreamProcessor {
             chunk_count: 0,
             start_time,
             time_to_first_token: None,
+            gen_ai: None,
+            usage: None,
+            completion_tap: None,
+            cache_fill: None,
         }
     }
+
+    /// Store the response in the semantic cache when it is done
+    pub fn with_cache_fill(mut self, cache_fill: PendingCacheFill) -> Self {
+        self.cache_fill = Some(cache_fill);
+        self
+    }
+
+    /// Export a usage record for the request when the response is done
+    pub fn with_usage(mut self, usage: PendingUsage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Read usage, finish reasons and the completion from the response into the span
+    pub fn with_gen_ai_recorder(mut self, recorder: GenAiResponseRecorder) -> Self {
+        self.gen_ai = Some(recorder);
+        self
+    }
+
+    /// Send the completion text to `sender` when the response is done, regardless of
+    /// the content capture mode. The sender is dropped if the response fails. Taps added
+    /// after the first share its recorder.
+    pub fn with_completion_tap(
+        mut self,
+        is_streaming: bool,
+        sender: oneshot::Sender<String>,
+    ) -> Self {
+        let (_, senders) = self.completion_tap.get_or_insert_with(|| {
+            let recorder = GenAiResponseRecorder::new(
+                is_streaming,
+                Some(Arc::new(ContentCapturePolicy::default())),
+            );
+            (recorder, Vec::new())
+        });
+        senders.push(sender);
+        self
+    }
 }
 
 impl StreamProcessor for ObservableStreamProcessor {
     fn process_chunk(&mut self, chunk: Bytes) -> Result<Option<Bytes>, String> {
         self.total_bytes += chunk.len();
         self.chunk_count += 1;
+        if let Some(recorder) = self.gen_ai.as_mut() {
+            recorder.observe_chunk(&chunk);
+        }
+        if let Some((recorder, _)) = self.completion_tap.as_mut() {
+            recorder.observe_chunk(&chunk);
+        }
+        if let Some(cache_fill) = self.cache_fill.as_mut() {
+            cache_fill.body.extend_from_slice(&chunk);
+        }
         Ok(Some(chunk))
     }
 
@@ -133,6 +285,68 @@ impl StreamProcessor for ObservableStreamProcessor {
             }
         }
 
+        let mut tokens = (None, None);
+        if let Some(mut recorder) = self.gen_ai.take() {
+            let attributes = recorder.finish();
+            tokens = (recorder.input_tokens(), recorder.output_tokens());
+            for (key, value) in attributes {
+                self.span.attributes.push(Attribute {
+                    key: key.to_string(),
+                    value: AttributeValue {
+                        string_value: Some(value),
+                    },
+                });
+            }
+
+            if let Some(completion) = recorder.completion() {
+                let mut event = Event::new(gen_ai::CONTENT_COMPLETION.to_string(), end_time_nanos);
+                event.add_attribute(gen_ai::COMPLETION.to_string(), completion);
+                self.span.events.get_or_insert_with(Vec::new).push(event);
+            }
+        }
+
+        if let Some((mut recorder, senders)) = self.completion_tap.take() {
+            recorder.finish();
+            let completion = recorder.completion().unwrap_or_default();
+            for sender in senders {
+                let _ = sender.send(completion.clone());
+            }
+        }
+
+        if let Some(fill) = self.cache_fill.take() {
+            let cost = match (fill.pricing, tokens) {
+                (Some(pricing), (Some(input), Some(output))) => Some(pricing.cost(input, output)),
+                _ => None,
+            };
+            fill.cache.insert(
+                fill.key,
+                CachedResponse {
+                    body: Bytes::from(fill.body),
+                    content_type: fill.content_type,
+                    content_encoding: fill.content_encoding,
+                },
+                tokens,
+                cost,
+            );
+        }
+
+        if let Some(PendingUsage {
+            exporter,
+            mut record,
+            pricing,
+        }) = self.usage.take()
+        {
+            record.input_tokens = tokens.0;
+            record.output_tokens = tokens.1;
+            record.cost = match (pricing, tokens) {
+                (Some(pricing), (Some(input), Some(output))) => Some(pricing.cost(input, output)),
+                _ => None,
+            };
+            record.latency_ms = self.start_time.elapsed().as_millis() as u64;
+            record.timestamp = chrono::Utc::now().to_rfc3339();
+            exporter.record(record);
+        }
+
         // Record the finalized span
         self.collector
             .record_span(&self.service_name, self.span.clone());
@@ -176,6 +390,87 @@ impl StreamProcessor for ObservableStreamProcessor {
     }
 }
 
+/// Decodes compressed upstream chunks for the wrapped processor and compresses its
+/// output for the client
+pub struct CompressionProcessor<P: StreamProcessor> {
+    inner: P,
+    decoder: Option<StreamDecoder>,
+    encoder: Option<StreamEncoder>,
+}
+
+impl<P: StreamProcessor> CompressionProcessor<P> {
+    pub fn new(
+        inner: P,
+        upstream_encoding: Option<ContentEncoding>,
+        client_encoding: Option<ContentEncoding>,
+    ) -> Self {
+        CompressionProcessor {
+            inner,
+            decoder: upstream_encoding.map(StreamDecoder::new),
+            encoder: client_encoding.map(StreamEncoder::new),
+        }
+    }
+}
+
+impl<P: StreamProcessor> StreamProcessor for CompressionProcessor<P> {
+    fn process_chunk(&mut self, chunk: Bytes) -> Result<Option<Bytes>, String> {
+        let chunk = match self.decoder.as_mut() {
+            Some(decoder) => {
+                let decoded = decoder.decode(&chunk).map_err(|err| {
+                    format!(
+                        "failed to decode {} response: {}",
+                        decoder.encoding().as_str(),
+                        err
+                    )
+                })?;
+                // the compressed block continues in the next chunk
+                if decoded.is_empty() {
+                    return Ok(None);
+                }
+                Bytes::from(decoded)
+            }
+            None => chunk,
+        };
+        let processed = self.inner.process_chunk(chunk)?;
+        Ok(match (processed, self.encoder.as_mut()) {
+            (Some(processed), Some(encoder)) => {
+                Some(Bytes::from(encoder.encode(&processed))).filter(|chunk| !chunk.is_empty())
+            }
+            (processed, _) => processed,
+        })
+    }
+
+    fn on_first_bytes(&mut self) {
+        self.inner.on_first_bytes();
+    }
+
+    fn finish(&mut self) -> Option<Bytes> {
+        let tail = self.inner.finish();
+        match self.encoder.take() {
+            Some(encoder) => Some(Bytes::from(
+                encoder.finish(tail.as_deref().unwrap_or_default()),
+            )),
+            None => tail,
+        }
+    }
+
+    fn keep_alive(&mut self, keep_alive: Bytes) -> Option<Bytes> {
+        let keep_alive = self.inner.keep_alive(keep_alive)?;
+        match self.encoder.as_mut() {
+            Some(encoder) => Some(Bytes::from(encoder.encode(&keep_alive))),
+            None => Some(keep_alive),
+        }
+    }
+
+    fn on_complete(&mut self) {
+        self.inner.on_complete();
+    }
+
+    fn on_error(&mut self, error: &str) {
+        self.inner.on_error(error);
+    }
+}
+
 /// Result of creating a streaming response
 pub struct StreamingResponse {
     pub body: BoxBody<Bytes, hyper::Error>,
@@ -183,9 +478,25 @@ pub struct StreamingResponse {
 }
 
 pub fn create_streaming_response<S, P>(
+    byte_stream: S,
+    processor: P,
+    buffer_size: usize,
+) -> StreamingResponse
+where
+    S: StreamExt<Item = Result<Bytes, reqwest::Error>> + Send + Unpin + 'static,
+    P: StreamProcessor,
+{
+    create_streaming_response_with_keep_alive(byte_stream, processor, buffer_size, None)
+}
+
+/// Like [`create_streaming_response`], sending keep-alives whenever the upstream is quiet
+/// for the keep-alive interval and ending the stream after its max quiet time. Keep-alives
+/// are only sent between events, never inside one the upstream sent part of.
+pub fn create_streaming_response_with_keep_alive<S, P>(
     mut byte_stream: S,
     mut processor: P,
     buffer_size: usize,
+    keep_alive: Option<KeepAlive>,
 ) -> StreamingResponse
 where
     S: StreamExt<Item = Result<Bytes, reqwest::Error>> + Send + Unpin + 'static,
@@ -196,8 +507,49 @@ where
     // Spawn a task to process and forward chunks
     let processor_handle = tokio::spawn(async move {
         let mut is_first_chunk = true;
+        let mut quiet_since = Instant::now();
+        // upstream bytes, keep-alives wait while an event is half forwarded
+        let mut boundary = EventBoundary::new();
+
+        loop {
+            let next = match keep_alive {
+                Some(keep_alive) => {
+                    match tokio::time::timeout(keep_alive.wait(quiet_since), byte_stream.next())
+                        .await
+                    {
+                        Ok(next) => next,
+                        Err(_) if keep_alive.is_exceeded(quiet_since) => {
+                            let err_msg = format!(
+                                "No data from upstream for {}ms",
+                                quiet_since.elapsed().as_millis()
+                            );
+                            warn!("{}", err_msg);
+                            processor.on_error(&err_msg);
+                            break;
+                        }
+                        Err(_) => {
+                            if !boundary.is_at_boundary() {
+                                continue;
+                            }
+                            if let Some(chunk) =
+                                processor.keep_alive(Bytes::from_static(KEEP_ALIVE_COMMENT))
+                            {
+                                if tx.send(chunk).await.is_err() {
+                                    warn!("Receiver dropped");
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                    }
+                }
+                None => byte_stream.next().await,
+            };
+            let Some(item) = next else {
+                break;
+            };
+            quiet_since = Instant::now();
 
-        while let Some(item) = byte_stream.next().await {
             let chunk = match item {
                 Ok(chunk) => chunk,
                 Err(err) => {
@@ -208,6 +560,8 @@ where
                 }
             };
 
+            boundary.observe(&chunk);
+
             // Call on_first_bytes for the first chunk
             if is_first_chunk {
                 processor.on_first_bytes();
@@ -234,6 +588,11 @@ where
             }
         }
 
+        if let Some(tail) = processor.finish() {
+            if tx.send(tail).await.is_err() {
+                warn!("Receiver dropped");
+            }
+        }
         processor.on_complete();
     });
 
@@ -247,6 +606,107 @@ where
     }
 }
 
+/// Answers a streaming request right away with an SSE response that carries keep-alives
+/// until `response` is ready, then the events of its body. Headers of `response` are
+/// not sent, responses that failed are sent as an `error` event.
+pub fn keep_alive_response<F>(
+    response: F,
+    keep_alive: KeepAlive,
+) -> Response<BoxBody<Bytes, hyper::Error>>
+where
+    F: Future<Output = Response<BoxBody<Bytes, hyper::Error>>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Bytes>(16);
+
+    tokio::spawn(async move {
+        let mut quiet_since = Instant::now();
+        tokio::pin!(response);
+        let response = loop {
+            tokio::select! {
+                response = &mut response => break response,
+                _ = tokio::time::sleep(keep_alive.wait(quiet_since)) => {
+                    if keep_alive.is_exceeded(quiet_since) {
+                        warn!(
+                            "No response for {}ms, ending the stream",
+                            quiet_since.elapsed().as_millis()
+                        );
+                        return;
+                    }
+                    if tx.send(Bytes::from_static(KEEP_ALIVE_COMMENT)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+
+        if !response.status().is_success() {
+            let body = match response.into_body().collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(err) => Bytes::from(err.to_string()),
+            };
+            let mut event = String::from("event: error\n");
+            for line in String::from_utf8_lossy(&body).lines() {
+                event.push_str(&format!("data: {}\n", line));
+            }
+            event.push('\n');
+            let _ = tx.send(Bytes::from(event)).await;
+            return;
+        }
+
+        let mut body = response.into_body();
+        let mut boundary = EventBoundary::new();
+        quiet_since = Instant::now();
+        loop {
+            match tokio::time::timeout(keep_alive.wait(quiet_since), body.frame()).await {
+                Ok(Some(Ok(frame))) => {
+                    quiet_since = Instant::now();
+                    if let Ok(data) = frame.into_data() {
+                        boundary.observe(&data);
+                        if tx.send(data).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(Some(Err(err))) => {
+                    warn!("Error receiving response body: {}", err);
+                    return;
+                }
+                Ok(None) => return,
+                Err(_) if keep_alive.is_exceeded(quiet_since) => {
+                    warn!(
+                        "No data for {}ms, ending the stream",
+                        quiet_since.elapsed().as_millis()
+                    );
+                    return;
+                }
+                Err(_) if !boundary.is_at_boundary() => {}
+                Err(_) => {
+                    if tx
+                        .send(Bytes::from_static(KEEP_ALIVE_COMMENT))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(|chunk| Ok::<_, hyper::Error>(Frame::data(chunk)));
+    let mut response = Response::new(BoxBody::new(StreamBody::new(stream)));
+    *response.status_mut() = StatusCode::OK;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/event-stream"),
+    );
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-cache"),
+    );
+    response
+}
+
 /// Truncates a message to the specified maximum length, adding "..." if truncated.
 pub fn truncate_message(message: &str, max_length: usize) -> String {
     if message.chars().count() > max_length {
@@ -256,3 +716,55 @@ pub fn truncate_message(message: &str, max_length: usize) -> String {
         message.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct PassThrough;
+
+    impl StreamProcessor for PassThrough {
+        fn process_chunk(&mut self, chunk: Bytes) -> Result<Option<Bytes>, String> {
+            Ok(Some(chunk))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_waits_for_the_end_of_a_split_event() {
+        let chunks: Vec<(u64, &'static [u8])> = vec![
+            (0, b"data: {\"a\":"),
+            (80, b"1}\n"),
+            (80, b"\n"),
+            (80, b"data: {\"b\":2}\n\n"),
+        ];
+        let byte_stream = Box::pin(futures::stream::unfold(
+            chunks.into_iter(),
+            |mut chunks| async move {
+                let (delay_ms, chunk) = chunks.next()?;
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                Some((Ok::<_, reqwest::Error>(Bytes::from_static(chunk)), chunks))
+            },
+        ));
+        let keep_alive = KeepAlive {
+            interval: Duration::from_millis(30),
+            max_quiet: None,
+        };
+
+        let response = create_streaming_response_with_keep_alive(
+            byte_stream,
+            PassThrough,
+            16,
+            Some(keep_alive),
+        );
+        let body = response.body.collect().await.unwrap().to_bytes();
+
+        let keep_alive = String::from_utf8_lossy(KEEP_ALIVE_COMMENT);
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("data: {\"a\":1}\n\n"));
+        assert!(body.contains(&format!("\n\n{}", keep_alive)));
+        assert_eq!(
+            body.replace(keep_alive.as_ref(), ""),
+            "data: {\"a\":1}\n\ndata: {\"b\":2}\n\n"
+        );
+    }
+}
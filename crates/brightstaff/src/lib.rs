@@ -1,5 +1,7 @@
+pub mod discovery;
 pub mod handlers;
 pub mod router;
 pub mod state;
 pub mod tracing;
+pub mod usage;
 pub mod utils;
@@ -1,21 +1,80 @@
+use brightstaff::discovery::{
+    discovery_sources, DiscoveryScheduler, DiscoverySnapshot, DEFAULT_DISCOVERY_INTERVAL,
+    DEFAULT_DISCOVERY_JITTER,
+};
+use brightstaff::handlers::a2a::{a2a_agent_card, a2a_handler};
+use brightstaff::handlers::admin::AdminToken;
 use brightstaff::handlers::agent_chat_completions::agent_chat;
+use brightstaff::handlers::approvals::agent_approvals;
+use brightstaff::handlers::archive::{archived_conversation, ConversationArchive};
+use brightstaff::handlers::audio::audio_passthrough;
+use brightstaff::handlers::batch::batch_passthrough;
+use brightstaff::handlers::data_residency::DataResidency;
+use brightstaff::handlers::dlp::DlpScanner;
+use brightstaff::handlers::evaluation::{evaluate, Evaluator};
+use brightstaff::handlers::fault_injection::{fault_injection_enabled, FAULT_INJECTION_ENV};
 use brightstaff::handlers::function_calling::function_calling_chat_handler;
 use brightstaff::handlers::llm::llm_chat;
+use brightstaff::handlers::mcp_connector::McpConnectorPolicy;
+use brightstaff::handlers::mcp_tools::McpToolSchemaRefresher;
+use brightstaff::handlers::mcp_transport::validate_mcp_transports;
+use brightstaff::handlers::message_batches::message_batches;
 use brightstaff::handlers::models::list_models;
+use brightstaff::handlers::oidc::ListenerAuth;
+use brightstaff::handlers::pipeline_processor::validate_filter_conditions;
+use brightstaff::handlers::prompt_templates::PromptTemplates;
+use brightstaff::handlers::provenance::ProvenancePolicy;
+use brightstaff::handlers::provider_queue::{provider_queue_stats, RequestPriorities};
+use brightstaff::handlers::rate_limit_retry::{rate_limit_retry_stats, RateLimitRetry};
+use brightstaff::handlers::ratelimit_jwt::RatelimitJwt;
+use brightstaff::handlers::readiness::readiness;
+use brightstaff::handlers::realtime::realtime_proxy;
+use brightstaff::handlers::request_callout::RequestCallout;
+use brightstaff::handlers::semantic_cache::{semantic_cache_stats, SemanticCache};
+use brightstaff::handlers::session_memory::SessionSummarizer;
+use brightstaff::handlers::sessions::{
+    agent_sessions, requires_admin_token, summarize_agent_session,
+};
+use brightstaff::handlers::sse_tap::{sse_tap_streams, SseTap};
+use brightstaff::handlers::tools::{list_tools, ToolCatalog};
+use brightstaff::handlers::utils::KeepAlive;
 use brightstaff::router::llm_router::RouterService;
 use brightstaff::router::plano_orchestrator::OrchestratorService;
+use brightstaff::state::agent_session::{
+    AgentSessionStore, MemoryAgentSessionStore, PostgreSQLAgentSessionStore,
+    DEFAULT_AGENT_SESSION_TTL,
+};
 use brightstaff::state::memory::MemoryConversationalStorage;
 use brightstaff::state::postgresql::PostgreSQLConversationStorage;
 use brightstaff::state::StateStorage;
-use brightstaff::utils::tracing::init_tracer;
+use brightstaff::tracing::llm_observability::LlmObservabilityExporter;
+use brightstaff::tracing::ContentCapturePolicy;
+use brightstaff::usage::UsageExporter;
+use brightstaff::utils::connection_pool::ConnectionWarmer;
+use brightstaff::utils::egress_proxy;
+use brightstaff::utils::shutdown::{
+    drain, shutdown_signal, AGENT_SESSIONS_SNAPSHOT, DEFAULT_DRAIN_TIMEOUT, SEMANTIC_CACHE_SNAPSHOT,
+};
+use brightstaff::utils::tracing::{init_tracer, REQUEST_SPAN};
 use bytes::Bytes;
 use common::configuration::{Agent, Configuration};
 use common::consts::{
-    CHAT_COMPLETIONS_PATH, MESSAGES_PATH, OPENAI_RESPONSES_API_PATH, PLANO_ORCHESTRATOR_MODEL_NAME,
+    A2A_AGENT_CARD_PATH, A2A_PATH, AGENT_APPROVALS_PATH, AGENT_SESSIONS_PATH,
+    AGENT_SESSION_SUMMARIZE_SUFFIX, ARCH_REQUEST_ID_HEADER, ARCH_TENANT_ID_HEADER,
+    AUDIO_SPEECH_PATH, AUDIO_TRANSCRIPTIONS_PATH, CHAT_COMPLETIONS_PATH, CONVERSATION_ARCHIVE_PATH,
+    EMBEDDINGS_PATH, EVALUATION_PATH, MESSAGES_PATH, OPENAI_RESPONSES_API_PATH,
+    PLANO_ORCHESTRATOR_MODEL_NAME, PROVIDER_QUEUE_STATS_PATH, RATE_LIMIT_RETRY_STATS_PATH,
+    READYZ_PATH, REALTIME_PATH, REQUEST_ID_HEADER, SEMANTIC_CACHE_STATS_PATH, SSE_TAP_PATH,
+    TOOLS_PATH,
 };
+use common::request_id::resolve_request_id;
 use common::traces::TraceCollector;
-use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hermesllm::apis::openai_audio::AudioApi;
+use hermesllm::apis::openai_batch::BatchApi;
+use hermesllm::apis::parse_mode::set_parse_mode;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
 use hyper::body::Incoming;
+use hyper::header::{self, HeaderValue};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response, StatusCode};
@@ -23,11 +82,14 @@ use hyper_util::rt::TokioIo;
 use opentelemetry::trace::FutureExt;
 use opentelemetry::{global, Context};
 use opentelemetry_http::HeaderExtractor;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{env, fs};
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinSet;
+use tracing::{debug, info, info_span, warn, Instrument};
 
 pub mod router;
 
@@ -35,6 +97,12 @@ const BIND_ADDRESS: &str = "0.0.0.0:9091";
 const DEFAULT_ROUTING_LLM_PROVIDER: &str = "arch-router";
 const DEFAULT_ROUTING_MODEL_NAME: &str = "Arch-Router";
 
+/// Conversation state storage and the agent session store kept in the same backend
+type StateStores = (
+    Option<Arc<dyn StateStorage>>,
+    Option<Arc<dyn AgentSessionStore>>,
+);
+
 // Utility function to extract the context from the incoming request headers
 fn extract_context_from_request(req: &Request<Incoming>) -> Context {
     global::get_text_map_propagator(|propagator| {
@@ -42,12 +110,87 @@ fn extract_context_from_request(req: &Request<Incoming>) -> Context {
     })
 }
 
+/// Endpoints authorized with `overrides.admin_token` instead of the listener's oidc
+fn uses_admin_token(method: &Method, path: &str) -> bool {
+    if matches!(
+        path,
+        SSE_TAP_PATH | CONVERSATION_ARCHIVE_PATH | EVALUATION_PATH
+    ) {
+        return true;
+    }
+    let Some(path) = path.strip_prefix("/agents") else {
+        return false;
+    };
+    if path.starts_with(AGENT_APPROVALS_PATH) {
+        return true;
+    }
+    match path.strip_prefix(AGENT_SESSIONS_PATH) {
+        Some("") => requires_admin_token(method, None),
+        Some(session_path) => session_path.starts_with('/'),
+        None => false,
+    }
+}
+
 fn empty() -> BoxBody<Bytes, hyper::Error> {
     Empty::<Bytes>::new()
         .map_err(|never| match never {})
         .boxed()
 }
 
+fn header_value<'a>(req: &'a Request<Incoming>, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Returns the request id to the client, in a header and in the body of json errors
+async fn with_request_id(
+    response: Response<BoxBody<Bytes, hyper::Error>>,
+    request_id: &str,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let (mut parts, body) = response.into_parts();
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        parts.headers.insert(ARCH_REQUEST_ID_HEADER, value);
+    }
+    let is_json_error = (parts.status.is_client_error() || parts.status.is_server_error())
+        && parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json_error {
+        return Response::from_parts(parts, body);
+    }
+
+    let body = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => {
+            warn!("failed to read error response: {}", err);
+            return Response::from_parts(parts, empty());
+        }
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Object(mut error_body)) => {
+            // openai and anthropic errors nest the details in an error object
+            match error_body.get_mut("error") {
+                Some(serde_json::Value::Object(error)) => {
+                    error.insert("request_id".to_string(), request_id.into());
+                }
+                _ => {
+                    error_body.insert("request_id".to_string(), request_id.into());
+                }
+            }
+            Bytes::from(serde_json::Value::Object(error_body).to_string())
+        }
+        _ => body,
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(
+        parts,
+        Full::new(body).map_err(|never| match never {}).boxed(),
+    )
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let _tracer_provider = init_tracer();
@@ -64,7 +207,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let config: Configuration =
         serde_yaml::from_str(&config_contents).expect("Failed to parse arch_config.yaml");
 
+    validate_filter_conditions(&config.listeners).expect("invalid filter_chain condition");
+    validate_mcp_transports(config.filters.as_deref().unwrap_or_default())
+        .expect("invalid filter transport");
+
     let arch_config = Arc::new(config);
+    // before the discovery and JWKS clients are built
+    egress_proxy::init(
+        arch_config.egress_proxy.as_ref(),
+        &arch_config.model_providers,
+    );
 
     // combine agents and filters into a single list of agents
     let all_agents: Vec<Agent> = arch_config
@@ -82,6 +234,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let llm_provider_url =
         env::var("LLM_PROVIDER_ENDPOINT").unwrap_or_else(|_| "http://localhost:12001".to_string());
 
+    // Providers with warm_connections get their pooled connections reused in the background
+    if let Some(warmer) = ConnectionWarmer::new(&arch_config.model_providers, &llm_provider_url) {
+        warmer.start();
+    }
+
     let listener = TcpListener::bind(bind_address).await?;
     let routing_model_name: String = arch_config
         .routing
@@ -102,10 +259,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         routing_llm_provider.clone(),
     ));
 
-    let orchestrator_service: Arc<OrchestratorService> = Arc::new(OrchestratorService::new(
-        llm_provider_url.clone() + CHAT_COMPLETIONS_PATH,
-        PLANO_ORCHESTRATOR_MODEL_NAME.to_string(),
-    ));
+    let orchestrator_service: Arc<OrchestratorService> = Arc::new(
+        OrchestratorService::new(
+            llm_provider_url.clone() + CHAT_COMPLETIONS_PATH,
+            PLANO_ORCHESTRATOR_MODEL_NAME.to_string(),
+        )
+        .with_embedding_router(llm_provider_url.clone() + EMBEDDINGS_PATH),
+    );
 
     let model_aliases = Arc::new(arch_config.model_aliases.clone());
 
@@ -121,43 +281,288 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         );
         None
     };
-    let trace_collector = Arc::new(TraceCollector::new(tracing_enabled));
+    let llm_observability: Option<Arc<LlmObservabilityExporter>> = arch_config
+        .tracing
+        .as_ref()
+        .and_then(|tracing| tracing.llm_observability.as_ref())
+        .map(|config| Arc::new(LlmObservabilityExporter::from(config)));
+    let mut trace_collector = TraceCollector::new(tracing_enabled);
+    if let Some(exporter) = &llm_observability {
+        info!("exporting llm generations to an llm observability backend");
+        trace_collector = trace_collector.with_listener(exporter.clone());
+        let _llm_observability_flusher_handle = exporter.clone().start_background_flusher();
+    }
+    let trace_collector = Arc::new(trace_collector);
+    if fault_injection_enabled() {
+        warn!(
+            "{} is set, the fault_injection of model providers is applied",
+            FAULT_INJECTION_ENV
+        );
+    }
     let _flusher_handle = trace_collector.clone().start_background_flusher();
+    let content_capture = Arc::new(ContentCapturePolicy::from_config(
+        arch_config
+            .tracing
+            .as_ref()
+            .and_then(|tracing| tracing.capture_content.as_ref()),
+    ));
 
     // Initialize conversation state storage for v1/responses
     // Configurable via arch_config.yaml state_storage section
     // If not configured, state management is disabled
     // Environment variables are substituted by envsubst before config is read
-    let state_storage: Option<Arc<dyn StateStorage>> =
+    // Agent sessions (x-arch-session-id) are kept in the same backend
+    let snapshot_dir = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.shutdown_snapshot_dir.as_deref())
+        .map(PathBuf::from);
+    let (state_storage, session_store): StateStores =
         if let Some(storage_config) = &arch_config.state_storage {
-            let storage: Arc<dyn StateStorage> = match storage_config.storage_type {
-                common::configuration::StateStorageType::Memory => {
-                    info!("Initialized conversation state storage: Memory");
-                    Arc::new(MemoryConversationalStorage::new())
-                }
-                common::configuration::StateStorageType::Postgres => {
-                    let connection_string = storage_config
-                        .connection_string
-                        .as_ref()
-                        .expect("connection_string is required for postgres state_storage");
-
-                    debug!("Postgres connection string (full): {}", connection_string);
-                    info!("Initializing conversation state storage: Postgres");
-                    Arc::new(
-                        PostgreSQLConversationStorage::new(connection_string.clone())
+            let session_ttl = storage_config
+                .session_ttl_seconds
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_AGENT_SESSION_TTL);
+            let storage: (Arc<dyn StateStorage>, Arc<dyn AgentSessionStore>) =
+                match storage_config.storage_type {
+                    common::configuration::StateStorageType::Memory => {
+                        info!("Initialized conversation state storage: Memory");
+                        let session_store = MemoryAgentSessionStore::new(session_ttl);
+                        if let Some(dir) = &snapshot_dir {
+                            match session_store
+                                .restore(&dir.join(AGENT_SESSIONS_SNAPSHOT))
+                                .await
+                            {
+                                Ok(restored) => info!("restored {} agent sessions", restored),
+                                Err(err) => warn!("failed to restore agent sessions: {}", err),
+                            }
+                        }
+                        (
+                            Arc::new(MemoryConversationalStorage::new()),
+                            Arc::new(session_store),
+                        )
+                    }
+                    common::configuration::StateStorageType::Postgres => {
+                        let connection_string = storage_config
+                            .connection_string
+                            .as_ref()
+                            .expect("connection_string is required for postgres state_storage");
+
+                        debug!("Postgres connection string (full): {}", connection_string);
+                        info!("Initializing conversation state storage: Postgres");
+                        let storage = PostgreSQLConversationStorage::new(connection_string.clone())
                             .await
-                            .expect("Failed to initialize Postgres state storage"),
-                    )
-                }
-            };
-            Some(storage)
+                            .expect("Failed to initialize Postgres state storage");
+                        let session_store =
+                            PostgreSQLAgentSessionStore::new(storage.client(), session_ttl);
+                        (Arc::new(storage), Arc::new(session_store))
+                    }
+                };
+            (Some(storage.0), Some(storage.1))
         } else {
             info!("No state_storage configured - conversation state management disabled");
-            None
+            (None, None)
         };
 
+    // Long agent sessions are summarized with the configured model
+    let session_summarizer: Option<Arc<SessionSummarizer>> = arch_config
+        .state_storage
+        .as_ref()
+        .and_then(|storage_config| storage_config.session_summarization.as_ref())
+        .map(|config| {
+            info!("Agent sessions are summarized with {}", config.model);
+            Arc::new(SessionSummarizer::new(config, &llm_provider_url))
+        });
+
+    let tenant_header = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.tenant_header.as_deref())
+        .unwrap_or(ARCH_TENANT_ID_HEADER);
+
+    // Usage records are only collected when a usage_export sink is configured
+    let usage_exporter: Option<Arc<UsageExporter>> =
+        arch_config.usage_export.as_ref().map(|usage_export| {
+            let exporter = Arc::new(
+                UsageExporter::from_config(usage_export)
+                    .expect("invalid usage_export configuration")
+                    .with_tenant_header(tenant_header),
+            );
+            info!("Initialized usage export");
+            let _usage_flusher_handle = exporter.clone().start_background_flusher();
+            exporter
+        });
+
+    let malformed_response_retries = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.malformed_responses.as_ref())
+        .and_then(|policy| policy.max_retries)
+        .unwrap_or_default();
+    let compress_responses = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.compress_responses)
+        .unwrap_or(false);
+    let stream_keep_alive: Option<KeepAlive> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.stream_keep_alive.as_ref())
+        .map(KeepAlive::from);
+    let rate_limit_retry: Option<RateLimitRetry> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.rate_limit_retry.as_ref())
+        .map(RateLimitRetry::from);
+    let provenance_policy: Option<ProvenancePolicy> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.provenance.as_ref())
+        .map(ProvenancePolicy::from);
+    let request_priorities = Arc::new(
+        arch_config
+            .overrides
+            .as_ref()
+            .and_then(|overrides| overrides.request_priority.as_ref())
+            .map(|config| RequestPriorities::new(config, tenant_header))
+            .unwrap_or_default(),
+    );
+    let admin_token = AdminToken::new(
+        arch_config
+            .overrides
+            .as_ref()
+            .and_then(|overrides| overrides.admin_token.as_deref()),
+    );
+    let sse_tap: Option<Arc<SseTap>> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.sse_tap.as_ref())
+        .map(|config| Arc::new(SseTap::new(config, admin_token.clone())));
+    set_parse_mode(
+        arch_config
+            .overrides
+            .as_ref()
+            .and_then(|overrides| overrides.response_parsing)
+            .unwrap_or_default(),
+    );
+
+    let request_callout: Option<Arc<RequestCallout>> = arch_config
+        .request_callout
+        .as_ref()
+        .map(|config| Arc::new(RequestCallout::new(config)));
+
+    let ratelimit_jwt: Option<Arc<RatelimitJwt>> = arch_config
+        .ratelimit_jwt
+        .as_ref()
+        .map(|config| Arc::new(RatelimitJwt::new(config)));
+    let listener_auth = Arc::new(ListenerAuth::new(&arch_config.listeners)?);
+    let data_residency: Option<Arc<DataResidency>> = arch_config
+        .data_residency
+        .as_ref()
+        .map(|config| Arc::new(DataResidency::new(config, tenant_header)));
+    let dlp: Option<Arc<DlpScanner>> = arch_config
+        .dlp
+        .as_ref()
+        .map(|config| Arc::new(DlpScanner::new(config)));
+    let conversation_archive: Option<Arc<ConversationArchive>> = arch_config
+        .conversation_archive
+        .as_ref()
+        .map(|config| Arc::new(ConversationArchive::new(config, admin_token.clone())));
+    let evaluator: Option<Arc<Evaluator>> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.evaluation.as_ref())
+        .map(|config| {
+            Arc::new(Evaluator::new(
+                config,
+                &llm_provider_url,
+                admin_token.clone(),
+            ))
+        });
+
+    let mcp_connector: Option<Arc<McpConnectorPolicy>> = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.mcp_connector.as_ref())
+        .map(|config| Arc::new(McpConnectorPolicy::new(config)));
+
+    let prompt_templates = Arc::new(match arch_config.prompt_templates.as_ref() {
+        Some(configs) => PromptTemplates::new(configs).expect("invalid prompt_templates"),
+        None => PromptTemplates::default(),
+    });
+
+    let semantic_cache = Arc::new(SemanticCache::new(
+        arch_config.model_aliases.as_ref(),
+        &llm_provider_url,
+        tenant_header,
+    ));
+    if let Some(dir) = &snapshot_dir {
+        match semantic_cache.restore(&dir.join(SEMANTIC_CACHE_SNAPSHOT)) {
+            Ok(restored) => info!("restored {} semantic cache entries", restored),
+            Err(err) => warn!("failed to restore the semantic cache: {}", err),
+        }
+    }
+
+    let tool_catalog = Arc::new(ToolCatalog::new(
+        arch_config.prompt_targets.as_deref().unwrap_or_default(),
+        arch_config.filters.as_deref().unwrap_or_default(),
+    ));
+
+    // Tool schemas of the MCP filters are listed in the background, tool calls are
+    // checked against them
+    if let Some(refresher) =
+        McpToolSchemaRefresher::new(arch_config.filters.as_deref().unwrap_or_default())
+    {
+        refresher.start();
+    }
+
+    // Model lists are refreshed in the background when model_discovery is configured,
+    // /v1/models subscribes to the changes
+    let discovered_models: Option<watch::Receiver<DiscoverySnapshot>> =
+        match arch_config.model_discovery.as_ref() {
+            Some(model_discovery) => {
+                let sources = discovery_sources(&llm_providers.read().await, model_discovery);
+                info!(
+                    "Initialized model discovery for {} upstreams",
+                    sources.len()
+                );
+                let scheduler = Arc::new(DiscoveryScheduler::new(
+                    sources,
+                    model_discovery
+                        .interval_ms
+                        .map(Duration::from_millis)
+                        .unwrap_or(DEFAULT_DISCOVERY_INTERVAL),
+                    model_discovery
+                        .jitter_ms
+                        .map(Duration::from_millis)
+                        .unwrap_or(DEFAULT_DISCOVERY_JITTER),
+                ));
+                let receiver = scheduler.subscribe();
+                let _discovery_handles = scheduler.start();
+                Some(receiver)
+            }
+            None => None,
+        };
+
+    let drain_timeout = arch_config
+        .overrides
+        .as_ref()
+        .and_then(|overrides| overrides.shutdown_drain_timeout_ms)
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+    // connections stop taking new requests once this is set, the ones in flight complete
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut shutdown => break,
+            // reaps the connections that are done
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+        };
         let peer_addr = stream.peer_addr()?;
         let io = TokioIo::new(stream);
 
@@ -173,7 +578,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let listeners = listeners.clone();
         let trace_collector = trace_collector.clone();
         let state_storage = state_storage.clone();
-        let service = service_fn(move |req| {
+        let session_store = session_store.clone();
+        let session_summarizer = session_summarizer.clone();
+        let content_capture = content_capture.clone();
+        let usage_exporter = usage_exporter.clone();
+        let discovered_models = discovered_models.clone();
+        let request_callout = request_callout.clone();
+        let ratelimit_jwt = ratelimit_jwt.clone();
+        let listener_auth = listener_auth.clone();
+        let prompt_templates = prompt_templates.clone();
+        let request_priorities = request_priorities.clone();
+        let sse_tap = sse_tap.clone();
+        let semantic_cache = semantic_cache.clone();
+        let tool_catalog = tool_catalog.clone();
+        let data_residency = data_residency.clone();
+        let dlp = dlp.clone();
+        let conversation_archive = conversation_archive.clone();
+        let evaluator = evaluator.clone();
+        let mcp_connector = mcp_connector.clone();
+        let admin_token = admin_token.clone();
+        let shutdown = shutdown_receiver.clone();
+        let service = service_fn(move |mut req: Request<Incoming>| {
+            let request_id = resolve_request_id(
+                header_value(&req, ARCH_REQUEST_ID_HEADER),
+                header_value(&req, REQUEST_ID_HEADER),
+            );
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                req.headers_mut().insert(ARCH_REQUEST_ID_HEADER, value);
+            }
             let router_service = Arc::clone(&router_service);
             let orchestrator_service = Arc::clone(&orchestrator_service);
             let parent_cx = extract_context_from_request(&req);
@@ -184,8 +616,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             let listeners = listeners.clone();
             let trace_collector = trace_collector.clone();
             let state_storage = state_storage.clone();
+            let session_store = session_store.clone();
+            let session_summarizer = session_summarizer.clone();
+            let content_capture = content_capture.clone();
+            let usage_exporter = usage_exporter.clone();
+            let discovered_models = discovered_models.clone();
+            let request_callout = request_callout.clone();
+            let ratelimit_jwt = ratelimit_jwt.clone();
+            let listener_auth = listener_auth.clone();
+            let prompt_templates = prompt_templates.clone();
+            let request_priorities = request_priorities.clone();
+            let sse_tap = sse_tap.clone();
+            let semantic_cache = semantic_cache.clone();
+            let tool_catalog = tool_catalog.clone();
+            let data_residency = data_residency.clone();
+            let dlp = dlp.clone();
+            let conversation_archive = conversation_archive.clone();
+            let evaluator = evaluator.clone();
+            let mcp_connector = mcp_connector.clone();
+            let admin_token = admin_token.clone();
+            let shutdown = shutdown.clone();
 
-            async move {
+            let handler = async move {
+                // preflights carry no credentials, admin endpoints have their own token and
+                // readiness probes carry none
+                if req.method() != Method::OPTIONS
+                    && req.uri().path() != READYZ_PATH
+                    && !uses_admin_token(req.method(), req.uri().path())
+                {
+                    if let Some(oidc) = listener_auth.for_request(&req) {
+                        if let Err(unauthorized) = oidc.authenticate(&mut req).await {
+                            return Ok(unauthorized);
+                        }
+                    }
+                }
                 let path = req.uri().path();
                 // Check if path starts with /agents
                 if path.starts_with("/agents") {
@@ -195,18 +659,102 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                         stripped_path,
                         CHAT_COMPLETIONS_PATH | MESSAGES_PATH | OPENAI_RESPONSES_API_PATH
                     ) {
-                        let fully_qualified_url = format!("{}{}", llm_provider_url, stripped_path);
                         return agent_chat(
                             req,
                             orchestrator_service,
-                            fully_qualified_url,
+                            llm_provider_url,
                             agents_list,
                             listeners,
                             trace_collector,
+                            session_store,
+                            session_summarizer,
+                            stream_keep_alive,
                         )
                         .with_context(parent_cx)
                         .await;
                     }
+                    if stripped_path == AGENT_SESSIONS_PATH {
+                        return Ok(agent_sessions(req, None, session_store, &admin_token).await);
+                    }
+                    if let Some(session_id) = stripped_path
+                        .strip_prefix(AGENT_SESSIONS_PATH)
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .and_then(|rest| rest.strip_suffix(AGENT_SESSION_SUMMARIZE_SUFFIX))
+                        .filter(|id| !id.is_empty() && !id.contains('/'))
+                    {
+                        let session_id = session_id.to_string();
+                        return Ok(summarize_agent_session(
+                            req,
+                            &session_id,
+                            session_store,
+                            session_summarizer,
+                            &admin_token,
+                        )
+                        .await);
+                    }
+                    if let Some(session_id) = stripped_path
+                        .strip_prefix(AGENT_SESSIONS_PATH)
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .filter(|id| !id.is_empty() && !id.contains('/'))
+                    {
+                        let session_id = session_id.to_string();
+                        return Ok(agent_sessions(
+                            req,
+                            Some(&session_id),
+                            session_store,
+                            &admin_token,
+                        )
+                        .await);
+                    }
+                    if stripped_path == AGENT_APPROVALS_PATH {
+                        return agent_approvals(req, None, &admin_token).await;
+                    }
+                    if let Some(approval_id) = stripped_path
+                        .strip_prefix(AGENT_APPROVALS_PATH)
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .filter(|id| !id.is_empty() && !id.contains('/'))
+                    {
+                        let approval_id = approval_id.to_string();
+                        return agent_approvals(req, Some(&approval_id), &admin_token).await;
+                    }
+                    match (req.method(), stripped_path) {
+                        (&Method::POST, A2A_PATH) => {
+                            return a2a_handler(
+                                req,
+                                orchestrator_service,
+                                agents_list,
+                                listeners,
+                                trace_collector,
+                            )
+                            .with_context(parent_cx)
+                            .await;
+                        }
+                        (&Method::GET, A2A_AGENT_CARD_PATH) => {
+                            return Ok(a2a_agent_card(req, listeners).await);
+                        }
+                        _ => {}
+                    }
+                }
+                if BatchApi::from_endpoint(path) == Some(BatchApi::MessageBatches) {
+                    return message_batches(req, llm_provider_url, llm_providers, trace_collector)
+                        .with_context(parent_cx)
+                        .await;
+                }
+                if BatchApi::from_endpoint(path).is_some() {
+                    let path_and_query = req
+                        .uri()
+                        .path_and_query()
+                        .map(|pq| pq.as_str())
+                        .unwrap_or(path);
+                    let fully_qualified_url = format!("{}{}", llm_provider_url, path_and_query);
+                    return batch_passthrough(
+                        req,
+                        fully_qualified_url,
+                        llm_providers,
+                        trace_collector,
+                    )
+                    .with_context(parent_cx)
+                    .await;
                 }
                 match (req.method(), path) {
                     (
@@ -222,10 +770,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                             llm_providers,
                             trace_collector,
                             state_storage,
+                            content_capture,
+                            usage_exporter,
+                            malformed_response_retries,
+                            compress_responses,
+                            stream_keep_alive,
+                            rate_limit_retry,
+                            request_priorities,
+                            sse_tap,
+                            request_callout,
+                            ratelimit_jwt,
+                            prompt_templates,
+                            semantic_cache,
+                            data_residency,
+                            dlp,
+                            provenance_policy,
+                            conversation_archive,
+                            mcp_connector,
+                        )
+                        .with_context(parent_cx)
+                        .await
+                    }
+                    (&Method::POST, AUDIO_TRANSCRIPTIONS_PATH | AUDIO_SPEECH_PATH) => {
+                        let fully_qualified_url = format!("{}{}", llm_provider_url, path);
+                        let audio_api = AudioApi::from_endpoint(path)
+                            .expect("audio path should map to an audio api");
+                        audio_passthrough(
+                            req,
+                            audio_api,
+                            fully_qualified_url,
+                            model_aliases,
+                            trace_collector,
                         )
                         .with_context(parent_cx)
                         .await
                     }
+                    (&Method::GET, REALTIME_PATH) => {
+                        realtime_proxy(req, llm_providers, trace_collector)
+                            .with_context(parent_cx)
+                            .await
+                    }
                     (&Method::POST, "/function_calling") => {
                         let fully_qualified_url =
                             format!("{}{}", llm_provider_url, "/v1/chat/completions");
@@ -233,9 +817,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                             .with_context(parent_cx)
                             .await
                     }
-                    (&Method::GET, "/v1/models" | "/agents/v1/models") => {
-                        Ok(list_models(llm_providers).await)
+                    (&Method::GET, TOOLS_PATH) => Ok(list_tools(&tool_catalog).await),
+                    (&Method::GET, SEMANTIC_CACHE_STATS_PATH) => {
+                        Ok(semantic_cache_stats(&semantic_cache))
+                    }
+                    (&Method::GET, RATE_LIMIT_RETRY_STATS_PATH) => Ok(rate_limit_retry_stats()),
+                    (&Method::GET, PROVIDER_QUEUE_STATS_PATH) => Ok(provider_queue_stats()),
+                    (&Method::GET, READYZ_PATH) => {
+                        Ok(
+                            readiness(llm_providers, discovered_models, state_storage, shutdown)
+                                .await,
+                        )
+                    }
+                    (&Method::GET, SSE_TAP_PATH) => Ok(sse_tap_streams(
+                        sse_tap.as_deref(),
+                        req.headers(),
+                        req.uri().query(),
+                    )),
+                    (&Method::GET, CONVERSATION_ARCHIVE_PATH) => Ok(archived_conversation(
+                        conversation_archive.as_deref(),
+                        req.headers(),
+                        req.uri().query(),
+                    )),
+                    (&Method::POST, EVALUATION_PATH) => {
+                        evaluate(req, evaluator, model_aliases, llm_providers).await
                     }
+                    (&Method::GET, "/v1/models" | "/agents/v1/models") => Ok(list_models(
+                        llm_providers,
+                        discovered_models,
+                        model_aliases,
+                        req.headers(),
+                    )
+                    .await),
                     // hack for now to get openw-web-ui to work
                     (&Method::OPTIONS, "/v1/models" | "/agents/v1/models") => {
                         let mut response = Response::new(empty());
@@ -267,18 +880,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                         Ok(not_found)
                     }
                 }
+            };
+
+            // log lines of the request are prefixed with its id
+            let span = info_span!(REQUEST_SPAN, request_id = %request_id);
+            async move {
+                let response = handler.instrument(span).await?;
+                Ok::<_, hyper::Error>(with_request_id(response, &request_id).await)
             }
         });
 
-        tokio::task::spawn(async move {
+        let mut shutdown_receiver = shutdown_receiver.clone();
+        connections.spawn(async move {
             debug!("Accepted connection from {:?}", peer_addr);
-            if let Err(err) = http1::Builder::new()
+            let connection = http1::Builder::new()
                 // .serve_connection(io, service_fn(chat_completion))
                 .serve_connection(io, service)
-                .await
-            {
+                // realtime sessions upgrade the connection to a websocket
+                .with_upgrades();
+            tokio::pin!(connection);
+            let served = tokio::select! {
+                served = connection.as_mut() => served,
+                _ = shutdown_receiver.changed() => {
+                    // the request in flight and its stream complete, keep-alive ends
+                    connection.as_mut().graceful_shutdown();
+                    connection.as_mut().await
+                }
+            };
+            if let Err(err) = served {
                 warn!("Error serving connection: {:?}", err);
             }
         });
     }
+
+    drop(listener);
+    info!(
+        "draining {} connections for up to {:?}",
+        connections.len(),
+        drain_timeout
+    );
+    let _ = shutdown_sender.send(true);
+    let aborted = drain(&mut connections, drain_timeout).await;
+    if aborted > 0 {
+        warn!("closed {} connections that didn't drain in time", aborted);
+    }
+
+    // what the background flushers hold would be lost on exit
+    if let Err(err) = trace_collector.flush().await {
+        warn!("failed to flush traces on shutdown: {}", err);
+    }
+    if let Some(exporter) = &llm_observability {
+        match exporter.flush().await {
+            Ok(sent) => info!("exported {} llm generations on shutdown", sent),
+            Err(err) => warn!("failed to export llm generations on shutdown: {}", err),
+        }
+    }
+    if let Some(usage_exporter) = &usage_exporter {
+        match usage_exporter.flush().await {
+            Ok(sent) => info!("exported {} usage records on shutdown", sent),
+            Err(err) => warn!("failed to export usage records on shutdown: {}", err),
+        }
+    }
+    // the semantic cache and in-memory sessions are restored from these on the next start
+    if let Some(dir) = &snapshot_dir {
+        match semantic_cache.save(&dir.join(SEMANTIC_CACHE_SNAPSHOT)) {
+            Ok(saved) => info!("saved {} semantic cache entries on shutdown", saved),
+            Err(err) => warn!("failed to save the semantic cache on shutdown: {}", err),
+        }
+        if let Some(session_store) = &session_store {
+            match session_store.save(&dir.join(AGENT_SESSIONS_SNAPSHOT)).await {
+                Ok(saved) => info!("saved {} agent sessions on shutdown", saved),
+                Err(err) => warn!("failed to save agent sessions on shutdown: {}", err),
+            }
+        }
+    }
+    info!("shutdown complete");
+    Ok(())
 }
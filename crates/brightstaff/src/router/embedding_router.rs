@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use common::configuration::{AgentFilterChain, EmbeddingRouterConfig};
+use common::consts::ARCH_PROVIDER_HINT_HEADER;
+use hermesllm::apis::openai::{Message, Role};
+use hermesllm::transforms::ExtractText;
+use hyper::header;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info};
+
+/// Minimum similarity for a chain to be picked when the listener does not set one
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Error)]
+pub enum EmbeddingRouterError {
+    #[error("Failed to send request: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Embeddings request failed with HTTP {status}: {body}")]
+    UpstreamError { status: u16, body: String },
+
+    #[error("Failed to parse embeddings response: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Expected {expected} embeddings, got {actual}")]
+    EmbeddingCountMismatch { expected: usize, actual: usize },
+}
+
+pub type Result<T> = std::result::Result<T, EmbeddingRouterError>;
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Similarity of the request to one agent filter chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingCandidate {
+    pub agent: String,
+    pub score: f64,
+}
+
+/// Outcome of embedding based routing, reported to clients and on traces
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingDecision {
+    pub router: String,
+    pub model: String,
+    pub selected: String,
+    pub score: f64,
+    pub threshold: f64,
+    /// Set when no candidate reached the threshold and the default chain was used
+    pub fallback: bool,
+    /// Candidates by descending score
+    pub candidates: Vec<RoutingCandidate>,
+}
+
+/// Routes requests to the agent filter chain whose description is closest to the
+/// last user message. Description embeddings are computed once per model.
+pub struct EmbeddingRouter {
+    embeddings_url: String,
+    client: reqwest::Client,
+    description_embeddings: Mutex<HashMap<(String, String), Vec<f32>>>,
+}
+
+impl EmbeddingRouter {
+    pub fn new(embeddings_url: String) -> Self {
+        Self {
+            embeddings_url,
+            client: reqwest::Client::new(),
+            description_embeddings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Pick one of `agents` for the conversation, falling back to `default_agent` when
+    /// the best match scores below the confidence threshold. None when there is no
+    /// user text to route on.
+    pub async fn route(
+        &self,
+        messages: &[Message],
+        agents: &[AgentFilterChain],
+        default_agent: &AgentFilterChain,
+        config: &EmbeddingRouterConfig,
+        trace_parent: Option<String>,
+    ) -> Result<Option<(AgentFilterChain, RoutingDecision)>> {
+        let Some(query) = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.extract_text())
+            .filter(|text| !text.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+
+        let query_embedding = self.embed(&query, config, trace_parent.clone()).await?;
+        let description_embeddings = self
+            .description_embeddings(agents, config, trace_parent)
+            .await?;
+
+        let mut candidates: Vec<RoutingCandidate> = agents
+            .iter()
+            .zip(description_embeddings.iter())
+            .map(|(agent, embedding)| RoutingCandidate {
+                agent: agent.id.clone(),
+                score: cosine_similarity(&query_embedding, embedding),
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let threshold = config
+            .confidence_threshold
+            .unwrap_or(DEFAULT_CONFIDENCE_THRESHOLD);
+        let best = candidates.first().cloned();
+        let (selected, score, fallback) = match best {
+            Some(best) if best.score >= threshold => {
+                let agent = agents.iter().find(|a| a.id == best.agent).cloned();
+                (
+                    agent.unwrap_or_else(|| default_agent.clone()),
+                    best.score,
+                    false,
+                )
+            }
+            best => {
+                info!(
+                    "best embedding route scored {:.3}, below threshold {:.3}, using default agent {}",
+                    best.as_ref().map(|c| c.score).unwrap_or_default(),
+                    threshold,
+                    default_agent.id
+                );
+                (
+                    default_agent.clone(),
+                    best.map(|c| c.score).unwrap_or_default(),
+                    true,
+                )
+            }
+        };
+
+        let decision = RoutingDecision {
+            router: "embedding".to_string(),
+            model: config.model.clone(),
+            selected: selected.id.clone(),
+            score,
+            threshold,
+            fallback,
+            candidates,
+        };
+        debug!("embedding routing decision: {:?}", decision);
+        Ok(Some((selected, decision)))
+    }
+
+    async fn description_embeddings(
+        &self,
+        agents: &[AgentFilterChain],
+        config: &EmbeddingRouterConfig,
+        trace_parent: Option<String>,
+    ) -> Result<Vec<Vec<f32>>> {
+        let texts: Vec<String> = agents.iter().map(route_text).collect();
+        let missing: Vec<&str> = {
+            let cache = self.description_embeddings.lock().unwrap();
+            texts
+                .iter()
+                .filter(|text| !cache.contains_key(&(config.model.clone(), (*text).clone())))
+                .map(String::as_str)
+                .collect()
+        };
+
+        if !missing.is_empty() {
+            let embeddings = self.embed_batch(&missing, config, trace_parent).await?;
+            let mut cache = self.description_embeddings.lock().unwrap();
+            for (text, embedding) in missing.iter().zip(embeddings) {
+                cache.insert((config.model.clone(), text.to_string()), embedding);
+            }
+        }
+
+        let cache = self.description_embeddings.lock().unwrap();
+        Ok(texts
+            .into_iter()
+            .map(|text| {
+                cache
+                    .get(&(config.model.clone(), text))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect())
+    }
+
+    async fn embed(
+        &self,
+        text: &str,
+        config: &EmbeddingRouterConfig,
+        trace_parent: Option<String>,
+    ) -> Result<Vec<f32>> {
+        let mut embeddings = self.embed_batch(&[text], config, trace_parent).await?;
+        Ok(embeddings.remove(0))
+    }
+
+    async fn embed_batch(
+        &self,
+        input: &[&str],
+        config: &EmbeddingRouterConfig,
+        trace_parent: Option<String>,
+    ) -> Result<Vec<Vec<f32>>> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        if let Some(provider) = config
+            .model_provider
+            .as_deref()
+            .and_then(|provider| header::HeaderValue::from_str(provider).ok())
+        {
+            headers.insert(
+                header::HeaderName::from_static(ARCH_PROVIDER_HINT_HEADER),
+                provider,
+            );
+        }
+        if let Some(trace_parent) = trace_parent
+            .as_deref()
+            .and_then(|tp| header::HeaderValue::from_str(tp).ok())
+        {
+            headers.insert(header::HeaderName::from_static("traceparent"), trace_parent);
+        }
+
+        let request = EmbeddingsRequest {
+            model: &config.model,
+            input: input.to_vec(),
+        };
+        debug!(
+            "sending embeddings request for {} input(s) to {}",
+            input.len(),
+            self.embeddings_url
+        );
+        let response = self
+            .client
+            .post(&self.embeddings_url)
+            .headers(headers)
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(EmbeddingRouterError::UpstreamError {
+                status: status.as_u16(),
+                body,
+            });
+        }
+
+        let mut response: EmbeddingsResponse = serde_json::from_str(&body)?;
+        if response.data.len() != input.len() {
+            return Err(EmbeddingRouterError::EmbeddingCountMismatch {
+                expected: input.len(),
+                actual: response.data.len(),
+            });
+        }
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+}
+
+/// Text an agent chain is matched on, its description or its id without one
+fn route_text(agent: &AgentFilterChain) -> String {
+    agent
+        .description
+        .clone()
+        .filter(|description| !description.trim().is_empty())
+        .unwrap_or_else(|| agent.id.clone())
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (*x as f64, *y as f64);
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::MessageContent;
+    use mockito::{Matcher, Server};
+
+    fn chain(id: &str, description: &str, default: bool) -> AgentFilterChain {
+        AgentFilterChain {
+            id: id.to_string(),
+            default: Some(default),
+            description: Some(description.to_string()),
+            filter_chain: None,
+            stream_filter_chain: None,
+        }
+    }
+
+    fn config(threshold: f64) -> EmbeddingRouterConfig {
+        EmbeddingRouterConfig {
+            model: "text-embedding-3-small".to_string(),
+            model_provider: None,
+            confidence_threshold: Some(threshold),
+        }
+    }
+
+    fn user_message(text: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    fn embeddings_body(embeddings: &[[f32; 2]]) -> String {
+        let data: Vec<_> = embeddings
+            .iter()
+            .enumerate()
+            .map(|(index, embedding)| serde_json::json!({"index": index, "embedding": embedding}))
+            .collect();
+        serde_json::json!({ "data": data }).to_string()
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_route_selects_closest_chain_and_falls_back_below_threshold() {
+        let agents = vec![
+            chain("weather", "weather forecasts", true),
+            chain("flights", "flight bookings", false),
+        ];
+
+        let mut server = Server::new_async().await;
+        let descriptions = server
+            .mock("POST", "/v1/embeddings")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "input": ["weather forecasts", "flight bookings"]
+            })))
+            .with_body(embeddings_body(&[[1.0, 0.0], [0.0, 1.0]]))
+            .expect(1)
+            .create();
+        let _query = server
+            .mock("POST", "/v1/embeddings")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "input": ["book me a flight"]
+            })))
+            .with_body(embeddings_body(&[[0.6, 0.8]]))
+            .create();
+
+        let router = EmbeddingRouter::new(format!("{}/v1/embeddings", server.url()));
+        let messages = vec![user_message("book me a flight")];
+
+        let (selected, decision) = router
+            .route(&messages, &agents, &agents[0], &config(0.5), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected.id, "flights");
+        assert!(!decision.fallback);
+        assert!((decision.score - 0.8).abs() < 1e-6);
+        assert_eq!(decision.candidates[0].agent, "flights");
+        assert_eq!(decision.candidates[1].agent, "weather");
+
+        // descriptions are cached, only the query is embedded again
+        let (selected, decision) = router
+            .route(&messages, &agents, &agents[0], &config(0.9), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected.id, "weather");
+        assert!(decision.fallback);
+        descriptions.assert();
+    }
+
+    #[tokio::test]
+    async fn test_route_without_user_text() {
+        let router = EmbeddingRouter::new("http://localhost:1/v1/embeddings".to_string());
+        let agents = vec![chain("weather", "weather forecasts", true)];
+        let result = router
+            .route(&[], &agents, &agents[0], &config(0.5), None)
+            .await
+            .unwrap();
+        assert!(result.is_none());
+    }
+}
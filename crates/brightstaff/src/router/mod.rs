@@ -1,6 +1,8 @@
+pub mod embedding_router;
 pub mod llm_router;
 pub mod orchestrator_model;
 pub mod orchestrator_model_v1;
 pub mod plano_orchestrator;
 pub mod router_model;
 pub mod router_model_v1;
+pub mod traffic_split;
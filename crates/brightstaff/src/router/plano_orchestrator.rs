@@ -9,6 +9,7 @@ use hyper::header;
 use thiserror::Error;
 use tracing::{debug, info, warn};
 
+use crate::router::embedding_router::EmbeddingRouter;
 use crate::router::orchestrator_model_v1::{self};
 
 use super::orchestrator_model::OrchestratorModel;
@@ -17,6 +18,7 @@ pub struct OrchestratorService {
     orchestrator_url: String,
     client: reqwest::Client,
     orchestrator_model: Arc<dyn OrchestratorModel>,
+    embedding_router: Option<Arc<EmbeddingRouter>>,
 }
 
 #[derive(Debug, Error)]
@@ -48,9 +50,20 @@ impl OrchestratorService {
             orchestrator_url,
             client: reqwest::Client::new(),
             orchestrator_model,
+            embedding_router: None,
         }
     }
 
+    /// Enable the `embedding` router for listeners configured with it
+    pub fn with_embedding_router(mut self, embeddings_url: String) -> Self {
+        self.embedding_router = Some(Arc::new(EmbeddingRouter::new(embeddings_url)));
+        self
+    }
+
+    pub fn embedding_router(&self) -> Option<&EmbeddingRouter> {
+        self.embedding_router.as_deref()
+    }
+
     pub async fn determine_orchestration(
         &self,
         messages: &[Message],
@@ -0,0 +1,136 @@
+use common::configuration::ModelAlias;
+use hyper::HeaderMap;
+use sha2::{Digest, Sha256};
+
+/// Label of requests to an alias that weren't picked for any split
+pub const CONTROL_LABEL: &str = "control";
+
+/// Requests are assigned one of this many buckets, so weights have two decimals
+const BUCKETS: u64 = 10_000;
+
+/// Model a request to an alias with traffic splits was sent to
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitDecision {
+    pub alias: String,
+    pub target: String,
+    /// Label of the split, `control` for requests sent to the alias target
+    pub label: String,
+}
+
+/// Picks the model for a request to an alias with traffic splits. Requests carrying the
+/// sticky header are hashed on its value so a client always lands on the same split,
+/// others are assigned at random. Returns None for aliases without splits.
+pub fn select_split(
+    alias_name: &str,
+    alias: &ModelAlias,
+    request_headers: &HeaderMap,
+) -> Option<SplitDecision> {
+    let splits = alias.splits.as_ref().filter(|splits| !splits.is_empty())?;
+    let sticky_value = alias
+        .sticky_header
+        .as_deref()
+        .and_then(|header| request_headers.get(header))
+        .and_then(|value| value.to_str().ok());
+    let bucket = match sticky_value {
+        Some(value) => sticky_bucket(alias_name, value),
+        None => rand::random_range(0..BUCKETS),
+    };
+
+    let mut upper = 0.0;
+    for split in splits {
+        upper += split.weight * (BUCKETS as f64 / 100.0);
+        if (bucket as f64) < upper {
+            return Some(SplitDecision {
+                alias: alias_name.to_string(),
+                target: split.target.clone(),
+                label: split.label.clone().unwrap_or_else(|| split.target.clone()),
+            });
+        }
+    }
+    Some(SplitDecision {
+        alias: alias_name.to_string(),
+        target: alias.target.clone(),
+        label: CONTROL_LABEL.to_string(),
+    })
+}
+
+/// Stable across restarts and gateway replicas, unlike the std hasher
+fn sticky_bucket(alias_name: &str, value: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(alias_name.as_bytes())
+        .chain_update(b":")
+        .chain_update(value.as_bytes())
+        .finalize();
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(prefix) % BUCKETS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::configuration::TrafficSplit;
+
+    fn alias(weight: f64) -> ModelAlias {
+        ModelAlias {
+            target: "gpt-4o".to_string(),
+            splits: Some(vec![TrafficSplit {
+                target: "claude-sonnet-4".to_string(),
+                weight,
+                label: Some("sonnet".to_string()),
+            }]),
+            sticky_header: Some("x-user-id".to_string()),
+            shadow: None,
+            prompt_template: None,
+            system_prompt_policy: None,
+            semantic_cache: None,
+        }
+    }
+
+    fn headers(user: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-user-id", user.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_no_splits() {
+        let mut alias = alias(10.0);
+        alias.splits = None;
+        assert_eq!(select_split("chat", &alias, &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_sticky_split() {
+        let alias = alias(50.0);
+        let first = select_split("chat", &alias, &headers("user-1")).unwrap();
+        for _ in 0..20 {
+            assert_eq!(
+                select_split("chat", &alias, &headers("user-1")).unwrap(),
+                first
+            );
+        }
+
+        let labels: Vec<String> = (0..200)
+            .map(|i| {
+                select_split("chat", &alias, &headers(&format!("user-{}", i)))
+                    .unwrap()
+                    .label
+            })
+            .collect();
+        let sonnet = labels.iter().filter(|label| *label == "sonnet").count();
+        assert!(sonnet > 60 && sonnet < 140, "sonnet got {} of 200", sonnet);
+        assert!(labels.iter().any(|label| label == CONTROL_LABEL));
+    }
+
+    #[test]
+    fn test_weights_at_the_edges() {
+        let decision = select_split("chat", &alias(100.0), &HeaderMap::new()).unwrap();
+        assert_eq!(decision.target, "claude-sonnet-4");
+        assert_eq!(decision.label, "sonnet");
+
+        let decision = select_split("chat", &alias(0.0), &headers("user-1")).unwrap();
+        assert_eq!(decision.target, "gpt-4o");
+        assert_eq!(decision.label, CONTROL_LABEL);
+    }
+}
@@ -0,0 +1,639 @@
+use super::StateStorageError;
+use async_trait::async_trait;
+use hermesllm::apis::OpenAIMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{OnceCell, RwLock};
+use tokio_postgres::Client;
+use tracing::{debug, info};
+
+use crate::utils::shutdown::{read_snapshot, write_snapshot};
+
+/// Sessions not updated for this long are dropped
+pub const DEFAULT_AGENT_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Conversation of an agent listener that outlives a single request. Prior turns and
+/// agent responses are prepended to the messages of the next request of the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentSession {
+    pub session_id: String,
+
+    /// Listener the session was created for, if known
+    pub listener: Option<String>,
+
+    /// Accumulated conversation, oldest message first
+    pub messages: Vec<OpenAIMessage>,
+
+    /// Rolling summary of the messages dropped from the start of the conversation
+    #[serde(default)]
+    pub summary: Option<String>,
+
+    /// Unix timestamp (seconds) when the session was created
+    pub created_at: i64,
+
+    /// Unix timestamp (seconds) of the last update
+    pub updated_at: i64,
+}
+
+impl AgentSession {
+    pub fn new(session_id: String, listener: Option<String>) -> Self {
+        let now = unix_now();
+        Self {
+            session_id,
+            listener,
+            messages: Vec::new(),
+            summary: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Summary of a session returned when listing, without the conversation itself
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentSessionSummary {
+    pub session_id: String,
+    pub listener: Option<String>,
+    pub message_count: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<&AgentSession> for AgentSessionSummary {
+    fn from(session: &AgentSession) -> Self {
+        Self {
+            session_id: session.session_id.clone(),
+            listener: session.listener.clone(),
+            message_count: session.messages.len(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Trait for agent session storage backends
+#[async_trait]
+pub trait AgentSessionStore: Send + Sync {
+    /// Create an empty session, replacing any existing session with the same id
+    async fn create(&self, session: AgentSession) -> Result<(), StateStorageError>;
+
+    /// Retrieve a session, expired sessions are reported as not found
+    async fn get(&self, session_id: &str) -> Result<AgentSession, StateStorageError>;
+
+    /// Append messages to a session, creating it if it does not exist yet
+    async fn append(
+        &self,
+        session_id: &str,
+        listener: Option<&str>,
+        messages: Vec<OpenAIMessage>,
+    ) -> Result<(), StateStorageError>;
+
+    /// Replace the first `summarized` messages of a session with `summary`. Messages
+    /// appended meanwhile are kept.
+    async fn compact(
+        &self,
+        session_id: &str,
+        summary: String,
+        summarized: usize,
+    ) -> Result<(), StateStorageError>;
+
+    /// List sessions that have not expired
+    async fn list(&self) -> Result<Vec<AgentSessionSummary>, StateStorageError>;
+
+    async fn delete(&self, session_id: &str) -> Result<(), StateStorageError>;
+
+    /// Writes the sessions to a snapshot on shutdown. Backends that store sessions as
+    /// they change have nothing to write.
+    async fn save(&self, _path: &Path) -> Result<usize, StateStorageError> {
+        Ok(0)
+    }
+}
+
+/// In-memory agent session storage with an idle TTL
+#[derive(Clone)]
+pub struct MemoryAgentSessionStore {
+    sessions: Arc<RwLock<HashMap<String, AgentSession>>>,
+    ttl: Duration,
+}
+
+impl MemoryAgentSessionStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    fn is_expired(&self, session: &AgentSession) -> bool {
+        unix_now() - session.updated_at >= self.ttl.as_secs() as i64
+    }
+
+    /// Restores the sessions saved on the last shutdown, the ones that expired meanwhile
+    /// are dropped
+    pub async fn restore(&self, path: &Path) -> Result<usize, StateStorageError> {
+        let saved = read_snapshot::<Vec<AgentSession>>(path)
+            .map_err(|e| StateStorageError::StorageError(e.to_string()))?
+            .unwrap_or_default();
+        let mut sessions = self.sessions.write().await;
+        let mut restored = 0;
+        for session in saved {
+            if self.is_expired(&session) {
+                continue;
+            }
+            sessions.insert(session.session_id.clone(), session);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+}
+
+impl Default for MemoryAgentSessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_AGENT_SESSION_TTL)
+    }
+}
+
+#[async_trait]
+impl AgentSessionStore for MemoryAgentSessionStore {
+    async fn create(&self, session: AgentSession) -> Result<(), StateStorageError> {
+        debug!("creating agent session {}", session.session_id);
+        self.sessions
+            .write()
+            .await
+            .insert(session.session_id.clone(), session);
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<AgentSession, StateStorageError> {
+        let mut sessions = self.sessions.write().await;
+        match sessions.get(session_id) {
+            Some(session) if self.is_expired(session) => {
+                info!("agent session {} expired", session_id);
+                sessions.remove(session_id);
+                Err(StateStorageError::NotFound(session_id.to_string()))
+            }
+            Some(session) => Ok(session.clone()),
+            None => Err(StateStorageError::NotFound(session_id.to_string())),
+        }
+    }
+
+    async fn append(
+        &self,
+        session_id: &str,
+        listener: Option<&str>,
+        messages: Vec<OpenAIMessage>,
+    ) -> Result<(), StateStorageError> {
+        let mut sessions = self.sessions.write().await;
+        if sessions
+            .get(session_id)
+            .is_some_and(|session| self.is_expired(session))
+        {
+            sessions.remove(session_id);
+        }
+        let session = sessions.entry(session_id.to_string()).or_insert_with(|| {
+            AgentSession::new(session_id.to_string(), listener.map(str::to_string))
+        });
+        session.messages.extend(messages);
+        session.updated_at = unix_now();
+        debug!(
+            "agent session {} now has {} messages",
+            session_id,
+            session.messages.len()
+        );
+        Ok(())
+    }
+
+    async fn compact(
+        &self,
+        session_id: &str,
+        summary: String,
+        summarized: usize,
+    ) -> Result<(), StateStorageError> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| StateStorageError::NotFound(session_id.to_string()))?;
+        let summarized = summarized.min(session.messages.len());
+        session.messages.drain(..summarized);
+        session.summary = Some(summary);
+        debug!(
+            "agent session {} compacted, {} messages summarized",
+            session_id, summarized
+        );
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<AgentSessionSummary>, StateStorageError> {
+        let mut sessions = self.sessions.write().await;
+        sessions.retain(|_, session| !self.is_expired(session));
+        let mut summaries: Vec<AgentSessionSummary> =
+            sessions.values().map(AgentSessionSummary::from).collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.updated_at));
+        Ok(summaries)
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), StateStorageError> {
+        match self.sessions.write().await.remove(session_id) {
+            Some(_) => Ok(()),
+            None => Err(StateStorageError::NotFound(session_id.to_string())),
+        }
+    }
+
+    async fn save(&self, path: &Path) -> Result<usize, StateStorageError> {
+        let sessions = self.sessions.read().await;
+        let saved: Vec<&AgentSession> = sessions
+            .values()
+            .filter(|session| !self.is_expired(session))
+            .collect();
+        write_snapshot(path, &saved).map_err(|e| StateStorageError::StorageError(e.to_string()))?;
+        Ok(saved.len())
+    }
+}
+
+/// PostgreSQL agent session storage, schema in docs/db_setup/agent_sessions.sql
+#[derive(Clone)]
+pub struct PostgreSQLAgentSessionStore {
+    client: Arc<Client>,
+    ttl: Duration,
+    table_verified: Arc<OnceCell<()>>,
+}
+
+impl PostgreSQLAgentSessionStore {
+    pub fn new(client: Arc<Client>, ttl: Duration) -> Self {
+        Self {
+            client,
+            ttl,
+            table_verified: Arc::new(OnceCell::new()),
+        }
+    }
+
+    fn expires_before(&self) -> i64 {
+        unix_now() - self.ttl.as_secs() as i64
+    }
+
+    async fn ensure_ready(&self) -> Result<(), StateStorageError> {
+        self.table_verified
+            .get_or_try_init(|| async {
+                let row = self
+                    .client
+                    .query_one(
+                        "SELECT EXISTS (
+                            SELECT FROM pg_tables
+                            WHERE tablename = 'agent_sessions'
+                        )",
+                        &[],
+                    )
+                    .await
+                    .map_err(|e| {
+                        StateStorageError::StorageError(format!(
+                            "Failed to verify table existence: {}",
+                            e
+                        ))
+                    })?;
+
+                let exists: bool = row.get(0);
+                if !exists {
+                    return Err(StateStorageError::StorageError(
+                        "Table 'agent_sessions' does not exist. \
+                         Please run the setup SQL from docs/db_setup/agent_sessions.sql"
+                            .to_string(),
+                    ));
+                }
+
+                info!("Agent session storage table verified");
+                Ok(())
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn messages_to_json(messages: &[OpenAIMessage]) -> Result<serde_json::Value, StateStorageError> {
+    serde_json::to_value(messages).map_err(|e| {
+        StateStorageError::SerializationError(format!("Failed to serialize messages: {}", e))
+    })
+}
+
+#[async_trait]
+impl AgentSessionStore for PostgreSQLAgentSessionStore {
+    async fn create(&self, session: AgentSession) -> Result<(), StateStorageError> {
+        self.ensure_ready().await?;
+        let messages = messages_to_json(&session.messages)?;
+        self.client
+            .execute(
+                r#"
+                INSERT INTO agent_sessions
+                    (session_id, listener, messages, summary, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (session_id)
+                DO UPDATE SET
+                    listener = EXCLUDED.listener,
+                    messages = EXCLUDED.messages,
+                    summary = EXCLUDED.summary,
+                    created_at = EXCLUDED.created_at,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+                &[
+                    &session.session_id,
+                    &session.listener,
+                    &messages,
+                    &session.summary,
+                    &session.created_at,
+                    &session.updated_at,
+                ],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!(
+                    "Failed to create agent session {}: {}",
+                    session.session_id, e
+                ))
+            })?;
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<AgentSession, StateStorageError> {
+        self.ensure_ready().await?;
+        let row = self
+            .client
+            .query_opt(
+                r#"
+                SELECT session_id, listener, messages, summary, created_at, updated_at
+                FROM agent_sessions
+                WHERE session_id = $1 AND updated_at > $2
+                "#,
+                &[&session_id, &self.expires_before()],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!(
+                    "Failed to fetch agent session {}: {}",
+                    session_id, e
+                ))
+            })?
+            .ok_or_else(|| StateStorageError::NotFound(session_id.to_string()))?;
+
+        let messages: serde_json::Value = row.get("messages");
+        Ok(AgentSession {
+            session_id: row.get("session_id"),
+            listener: row.get("listener"),
+            messages: serde_json::from_value(messages).map_err(|e| {
+                StateStorageError::SerializationError(format!(
+                    "Failed to deserialize messages: {}",
+                    e
+                ))
+            })?,
+            summary: row.get("summary"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    async fn append(
+        &self,
+        session_id: &str,
+        listener: Option<&str>,
+        messages: Vec<OpenAIMessage>,
+    ) -> Result<(), StateStorageError> {
+        self.ensure_ready().await?;
+        let messages = messages_to_json(&messages)?;
+        let now = unix_now();
+        // expired sessions are restarted instead of extended
+        self.client
+            .execute(
+                r#"
+                INSERT INTO agent_sessions
+                    (session_id, listener, messages, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $4)
+                ON CONFLICT (session_id)
+                DO UPDATE SET
+                    messages = CASE
+                        WHEN agent_sessions.updated_at > $5
+                        THEN agent_sessions.messages || EXCLUDED.messages
+                        ELSE EXCLUDED.messages
+                    END,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+                &[
+                    &session_id,
+                    &listener,
+                    &messages,
+                    &now,
+                    &self.expires_before(),
+                ],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!(
+                    "Failed to update agent session {}: {}",
+                    session_id, e
+                ))
+            })?;
+        Ok(())
+    }
+
+    async fn compact(
+        &self,
+        session_id: &str,
+        summary: String,
+        summarized: usize,
+    ) -> Result<(), StateStorageError> {
+        self.ensure_ready().await?;
+        // ordinality starts at 1, messages past `summarized` are kept in order
+        let rows_affected = self
+            .client
+            .execute(
+                r#"
+                UPDATE agent_sessions
+                SET summary = $2,
+                    messages = COALESCE(
+                        (SELECT jsonb_agg(message ORDER BY position)
+                         FROM jsonb_array_elements(messages) WITH ORDINALITY AS m(message, position)
+                         WHERE position > $3),
+                        '[]'::jsonb
+                    )
+                WHERE session_id = $1
+                "#,
+                &[&session_id, &summary, &(summarized as i64)],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!(
+                    "Failed to compact agent session {}: {}",
+                    session_id, e
+                ))
+            })?;
+
+        if rows_affected == 0 {
+            return Err(StateStorageError::NotFound(session_id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<AgentSessionSummary>, StateStorageError> {
+        self.ensure_ready().await?;
+        let rows = self
+            .client
+            .query(
+                r#"
+                SELECT session_id, listener, jsonb_array_length(messages) AS message_count,
+                    created_at, updated_at
+                FROM agent_sessions
+                WHERE updated_at > $1
+                ORDER BY updated_at DESC
+                "#,
+                &[&self.expires_before()],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!("Failed to list agent sessions: {}", e))
+            })?;
+
+        Ok(rows
+            .iter()
+            .map(|row| AgentSessionSummary {
+                session_id: row.get("session_id"),
+                listener: row.get("listener"),
+                message_count: row.get::<_, i32>("message_count") as usize,
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), StateStorageError> {
+        self.ensure_ready().await?;
+        let rows_affected = self
+            .client
+            .execute(
+                "DELETE FROM agent_sessions WHERE session_id = $1",
+                &[&session_id],
+            )
+            .await
+            .map_err(|e| {
+                StateStorageError::StorageError(format!(
+                    "Failed to delete agent session {}: {}",
+                    session_id, e
+                ))
+            })?;
+
+        if rows_affected == 0 {
+            return Err(StateStorageError::NotFound(session_id.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::{MessageContent, Role};
+
+    fn user_message(text: &str) -> OpenAIMessage {
+        OpenAIMessage {
+            role: Role::User,
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_append_creates_and_extends_session() {
+        let store = MemoryAgentSessionStore::default();
+        store
+            .append("s1", Some("agents"), vec![user_message("hi")])
+            .await
+            .unwrap();
+        store
+            .append("s1", Some("agents"), vec![user_message("again")])
+            .await
+            .unwrap();
+
+        let session = store.get("s1").await.unwrap();
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.listener.as_deref(), Some("agents"));
+
+        let summaries = store.list().await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 2);
+
+        store.delete("s1").await.unwrap();
+        assert!(matches!(
+            store.get("s1").await,
+            Err(StateStorageError::NotFound(_))
+        ));
+        assert!(store.delete("s1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_restore() {
+        let path =
+            std::env::temp_dir().join(format!("agent-sessions-{}.json", uuid::Uuid::new_v4()));
+        let store = MemoryAgentSessionStore::default();
+        store
+            .append("s1", Some("agents"), vec![user_message("hello")])
+            .await
+            .unwrap();
+        assert_eq!(store.save(&path).await.unwrap(), 1);
+
+        let restarted = MemoryAgentSessionStore::default();
+        assert_eq!(restarted.restore(&path).await.unwrap(), 1);
+        let session = restarted.get("s1").await.unwrap();
+        assert_eq!(session.listener.as_deref(), Some("agents"));
+        assert_eq!(session.messages.len(), 1);
+        assert_eq!(restarted.restore(&path).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_sessions_are_dropped() {
+        let store = MemoryAgentSessionStore::new(Duration::ZERO);
+        store
+            .create(AgentSession::new("s1".to_string(), None))
+            .await
+            .unwrap();
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(store.get("s1").await.is_err());
+
+        // appending to an expired session starts over
+        store
+            .append("s2", None, vec![user_message("one")])
+            .await
+            .unwrap();
+        store
+            .append("s2", None, vec![user_message("two")])
+            .await
+            .unwrap();
+        let sessions = store.sessions.read().await;
+        assert_eq!(sessions["s2"].messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compact_replaces_oldest_messages() {
+        let store = MemoryAgentSessionStore::default();
+        let messages = ["one", "two", "three"].map(user_message).to_vec();
+        store.append("s1", None, messages).await.unwrap();
+        store
+            .compact("s1", "counted to two".to_string(), 2)
+            .await
+            .unwrap();
+
+        let session = store.get("s1").await.unwrap();
+        assert_eq!(session.summary.as_deref(), Some("counted to two"));
+        assert_eq!(session.messages.len(), 1);
+        assert_eq!(session.messages[0].content.to_string(), "three");
+        assert!(store.compact("s2", String::new(), 1).await.is_err());
+    }
+}
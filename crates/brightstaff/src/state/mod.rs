@@ -8,6 +8,7 @@ use std::fmt;
 use std::sync::Arc;
 use tracing::debug;
 
+pub mod agent_session;
 pub mod memory;
 pub mod postgresql;
 pub mod response_state_processor;
@@ -75,6 +76,11 @@ pub trait StateStorage: Send + Sync {
     /// Delete state for a response_id (optional, for cleanup)
     async fn delete(&self, response_id: &str) -> Result<(), StateStorageError>;
 
+    /// Whether the backend can be reached, checked by /readyz
+    async fn ping(&self) -> Result<(), StateStorageError> {
+        Ok(())
+    }
+
     fn merge(
         &self,
         prev_state: &OpenAIConversationState,
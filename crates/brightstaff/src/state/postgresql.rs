@@ -35,6 +35,11 @@ impl PostgreSQLConversationStorage {
         })
     }
 
+    /// Client shared with the agent session store so both use one connection
+    pub fn client(&self) -> Arc<Client> {
+        self.client.clone()
+    }
+
     /// Ensures the conversation_states table exists (checks once, caches result)
     async fn ensure_ready(&self) -> Result<(), StateStorageError> {
         self.table_verified
@@ -219,6 +224,14 @@ impl StateStorage for PostgreSQLConversationStorage {
         debug!("Deleted conversation state for {}", response_id);
         Ok(())
     }
+
+    async fn ping(&self) -> Result<(), StateStorageError> {
+        self.client
+            .simple_query("SELECT 1")
+            .await
+            .map(|_| ())
+            .map_err(|e| StateStorageError::StorageError(format!("Failed to ping database: {}", e)))
+    }
 }
 
 /*
@@ -38,6 +38,10 @@ pub mod http {
 
     /// Size of the response payload body in bytes
     pub const RESPONSE_CONTENT_LENGTH: &str = "http.response_content_length";
+
+    /// Id correlating the request across gateway components and providers
+    /// Example: "469793af-b25f-4b57-b265-f376e8d8c586"
+    pub const REQUEST_ID: &str = "http.request_id";
 }
 
 // =============================================================================
@@ -94,6 +98,91 @@ pub mod llm {
 
     /// Preview of the user message (truncated)
     pub const USER_MESSAGE_PREVIEW: &str = "llm.user_message_preview";
+
+    /// Model alias requested by the client, set when the alias splits traffic
+    /// Example: "chat.default"
+    pub const MODEL_ALIAS: &str = "llm.model_alias";
+
+    /// Traffic split of the model alias the request was assigned to
+    /// Example: "control", "sonnet"
+    pub const TRAFFIC_SPLIT: &str = "llm.traffic_split";
+
+    /// Model whose request was mirrored, set on the spans of shadow requests
+    /// Example: "gpt-4o"
+    pub const SHADOW_OF: &str = "llm.shadow_of";
+
+    /// Similarity of the shadow and the primary completion, from 0 to 1
+    /// Example: "0.82"
+    pub const SHADOW_CONTENT_SIMILARITY: &str = "llm.shadow.content_similarity";
+
+    /// Prompt template rendered into the system prompt, with its version
+    /// Example: "support@v3"
+    pub const PROMPT_TEMPLATE: &str = "llm.prompt_template";
+
+    /// Outcome of the semantic cache lookup, "hit" or "miss"
+    pub const SEMANTIC_CACHE: &str = "llm.semantic_cache";
+
+    /// Tenant whose data residency policy applied to the request
+    pub const RESIDENCY_TENANT: &str = "llm.residency.tenant";
+
+    /// Residency of the provider the request was routed to
+    pub const RESIDENCY: &str = "llm.residency.provider";
+
+    /// Outcome of the data residency policy, "allowed" or "denied"
+    pub const RESIDENCY_DECISION: &str = "llm.residency.decision";
+}
+
+// =============================================================================
+// Span Attributes - GenAI Semantic Conventions
+// =============================================================================
+
+/// OpenTelemetry GenAI semantic conventions, understood by LLM observability tools
+/// See: https://opentelemetry.io/docs/specs/semconv/gen-ai/
+pub mod gen_ai {
+    /// Provider family of the model
+    /// Example: "openai", "anthropic", "aws.bedrock"
+    pub const SYSTEM: &str = "gen_ai.system";
+
+    /// Kind of operation requested from the model
+    /// Example: "chat"
+    pub const OPERATION_NAME: &str = "gen_ai.operation.name";
+
+    /// Model the client asked for, after alias resolution
+    pub const REQUEST_MODEL: &str = "gen_ai.request.model";
+
+    /// Temperature parameter of the request
+    pub const REQUEST_TEMPERATURE: &str = "gen_ai.request.temperature";
+
+    /// Model that produced the response, as reported by the provider
+    pub const RESPONSE_MODEL: &str = "gen_ai.response.model";
+
+    /// Identifier of the completion, as reported by the provider
+    pub const RESPONSE_ID: &str = "gen_ai.response.id";
+
+    /// Reasons the model stopped generating, one per choice
+    /// Example: "stop", "end_turn", "tool_calls"
+    pub const RESPONSE_FINISH_REASONS: &str = "gen_ai.response.finish_reasons";
+
+    /// Number of tokens in the prompt
+    pub const USAGE_INPUT_TOKENS: &str = "gen_ai.usage.input_tokens";
+
+    /// Number of tokens in the completion
+    pub const USAGE_OUTPUT_TOKENS: &str = "gen_ai.usage.output_tokens";
+
+    /// Event carrying the captured prompt
+    pub const CONTENT_PROMPT: &str = "gen_ai.content.prompt";
+
+    /// Event carrying the captured completion
+    pub const CONTENT_COMPLETION: &str = "gen_ai.content.completion";
+
+    /// Prompt messages, attribute of the prompt event
+    pub const PROMPT: &str = "gen_ai.prompt";
+
+    /// Completion text, attribute of the completion event
+    pub const COMPLETION: &str = "gen_ai.completion";
+
+    /// Operation name of chat completions, messages and responses requests
+    pub const OPERATION_CHAT: &str = "chat";
 }
 
 // =============================================================================
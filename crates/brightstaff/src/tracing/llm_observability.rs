@@ -0,0 +1,451 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use common::configuration::{LlmObservabilityConfig, LlmObservabilityExporterConfig};
+use common::traces::{Attribute, AttributeValue, ResourceSpanBuilder, Span, SpanListener};
+use serde_json::{json, Map, Value};
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+use super::constants::{gen_ai, http, llm, operation_component};
+
+const DEFAULT_LANGFUSE_HOST: &str = "https://cloud.langfuse.com";
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+/// Generations kept while the backend is unreachable, the oldest are dropped beyond this
+const MAX_BUFFERED_GENERATIONS: usize = 10_000;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Resource service name of the spans sent to an OTLP backend
+const OTLP_SERVICE_NAME: &str = "archgw";
+
+/// One llm call as llm observability backends model it, read from the attributes and
+/// content events of an llm span
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Generation {
+    pub trace_id: String,
+    pub span_id: String,
+    pub name: String,
+    pub request_id: Option<String>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    /// Unix time in nanoseconds
+    pub start_time: u128,
+    pub end_time: u128,
+    pub time_to_first_token_ms: Option<u128>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub temperature: Option<f64>,
+    pub status: Option<u16>,
+    pub streaming: bool,
+    /// Captured prompt and completion, only set with content capture mode `full`
+    pub prompt: Option<String>,
+    pub completion: Option<String>,
+}
+
+impl Generation {
+    pub fn from_span(span: &Span) -> Self {
+        let attribute = |key: &str| {
+            span.attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .and_then(|attribute| attribute.value.string_value.clone())
+        };
+        let event_attribute = |event_name: &str, key: &str| {
+            span.events
+                .iter()
+                .flatten()
+                .filter(|event| event.name == event_name)
+                .flat_map(|event| event.attributes.iter())
+                .find(|attribute| attribute.key == key)
+                .and_then(|attribute| attribute.value.string_value.clone())
+        };
+        Generation {
+            trace_id: span.trace_id.clone(),
+            span_id: span.span_id.clone(),
+            name: span.name.clone(),
+            request_id: attribute(http::REQUEST_ID),
+            model: attribute(gen_ai::RESPONSE_MODEL).or_else(|| attribute(llm::MODEL_NAME)),
+            provider: attribute(gen_ai::SYSTEM),
+            start_time: span.start_time_unix_nano.parse().unwrap_or_default(),
+            end_time: span.end_time_unix_nano.parse().unwrap_or_default(),
+            time_to_first_token_ms: attribute(llm::TIME_TO_FIRST_TOKEN_MS)
+                .and_then(|ttft| ttft.parse().ok()),
+            input_tokens: attribute(gen_ai::USAGE_INPUT_TOKENS).and_then(|n| n.parse().ok()),
+            output_tokens: attribute(gen_ai::USAGE_OUTPUT_TOKENS).and_then(|n| n.parse().ok()),
+            temperature: attribute(gen_ai::REQUEST_TEMPERATURE).and_then(|t| t.parse().ok()),
+            status: attribute(http::STATUS_CODE).and_then(|status| status.parse().ok()),
+            streaming: attribute(llm::IS_STREAMING).is_some_and(|streaming| streaming == "true"),
+            prompt: event_attribute(gen_ai::CONTENT_PROMPT, gen_ai::PROMPT),
+            completion: event_attribute(gen_ai::CONTENT_COMPLETION, gen_ai::COMPLETION),
+        }
+    }
+
+    /// A trace-create and a generation-create event of the Langfuse ingestion api
+    pub fn langfuse_events(&self) -> Vec<Value> {
+        let start_time = rfc3339(self.start_time);
+        let mut generation = Map::new();
+        generation.insert("id".to_string(), json!(self.span_id));
+        generation.insert("traceId".to_string(), json!(self.trace_id));
+        generation.insert("name".to_string(), json!(self.name));
+        generation.insert("startTime".to_string(), json!(start_time));
+        generation.insert("endTime".to_string(), json!(rfc3339(self.end_time)));
+        if let Some(ttft) = self.time_to_first_token_ms {
+            generation.insert(
+                "completionStartTime".to_string(),
+                json!(rfc3339(self.start_time + ttft * 1_000_000)),
+            );
+        }
+        if let Some(model) = &self.model {
+            generation.insert("model".to_string(), json!(model));
+        }
+        if let Some(temperature) = self.temperature {
+            generation.insert(
+                "modelParameters".to_string(),
+                json!({ "temperature": temperature }),
+            );
+        }
+        if let Some(prompt) = &self.prompt {
+            generation.insert("input".to_string(), json!(prompt));
+        }
+        if let Some(completion) = &self.completion {
+            generation.insert("output".to_string(), json!(completion));
+        }
+        if self.input_tokens.is_some() || self.output_tokens.is_some() {
+            generation.insert(
+                "usage".to_string(),
+                json!({
+                    "input": self.input_tokens,
+                    "output": self.output_tokens,
+                    "unit": "TOKENS",
+                }),
+            );
+        }
+        if let Some(status) = self.status.filter(|status| *status >= 400) {
+            generation.insert("level".to_string(), json!("ERROR"));
+            generation.insert(
+                "statusMessage".to_string(),
+                json!(format!("HTTP {}", status)),
+            );
+        }
+        generation.insert(
+            "metadata".to_string(),
+            json!({
+                "request_id": self.request_id,
+                "provider": self.provider,
+                "streaming": self.streaming,
+            }),
+        );
+
+        vec![
+            json!({
+                "id": uuid::Uuid::new_v4().to_string(),
+                "timestamp": start_time,
+                "type": "trace-create",
+                "body": {
+                    "id": self.trace_id,
+                    "name": self.name,
+                    "timestamp": start_time,
+                },
+            }),
+            json!({
+                "id": uuid::Uuid::new_v4().to_string(),
+                "timestamp": start_time,
+                "type": "generation-create",
+                "body": generation,
+            }),
+        ]
+    }
+}
+
+fn rfc3339(unix_nanos: u128) -> String {
+    DateTime::<Utc>::from_timestamp_nanos(unix_nanos as i64)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The llm span with the captured prompt and completion as the indexed attributes that
+/// OpenLLMetry backends read
+fn with_openllmetry_attributes(mut span: Span, generation: &Generation) -> Span {
+    let mut add = |key: &str, value: &str| {
+        span.attributes.push(Attribute {
+            key: key.to_string(),
+            value: AttributeValue {
+                string_value: Some(value.to_string()),
+            },
+        });
+    };
+    if let Some(prompt) = &generation.prompt {
+        add("gen_ai.prompt.0.role", "user");
+        add("gen_ai.prompt.0.content", prompt);
+    }
+    if let Some(completion) = &generation.completion {
+        add("gen_ai.completion.0.role", "assistant");
+        add("gen_ai.completion.0.content", completion);
+    }
+    span
+}
+
+enum Backend {
+    Langfuse {
+        url: String,
+        public_key: String,
+        secret_key: String,
+    },
+    Otlp {
+        endpoint: String,
+        headers: HashMap<String, String>,
+    },
+}
+
+/// Takes the llm spans out of the trace queue when it is flushed and ships them as
+/// generations to an llm observability backend, in batches on an interval. Batches the
+/// backend fails to accept are kept and retried on the next flush.
+pub struct LlmObservabilityExporter {
+    spans: Mutex<VecDeque<Span>>,
+    backend: Backend,
+    client: reqwest::Client,
+    sampling_rate: f64,
+    flush_interval: Duration,
+    max_batch_size: usize,
+}
+
+impl From<&LlmObservabilityConfig> for LlmObservabilityExporter {
+    fn from(config: &LlmObservabilityConfig) -> Self {
+        let backend = match &config.exporter {
+            LlmObservabilityExporterConfig::Langfuse {
+                host,
+                public_key,
+                secret_key,
+            } => Backend::Langfuse {
+                url: format!(
+                    "{}/api/public/ingestion",
+                    host.as_deref()
+                        .unwrap_or(DEFAULT_LANGFUSE_HOST)
+                        .trim_end_matches('/')
+                ),
+                public_key: public_key.clone(),
+                secret_key: secret_key.clone(),
+            },
+            LlmObservabilityExporterConfig::Otlp { endpoint, headers } => Backend::Otlp {
+                endpoint: endpoint.clone(),
+                headers: headers.clone().unwrap_or_default(),
+            },
+        };
+        LlmObservabilityExporter {
+            spans: Mutex::new(VecDeque::new()),
+            backend,
+            client: reqwest::Client::new(),
+            sampling_rate: config.sampling_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+            flush_interval: config
+                .flush_interval_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            max_batch_size: config
+                .max_batch_size
+                .unwrap_or(DEFAULT_MAX_BATCH_SIZE)
+                .max(1),
+        }
+    }
+}
+
+impl SpanListener for LlmObservabilityExporter {
+    fn on_spans(&self, service_name: &str, spans: &[Span]) {
+        if service_name != operation_component::LLM {
+            return;
+        }
+        let mut buffered = self.spans.lock().unwrap();
+        for span in spans {
+            if self.sampling_rate < 1.0 && rand::random::<f64>() >= self.sampling_rate {
+                continue;
+            }
+            if buffered.len() >= MAX_BUFFERED_GENERATIONS {
+                buffered.pop_front();
+                warn!("llm observability buffer is full, dropping the oldest generation");
+            }
+            buffered.push_back(span.clone());
+        }
+    }
+}
+
+impl LlmObservabilityExporter {
+    /// Ships all buffered generations, returns how many the backend accepted
+    pub async fn flush(&self) -> Result<usize, String> {
+        let mut sent = 0;
+        loop {
+            let batch: Vec<Span> = {
+                let mut spans = self.spans.lock().unwrap();
+                let batch_size = spans.len().min(self.max_batch_size);
+                spans.drain(..batch_size).collect()
+            };
+            if batch.is_empty() {
+                return Ok(sent);
+            }
+
+            if let Err(err) = self.send(&batch).await {
+                let mut spans = self.spans.lock().unwrap();
+                for span in batch.into_iter().rev() {
+                    if spans.len() >= MAX_BUFFERED_GENERATIONS {
+                        break;
+                    }
+                    spans.push_front(span);
+                }
+                return Err(err);
+            }
+            sent += batch.len();
+        }
+    }
+
+    async fn send(&self, spans: &[Span]) -> Result<(), String> {
+        let request = match &self.backend {
+            Backend::Langfuse {
+                url,
+                public_key,
+                secret_key,
+            } => {
+                let events: Vec<Value> = spans
+                    .iter()
+                    .flat_map(|span| Generation::from_span(span).langfuse_events())
+                    .collect();
+                self.client
+                    .post(url)
+                    .basic_auth(public_key, Some(secret_key))
+                    .json(&json!({ "batch": events }))
+            }
+            Backend::Otlp { endpoint, headers } => {
+                let spans = spans
+                    .iter()
+                    .map(|span| {
+                        with_openllmetry_attributes(span.clone(), &Generation::from_span(span))
+                    })
+                    .collect();
+                let resource_spans = ResourceSpanBuilder::new(OTLP_SERVICE_NAME)
+                    .add_spans(spans)
+                    .build();
+                let mut request = self
+                    .client
+                    .post(endpoint)
+                    .json(&json!({ "resourceSpans": [resource_spans] }));
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                request
+            }
+        };
+
+        let response = request
+            .timeout(EXPORT_TIMEOUT)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "llm observability backend returned {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn start_background_flusher(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = interval(self.flush_interval);
+            loop {
+                ticker.tick().await;
+                match self.flush().await {
+                    Ok(0) => {}
+                    Ok(sent) => debug!("exported {} llm generations", sent),
+                    Err(err) => warn!("llm observability export failed, will retry: {}", err),
+                }
+            }
+        })
+    }
+
+    pub fn buffered_count(&self) -> usize {
+        self.spans.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::traces::{Event, SpanBuilder};
+
+    fn llm_span() -> Span {
+        let mut span = SpanBuilder::new("POST /v1/chat/completions gpt-4o")
+            .with_trace_id("4bf92f3577b34da6a3ce929d0e0e4736")
+            .with_attribute(http::REQUEST_ID, "req-1")
+            .with_attribute(http::STATUS_CODE, "200")
+            .with_attribute(llm::MODEL_NAME, "gpt-4o")
+            .with_attribute(llm::IS_STREAMING, "true")
+            .with_attribute(llm::TIME_TO_FIRST_TOKEN_MS, "120")
+            .with_attribute(gen_ai::SYSTEM, "openai")
+            .with_attribute(gen_ai::USAGE_INPUT_TOKENS, "12")
+            .with_attribute(gen_ai::USAGE_OUTPUT_TOKENS, "30")
+            .build();
+        span.start_time_unix_nano = "1735689600000000000".to_string();
+        span.end_time_unix_nano = "1735689601500000000".to_string();
+        let mut prompt = Event::new(gen_ai::CONTENT_PROMPT.to_string(), 0);
+        prompt.add_attribute(gen_ai::PROMPT.to_string(), "hi".to_string());
+        let mut completion = Event::new(gen_ai::CONTENT_COMPLETION.to_string(), 0);
+        completion.add_attribute(gen_ai::COMPLETION.to_string(), "hello!".to_string());
+        span.events = Some(vec![prompt, completion]);
+        span
+    }
+
+    #[test]
+    fn test_generation_from_span() {
+        let generation = Generation::from_span(&llm_span());
+        assert_eq!(generation.request_id.as_deref(), Some("req-1"));
+        assert_eq!(generation.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(generation.input_tokens, Some(12));
+        assert_eq!(generation.output_tokens, Some(30));
+        assert_eq!(generation.status, Some(200));
+        assert!(generation.streaming);
+        assert_eq!(generation.prompt.as_deref(), Some("hi"));
+        assert_eq!(generation.completion.as_deref(), Some("hello!"));
+    }
+
+    #[test]
+    fn test_langfuse_events() {
+        let events = Generation::from_span(&llm_span()).langfuse_events();
+        assert_eq!(events[0]["type"], "trace-create");
+        assert_eq!(events[0]["body"]["id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        let generation = &events[1]["body"];
+        assert_eq!(events[1]["type"], "generation-create");
+        assert_eq!(generation["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(generation["startTime"], "2025-01-01T00:00:00.000Z");
+        assert_eq!(generation["endTime"], "2025-01-01T00:00:01.500Z");
+        assert_eq!(
+            generation["completionStartTime"],
+            "2025-01-01T00:00:00.120Z"
+        );
+        assert_eq!(generation["input"], "hi");
+        assert_eq!(generation["output"], "hello!");
+        assert_eq!(
+            generation["usage"],
+            json!({"input": 12, "output": 30, "unit": "TOKENS"})
+        );
+        assert!(generation.get("level").is_none());
+    }
+
+    #[test]
+    fn test_only_llm_spans_are_buffered() {
+        let exporter = LlmObservabilityExporter::from(&LlmObservabilityConfig {
+            exporter: LlmObservabilityExporterConfig::Langfuse {
+                host: None,
+                public_key: "pk-lf-1".to_string(),
+                secret_key: "sk-lf-1".to_string(),
+            },
+            sampling_rate: None,
+            flush_interval_ms: None,
+            max_batch_size: None,
+        });
+        exporter.on_spans(operation_component::ROUTING, &[llm_span()]);
+        assert_eq!(exporter.buffered_count(), 0);
+        exporter.on_spans(operation_component::LLM, &[llm_span(), llm_span()]);
+        assert_eq!(exporter.buffered_count(), 2);
+    }
+}
@@ -1,3 +1,6 @@
 mod constants;
+pub mod llm_observability;
+mod semconv;
 
-pub use constants::{error, http, llm, operation_component, routing, OperationNameBuilder};
+pub use constants::{error, gen_ai, http, llm, operation_component, routing, OperationNameBuilder};
+pub use semconv::{gen_ai_system, ContentCapturePolicy, GenAiResponseRecorder, REDACTED};
@@ -0,0 +1,471 @@
+use common::configuration::{ContentCapture, ContentCaptureMode, LlmProviderType};
+pub use hermesllm::fixtures::REDACTED;
+use regex::Regex;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+use super::constants::gen_ai;
+
+/// Value of `gen_ai.system` for a provider, using the well known names of the semconv
+/// where there is one
+pub fn gen_ai_system(provider_interface: &LlmProviderType) -> String {
+    match provider_interface {
+        LlmProviderType::AzureOpenAI => "az.ai.openai".to_string(),
+        LlmProviderType::AmazonBedrock => "aws.bedrock".to_string(),
+        LlmProviderType::Mistral => "mistral_ai".to_string(),
+        LlmProviderType::VertexAI => "gcp.vertex_ai".to_string(),
+        LlmProviderType::ChatGPT => "openai".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Longest prompt or completion attached to a span unless configured otherwise
+pub const DEFAULT_MAX_CONTENT_BYTES: usize = 8192;
+
+/// Decides how much of the prompt and completion of an llm request is exported in spans
+/// and logs, and scrubs it before it leaves the gateway
+#[derive(Debug)]
+pub struct ContentCapturePolicy {
+    mode: ContentCaptureMode,
+    sampling_rate: f64,
+    max_content_bytes: usize,
+    redactions: Vec<Regex>,
+}
+
+impl Default for ContentCapturePolicy {
+    fn default() -> Self {
+        ContentCapturePolicy {
+            mode: ContentCaptureMode::default(),
+            sampling_rate: 1.0,
+            max_content_bytes: DEFAULT_MAX_CONTENT_BYTES,
+            redactions: Vec::new(),
+        }
+    }
+}
+
+impl ContentCapturePolicy {
+    /// Invalid redaction patterns are skipped
+    pub fn from_config(config: Option<&ContentCapture>) -> Self {
+        let Some(config) = config else {
+            return ContentCapturePolicy::default();
+        };
+        let redactions = config
+            .redact
+            .iter()
+            .flatten()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    warn!("ignoring invalid redaction pattern {}: {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+        ContentCapturePolicy {
+            mode: config.mode.unwrap_or_default(),
+            sampling_rate: config.sampling_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+            max_content_bytes: config
+                .max_content_bytes
+                .unwrap_or(DEFAULT_MAX_CONTENT_BYTES)
+                .max(1),
+            redactions,
+        }
+    }
+
+    /// Capture mode for one request, `Off` when the request is not sampled
+    pub fn sample(&self) -> ContentCaptureMode {
+        if self.mode == ContentCaptureMode::Off
+            || (self.sampling_rate < 1.0 && rand::random::<f64>() >= self.sampling_rate)
+        {
+            return ContentCaptureMode::Off;
+        }
+        self.mode
+    }
+
+    pub fn max_content_bytes(&self) -> usize {
+        self.max_content_bytes
+    }
+
+    pub fn redact(&self, content: &str) -> String {
+        self.redactions
+            .iter()
+            .fold(content.to_string(), |content, regex| {
+                regex.replace_all(&content, REDACTED).into_owned()
+            })
+    }
+
+    /// Redacted content cut down to the size cap
+    pub fn scrub(&self, content: &str) -> String {
+        let mut content = self.redact(content);
+        if content.len() > self.max_content_bytes {
+            let mut end = self.max_content_bytes;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            content.truncate(end);
+            content.push_str("...");
+        }
+        content
+    }
+
+    /// Request or response body as it may appear in logs
+    pub fn log_body(&self, body: &[u8]) -> String {
+        match self.mode {
+            ContentCaptureMode::Off => format!("<{} bytes>", body.len()),
+            _ => self.scrub(&String::from_utf8_lossy(body)),
+        }
+    }
+}
+
+/// Collects model, usage, finish reasons and optionally the completion text from an
+/// llm response, either a json body or server sent events in the OpenAI, Anthropic or
+/// Responses API shape
+#[derive(Debug, Default)]
+pub struct GenAiResponseRecorder {
+    is_streaming: bool,
+    /// Set when the completion text of this request is captured
+    capture: Option<Arc<ContentCapturePolicy>>,
+    buffer: Vec<u8>,
+    response_id: Option<String>,
+    response_model: Option<String>,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    finish_reasons: Vec<String>,
+    completion: String,
+}
+
+impl GenAiResponseRecorder {
+    pub fn new(is_streaming: bool, capture: Option<Arc<ContentCapturePolicy>>) -> Self {
+        GenAiResponseRecorder {
+            is_streaming,
+            capture,
+            ..Default::default()
+        }
+    }
+
+    pub fn observe_chunk(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+        if !self.is_streaming {
+            return;
+        }
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.observe_event_line(&line);
+        }
+    }
+
+    /// Span attributes for everything the response reported
+    pub fn finish(&mut self) -> Vec<(&'static str, String)> {
+        let remaining = std::mem::take(&mut self.buffer);
+        if self.is_streaming {
+            self.observe_event_line(&remaining);
+        } else if let Ok(body) = serde_json::from_slice::<Value>(&remaining) {
+            self.observe(&body);
+        }
+
+        let mut attributes = Vec::new();
+        if let Some(response_id) = &self.response_id {
+            attributes.push((gen_ai::RESPONSE_ID, response_id.clone()));
+        }
+        if let Some(response_model) = &self.response_model {
+            attributes.push((gen_ai::RESPONSE_MODEL, response_model.clone()));
+        }
+        if let Some(input_tokens) = self.input_tokens {
+            attributes.push((gen_ai::USAGE_INPUT_TOKENS, input_tokens.to_string()));
+        }
+        if let Some(output_tokens) = self.output_tokens {
+            attributes.push((gen_ai::USAGE_OUTPUT_TOKENS, output_tokens.to_string()));
+        }
+        if !self.finish_reasons.is_empty() {
+            attributes.push((
+                gen_ai::RESPONSE_FINISH_REASONS,
+                self.finish_reasons.join(","),
+            ));
+        }
+        attributes
+    }
+
+    pub fn input_tokens(&self) -> Option<u64> {
+        self.input_tokens
+    }
+
+    pub fn output_tokens(&self) -> Option<u64> {
+        self.output_tokens
+    }
+
+    /// Scrubbed completion text, when capture is enabled and the response had any
+    pub fn completion(&self) -> Option<String> {
+        match &self.capture {
+            Some(policy) if !self.completion.is_empty() => Some(policy.scrub(&self.completion)),
+            _ => None,
+        }
+    }
+
+    /// Keeps a bit more than the size cap so redaction still sees whole matches near the end
+    fn push_completion(&mut self, text: &str) {
+        let limit = self
+            .capture
+            .as_ref()
+            .map(|policy| policy.max_content_bytes().saturating_mul(2))
+            .unwrap_or_default();
+        if self.completion.len() < limit {
+            self.completion.push_str(text);
+        }
+    }
+
+    fn observe_event_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return;
+        };
+        if let Ok(event) = serde_json::from_str::<Value>(data.trim()) {
+            self.observe(&event);
+        }
+    }
+
+    fn observe(&mut self, value: &Value) {
+        // anthropic message_start and responses api events nest the response
+        let response = value
+            .get("message")
+            .filter(|message| message.get("usage").is_some())
+            .or_else(|| value.get("response"))
+            .unwrap_or(value);
+
+        if let Some(id) = response.get("id").and_then(Value::as_str) {
+            self.response_id.get_or_insert_with(|| id.to_string());
+        }
+        if let Some(model) = response.get("model").and_then(Value::as_str) {
+            self.response_model.get_or_insert_with(|| model.to_string());
+        }
+        if let Some(usage) = value.get("usage").or_else(|| response.get("usage")) {
+            if let Some(tokens) = usage_tokens(usage, &["prompt_tokens", "input_tokens"]) {
+                self.input_tokens = Some(tokens);
+            }
+            if let Some(tokens) = usage_tokens(usage, &["completion_tokens", "output_tokens"]) {
+                self.output_tokens = Some(tokens);
+            }
+        }
+
+        for choice in value
+            .get("choices")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+                self.finish_reasons.push(reason.to_string());
+            }
+            if self.capture.is_some() {
+                let content = choice
+                    .pointer("/delta/content")
+                    .or_else(|| choice.pointer("/message/content"))
+                    .and_then(Value::as_str);
+                self.push_completion(content.unwrap_or_default());
+            }
+        }
+
+        let stop_reason = value
+            .get("stop_reason")
+            .or_else(|| value.pointer("/delta/stop_reason"))
+            .and_then(Value::as_str);
+        if let Some(reason) = stop_reason {
+            self.finish_reasons.push(reason.to_string());
+        }
+        if value.get("type").and_then(Value::as_str) == Some("response.completed")
+            || value.get("object").and_then(Value::as_str) == Some("response")
+        {
+            if let Some(status) = response.get("status").and_then(Value::as_str) {
+                self.finish_reasons.push(status.to_string());
+            }
+        }
+
+        if self.capture.is_some() {
+            self.capture_text(value);
+        }
+    }
+
+    fn capture_text(&mut self, value: &Value) {
+        match value.get("type").and_then(Value::as_str) {
+            // anthropic streaming
+            Some("content_block_delta") => {
+                if let Some(text) = value.pointer("/delta/text").and_then(Value::as_str) {
+                    self.push_completion(text);
+                }
+            }
+            // responses api streaming
+            Some("response.output_text.delta") => {
+                if let Some(text) = value.get("delta").and_then(Value::as_str) {
+                    self.push_completion(text);
+                }
+            }
+            // anthropic message
+            Some("message") => {
+                for block in value
+                    .get("content")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some(text) = block.get("text").and_then(Value::as_str) {
+                        self.push_completion(text);
+                    }
+                }
+            }
+            _ => {
+                if let Some(text) = value.get("output_text").and_then(Value::as_str) {
+                    self.push_completion(text);
+                }
+            }
+        }
+    }
+}
+
+fn usage_tokens(usage: &Value, keys: &[&str]) -> Option<u64> {
+    keys.iter()
+        .find_map(|key| usage.get(*key).and_then(Value::as_u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture_all() -> Option<Arc<ContentCapturePolicy>> {
+        Some(Arc::new(ContentCapturePolicy::default()))
+    }
+
+    fn attribute(attributes: &[(&'static str, String)], key: &str) -> Option<String> {
+        attributes
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_openai_streaming_response() {
+        let mut recorder = GenAiResponseRecorder::new(true, capture_all());
+        recorder.observe_chunk(b"data: {\"id\":\"chatcmpl-1\",\"model\":\"gpt-4o-2024-08-06\",\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"id\":\"chatcmpl-1\",\"choices\":[{\"delta\":{\"con");
+        recorder.observe_chunk(b"tent\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\ndata: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":2}}\n\ndata: [DONE]\n\n");
+
+        let attributes = recorder.finish();
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_MODEL).as_deref(),
+            Some("gpt-4o-2024-08-06")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_ID).as_deref(),
+            Some("chatcmpl-1")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::USAGE_INPUT_TOKENS).as_deref(),
+            Some("12")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::USAGE_OUTPUT_TOKENS).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_FINISH_REASONS).as_deref(),
+            Some("stop")
+        );
+        assert_eq!(recorder.completion().as_deref(), Some("Hello"));
+    }
+
+    #[test]
+    fn test_anthropic_streaming_response() {
+        let mut recorder = GenAiResponseRecorder::new(true, None);
+        recorder.observe_chunk(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"model\":\"claude-sonnet-4\",\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n");
+        recorder.observe_chunk(b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n");
+        recorder.observe_chunk(b"event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":15}}\n\n");
+
+        let attributes = recorder.finish();
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_MODEL).as_deref(),
+            Some("claude-sonnet-4")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::USAGE_INPUT_TOKENS).as_deref(),
+            Some("25")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::USAGE_OUTPUT_TOKENS).as_deref(),
+            Some("15")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_FINISH_REASONS).as_deref(),
+            Some("end_turn")
+        );
+        assert_eq!(recorder.completion(), None);
+    }
+
+    #[test]
+    fn test_non_streaming_response() {
+        let mut recorder = GenAiResponseRecorder::new(false, capture_all());
+        recorder.observe_chunk(br#"{"id":"msg_2","type":"message","model":"claude-sonnet-4","#);
+        recorder.observe_chunk(br#""content":[{"type":"text","text":"Hello"}],"stop_reason":"max_tokens","usage":{"input_tokens":3,"output_tokens":4}}"#);
+
+        let attributes = recorder.finish();
+        assert_eq!(
+            attribute(&attributes, gen_ai::RESPONSE_FINISH_REASONS).as_deref(),
+            Some("max_tokens")
+        );
+        assert_eq!(
+            attribute(&attributes, gen_ai::USAGE_OUTPUT_TOKENS).as_deref(),
+            Some("4")
+        );
+        assert_eq!(recorder.completion().as_deref(), Some("Hello"));
+    }
+
+    #[test]
+    fn test_content_capture_policy() {
+        let default = ContentCapturePolicy::from_config(None);
+        assert_eq!(default.sample(), ContentCaptureMode::Metadata);
+
+        let policy = ContentCapturePolicy::from_config(Some(&ContentCapture {
+            mode: Some(ContentCaptureMode::Full),
+            max_content_bytes: Some(24),
+            redact: Some(vec![
+                r"\b\d{3}-\d{2}-\d{4}\b".to_string(),
+                "(unclosed".to_string(),
+            ]),
+            ..Default::default()
+        }));
+        assert_eq!(policy.sample(), ContentCaptureMode::Full);
+        assert_eq!(
+            policy.scrub("my ssn is 123-45-6789"),
+            "my ssn is [REDACTED]"
+        );
+        assert_eq!(
+            policy.scrub("my ssn is 123-45-6789, call me"),
+            "my ssn is [REDACTED], ca..."
+        );
+        assert_eq!(
+            policy.scrub(&"é".repeat(20)),
+            format!("{}...", "é".repeat(12))
+        );
+
+        let never = ContentCapturePolicy::from_config(Some(&ContentCapture {
+            mode: Some(ContentCaptureMode::Full),
+            sampling_rate: Some(0.0),
+            ..Default::default()
+        }));
+        assert_eq!(never.sample(), ContentCaptureMode::Off);
+
+        let off = ContentCapturePolicy::from_config(Some(&ContentCapture {
+            mode: Some(ContentCaptureMode::Off),
+            ..Default::default()
+        }));
+        assert_eq!(off.sample(), ContentCaptureMode::Off);
+        assert_eq!(off.log_body(b"{\"messages\":[]}"), "<15 bytes>");
+    }
+
+    #[test]
+    fn test_gen_ai_system() {
+        assert_eq!(gen_ai_system(&LlmProviderType::OpenAI), "openai");
+        assert_eq!(gen_ai_system(&LlmProviderType::Anthropic), "anthropic");
+        assert_eq!(
+            gen_ai_system(&LlmProviderType::AmazonBedrock),
+            "aws.bedrock"
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use super::{UsageExportError, UsageRecord, UsageSink};
+
+/// Appends usage records to a local file, one json object per line
+pub struct FileSink {
+    path: String,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> Self {
+        FileSink {
+            path: path.to_string(),
+        }
+    }
+}
+
+/// Records as json lines, the format of the file and s3 sinks
+pub fn to_json_lines(records: &[UsageRecord]) -> Result<Vec<u8>, UsageExportError> {
+    let mut body = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut body, record)?;
+        body.push(b'\n');
+    }
+    Ok(body)
+}
+
+#[async_trait]
+impl UsageSink for FileSink {
+    async fn send(&self, records: &[UsageRecord]) -> Result<(), UsageExportError> {
+        let body = to_json_lines(records)?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&body).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
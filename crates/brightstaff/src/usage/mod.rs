@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use common::configuration::{UsageExportConfig, UsageSinkConfig};
+use common::consts::ARCH_TENANT_ID_HEADER;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::interval;
+use tracing::{debug, warn};
+
+pub mod file;
+pub mod s3;
+pub mod webhook;
+
+pub const DEFAULT_USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+pub const DEFAULT_USAGE_MAX_BATCH_SIZE: usize = 1000;
+/// Records kept while the sink is unreachable, the oldest are dropped beyond this
+pub const MAX_BUFFERED_USAGE_RECORDS: usize = 100_000;
+
+/// Usage of one llm request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsageRecord {
+    pub request_id: String,
+    /// RFC 3339 time the request completed
+    pub timestamp: String,
+    pub tenant: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// USD, when the provider has pricing configured and the response reported usage
+    pub cost: Option<f64>,
+    pub latency_ms: u64,
+    pub status: u16,
+    pub streaming: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum UsageExportError {
+    #[error("failed to send usage records: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("usage sink returned {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("failed to write usage records: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to serialize usage records: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("invalid usage sink configuration: {0}")]
+    Config(String),
+}
+
+/// Destination of usage records
+#[async_trait]
+pub trait UsageSink: Send + Sync {
+    async fn send(&self, records: &[UsageRecord]) -> Result<(), UsageExportError>;
+}
+
+/// Buffers usage records and ships them to the sink in batches on an interval. Batches
+/// the sink fails to accept are kept and retried on the next flush.
+pub struct UsageExporter {
+    records: Mutex<VecDeque<UsageRecord>>,
+    sink: Box<dyn UsageSink>,
+    flush_interval: Duration,
+    max_batch_size: usize,
+    tenant_header: String,
+}
+
+impl UsageExporter {
+    pub fn new(sink: Box<dyn UsageSink>, flush_interval: Duration, max_batch_size: usize) -> Self {
+        UsageExporter {
+            records: Mutex::new(VecDeque::new()),
+            sink,
+            flush_interval,
+            max_batch_size: max_batch_size.max(1),
+            tenant_header: ARCH_TENANT_ID_HEADER.to_string(),
+        }
+    }
+
+    pub fn with_tenant_header(mut self, tenant_header: &str) -> Self {
+        self.tenant_header = tenant_header.to_ascii_lowercase();
+        self
+    }
+
+    /// Request header carrying the tenant of a request
+    pub fn tenant_header(&self) -> &str {
+        &self.tenant_header
+    }
+
+    pub fn from_config(config: &UsageExportConfig) -> Result<Self, UsageExportError> {
+        let sink: Box<dyn UsageSink> = match &config.sink {
+            UsageSinkConfig::Webhook { url, headers } => {
+                Box::new(webhook::WebhookSink::new(url, headers.clone()))
+            }
+            UsageSinkConfig::File { path } => Box::new(file::FileSink::new(path)),
+            UsageSinkConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                prefix,
+                access_key_id,
+                secret_access_key,
+            } => Box::new(s3::S3Sink::new(
+                endpoint,
+                bucket,
+                region.as_deref(),
+                prefix.as_deref(),
+                access_key_id,
+                secret_access_key,
+            )?),
+        };
+        Ok(UsageExporter::new(
+            sink,
+            config
+                .flush_interval_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_USAGE_FLUSH_INTERVAL),
+            config
+                .max_batch_size
+                .unwrap_or(DEFAULT_USAGE_MAX_BATCH_SIZE),
+        ))
+    }
+
+    pub fn record(&self, record: UsageRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= MAX_BUFFERED_USAGE_RECORDS {
+            records.pop_front();
+            warn!("usage record buffer is full, dropping the oldest record");
+        }
+        records.push_back(record);
+    }
+
+    /// Ships all buffered records, returns how many the sink accepted
+    pub async fn flush(&self) -> Result<usize, UsageExportError> {
+        let mut sent = 0;
+        loop {
+            let batch: Vec<UsageRecord> = {
+                let mut records = self.records.lock().unwrap();
+                let batch_size = records.len().min(self.max_batch_size);
+                records.drain(..batch_size).collect()
+            };
+            if batch.is_empty() {
+                return Ok(sent);
+            }
+
+            if let Err(err) = self.sink.send(&batch).await {
+                let mut records = self.records.lock().unwrap();
+                for record in batch.into_iter().rev() {
+                    if records.len() >= MAX_BUFFERED_USAGE_RECORDS {
+                        break;
+                    }
+                    records.push_front(record);
+                }
+                return Err(err);
+            }
+            sent += batch.len();
+        }
+    }
+
+    pub fn start_background_flusher(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = interval(self.flush_interval);
+            loop {
+                ticker.tick().await;
+                match self.flush().await {
+                    Ok(0) => {}
+                    Ok(sent) => debug!("exported {} usage records", sent),
+                    Err(err) => warn!("usage export failed, will retry: {}", err),
+                }
+            }
+        })
+    }
+
+    pub fn buffered_count(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Default)]
+    struct TestSink {
+        batches: Arc<Mutex<Vec<Vec<UsageRecord>>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl UsageSink for TestSink {
+        async fn send(&self, records: &[UsageRecord]) -> Result<(), UsageExportError> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(UsageExportError::Status {
+                    status: 503,
+                    body: String::new(),
+                });
+            }
+            self.batches.lock().unwrap().push(records.to_vec());
+            Ok(())
+        }
+    }
+
+    fn record(request_id: &str) -> UsageRecord {
+        UsageRecord {
+            request_id: request_id.to_string(),
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            tenant: Some("acme".to_string()),
+            provider: "openai/gpt-4o".to_string(),
+            model: "gpt-4o".to_string(),
+            input_tokens: Some(10),
+            output_tokens: Some(20),
+            cost: None,
+            latency_ms: 250,
+            status: 200,
+            streaming: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_batches() {
+        let sink = TestSink::default();
+        let batches = sink.batches.clone();
+        let exporter = UsageExporter::new(Box::new(sink), Duration::from_secs(1), 2);
+        for id in ["a", "b", "c"] {
+            exporter.record(record(id));
+        }
+
+        assert_eq!(exporter.flush().await.unwrap(), 3);
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[1][0].request_id, "c");
+        assert_eq!(exporter.buffered_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_batches_are_retried() {
+        let sink = TestSink::default();
+        let batches = sink.batches.clone();
+        let failing = sink.failing.clone();
+        let exporter = UsageExporter::new(Box::new(sink), Duration::from_secs(1), 10);
+        exporter.record(record("a"));
+        exporter.record(record("b"));
+
+        failing.store(true, Ordering::SeqCst);
+        assert!(exporter.flush().await.is_err());
+        assert_eq!(exporter.buffered_count(), 2);
+
+        failing.store(false, Ordering::SeqCst);
+        exporter.record(record("c"));
+        assert_eq!(exporter.flush().await.unwrap(), 3);
+        let ids: Vec<String> = batches.lock().unwrap()[0]
+            .iter()
+            .map(|record| record.request_id.clone())
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+}
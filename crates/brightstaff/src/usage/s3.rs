@@ -0,0 +1,241 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use super::file::to_json_lines;
+use super::{UsageExportError, UsageRecord, UsageSink};
+
+const S3_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Uploads every batch of usage records as a json lines object to an S3 compatible
+/// bucket, addressed path style and signed with AWS signature version 4
+pub struct S3Sink {
+    client: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3Sink {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: Option<&str>,
+        prefix: Option<&str>,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> Result<Self, UsageExportError> {
+        let endpoint = Url::parse(endpoint).map_err(|err| {
+            UsageExportError::Config(format!("s3 endpoint {}: {}", endpoint, err))
+        })?;
+        if endpoint.host_str().is_none() {
+            return Err(UsageExportError::Config(format!(
+                "s3 endpoint {} has no host",
+                endpoint
+            )));
+        }
+        Ok(S3Sink {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.to_string(),
+            region: region.unwrap_or(DEFAULT_S3_REGION).to_string(),
+            prefix: prefix.unwrap_or_default().to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+        })
+    }
+
+    /// Objects are partitioned by day, e.g. `usage/2025/01/31/20250131T120000Z-<uuid>.jsonl`
+    fn object_key(&self, now: DateTime<Utc>) -> String {
+        format!(
+            "{}{}/{}-{}.jsonl",
+            self.prefix,
+            now.format("%Y/%m/%d"),
+            now.format("%Y%m%dT%H%M%SZ"),
+            uuid::Uuid::new_v4()
+        )
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    fn canonical_uri(&self, key: &str) -> String {
+        let base = self.endpoint.path().trim_end_matches('/');
+        format!(
+            "{}/{}/{}",
+            base,
+            uri_encode(&self.bucket),
+            key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+        )
+    }
+
+    /// Value of the authorization header of a PUT of an object
+    fn authorization(&self, canonical_uri: &str, payload_hash: &str, now: DateTime<Utc>) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let canonical_request = format!(
+            "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            canonical_uri,
+            self.host(),
+            payload_hash,
+            amz_date,
+            SIGNED_HEADERS,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = signing_key(&self.secret_access_key, &date, &self.region, "s3");
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, SIGNED_HEADERS, signature
+        )
+    }
+}
+
+#[async_trait]
+impl UsageSink for S3Sink {
+    async fn send(&self, records: &[UsageRecord]) -> Result<(), UsageExportError> {
+        let body = to_json_lines(records)?;
+        let now = Utc::now();
+        let canonical_uri = self.canonical_uri(&self.object_key(now));
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&canonical_uri);
+        let response = self
+            .client
+            .put(url)
+            .timeout(S3_TIMEOUT)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("x-amz-content-sha256", &payload_hash)
+            .header(
+                "authorization",
+                self.authorization(&canonical_uri, &payload_hash, now),
+            )
+            .header("content-type", "application/x-ndjson")
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(UsageExportError::Status {
+                status: response.status().as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent encodes everything but the unreserved characters, as sigv4 expects
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sink(endpoint: &str) -> S3Sink {
+        S3Sink::new(
+            endpoint,
+            "usage",
+            None,
+            Some("arch gw/"),
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // example from the aws documentation on deriving a signing key
+        assert_eq!(
+            hex(&signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_object_key_and_canonical_uri() {
+        let sink = sink("http://minio:9000");
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap();
+        let key = sink.object_key(now);
+        assert!(key.starts_with("arch gw/2025/01/31/20250131T120000Z-"));
+        assert!(key.ends_with(".jsonl"));
+
+        assert_eq!(
+            sink.canonical_uri("arch gw/2025/01/31/a.jsonl"),
+            "/usage/arch%20gw/2025/01/31/a.jsonl"
+        );
+        assert_eq!(sink.host(), "minio:9000");
+        assert_eq!(sink.region, DEFAULT_S3_REGION);
+    }
+
+    #[test]
+    fn test_authorization_header() {
+        let sink = sink("https://s3.amazonaws.com");
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap();
+        let authorization = sink.authorization("/usage/a.jsonl", &hex(&Sha256::digest(b"")), now);
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20250131/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+        let signature = authorization.rsplit('=').next().unwrap();
+        assert_eq!(signature.len(), 64);
+
+        assert!(S3Sink::new("not a url", "usage", None, None, "a", "b").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::{UsageExportError, UsageRecord, UsageSink};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POSTs every batch of usage records as a json array
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, headers: Option<HashMap<String, String>>) -> Self {
+        WebhookSink {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            headers: headers.unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl UsageSink for WebhookSink {
+    async fn send(&self, records: &[UsageRecord]) -> Result<(), UsageExportError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(records);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(UsageExportError::Status {
+                status: response.status().as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+}
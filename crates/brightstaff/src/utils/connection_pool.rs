@@ -0,0 +1,181 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use common::configuration::LlmProvider;
+use common::consts::{
+    ARCH_CONNECTION_WARMUP_HEADER, ARCH_PROVIDER_HINT_HEADER, CHAT_COMPLETIONS_PATH,
+};
+use futures::future::join_all;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+/// How often the warm connections are reused, below the idle timeout of most providers
+const WARMUP_INTERVAL: Duration = Duration::from_secs(60);
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client of the llm requests brightstaff sends through llm_gateway, shared so its
+/// connections to Envoy are pooled instead of opened for every request
+pub fn llm_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|err| {
+                warn!("failed to build the llm client: {}", err);
+                reqwest::Client::new()
+            })
+    })
+}
+
+/// Keeps `warm_connections` upstream connections of every provider with a connection pool
+/// open. Warmup requests go through llm_gateway like llm requests, so Envoy opens and
+/// pools the connections of the provider's cluster, the provider just sees a HEAD.
+pub struct ConnectionWarmer {
+    llm_provider_url: String,
+    /// Provider names with their warm connections
+    providers: Vec<(String, usize)>,
+    interval: Duration,
+}
+
+impl ConnectionWarmer {
+    /// None when no provider asks for warm connections
+    pub fn new(providers: &[LlmProvider], llm_provider_url: &str) -> Option<Self> {
+        let mut warmed = Vec::new();
+        let mut warmup_interval = WARMUP_INTERVAL;
+        for provider in providers {
+            let Some(pool) = provider.connection_pool.as_ref() else {
+                continue;
+            };
+            let count = pool.warm_connections.unwrap_or_default();
+            if count == 0 {
+                continue;
+            }
+            // warm connections are reused before the cluster closes them as idle
+            if let Some(idle_timeout_ms) = pool.idle_timeout_ms {
+                warmup_interval = warmup_interval.min(Duration::from_millis(idle_timeout_ms / 2));
+            }
+            warmed.push((provider.name.clone(), count as usize));
+        }
+        if warmed.is_empty() {
+            return None;
+        }
+        Some(ConnectionWarmer {
+            llm_provider_url: llm_provider_url.to_string(),
+            providers: warmed,
+            interval: warmup_interval.max(Duration::from_secs(1)),
+        })
+    }
+
+    /// Sends the warmup requests of every provider at the same time, so each needs a
+    /// connection of its own. Returns how many were answered.
+    pub async fn warm(&self) -> usize {
+        let requests = self
+            .providers
+            .iter()
+            .flat_map(|(provider, count)| (0..*count).map(move |_| self.warmup_request(provider)));
+        let answered = join_all(requests)
+            .await
+            .into_iter()
+            .filter(|answered| *answered)
+            .count();
+        debug!("CONNECTION_WARMUP | answered={}", answered);
+        answered
+    }
+
+    async fn warmup_request(&self, provider: &str) -> bool {
+        let response = llm_client()
+            .head(format!(
+                "{}{}",
+                self.llm_provider_url, CHAT_COMPLETIONS_PATH
+            ))
+            .header(ARCH_PROVIDER_HINT_HEADER, provider)
+            .header(ARCH_CONNECTION_WARMUP_HEADER, "true")
+            .timeout(WARMUP_TIMEOUT)
+            .send()
+            .await;
+        match response {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("CONNECTION_WARMUP | provider={} failed: {}", provider, err);
+                false
+            }
+        }
+    }
+
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        info!(
+            "keeping connections warm to {} providers every {:?}",
+            self.providers.len(),
+            self.interval
+        );
+        tokio::spawn(async move {
+            let mut ticker = interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.warm().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::configuration::ConnectionPoolConfig;
+
+    fn provider(name: &str, connection_pool: Option<ConnectionPoolConfig>) -> LlmProvider {
+        LlmProvider {
+            name: name.to_string(),
+            connection_pool,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let providers = vec![
+            provider("openai/gpt-4o", None),
+            provider(
+                "anthropic/claude-sonnet-4",
+                Some(ConnectionPoolConfig {
+                    warm_connections: Some(2),
+                    idle_timeout_ms: Some(30000),
+                    ..Default::default()
+                }),
+            ),
+        ];
+        let warmer = ConnectionWarmer::new(&providers, "http://localhost:12001").unwrap();
+        assert_eq!(
+            warmer.providers,
+            vec![("anthropic/claude-sonnet-4".to_string(), 2)]
+        );
+        assert_eq!(warmer.interval, Duration::from_secs(15));
+
+        assert!(ConnectionWarmer::new(&providers[..1], "http://localhost:12001").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_warm() {
+        let mut server = mockito::Server::new_async().await;
+        let warmups = server
+            .mock("HEAD", CHAT_COMPLETIONS_PATH)
+            .match_header(ARCH_PROVIDER_HINT_HEADER, "openai/gpt-4o")
+            .match_header(ARCH_CONNECTION_WARMUP_HEADER, "true")
+            .with_status(404)
+            .expect(3)
+            .create_async()
+            .await;
+        let providers = vec![provider(
+            "openai/gpt-4o",
+            Some(ConnectionPoolConfig {
+                warm_connections: Some(3),
+                ..Default::default()
+            }),
+        )];
+        let warmer = ConnectionWarmer::new(&providers, &server.url()).unwrap();
+        assert_eq!(warmer.warm().await, 3);
+        warmups.assert_async().await;
+    }
+}
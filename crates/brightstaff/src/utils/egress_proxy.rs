@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use common::configuration::{EgressProxyConfig, LlmProvider};
+use reqwest::{Proxy, Url};
+use tracing::{info, warn};
+
+/// Proxies of the outbound calls to model providers, set once at startup
+#[derive(Debug, Default)]
+pub struct EgressProxies {
+    proxy_url: Option<Url>,
+    no_proxy: Vec<String>,
+    /// Proxies of the providers that set their own, by upstream host
+    provider_proxies: HashMap<String, Url>,
+}
+
+static EGRESS_PROXIES: OnceLock<EgressProxies> = OnceLock::new();
+
+/// Sets the proxies from the config, before any client is built with [`egress_client`]
+pub fn init(config: Option<&EgressProxyConfig>, providers: &[LlmProvider]) {
+    let proxies = EgressProxies::new(config, providers);
+    if proxies.proxy_url.is_some() || !proxies.provider_proxies.is_empty() {
+        info!(
+            "egress proxy {:?}, {} provider proxies",
+            proxies.proxy_url.as_ref().map(Url::as_str),
+            proxies.provider_proxies.len()
+        );
+    }
+    let _ = EGRESS_PROXIES.set(proxies);
+}
+
+/// Client for calls to model providers and identity providers. Without a configured
+/// proxy it is a plain client, which honors the usual proxy environment variables.
+pub fn egress_client() -> reqwest::Client {
+    let Some(proxies) = EGRESS_PROXIES
+        .get()
+        .filter(|proxies| proxies.proxy_url.is_some() || !proxies.provider_proxies.is_empty())
+    else {
+        return reqwest::Client::new();
+    };
+    reqwest::Client::builder()
+        .proxy(Proxy::custom(move |url| proxies.proxy_for(url)))
+        .build()
+        .unwrap_or_else(|err| {
+            warn!("failed to build the egress client: {}", err);
+            reqwest::Client::new()
+        })
+}
+
+impl EgressProxies {
+    pub fn new(config: Option<&EgressProxyConfig>, providers: &[LlmProvider]) -> Self {
+        let parse = |url: &str| match Url::parse(url) {
+            Ok(url) => Some(url),
+            Err(err) => {
+                warn!("ignoring invalid proxy url {}: {}", url, err);
+                None
+            }
+        };
+        EgressProxies {
+            proxy_url: config
+                .and_then(|config| config.proxy_url.as_deref())
+                .and_then(parse),
+            no_proxy: config
+                .and_then(|config| config.no_proxy.clone())
+                .unwrap_or_default(),
+            provider_proxies: providers
+                .iter()
+                .filter_map(|provider| {
+                    let host = provider.endpoint.clone()?;
+                    Some((host, parse(provider.proxy_url.as_deref()?)?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Proxy of a request url, None to connect directly
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?;
+        if let Some(proxy) = self.provider_proxies.get(host) {
+            return Some(proxy.clone());
+        }
+        if is_loopback(host) || bypasses_proxy(host, &self.no_proxy) {
+            return None;
+        }
+        self.proxy_url.clone()
+    }
+}
+
+/// Whether a `no_proxy` entry matches the host, entries also match their subdomains
+fn bypasses_proxy(host: &str, no_proxy: &[String]) -> bool {
+    no_proxy.iter().any(|entry| {
+        let entry = entry.trim().trim_start_matches('.');
+        entry == "*"
+            || host.eq_ignore_ascii_case(entry)
+            || host
+                .to_ascii_lowercase()
+                .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+    })
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_for() {
+        let vllm = LlmProvider {
+            name: "vllm".to_string(),
+            endpoint: Some("vllm.internal".to_string()),
+            proxy_url: Some("http://mesh-egress:3128".to_string()),
+            ..Default::default()
+        };
+        let proxies = EgressProxies::new(
+            Some(&EgressProxyConfig {
+                proxy_url: Some("http://proxy.corp:3128".to_string()),
+                no_proxy: Some(vec![".corp.example.com".to_string()]),
+            }),
+            &[vllm],
+        );
+        let proxy_of = |url: &str| {
+            proxies
+                .proxy_for(&Url::parse(url).unwrap())
+                .map(|proxy| proxy.to_string())
+        };
+
+        assert_eq!(
+            proxy_of("https://api.openai.com/v1/models").as_deref(),
+            Some("http://proxy.corp:3128/")
+        );
+        assert_eq!(
+            proxy_of("http://vllm.internal:8000/v1/models").as_deref(),
+            Some("http://mesh-egress:3128/")
+        );
+        assert_eq!(proxy_of("https://corp.example.com/jwks.json"), None);
+        assert_eq!(proxy_of("https://auth.corp.example.com/jwks.json"), None);
+        assert_eq!(proxy_of("http://localhost:11434/api/tags"), None);
+        assert_eq!(proxy_of("http://127.0.0.1:8080/token"), None);
+    }
+}
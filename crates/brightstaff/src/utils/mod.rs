@@ -1 +1,4 @@
+pub mod connection_pool;
+pub mod egress_proxy;
+pub mod shutdown;
 pub mod tracing;
@@ -0,0 +1,107 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+/// Time in-flight requests get to complete once brightstaff is asked to stop, below the
+/// default termination grace period of Kubernetes
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Snapshot files written to `shutdown_snapshot_dir`
+pub const SEMANTIC_CACHE_SNAPSHOT: &str = "semantic_cache.json";
+pub const AGENT_SESSIONS_SNAPSHOT: &str = "agent_sessions.json";
+
+/// Resolves on SIGTERM or ctrl-c
+pub async fn shutdown_signal() {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                warn!("failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("received ctrl-c, shutting down"),
+        _ = terminate => info!("received SIGTERM, shutting down"),
+    }
+}
+
+/// Waits up to the timeout for the connections to complete, the ones still open after
+/// it are aborted. Returns how many were aborted.
+pub async fn drain(connections: &mut JoinSet<()>, timeout: Duration) -> usize {
+    let drained = tokio::time::timeout(timeout, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_ok() {
+        return 0;
+    }
+    let aborted = connections.len();
+    connections.shutdown().await;
+    aborted
+}
+
+/// Writes state held in memory to a snapshot file, read back by [`read_snapshot`] on the
+/// next start. The file is replaced whole, a snapshot is never half written.
+pub fn write_snapshot<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, serde_json::to_vec(value)?)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Reads the snapshot written on the last shutdown and removes it, so the state isn't
+/// restored again after a crash. None when there is none.
+pub fn read_snapshot<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    std::fs::remove_file(path)?;
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain() {
+        let mut connections = JoinSet::new();
+        connections.spawn(tokio::time::sleep(Duration::from_millis(10)));
+        assert_eq!(drain(&mut connections, Duration::from_secs(5)).await, 0);
+
+        connections.spawn(tokio::time::sleep(Duration::from_millis(10)));
+        connections.spawn(std::future::pending());
+        assert_eq!(drain(&mut connections, Duration::from_millis(100)).await, 1);
+        assert!(connections.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_is_read_once() {
+        let path = std::env::temp_dir()
+            .join(format!("snapshot-{}", uuid::Uuid::new_v4()))
+            .join("state.json");
+        assert_eq!(read_snapshot::<Vec<u32>>(&path).unwrap(), None);
+
+        write_snapshot(&path, &vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            read_snapshot::<Vec<u32>>(&path).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(read_snapshot::<Vec<u32>>(&path).unwrap(), None);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
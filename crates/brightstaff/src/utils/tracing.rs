@@ -6,9 +6,14 @@ use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerPro
 use opentelemetry_stdout::SpanExporter;
 use time::macros::format_description;
 use tracing::{Event, Subscriber};
-use tracing_subscriber::fmt::{format, time::FormatTime, FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::{
+    format, time::FormatTime, FmtContext, FormatEvent, FormatFields, FormattedFields,
+};
 use tracing_subscriber::EnvFilter;
 
+/// Name of the span each request is handled in, its fields prefix the request's log lines
+pub const REQUEST_SPAN: &str = "request";
+
 struct BracketedTime;
 
 impl FormatTime for BracketedTime {
@@ -47,6 +52,14 @@ where
             event.metadata().level().to_string().to_lowercase()
         )?;
 
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root().filter(|span| span.name() == REQUEST_SPAN) {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    write!(writer, "[{}] ", fields)?;
+                }
+            }
+        }
+
         ctx.field_format().format_fields(writer.by_ref(), event)?;
 
         writeln!(writer)
@@ -22,6 +22,10 @@ pub struct ChatCompletionsRequest {
     pub stream_options: Option<StreamOptions>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -568,6 +572,8 @@ mod test {
                 include_usage: true,
             }),
             metadata: None,
+            temperature: None,
+            max_tokens: None,
         };
 
         let serialized = serde_json::to_string_pretty(&chat_completions_request).unwrap();
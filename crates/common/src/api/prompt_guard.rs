@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::configuration::GuardStage;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PromptGuardTask {
     #[serde(rename = "jailbreak")]
@@ -23,3 +25,18 @@ pub struct PromptGuardResponse {
     pub toxic_verdict: Option<bool>,
     pub jailbreak_verdict: Option<bool>,
 }
+
+/// Body sent to external guard services
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardRequest {
+    pub guard: String,
+    pub stage: GuardStage,
+    pub input: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GuardResponse {
+    pub flagged: bool,
+    pub reason: Option<String>,
+    pub score: Option<f64>,
+}
@@ -0,0 +1,245 @@
+//! gzip and brotli content encodings of request and response bodies.
+//!
+//! Streaming bodies are encoded and decoded chunk by chunk, every chunk is flushed so that
+//! SSE events reach the client as soon as they are produced.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use flate2::write::{GzDecoder, GzEncoder};
+use flate2::Compression;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// Parse a Content-Encoding value, None for identity and unsupported encodings
+    pub fn from_header(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            "br" => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Whether a Content-Encoding value needs no decoding
+    pub fn is_identity(value: &str) -> bool {
+        let value = value.trim();
+        value.is_empty() || value.eq_ignore_ascii_case("identity")
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// Encoding preferred by an Accept-Encoding value. Brotli wins over gzip at equal
+    /// quality, None when the client accepts neither.
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut best: Option<(ContentEncoding, f32)> = None;
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let encoding = match parts.next().unwrap_or_default().trim() {
+                "*" => ContentEncoding::Gzip,
+                name => match ContentEncoding::from_header(name) {
+                    Some(encoding) => encoding,
+                    None => continue,
+                },
+            };
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+            let better = best.is_none_or(|(current, current_quality)| {
+                quality > current_quality
+                    || (quality == current_quality
+                        && encoding == ContentEncoding::Brotli
+                        && current == ContentEncoding::Gzip)
+            });
+            if better {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+/// Output buffer shared with the gzip and brotli writers, drained after every chunk
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Sink {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compresses a body chunk by chunk
+pub struct StreamEncoder {
+    encoding: ContentEncoding,
+    writer: Box<dyn Write + Send>,
+    sink: Sink,
+}
+
+impl StreamEncoder {
+    pub fn new(encoding: ContentEncoding) -> Self {
+        let sink = Sink::default();
+        let writer: Box<dyn Write + Send> = match encoding {
+            ContentEncoding::Gzip => Box::new(GzEncoder::new(sink.clone(), Compression::fast())),
+            ContentEncoding::Brotli => Box::new(brotli::CompressorWriter::new(
+                sink.clone(),
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            )),
+        };
+        StreamEncoder {
+            encoding,
+            writer,
+            sink,
+        }
+    }
+
+    pub fn encoding(&self) -> ContentEncoding {
+        self.encoding
+    }
+
+    /// Compressed bytes of the chunk, flushed so the client can decode them right away
+    pub fn encode(&mut self, chunk: &[u8]) -> Vec<u8> {
+        // writes to the in memory sink can't fail
+        let _ = self.writer.write_all(chunk);
+        let _ = self.writer.flush();
+        self.sink.take()
+    }
+
+    /// Compressed bytes of the last chunk followed by the end of the stream
+    pub fn finish(mut self, chunk: &[u8]) -> Vec<u8> {
+        let _ = self.writer.write_all(chunk);
+        // both writers finish the stream when dropped
+        drop(self.writer);
+        self.sink.take()
+    }
+}
+
+/// Decompresses a body chunk by chunk
+pub struct StreamDecoder {
+    encoding: ContentEncoding,
+    writer: Box<dyn Write + Send>,
+    sink: Sink,
+}
+
+impl StreamDecoder {
+    pub fn new(encoding: ContentEncoding) -> Self {
+        let sink = Sink::default();
+        let writer: Box<dyn Write + Send> = match encoding {
+            ContentEncoding::Gzip => Box::new(GzDecoder::new(sink.clone())),
+            ContentEncoding::Brotli => Box::new(brotli::DecompressorWriter::new(
+                sink.clone(),
+                BROTLI_BUFFER_SIZE,
+            )),
+        };
+        StreamDecoder {
+            encoding,
+            writer,
+            sink,
+        }
+    }
+
+    pub fn encoding(&self) -> ContentEncoding {
+        self.encoding
+    }
+
+    /// Decompressed bytes of the chunk, empty while a compressed block is incomplete
+    pub fn decode(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        self.writer.write_all(chunk)?;
+        self.writer.flush()?;
+        Ok(self.sink.take())
+    }
+}
+
+/// Compresses a whole body
+pub fn encode(encoding: ContentEncoding, body: &[u8]) -> Vec<u8> {
+    StreamEncoder::new(encoding).finish(body)
+}
+
+/// Decompresses a whole body
+pub fn decode(encoding: ContentEncoding, body: &[u8]) -> io::Result<Vec<u8>> {
+    StreamDecoder::new(encoding).decode(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            ContentEncoding::negotiate("gzip, deflate, br"),
+            Some(ContentEncoding::Brotli)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate("br;q=0.5, gzip"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(ContentEncoding::negotiate("gzip;q=0, deflate"), None);
+        assert_eq!(ContentEncoding::negotiate("*"), Some(ContentEncoding::Gzip));
+        assert_eq!(ContentEncoding::negotiate("identity"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let body = br#"{"choices":[{"message":{"content":"hello hello hello hello"}}]}"#;
+        for encoding in [ContentEncoding::Gzip, ContentEncoding::Brotli] {
+            let encoded = encode(encoding, body);
+            assert_ne!(encoded, body);
+            assert_eq!(decode(encoding, &encoded).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn test_streaming_chunks_decode_as_they_arrive() {
+        let events = [
+            "data: {\"delta\":\"Hel\"}\n\n",
+            "data: {\"delta\":\"lo\"}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        for encoding in [ContentEncoding::Gzip, ContentEncoding::Brotli] {
+            let mut encoder = StreamEncoder::new(encoding);
+            let mut decoder = StreamDecoder::new(encoding);
+            for event in &events[..2] {
+                let decoded = decoder.decode(&encoder.encode(event.as_bytes())).unwrap();
+                assert_eq!(String::from_utf8(decoded).unwrap(), *event);
+            }
+            let last = encoder.finish(events[2].as_bytes());
+            assert_eq!(decoder.decode(&last).unwrap(), events[2].as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_body() {
+        assert!(decode(ContentEncoding::Gzip, b"not gzip at all").is_err());
+    }
+}
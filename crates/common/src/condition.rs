@@ -0,0 +1,428 @@
+//! Conditions of filter chain steps
+//!
+//! A condition is a small boolean expression over variables of the request, e.g.
+//! `last_user_message contains "refund" && metadata.tier != "free"`. Values are compared
+//! with `==`, `!=`, `<`, `<=`, `>`, `>=`, `contains`, `starts_with` and `ends_with`, and
+//! combined with `&&`, `||`, `!` and parentheses. Literals are quoted text, numbers,
+//! `true`, `false` and `null`. A value on its own holds unless it's missing, `null`,
+//! `false`, `0`, empty text or an empty list.
+
+use serde_json::{Number, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConditionError {
+    #[error("unexpected character {ch:?} at offset {offset}")]
+    UnexpectedChar { ch: char, offset: usize },
+    #[error("text at offset {0} is not closed")]
+    UnclosedText(usize),
+    #[error("invalid number {0:?}")]
+    InvalidNumber(String),
+    #[error("expected {expected} at offset {offset}")]
+    Expected {
+        expected: &'static str,
+        offset: usize,
+    },
+    #[error("unexpected input at offset {0}")]
+    TrailingInput(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(Op),
+    Variable(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Variable(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+    Value(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ConditionError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            end: source.len(),
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(ConditionError::TrailingInput(parser.offset()));
+        }
+        Ok(Condition { expr })
+    }
+
+    /// Whether the condition holds, `variables` resolves the variables it names
+    pub fn evaluate(&self, variables: &dyn Fn(&str) -> Option<Value>) -> bool {
+        self.expr.evaluate(variables)
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, variables: &dyn Fn(&str) -> Option<Value>) -> bool {
+        match self {
+            Expr::Or(left, right) => left.evaluate(variables) || right.evaluate(variables),
+            Expr::And(left, right) => left.evaluate(variables) && right.evaluate(variables),
+            Expr::Not(expr) => !expr.evaluate(variables),
+            Expr::Compare(left, op, right) => {
+                compare(&left.value(variables), *op, &right.value(variables))
+            }
+            Expr::Value(operand) => is_truthy(&operand.value(variables)),
+        }
+    }
+}
+
+impl Operand {
+    fn value(&self, variables: &dyn Fn(&str) -> Option<Value>) -> Value {
+        match self {
+            Operand::Variable(name) => variables(name).unwrap_or(Value::Null),
+            Operand::Literal(value) => value.clone(),
+        }
+    }
+}
+
+fn compare(left: &Value, op: Op, right: &Value) -> bool {
+    match op {
+        Op::Eq => loosely_equal(left, right),
+        Op::Ne => !loosely_equal(left, right),
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+            let (Some(left), Some(right)) = (as_number(left), as_number(right)) else {
+                return false;
+            };
+            match op {
+                Op::Lt => left < right,
+                Op::Le => left <= right,
+                Op::Gt => left > right,
+                _ => left >= right,
+            }
+        }
+        Op::Contains => match (left, right) {
+            (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+            (Value::Array(items), _) => items.iter().any(|item| loosely_equal(item, right)),
+            _ => false,
+        },
+        Op::StartsWith => matches!(
+            (left, right),
+            (Value::String(text), Value::String(prefix)) if text.starts_with(prefix.as_str())
+        ),
+        Op::EndsWith => matches!(
+            (left, right),
+            (Value::String(text), Value::String(suffix)) if text.ends_with(suffix.as_str())
+        ),
+    }
+}
+
+/// Numbers are compared by value, headers and metadata often hold them as text
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn loosely_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), _) | (_, Value::Number(_)) => {
+            as_number(left).is_some() && as_number(left) == as_number(right)
+        }
+        _ => left == right,
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(offset, ch)) = chars.peek() {
+        let token = match ch {
+            _ if ch.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if ch == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                }
+            }
+            '&' | '|' | '=' => {
+                chars.next();
+                if chars.next_if(|&(_, next)| next == ch).is_none() {
+                    return Err(ConditionError::UnexpectedChar { ch, offset });
+                }
+                match ch {
+                    '&' => Token::And,
+                    '|' => Token::Or,
+                    _ => Token::Op(Op::Eq),
+                }
+            }
+            '!' | '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if(|&(_, next)| next == '=').is_some();
+                match (ch, or_equal) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Op(Op::Ne),
+                    ('<', false) => Token::Op(Op::Lt),
+                    ('<', true) => Token::Op(Op::Le),
+                    ('>', false) => Token::Op(Op::Gt),
+                    _ => Token::Op(Op::Ge),
+                }
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, next)) if next == ch => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => text.push(escaped),
+                            None => return Err(ConditionError::UnclosedText(offset)),
+                        },
+                        Some((_, next)) => text.push(next),
+                        None => return Err(ConditionError::UnclosedText(offset)),
+                    }
+                }
+                Token::Literal(Value::String(text))
+            }
+            _ if ch.is_alphanumeric() || ch == '_' || ch == '-' => {
+                let mut word = String::new();
+                while let Some((_, next)) = chars
+                    .next_if(|&(_, next)| next.is_alphanumeric() || matches!(next, '_' | '-' | '.'))
+                {
+                    word.push(next);
+                }
+                word_token(&word)?
+            }
+            _ => return Err(ConditionError::UnexpectedChar { ch, offset }),
+        };
+        tokens.push((token, offset));
+    }
+    Ok(tokens)
+}
+
+fn word_token(word: &str) -> Result<Token, ConditionError> {
+    let token = match word {
+        "true" => Token::Literal(Value::Bool(true)),
+        "false" => Token::Literal(Value::Bool(false)),
+        "null" => Token::Literal(Value::Null),
+        "contains" => Token::Op(Op::Contains),
+        "starts_with" => Token::Op(Op::StartsWith),
+        "ends_with" => Token::Op(Op::EndsWith),
+        _ if word.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-') => {
+            let number = word
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .ok_or_else(|| ConditionError::InvalidNumber(word.to_string()))?;
+            Token::Literal(Value::Number(number))
+        }
+        _ => Token::Variable(word.to_string()),
+    };
+    Ok(token)
+}
+
+/// Recursive descent over the tokens, `!` binds tighter than `&&`, which binds tighter
+/// than `||`
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Offset reported for errors at the end of the source
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, offset)| *offset)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<Expr, ConditionError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ConditionError> {
+        let mut expr = self.not()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, ConditionError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.or()?;
+            if !self.eat(&Token::RParen) {
+                return Err(ConditionError::Expected {
+                    expected: "')'",
+                    offset: self.offset(),
+                });
+            }
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        if let Some(&Token::Op(op)) = self.peek() {
+            self.pos += 1;
+            return Ok(Expr::Compare(left, op, self.operand()?));
+        }
+        Ok(Expr::Value(left))
+    }
+
+    fn operand(&mut self) -> Result<Operand, ConditionError> {
+        let operand = match self.peek() {
+            Some(Token::Variable(name)) => Operand::Variable(name.clone()),
+            Some(Token::Literal(value)) => Operand::Literal(value.clone()),
+            _ => {
+                return Err(ConditionError::Expected {
+                    expected: "a variable or value",
+                    offset: self.offset(),
+                })
+            }
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn variables(name: &str) -> Option<Value> {
+        match name {
+            "last_user_message" => Some(json!("I want a refund for order 42")),
+            "message_count" => Some(json!(3)),
+            "header.x-tier" => Some(json!("gold")),
+            "header.x-retries" => Some(json!("2")),
+            "metadata.tags" => Some(json!(["billing", "urgent"])),
+            _ => None,
+        }
+    }
+
+    fn holds(source: &str) -> bool {
+        Condition::parse(source).unwrap().evaluate(&variables)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert!(holds(r#"last_user_message contains "refund""#));
+        assert!(holds("last_user_message starts_with 'I want'"));
+        assert!(!holds(r#"last_user_message ends_with "refund""#));
+        assert!(holds("message_count >= 3 && header.x-retries < 3"));
+        assert!(holds(
+            r#"header.x-tier == "gold" || header.x-tier == "silver""#
+        ));
+        assert!(holds(r#"metadata.tags contains "urgent""#));
+        assert!(holds("header.x-retries == 2"));
+        assert!(holds("!metadata.missing && header.x-tier"));
+        assert!(holds(r#"metadata.missing == null"#));
+        // && binds tighter than ||
+        assert!(holds("true || false && false"));
+        assert!(!holds("(true || false) && false"));
+        assert!(!holds(r#"message_count > "three""#));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Condition::parse(r#"header.x-tier = "gold""#),
+            Err(ConditionError::UnexpectedChar {
+                ch: '=',
+                offset: 14
+            })
+        );
+        assert_eq!(
+            Condition::parse(r#"last_user_message contains "refund"#),
+            Err(ConditionError::UnclosedText(27))
+        );
+        assert_eq!(
+            Condition::parse("(message_count > 2"),
+            Err(ConditionError::Expected {
+                expected: "')'",
+                offset: 18
+            })
+        );
+        assert_eq!(
+            Condition::parse("message_count >"),
+            Err(ConditionError::Expected {
+                expected: "a variable or value",
+                offset: 15
+            })
+        );
+        assert_eq!(
+            Condition::parse("message_count 2"),
+            Err(ConditionError::TrailingInput(14))
+        );
+        assert_eq!(
+            Condition::parse("message_count > 1x"),
+            Err(ConditionError::InvalidNumber("1x".to_string()))
+        );
+    }
+}
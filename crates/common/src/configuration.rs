@@ -1,11 +1,17 @@
+use hermesllm::apis::gemini::gemini_model_name;
 use hermesllm::apis::openai::{ModelDetail, ModelObject, Models};
+use hermesllm::apis::parse_mode::ParseMode;
+use hermesllm::clients::endpoints::SupportedAPIsFromClient;
+use hermesllm::providers::{claude_model_id, is_claude_model, SystemPromptMode};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Display;
 
 use crate::api::open_ai::{
     ChatCompletionTool, FunctionDefinition, FunctionParameter, FunctionParameters, ParameterType,
 };
+use crate::consts::{ARCH_REQUEST_ID_HEADER, REQUEST_ID_HEADER, TRACE_PARENT_HEADER};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Routing {
@@ -16,16 +22,191 @@ pub struct Routing {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelAlias {
     pub target: String,
+    /// Share of the traffic sent to other models for A/B tests, the rest goes to `target`
+    pub splits: Option<Vec<TrafficSplit>>,
+    /// Request header, e.g. a user id, whose value keeps a client on the same split
+    pub sticky_header: Option<String>,
+    /// Model that a share of the requests is mirrored to, its responses are discarded
+    pub shadow: Option<ShadowTraffic>,
+    /// Name of the prompt template rendered into the system prompt of requests to the alias
+    pub prompt_template: Option<String>,
+    /// Text added to or replacing the system prompt of requests to the alias, applied
+    /// after `prompt_template`
+    pub system_prompt_policy: Option<SystemPromptPolicy>,
+    /// Serve completions of earlier, semantically similar prompts to the alias
+    pub semantic_cache: Option<SemanticCacheConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SemanticCacheConfig {
+    pub embedding_model: String,
+    /// Provider serving the embeddings model, sent as the provider hint
+    pub embedding_provider: Option<String>,
+    /// Minimum cosine similarity to a cached prompt to serve its completion, 0.95 when not set
+    pub similarity_threshold: Option<f64>,
+    /// How long completions are served from the cache, an hour when not set
+    pub ttl_seconds: Option<u64>,
+    /// Completions kept per alias, the oldest ones are dropped beyond it, 1000 when not set
+    pub max_entries: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrafficSplit {
+    pub target: String,
+    /// Percentage of the requests to the alias
+    pub weight: f64,
+    /// Name of the split on metrics and traces, defaults to the target
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShadowTraffic {
+    pub target: String,
+    /// Percentage of the requests to the alias that are mirrored
+    pub percentage: f64,
+    /// Deadline for the mirrored request, defaults to the deadline of the target provider
+    /// or a minute
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Agent {
     pub id: String,
-    pub transport: Option<String>,
+    /// Transport of an MCP filter, streamable HTTP when not set
+    pub transport: Option<McpTransport>,
     pub tool: Option<String>,
+    /// Endpoint of the agent, empty for filters run over stdio
+    #[serde(default)]
     pub url: String,
     #[serde(rename = "type")]
     pub agent_type: Option<String>,
+    /// Per attempt timeout for calls to the agent
+    pub timeout_ms: Option<u64>,
+    /// Retries on connection errors, timeouts and 5xx responses
+    pub max_retries: Option<u32>,
+    /// Base backoff between retries, doubled on every attempt
+    pub retry_backoff_ms: Option<u64>,
+    pub circuit_breaker: Option<AgentCircuitBreaker>,
+    /// Pause before every call to the agent until it is approved on /agents/approvals
+    pub requires_approval: Option<bool>,
+    /// Receives pending approval events of the agent
+    pub approval_webhook: Option<String>,
+    /// How long a call waits for a decision before it is rejected
+    pub approval_timeout_ms: Option<u64>,
+    /// Settings of the built-in `retrieval` filter, `url` is the vector store endpoint
+    pub retrieval: Option<RetrievalConfig>,
+    /// API the agent serves when it is the terminal agent, chat completions when not set
+    pub api: Option<AgentApi>,
+    /// How often the tools of an MCP filter are listed again to pick up schema changes
+    pub tools_refresh_interval_ms: Option<u64>,
+    /// Process brightstaff runs the MCP server of a filter in, for `transport: stdio`
+    pub stdio: Option<StdioServerConfig>,
+}
+
+/// How brightstaff talks to an MCP filter
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum McpTransport {
+    /// JSON-RPC requests posted to `/mcp` of the filter through Envoy
+    #[default]
+    StreamableHttp,
+    /// Legacy HTTP with SSE: responses arrive on an SSE stream opened on the filter's url,
+    /// which announces the endpoint requests are posted to
+    Sse,
+    /// Child process of brightstaff, with newline delimited JSON-RPC on stdin and stdout
+    Stdio,
+}
+
+impl Display for McpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            McpTransport::StreamableHttp => write!(f, "streamable-http"),
+            McpTransport::Sse => write!(f, "sse"),
+            McpTransport::Stdio => write!(f, "stdio"),
+        }
+    }
+}
+
+/// MCP server started by brightstaff for a filter, and restarted when it exits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StdioServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Set on top of the environment of brightstaff
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory of the server
+    pub cwd: Option<String>,
+    /// Restarted when it fails unless set otherwise
+    pub restart: Option<RestartPolicy>,
+    /// Restarts in a row, without the server getting ready in between, before it's given
+    /// up on. Unlimited when not set.
+    pub max_restarts: Option<u32>,
+    /// Delay before a restart, doubled on every restart in a row up to 30s
+    pub restart_backoff_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    Always,
+    /// Restarted when it exits with an error or is killed
+    #[default]
+    OnFailure,
+    Never,
+}
+
+/// API of a terminal agent, requests are sent and responses returned in the client's API
+/// whatever the agent serves
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentApi {
+    /// OpenAI `/v1/chat/completions`
+    #[default]
+    ChatCompletions,
+    /// Anthropic `/v1/messages`
+    Messages,
+}
+
+/// Built-in retrieval filter: the last user message is embedded, the closest chunks are
+/// fetched from a vector store and added to the conversation as context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalConfig {
+    pub vector_store: VectorStoreType,
+    /// Qdrant collection, pgvector table or Pinecone namespace
+    pub collection: Option<String>,
+    pub embedding_model: String,
+    /// See [`SemanticCacheConfig::embedding_provider`]
+    pub embedding_provider: Option<String>,
+    /// Number of chunks added to the conversation, 4 when not set
+    pub top_k: Option<usize>,
+    /// Chunks scoring below it are dropped
+    pub score_threshold: Option<f32>,
+    /// Sent as `api-key` to Qdrant and `Api-Key` to Pinecone
+    pub api_key: Option<String>,
+    /// Payload field (or column) holding the chunk text, `text` when not set
+    pub text_field: Option<String>,
+    /// Payload field (or column) holding the chunk source, `source` when not set
+    pub source_field: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorStoreType {
+    Qdrant,
+    Pgvector,
+    Pinecone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentCircuitBreaker {
+    /// Consecutive failures after which the circuit opens
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a trial call is let through
+    pub cooldown_ms: u64,
+    /// Skip the filter instead of failing the request while the circuit is open
+    pub skip_when_open: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,7 +214,34 @@ pub struct AgentFilterChain {
     pub id: String,
     pub default: Option<bool>,
     pub description: Option<String>,
-    pub filter_chain: Option<Vec<String>>,
+    pub filter_chain: Option<Vec<FilterStep>>,
+    /// Filters applied to the streamed response of the agent, chunk by chunk
+    pub stream_filter_chain: Option<Vec<String>>,
+}
+
+/// Step of a filter chain, a filter or filters that only run when a condition holds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum FilterStep {
+    Filter(String),
+    Conditional(ConditionalFilters),
+}
+
+impl From<&str> for FilterStep {
+    fn from(filter: &str) -> Self {
+        FilterStep::Filter(filter.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConditionalFilters {
+    /// Condition on the request, see `crate::condition`
+    pub when: String,
+    /// Filters run when the condition holds
+    pub then: Vec<String>,
+    /// Filters run when it doesn't, the step is skipped when not set
+    #[serde(rename = "else")]
+    pub otherwise: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +250,63 @@ pub struct Listener {
     pub router: Option<String>,
     pub agents: Option<Vec<AgentFilterChain>>,
     pub port: u16,
+    /// Client APIs accepted on this listener, all of them when not set
+    pub allowed_apis: Option<Vec<ListenerApi>>,
+    /// Provider used when the request doesn't resolve to a configured provider
+    pub default_provider: Option<String>,
+    /// Forward the client's own credentials instead of the provider access key
+    pub passthrough_auth: Option<bool>,
+    /// Settings of the `embedding` agent router
+    pub embedding_router: Option<EmbeddingRouterConfig>,
+    /// Reject requests without a valid JWT of the identity provider
+    pub oidc: Option<OidcConfig>,
+}
+
+/// Agent routing on the similarity between the request and agent descriptions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingRouterConfig {
+    pub model: String,
+    /// Provider of `model`, like [`SemanticCacheConfig::embedding_provider`]
+    pub model_provider: Option<String>,
+    /// Minimum cosine similarity to route to an agent, the default agent is used below it
+    pub confidence_threshold: Option<f64>,
+}
+
+impl Listener {
+    pub fn allows_api(&self, api: &SupportedAPIsFromClient) -> bool {
+        self.allowed_apis
+            .as_ref()
+            .map(|apis| apis.iter().any(|allowed| allowed.matches(api)))
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ListenerApi {
+    #[serde(rename = "openai")]
+    OpenAIChatCompletions,
+    #[serde(rename = "openai_responses")]
+    OpenAIResponses,
+    #[serde(rename = "anthropic")]
+    AnthropicMessages,
+}
+
+impl ListenerApi {
+    pub fn matches(&self, api: &SupportedAPIsFromClient) -> bool {
+        matches!(
+            (self, api),
+            (
+                ListenerApi::OpenAIChatCompletions,
+                SupportedAPIsFromClient::OpenAIChatCompletions(_)
+            ) | (
+                ListenerApi::OpenAIResponses,
+                SupportedAPIsFromClient::OpenAIResponsesAPI(_)
+            ) | (
+                ListenerApi::AnthropicMessages,
+                SupportedAPIsFromClient::AnthropicMessagesAPI(_)
+            )
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +314,23 @@ pub struct StateStorageConfig {
     #[serde(rename = "type")]
     pub storage_type: StateStorageType,
     pub connection_string: Option<String>,
+    /// Idle time after which agent sessions expire, defaults to a day
+    pub session_ttl_seconds: Option<u64>,
+    /// Rolling summaries that keep long agent sessions short
+    pub session_summarization: Option<SessionSummarizationConfig>,
+}
+
+/// Older messages of long agent sessions are replaced with a summary written by `model`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionSummarizationConfig {
+    /// Model writing the summaries, a cheap one is enough
+    pub model: String,
+    /// Sessions are summarized once they hold more messages, defaults to 40
+    pub max_messages: Option<usize>,
+    /// Most recent messages kept as they are next to the summary, defaults to 10
+    pub keep_messages: Option<usize>,
+    /// Limit on the length of a summary, defaults to 512 tokens
+    pub max_summary_tokens: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -77,6 +359,268 @@ pub struct Configuration {
     pub filters: Option<Vec<Agent>>,
     pub listeners: Vec<Listener>,
     pub state_storage: Option<StateStorageConfig>,
+    pub function_calling: Option<FunctionCallingConfig>,
+    pub usage_export: Option<UsageExportConfig>,
+    pub model_discovery: Option<ModelDiscoveryConfig>,
+    /// llm_gateway middlewares, run in this order on every llm request and response
+    pub middlewares: Option<Vec<MiddlewareConfig>>,
+    /// Edits of llm request bodies, applied by llm_gateway in this order
+    pub request_rules: Option<Vec<RequestRule>>,
+    /// Service that can modify or reject llm requests before they are routed
+    pub request_callout: Option<RequestCalloutConfig>,
+    /// System prompts managed in the config, by name
+    pub prompt_templates: Option<HashMap<String, PromptTemplateConfig>>,
+    /// Ratelimit selectors taken from the claims of the JWT in the Authorization header
+    pub ratelimit_jwt: Option<RatelimitJwtConfig>,
+    /// Forward proxy of the traffic to model providers
+    pub egress_proxy: Option<EgressProxyConfig>,
+    /// Providers and residencies the requests of each tenant may be routed to
+    pub data_residency: Option<DataResidencyConfig>,
+    /// External scanner of the text of llm requests and responses
+    pub dlp: Option<DlpConfig>,
+    /// Whole llm conversations kept for debugging and compliance review
+    pub conversation_archive: Option<ConversationArchiveConfig>,
+}
+
+/// Restricts the providers a tenant's requests may use, e.g. to models hosted in the EU
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DataResidencyConfig {
+    /// Policy of each tenant
+    pub tenants: Option<HashMap<String, ResidencyPolicy>>,
+    /// Policy of requests without a tenant or of other tenants, unrestricted when left out
+    pub default: Option<ResidencyPolicy>,
+}
+
+/// Providers a request may be routed to. Both lists have to allow the provider when set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ResidencyPolicy {
+    /// Allowed `residency` of the providers, e.g. `eu`
+    pub residencies: Option<Vec<String>>,
+    /// Allowed provider names
+    pub providers: Option<Vec<String>>,
+}
+
+/// HTTP proxy that outbound provider calls go through, e.g. in corporate networks
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EgressProxyConfig {
+    /// `http://host:port` of the proxy, providers can set their own `proxy_url`
+    pub proxy_url: Option<String>,
+    /// Hosts reached directly, an entry also matches its subdomains and `*` matches all
+    pub no_proxy: Option<Vec<String>>,
+}
+
+/// Turns the claims of the validated bearer JWT of llm requests into ratelimit selectors,
+/// so limits apply per end user without special headers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RatelimitJwtConfig {
+    #[serde(flatten)]
+    pub validation: JwtValidationConfig,
+    /// Ratelimit selector key of each claim, e.g. `sub: user_id`
+    pub claims: HashMap<String, String>,
+}
+
+/// Validation of JWTs against the keys of a JWKS endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtValidationConfig {
+    pub jwks_url: String,
+    /// Expected `iss` of the tokens, not checked when left out
+    pub issuer: Option<String>,
+    /// Expected `aud` of the tokens, not checked when left out
+    pub audience: Option<String>,
+    /// How long the keys are cached, defaults to ten minutes. Tokens signed with an
+    /// unknown key id fetch the keys again sooner.
+    pub jwks_cache_ttl_ms: Option<u64>,
+}
+
+/// Requests to the listener need a bearer JWT of the identity provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    #[serde(flatten)]
+    pub validation: JwtValidationConfig,
+    /// Request header each claim is copied to, e.g. `org_id: x-arch-tenant-id`, for the
+    /// policies keyed on headers such as routing, rate limits and usage export. Values
+    /// the client sent in these headers are dropped.
+    pub claim_headers: Option<HashMap<String, String>>,
+}
+
+/// System prompt with `{{ variable }}` placeholders filled from the request metadata,
+/// see `prompt_template`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromptTemplateConfig {
+    pub template: String,
+    /// Label of this revision of the template, reported on traces
+    pub version: Option<String>,
+}
+
+impl PromptTemplateConfig {
+    /// `name@version` of the template, or its name when it has no version
+    pub fn label(&self, name: &str) -> String {
+        match &self.version {
+            Some(version) => format!("{}@{}", name, version),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Pre-request callout to a user service, e.g. a policy engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestCalloutConfig {
+    pub url: String,
+    /// Deadline of the callout, defaults to a second
+    pub timeout_ms: Option<u64>,
+    /// What happens to requests when the service fails or times out, defaults to `fail_closed`
+    pub failure_mode: Option<CalloutFailureMode>,
+}
+
+/// Data loss prevention service that scans the text of llm requests and responses and
+/// allows, redacts or blocks it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DlpConfig {
+    pub url: String,
+    /// Deadline of a scan, defaults to a second
+    pub timeout_ms: Option<u64>,
+    /// What happens to requests when the service fails or times out, defaults to `fail_closed`
+    pub failure_mode: Option<CalloutFailureMode>,
+    /// Scan non streaming responses too, defaults to true
+    pub scan_responses: Option<bool>,
+    /// How long verdicts are reused for the same text, defaults to 5 minutes
+    pub cache_ttl_ms: Option<u64>,
+    /// Verdicts kept in the cache, defaults to 10000
+    pub cache_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CalloutFailureMode {
+    /// Send the request on unchanged
+    FailOpen,
+    /// Reject the request
+    #[default]
+    FailClosed,
+}
+
+/// A middleware compiled into llm_gateway, looked up by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiddlewareConfig {
+    pub name: String,
+    /// Settings of the middleware, their shape is up to the middleware
+    pub config: Option<serde_json::Value>,
+}
+
+/// Declarative edit of the llm requests it matches, e.g. forcing stream or capping
+/// max_tokens. Fields are dotted paths into the body the client sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestRule {
+    pub name: String,
+    /// Requests the rule applies to, all of them when left out
+    #[serde(rename = "match", default)]
+    pub matches: RequestRuleMatch,
+    pub actions: Vec<RequestRuleAction>,
+    /// Log what the rule would change without changing the request (default false)
+    pub dry_run: Option<bool>,
+}
+
+/// Every condition that is set has to hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestRuleMatch {
+    /// Request path, e.g. `/v1/chat/completions`
+    pub path: Option<String>,
+    /// Requested model, upstream model or provider name
+    pub models: Option<Vec<String>>,
+    /// Request headers and their values
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestRuleAction {
+    /// Sets the field, e.g. `stream` to true or `model` to another model of the provider
+    Set {
+        field: String,
+        value: serde_json::Value,
+    },
+    Remove {
+        field: String,
+    },
+    /// Lowers a numeric field above `max` to `max`, fields that aren't set stay unset
+    Cap {
+        field: String,
+        max: serde_json::Number,
+    },
+    /// Appends a message to the conversation, system messages go to the system prompt
+    AddMessage {
+        role: String,
+        content: String,
+    },
+}
+
+/// Periodic refresh of the models each provider offers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelDiscoveryConfig {
+    /// How often each provider is asked for its models, defaults to 5 minutes
+    pub interval_ms: Option<u64>,
+    /// Random delay of up to this much added to every interval, defaults to 30 seconds
+    pub jitter_ms: Option<u64>,
+    /// Names of the providers to discover models of, defaults to all of them
+    pub providers: Option<Vec<String>>,
+}
+
+/// Per request usage records, batched and shipped to a sink for billing and analytics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageExportConfig {
+    pub sink: UsageSinkConfig,
+    /// How often buffered records are shipped, defaults to 10 seconds
+    pub flush_interval_ms: Option<u64>,
+    /// Most records sent to the sink at once, defaults to 1000
+    pub max_batch_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UsageSinkConfig {
+    /// POSTs every batch as a json array
+    Webhook {
+        url: String,
+        headers: Option<HashMap<String, String>>,
+    },
+    /// Appends records to a local file, one json object per line
+    File { path: String },
+    /// Uploads every batch as a json lines object to an S3 compatible bucket
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: Option<String>,
+        /// Key prefix of the uploaded objects, e.g. `usage/`
+        prefix: Option<String>,
+        access_key_id: String,
+        secret_access_key: String,
+    },
+}
+
+/// Model that matches prompts to prompt targets. Defaults to Arch-Function served
+/// by the bundled model server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunctionCallingConfig {
+    /// Name of the endpoint serving the model. The cli registers `url` as an endpoint
+    /// and fills this in.
+    pub endpoint: Option<String>,
+    pub url: Option<String>,
+    pub path: Option<String>,
+    pub model: Option<String>,
+    pub api: Option<FunctionCallingApi>,
+    pub access_key: Option<String>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionCallingApi {
+    /// Arch-Function protocol of the model server (`/function_calling`)
+    #[default]
+    Arch,
+    /// Any OpenAI compatible chat completions server supporting tools
+    #[serde(rename = "openai")]
+    OpenAi,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -84,12 +628,261 @@ pub struct Overrides {
     pub prompt_target_intent_matching_threshold: Option<f64>,
     pub optimize_context_window: Option<bool>,
     pub use_agent_orchestrator: Option<bool>,
+    /// Requests with a body larger than this are rejected with 413
+    pub max_request_body_bytes: Option<usize>,
+    /// Upstream responses with a body larger than this are rejected (non streaming)
+    /// or cut off with an error event (streaming)
+    pub max_response_body_bytes: Option<usize>,
+    /// Compress llm responses with gzip or brotli when the client's Accept-Encoding allows
+    /// it (default false). Compressed upstream responses are always decoded.
+    pub compress_responses: Option<bool>,
+    /// What to do when Arch-Function answers without calling a prompt target, unless
+    /// the prompt target sets its own policy
+    pub on_direct_answer: Option<DirectAnswerPolicy>,
+    /// Upper bound on tool calls of one Arch-Function response dispatched concurrently
+    pub max_parallel_tool_calls: Option<usize>,
+    /// How many times Arch-Function is asked to correct tool arguments that fail validation
+    pub tool_params_validation_retries: Option<u32>,
+    /// Ask the user for missing required parameters instead of failing the tool call (default true)
+    pub clarify_missing_params: Option<bool>,
+    /// Gap between two chunks of a streaming llm response after which the stream counts as stalled
+    pub stream_stall_threshold_ms: Option<u64>,
+    /// Lower max_tokens of requests that wouldn't fit in the model's context window next
+    /// to the prompt, instead of letting the provider reject them
+    pub adjust_max_tokens_to_context_window: Option<bool>,
+    /// Repair and retry of upstream responses that don't parse
+    pub malformed_responses: Option<MalformedResponsePolicy>,
+    /// SSE comments sent to streaming clients while the upstream is quiet
+    pub stream_keep_alive: Option<StreamKeepAlive>,
+    /// Hold llm requests a provider answers with 429 and a Retry-After and send them again
+    pub rate_limit_retry: Option<RateLimitRetryPolicy>,
+    /// Priority of llm requests in the queues of providers with a concurrency limit
+    pub request_priority: Option<RequestPriorityConfig>,
+    /// Record the raw upstream and the translated client events of sampled streaming
+    /// requests, for debugging stream translations
+    pub sse_tap: Option<SseTapConfig>,
+    /// How enum values providers add, e.g. a new finish reason, are parsed (default lenient)
+    pub response_parsing: Option<ParseMode>,
+    /// Send an HMAC of the end user of llm requests upstream instead of the raw identifier
+    pub user_hashing: Option<UserHashingConfig>,
+    /// Expose the provider, model and policy decisions behind llm responses
+    pub provenance: Option<ProvenanceConfig>,
+    /// How long in-flight requests, streams included, get to complete after SIGTERM before
+    /// their connections are closed (default 25s)
+    pub shutdown_drain_timeout_ms: Option<u64>,
+    /// Directory the in-memory semantic cache and agent sessions are written to on shutdown
+    /// and restored from on start, they're lost on restart without it
+    pub shutdown_snapshot_dir: Option<String>,
+    /// Admin endpoint that sends a prompt to several models and compares their answers
+    pub evaluation: Option<EvaluationConfig>,
+    /// Bearer token required by the debug and admin endpoints: the sse tap, the
+    /// conversation archive, evaluations, agent approvals and agent sessions
+    pub admin_token: Option<String>,
+    /// Request header carrying the tenant of a request, read by the usage export, request
+    /// priorities, data residency and the semantic cache (default `x-arch-tenant-id`)
+    pub tenant_header: Option<String>,
+    /// Send llm requests the gateway doesn't need to change without parsing them into the
+    /// api types (default true)
+    pub request_fast_path: Option<bool>,
+    /// Stream large request bodies upstream as they arrive when the gateway doesn't need
+    /// to change them, instead of buffering them whole
+    pub request_body_streaming: Option<RequestBodyStreaming>,
+    /// Resolve the `mcp_servers` of Messages requests in the gateway. Requests naming MCP
+    /// servers are rejected unless this is set.
+    pub mcp_connector: Option<McpConnectorConfig>,
+}
+
+/// MCP servers the gateway may connect to on behalf of a request's `mcp_servers`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct McpConnectorConfig {
+    /// Hosts of the allowed server urls, matched exactly. Servers are only reached over https.
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Request bodies forwarded upstream while the client is still sending them
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RequestBodyStreaming {
+    /// Bodies are buffered until this many bytes have arrived, smaller bodies are always
+    /// buffered whole (default 1048576)
+    pub min_body_bytes: Option<usize>,
+}
+
+/// Evaluation harness served on `/v1/debug/evaluate` to callers with the admin token of
+/// the overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvaluationConfig {
+    /// Model scoring the answers when a request asks for a judge without naming one
+    pub judge_model: Option<String>,
+    /// Deadline of each model call, defaults to 60 seconds
+    pub timeout_ms: Option<u64>,
+    /// Models one evaluation may compare, defaults to 10
+    pub max_models: Option<usize>,
+}
+
+/// Provenance of llm responses, sent as `x-arch-served-*` and `x-arch-policy-decisions`
+/// response headers
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProvenanceConfig {
+    /// Also add an `arch_provenance` field to non streaming json responses (default false)
+    pub body_field: Option<bool>,
+}
+
+/// The `user` of OpenAI requests and `metadata.user_id` of Anthropic requests are replaced
+/// by their HMAC-SHA256 before the request is sent upstream. Rate limits still see the
+/// original value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserHashingConfig {
+    /// Key of the HMAC, the same user hashes to the same value as long as it is kept
+    pub secret: String,
+}
+
+/// Debug tap of streaming llm responses, served on `/v1/debug/sse_tap` to callers with
+/// the admin token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SseTapConfig {
+    /// Percentage of the streaming requests that are recorded
+    pub percentage: f64,
+    /// Recent requests kept, the oldest is dropped first (default 20)
+    pub max_requests: Option<usize>,
+    /// Bytes recorded per request, upstream and client side together (default 262144)
+    pub max_bytes_per_request: Option<usize>,
+    /// Directory complete recordings are written to as hermesllm replay fixtures
+    pub fixture_dir: Option<String>,
+}
+
+/// Archive of llm conversations, the request with the completion the client received,
+/// served by request id on `/v1/debug/conversations` to callers with the admin token
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConversationArchiveConfig {
+    /// Directory conversations are written to as `<request_id>.json`, kept in memory
+    /// when unset
+    pub directory: Option<String>,
+    /// Percentage of the llm requests that are archived (default 100)
+    pub percentage: Option<f64>,
+    /// How long conversations are kept (default 7 days)
+    pub retention_seconds: Option<u64>,
+    /// Conversations kept in memory, the oldest is dropped first (default 1000)
+    pub max_entries: Option<usize>,
+    /// Regexes of text replaced with `[REDACTED]` before a conversation is stored
+    pub redact: Option<Vec<String>>,
+}
+
+/// Retries of llm requests rate limited by the provider, after the delay of its
+/// Retry-After header
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RateLimitRetryPolicy {
+    /// Longest a request is held across its retries, a Retry-After beyond it is returned
+    /// to the client (default 30000)
+    pub max_wait_ms: Option<u64>,
+    /// How many times a request is sent again (default 2)
+    pub max_retries: Option<u32>,
+    /// Retries sent to one provider at the same time, held requests queue for a slot
+    /// once their delay is over (default 4)
+    pub max_concurrent_retries: Option<usize>,
+}
+
+/// Keep-alives of streaming responses, sent while waiting for the first token, between
+/// tokens and while agent filters run
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamKeepAlive {
+    /// Quiet time after which a keep-alive is sent (default 15000)
+    pub interval_ms: Option<u64>,
+    /// Quiet time after which the stream is ended (default no limit)
+    pub max_quiet_ms: Option<u64>,
+}
+
+/// Handling of non streaming upstream responses that are truncated, contain invalid
+/// UTF-8 or miss required fields
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MalformedResponsePolicy {
+    /// Complete truncated json, replace invalid UTF-8 and fill in missing required
+    /// fields (default true)
+    pub repair: Option<bool>,
+    /// How many times the request is sent again when the response can't be repaired
+    /// (default 0)
+    pub max_retries: Option<u32>,
+}
+
+/// Handling of an Arch-Function response that answers the prompt directly instead of
+/// returning tool calls
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum DirectAnswerPolicy {
+    /// Return the model's answer to the client
+    #[default]
+    #[serde(rename = "pass_through")]
+    PassThrough,
+    /// Ask Arch-Function once more, instructing it to call one of the prompt targets
+    #[serde(rename = "reprompt")]
+    Reprompt,
+    /// Forward the request to the default prompt target
+    #[serde(rename = "default_target")]
+    DefaultTarget,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Tracing {
     pub sampling_rate: Option<f64>,
     pub trace_arch_internal: Option<bool>,
+    /// How much of prompts and completions ends up in llm spans and logs
+    pub capture_content: Option<ContentCapture>,
+    /// Generations taken from the llm spans, exported to an llm observability backend
+    pub llm_observability: Option<LlmObservabilityConfig>,
+}
+
+/// Export of llm generations (model, usage, latency and the captured prompt and
+/// completion) to an llm observability backend, next to the OTEL export of the spans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmObservabilityConfig {
+    pub exporter: LlmObservabilityExporterConfig,
+    /// Share of the generations that are exported, 0.0 to 1.0 (default 1.0)
+    pub sampling_rate: Option<f64>,
+    /// Defaults to 5000
+    pub flush_interval_ms: Option<u64>,
+    /// Generations sent in one request, defaults to 100
+    pub max_batch_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LlmObservabilityExporterConfig {
+    /// Langfuse ingestion api
+    Langfuse {
+        /// Defaults to https://cloud.langfuse.com
+        host: Option<String>,
+        public_key: String,
+        secret_key: String,
+    },
+    /// OTLP/HTTP json endpoint that reads the GenAI semantic conventions, such as
+    /// OpenLLMetry compatible backends
+    Otlp {
+        endpoint: String,
+        headers: Option<HashMap<String, String>>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ContentCapture {
+    pub mode: Option<ContentCaptureMode>,
+    /// Share of llm requests whose content is captured, 0.0 to 1.0 (default 1.0)
+    pub sampling_rate: Option<f64>,
+    /// Captured prompts and completions are truncated to this many bytes
+    pub max_content_bytes: Option<usize>,
+    /// Regular expressions whose matches are replaced with `[REDACTED]` before export
+    pub redact: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum ContentCaptureMode {
+    /// Nothing taken from the messages is exported
+    #[serde(rename = "off")]
+    Off,
+    /// Only a short preview of the last user message
+    #[default]
+    #[serde(rename = "metadata")]
+    Metadata,
+    /// The preview, the prompt messages and the completion
+    #[serde(rename = "full")]
+    Full,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
@@ -108,7 +901,10 @@ pub struct ErrorTargetDetail {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PromptGuards {
+    #[serde(default)]
     pub input_guards: HashMap<GuardType, GuardOptions>,
+    /// External guard services checking prompts or model responses
+    pub guards: Option<Vec<GuardConfig>>,
 }
 
 impl PromptGuards {
@@ -122,6 +918,55 @@ impl PromptGuards {
             .as_str()
             .into()
     }
+
+    pub fn guards_for(&self, stage: GuardStage) -> Vec<&GuardConfig> {
+        self.guards
+            .iter()
+            .flatten()
+            .filter(|guard| guard.stage == stage)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardStage {
+    /// Checks the user prompt before it is processed
+    Input,
+    /// Checks the model response before it is returned (non streaming responses)
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardTransport {
+    /// POST of a `GuardRequest`, answered with a `GuardResponse`
+    #[default]
+    Http,
+    /// MCP `tools/call` of `tool`, returning a `GuardResponse`
+    Mcp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardConfig {
+    pub name: String,
+    pub stage: GuardStage,
+    pub transport: Option<GuardTransport>,
+    pub endpoint: EndpointDetails,
+    /// MCP tool to call, defaults to the guard name
+    pub tool: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub on_exception: Option<OnExceptionDetails>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardAction {
+    /// Reject the prompt, or replace the response, with the on_exception message
+    #[default]
+    Block,
+    /// Only log and count the violation
+    Log,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -140,6 +985,9 @@ pub struct OnExceptionDetails {
     pub forward_to_error_target: Option<bool>,
     pub error_handler: Option<String>,
     pub message: Option<String>,
+    pub action: Option<GuardAction>,
+    /// Let traffic through when the guard service fails or is unreachable
+    pub fail_open: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -227,6 +1075,12 @@ pub enum LlmProviderType {
     Qwen,
     #[serde(rename = "amazon_bedrock")]
     AmazonBedrock,
+    #[serde(rename = "vertex_ai")]
+    VertexAI,
+    #[serde(rename = "chatgpt")]
+    ChatGPT,
+    #[serde(rename = "custom")]
+    Custom,
 }
 
 impl Display for LlmProviderType {
@@ -247,6 +1101,9 @@ impl Display for LlmProviderType {
             LlmProviderType::Zhipu => write!(f, "zhipu"),
             LlmProviderType::Qwen => write!(f, "qwen"),
             LlmProviderType::AmazonBedrock => write!(f, "amazon_bedrock"),
+            LlmProviderType::VertexAI => write!(f, "vertex_ai"),
+            LlmProviderType::ChatGPT => write!(f, "chatgpt"),
+            LlmProviderType::Custom => write!(f, "custom"),
         }
     }
 }
@@ -307,6 +1164,10 @@ impl serde::Serialize for OrchestrationPreference {
     }
 }
 
+/// Access tokens of the service account attached to the GCE, GKE or Cloud Run instance
+pub const GCP_METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 //TODO: use enum for model, but if there is a new model, we need to update the code
 pub struct LlmProvider {
@@ -322,9 +1183,242 @@ pub struct LlmProvider {
     pub usage: Option<String>,
     pub routing_preferences: Option<Vec<RoutingPreference>>,
     pub cluster_name: Option<String>,
+    /// Scheme of `endpoint`, set by the cli from `base_url`
+    pub protocol: Option<String>,
     pub base_url_path_prefix: Option<String>,
+    /// Upstream path template for chat completions, e.g. `/api/{model}/chat`.
+    /// `{model}` is replaced with the resolved model id.
+    pub chat_path: Option<String>,
+    /// Header used to carry the access key. Defaults to the API specific header
+    /// (`Authorization: Bearer` or `x-api-key`). Any other header receives the raw key.
+    pub auth_header_name: Option<String>,
+    /// Static headers added to every upstream request for this provider
+    pub static_headers: Option<HashMap<String, String>>,
+    /// Prefix stripped from the model id before it is sent upstream
+    pub model_prefix_strip: Option<String>,
+    /// Rules for forwarding, adding and removing upstream request headers
+    pub headers: Option<HeaderRules>,
+    /// OpenAI organization id, sent upstream as `OpenAI-Organization`
+    pub organization: Option<String>,
+    /// OpenAI project id, sent upstream as `OpenAI-Project`
+    pub project: Option<String>,
+    /// Mistral `safe_prompt` default, applied when the request doesn't set it
+    pub safe_prompt: Option<bool>,
+    /// Deadline for a whole request to this provider, including streamed responses
+    pub timeout_ms: Option<u64>,
+    /// Price of the model, used for the cost in usage records
+    pub pricing: Option<ModelPricing>,
+    /// Context window of the model in tokens, defaults to the known context window of
+    /// well known models
+    pub context_window: Option<u64>,
+    /// Text added to or replacing the system prompt of requests to this provider
+    pub system_prompt_policy: Option<SystemPromptPolicy>,
+    /// Requests sent to this provider at the same time, the rest wait in a priority queue
+    pub concurrency: Option<ProviderConcurrency>,
+    /// API used to talk to providers that offer both their own API and an OpenAI
+    /// compatible one, currently Gemini (default `openai_compatible`)
+    pub api_interface: Option<ApiInterface>,
+    /// Endpoint minting OAuth2 access tokens for providers authenticated with a service
+    /// account, e.g. the GCP metadata server or a token sidecar. Defaults to the GCP
+    /// metadata server for Vertex AI providers without an `access_key`.
+    pub token_url: Option<String>,
+    /// Request body fields removed before the request is sent upstream, as dotted paths
+    /// into the body the provider receives, e.g. `user` or `metadata.session_id`, for
+    /// fields the provider rejects
+    pub strip_fields: Option<Vec<String>>,
+    /// TLS of the connection to a self-hosted upstream, rendered into its Envoy cluster
+    pub tls: Option<UpstreamTlsConfig>,
+    /// Forward proxy of this provider's traffic, overrides the global `egress_proxy`
+    pub proxy_url: Option<String>,
+    /// Where the provider keeps and processes data, e.g. `eu`, matched by data residency
+    /// policies
+    pub residency: Option<String>,
+    /// ChatGPT account the OAuth token belongs to, sent to the Codex backend as
+    /// `chatgpt-account-id`. Defaults to the account in the claims of the token.
+    pub account_id: Option<String>,
+    /// Failures injected into requests to this provider, for testing
+    pub fault_injection: Option<FaultInjectionConfig>,
+    /// Upstream connections kept open between requests, rendered into the provider's
+    /// Envoy cluster
+    pub connection_pool: Option<ConnectionPoolConfig>,
+}
+
+/// Connections to a provider kept open so requests don't pay for TCP and TLS setup
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConnectionPoolConfig {
+    /// Connections brightstaff opens at startup and keeps warm by reusing them
+    pub warm_connections: Option<u32>,
+    /// How long an idle upstream connection is kept open, defaults to Envoy's one hour
+    pub idle_timeout_ms: Option<u64>,
+    /// Idle seconds before TCP keepalive probes are sent on an upstream connection
+    pub tcp_keepalive_s: Option<u32>,
 }
 
+/// Failures injected into llm requests to a provider to test how retries, fallbacks and
+/// clients cope with them. Only applied when brightstaff runs with
+/// `FAULT_INJECTION_ENABLED=true`, rates are shares of the requests from 0.0 to 1.0.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct FaultInjectionConfig {
+    /// Answered with a 429 and a Retry-After of a second instead of being sent
+    pub rate_limit_rate: Option<f64>,
+    /// Answered with a 500 instead of being sent
+    pub server_error_rate: Option<f64>,
+    /// Latency added before every request is sent
+    pub added_latency_ms: Option<u64>,
+    /// Streams cut off after `truncate_after_chunks` chunks
+    pub truncated_stream_rate: Option<f64>,
+    /// Defaults to 3
+    pub truncate_after_chunks: Option<usize>,
+    /// Successful responses whose json is cut in half, or streams with a broken event
+    pub malformed_json_rate: Option<f64>,
+}
+
+/// Client certificate, trusted CA and expected identity of a provider's upstream. Paths
+/// are files inside the gateway container.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UpstreamTlsConfig {
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub ca_cert: Option<String>,
+    /// Server name sent in the TLS handshake, defaults to the host of `base_url`
+    pub sni: Option<String>,
+    /// URI SANs the upstream certificate must have one of, e.g. SPIFFE ids
+    pub subject_alt_names: Option<Vec<String>>,
+}
+
+/// Interface of a provider that serves both a native and an OpenAI compatible API
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiInterface {
+    /// The provider's own API, e.g. Gemini `generateContent` with `x-goog-api-key`
+    Native,
+    /// The OpenAI compatible endpoint with a Bearer token
+    #[default]
+    OpenaiCompatible,
+}
+
+/// Concurrency limit of a provider. Requests over the limit are queued by priority and
+/// shed with 429 when the queue is full or they waited too long.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProviderConcurrency {
+    pub max_concurrent_requests: usize,
+    /// Requests waiting for a slot, a full queue sheds its lowest priority request
+    /// (default `max_concurrent_requests`)
+    pub max_queue_size: Option<usize>,
+    /// Longest a request waits for a slot (default 30000)
+    pub max_queue_wait_ms: Option<u64>,
+}
+
+/// Priority of a request in the queues of providers with a concurrency limit
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl RequestPriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestPriority::Low => "low",
+            RequestPriority::Normal => "normal",
+            RequestPriority::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for RequestPriority {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(RequestPriority::Low),
+            "normal" => Ok(RequestPriority::Normal),
+            "high" => Ok(RequestPriority::High),
+            other => Err(format!("unknown request priority: {}", other)),
+        }
+    }
+}
+
+/// Where the priority of a request comes from
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RequestPriorityConfig {
+    /// Request header carrying `high`, `normal` or `low`, defaults to `x-arch-priority`
+    pub header: Option<String>,
+    /// Priority of the requests of each tenant, used when the request has no priority header
+    pub tenants: Option<HashMap<String, RequestPriority>>,
+    /// Priority of all other requests (default normal)
+    pub default: Option<RequestPriority>,
+}
+
+/// How the gateway changes the system prompt of requests, e.g. to add a compliance banner
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SystemPromptPolicy {
+    pub mode: SystemPromptMode,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ModelPricing {
+    /// USD per million input tokens
+    pub input_per_million_tokens: f64,
+    /// USD per million output tokens
+    pub output_per_million_tokens: f64,
+}
+
+impl ModelPricing {
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_million_tokens
+            + output_tokens as f64 * self.output_per_million_tokens)
+            / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct HeaderRules {
+    /// Allowlist of client headers forwarded upstream. When set, any other client
+    /// header (except pseudo, gateway and content negotiation headers) is dropped.
+    pub passthrough: Option<Vec<String>>,
+    /// Headers set on every upstream request, overriding client supplied values
+    pub add: Option<HashMap<String, String>>,
+    /// Headers removed from every upstream request
+    pub remove: Option<Vec<String>>,
+}
+
+impl HeaderRules {
+    /// Returns true if the client header should be forwarded under the passthrough allowlist
+    pub fn allows_passthrough(&self, name: &str) -> bool {
+        let allowlist = match self.passthrough.as_ref() {
+            Some(allowlist) => allowlist,
+            None => return true,
+        };
+        let name = name.to_ascii_lowercase();
+        name.starts_with(':')
+            || name.starts_with("x-arch-")
+            || name.starts_with("x-envoy-")
+            || ALWAYS_FORWARDED_HEADERS.contains(&name.as_str())
+            || allowlist
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(&name))
+    }
+}
+
+/// Client headers that are always forwarded, even when a passthrough allowlist is configured
+const ALWAYS_FORWARDED_HEADERS: &[&str] = &[
+    "accept",
+    "content-type",
+    "content-length",
+    "host",
+    REQUEST_ID_HEADER,
+    ARCH_REQUEST_ID_HEADER,
+    TRACE_PARENT_HEADER,
+    "tracestate",
+];
+
 pub trait IntoModels {
     fn into_models(self) -> Models;
 }
@@ -363,7 +1457,30 @@ impl Default for LlmProvider {
             usage: None,
             routing_preferences: None,
             cluster_name: None,
+            protocol: None,
             base_url_path_prefix: None,
+            chat_path: None,
+            auth_header_name: None,
+            static_headers: None,
+            model_prefix_strip: None,
+            headers: None,
+            organization: None,
+            project: None,
+            safe_prompt: None,
+            timeout_ms: None,
+            pricing: None,
+            context_window: None,
+            system_prompt_policy: None,
+            concurrency: None,
+            api_interface: None,
+            token_url: None,
+            strip_fields: None,
+            tls: None,
+            proxy_url: None,
+            residency: None,
+            account_id: None,
+            fault_injection: None,
+            connection_pool: None,
         }
     }
 }
@@ -378,7 +1495,57 @@ impl LlmProvider {
     /// Get the ProviderId for this LlmProvider
     /// Used with the new function-based hermesllm API
     pub fn to_provider_id(&self) -> hermesllm::ProviderId {
-        self.provider_interface.to_provider_id()
+        match (
+            &self.provider_interface,
+            self.api_interface.unwrap_or_default(),
+        ) {
+            (LlmProviderType::Gemini, ApiInterface::Native) => hermesllm::ProviderId::GeminiNative,
+            // Claude on Vertex AI speaks the Messages API rather than generateContent
+            (LlmProviderType::VertexAI, _)
+                if self.model.as_deref().is_some_and(is_claude_model) =>
+            {
+                hermesllm::ProviderId::VertexAnthropic
+            }
+            _ => self.provider_interface.to_provider_id(),
+        }
+    }
+
+    /// Endpoint to get access tokens from, None for providers authenticated with their
+    /// `access_key`
+    pub fn access_token_url(&self) -> Option<&str> {
+        match (&self.provider_interface, self.token_url.as_deref()) {
+            (_, Some(token_url)) => Some(token_url),
+            (LlmProviderType::VertexAI, None) if self.access_key.is_none() => {
+                Some(GCP_METADATA_TOKEN_URL)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the model id that should be sent upstream, with `model_prefix_strip` removed.
+    /// Gemini model ids also lose the `models/` resource prefix, and Claude model ids are
+    /// translated to the form of Anthropic, Bedrock or Vertex AI.
+    pub fn upstream_model_id<'a>(&self, model: &'a str) -> Cow<'a, str> {
+        let model = match self.model_prefix_strip.as_deref() {
+            Some(prefix) if !prefix.is_empty() => model.strip_prefix(prefix).unwrap_or(model),
+            _ => model,
+        };
+        match self.provider_interface {
+            LlmProviderType::Gemini => Cow::Borrowed(gemini_model_name(model)),
+            LlmProviderType::Anthropic
+            | LlmProviderType::AmazonBedrock
+            | LlmProviderType::VertexAI => claude_model_id(self.to_provider_id(), model),
+            _ => Cow::Borrowed(model),
+        }
+    }
+
+    /// Deadline of a request, the shorter of the provider timeout and the one asked
+    /// for by the client
+    pub fn request_timeout_ms(&self, requested_ms: Option<u64>) -> Option<u64> {
+        match (self.timeout_ms, requested_ms.filter(|ms| *ms > 0)) {
+            (Some(timeout_ms), Some(requested_ms)) => Some(timeout_ms.min(requested_ms)),
+            (timeout_ms, requested_ms) => timeout_ms.or(requested_ms),
+        }
     }
 }
 
@@ -436,7 +1603,31 @@ pub struct PromptTarget {
     pub endpoint: Option<EndpointDetails>,
     pub parameters: Option<Vec<Parameter>>,
     pub system_prompt: Option<String>,
+    /// Name of a prompt template used in place of `system_prompt`
+    pub prompt_template: Option<String>,
     pub auto_llm_dispatch_on_response: Option<bool>,
+    pub tool_cache: Option<ToolCacheConfig>,
+    pub on_direct_answer: Option<DirectAnswerPolicy>,
+    pub llm: Option<PromptTargetLlm>,
+}
+
+/// LLM used to summarize the response of a prompt target, in place of the one
+/// requested by the client. Unset fields keep the values of the client request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PromptTargetLlm {
+    /// Name of the llm_provider the summarization request is routed to
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// Caches successful endpoint responses of a prompt target so identical calls
+/// within the ttl are answered by the gateway.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCacheConfig {
+    pub ttl_seconds: u64,
+    pub max_entries: Option<usize>,
 }
 
 // convert PromptTarget to ChatCompletionTool
@@ -508,6 +1699,357 @@ mod test {
         assert_eq!(*mode, super::GatewayMode::Prompt);
     }
 
+    #[test]
+    fn test_custom_provider_model_prefix_strip() {
+        let provider_yaml = r#"
+name: local-llm
+provider_interface: custom
+model: litellm/llama-3-8b
+chat_path: /api/{model}/chat
+auth_header_name: api-key
+static_headers:
+  x-tenant-id: acme
+model_prefix_strip: litellm/
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        assert_eq!(provider.provider_interface, super::LlmProviderType::Custom);
+        assert_eq!(provider.to_provider_id(), hermesllm::ProviderId::Custom);
+        assert_eq!(
+            provider.upstream_model_id("litellm/llama-3-8b"),
+            "llama-3-8b"
+        );
+        assert_eq!(provider.upstream_model_id("llama-3-8b"), "llama-3-8b");
+        assert_eq!(
+            provider
+                .static_headers
+                .as_ref()
+                .and_then(|h| h.get("x-tenant-id"))
+                .map(String::as_str),
+            Some("acme")
+        );
+    }
+
+    #[test]
+    fn test_gemini_api_interface() {
+        let provider_yaml = r#"
+name: gemini
+provider_interface: gemini
+model: models/gemini-2.5-flash
+api_interface: native
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        assert_eq!(provider.api_interface, Some(super::ApiInterface::Native));
+        assert_eq!(
+            provider.to_provider_id(),
+            hermesllm::ProviderId::GeminiNative
+        );
+        assert_eq!(
+            provider.upstream_model_id("models/gemini-2.5-flash"),
+            "gemini-2.5-flash"
+        );
+
+        let compatible = super::LlmProvider {
+            api_interface: None,
+            ..provider
+        };
+        assert_eq!(compatible.to_provider_id(), hermesllm::ProviderId::Gemini);
+    }
+
+    #[test]
+    fn test_vertex_ai_access_token_url() {
+        let provider_yaml = r#"
+name: vertex
+provider_interface: vertex_ai
+model: gemini-2.5-pro
+base_url_path_prefix: /v1/projects/acme/locations/us-central1
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        assert_eq!(provider.to_provider_id(), hermesllm::ProviderId::VertexAI);
+        assert_eq!(
+            provider.access_token_url(),
+            Some(super::GCP_METADATA_TOKEN_URL)
+        );
+
+        let sidecar = super::LlmProvider {
+            token_url: Some("http://token-sidecar:8080/token".to_string()),
+            ..provider.clone()
+        };
+        assert_eq!(
+            sidecar.access_token_url(),
+            Some("http://token-sidecar:8080/token")
+        );
+
+        let static_token = super::LlmProvider {
+            access_key: Some("ya29.token".to_string()),
+            ..provider
+        };
+        assert_eq!(static_token.access_token_url(), None);
+    }
+
+    #[test]
+    fn test_claude_served_by_anthropic_bedrock_and_vertex() {
+        let provider = |provider_interface: &str| -> super::LlmProvider {
+            serde_yaml::from_str(&format!(
+                "name: claude\nprovider_interface: {}\nmodel: claude-sonnet-4\n",
+                provider_interface
+            ))
+            .unwrap()
+        };
+
+        let anthropic = provider("anthropic");
+        assert_eq!(
+            anthropic.upstream_model_id("claude-sonnet-4"),
+            "claude-sonnet-4-20250514"
+        );
+
+        let bedrock = provider("amazon_bedrock");
+        assert_eq!(
+            bedrock.upstream_model_id("claude-sonnet-4"),
+            "anthropic.claude-sonnet-4-20250514-v1:0"
+        );
+
+        let vertex = provider("vertex_ai");
+        assert_eq!(
+            vertex.to_provider_id(),
+            hermesllm::ProviderId::VertexAnthropic
+        );
+        assert_eq!(
+            vertex.upstream_model_id("claude-sonnet-4"),
+            "claude-sonnet-4@20250514"
+        );
+    }
+
+    #[test]
+    fn test_chatgpt_provider() {
+        let provider_yaml = r#"
+name: chatgpt/gpt-5-codex
+provider_interface: chatgpt
+model: gpt-5-codex
+access_key: $CHATGPT_ACCESS_TOKEN
+account_id: acct_123
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        assert_eq!(provider.to_provider_id(), hermesllm::ProviderId::ChatGPT);
+        assert_eq!(provider.account_id.as_deref(), Some("acct_123"));
+        assert_eq!(provider.access_token_url(), None);
+    }
+
+    #[test]
+    fn test_openai_organization_and_project() {
+        let provider_yaml = r#"
+name: openai-team-a
+provider_interface: openai
+model: gpt-4o
+organization: org-123
+project: proj-team-a
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        assert_eq!(provider.organization.as_deref(), Some("org-123"));
+        assert_eq!(provider.project.as_deref(), Some("proj-team-a"));
+    }
+
+    #[test]
+    fn test_prompt_target_direct_answer_policy() {
+        let prompt_target_yaml = r#"
+name: get_weather
+description: Get the current weather for a location
+on_direct_answer: reprompt
+tool_cache:
+  ttl_seconds: 60
+"#;
+        let prompt_target: super::PromptTarget = serde_yaml::from_str(prompt_target_yaml).unwrap();
+        assert_eq!(
+            prompt_target.on_direct_answer,
+            Some(super::DirectAnswerPolicy::Reprompt)
+        );
+        assert_eq!(prompt_target.tool_cache.unwrap().ttl_seconds, 60);
+
+        let overrides: super::Overrides =
+            serde_yaml::from_str("on_direct_answer: default_target").unwrap();
+        assert_eq!(
+            overrides.on_direct_answer,
+            Some(super::DirectAnswerPolicy::DefaultTarget)
+        );
+    }
+
+    #[test]
+    fn test_llm_provider_request_timeout() {
+        let mut provider = super::LlmProvider::default();
+        assert_eq!(provider.request_timeout_ms(None), None);
+        assert_eq!(provider.request_timeout_ms(Some(5000)), Some(5000));
+
+        provider.timeout_ms = Some(30000);
+        assert_eq!(provider.request_timeout_ms(None), Some(30000));
+        assert_eq!(provider.request_timeout_ms(Some(5000)), Some(5000));
+        assert_eq!(provider.request_timeout_ms(Some(60000)), Some(30000));
+        assert_eq!(provider.request_timeout_ms(Some(0)), Some(30000));
+    }
+
+    #[test]
+    fn test_usage_export_config() {
+        let usage_export_yaml = r#"
+sink:
+  type: s3
+  endpoint: http://minio:9000
+  bucket: usage
+  prefix: archgw/
+  access_key_id: minio
+  secret_access_key: minio123
+flush_interval_ms: 30000
+"#;
+        let usage_export: super::UsageExportConfig =
+            serde_yaml::from_str(usage_export_yaml).unwrap();
+        assert_eq!(usage_export.flush_interval_ms, Some(30000));
+        match usage_export.sink {
+            super::UsageSinkConfig::S3 { bucket, region, .. } => {
+                assert_eq!(bucket, "usage");
+                assert_eq!(region, None);
+            }
+            sink => panic!("unexpected sink {:?}", sink),
+        }
+
+        let pricing = super::ModelPricing {
+            input_per_million_tokens: 2.5,
+            output_per_million_tokens: 10.0,
+        };
+        assert!((pricing.cost(1000, 500) - 0.0075).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_function_calling_config() {
+        let function_calling_yaml = r#"
+endpoint: vllm
+model: katanemo/Arch-Function-3B
+api: openai
+timeout_ms: 10000
+"#;
+        let function_calling: super::FunctionCallingConfig =
+            serde_yaml::from_str(function_calling_yaml).unwrap();
+        assert_eq!(function_calling.endpoint.as_deref(), Some("vllm"));
+        assert_eq!(
+            function_calling.api,
+            Some(super::FunctionCallingApi::OpenAi)
+        );
+        assert_eq!(function_calling.path, None);
+    }
+
+    #[test]
+    fn test_prompt_target_llm_override() {
+        let prompt_target_yaml = r#"
+name: summarize_ticket
+description: Summarize a support ticket
+llm:
+  provider: gpt-4o-mini
+  temperature: 0.2
+  max_tokens: 256
+"#;
+        let prompt_target: super::PromptTarget = serde_yaml::from_str(prompt_target_yaml).unwrap();
+        assert_eq!(
+            prompt_target.llm,
+            Some(super::PromptTargetLlm {
+                provider: Some("gpt-4o-mini".to_string()),
+                model: None,
+                temperature: Some(0.2),
+                max_tokens: Some(256),
+            })
+        );
+    }
+
+    #[test]
+    fn test_header_rules() {
+        let provider_yaml = r#"
+name: anthropic
+provider_interface: anthropic
+model: claude-sonnet-4
+headers:
+  passthrough:
+    - anthropic-beta
+  add:
+    x-tenant-id: acme
+  remove:
+    - user-agent
+"#;
+        let provider: super::LlmProvider = serde_yaml::from_str(provider_yaml).unwrap();
+        let rules = provider.headers.unwrap();
+        assert!(rules.allows_passthrough("Anthropic-Beta"));
+        assert!(rules.allows_passthrough(":path"));
+        assert!(rules.allows_passthrough("x-arch-llm-provider-hint"));
+        assert!(rules.allows_passthrough("x-request-id"));
+        assert!(!rules.allows_passthrough("cookie"));
+        assert_eq!(
+            rules
+                .add
+                .as_ref()
+                .and_then(|h| h.get("x-tenant-id"))
+                .map(String::as_str),
+            Some("acme")
+        );
+        assert_eq!(rules.remove, Some(vec!["user-agent".to_string()]));
+
+        assert!(super::HeaderRules::default().allows_passthrough("cookie"));
+    }
+
+    #[test]
+    fn test_listener_allowed_apis() {
+        use hermesllm::apis::anthropic::AnthropicApi;
+        use hermesllm::apis::openai::OpenAIApi;
+        use hermesllm::clients::endpoints::SupportedAPIsFromClient;
+
+        let listener_yaml = r#"
+name: anthropic_clients
+port: 12010
+allowed_apis:
+  - anthropic
+default_provider: claude-sonnet
+passthrough_auth: true
+"#;
+        let listener: super::Listener = serde_yaml::from_str(listener_yaml).unwrap();
+        assert_eq!(listener.default_provider.as_deref(), Some("claude-sonnet"));
+        assert_eq!(listener.passthrough_auth, Some(true));
+        assert!(
+            listener.allows_api(&SupportedAPIsFromClient::AnthropicMessagesAPI(
+                AnthropicApi::Messages
+            ))
+        );
+        assert!(
+            !listener.allows_api(&SupportedAPIsFromClient::OpenAIChatCompletions(
+                OpenAIApi::ChatCompletions
+            ))
+        );
+
+        let open_listener: super::Listener =
+            serde_yaml::from_str("name: egress_traffic\nport: 12000\n").unwrap();
+        assert!(
+            open_listener.allows_api(&SupportedAPIsFromClient::OpenAIResponsesAPI(
+                OpenAIApi::Responses
+            ))
+        );
+    }
+
+    #[test]
+    fn test_conditional_filter_steps() {
+        let agent_yaml = r#"
+id: support_agent
+filter_chain:
+  - query_rewriter
+  - when: 'last_user_message contains "refund"'
+    then: [order_lookup, refund_policy]
+    else: [faq_retrieval]
+"#;
+        let agent: super::AgentFilterChain = serde_yaml::from_str(agent_yaml).unwrap();
+        assert_eq!(
+            agent.filter_chain.unwrap(),
+            vec![
+                super::FilterStep::from("query_rewriter"),
+                super::FilterStep::Conditional(super::ConditionalFilters {
+                    when: r#"last_user_message contains "refund""#.to_string(),
+                    then: vec!["order_lookup".to_string(), "refund_policy".to_string()],
+                    otherwise: Some(vec!["faq_retrieval".to_string()]),
+                }),
+            ]
+        );
+    }
+
     #[test]
     fn test_tool_conversion() {
         let ref_config = fs::read_to_string(
@@ -1,4 +1,8 @@
 pub const RATELIMIT_SELECTOR_HEADER_KEY: &str = "x-arch-ratelimit-selector";
+/// Ratelimit selector key matched against the end user of the request rather than a header
+pub const USER_RATELIMIT_SELECTOR: &str = "user";
+/// Ratelimit selectors of the validated JWT claims, a JSON object of selector key to value
+pub const ARCH_RATELIMIT_CLAIMS_HEADER: &str = "x-arch-ratelimit-claims";
 pub const SYSTEM_ROLE: &str = "system";
 pub const USER_ROLE: &str = "user";
 pub const TOOL_ROLE: &str = "tool";
@@ -12,26 +16,86 @@ pub const ARCH_ROUTING_HEADER: &str = "x-arch-llm-provider";
 pub const MESSAGES_KEY: &str = "messages";
 pub const ARCH_PROVIDER_HINT_HEADER: &str = "x-arch-llm-provider-hint";
 pub const ARCH_IS_STREAMING_HEADER: &str = "x-arch-streaming-request";
+pub const ARCH_LISTENER_NAME_HEADER: &str = "x-arch-listener-name";
+pub const ARCH_AGENT_LISTENER_NAME_HEADER: &str = "x-arch-agent-listener-name";
+pub const ARCH_SESSION_ID_HEADER: &str = "x-arch-session-id";
+pub const ARCH_TENANT_ID_HEADER: &str = "x-arch-tenant-id";
+pub const ARCH_PRIORITY_HEADER: &str = "x-arch-priority";
+pub const ARCH_SSE_TAP_HEADER: &str = "x-arch-sse-tap";
+/// Prefix of the SSE comments carrying raw upstream chunks of tapped streams
+pub const SSE_TAP_UPSTREAM_PREFIX: &str = ": x-arch-upstream ";
+pub const ARCH_AGENT_ROUTING_HEADER: &str = "x-arch-agent-routing";
+pub const ARCH_TOOL_CACHE_HEADER: &str = "x-arch-tool-cache";
+pub const ARCH_REQUEST_TIMEOUT_HEADER: &str = "x-arch-request-timeout-ms";
+pub const ARCH_TOOL_CACHE_BYPASS: &str = "bypass";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
 pub const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
 pub const OPENAI_RESPONSES_API_PATH: &str = "/v1/responses";
 pub const MESSAGES_PATH: &str = "/v1/messages";
+pub const EMBEDDINGS_PATH: &str = "/v1/embeddings";
+pub const REALTIME_PATH: &str = "/v1/realtime";
+pub const AUDIO_TRANSCRIPTIONS_PATH: &str = "/v1/audio/transcriptions";
+pub const AUDIO_SPEECH_PATH: &str = "/v1/audio/speech";
 pub const HEALTHZ_PATH: &str = "/healthz";
+pub const READYZ_PATH: &str = "/readyz";
+pub const A2A_PATH: &str = "/a2a";
+pub const A2A_AGENT_CARD_PATH: &str = "/.well-known/agent.json";
+pub const AGENT_SESSIONS_PATH: &str = "/sessions";
+pub const AGENT_SESSION_SUMMARIZE_SUFFIX: &str = "/summarize";
+pub const AGENT_APPROVALS_PATH: &str = "/approvals";
 pub const X_ARCH_STATE_HEADER: &str = "x-arch-state";
 pub const X_ARCH_API_RESPONSE: &str = "x-arch-api-response-message";
 pub const X_ARCH_TOOL_CALL: &str = "x-arch-tool-call-message";
 pub const X_ARCH_FC_MODEL_RESPONSE: &str = "x-arch-fc-model-response";
 pub const ARCH_FC_MODEL_NAME: &str = "Arch-Function";
 pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const ARCH_REQUEST_ID_HEADER: &str = "x-archgw-request-id";
+pub const OPENAI_CLIENT_REQUEST_ID_HEADER: &str = "X-Client-Request-Id";
+pub const AZURE_CLIENT_REQUEST_ID_HEADER: &str = "x-ms-client-request-id";
 pub const TRACE_PARENT_HEADER: &str = "traceparent";
+pub const OPENAI_ORGANIZATION_HEADER: &str = "OpenAI-Organization";
+pub const OPENAI_PROJECT_HEADER: &str = "OpenAI-Project";
+pub const CHATGPT_ACCOUNT_ID_HEADER: &str = "chatgpt-account-id";
+pub const CHATGPT_SESSION_ID_HEADER: &str = "session_id";
 pub const ARCH_INTERNAL_CLUSTER_NAME: &str = "arch_internal";
 pub const ARCH_UPSTREAM_HOST_HEADER: &str = "x-arch-upstream";
+pub const ARCH_UPSTREAM_ERROR_HEADER: &str = "x-archgw-upstream-error";
+pub const ARCH_TRAFFIC_SPLIT_HEADER: &str = "x-arch-traffic-split";
+pub const ARCH_MAX_TOKENS_ADJUSTED_HEADER: &str = "x-arch-max-tokens-adjusted";
+pub const ARCH_MALFORMED_RESPONSE_HEADER: &str = "x-arch-malformed-response";
+pub const ARCH_SEMANTIC_CACHE_HEADER: &str = "x-arch-semantic-cache";
+pub const ARCH_SERVED_PROVIDER_HEADER: &str = "x-arch-served-provider";
+pub const ARCH_SERVED_MODEL_HEADER: &str = "x-arch-served-model";
+pub const ARCH_GATEWAY_VERSION_HEADER: &str = "x-arch-gateway-version";
+pub const ARCH_POLICY_DECISIONS_HEADER: &str = "x-arch-policy-decisions";
+/// OAuth2 access token minted by brightstaff for providers that don't take a static key
+pub const ARCH_UPSTREAM_ACCESS_TOKEN_HEADER: &str = "x-arch-upstream-access-token";
+/// Marks requests brightstaff sends to open connections to a provider, llm_gateway sends
+/// them to the provider's cluster as a HEAD of the base path
+pub const ARCH_CONNECTION_WARMUP_HEADER: &str = "x-arch-connection-warmup";
+pub const SEMANTIC_CACHE_STATS_PATH: &str = "/v1/semantic_cache/stats";
+pub const RATE_LIMIT_RETRY_STATS_PATH: &str = "/v1/rate_limit_retries/stats";
+pub const PROVIDER_QUEUE_STATS_PATH: &str = "/v1/provider_queues/stats";
+pub const SSE_TAP_PATH: &str = "/v1/debug/sse_tap";
+pub const CONVERSATION_ARCHIVE_PATH: &str = "/v1/debug/conversations";
+pub const EVALUATION_PATH: &str = "/v1/debug/evaluate";
+pub const TOOLS_PATH: &str = "/v1/tools";
 pub const ARCH_MODEL_PREFIX: &str = "Arch";
 pub const HALLUCINATION_TEMPLATE: &str =
     "It seems I'm missing some information. Could you provide the following details ";
 pub const OTEL_COLLECTOR_HTTP: &str = "opentelemetry_collector_http";
 pub const OTEL_POST_PATH: &str = "/v1/traces";
 pub const LLM_ROUTE_HEADER: &str = "x-arch-llm-route";
+pub const DEFAULT_STREAM_STALL_THRESHOLD_MS: u64 = 10000;
 pub const ENVOY_RETRY_HEADER: &str = "x-envoy-max-retries";
+pub const ENVOY_UPSTREAM_TIMEOUT_HEADER: &str = "x-envoy-upstream-rq-timeout-ms";
 pub const BRIGHT_STAFF_SERVICE_NAME: &str = "brightstaff";
 pub const PLANO_ORCHESTRATOR_MODEL_NAME: &str = "Plano-Orchestrator";
 pub const ARCH_FC_CLUSTER: &str = "arch";
+pub const RESPONSE_BODY_READ_CHUNK_BYTES: usize = 1024 * 1024; // 1 MiB
+/// Non streaming responses from this size on are scanned for their usage instead of parsed
+/// into the api types, when nothing needs them parsed
+pub const LARGE_RESPONSE_BODY_BYTES: usize = 1024 * 1024; // 1 MiB
+/// End of a large response kept to read its usage from when the rest is forwarded as it
+/// arrives
+pub const RESPONSE_USAGE_TAIL_BYTES: usize = 16 * 1024; // 16 KiB
@@ -32,6 +32,8 @@ pub enum ServerError {
     },
     #[error("jailbreak detected: {0}")]
     Jailbreak(String),
+    #[error("{guard} guard: {message}")]
+    GuardViolation { guard: String, message: String },
     #[error("{why}")]
     NoMessagesFound { why: String },
     #[error(transparent)]
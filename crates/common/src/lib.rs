@@ -1,12 +1,17 @@
 pub mod api;
+pub mod compression;
+pub mod condition;
 pub mod configuration;
 pub mod consts;
 pub mod errors;
 pub mod http;
 pub mod llm_providers;
+pub mod lru;
 pub mod path;
 pub mod pii;
+pub mod prompt_template;
 pub mod ratelimit;
+pub mod request_id;
 pub mod routing;
 pub mod stats;
 pub mod tokenizer;
@@ -0,0 +1,94 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Map holding at most `capacity` entries, the least recently used one is evicted to
+/// make room. Meant for small per worker caches, eviction scans the entries.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    capacity: usize,
+    /// Bumped on every access, entries keep the tick of their last use
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            tick: 0,
+        }
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.tick += 1;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        *last_used = self.tick;
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let lru_key = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru_key) = lru_key {
+                self.entries.remove(&lru_key);
+            }
+        }
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    /// The cached value of the key, computed and cached on a miss
+    pub fn get_or_insert_with(&mut self, key: &K, value: impl FnOnce() -> V) -> &V {
+        if self.get(key).is_none() {
+            self.insert(key.clone(), value());
+        }
+        // present, it was just looked up or inserted
+        &self.entries[key].0
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = LruCache::new(2);
+        cache.insert("gpt-4o", 1);
+        cache.insert("claude-sonnet-4", 2);
+        assert_eq!(cache.get("gpt-4o"), Some(&1));
+
+        cache.insert("gemini-2.5-pro", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("claude-sonnet-4"), None);
+        assert_eq!(cache.get("gpt-4o"), Some(&1));
+
+        let mut computed = 0;
+        assert_eq!(
+            *cache.get_or_insert_with(&"gemini-2.5-pro", || {
+                computed += 1;
+                4
+            }),
+            3
+        );
+        assert_eq!(computed, 0);
+    }
+}
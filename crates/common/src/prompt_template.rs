@@ -0,0 +1,268 @@
+//! Server side prompt templates
+//!
+//! A template is text with `{{ variable }}` placeholders that are filled from the
+//! metadata of the request. Placeholders can pipe the value through filters:
+//! `{{ team | default("everyone") | upper }}`. The filters are `default("..")`, used
+//! when the variable isn't set, and `upper`, `lower` and `trim`.
+
+use crate::configuration::PromptTemplateConfig;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    #[error("placeholder at offset {0} is not closed")]
+    Unclosed(usize),
+    #[error("placeholder at offset {0} has no variable")]
+    EmptyPlaceholder(usize),
+    #[error("invalid variable name {0:?}")]
+    InvalidVariable(String),
+    #[error("unknown filter {0:?}")]
+    UnknownFilter(String),
+    #[error("variable {0} is not set and has no default")]
+    MissingVariable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Default(String),
+    Upper,
+    Lower,
+    Trim,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable { name: String, filters: Vec<Filter> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PromptTemplate {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        let mut offset = 0;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or(TemplateError::Unclosed(offset + start))?;
+            let placeholder = &rest[start + 2..start + end];
+            segments.push(parse_placeholder(placeholder, offset + start)?);
+            offset += start + end + 2;
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(PromptTemplate { segments })
+    }
+
+    /// Names of the variables the template uses, in order of first use
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Variable { name, .. } = segment {
+                if !variables.contains(&name.as_str()) {
+                    variables.push(name);
+                }
+            }
+        }
+        variables
+    }
+
+    pub fn render(&self, variables: &HashMap<String, String>) -> Result<String, TemplateError> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable { name, filters } => {
+                    let value = filters
+                        .iter()
+                        .fold(variables.get(name).cloned(), |value, filter| {
+                            apply_filter(filter, value)
+                        })
+                        .ok_or_else(|| TemplateError::MissingVariable(name.clone()))?;
+                    rendered.push_str(&value);
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+/// Template of the configuration, parsed, with its `name@version` label
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabeledTemplate {
+    pub template: PromptTemplate,
+    pub label: String,
+}
+
+/// Parses the `prompt_templates` of the configuration, failing on the first invalid one
+pub fn parse_templates(
+    configs: &HashMap<String, PromptTemplateConfig>,
+) -> Result<HashMap<String, LabeledTemplate>, String> {
+    configs
+        .iter()
+        .map(|(name, config)| {
+            let template = PromptTemplate::parse(&config.template)
+                .map_err(|err| format!("prompt template {}: {}", name, err))?;
+            let labeled = LabeledTemplate {
+                template,
+                label: config.label(name),
+            };
+            Ok((name.clone(), labeled))
+        })
+        .collect()
+}
+
+fn apply_filter(filter: &Filter, value: Option<String>) -> Option<String> {
+    match filter {
+        Filter::Default(default) => value.or_else(|| Some(default.clone())),
+        Filter::Upper => value.map(|value| value.to_uppercase()),
+        Filter::Lower => value.map(|value| value.to_lowercase()),
+        Filter::Trim => value.map(|value| value.trim().to_string()),
+    }
+}
+
+fn parse_placeholder(placeholder: &str, offset: usize) -> Result<Segment, TemplateError> {
+    let mut parts = split_filters(placeholder).into_iter();
+    let name = parts.next().unwrap_or_default().trim();
+    if name.is_empty() {
+        return Err(TemplateError::EmptyPlaceholder(offset));
+    }
+    let valid_name = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if !valid_name {
+        return Err(TemplateError::InvalidVariable(name.to_string()));
+    }
+    let filters = parts
+        .map(|filter| parse_filter(filter.trim()))
+        .collect::<Result<_, _>>()?;
+    Ok(Segment::Variable {
+        name: name.to_string(),
+        filters,
+    })
+}
+
+/// Splits on the `|` outside of quoted filter arguments
+fn split_filters(placeholder: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in placeholder.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            (None, '|') => {
+                parts.push(&placeholder[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&placeholder[start..]);
+    parts
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, TemplateError> {
+    match filter {
+        "upper" => return Ok(Filter::Upper),
+        "lower" => return Ok(Filter::Lower),
+        "trim" => return Ok(Filter::Trim),
+        _ => {}
+    }
+    let argument = filter
+        .strip_prefix("default(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .map(str::trim)
+        .filter(|argument| argument.len() >= 2)
+        .and_then(|argument| {
+            let quote = argument.chars().next()?;
+            (matches!(quote, '"' | '\'') && argument.ends_with(quote))
+                .then(|| argument[1..argument.len() - 1].to_string())
+        });
+    argument
+        .map(Filter::Default)
+        .ok_or_else(|| TemplateError::UnknownFilter(filter.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let template =
+            PromptTemplate::parse("You help the {{ team }} team of {{org|upper}}.").unwrap();
+        assert_eq!(template.variables(), vec!["team", "org"]);
+        assert_eq!(
+            template
+                .render(&variables(&[("team", "search"), ("org", "acme")]))
+                .unwrap(),
+            "You help the search team of ACME."
+        );
+        assert_eq!(
+            template.render(&variables(&[("team", "search")])),
+            Err(TemplateError::MissingVariable("org".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_default_filter() {
+        let template =
+            PromptTemplate::parse(r#"Answer in {{ language | default("English | plain") }}."#)
+                .unwrap();
+        assert_eq!(
+            template.render(&HashMap::new()).unwrap(),
+            "Answer in English | plain."
+        );
+        assert_eq!(
+            template
+                .render(&variables(&[("language", "French")]))
+                .unwrap(),
+            "Answer in French."
+        );
+        let template = PromptTemplate::parse("{{ tier | default('free') | upper }}").unwrap();
+        assert_eq!(template.render(&HashMap::new()).unwrap(), "FREE");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            PromptTemplate::parse("Hi {{ name"),
+            Err(TemplateError::Unclosed(3))
+        );
+        assert_eq!(
+            PromptTemplate::parse("Hi {{ }}"),
+            Err(TemplateError::EmptyPlaceholder(3))
+        );
+        assert_eq!(
+            PromptTemplate::parse("{{ first name }}"),
+            Err(TemplateError::InvalidVariable("first name".to_string()))
+        );
+        assert_eq!(
+            PromptTemplate::parse("{{ name | title }}"),
+            Err(TemplateError::UnknownFilter("title".to_string()))
+        );
+        assert_eq!(
+            PromptTemplate::parse("{{ name | default(free) }}"),
+            Err(TemplateError::UnknownFilter("default(free)".to_string()))
+        );
+    }
+}
@@ -1,12 +1,22 @@
 use crate::configuration;
+use crate::lru::LruCache;
 use configuration::{Limit, Ratelimit, TimeUnit};
 use governor::{DefaultKeyedRateLimiter, InsufficientCapacity, Quota};
 use log::debug;
+use std::cell::RefCell;
 use std::fmt::Display;
 use std::num::{NonZero, NonZeroU32};
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 use std::{collections::HashMap, sync::OnceLock};
 
+/// Descriptors kept per worker, keyed by model and selector
+const MAX_CACHED_DESCRIPTORS: usize = 1024;
+
+thread_local! {
+    static DESCRIPTORS: RefCell<LruCache<(String, String, String), Option<Descriptor>>> =
+        RefCell::new(LruCache::new(MAX_CACHED_DESCRIPTORS));
+}
+
 pub type RatelimitData = RwLock<RatelimitMap>;
 
 pub fn ratelimits(ratelimits_config: Option<Vec<Ratelimit>>) -> &'static RatelimitData {
@@ -25,7 +35,15 @@ pub fn ratelimits(ratelimits_config: Option<Vec<Ratelimit>>) -> &'static Ratelim
 //   b) Has Some() value, then there will be 1 Limit keyed by the empty string.
 // It would have been nicer to use a non-keyed limit for b). However, the type system made that option a nightmare.
 pub struct RatelimitMap {
-    datastore: HashMap<String, HashMap<configuration::Header, DefaultKeyedRateLimiter<String>>>,
+    datastore:
+        HashMap<String, HashMap<configuration::Header, Arc<DefaultKeyedRateLimiter<String>>>>,
+}
+
+/// The limit a selector is checked against with the key of the selector in it
+#[derive(Clone)]
+struct Descriptor {
+    limit: Arc<DefaultKeyedRateLimiter<String>>,
+    key: String,
 }
 
 // This version of Header demands that the user passes a header value to match on.
@@ -68,7 +86,9 @@ impl RatelimitMap {
             datastore: HashMap::new(),
         };
         for ratelimit_config in ratelimits_config {
-            let limit = DefaultKeyedRateLimiter::keyed(get_quota(ratelimit_config.limit));
+            let limit = Arc::new(DefaultKeyedRateLimiter::keyed(get_quota(
+                ratelimit_config.limit,
+            )));
 
             match new_ratelimit_map.datastore.get_mut(&ratelimit_config.model) {
                 Some(limits) => match limits.get_mut(&ratelimit_config.selector) {
@@ -104,46 +124,92 @@ impl RatelimitMap {
             provider, selector, tokens_used
         );
 
-        let provider_limits = match self.datastore.get(&provider) {
-            None => {
-                // No limit configured for this provider, hence ok.
-                return Ok(());
-            }
-            Some(limit) => limit,
-        };
+        let descriptor = self.descriptor(&provider, &selector);
+        check_descriptor(descriptor.as_ref(), provider, selector, tokens_used)
+    }
+
+    /// The limit the selector of the provider is checked against, None when no limit
+    /// applies to it
+    fn descriptor(&self, provider: &str, selector: &Header) -> Option<Descriptor> {
+        // No limit configured for this provider, hence ok.
+        let provider_limits = self.datastore.get(provider)?;
 
         let mut config_selector = configuration::Header::from(selector.clone());
 
-        let (limit, limit_key) = match provider_limits.get(&config_selector) {
+        match provider_limits.get(&config_selector) {
             // This is a specific limit, i.e one that was configured with both key, and value.
             // Therefore, the key for the internal limit does not matter, and hence the empty string is always returned.
-            Some(limit) => (limit, String::from("")),
+            Some(limit) => Some(Descriptor {
+                limit: limit.clone(),
+                key: String::new(),
+            }),
             None => {
                 // Unwrap is ok here because we _know_ the value exists.
                 let header_key = config_selector.value.take().unwrap();
                 // Search for less specific limit, i.e, one that was configured without a value, therefore every Header
                 // value has its own key in the internal limit.
-                match provider_limits.get(&config_selector) {
-                    Some(limit) => (limit, header_key),
-                    // No limit for that header key, value pair exists within that provider limits.
-                    None => {
-                        return Ok(());
-                    }
-                }
+                // No limit for that header key, value pair exists within that provider limits.
+                provider_limits
+                    .get(&config_selector)
+                    .map(|limit| Descriptor {
+                        limit: limit.clone(),
+                        key: header_key,
+                    })
             }
-        };
-
-        match limit.check_key_n(&limit_key, tokens_used) {
-            Ok(Ok(())) => Ok(()),
-            Ok(Err(_)) | Err(InsufficientCapacity(_)) => Err(Error::ExceededLimit {
-                provider,
-                selector,
-                tokens_used,
-            }),
         }
     }
 }
 
+fn check_descriptor(
+    descriptor: Option<&Descriptor>,
+    provider: String,
+    selector: Header,
+    tokens_used: NonZeroU32,
+) -> Result<(), Error> {
+    let Some(descriptor) = descriptor else {
+        return Ok(());
+    };
+    match descriptor.limit.check_key_n(&descriptor.key, tokens_used) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) | Err(InsufficientCapacity(_)) => Err(Error::ExceededLimit {
+            provider,
+            selector,
+            tokens_used,
+        }),
+    }
+}
+
+/// Checks a limit of the shared ratelimits like [`RatelimitMap::check_limit`]. The
+/// descriptor of the model and selector is cached per worker, so the shared map is only
+/// locked the first time a selector is seen.
+pub fn check_limit(
+    provider: String,
+    selector: Header,
+    tokens_used: NonZeroU32,
+) -> Result<(), Error> {
+    debug!(
+        "Checking limit for provider={}, with selector={:?}, consuming tokens={:?}",
+        provider, selector, tokens_used
+    );
+    let cache_key = (
+        provider.clone(),
+        selector.key.clone(),
+        selector.value.clone(),
+    );
+    let descriptor = DESCRIPTORS
+        .with_borrow_mut(|descriptors| descriptors.get(&cache_key).cloned())
+        .unwrap_or_else(|| {
+            let descriptor = ratelimits(None)
+                .read()
+                .unwrap()
+                .descriptor(&provider, &selector);
+            DESCRIPTORS
+                .with_borrow_mut(|descriptors| descriptors.insert(cache_key, descriptor.clone()));
+            descriptor
+        });
+    check_descriptor(descriptor.as_ref(), provider, selector, tokens_used)
+}
+
 fn get_quota(limit: Limit) -> Quota {
     let tokens = NonZero::new(limit.tokens).expect("Limit's tokens must be positive");
     match limit.unit {
@@ -153,6 +219,62 @@ fn get_quota(limit: Limit) -> Quota {
     }
 }
 
+/// Selectors of the end user's JWT claims, brightstaff sends them as a JSON object of
+/// selector key to value. Values that aren't strings are ignored.
+pub fn claim_selectors(header_value: &str) -> Vec<Header> {
+    let Ok(claims) = serde_json::from_str::<HashMap<String, serde_json::Value>>(header_value)
+    else {
+        return Vec::new();
+    };
+    claims
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::String(value) => Some(Header { key, value }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn descriptors_share_the_limit_of_their_selector() {
+    let ratelimits_config = vec![Ratelimit {
+        model: String::from("provider"),
+        selector: configuration::Header {
+            key: String::from("key"),
+            value: None,
+        },
+        limit: Limit {
+            tokens: 100,
+            unit: TimeUnit::Minute,
+        },
+    }];
+
+    let ratelimits = RatelimitMap::new(ratelimits_config);
+    let selector = |value: &str| Header {
+        key: String::from("key"),
+        value: String::from(value),
+    };
+
+    let first = ratelimits.descriptor("provider", &selector("a")).unwrap();
+    let second = ratelimits.descriptor("provider", &selector("b")).unwrap();
+    assert!(Arc::ptr_eq(&first.limit, &second.limit));
+    assert_eq!((first.key.as_str(), second.key.as_str()), ("a", "b"));
+    assert!(ratelimits.descriptor("other", &selector("a")).is_none());
+
+    // a descriptor taken before the limit was used sees the tokens used since
+    let tokens = NonZero::new(100).unwrap();
+    assert!(check_descriptor(
+        Some(&first),
+        String::from("provider"),
+        selector("a"),
+        tokens
+    )
+    .is_ok());
+    assert!(ratelimits
+        .check_limit(String::from("provider"), selector("a"), tokens)
+        .is_err());
+}
+
 // The following tests are inside the ratelimit module in order to access RatelimitMap::new() in order to provide
 // different configuration values per test.
 #[test]
@@ -405,6 +527,20 @@ mod test {
     use std::num::NonZero;
     use std::thread;
 
+    #[test]
+    fn claim_selectors_of_header() {
+        let mut selectors = super::claim_selectors(r#"{"user_id":"alice","org_id":"42","n":1}"#);
+        selectors.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            selectors
+                .iter()
+                .map(|selector| (selector.key.as_str(), selector.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("org_id", "42"), ("user_id", "alice")]
+        );
+        assert!(super::claim_selectors("not json").is_empty());
+    }
+
     #[test]
     fn make_ratelimits_optional() {
         let ratelimits_config = Vec::new();
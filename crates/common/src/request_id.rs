@@ -0,0 +1,70 @@
+//! Request ids that correlate one request across brightstaff, the gateway filters, upstream
+//! providers, logs, traces and error responses.
+
+use rand::RngCore;
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Id of a request: the `x-archgw-request-id` sent by the client or an earlier hop, else
+/// envoy's `x-request-id`, else a new one. Ids that aren't short printable ascii are
+/// replaced, they are echoed in headers and logs.
+pub fn resolve_request_id(archgw_request_id: Option<&str>, request_id: Option<&str>) -> String {
+    archgw_request_id
+        .filter(|id| is_valid_request_id(id))
+        .or(request_id.filter(|id| is_valid_request_id(id)))
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id)
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// Random id in the uuid v4 format, like the ones envoy generates
+pub fn generate_request_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_request_id_prefers_archgw_header() {
+        assert_eq!(
+            resolve_request_id(Some("client-id"), Some("envoy-id")),
+            "client-id"
+        );
+        assert_eq!(resolve_request_id(None, Some("envoy-id")), "envoy-id");
+        assert_eq!(
+            resolve_request_id(Some("has spaces"), Some("envoy-id")),
+            "envoy-id"
+        );
+        assert_eq!(
+            resolve_request_id(Some(&"x".repeat(200)), Some("envoy-id")),
+            "envoy-id"
+        );
+    }
+
+    #[test]
+    fn test_generate_request_id() {
+        let id = resolve_request_id(None, Some(""));
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.as_bytes()[14], b'4');
+        assert_ne!(id, generate_request_id());
+    }
+}
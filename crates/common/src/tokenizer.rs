@@ -1,4 +1,27 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::lru::LruCache;
 use log::debug;
+use tiktoken_rs::CoreBPE;
+
+/// Encoders kept per worker, one per tokenizer model
+const MAX_CACHED_ENCODERS: usize = 8;
+
+thread_local! {
+    static ENCODERS: RefCell<LruCache<String, Rc<CoreBPE>>> =
+        RefCell::new(LruCache::new(MAX_CACHED_ENCODERS));
+}
+
+/// The encoder of the model, built once per worker instead of for every request
+fn encoder(model_name: &str) -> Result<Rc<CoreBPE>, String> {
+    if let Some(bpe) = ENCODERS.with_borrow_mut(|encoders| encoders.get(model_name).cloned()) {
+        return Ok(bpe);
+    }
+    let bpe = Rc::new(tiktoken_rs::get_bpe_from_model(model_name).map_err(|e| e.to_string())?);
+    ENCODERS.with_borrow_mut(|encoders| encoders.insert(model_name.to_string(), bpe.clone()));
+    Ok(bpe)
+}
 
 #[allow(dead_code)]
 pub fn token_count(model_name: &str, text: &str) -> Result<usize, String> {
@@ -23,8 +46,7 @@ pub fn token_count(model_name: &str, text: &str) -> Result<usize, String> {
         }
     };
 
-    // Consideration: is it more expensive to instantiate the BPE object every time, or to contend the singleton?
-    let bpe = tiktoken_rs::get_bpe_from_model(updated_model).map_err(|e| e.to_string())?;
+    let bpe = encoder(updated_model)?;
     Ok(bpe.encode_ordinary(text).len())
 }
 
@@ -41,4 +63,12 @@ mod test {
             token_count(model_name, text).expect("correct tokenization")
         );
     }
+
+    #[test]
+    fn encoders_are_cached_per_model() {
+        let first = encoder("gpt-4o").unwrap();
+        let second = encoder("gpt-4o").unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert!(encoder("not-a-model").is_err());
+    }
 }
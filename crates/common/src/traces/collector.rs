@@ -32,6 +32,11 @@ pub fn parse_traceparent(traceparent: &str) -> (String, Option<String>) {
     }
 }
 
+/// Receives the spans the collector flushes, e.g. to export them to another backend too
+pub trait SpanListener: Send + Sync {
+    fn on_spans(&self, service_name: &str, spans: &[Span]);
+}
+
 /// Collects and batches spans, flushing them to an OTEL collector
 ///
 /// Supports multiple services, with each service (e.g., "archgw(routing)", "archgw(llm)")
@@ -51,6 +56,8 @@ pub struct TraceCollector {
     otel_url: String,
     /// Whether tracing is enabled
     enabled: bool,
+    /// Get every batch of flushed spans before it is sent
+    listeners: Vec<Arc<dyn SpanListener>>,
 }
 
 impl TraceCollector {
@@ -95,9 +102,16 @@ impl TraceCollector {
             flush_interval: Duration::from_millis(flush_interval_ms),
             otel_url,
             enabled,
+            listeners: Vec::new(),
         }
     }
 
+    /// Hand every batch of flushed spans to the listener too
+    pub fn with_listener(mut self, listener: Arc<dyn SpanListener>) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
     /// Record a span for a specific service
     ///
     /// # Arguments
@@ -159,6 +173,12 @@ impl TraceCollector {
             return Ok(());
         }
 
+        for (service_name, spans) in &service_batches {
+            for listener in &self.listeners {
+                listener.on_spans(service_name, spans);
+            }
+        }
+
         let total_spans: usize = service_batches.iter().map(|(_, spans)| spans.len()).sum();
         debug!(
             "Flushing {} spans across {} services to OTEL collector",
@@ -275,6 +295,32 @@ mod tests {
         assert_eq!(collector.buffered_count().await, 1);
     }
 
+    #[tokio::test]
+    async fn test_listeners_get_flushed_spans() {
+        struct Count(std::sync::Mutex<Vec<(String, usize)>>);
+
+        impl SpanListener for Count {
+            fn on_spans(&self, service_name: &str, spans: &[Span]) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((service_name.to_string(), spans.len()));
+            }
+        }
+
+        let count = Arc::new(Count(std::sync::Mutex::new(Vec::new())));
+        let mut collector = TraceCollector::new(Some(true)).with_listener(count.clone());
+        collector.otel_url = "http://127.0.0.1:1/v1/traces".to_string();
+        collector.record_span("test-service", SpanBuilder::new("test").build());
+
+        // the OTEL collector isn't reachable, the listener still gets the spans
+        assert!(collector.flush().await.is_err());
+        assert_eq!(
+            *count.0.lock().unwrap(),
+            vec![("test-service".to_string(), 1)]
+        );
+    }
+
     #[tokio::test]
     async fn test_collector_auto_flush() {
         // Since batch-triggered flush behavior was removed, record two spans and verify both are buffered
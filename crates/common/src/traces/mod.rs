@@ -23,4 +23,4 @@ pub use resource_span_builder::ResourceSpanBuilder;
 pub use span_builder::{generate_random_span_id, SpanBuilder, SpanKind};
 
 #[cfg(feature = "trace-collection")]
-pub use collector::{parse_traceparent, TraceCollector};
+pub use collector::{parse_traceparent, SpanListener, TraceCollector};
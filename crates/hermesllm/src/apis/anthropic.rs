@@ -4,6 +4,7 @@ use serde_json::Value;
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
 
+use super::parse_mode::lenient_string_enum;
 use super::ApiDefinition;
 use crate::providers::request::{ProviderRequest, ProviderRequestError};
 use crate::providers::response::ProviderResponse;
@@ -217,6 +218,9 @@ pub enum MessagesContentBlock {
         media_type: String,
         data: String,
     },
+    /// A block of a type not known yet, kept as sent in lenient parse mode
+    #[serde(untagged, deserialize_with = "super::parse_mode::unknown_block")]
+    Other(Value),
 }
 
 impl ExtractText for Vec<MessagesContentBlock> {
@@ -303,8 +307,7 @@ pub struct MessagesToolChoice {
     pub disable_parallel_tool_use: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MessagesStopReason {
     EndTurn,
     MaxTokens,
@@ -312,8 +315,19 @@ pub enum MessagesStopReason {
     ToolUse,
     PauseTurn,
     Refusal,
+    /// A stop reason not known yet, kept in lenient parse mode
+    Other(String),
 }
 
+lenient_string_enum!(MessagesStopReason {
+    EndTurn => "end_turn",
+    MaxTokens => "max_tokens",
+    StopSequence => "stop_sequence",
+    ToolUse => "tool_use",
+    PauseTurn => "pause_turn",
+    Refusal => "refusal",
+});
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessagesUsage {
@@ -400,6 +414,9 @@ pub enum MessagesContentDelta {
     ThinkingDelta { thinking: String },
     #[serde(rename = "signature_delta")]
     SignatureDelta { signature: String },
+    /// A delta of a type not known yet, kept as sent in lenient parse mode
+    #[serde(untagged, deserialize_with = "super::parse_mode::unknown_block")]
+    Other(Value),
 }
 
 #[skip_serializing_none]
@@ -410,6 +427,26 @@ pub struct MessagesMessageDelta {
 }
 
 // Helper functions for API detection and conversion
+/// Model of the `/v1/models` list
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub obj_type: String,
+    pub display_name: String,
+    /// RFC 3339 time the model was released
+    pub created_at: String,
+}
+
+/// Page of the `/v1/models` list
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelsList {
+    pub data: Vec<ModelInfo>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
 impl MessagesRequest {
     pub fn api_type() -> AnthropicApi {
         AnthropicApi::Messages
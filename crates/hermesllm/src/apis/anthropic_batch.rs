@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::apis::anthropic::{MessagesRequest, MessagesResponse};
+
+// ============================================================================
+// ANTHROPIC MESSAGE BATCHES API
+// ============================================================================
+
+/// Processing status of a message batch
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageBatchStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+/// Number of requests of a batch in each state
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MessageBatchRequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// Message batch returned by create, retrieve, list and cancel. Timestamps are RFC 3339.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageBatch {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub obj_type: String,
+    pub processing_status: MessageBatchStatus,
+    pub request_counts: MessageBatchRequestCounts,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub cancel_initiated_at: Option<String>,
+    pub archived_at: Option<String>,
+    pub results_url: Option<String>,
+}
+
+impl MessageBatch {
+    /// Parse a message batch, returns None for other bodies (lists, results, errors)
+    pub fn from_response(body: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<MessageBatch>(body)
+            .ok()
+            .filter(|batch| batch.obj_type == "message_batch")
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.processing_status == MessageBatchStatus::Ended
+    }
+}
+
+/// One request of a batch, answered in the results under the same custom_id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatchRequest {
+    pub custom_id: String,
+    pub params: MessagesRequest,
+}
+
+/// Body of `POST /v1/messages/batches`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatchCreateRequest {
+    pub requests: Vec<MessageBatchRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum MessageBatchResultType {
+    Succeeded {
+        message: Box<MessagesResponse>,
+    },
+    /// The error is an Anthropic error body, `{"type":"error","error":{...}}`
+    Errored {
+        error: Value,
+    },
+    Canceled,
+    Expired,
+}
+
+/// One line of the JSONL results of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatchResult {
+    pub custom_id: String,
+    pub result: MessageBatchResultType,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_batch_from_response() {
+        let body = br#"{
+            "id": "msgbatch_013Zva2CMHLNnXjNJJKqJ2EF",
+            "type": "message_batch",
+            "processing_status": "in_progress",
+            "request_counts": {"processing": 100, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+            "ended_at": null,
+            "created_at": "2024-08-20T18:37:24.100435Z",
+            "expires_at": "2024-08-21T18:37:24.100435Z",
+            "cancel_initiated_at": null,
+            "archived_at": null,
+            "results_url": null
+        }"#;
+        let batch = MessageBatch::from_response(body).unwrap();
+        assert!(!batch.is_ended());
+        assert_eq!(batch.request_counts.processing, 100);
+
+        assert_eq!(
+            MessageBatch::from_response(br#"{"data":[],"has_more":false}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_message_batch_result_round_trip() {
+        let line = r#"{"custom_id":"req-1","result":{"type":"succeeded","message":{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"Hello"}],"model":"claude-sonnet-4","stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":2}}}}"#;
+        let result: MessageBatchResult = serde_json::from_str(line).unwrap();
+        assert_eq!(result.custom_id, "req-1");
+        assert!(matches!(
+            result.result,
+            MessageBatchResultType::Succeeded { .. }
+        ));
+
+        let canceled = MessageBatchResult {
+            custom_id: "req-2".to_string(),
+            result: MessageBatchResultType::Canceled,
+        };
+        assert_eq!(
+            serde_json::to_string(&canceled).unwrap(),
+            r#"{"custom_id":"req-2","result":{"type":"canceled"}}"#
+        );
+    }
+}
@@ -0,0 +1,590 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use std::collections::HashMap;
+
+use super::parse_mode::lenient_string_enum;
+use super::ApiDefinition;
+use crate::providers::request::{ProviderRequest, ProviderRequestError};
+use crate::providers::response::TokenUsage;
+
+// ============================================================================
+// GEMINI API ENUMERATION
+// ============================================================================
+
+/// Enum for the native Gemini generateContent APIs. The model is part of the path,
+/// e.g. `/v1beta/models/gemini-2.5-flash:generateContent`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeminiApi {
+    GenerateContent,
+    StreamGenerateContent,
+}
+
+impl ApiDefinition for GeminiApi {
+    fn endpoint(&self) -> &'static str {
+        match self {
+            GeminiApi::GenerateContent => "/v1beta/models/{model}:generateContent",
+            GeminiApi::StreamGenerateContent => "/v1beta/models/{model}:streamGenerateContent",
+        }
+    }
+
+    fn from_endpoint(endpoint: &str) -> Option<Self> {
+        let path = endpoint.split('?').next().unwrap_or(endpoint);
+        if path.ends_with(":generateContent") {
+            Some(GeminiApi::GenerateContent)
+        } else if path.ends_with(":streamGenerateContent") {
+            Some(GeminiApi::StreamGenerateContent)
+        } else {
+            None
+        }
+    }
+
+    fn supports_streaming(&self) -> bool {
+        match self {
+            GeminiApi::GenerateContent => false,
+            GeminiApi::StreamGenerateContent => true,
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_vision(&self) -> bool {
+        true
+    }
+
+    fn all_variants() -> Vec<Self> {
+        vec![GeminiApi::GenerateContent, GeminiApi::StreamGenerateContent]
+    }
+}
+
+impl GeminiApi {
+    /// Method of the generateContent path, streams are requested as SSE
+    pub fn method(&self) -> &'static str {
+        match self {
+            GeminiApi::GenerateContent => ":generateContent",
+            GeminiApi::StreamGenerateContent => ":streamGenerateContent?alt=sse",
+        }
+    }
+}
+
+/// Model id as it goes in a Gemini path or body, without the `models/` resource prefix
+/// clients sometimes copy from the Gemini model list
+pub fn gemini_model_name(model: &str) -> &str {
+    model.strip_prefix("models/").unwrap_or(model)
+}
+
+/// Publisher model resource of a Vertex AI model id. Bare ids are Google models,
+/// `publisher/model` and full `publishers/{publisher}/models/{model}` ids name the
+/// publisher.
+pub fn vertex_model_path(model: &str) -> String {
+    let model = gemini_model_name(model);
+    if model.starts_with("publishers/") {
+        return model.to_string();
+    }
+    match model.split_once('/') {
+        Some((publisher, model)) => format!("publishers/{}/models/{}", publisher, model),
+        None => format!("publishers/google/models/{}", model),
+    }
+}
+
+// ============================================================================
+// GENERATE CONTENT REQUEST STRUCTURES
+// ============================================================================
+
+/// Gemini generateContent request
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    pub contents: Vec<Content>,
+    pub system_instruction: Option<Content>,
+    pub tools: Option<Vec<Tool>>,
+    pub tool_config: Option<ToolConfig>,
+    pub generation_config: Option<GenerationConfig>,
+    pub safety_settings: Option<Vec<Value>>,
+    pub cached_content: Option<String>,
+    /// The model is part of the path, not the body
+    #[serde(skip)]
+    pub model: String,
+    /// Whether the request goes to streamGenerateContent (internal field, not serialized)
+    #[serde(skip)]
+    pub stream: bool,
+    /// Additional custom metadata (for internal use), Gemini rejects unknown fields
+    #[serde(skip)]
+    pub metadata: Option<HashMap<String, Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentRole {
+    User,
+    Model,
+    Function,
+}
+
+/// A turn of the conversation, or the system instruction when it has no role
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Content {
+    pub role: Option<ContentRole>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+/// Part of a content, exactly one of the data fields is set
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    pub text: Option<String>,
+    /// Set on text parts that hold the model's thinking
+    pub thought: Option<bool>,
+    pub thought_signature: Option<String>,
+    pub inline_data: Option<Blob>,
+    pub file_data: Option<FileData>,
+    pub function_call: Option<FunctionCall>,
+    pub function_response: Option<FunctionResponse>,
+}
+
+impl Part {
+    pub fn text(text: impl Into<String>) -> Self {
+        Part {
+            text: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Answer text of the part, None for thoughts and non text parts
+    pub fn answer_text(&self) -> Option<&str> {
+        match self.thought {
+            Some(true) => None,
+            _ => self.text.as_deref(),
+        }
+    }
+
+    /// Thinking text of the part
+    pub fn thought_text(&self) -> Option<&str> {
+        match self.thought {
+            Some(true) => self.text.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    pub mime_type: String,
+    /// Base64 encoded bytes
+    pub data: String,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    pub mime_type: Option<String>,
+    pub file_uri: String,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+    pub id: Option<String>,
+    pub name: String,
+    pub args: Option<Value>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionResponse {
+    pub id: Option<String>,
+    pub name: String,
+    /// The function result, always a json object
+    pub response: Value,
+}
+
+/// Tools of a request, function declarations or one of Gemini's built in tools
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub function_declarations: Option<Vec<FunctionDeclaration>>,
+    pub google_search: Option<Value>,
+    pub code_execution: Option<Value>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionDeclaration {
+    pub name: String,
+    pub description: Option<String>,
+    /// JSON schema of the arguments
+    pub parameters_json_schema: Option<Value>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    pub function_calling_config: Option<FunctionCallingConfig>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallingConfig {
+    pub mode: FunctionCallingMode,
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FunctionCallingMode {
+    Auto,
+    Any,
+    None,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    pub stop_sequences: Option<Vec<String>>,
+    pub response_mime_type: Option<String>,
+    pub response_json_schema: Option<Value>,
+    pub candidate_count: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub seed: Option<i32>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub thinking_config: Option<ThinkingConfig>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingConfig {
+    pub include_thoughts: Option<bool>,
+    pub thinking_budget: Option<i32>,
+}
+
+// ============================================================================
+// GENERATE CONTENT RESPONSE STRUCTURES
+// ============================================================================
+
+/// Gemini generateContent response, also the shape of each streamGenerateContent chunk
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    pub prompt_feedback: Option<Value>,
+    pub usage_metadata: Option<UsageMetadata>,
+    pub model_version: Option<String>,
+    pub response_id: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    pub content: Option<Content>,
+    pub finish_reason: Option<FinishReason>,
+    pub index: Option<u32>,
+    pub safety_ratings: Option<Vec<Value>>,
+}
+
+/// Why a candidate stopped. `FINISH_REASON_UNSPECIFIED`, `OTHER` and reasons added
+/// later are kept in `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    Stop,
+    MaxTokens,
+    Safety,
+    Recitation,
+    Blocklist,
+    ProhibitedContent,
+    Spii,
+    MalformedFunctionCall,
+    /// A finish reason not known yet, kept in lenient parse mode
+    Other(String),
+}
+
+lenient_string_enum!(FinishReason {
+    Stop => "STOP",
+    MaxTokens => "MAX_TOKENS",
+    Safety => "SAFETY",
+    Recitation => "RECITATION",
+    Blocklist => "BLOCKLIST",
+    ProhibitedContent => "PROHIBITED_CONTENT",
+    Spii => "SPII",
+    MalformedFunctionCall => "MALFORMED_FUNCTION_CALL",
+});
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    #[serde(default)]
+    pub prompt_token_count: u32,
+    #[serde(default)]
+    pub candidates_token_count: u32,
+    #[serde(default)]
+    pub total_token_count: u32,
+    pub cached_content_token_count: Option<u32>,
+    pub thoughts_token_count: Option<u32>,
+}
+
+impl TokenUsage for UsageMetadata {
+    fn completion_tokens(&self) -> usize {
+        (self.candidates_token_count + self.thoughts_token_count.unwrap_or(0)) as usize
+    }
+
+    fn prompt_tokens(&self) -> usize {
+        self.prompt_token_count as usize
+    }
+
+    fn total_tokens(&self) -> usize {
+        self.total_token_count as usize
+    }
+}
+
+// ============================================================================
+// PROVIDER REQUEST
+// ============================================================================
+
+impl TryFrom<&[u8]> for GenerateContentRequest {
+    type Error = serde_json::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+impl ProviderRequest for GenerateContentRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.stream
+    }
+
+    fn extract_messages_text(&self) -> String {
+        self.system_instruction
+            .iter()
+            .chain(&self.contents)
+            .flat_map(|content| &content.parts)
+            .filter_map(|part| part.text.as_deref())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn get_recent_user_message(&self) -> Option<String> {
+        self.contents
+            .iter()
+            .rev()
+            .filter(|content| content.role == Some(ContentRole::User))
+            .find_map(|content| content.parts.iter().find_map(|part| part.text.clone()))
+    }
+
+    fn get_tool_names(&self) -> Option<Vec<String>> {
+        let names: Vec<String> = self
+            .tools
+            .as_ref()?
+            .iter()
+            .flat_map(|tool| tool.function_declarations.iter().flatten())
+            .map(|declaration| declaration.name.clone())
+            .collect();
+        Some(names)
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, ProviderRequestError> {
+        serde_json::to_vec(self).map_err(|e| ProviderRequestError {
+            message: format!("Failed to serialize Gemini request: {}", e),
+            source: Some(Box::new(e)),
+        })
+    }
+
+    fn metadata(&self) -> &Option<HashMap<String, Value>> {
+        &self.metadata
+    }
+
+    fn remove_metadata_key(&mut self, key: &str) -> bool {
+        if let Some(ref mut metadata) = self.metadata {
+            metadata.remove(key).is_some()
+        } else {
+            false
+        }
+    }
+
+    fn get_temperature(&self) -> Option<f32> {
+        self.generation_config.as_ref()?.temperature
+    }
+
+    fn get_messages(&self) -> Vec<crate::apis::openai::Message> {
+        use crate::apis::openai::{Message, MessageContent, Role};
+
+        let text_of = |content: &Content| {
+            content
+                .parts
+                .iter()
+                .filter_map(Part::answer_text)
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let system = self
+            .system_instruction
+            .iter()
+            .map(|content| (Role::System, content));
+        let turns = self.contents.iter().map(|content| {
+            let role = match content.role {
+                Some(ContentRole::Model) => Role::Assistant,
+                _ => Role::User,
+            };
+            (role, content)
+        });
+        system
+            .chain(turns)
+            .map(|(role, content)| Message {
+                role,
+                content: MessageContent::Text(text_of(content)),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            })
+            .collect()
+    }
+
+    fn set_messages(&mut self, messages: &[crate::apis::openai::Message]) {
+        use crate::apis::openai::Role;
+        use crate::transforms::lib::ExtractText;
+
+        let mut system_parts = Vec::new();
+        let mut contents = Vec::new();
+        for message in messages {
+            let text = message.content.extract_text();
+            let role = match message.role {
+                Role::System => {
+                    system_parts.push(Part::text(text));
+                    continue;
+                }
+                Role::Assistant => ContentRole::Model,
+                Role::User | Role::Tool => ContentRole::User,
+            };
+            contents.push(Content {
+                role: Some(role),
+                parts: vec![Part::text(text)],
+            });
+        }
+
+        self.system_instruction = (!system_parts.is_empty()).then_some(Content {
+            role: None,
+            parts: system_parts,
+        });
+        self.contents = contents;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_api_from_endpoint() {
+        assert_eq!(
+            GeminiApi::from_endpoint("/v1beta/models/gemini-2.5-flash:generateContent"),
+            Some(GeminiApi::GenerateContent)
+        );
+        assert_eq!(
+            GeminiApi::from_endpoint(
+                "/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+            ),
+            Some(GeminiApi::StreamGenerateContent)
+        );
+        assert_eq!(GeminiApi::from_endpoint("/v1/chat/completions"), None);
+        assert_eq!(gemini_model_name("models/gemini-2.5-pro"), "gemini-2.5-pro");
+        assert_eq!(gemini_model_name("gemini-2.5-pro"), "gemini-2.5-pro");
+        assert_eq!(
+            vertex_model_path("gemini-2.5-pro"),
+            "publishers/google/models/gemini-2.5-pro"
+        );
+        assert_eq!(
+            vertex_model_path("meta/llama-4-scout"),
+            "publishers/meta/models/llama-4-scout"
+        );
+        assert_eq!(
+            vertex_model_path("publishers/google/models/gemini-2.5-pro"),
+            "publishers/google/models/gemini-2.5-pro"
+        );
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let body = json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "Thinking about it", "thought": true},
+                    {"text": "Paris"},
+                    {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}
+                ]},
+                "finishReason": "STOP",
+                "index": 0
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 7,
+                "candidatesTokenCount": 3,
+                "thoughtsTokenCount": 4,
+                "totalTokenCount": 14
+            },
+            "modelVersion": "gemini-2.5-flash",
+            "responseId": "abc"
+        });
+        let response: GenerateContentResponse = serde_json::from_value(body.clone()).unwrap();
+        let candidate = &response.candidates[0];
+        assert_eq!(candidate.finish_reason, Some(FinishReason::Stop));
+        let parts = &candidate.content.as_ref().unwrap().parts;
+        assert_eq!(parts[0].thought_text(), Some("Thinking about it"));
+        assert_eq!(parts[1].answer_text(), Some("Paris"));
+        assert_eq!(parts[2].function_call.as_ref().unwrap().name, "get_weather");
+        let usage = response.usage_metadata.as_ref().unwrap();
+        assert_eq!(usage.completion_tokens(), 7);
+        assert_eq!(serde_json::to_value(&response).unwrap(), body);
+
+        let unknown: FinishReason = serde_json::from_value(json!("LANGUAGE")).unwrap();
+        assert_eq!(unknown, FinishReason::Other("LANGUAGE".to_string()));
+    }
+
+    #[test]
+    fn test_request_skips_internal_fields() {
+        let request = GenerateContentRequest {
+            contents: vec![Content {
+                role: Some(ContentRole::User),
+                parts: vec![Part::text("Hello")],
+            }],
+            model: "gemini-2.5-flash".to_string(),
+            stream: true,
+            metadata: Some(HashMap::from([("user".to_string(), json!("u1"))])),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"contents": [{"role": "user", "parts": [{"text": "Hello"}]}]})
+        );
+        assert_eq!(request.get_recent_user_message(), Some("Hello".to_string()));
+    }
+}
@@ -1,7 +1,12 @@
 pub mod amazon_bedrock;
 pub mod anthropic;
+pub mod anthropic_batch;
+pub mod gemini;
 pub mod openai;
+pub mod openai_audio;
+pub mod openai_batch;
 pub mod openai_responses;
+pub mod parse_mode;
 pub mod streaming_shapes;
 
 // Explicit exports to avoid naming conflicts
@@ -10,10 +15,14 @@ pub use amazon_bedrock::{
     Message as BedrockMessage, Tool as BedrockTool, ToolChoice as BedrockToolChoice,
 };
 pub use anthropic::{AnthropicApi, MessagesRequest, MessagesResponse, MessagesStreamEvent};
+pub use anthropic_batch::{MessageBatch, MessageBatchCreateRequest, MessageBatchResult};
+pub use gemini::{GeminiApi, GenerateContentRequest, GenerateContentResponse};
 pub use openai::{
     ChatCompletionsRequest, ChatCompletionsResponse, ChatCompletionsStreamResponse, OpenAIApi,
 };
 pub use openai::{Message as OpenAIMessage, Tool as OpenAITool, ToolChoice as OpenAIToolChoice};
+pub use openai_audio::AudioApi;
+pub use openai_batch::BatchApi;
 
 pub trait ApiDefinition {
     /// Returns the endpoint path for this API
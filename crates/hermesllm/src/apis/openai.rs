@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use thiserror::Error;
 
+use super::parse_mode::lenient_string_enum;
 use super::ApiDefinition;
 use crate::providers::request::{ProviderRequest, ProviderRequestError};
 use crate::providers::response::{ProviderResponse, TokenUsage};
@@ -114,6 +115,10 @@ pub struct ChatCompletionsRequest {
     pub stop_token_ids: Option<Vec<u32>>,
     pub continue_final_message: Option<bool>,
     pub add_generation_prompt: Option<bool>,
+
+    // Mistral-specific parameters
+    pub safe_prompt: Option<bool>,
+    pub random_seed: Option<i32>,
 }
 
 impl ChatCompletionsRequest {
@@ -135,6 +140,42 @@ impl ChatCompletionsRequest {
             self.temperature = Some(1.0);
         }
     }
+
+    /// Rewrite the request into the subset of fields the Mistral chat API accepts.
+    /// Mistral rejects unknown fields, names the seed `random_seed`, only knows
+    /// `max_tokens` and spells a forced tool call as `any`.
+    pub fn sanitize_for_mistral(&mut self, safe_prompt: Option<bool>) {
+        if self.max_tokens.is_none() {
+            self.max_tokens = self.max_completion_tokens;
+        }
+        self.max_completion_tokens = None;
+        if let Some(seed) = self.seed.take() {
+            self.random_seed = Some(seed);
+        }
+        if let Some(ToolChoice::Type(ToolChoiceType::Required)) = self.tool_choice {
+            self.tool_choice = Some(ToolChoice::Type(ToolChoiceType::Any));
+        }
+        if self.safe_prompt.is_none() {
+            self.safe_prompt = safe_prompt;
+        }
+
+        self.function_call = None;
+        self.functions = None;
+        self.logit_bias = None;
+        self.logprobs = None;
+        self.top_logprobs = None;
+        self.modalities = None;
+        self.metadata = None;
+        self.reasoning_effort = None;
+        self.service_tier = None;
+        self.store = None;
+        self.stream_options = None;
+        self.user = None;
+        self.top_k = None;
+        self.stop_token_ids = None;
+        self.continue_final_message = None;
+        self.add_generation_prompt = None;
+    }
 }
 
 // ============================================================================
@@ -179,6 +220,8 @@ pub struct ResponseMessage {
     pub function_call: Option<FunctionCall>,
     /// The tool calls generated by the model, such as function calls
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Chain of thought returned by reasoning models on OpenAI compatible APIs (e.g. DeepSeek)
+    pub reasoning_content: Option<String>,
 }
 
 impl Default for ResponseMessage {
@@ -191,6 +234,7 @@ impl Default for ResponseMessage {
             audio: None,
             function_call: None,
             tool_calls: None,
+            reasoning_content: None,
         }
     }
 }
@@ -330,6 +374,8 @@ pub enum ToolChoiceType {
     Required,
     /// Prevent the model from calling any tools
     None,
+    /// Mistral's spelling of `required`
+    Any,
 }
 
 /// Tool choice configuration
@@ -399,19 +445,39 @@ pub struct ChatCompletionsResponse {
     pub service_tier: Option<String>,
     // This isn't a standard OpenAI field, but we include it for extensibility
     pub metadata: Option<HashMap<String, Value>>,
+    /// Unknown top-level fields from OpenAI compatible providers (e.g. Groq's `x_groq`,
+    /// `usage_breakdown`), kept so they round-trip on passthrough
+    #[serde(flatten)]
+    pub extensions: HashMap<String, Value>,
+}
+
+impl ChatCompletionsResponse {
+    /// Seconds the request spent queued at the provider, as reported by Groq
+    pub fn queue_time(&self) -> Option<f64> {
+        groq_queue_time(Some(&self.usage), &self.extensions)
+    }
 }
 
 /// Finish reason for completion
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FinishReason {
     Stop,
     Length,
     ToolCalls,
     ContentFilter,
     FunctionCall, // Legacy
+    /// A finish reason not known yet, kept in lenient parse mode
+    Other(String),
 }
 
+lenient_string_enum!(FinishReason {
+    Stop => "stop",
+    Length => "length",
+    ToolCalls => "tool_calls",
+    ContentFilter => "content_filter",
+    FunctionCall => "function_call",
+});
+
 /// Token usage information
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -421,6 +487,37 @@ pub struct Usage {
     pub total_tokens: u32,
     pub prompt_tokens_details: Option<PromptTokensDetails>,
     pub completion_tokens_details: Option<CompletionTokensDetails>,
+    /// Non-standard usage fields reported by OpenAI compatible providers
+    #[serde(flatten)]
+    pub provider_extensions: UsageExtensions,
+}
+
+/// Provider specific usage fields that sit next to the standard OpenAI ones
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UsageExtensions {
+    /// DeepSeek context caching: prompt tokens served from cache
+    pub prompt_cache_hit_tokens: Option<u32>,
+    /// DeepSeek context caching: prompt tokens not found in cache
+    pub prompt_cache_miss_tokens: Option<u32>,
+    /// Groq: seconds the request spent queued before processing started
+    pub queue_time: Option<f64>,
+    /// Any other provider specific usage fields (e.g. Groq's `prompt_time`)
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl Usage {
+    /// Prompt tokens served from the provider's prompt cache, if reported
+    pub fn cached_prompt_tokens(&self) -> Option<u32> {
+        self.provider_extensions
+            .prompt_cache_hit_tokens
+            .or_else(|| {
+                self.prompt_tokens_details
+                    .as_ref()
+                    .and_then(|details| details.cached_tokens)
+            })
+    }
 }
 
 /// Detailed breakdown of prompt tokens
@@ -449,6 +546,9 @@ pub struct Choice {
     pub message: ResponseMessage,
     pub finish_reason: Option<FinishReason>,
     pub logprobs: Option<Value>,
+    /// Stop sequence that ended a `stop` completion. Not an OpenAI field, set for
+    /// providers that report it (Anthropic).
+    pub stop_sequence: Option<String>,
 }
 
 // ============================================================================
@@ -468,6 +568,29 @@ pub struct ChatCompletionsStreamResponse {
     pub system_fingerprint: Option<String>,
     /// Specifies the processing type used for serving the request
     pub service_tier: Option<String>,
+    /// Unknown top-level fields from OpenAI compatible providers (e.g. Groq's `x_groq`)
+    #[serde(flatten)]
+    pub extensions: HashMap<String, Value>,
+}
+
+impl ChatCompletionsStreamResponse {
+    /// Seconds the request spent queued at the provider, as reported by Groq.
+    /// Groq reports streaming usage inside `x_groq` on the final chunk.
+    pub fn queue_time(&self) -> Option<f64> {
+        groq_queue_time(self.usage.as_ref(), &self.extensions)
+    }
+}
+
+fn groq_queue_time(usage: Option<&Usage>, extensions: &HashMap<String, Value>) -> Option<f64> {
+    usage
+        .and_then(|usage| usage.provider_extensions.queue_time)
+        .or_else(|| {
+            extensions
+                .get("x_groq")
+                .and_then(|x_groq| x_groq.get("usage"))
+                .and_then(|usage| usage.get("queue_time"))
+                .and_then(Value::as_f64)
+        })
 }
 
 /// A choice in a streaming response
@@ -478,6 +601,8 @@ pub struct StreamChoice {
     pub delta: MessageDelta,
     pub finish_reason: Option<FinishReason>,
     pub logprobs: Option<Value>,
+    /// Stop sequence that ended a `stop` completion, see [`Choice::stop_sequence`]
+    pub stop_sequence: Option<String>,
 }
 
 /// Message delta for streaming updates
@@ -491,6 +616,8 @@ pub struct MessageDelta {
     /// Deprecated and replaced by tool_calls. The name and arguments of a function that should be called
     pub function_call: Option<FunctionCall>,
     pub tool_calls: Option<Vec<ToolCallDelta>>,
+    /// Streaming chain of thought from reasoning models (e.g. DeepSeek)
+    pub reasoning_content: Option<String>,
 }
 
 /// Tool call delta for streaming tool call updates
@@ -1316,4 +1443,98 @@ mod tests {
         assert_eq!(response.service_tier, None); // Should be None when not present
         assert_eq!(response.system_fingerprint, None);
     }
+
+    #[test]
+    fn test_sanitize_for_mistral() {
+        let request_json = json!({
+            "model": "mistral-large-latest",
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_completion_tokens": 256,
+            "seed": 7,
+            "tool_choice": "required",
+            "logit_bias": {"50256": -100},
+            "stream_options": {"include_usage": true},
+            "user": "user-1",
+            "store": true
+        });
+        let mut request: ChatCompletionsRequest = serde_json::from_value(request_json).unwrap();
+
+        request.sanitize_for_mistral(Some(true));
+
+        let sanitized = serde_json::to_value(&request).unwrap();
+        assert_eq!(sanitized["max_tokens"], 256);
+        assert_eq!(sanitized["random_seed"], 7);
+        assert_eq!(sanitized["tool_choice"], "any");
+        assert_eq!(sanitized["safe_prompt"], true);
+        for field in [
+            "max_completion_tokens",
+            "seed",
+            "logit_bias",
+            "stream_options",
+            "user",
+            "store",
+        ] {
+            assert!(sanitized.get(field).is_none(), "{} not stripped", field);
+        }
+
+        // An explicit safe_prompt on the request wins over the provider default
+        let mut request = ChatCompletionsRequest {
+            safe_prompt: Some(false),
+            ..Default::default()
+        };
+        request.sanitize_for_mistral(Some(true));
+        assert_eq!(request.safe_prompt, Some(false));
+    }
+
+    #[test]
+    fn test_groq_extension_fields_round_trip() {
+        let groq_response = json!({
+            "id": "chatcmpl-groq",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "llama-3.3-70b-versatile",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "queue_time": 0.037,
+                "prompt_tokens": 18,
+                "prompt_time": 0.00068,
+                "completion_tokens": 2,
+                "completion_time": 0.0015,
+                "total_tokens": 20,
+                "total_time": 0.0022
+            },
+            "usage_breakdown": {"models": null},
+            "x_groq": {"id": "req_01"}
+        });
+
+        let response: ChatCompletionsResponse =
+            serde_json::from_value(groq_response.clone()).unwrap();
+        assert_eq!(response.queue_time(), Some(0.037));
+        assert_eq!(response.extensions["x_groq"]["id"], "req_01");
+
+        let round_trip = serde_json::to_value(&response).unwrap();
+        assert_eq!(round_trip["x_groq"], groq_response["x_groq"]);
+        assert_eq!(
+            round_trip["usage_breakdown"],
+            groq_response["usage_breakdown"]
+        );
+        assert_eq!(round_trip["usage"]["prompt_time"], 0.00068);
+        assert_eq!(round_trip["usage"]["queue_time"], 0.037);
+
+        // Streaming responses carry usage inside x_groq on the final chunk
+        let groq_chunk = json!({
+            "id": "chatcmpl-groq",
+            "object": "chat.completion.chunk",
+            "created": 1234567890,
+            "model": "llama-3.3-70b-versatile",
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}],
+            "x_groq": {"id": "req_01", "usage": {"queue_time": 0.05, "total_tokens": 20}}
+        });
+        let chunk: ChatCompletionsStreamResponse = serde_json::from_value(groq_chunk).unwrap();
+        assert_eq!(chunk.queue_time(), Some(0.05));
+    }
 }
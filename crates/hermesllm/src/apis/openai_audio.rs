@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+
+use crate::providers::id::ProviderId;
+use crate::{AUDIO_SPEECH_PATH, AUDIO_TRANSCRIPTIONS_PATH};
+
+// ============================================================================
+// OPENAI AUDIO API ENUMERATION
+// ============================================================================
+
+/// Whisper style audio endpoints. Unlike the chat APIs these are proxied without
+/// conversion: transcription uploads are multipart and speech responses are binary
+/// audio, so only the model and the usage are looked at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioApi {
+    Transcriptions,
+    Speech,
+}
+
+impl AudioApi {
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            AudioApi::Transcriptions => AUDIO_TRANSCRIPTIONS_PATH,
+            AudioApi::Speech => AUDIO_SPEECH_PATH,
+        }
+    }
+
+    /// Create an AudioApi from a request path, ignoring any query string
+    pub fn from_endpoint(endpoint: &str) -> Option<Self> {
+        let path = endpoint.split('?').next().unwrap_or_default();
+        match path {
+            AUDIO_TRANSCRIPTIONS_PATH => Some(AudioApi::Transcriptions),
+            AUDIO_SPEECH_PATH => Some(AudioApi::Speech),
+            _ => None,
+        }
+    }
+
+    /// Upstream path for the provider. Groq serves the OpenAI compatible audio API under
+    /// `/openai`, Azure under the deployment path; everything else uses `/v1`.
+    pub fn target_endpoint_for_provider(
+        &self,
+        provider_id: &ProviderId,
+        model_id: &str,
+        base_url_path_prefix: Option<&str>,
+    ) -> String {
+        let suffix = self.endpoint().trim_start_matches("/v1/");
+        let default_prefix = match provider_id {
+            ProviderId::Groq => "openai/v1".to_string(),
+            ProviderId::AzureOpenAI => {
+                return format!(
+                    "/openai/deployments/{}/{}?api-version=2025-01-01-preview",
+                    model_id, suffix
+                );
+            }
+            _ => "v1".to_string(),
+        };
+        let prefix = base_url_path_prefix
+            .map(|p| p.trim_matches('/'))
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string())
+            .unwrap_or(default_prefix);
+        format!("/{}/{}", prefix, suffix)
+    }
+}
+
+/// Text to speech request body, only the fields the gateway needs are typed
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SpeechRequest {
+    pub model: String,
+    pub input: String,
+    pub voice: Option<String>,
+    pub response_format: Option<String>,
+    pub speed: Option<f32>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
+}
+
+/// Usage reported by a transcription response
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscriptionUsage {
+    /// Seconds of audio that were transcribed
+    pub duration_seconds: Option<f64>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+impl TranscriptionUsage {
+    /// Extract usage from a json transcription response. Whisper models report
+    /// `usage: {type: "duration", seconds}` (or `duration` for verbose_json), the
+    /// gpt-4o transcribe models report token usage. Returns None for text formats.
+    pub fn from_response(body: &[u8]) -> Option<Self> {
+        let value: Value = serde_json::from_slice(body).ok()?;
+        let usage = value.get("usage");
+        let usage_field = |key: &str| usage.and_then(|u| u.get(key));
+
+        let duration_seconds = usage_field("seconds")
+            .and_then(Value::as_f64)
+            .or_else(|| value.get("duration").and_then(Value::as_f64));
+        let result = TranscriptionUsage {
+            duration_seconds,
+            input_tokens: usage_field("input_tokens").and_then(Value::as_u64),
+            output_tokens: usage_field("output_tokens").and_then(Value::as_u64),
+        };
+
+        if result == TranscriptionUsage::default() {
+            None
+        } else {
+            Some(result)
+        }
+    }
+}
+
+fn multipart_boundary(content_type: &str) -> Option<&str> {
+    Some(
+        content_type
+            .split(';')
+            .map(str::trim)
+            .find_map(|param| param.strip_prefix("boundary="))?
+            .trim_matches('"'),
+    )
+}
+
+fn is_model_part(headers: &[u8]) -> bool {
+    String::from_utf8_lossy(headers).lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("content-disposition:") && line.contains("name=\"model\"")
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+/// Extract the `model` form field from a multipart/form-data body
+pub fn multipart_model_field(content_type: &str, body: &[u8]) -> Option<String> {
+    let boundary = multipart_boundary(content_type)?;
+    let delimiter = format!("--{}", boundary);
+    let body = String::from_utf8_lossy(body);
+
+    body.split(delimiter.as_str()).find_map(|part| {
+        let (headers, value) = part.split_once("\r\n\r\n")?;
+        is_model_part(headers.as_bytes()).then(|| value.trim_end_matches("\r\n").trim().to_string())
+    })
+}
+
+/// Replace the value of the `model` form field of a multipart/form-data body, the other
+/// parts, audio included, are kept byte for byte. None when there is no model field.
+pub fn replace_multipart_model_field(
+    content_type: &str,
+    body: &[u8],
+    model: &str,
+) -> Option<Vec<u8>> {
+    let boundary = multipart_boundary(content_type)?;
+    let delimiter = format!("--{}", boundary);
+    let value_delimiter = format!("\r\n{}", delimiter);
+    let mut part_start = find(body, delimiter.as_bytes(), 0)? + delimiter.len();
+    loop {
+        let headers_end = find(body, b"\r\n\r\n", part_start)?;
+        let value_start = headers_end + 4;
+        let value_end = find(body, value_delimiter.as_bytes(), value_start)?;
+        if is_model_part(&body[part_start..headers_end]) {
+            let mut replaced = Vec::with_capacity(body.len() + model.len());
+            replaced.extend_from_slice(&body[..value_start]);
+            replaced.extend_from_slice(model.as_bytes());
+            replaced.extend_from_slice(&body[value_end..]);
+            return Some(replaced);
+        }
+        part_start = value_end + value_delimiter.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_api_from_endpoint() {
+        assert_eq!(
+            AudioApi::from_endpoint("/v1/audio/transcriptions"),
+            Some(AudioApi::Transcriptions)
+        );
+        assert_eq!(
+            AudioApi::from_endpoint("/v1/audio/speech?foo=bar"),
+            Some(AudioApi::Speech)
+        );
+        assert_eq!(AudioApi::from_endpoint("/v1/chat/completions"), None);
+    }
+
+    #[test]
+    fn test_audio_target_endpoint_for_provider() {
+        let api = AudioApi::Transcriptions;
+        assert_eq!(
+            api.target_endpoint_for_provider(&ProviderId::OpenAI, "whisper-1", None),
+            "/v1/audio/transcriptions"
+        );
+        assert_eq!(
+            api.target_endpoint_for_provider(&ProviderId::Groq, "whisper-large-v3", None),
+            "/openai/v1/audio/transcriptions"
+        );
+        assert_eq!(
+            api.target_endpoint_for_provider(&ProviderId::Custom, "nova-2", Some("/deepgram/v1")),
+            "/deepgram/v1/audio/transcriptions"
+        );
+        assert_eq!(
+            AudioApi::Speech.target_endpoint_for_provider(&ProviderId::AzureOpenAI, "tts", None),
+            "/openai/deployments/tts/audio/speech?api-version=2025-01-01-preview"
+        );
+    }
+
+    #[test]
+    fn test_transcription_usage() {
+        let usage = TranscriptionUsage::from_response(
+            br#"{"text":"hello","usage":{"type":"duration","seconds":12}}"#,
+        )
+        .unwrap();
+        assert_eq!(usage.duration_seconds, Some(12.0));
+
+        let usage = TranscriptionUsage::from_response(
+            br#"{"task":"transcribe","language":"english","duration":3.5,"text":"hi"}"#,
+        )
+        .unwrap();
+        assert_eq!(usage.duration_seconds, Some(3.5));
+
+        let usage = TranscriptionUsage::from_response(
+            br#"{"text":"hi","usage":{"type":"tokens","input_tokens":14,"output_tokens":4}}"#,
+        )
+        .unwrap();
+        assert_eq!(usage.input_tokens, Some(14));
+        assert_eq!(usage.duration_seconds, None);
+
+        assert_eq!(TranscriptionUsage::from_response(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_multipart_model_field() {
+        let body = "--abc123\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\nContent-Type: audio/wav\r\n\r\nRIFF....\r\n--abc123\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-large-v3\r\n--abc123--\r\n";
+        assert_eq!(
+            multipart_model_field("multipart/form-data; boundary=abc123", body.as_bytes()),
+            Some("whisper-large-v3".to_string())
+        );
+        assert_eq!(
+            multipart_model_field("application/json", body.as_bytes()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_replace_multipart_model_field() {
+        let content_type = "multipart/form-data; boundary=abc123";
+        let mut body = b"--abc123\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\nContent-Type: audio/wav\r\n\r\nRIFF\xff\xfe\r\n--abc123\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n".to_vec();
+        body.extend_from_slice(b"groq/whisper-large-v3\r\n--abc123--\r\n");
+
+        let replaced =
+            replace_multipart_model_field(content_type, &body, "whisper-large-v3").unwrap();
+        assert_eq!(
+            multipart_model_field(content_type, &replaced),
+            Some("whisper-large-v3".to_string())
+        );
+        assert!(replaced.starts_with(&body[..body.len() - 36]));
+        assert!(replaced.ends_with(b"\r\n\r\nwhisper-large-v3\r\n--abc123--\r\n"));
+
+        let no_model = b"--abc123\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\nRIFF\r\n--abc123--\r\n";
+        assert_eq!(
+            replace_multipart_model_field(content_type, no_model, "whisper-1"),
+            None
+        );
+    }
+}
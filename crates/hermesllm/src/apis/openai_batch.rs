@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+use crate::providers::id::ProviderId;
+use crate::{BATCHES_PATH, FILES_PATH, MESSAGE_BATCHES_PATH};
+
+// ============================================================================
+// OPENAI BATCH API ENUMERATION
+// ============================================================================
+
+/// Batch endpoints (`/v1/batches`, `/v1/files`, Anthropic's `/v1/messages/batches` and
+/// their sub resources). These are proxied without conversion, file uploads are streamed
+/// through as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchApi {
+    Batches,
+    Files,
+    MessageBatches,
+}
+
+impl BatchApi {
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            BatchApi::Batches => BATCHES_PATH,
+            BatchApi::Files => FILES_PATH,
+            BatchApi::MessageBatches => MESSAGE_BATCHES_PATH,
+        }
+    }
+
+    /// Create a BatchApi from a request path, e.g. `/v1/batches/batch_abc/cancel`
+    pub fn from_endpoint(endpoint: &str) -> Option<Self> {
+        let path = endpoint.split('?').next().unwrap_or_default();
+        [BatchApi::Batches, BatchApi::Files, BatchApi::MessageBatches]
+            .into_iter()
+            .find(|api| {
+                path.strip_prefix(api.endpoint())
+                    .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Upstream path for the provider, the sub resource and query string are kept.
+    /// Groq serves the batch API under `/openai/v1`, Azure under `/openai` with an api-version.
+    pub fn target_endpoint_for_provider(
+        &self,
+        provider_id: &ProviderId,
+        request_path: &str,
+        base_url_path_prefix: Option<&str>,
+    ) -> String {
+        let resource = request_path.strip_prefix("/v1/").unwrap_or(request_path);
+        let default_prefix = match provider_id {
+            ProviderId::Groq => "openai/v1",
+            ProviderId::AzureOpenAI => {
+                let separator = if resource.contains('?') { '&' } else { '?' };
+                return format!(
+                    "/openai/{}{}api-version=2025-01-01-preview",
+                    resource, separator
+                );
+            }
+            _ => "v1",
+        };
+        let prefix = base_url_path_prefix
+            .map(|p| p.trim_matches('/'))
+            .filter(|p| !p.is_empty())
+            .unwrap_or(default_prefix);
+        format!("/{}/{}", prefix, resource)
+    }
+}
+
+/// Progress counters of a batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BatchRequestCounts {
+    pub total: u64,
+    pub completed: u64,
+    pub failed: u64,
+}
+
+/// Batch object returned by create, retrieve and cancel, only the fields the gateway reports on
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchObject {
+    pub id: String,
+    pub object: String,
+    pub endpoint: Option<String>,
+    pub status: String,
+    pub input_file_id: Option<String>,
+    pub output_file_id: Option<String>,
+    pub error_file_id: Option<String>,
+    pub request_counts: Option<BatchRequestCounts>,
+}
+
+impl BatchObject {
+    /// Parse a batch object, returns None for other bodies (lists, files, errors)
+    pub fn from_response(body: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<BatchObject>(body)
+            .ok()
+            .filter(|batch| batch.object == "batch")
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "completed" | "failed" | "expired" | "cancelled"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_api_from_endpoint() {
+        assert_eq!(
+            BatchApi::from_endpoint("/v1/batches"),
+            Some(BatchApi::Batches)
+        );
+        assert_eq!(
+            BatchApi::from_endpoint("/v1/batches/batch_abc/cancel"),
+            Some(BatchApi::Batches)
+        );
+        assert_eq!(
+            BatchApi::from_endpoint("/v1/files/file-abc/content"),
+            Some(BatchApi::Files)
+        );
+        assert_eq!(
+            BatchApi::from_endpoint("/v1/files?purpose=batch"),
+            Some(BatchApi::Files)
+        );
+        assert_eq!(
+            BatchApi::from_endpoint("/v1/messages/batches/msgbatch_abc/results"),
+            Some(BatchApi::MessageBatches)
+        );
+        assert_eq!(BatchApi::from_endpoint("/v1/messages"), None);
+        assert_eq!(BatchApi::from_endpoint("/v1/filesystem"), None);
+        assert_eq!(BatchApi::from_endpoint("/v1/chat/completions"), None);
+    }
+
+    #[test]
+    fn test_batch_target_endpoint_for_provider() {
+        assert_eq!(
+            BatchApi::Batches.target_endpoint_for_provider(
+                &ProviderId::OpenAI,
+                "/v1/batches/batch_abc",
+                None
+            ),
+            "/v1/batches/batch_abc"
+        );
+        assert_eq!(
+            BatchApi::Files.target_endpoint_for_provider(&ProviderId::Groq, "/v1/files", None),
+            "/openai/v1/files"
+        );
+        assert_eq!(
+            BatchApi::Batches.target_endpoint_for_provider(
+                &ProviderId::AzureOpenAI,
+                "/v1/batches?limit=10",
+                None
+            ),
+            "/openai/batches?limit=10&api-version=2025-01-01-preview"
+        );
+        assert_eq!(
+            BatchApi::MessageBatches.target_endpoint_for_provider(
+                &ProviderId::Anthropic,
+                "/v1/messages/batches/msgbatch_abc/cancel",
+                None
+            ),
+            "/v1/messages/batches/msgbatch_abc/cancel"
+        );
+    }
+
+    #[test]
+    fn test_batch_object_from_response() {
+        let body = br#"{
+            "id": "batch_abc123",
+            "object": "batch",
+            "endpoint": "/v1/chat/completions",
+            "status": "completed",
+            "input_file_id": "file-abc123",
+            "output_file_id": "file-cvaTdG",
+            "completion_window": "24h",
+            "request_counts": {"total": 100, "completed": 95, "failed": 5}
+        }"#;
+        let batch = BatchObject::from_response(body).unwrap();
+        assert!(batch.is_terminal());
+        assert_eq!(batch.request_counts.unwrap().completed, 95);
+
+        assert_eq!(
+            BatchObject::from_response(br#"{"object":"list","data":[]}"#),
+            None
+        );
+    }
+}
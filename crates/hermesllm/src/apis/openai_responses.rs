@@ -123,18 +123,26 @@ pub enum InputParam {
 pub enum InputItem {
     /// Input message (role + content)
     Message(InputMessage),
-    /// Item reference
-    ItemReference {
+    /// Function call made by the model in an earlier turn
+    FunctionCall {
         #[serde(rename = "type")]
         item_type: String,
-        id: String,
+        call_id: String,
+        name: String,
+        arguments: String,
     },
-    /// Function call output
+    /// Function call output, text or an array of text, image and file content
     FunctionCallOutput {
         #[serde(rename = "type")]
         item_type: String,
         call_id: String,
-        output: String,
+        output: MessageContent,
+    },
+    /// Item reference
+    ItemReference {
+        #[serde(rename = "type")]
+        item_type: String,
+        id: String,
     },
 }
 
@@ -333,6 +341,12 @@ pub enum ToolChoice {
         tool_type: String,
         function: NamedFunction,
     },
+    /// Function tool choice in the flat Responses API shape
+    Function {
+        #[serde(rename = "type")]
+        tool_type: String,
+        name: String,
+    },
 }
 
 /// Named function for tool choice
@@ -1194,7 +1208,9 @@ impl ProviderRequest for ResponsesAPIRequest {
                             });
                         }
                         // Skip other input item types for now
-                        InputItem::ItemReference { .. } | InputItem::FunctionCallOutput { .. } => {
+                        InputItem::ItemReference { .. }
+                        | InputItem::FunctionCall { .. }
+                        | InputItem::FunctionCallOutput { .. } => {
                             // These are not yet supported in agent framework
                         }
                     }
@@ -0,0 +1,144 @@
+//! Strict or lenient parsing of enum values providers ship before hermesllm knows them
+//!
+//! A new finish reason or content block type would otherwise fail the whole response.
+//! In lenient mode such values are kept in an `Other` variant, passed through unchanged
+//! to clients of the same api and mapped to the closest known value when translated. In
+//! strict mode they are rejected as before.
+
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// Unknown enum values fail parsing
+    Strict,
+    /// Unknown enum values are kept in an `Other` variant
+    #[default]
+    Lenient,
+}
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Sets the parse mode of the process
+pub fn set_parse_mode(mode: ParseMode) {
+    STRICT.store(mode == ParseMode::Strict, Ordering::Relaxed);
+}
+
+pub fn parse_mode() -> ParseMode {
+    if STRICT.load(Ordering::Relaxed) {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    }
+}
+
+/// Accepts an unknown enum value in lenient mode, rejects it in strict mode
+pub(crate) fn accept_unknown<E: de::Error>(
+    value: &str,
+    expected: &'static [&'static str],
+) -> Result<(), E> {
+    match parse_mode() {
+        ParseMode::Lenient => Ok(()),
+        ParseMode::Strict => Err(E::unknown_variant(value, expected)),
+    }
+}
+
+/// Deserializes a content block or delta of a type that didn't match any known variant
+pub(crate) fn unknown_block<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    let block = Value::deserialize(deserializer)?;
+    let block_type = block
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    accept_unknown::<D::Error>(block_type, &[])?;
+    Ok(block)
+}
+
+/// Implements serde for a string enum with an `Other(String)` variant holding values
+/// that aren't listed
+macro_rules! lenient_string_enum {
+    ($name:ident { $($variant:ident => $value:literal),+ $(,)? }) => {
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $value,)+
+                    $name::Other(value) => value,
+                }
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                const VARIANTS: &[&str] = &[$($value),+];
+                let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+                Ok(match value.as_str() {
+                    $($value => $name::$variant,)+
+                    _ => {
+                        $crate::apis::parse_mode::accept_unknown::<D::Error>(&value, VARIANTS)?;
+                        $name::Other(value)
+                    }
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use lenient_string_enum;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::anthropic::{MessagesContentBlock, MessagesContentDelta, MessagesStopReason};
+    use crate::apis::openai::FinishReason;
+    use serde_json::json;
+
+    // The mode is process wide, so strict and lenient parsing are checked in one test
+    #[test]
+    fn test_unknown_values_in_both_modes() {
+        let finish_reason: FinishReason = serde_json::from_value(json!("tool_calls")).unwrap();
+        assert_eq!(finish_reason, FinishReason::ToolCalls);
+        let finish_reason: FinishReason = serde_json::from_value(json!("max_output")).unwrap();
+        assert_eq!(finish_reason, FinishReason::Other("max_output".to_string()));
+        assert_eq!(serde_json::to_value(&finish_reason).unwrap(), "max_output");
+
+        let stop_reason: MessagesStopReason =
+            serde_json::from_value(json!("model_context_window_exceeded")).unwrap();
+        assert_eq!(
+            stop_reason,
+            MessagesStopReason::Other("model_context_window_exceeded".to_string())
+        );
+
+        let block = json!({"type": "citation_card", "title": "Paris"});
+        let parsed: MessagesContentBlock = serde_json::from_value(block.clone()).unwrap();
+        assert!(matches!(parsed, MessagesContentBlock::Other(_)));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), block);
+        let text: MessagesContentBlock =
+            serde_json::from_value(json!({"type": "text", "text": "hi"})).unwrap();
+        assert!(matches!(text, MessagesContentBlock::Text { .. }));
+        let delta: MessagesContentDelta =
+            serde_json::from_value(json!({"type": "citations_delta", "citation": {}})).unwrap();
+        assert!(matches!(delta, MessagesContentDelta::Other(_)));
+
+        set_parse_mode(ParseMode::Strict);
+        let strict_finish_reason = serde_json::from_value::<FinishReason>(json!("max_output"));
+        let strict_block = serde_json::from_value::<MessagesContentBlock>(block);
+        let known = serde_json::from_value::<FinishReason>(json!("stop"));
+        set_parse_mode(ParseMode::Lenient);
+
+        assert!(strict_finish_reason
+            .unwrap_err()
+            .to_string()
+            .contains("unknown variant `max_output`"));
+        assert!(strict_block.is_err());
+        assert_eq!(known.unwrap(), FinishReason::Stop);
+    }
+}
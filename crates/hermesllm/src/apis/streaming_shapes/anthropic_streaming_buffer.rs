@@ -1,4 +1,4 @@
-use crate::apis::anthropic::MessagesStreamEvent;
+use crate::apis::anthropic::{MessagesContentBlock, MessagesContentDelta, MessagesStreamEvent};
 use crate::apis::streaming_shapes::sse::{SseEvent, SseStreamBufferTrait};
 use crate::providers::streaming_response::ProviderStreamResponseType;
 use std::collections::HashSet;
@@ -29,6 +29,16 @@ pub struct AnthropicMessagesStreamBuffer {
 
     /// Model name to use when generating message_start events
     model: Option<String>,
+
+    /// Track if the open content block is an injected thinking block (from reasoning_content)
+    thinking_block_open: bool,
+
+    /// Shift applied to upstream block indices once a thinking block has been closed,
+    /// since upstream numbers its text/tool blocks without counting the thinking block
+    block_index_offset: u32,
+
+    /// Index of the most recently started content block
+    current_block_index: u32,
 }
 
 impl Default for AnthropicMessagesStreamBuffer {
@@ -46,6 +56,9 @@ impl AnthropicMessagesStreamBuffer {
             needs_content_block_stop: false,
             seen_message_delta: false,
             model: None,
+            thinking_block_open: false,
+            block_index_offset: 0,
+            current_block_index: 0,
         }
     }
 
@@ -60,13 +73,22 @@ impl AnthropicMessagesStreamBuffer {
     }
 
     /// Helper to create and format a ContentBlockStart SSE event
-    fn create_content_block_start_event() -> SseEvent {
-        let content_block_start = MessagesStreamEvent::ContentBlockStart {
-            index: 0,
-            content_block: crate::apis::anthropic::MessagesContentBlock::Text {
+    fn create_content_block_start_event(index: u32, thinking: bool) -> SseEvent {
+        let content_block = if thinking {
+            MessagesContentBlock::Thinking {
+                thinking: String::new(),
+                signature: None,
+                cache_control: None,
+            }
+        } else {
+            MessagesContentBlock::Text {
                 text: String::new(),
                 cache_control: None,
-            },
+            }
+        };
+        let content_block_start = MessagesStreamEvent::ContentBlockStart {
+            index,
+            content_block,
         };
         let sse_string: String = content_block_start.into();
 
@@ -110,8 +132,8 @@ impl AnthropicMessagesStreamBuffer {
     }
 
     /// Helper to create and format a ContentBlockStop SSE event
-    fn create_content_block_stop_event() -> SseEvent {
-        let content_block_stop = MessagesStreamEvent::ContentBlockStop { index: 0 };
+    fn create_content_block_stop_event(index: u32) -> SseEvent {
+        let content_block_stop = MessagesStreamEvent::ContentBlockStop { index };
         let sse_string: String = content_block_stop.into();
 
         SseEvent {
@@ -122,6 +144,51 @@ impl AnthropicMessagesStreamBuffer {
             provider_stream_response: None,
         }
     }
+
+    /// Close an open thinking block before upstream moves on to text or tool blocks
+    fn close_thinking_block(&mut self) {
+        if self.thinking_block_open {
+            self.buffered_events.push(
+                AnthropicMessagesStreamBuffer::create_content_block_stop_event(
+                    self.current_block_index,
+                ),
+            );
+            self.thinking_block_open = false;
+            self.block_index_offset += 1;
+        }
+    }
+
+    /// Re-number a content block event by the thinking block offset
+    fn shift_block_index(&self, event: SseEvent) -> SseEvent {
+        if self.block_index_offset == 0 {
+            return event;
+        }
+        let shifted = match &event.provider_stream_response {
+            Some(ProviderStreamResponseType::MessagesStreamEvent(evt)) => match evt.clone() {
+                MessagesStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                } => MessagesStreamEvent::ContentBlockStart {
+                    index: index + self.block_index_offset,
+                    content_block,
+                },
+                MessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                    MessagesStreamEvent::ContentBlockDelta {
+                        index: index + self.block_index_offset,
+                        delta,
+                    }
+                }
+                MessagesStreamEvent::ContentBlockStop { index } => {
+                    MessagesStreamEvent::ContentBlockStop {
+                        index: index + self.block_index_offset,
+                    }
+                }
+                _ => return event,
+            },
+            _ => return event,
+        };
+        SseEvent::from_provider_response(ProviderStreamResponseType::MessagesStreamEvent(shifted))
+    }
 }
 
 impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
@@ -156,7 +223,7 @@ impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
                         self.message_started = true;
                     }
                     MessagesStreamEvent::ContentBlockStart { index, .. } => {
-                        let index = *index as i32;
+                        let upstream_index = *index;
                         // Inject message_start if needed
                         if !self.message_started {
                             let model = self.model.as_deref().unwrap_or("unknown");
@@ -166,13 +233,20 @@ impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
                             self.message_started = true;
                         }
 
+                        self.close_thinking_block();
+                        let index = upstream_index + self.block_index_offset;
+                        let event = self.shift_block_index(event);
+
                         // Add the content_block_start event (from tool calls or other sources)
                         self.buffered_events.push(event);
-                        self.set_content_block_start_sent(index);
+                        self.set_content_block_start_sent(index as i32);
+                        self.current_block_index = index;
                         self.needs_content_block_stop = true;
                     }
-                    MessagesStreamEvent::ContentBlockDelta { index, .. } => {
-                        let index = *index as i32;
+                    MessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                        let upstream_index = *index;
+                        let is_thinking =
+                            matches!(delta, MessagesContentDelta::ThinkingDelta { .. });
                         // Inject message_start if needed
                         if !self.message_started {
                             let model = self.model.as_deref().unwrap_or("unknown");
@@ -182,13 +256,24 @@ impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
                             self.message_started = true;
                         }
 
+                        if !is_thinking {
+                            self.close_thinking_block();
+                        }
+                        let index = upstream_index + self.block_index_offset;
+                        let event = self.shift_block_index(event);
+
                         // Check if ContentBlockStart was sent for this index
-                        if !self.has_content_block_start_been_sent(index) {
+                        if !self.has_content_block_start_been_sent(index as i32) {
                             // Inject ContentBlockStart before delta
                             let content_block_start =
-                                AnthropicMessagesStreamBuffer::create_content_block_start_event();
+                                AnthropicMessagesStreamBuffer::create_content_block_start_event(
+                                    index,
+                                    is_thinking,
+                                );
                             self.buffered_events.push(content_block_start);
-                            self.set_content_block_start_sent(index);
+                            self.set_content_block_start_sent(index as i32);
+                            self.current_block_index = index;
+                            self.thinking_block_open = is_thinking;
                             self.needs_content_block_stop = true;
                         }
 
@@ -199,7 +284,9 @@ impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
                         // Inject ContentBlockStop before message_delta
                         if self.needs_content_block_stop {
                             let content_block_stop =
-                                AnthropicMessagesStreamBuffer::create_content_block_stop_event();
+                                AnthropicMessagesStreamBuffer::create_content_block_stop_event(
+                                    self.current_block_index,
+                                );
                             self.buffered_events.push(content_block_stop);
                             self.needs_content_block_stop = false;
                         }
@@ -232,6 +319,8 @@ impl SseStreamBufferTrait for AnthropicMessagesStreamBuffer {
                         // ContentBlockStop received from upstream (e.g., Bedrock)
                         // Clear the flag so we don't inject another one
                         self.needs_content_block_stop = false;
+                        self.thinking_block_open = false;
+                        let event = self.shift_block_index(event);
                         self.buffered_events.push(event);
                     }
                     MessagesStreamEvent::MessageStop => {
@@ -615,4 +704,54 @@ data: [DONE]"#;
         println!("✓ Stop reason: tool_use");
         println!("✓ Proper Anthropic tool_use protocol\n");
     }
+
+    #[test]
+    fn test_deepseek_reasoning_content_to_anthropic_thinking() {
+        let raw_input = r#"data: {"id":"chatcmpl-1","object":"chat.completion.chunk","created":1234567890,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"role":"assistant","content":null,"reasoning_content":"Let me think"},"finish_reason":null}]}
+
+data: {"id":"chatcmpl-1","object":"chat.completion.chunk","created":1234567890,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"content":"Answer"},"finish_reason":null}]}
+
+data: {"id":"chatcmpl-1","object":"chat.completion.chunk","created":1234567890,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}
+
+data: [DONE]"#;
+
+        let client_api = SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages);
+        let upstream_api = SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+
+        let stream_iter = SseStreamIter::try_from(raw_input.as_bytes()).unwrap();
+        let mut buffer = AnthropicMessagesStreamBuffer::new();
+        for raw_event in stream_iter {
+            let transformed_event =
+                SseEvent::try_from((raw_event, &client_api, &upstream_api)).unwrap();
+            buffer.add_transformed_event(transformed_event);
+        }
+
+        let output_bytes = buffer.to_bytes();
+        let output = String::from_utf8_lossy(&output_bytes);
+
+        // Thinking block at index 0 is closed before the text block opens at index 1
+        let thinking_start = output
+            .find(r#""index":0,"content_block":{"type":"thinking""#)
+            .expect("thinking block start");
+        let thinking_delta = output
+            .find(r#""thinking":"Let me think""#)
+            .expect("thinking delta");
+        let thinking_stop = output
+            .find(r#"{"type":"content_block_stop","index":0}"#)
+            .expect("thinking block stop");
+        let text_start = output
+            .find(r#""index":1,"content_block":{"type":"text""#)
+            .expect("text block start");
+        let text_delta = output.find(r#""text":"Answer""#).expect("text delta");
+        let text_stop = output
+            .find(r#"{"type":"content_block_stop","index":1}"#)
+            .expect("text block stop");
+
+        assert!(thinking_start < thinking_delta);
+        assert!(thinking_delta < thinking_stop);
+        assert!(thinking_stop < text_start);
+        assert!(text_start < text_delta);
+        assert!(text_delta < text_stop);
+        assert!(output.contains("event: message_stop"));
+    }
 }
@@ -5,6 +5,7 @@ use crate::apis::streaming_shapes::responses_api_streaming_buffer::ResponsesAPIS
 use crate::providers::streaming_response::ProviderStreamResponse;
 use crate::providers::streaming_response::ProviderStreamResponseType;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
@@ -248,16 +249,41 @@ where
     }
 }
 
+/// Lines of SSE bytes, borrowed from the bytes. Lines are found with memchr and only
+/// lines that aren't valid UTF-8 are copied, with the invalid bytes replaced.
+pub struct SseLines<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> SseLines<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { remaining: bytes }
+    }
+}
+
+impl<'a> Iterator for SseLines<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let (line, rest) = match memchr::memchr(b'\n', self.remaining) {
+            Some(end) => (&self.remaining[..end], &self.remaining[end + 1..]),
+            None => (self.remaining, &self.remaining[self.remaining.len()..]),
+        };
+        self.remaining = rest;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Some(String::from_utf8_lossy(line))
+    }
+}
+
 // TryFrom implementation to parse bytes into SseStreamIter
-// Handles both text-based SSE and binary AWS Event Stream formats
-impl TryFrom<&[u8]> for SseStreamIter<std::vec::IntoIter<String>> {
+impl<'a> TryFrom<&'a [u8]> for SseStreamIter<SseLines<'a>> {
     type Error = Box<dyn std::error::Error + Send + Sync>;
 
-    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        // Parse as text-based SSE format
-        let s = std::str::from_utf8(bytes)?;
-        let lines: Vec<String> = s.lines().map(|line| line.to_string()).collect();
-        Ok(SseStreamIter::new(lines.into_iter()))
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Ok(SseStreamIter::new(SseLines::new(bytes)))
     }
 }
 
@@ -294,3 +320,36 @@ where
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_lines() {
+        let bytes =
+            b"event: message_start\r\ndata: {\"a\":1}\n\ndata: caf\xc3\xa9 \xff\ndata: [DONE]";
+        let lines: Vec<Cow<str>> = SseLines::new(bytes).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "event: message_start",
+                "data: {\"a\":1}",
+                "",
+                "data: caf\u{e9} \u{fffd}",
+                "data: [DONE]"
+            ]
+        );
+        assert!(matches!(lines[1], Cow::Borrowed(_)));
+        assert!(matches!(lines[3], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_stream_iter_over_bytes() {
+        let bytes =
+            b"data: {\"type\": \"ping\"}\n\ndata: {\"a\":1}\n\ndata: [DONE]\n\ndata: {\"b\":2}\n\n";
+        let events: Vec<SseEvent> = SseStreamIter::try_from(&bytes[..]).unwrap().collect();
+        let data: Vec<_> = events.iter().map(|event| event.data.as_deref()).collect();
+        assert_eq!(data, vec![Some("{\"a\":1}"), Some("[DONE]")]);
+    }
+}
@@ -1,12 +1,20 @@
+use crate::apis::gemini::GenerateContentResponse;
+use crate::apis::openai::{ChatCompletionsStreamResponse, OpenAIApi};
+use crate::apis::openai_responses::ResponsesAPIStreamEvent;
 use crate::apis::streaming_shapes::sse::{SseEvent, SseStreamIter};
 use crate::clients::endpoints::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
+use crate::transforms::response_streaming::to_openai_streaming::split_finishing_chunk;
+use memchr::memmem;
 
-/// Stateful processor for handling SSE chunks that may contain incomplete events.
+/// Stateful, incremental SSE decoder for chunks that may split events at arbitrary bytes.
 ///
-/// This processor buffers incomplete SSE event bytes when transformation fails
-/// (e.g., due to incomplete JSON) and prepends them to the next chunk for retry.
+/// Upstream bytes are appended to a carry-over buffer and only the prefix that ends on an
+/// event boundary (a blank line, `\n\n` or `\r\n\r\n`) is parsed and transformed. The
+/// remainder is kept until the next chunk completes it, so events split across
+/// `on_http_response_body` calls (including multi-byte UTF-8 sequences) are never parsed
+/// as partial lines.
 pub struct SseChunkProcessor {
-    /// Buffered bytes from incomplete SSE events across chunks
+    /// Bytes received after the last complete event boundary
     incomplete_event_buffer: Vec<u8>,
 }
 
@@ -25,8 +33,8 @@ impl SseChunkProcessor {
 
     /// Process a chunk of SSE data, handling incomplete events across chunk boundaries.
     ///
-    /// Returns successfully transformed events. Incomplete events are buffered internally
-    /// and will be retried when more data arrives in the next chunk.
+    /// Returns successfully transformed events. Bytes after the last event boundary are
+    /// buffered internally and processed when more data arrives in the next chunk.
     ///
     /// # Arguments
     /// * `chunk` - Raw bytes from upstream SSE stream
@@ -42,47 +50,34 @@ impl SseChunkProcessor {
         client_api: &SupportedAPIsFromClient,
         upstream_api: &SupportedUpstreamAPIs,
     ) -> Result<Vec<SseEvent>, String> {
-        // Combine buffered incomplete event with new chunk
-        let mut combined_data = std::mem::take(&mut self.incomplete_event_buffer);
-        combined_data.extend_from_slice(chunk);
-
-        // Parse using SseStreamIter
-        let sse_iter = match SseStreamIter::try_from(combined_data.as_slice()) {
-            Ok(iter) => iter,
-            Err(e) => return Err(format!("Failed to create SSE iterator: {}", e)),
+        self.incomplete_event_buffer.extend_from_slice(chunk);
+
+        let boundary = match find_last_event_boundary(&self.incomplete_event_buffer) {
+            Some(boundary) => boundary,
+            None => return Ok(Vec::new()),
         };
 
-        let mut transformed_events = Vec::new();
+        // Keep everything after the boundary for the next chunk
+        let remainder = self.incomplete_event_buffer.split_off(boundary);
+        let complete_events = std::mem::replace(&mut self.incomplete_event_buffer, remainder);
 
-        // Process each parsed SSE event
-        for sse_event in sse_iter {
-            // Try to transform the event (this is where incomplete JSON fails)
-            match SseEvent::try_from((sse_event.clone(), client_api, upstream_api)) {
-                Ok(transformed) => {
-                    // Successfully transformed - add to results
-                    transformed_events.push(transformed);
-                }
-                Err(e) => {
-                    // Check if this is incomplete JSON (EOF while parsing) vs other errors
-                    let error_str = e.to_string().to_lowercase();
-                    let is_incomplete_json = error_str.contains("eof while parsing")
-                        || error_str.contains("unexpected end of json")
-                        || error_str.contains("unexpected eof");
-
-                    if is_incomplete_json {
-                        // Incomplete JSON - buffer for retry with next chunk
-                        self.incomplete_event_buffer = sse_event.raw_line.as_bytes().to_vec();
-                        break;
-                    } else {
-                        // Other error (unsupported event type, validation error, etc.)
-                        // Skip this event and continue processing others
-                        continue;
-                    }
-                }
-            }
-        }
+        transform_events(&complete_events, client_api, upstream_api)
+    }
 
-        Ok(transformed_events)
+    /// Process whatever is left in the buffer once the upstream stream has ended.
+    ///
+    /// Some providers do not terminate the last event with a blank line; this makes sure
+    /// that trailing event is not dropped.
+    pub fn flush(
+        &mut self,
+        client_api: &SupportedAPIsFromClient,
+        upstream_api: &SupportedUpstreamAPIs,
+    ) -> Result<Vec<SseEvent>, String> {
+        let remaining = std::mem::take(&mut self.incomplete_event_buffer);
+        if remaining.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(Vec::new());
+        }
+        transform_events(&remaining, client_api, upstream_api)
     }
 
     /// Check if there are buffered incomplete bytes
@@ -96,6 +91,128 @@ impl SseChunkProcessor {
     }
 }
 
+/// Returns the index just past the last SSE event boundary (`\n\n` or `\r\n\r\n`)
+fn find_last_event_boundary(buffer: &[u8]) -> Option<usize> {
+    let lf = memmem::rfind(buffer, b"\n\n").map(|i| i + 2);
+    let crlf = memmem::rfind(buffer, b"\n\r\n").map(|i| i + 3);
+    lf.max(crlf)
+}
+
+/// Parse complete SSE events and transform them into the client API format
+fn transform_events(
+    data: &[u8],
+    client_api: &SupportedAPIsFromClient,
+    upstream_api: &SupportedUpstreamAPIs,
+) -> Result<Vec<SseEvent>, String> {
+    let sse_iter = match SseStreamIter::try_from(data) {
+        Ok(iter) => iter,
+        Err(e) => return Err(format!("Failed to create SSE iterator: {}", e)),
+    };
+
+    // Gemini streams, and Responses API streams of other client APIs, are rewritten as
+    // chat completions chunks first, so every client API reuses the chat completions
+    // translations
+    let chat_upstream = SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+    let responses_to_chat = matches!(upstream_api, SupportedUpstreamAPIs::OpenAIResponsesAPI(_))
+        && !matches!(client_api, SupportedAPIsFromClient::OpenAIResponsesAPI(_));
+    let events: Vec<SseEvent> = match upstream_api {
+        SupportedUpstreamAPIs::GeminiGenerateContent(_) => sse_iter
+            .flat_map(|event| gemini_chat_events(&event))
+            .collect(),
+        SupportedUpstreamAPIs::OpenAIResponsesAPI(_) if responses_to_chat => sse_iter
+            .flat_map(|event| responses_chat_events(&event))
+            .collect(),
+        _ => sse_iter.collect(),
+    };
+    let upstream_api = match upstream_api {
+        SupportedUpstreamAPIs::GeminiGenerateContent(_) => &chat_upstream,
+        SupportedUpstreamAPIs::OpenAIResponsesAPI(_) if responses_to_chat => &chat_upstream,
+        _ => upstream_api,
+    };
+
+    let mut transformed_events = Vec::new();
+    for sse_event in events {
+        // Events that fail to transform (unsupported event type, validation error, etc.)
+        // are skipped so they don't block the events that follow
+        if let Ok(transformed) = SseEvent::try_from((sse_event, client_api, upstream_api)) {
+            transformed_events.push(transformed);
+        }
+    }
+
+    Ok(transformed_events)
+}
+
+/// Rewrites a Gemini `streamGenerateContent` event as chat completions chunk events.
+///
+/// Gemini sends the last delta together with the finish reason and never sends `[DONE]`,
+/// so the finishing chunk is split off and followed by a synthesized `[DONE]`.
+fn gemini_chat_events(event: &SseEvent) -> Vec<SseEvent> {
+    let Some(chunk) = event
+        .data
+        .as_deref()
+        .and_then(|data| serde_json::from_str::<GenerateContentResponse>(data).ok())
+    else {
+        return Vec::new();
+    };
+    let Ok(chat_chunk) = ChatCompletionsStreamResponse::try_from(chunk) else {
+        return Vec::new();
+    };
+    let finished = chat_chunk
+        .choices
+        .iter()
+        .any(|choice| choice.finish_reason.is_some());
+
+    let mut events: Vec<SseEvent> = split_finishing_chunk(chat_chunk)
+        .iter()
+        .filter_map(|chunk| serde_json::to_string(chunk).ok())
+        .map(data_event)
+        .collect();
+    if finished {
+        events.push(data_event("[DONE]".to_string()));
+    }
+    events
+}
+
+/// Rewrites a Responses API event as a chat completions chunk event. Events without a
+/// chat completions counterpart are dropped, and as the stream ends with
+/// `response.completed` rather than `[DONE]`, `[DONE]` is synthesized after it.
+fn responses_chat_events(event: &SseEvent) -> Vec<SseEvent> {
+    let Some(responses_event) = event
+        .data
+        .as_deref()
+        .and_then(|data| serde_json::from_str::<ResponsesAPIStreamEvent>(data).ok())
+    else {
+        return Vec::new();
+    };
+    let completed = matches!(
+        responses_event,
+        ResponsesAPIStreamEvent::ResponseCompleted { .. }
+    );
+    let Ok(chat_chunk) = ChatCompletionsStreamResponse::try_from(responses_event) else {
+        return Vec::new();
+    };
+    let mut events: Vec<SseEvent> = serde_json::to_string(&chat_chunk)
+        .ok()
+        .map(data_event)
+        .into_iter()
+        .collect();
+    if completed {
+        events.push(data_event("[DONE]".to_string()));
+    }
+    events
+}
+
+fn data_event(data: String) -> SseEvent {
+    let line = format!("data: {}\n\n", data);
+    SseEvent {
+        data: Some(data),
+        event: None,
+        raw_line: line.clone(),
+        sse_transformed_lines: line,
+        provider_stream_response: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,6 +288,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_event_split_at_every_byte() {
+        let client_api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let upstream_api = SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+
+        let stream = "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"héllo\"},\"finish_reason\":null}]}\n\ndata: {\"id\":\"chatcmpl-124\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"wörld\"},\"finish_reason\":null}]}\r\n\r\ndata: [DONE]\n\n";
+
+        // Feed the stream one byte at a time, which also splits multi-byte UTF-8 characters
+        let mut processor = SseChunkProcessor::new();
+        let mut events = Vec::new();
+        for byte in stream.as_bytes() {
+            events.extend(
+                processor
+                    .process_chunk(std::slice::from_ref(byte), &client_api, &upstream_api)
+                    .unwrap(),
+            );
+        }
+
+        assert_eq!(events.len(), 3);
+        assert!(events[2].is_done());
+        let contents: Vec<_> = events[..2]
+            .iter()
+            .map(|e| {
+                e.provider_response()
+                    .unwrap()
+                    .content_delta()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(contents, vec!["héllo", "wörld"]);
+        assert!(!processor.has_buffered_data());
+    }
+
+    #[test]
+    fn test_flush_processes_unterminated_event() {
+        let mut processor = SseChunkProcessor::new();
+        let client_api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let upstream_api = SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+
+        let chunk = b"data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}";
+
+        let events = processor
+            .process_chunk(chunk, &client_api, &upstream_api)
+            .unwrap();
+        assert!(events.is_empty());
+        assert!(processor.has_buffered_data());
+
+        let flushed = processor.flush(&client_api, &upstream_api).unwrap();
+        assert_eq!(flushed.len(), 1);
+        assert!(!processor.has_buffered_data());
+    }
+
     #[test]
     fn test_anthropic_signature_delta_from_production_logs() {
         use crate::apis::anthropic::AnthropicApi;
@@ -295,4 +465,85 @@ data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text
             }
         }
     }
+
+    #[test]
+    fn test_gemini_finishing_chunk_is_split_and_terminated() {
+        use crate::apis::gemini::GeminiApi;
+
+        let mut processor = SseChunkProcessor::new();
+        let client_api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let upstream_api =
+            SupportedUpstreamAPIs::GeminiGenerateContent(GeminiApi::StreamGenerateContent);
+
+        let chunk = br#"data: {"candidates":[{"content":{"role":"model","parts":[{"text":"Hi"}]},"index":0}],"modelVersion":"gemini-2.5-flash","responseId":"r1"}
+
+data: {"candidates":[{"content":{"role":"model","parts":[{"text":" there"}]},"finishReason":"STOP","index":0}],"usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":2,"totalTokenCount":5},"modelVersion":"gemini-2.5-flash","responseId":"r1"}
+
+"#;
+
+        let events = processor
+            .process_chunk(chunk, &client_api, &upstream_api)
+            .unwrap();
+
+        // Delta, delta, finish with usage, [DONE]
+        assert_eq!(events.len(), 4);
+        let last_chunk: ChatCompletionsStreamResponse =
+            serde_json::from_str(events[2].data.as_ref().unwrap()).unwrap();
+        assert!(last_chunk.choices[0].delta.content.is_none());
+        assert!(last_chunk.choices[0].finish_reason.is_some());
+        assert_eq!(last_chunk.usage.unwrap().total_tokens, 5);
+        assert!(events[3].is_done());
+    }
+
+    #[test]
+    fn test_responses_stream_to_chat_completions() {
+        let mut processor = SseChunkProcessor::new();
+        let client_api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let upstream_api = SupportedUpstreamAPIs::OpenAIResponsesAPI(OpenAIApi::Responses);
+
+        let chunk = br#"event: response.output_text.delta
+data: {"type":"response.output_text.delta","item_id":"msg_1","output_index":0,"content_index":0,"delta":"Checking","logprobs":[],"sequence_number":3}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":1,"item":{"type":"function_call","id":"fc_1","status":"in_progress","call_id":"call_1","name":"get_weather","arguments":""},"sequence_number":4}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","output_index":1,"item_id":"fc_1","delta":"{\"city\":\"Paris\"}","sequence_number":5}
+
+event: response.function_call_arguments.done
+data: {"type":"response.function_call_arguments.done","output_index":1,"item_id":"fc_1","arguments":"{\"city\":\"Paris\"}","sequence_number":6}
+
+event: response.completed
+data: {"type":"response.completed","response":{"id":"resp_1","object":"response","created_at":1,"status":"completed","model":"gpt-5-codex","output":[{"type":"function_call","id":"fc_1","status":"completed","call_id":"call_1","name":"get_weather","arguments":"{\"city\":\"Paris\"}"}],"usage":{"input_tokens":10,"output_tokens":5,"total_tokens":15},"parallel_tool_calls":true,"tools":[],"tool_choice":"auto","temperature":1.0,"top_p":1.0,"metadata":{}},"sequence_number":7}
+
+"#;
+
+        let events = processor
+            .process_chunk(chunk, &client_api, &upstream_api)
+            .unwrap();
+
+        // Text delta, tool call start, arguments delta, finish with usage, [DONE]
+        assert_eq!(events.len(), 5);
+        let chunks: Vec<ChatCompletionsStreamResponse> = events[..4]
+            .iter()
+            .map(|event| serde_json::from_str(event.data.as_ref().unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            chunks[0].choices[0].delta.content.as_deref(),
+            Some("Checking")
+        );
+        let tool_call = &chunks[1].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(tool_call.id.as_deref(), Some("call_1"));
+        let arguments = &chunks[2].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(
+            arguments.function.as_ref().unwrap().arguments.as_deref(),
+            Some("{\"city\":\"Paris\"}")
+        );
+        assert_eq!(
+            chunks[3].choices[0].finish_reason,
+            Some(crate::apis::openai::FinishReason::ToolCalls)
+        );
+        assert_eq!(chunks[3].usage.as_ref().unwrap().total_tokens, 15);
+        assert!(events[4].is_done());
+    }
 }
@@ -1,4 +1,5 @@
-use crate::apis::{AmazonBedrockApi, AnthropicApi, ApiDefinition, OpenAIApi};
+use crate::apis::gemini::{gemini_model_name, vertex_model_path};
+use crate::apis::{AmazonBedrockApi, AnthropicApi, ApiDefinition, GeminiApi, OpenAIApi};
 use crate::ProviderId;
 use std::fmt;
 
@@ -17,6 +18,7 @@ pub enum SupportedUpstreamAPIs {
     AmazonBedrockConverse(AmazonBedrockApi),
     AmazonBedrockConverseStream(AmazonBedrockApi),
     OpenAIResponsesAPI(OpenAIApi),
+    GeminiGenerateContent(GeminiApi),
 }
 
 impl fmt::Display for SupportedAPIsFromClient {
@@ -53,6 +55,9 @@ impl fmt::Display for SupportedUpstreamAPIs {
             SupportedUpstreamAPIs::OpenAIResponsesAPI(api) => {
                 write!(f, "OpenAI Responses ({})", api.endpoint())
             }
+            SupportedUpstreamAPIs::GeminiGenerateContent(api) => {
+                write!(f, "Gemini ({})", api.endpoint())
+            }
         }
     }
 }
@@ -165,6 +170,50 @@ impl SupportedAPIsFromClient {
             }
         };
 
+        // The native Gemini and Vertex AI APIs take the model in the path, whatever the
+        // client API is
+        let gemini_api = if is_streaming {
+            GeminiApi::StreamGenerateContent
+        } else {
+            GeminiApi::GenerateContent
+        };
+        match provider_id {
+            ProviderId::GeminiNative => {
+                return build_endpoint(
+                    "/v1beta",
+                    &format!(
+                        "/models/{}{}",
+                        gemini_model_name(model_id),
+                        gemini_api.method()
+                    ),
+                );
+            }
+            // The project and region are in the base url, e.g.
+            // `/v1/projects/{project}/locations/{region}`
+            ProviderId::VertexAI => {
+                return build_endpoint(
+                    "/v1",
+                    &format!("/{}{}", vertex_model_path(model_id), gemini_api.method()),
+                );
+            }
+            // Claude on Vertex AI takes Messages bodies at the raw predict methods
+            ProviderId::VertexAnthropic => {
+                let model = model_id.rsplit('/').next().unwrap_or(model_id);
+                let method = if is_streaming {
+                    "streamRawPredict"
+                } else {
+                    "rawPredict"
+                };
+                return build_endpoint(
+                    "/v1",
+                    &format!("/publishers/anthropic/models/{}:{}", model, method),
+                );
+            }
+            // The Codex backend of ChatGPT takes Responses API requests
+            ProviderId::ChatGPT => return build_endpoint("/backend-api/codex", "/responses"),
+            _ => {}
+        }
+
         match self {
             SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages) => {
                 match provider_id {
@@ -227,6 +276,10 @@ impl SupportedUpstreamAPIs {
             }
         }
 
+        if let Some(gemini_api) = GeminiApi::from_endpoint(endpoint) {
+            return Some(SupportedUpstreamAPIs::GeminiGenerateContent(gemini_api));
+        }
+
         None
     }
 }
@@ -248,6 +301,25 @@ pub fn supported_endpoints() -> Vec<&'static str> {
     endpoints
 }
 
+/// Render a provider supplied path template such as `/api/{model}/chat/completions`.
+/// `{model}` is replaced with the model id and the base url path prefix, when present,
+/// is prepended to the rendered path.
+pub fn render_path_template(
+    template: &str,
+    model_id: &str,
+    base_url_path_prefix: Option<&str>,
+) -> String {
+    let path = template.replace("{model}", model_id);
+    let path = path.trim_start_matches('/');
+    match base_url_path_prefix
+        .map(|p| p.trim_matches('/'))
+        .filter(|p| !p.is_empty())
+    {
+        Some(prefix) => format!("/{}/{}", prefix, path),
+        None => format!("/{}", path),
+    }
+}
+
 /// Identify which provider supports a given endpoint
 pub fn identify_provider(endpoint: &str) -> Option<&'static str> {
     if OpenAIApi::from_endpoint(endpoint).is_some() {
@@ -626,6 +698,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_custom_provider_endpoints() {
+        let api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+
+        // Custom providers default to the OpenAI compatible path
+        assert_eq!(
+            api.target_endpoint_for_provider(
+                &ProviderId::Custom,
+                "/v1/chat/completions",
+                "llama-3",
+                false,
+                None
+            ),
+            "/v1/chat/completions"
+        );
+
+        // Path templates honor the model placeholder and base url prefix
+        assert_eq!(
+            render_path_template("/api/{model}/chat", "llama-3", None),
+            "/api/llama-3/chat"
+        );
+        assert_eq!(
+            render_path_template("chat/completions", "llama-3", Some("/litellm/")),
+            "/litellm/chat/completions"
+        );
+    }
+
     #[test]
     fn test_azure_openai_with_query_params() {
         let api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
@@ -654,4 +753,95 @@ mod tests {
             "/custom/azure/path/gpt-4-deployment/chat/completions?api-version=2025-01-01-preview"
         );
     }
+
+    #[test]
+    fn test_gemini_native_and_vertex_endpoints() {
+        let chat = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let messages = SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages);
+
+        assert_eq!(
+            chat.target_endpoint_for_provider(
+                &ProviderId::GeminiNative,
+                "/v1/chat/completions",
+                "models/gemini-2.5-flash",
+                false,
+                None
+            ),
+            "/v1beta/models/gemini-2.5-flash:generateContent"
+        );
+        assert_eq!(
+            messages.target_endpoint_for_provider(
+                &ProviderId::GeminiNative,
+                "/v1/messages",
+                "gemini-2.5-flash",
+                true,
+                Some("/v1")
+            ),
+            "/v1/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+
+        assert_eq!(
+            chat.target_endpoint_for_provider(
+                &ProviderId::VertexAI,
+                "/v1/chat/completions",
+                "gemini-2.5-pro",
+                true,
+                Some("/v1/projects/acme/locations/us-central1")
+            ),
+            "/v1/projects/acme/locations/us-central1/publishers/google/models/gemini-2.5-pro:streamGenerateContent?alt=sse"
+        );
+
+        assert_eq!(
+            messages.target_endpoint_for_provider(
+                &ProviderId::VertexAnthropic,
+                "/v1/messages",
+                "claude-sonnet-4@20250514",
+                false,
+                Some("/v1/projects/acme/locations/us-east5")
+            ),
+            "/v1/projects/acme/locations/us-east5/publishers/anthropic/models/claude-sonnet-4@20250514:rawPredict"
+        );
+        assert_eq!(
+            chat.target_endpoint_for_provider(
+                &ProviderId::VertexAnthropic,
+                "/v1/chat/completions",
+                "claude-sonnet-4@20250514",
+                true,
+                None
+            ),
+            "/v1/publishers/anthropic/models/claude-sonnet-4@20250514:streamRawPredict"
+        );
+
+        assert_eq!(
+            chat.target_endpoint_for_provider(
+                &ProviderId::ChatGPT,
+                "/v1/chat/completions",
+                "gpt-5-codex",
+                true,
+                None
+            ),
+            "/backend-api/codex/responses"
+        );
+
+        // The compatible interface keeps the OpenAI path
+        assert_eq!(
+            chat.target_endpoint_for_provider(
+                &ProviderId::Gemini,
+                "/v1/chat/completions",
+                "gemini-2.5-flash",
+                false,
+                None
+            ),
+            "/v1beta/openai/chat/completions"
+        );
+
+        assert_eq!(
+            SupportedUpstreamAPIs::from_endpoint(
+                "/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+            ),
+            Some(SupportedUpstreamAPIs::GeminiGenerateContent(
+                GeminiApi::StreamGenerateContent
+            ))
+        );
+    }
 }
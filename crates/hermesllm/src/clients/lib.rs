@@ -19,6 +19,8 @@ pub enum TransformError {
     MissingField(String),
     #[error("Unsupported conversion: {0}")]
     UnsupportedConversion(String),
+    #[error("Unsupported capability: {0}")]
+    UnsupportedCapability(String),
 }
 
 #[cfg(test)]
@@ -0,0 +1,547 @@
+//! Recorded provider traffic and its replay through the conversion pipeline
+//!
+//! A fixture is a client request together with the raw response of the provider it was
+//! routed to, streamed responses kept chunk by chunk as they arrived. Replaying a fixture
+//! runs both through the same conversions llm_gateway applies, so a response that once
+//! broke translation can be checked in and kept working as a test.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::apis::streaming_shapes::sse::{SseStreamBuffer, SseStreamBufferTrait};
+use crate::apis::streaming_shapes::sse_chunk_processor::SseChunkProcessor;
+use crate::clients::endpoints::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
+use crate::providers::id::ProviderId;
+use crate::providers::repair::repair_response_body;
+use crate::providers::request::{ProviderRequest, ProviderRequestType};
+use crate::providers::response::ProviderResponseType;
+
+/// Replacement for redacted values, also used by the traces and the conversation archive
+/// of brightstaff
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Keys whose values are never written to a fixture, compared case insensitively
+const SENSITIVE_KEYS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "api-key",
+    "x-api-key",
+    "authorization",
+    "access_token",
+    "refresh_token",
+    "secret",
+    "client_secret",
+    "password",
+    "user",
+    "user_id",
+    "email",
+];
+
+/// Prefixes of credentials that can turn up inside free text
+const SECRET_PREFIXES: &[&str] = &["sk-", "gsk_", "xai-", "AKIA"];
+
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("failed to access fixture {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("invalid fixture json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported client api: {0}")]
+    UnknownApi(String),
+    #[error("unknown provider: {0}")]
+    UnknownProvider(String),
+    #[error("request conversion failed: {0}")]
+    Request(String),
+    #[error("response conversion failed: {0}")]
+    Response(String),
+    #[error("{0} streams can't be replayed from text chunks")]
+    BinaryStream(String),
+}
+
+/// Raw provider response as it reached the gateway
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FixtureResponse {
+    /// Non streaming response, the body kept as text since malformed bodies are the
+    /// interesting ones
+    Json { status: u16, body: String },
+    /// Server sent events, one entry per chunk the provider sent
+    Stream { status: u16, chunks: Vec<String> },
+}
+
+/// What the gateway makes of a fixture
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplayOutput {
+    /// Request body sent to the provider
+    pub upstream_request: Value,
+    /// Response for the client: the json body of non streaming responses, or one string of
+    /// server sent events per upstream chunk for streams
+    pub client_response: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub name: String,
+    /// Path the client called, e.g. `/v1/chat/completions`
+    pub client_api: String,
+    /// Provider the request was routed to, e.g. `groq`
+    pub provider: String,
+    pub request: Value,
+    pub response: FixtureResponse,
+    /// Replay output accepted as correct. Fields left out aren't compared, so fixtures
+    /// written by hand only need to spell out what they test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Value>,
+}
+
+impl Fixture {
+    /// Builds a sanitized fixture from captured traffic
+    pub fn new(
+        name: &str,
+        client_api: &str,
+        provider: &ProviderId,
+        request: &[u8],
+        response: FixtureResponse,
+    ) -> Result<Self, FixtureError> {
+        let mut request: Value = serde_json::from_slice(request)?;
+        sanitize(&mut request);
+        let response = match response {
+            FixtureResponse::Json { status, body } => FixtureResponse::Json {
+                status,
+                body: sanitize_text(&body),
+            },
+            FixtureResponse::Stream { status, chunks } => FixtureResponse::Stream {
+                status,
+                chunks: chunks.iter().map(|chunk| sanitize_text(chunk)).collect(),
+            },
+        };
+        Ok(Fixture {
+            name: file_name(name),
+            client_api: client_api.to_string(),
+            provider: provider.to_string().to_lowercase(),
+            request,
+            response,
+            expected: None,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, FixtureError> {
+        let contents = fs::read(path).map_err(|e| FixtureError::Io(path.to_path_buf(), e))?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    /// Loads every `.json` fixture in a directory, ordered by file name
+    pub fn load_dir(dir: &Path) -> Result<Vec<Self>, FixtureError> {
+        let entries = fs::read_dir(dir).map_err(|e| FixtureError::Io(dir.to_path_buf(), e))?;
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.iter().map(|path| Self::load(path)).collect()
+    }
+
+    /// Writes the fixture to `<dir>/<name>.json`
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, FixtureError> {
+        fs::create_dir_all(dir).map_err(|e| FixtureError::Io(dir.to_path_buf(), e))?;
+        let path = dir.join(format!("{}.json", self.name));
+        let contents = serde_json::to_vec_pretty(self)?;
+        fs::write(&path, contents).map_err(|e| FixtureError::Io(path.clone(), e))?;
+        Ok(path)
+    }
+
+    /// Runs the fixture through request and response conversion
+    pub fn replay(&self) -> Result<ReplayOutput, FixtureError> {
+        let client_api = SupportedAPIsFromClient::from_endpoint(&self.client_api)
+            .ok_or_else(|| FixtureError::UnknownApi(self.client_api.clone()))?;
+        let provider_id = ProviderId::from_name(&self.provider)
+            .ok_or_else(|| FixtureError::UnknownProvider(self.provider.clone()))?;
+
+        let request_bytes = serde_json::to_vec(&self.request)?;
+        let client_request = ProviderRequestType::try_from((&request_bytes[..], &client_api))
+            .map_err(|e| FixtureError::Request(e.to_string()))?;
+        let upstream_api =
+            provider_id.compatible_api_for_client(&client_api, client_request.is_streaming());
+        let upstream_request = ProviderRequestType::try_from((client_request, &upstream_api))
+            .map_err(|e| FixtureError::Request(e.to_string()))?
+            .to_bytes()
+            .map_err(|e| FixtureError::Request(e.to_string()))?;
+
+        let client_response = match &self.response {
+            FixtureResponse::Json { status, body } => {
+                replay_body(*status, body.as_bytes(), &client_api, &provider_id)?
+            }
+            FixtureResponse::Stream { status, chunks } => {
+                replay_stream(*status, chunks, &client_api, &upstream_api)?
+            }
+        };
+
+        Ok(ReplayOutput {
+            upstream_request: serde_json::from_slice(&upstream_request)?,
+            client_response,
+        })
+    }
+
+    /// Replays the fixture and records the output as the expected one
+    pub fn accept(&mut self) -> Result<(), FixtureError> {
+        self.expected = Some(serde_json::to_value(self.replay()?)?);
+        Ok(())
+    }
+
+    /// Replays the fixture and describes how the output differs from the expected one
+    pub fn check(&self) -> Result<(), String> {
+        let actual = self
+            .replay()
+            .and_then(|output| Ok(serde_json::to_value(output)?))
+            .map_err(|e| format!("{}: replay failed: {}", self.name, e))?;
+        match &self.expected {
+            Some(expected) if matches_expected(&actual, expected) => Ok(()),
+            Some(expected) => Err(format!(
+                "{}: replay output changed\nexpected: {}\nactual: {}",
+                self.name,
+                serde_json::to_string_pretty(expected).unwrap_or_default(),
+                serde_json::to_string_pretty(&actual).unwrap_or_default()
+            )),
+            None => Err(format!("{}: no expected output recorded", self.name)),
+        }
+    }
+}
+
+/// Prefixes of ids the conversions generate when the upstream response has none to
+/// carry over, followed by a random uuid
+const GENERATED_ID_PREFIXES: &[&str] = &["msg_", "resp_", "call_"];
+
+/// Whether `actual` has every field of `expected` with the same value. Arrays have to
+/// be of the same length, their items are compared the same way. Generated ids differ on
+/// every replay, so strings are compared with them masked.
+fn matches_expected(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::String(actual), Value::String(expected)) => {
+            mask_generated_ids(actual) == mask_generated_ids(expected)
+        }
+        (Value::Object(actual), Value::Object(expected)) => {
+            expected.iter().all(|(key, expected)| {
+                actual
+                    .get(key)
+                    .is_some_and(|actual| matches_expected(actual, expected))
+            })
+        }
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len()
+                && actual
+                    .iter()
+                    .zip(expected)
+                    .all(|(actual, expected)| matches_expected(actual, expected))
+        }
+        _ => actual == expected,
+    }
+}
+
+/// Replaces the uuid after each generated id prefix, with or without dashes, by `*`
+fn mask_generated_ids(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((start, prefix)) = GENERATED_ID_PREFIXES
+        .iter()
+        .filter_map(|prefix| rest.find(prefix).map(|start| (start, prefix)))
+        .min()
+    {
+        let id_start = start + prefix.len();
+        masked.push_str(&rest[..id_start]);
+        rest = &rest[id_start..];
+        let len = rest
+            .bytes()
+            .take_while(|b| b.is_ascii_hexdigit() || *b == b'-')
+            .count();
+        if len == 32 || len == 36 {
+            masked.push('*');
+            rest = &rest[len..];
+        }
+    }
+    masked.push_str(rest);
+    masked
+}
+
+fn replay_body(
+    status: u16,
+    body: &[u8],
+    client_api: &SupportedAPIsFromClient,
+    provider_id: &ProviderId,
+) -> Result<Value, FixtureError> {
+    // Error bodies are passed to the client as the provider sent them
+    if !(200..300).contains(&status) {
+        return Ok(serde_json::from_slice(body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned())));
+    }
+    let repaired = repair_response_body(body, client_api, provider_id);
+    let body = repaired.as_ref().map_or(body, |repaired| &repaired.body);
+    let response = ProviderResponseType::try_from((body, client_api, provider_id))
+        .map_err(|e| FixtureError::Response(e.to_string()))?;
+    Ok(serde_json::to_value(response)?)
+}
+
+fn replay_stream(
+    status: u16,
+    chunks: &[String],
+    client_api: &SupportedAPIsFromClient,
+    upstream_api: &SupportedUpstreamAPIs,
+) -> Result<Value, FixtureError> {
+    if !(200..300).contains(&status) {
+        return Ok(Value::Array(
+            chunks.iter().cloned().map(Value::String).collect(),
+        ));
+    }
+    if matches!(
+        upstream_api,
+        SupportedUpstreamAPIs::AmazonBedrockConverseStream(_)
+    ) {
+        return Err(FixtureError::BinaryStream(upstream_api.to_string()));
+    }
+
+    let mut processor = SseChunkProcessor::new();
+    let mut buffer = SseStreamBuffer::try_from((client_api, upstream_api))
+        .map_err(|e| FixtureError::Response(e.to_string()))?;
+    let mut output = Vec::new();
+    for chunk in chunks {
+        let events = processor
+            .process_chunk(chunk.as_bytes(), client_api, upstream_api)
+            .map_err(FixtureError::Response)?;
+        for event in events {
+            buffer.add_transformed_event(event);
+        }
+        output.push(Value::String(
+            String::from_utf8_lossy(&buffer.to_bytes()).into_owned(),
+        ));
+    }
+    let events = processor
+        .flush(client_api, upstream_api)
+        .map_err(FixtureError::Response)?;
+    for event in events {
+        buffer.add_transformed_event(event);
+    }
+    let remaining = buffer.to_bytes();
+    if !remaining.is_empty() {
+        output.push(Value::String(
+            String::from_utf8_lossy(&remaining).into_owned(),
+        ));
+    }
+    Ok(Value::Array(output))
+}
+
+/// Redacts credentials and end user identifiers from a json value in place
+pub fn sanitize(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.to_lowercase().as_str()) && !field.is_null() {
+                    *field = Value::String(REDACTED.to_string());
+                } else {
+                    sanitize(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(sanitize),
+        Value::String(text) => *text = sanitize_text(text),
+        _ => {}
+    }
+}
+
+/// Redacts bearer tokens and strings that look like provider api keys from free text
+pub fn sanitize_text(text: &str) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = find_secret(rest) {
+        sanitized.push_str(&rest[..start]);
+        let secret = &rest[start..];
+        let end = secret
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+            .unwrap_or(secret.len());
+        sanitized.push_str(REDACTED);
+        rest = &secret[end..];
+    }
+    sanitized.push_str(rest);
+    sanitized
+}
+
+/// Start of the first credential in `text`, skipping the `Bearer ` scheme
+fn find_secret(text: &str) -> Option<usize> {
+    let bearer = text.find("Bearer ").map(|i| i + "Bearer ".len());
+    SECRET_PREFIXES
+        .iter()
+        .filter_map(|prefix| {
+            text.match_indices(prefix)
+                .map(|(i, _)| i)
+                // Only at the start of a word, so e.g. "task-" isn't taken for a key
+                .find(|&i| {
+                    text[..i]
+                        .chars()
+                        .next_back()
+                        .is_none_or(|c| !c.is_ascii_alphanumeric())
+                })
+        })
+        .chain(bearer.filter(|&i| i < text.len()))
+        .min()
+}
+
+/// Keeps fixture names usable as file names
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixtures_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+    }
+
+    #[test]
+    fn test_checked_in_fixtures_replay_unchanged() {
+        let fixtures = Fixture::load_dir(&fixtures_dir()).unwrap();
+        assert!(!fixtures.is_empty());
+        let failures = fixtures
+            .iter()
+            .filter_map(|fixture| fixture.check().err())
+            .collect::<Vec<_>>();
+        assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+    }
+
+    #[test]
+    fn test_new_fixture_is_sanitized() {
+        let request = json!({
+            "model": "gpt-4o",
+            "user": "alice@example.com",
+            "messages": [{"role": "user", "content": "my key is sk-abc123XYZ, keep it safe"}],
+            "metadata": {"api_key": "gsk_secret", "max_tokens": 10}
+        });
+        let fixture = Fixture::new(
+            "req 1/2",
+            "/v1/chat/completions",
+            &ProviderId::OpenAI,
+            &serde_json::to_vec(&request).unwrap(),
+            FixtureResponse::Stream {
+                status: 200,
+                chunks: vec!["data: {\"note\":\"Bearer abc.def\"}\n\n".to_string()],
+            },
+        )
+        .unwrap();
+
+        assert_eq!(fixture.name, "req_1_2");
+        assert_eq!(fixture.provider, "openai");
+        assert_eq!(fixture.request["user"], REDACTED);
+        assert_eq!(fixture.request["metadata"]["api_key"], REDACTED);
+        assert_eq!(fixture.request["metadata"]["max_tokens"], 10);
+        assert_eq!(
+            fixture.request["messages"][0]["content"],
+            "my key is [REDACTED], keep it safe"
+        );
+        assert_eq!(
+            fixture.response,
+            FixtureResponse::Stream {
+                status: 200,
+                chunks: vec!["data: {\"note\":\"Bearer [REDACTED]\"}\n\n".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_matches_expected_ignores_fields_left_out() {
+        let actual = json!({"id": "1", "choices": [{"index": 0, "message": {"content": "hi"}}]});
+        assert!(matches_expected(
+            &actual,
+            &json!({"choices": [{"index": 0}]})
+        ));
+        assert!(!matches_expected(&actual, &json!({"choices": []})));
+        assert!(!matches_expected(&actual, &json!({"id": "2"})));
+        assert!(!matches_expected(&actual, &json!({"usage": {}})));
+    }
+
+    #[test]
+    fn test_matches_expected_ignores_generated_ids() {
+        let actual = json!({"id": "msg_0123456789abcdef0123456789abcdef", "n": "call_1"});
+        let expected = json!({"id": "msg_fedcba9876543210fedcba9876543210", "n": "call_1"});
+        assert!(matches_expected(&actual, &expected));
+        assert!(!matches_expected(
+            &json!({"n": "call_1"}),
+            &json!({"n": "call_2"})
+        ));
+        assert_eq!(
+            mask_generated_ids("call_7f3c2a1e-9b4d-4c8e-a6f0-1d2e3f4a5b6c, resp_1"),
+            "call_*, resp_1"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_text_keeps_words_containing_prefixes() {
+        assert_eq!(sanitize_text("ask-me task-list"), "ask-me task-list");
+        assert_eq!(sanitize_text("sk-1 and sk-2"), "[REDACTED] and [REDACTED]");
+    }
+
+    #[test]
+    fn test_stream_replay_translates_chunks_split_mid_event() {
+        let mut fixture = Fixture {
+            name: "split".to_string(),
+            client_api: "/v1/messages".to_string(),
+            provider: "openai".to_string(),
+            request: json!({
+                "model": "gpt-4o",
+                "max_tokens": 16,
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+            response: FixtureResponse::Stream {
+                status: 200,
+                chunks: vec![
+                    "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel".to_string(),
+                    "lo\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n".to_string(),
+                ],
+            },
+            expected: None,
+        };
+
+        let output = fixture.replay().unwrap();
+        assert_eq!(output.upstream_request["messages"][0]["content"], "hi");
+        let chunks = output.client_response.as_array().unwrap();
+        assert_eq!(chunks[0], "");
+        assert!(chunks[1].as_str().unwrap().contains("Hello"));
+
+        fixture.accept().unwrap();
+        assert!(fixture.check().is_ok());
+    }
+
+    #[test]
+    fn test_bedrock_streams_are_rejected() {
+        let fixture = Fixture {
+            name: "bedrock".to_string(),
+            client_api: "/v1/chat/completions".to_string(),
+            provider: "amazon_bedrock".to_string(),
+            request: json!({
+                "model": "anthropic.claude-3-haiku",
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+            response: FixtureResponse::Stream {
+                status: 200,
+                chunks: vec![],
+            },
+            expected: None,
+        };
+        assert!(matches!(
+            fixture.replay(),
+            Err(FixtureError::BinaryStream(_))
+        ));
+    }
+}
@@ -3,6 +3,7 @@
 
 pub mod apis;
 pub mod clients;
+pub mod fixtures;
 pub mod providers;
 pub mod transforms;
 // Re-export important types and traits
@@ -20,6 +21,11 @@ pub use providers::streaming_response::{ProviderStreamResponse, ProviderStreamRe
 pub const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
 pub const OPENAI_RESPONSES_API_PATH: &str = "/v1/responses";
 pub const MESSAGES_PATH: &str = "/v1/messages";
+pub const AUDIO_TRANSCRIPTIONS_PATH: &str = "/v1/audio/transcriptions";
+pub const AUDIO_SPEECH_PATH: &str = "/v1/audio/speech";
+pub const BATCHES_PATH: &str = "/v1/batches";
+pub const FILES_PATH: &str = "/v1/files";
+pub const MESSAGE_BATCHES_PATH: &str = "/v1/messages/batches";
 
 #[cfg(test)]
 mod tests {
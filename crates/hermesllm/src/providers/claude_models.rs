@@ -0,0 +1,166 @@
+//! Claude model ids across the platforms serving them
+//!
+//! Anthropic, Amazon Bedrock and Vertex AI name the same Claude snapshot differently,
+//! e.g. `claude-sonnet-4-20250514`, `anthropic.claude-sonnet-4-20250514-v1:0` and
+//! `claude-sonnet-4@20250514`. Any of these forms, or an undated id like `claude-sonnet-4`,
+//! is translated into the form of the platform the request is sent to, so one alias can
+//! be served by all of them.
+
+use std::borrow::Cow;
+
+use crate::providers::id::ProviderId;
+
+/// Snapshot of undated Claude model ids. More specific ids come first.
+const CLAUDE_SNAPSHOTS: &[(&str, &str)] = &[
+    ("claude-opus-4-1", "20250805"),
+    ("claude-opus-4", "20250514"),
+    ("claude-sonnet-4-5", "20250929"),
+    ("claude-sonnet-4", "20250514"),
+    ("claude-haiku-4-5", "20251001"),
+    ("claude-3-7-sonnet", "20250219"),
+    ("claude-3-5-haiku", "20241022"),
+    ("claude-3-opus", "20240229"),
+    ("claude-3-haiku", "20240307"),
+];
+
+/// Whether the model id names a Claude model, in any platform's form
+pub fn is_claude_model(model: &str) -> bool {
+    model.contains("claude-")
+}
+
+/// Claude model id in the form of the provider, other model ids are returned unchanged.
+/// Ids the provider already accepts are kept, e.g. Anthropic's `-latest` aliases or Bedrock
+/// ids with a `us.` inference profile.
+pub fn claude_model_id(provider_id: ProviderId, model: &str) -> Cow<'_, str> {
+    if !is_claude_model(model) {
+        return Cow::Borrowed(model);
+    }
+    if provider_id == ProviderId::Anthropic && is_anthropic_model_id(model) {
+        return Cow::Borrowed(model);
+    }
+    let Some((name, snapshot)) = claude_snapshot(model) else {
+        return Cow::Borrowed(model);
+    };
+    match provider_id {
+        ProviderId::Anthropic => Cow::Owned(format!("{}-{}", name, snapshot)),
+        ProviderId::AmazonBedrock if model.contains("anthropic.") => Cow::Borrowed(model),
+        ProviderId::AmazonBedrock => Cow::Owned(format!("anthropic.{}-{}-v1:0", name, snapshot)),
+        ProviderId::VertexAnthropic => Cow::Owned(format!("{}@{}", name, snapshot)),
+        _ => Cow::Borrowed(model),
+    }
+}
+
+/// Model name and snapshot date of a Claude model id
+fn claude_snapshot(model: &str) -> Option<(&str, &str)> {
+    // vertex publisher paths and bedrock region and vendor prefixes
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let model = model
+        .find("claude-")
+        .map(|start| &model[start..])
+        .unwrap_or(model);
+    // bedrock version suffix, e.g. `-v1:0`
+    let model = match model.rsplit_once("-v") {
+        Some((model, version)) if version.contains(':') => model,
+        _ => model,
+    };
+
+    if let Some((name, snapshot)) = model
+        .split_once('@')
+        .or_else(|| model.rsplit_once('-'))
+        .filter(|(_, snapshot)| is_snapshot_date(snapshot))
+    {
+        return Some((name, snapshot));
+    }
+
+    // Anthropic aliases of the latest snapshot, e.g. `claude-sonnet-4-0`
+    let name = model
+        .strip_suffix("-latest")
+        .or_else(|| model.strip_suffix("-0"))
+        .unwrap_or(model);
+    CLAUDE_SNAPSHOTS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(known, snapshot)| (*known, *snapshot))
+}
+
+/// Ids in Anthropic's own form, other than the undated names it doesn't serve
+fn is_anthropic_model_id(model: &str) -> bool {
+    model.starts_with("claude-")
+        && !model.contains('@')
+        && !CLAUDE_SNAPSHOTS.iter().any(|(name, _)| *name == model)
+}
+
+fn is_snapshot_date(value: &str) -> bool {
+    value.len() == 8 && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claude_model_id_per_platform() {
+        for model in [
+            "claude-sonnet-4",
+            "claude-sonnet-4-20250514",
+            "claude-sonnet-4@20250514",
+            "anthropic.claude-sonnet-4-20250514-v1:0",
+            "anthropic/claude-sonnet-4",
+        ] {
+            assert_eq!(
+                claude_model_id(ProviderId::Anthropic, model),
+                "claude-sonnet-4-20250514",
+                "{}",
+                model
+            );
+            assert_eq!(
+                claude_model_id(ProviderId::VertexAnthropic, model),
+                "claude-sonnet-4@20250514",
+                "{}",
+                model
+            );
+        }
+
+        // aliases of the latest snapshot are only served by Anthropic
+        assert_eq!(
+            claude_model_id(ProviderId::Anthropic, "claude-sonnet-4-0"),
+            "claude-sonnet-4-0"
+        );
+        assert_eq!(
+            claude_model_id(ProviderId::VertexAnthropic, "claude-3-7-sonnet-latest"),
+            "claude-3-7-sonnet@20250219"
+        );
+        assert_eq!(
+            claude_model_id(ProviderId::AmazonBedrock, "claude-sonnet-4"),
+            "anthropic.claude-sonnet-4-20250514-v1:0"
+        );
+        assert_eq!(
+            claude_model_id(ProviderId::AmazonBedrock, "claude-opus-4-1@20250805"),
+            "anthropic.claude-opus-4-1-20250805-v1:0"
+        );
+        assert_eq!(
+            claude_model_id(
+                ProviderId::AmazonBedrock,
+                "us.anthropic.claude-sonnet-4-20250514-v1:0"
+            ),
+            "us.anthropic.claude-sonnet-4-20250514-v1:0"
+        );
+    }
+
+    #[test]
+    fn test_other_models_are_unchanged() {
+        assert_eq!(
+            claude_model_id(ProviderId::AmazonBedrock, "us.amazon.nova-pro-v1:0"),
+            "us.amazon.nova-pro-v1:0"
+        );
+        assert_eq!(
+            claude_model_id(ProviderId::Anthropic, "claude-next-preview"),
+            "claude-next-preview"
+        );
+        assert_eq!(
+            claude_model_id(ProviderId::OpenAI, "claude-sonnet-4"),
+            "claude-sonnet-4"
+        );
+        assert!(!is_claude_model("gemini-2.5-pro"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Context window aware output token limits
+//!
+//! Providers reject requests whose prompt plus requested output tokens exceed the
+//! model's context window. This module knows the context window of common models and
+//! lowers the requested output tokens so that the request fits.
+
+use crate::providers::request::ProviderRequestType;
+
+/// Context windows by model id prefix. More specific prefixes come first.
+const CONTEXT_WINDOWS: &[(&str, u64)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-5", 400_000),
+    ("o1-mini", 128_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude-", 200_000),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-", 1_048_576),
+    ("mistral-large", 131_072),
+    ("mistral-small", 32_768),
+    ("codestral", 256_000),
+    ("deepseek-", 65_536),
+    ("grok-", 131_072),
+    ("llama-3", 131_072),
+];
+
+/// Context window of a well known model, matched on the model id without any
+/// provider prefix, e.g. `openai/gpt-4o` or `us.anthropic.claude-sonnet-4`
+pub fn known_context_window(model: &str) -> Option<u64> {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    // bedrock model ids are prefixed with the region and vendor, e.g. `us.anthropic.`
+    let candidates =
+        std::iter::once(model).chain(model.match_indices('.').map(|(i, _)| &model[i + 1..]));
+    candidates
+        .filter_map(|candidate| {
+            CONTEXT_WINDOWS
+                .iter()
+                .find(|(prefix, _)| candidate.starts_with(prefix))
+        })
+        .map(|(_, context_window)| *context_window)
+        .next()
+}
+
+/// Output token limit lowered to fit the context window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaxTokensAdjustment {
+    pub requested: u32,
+    pub adjusted: u32,
+}
+
+impl ProviderRequestType {
+    /// Output token limit of the request, `max_tokens`, `max_completion_tokens` or
+    /// `max_output_tokens` depending on the api
+    pub fn max_output_tokens(&self) -> Option<u32> {
+        match self {
+            ProviderRequestType::ChatCompletionsRequest(req) => {
+                req.max_completion_tokens.or(req.max_tokens)
+            }
+            ProviderRequestType::MessagesRequest(req) => Some(req.max_tokens),
+            ProviderRequestType::BedrockConverse(req)
+            | ProviderRequestType::BedrockConverseStream(req) => {
+                req.inference_config.as_ref()?.max_tokens
+            }
+            ProviderRequestType::ResponsesAPIRequest(req) => req
+                .max_output_tokens
+                .and_then(|max_output_tokens| u32::try_from(max_output_tokens).ok()),
+            ProviderRequestType::GeminiGenerateContent(req) => {
+                req.generation_config.as_ref()?.max_output_tokens
+            }
+        }
+    }
+
+    fn set_max_output_tokens(&mut self, max_output_tokens: u32) {
+        match self {
+            ProviderRequestType::ChatCompletionsRequest(req) => {
+                if req.max_completion_tokens.is_some() {
+                    req.max_completion_tokens = Some(max_output_tokens);
+                }
+                if req.max_tokens.is_some() {
+                    req.max_tokens = Some(max_output_tokens);
+                }
+            }
+            ProviderRequestType::MessagesRequest(req) => req.max_tokens = max_output_tokens,
+            ProviderRequestType::BedrockConverse(req)
+            | ProviderRequestType::BedrockConverseStream(req) => {
+                if let Some(inference_config) = req.inference_config.as_mut() {
+                    inference_config.max_tokens = Some(max_output_tokens);
+                }
+            }
+            ProviderRequestType::ResponsesAPIRequest(req) => {
+                req.max_output_tokens = Some(max_output_tokens as i32)
+            }
+            ProviderRequestType::GeminiGenerateContent(req) => {
+                if let Some(generation_config) = req.generation_config.as_mut() {
+                    generation_config.max_output_tokens = Some(max_output_tokens);
+                }
+            }
+        }
+    }
+
+    /// Lowers the output token limit so that it fits in the context window next to the
+    /// prompt. Requests without a limit, that already fit, or whose prompt alone fills the
+    /// context window are left unchanged.
+    pub fn clamp_max_tokens_to_context_window(
+        &mut self,
+        context_window: u64,
+        prompt_tokens: u64,
+    ) -> Option<MaxTokensAdjustment> {
+        let requested = self.max_output_tokens()?;
+        let available = context_window.checked_sub(prompt_tokens)?;
+        if available == 0 || u64::from(requested) <= available {
+            return None;
+        }
+        // available < requested, so it fits in a u32
+        let adjusted = available as u32;
+        self.set_max_output_tokens(adjusted);
+        Some(MaxTokensAdjustment {
+            requested,
+            adjusted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::anthropic::MessagesRequest;
+    use crate::apis::openai::ChatCompletionsRequest;
+
+    #[test]
+    fn test_known_context_window() {
+        assert_eq!(known_context_window("gpt-4o-mini"), Some(128_000));
+        assert_eq!(known_context_window("gpt-4-0613"), Some(8_192));
+        assert_eq!(known_context_window("openai/gpt-4.1"), Some(1_047_576));
+        assert_eq!(
+            known_context_window("claude-sonnet-4-20250514"),
+            Some(200_000)
+        );
+        assert_eq!(
+            known_context_window("us.anthropic.claude-3-5-sonnet-20241022-v2:0"),
+            Some(200_000)
+        );
+        assert_eq!(known_context_window("my-finetune"), None);
+    }
+
+    #[test]
+    fn test_clamp_chat_completions_max_tokens() {
+        let mut request = ProviderRequestType::ChatCompletionsRequest(ChatCompletionsRequest {
+            model: "gpt-4".to_string(),
+            max_tokens: Some(4096),
+            ..Default::default()
+        });
+        assert_eq!(
+            request.clamp_max_tokens_to_context_window(8192, 6000),
+            Some(MaxTokensAdjustment {
+                requested: 4096,
+                adjusted: 2192
+            })
+        );
+        assert_eq!(request.max_output_tokens(), Some(2192));
+
+        // fits, or can't be helped
+        assert_eq!(request.clamp_max_tokens_to_context_window(8192, 100), None);
+        assert_eq!(request.clamp_max_tokens_to_context_window(8192, 9000), None);
+        assert_eq!(request.max_output_tokens(), Some(2192));
+
+        let mut request = ProviderRequestType::ChatCompletionsRequest(ChatCompletionsRequest {
+            model: "gpt-4".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(request.clamp_max_tokens_to_context_window(8192, 6000), None);
+    }
+
+    #[test]
+    fn test_clamp_messages_max_tokens() {
+        let request: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 64000,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let mut request = ProviderRequestType::MessagesRequest(request);
+        let adjustment = request
+            .clamp_max_tokens_to_context_window(200_000, 150_000)
+            .unwrap();
+        assert_eq!(adjustment.adjusted, 50_000);
+        assert_eq!(request.max_output_tokens(), Some(50_000));
+    }
+}
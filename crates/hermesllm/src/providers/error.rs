@@ -0,0 +1,292 @@
+use crate::clients::endpoints::SupportedAPIsFromClient;
+use serde_json::{json, Value};
+
+/// Error returned by an upstream provider, extracted from the body of a 4xx/5xx response
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamError {
+    pub status: u16,
+    pub message: String,
+    /// Error type or status reported by the provider, e.g. `rate_limit_exceeded`
+    pub error_type: Option<String>,
+    pub code: Option<String>,
+    pub param: Option<String>,
+}
+
+impl UpstreamError {
+    /// Understands the OpenAI (and compatible, e.g. Groq), Anthropic, Gemini and Bedrock
+    /// error shapes. Anything else is kept as the message.
+    pub fn parse(status: u16, body: &[u8]) -> Self {
+        let mut error = UpstreamError {
+            status,
+            message: String::new(),
+            error_type: None,
+            code: None,
+            param: None,
+        };
+
+        let value: Value = match serde_json::from_slice(body) {
+            // gemini wraps errors in a list when streaming
+            Ok(Value::Array(mut errors)) if !errors.is_empty() => errors.remove(0),
+            Ok(value) => value,
+            Err(_) => Value::Null,
+        };
+        let detail = match value.get("error") {
+            Some(Value::String(message)) => {
+                error.message = message.clone();
+                &Value::Null
+            }
+            Some(detail) => detail,
+            None => &value,
+        };
+
+        if let Some(message) = detail.get("message").and_then(string_value) {
+            error.message = message;
+        }
+        error.error_type = detail
+            .get("type")
+            .or_else(|| detail.get("status"))
+            .or_else(|| detail.get("__type"))
+            .and_then(string_value);
+        error.code = detail.get("code").and_then(string_value);
+        error.param = detail.get("param").and_then(string_value);
+
+        if error.message.is_empty() {
+            error.message = match String::from_utf8_lossy(body).trim() {
+                "" => status_reason(status).to_string(),
+                body => body.to_string(),
+            };
+        }
+        error
+    }
+
+    /// Error body in the schema of the api the client called
+    pub fn to_client_body(&self, client_api: &SupportedAPIsFromClient) -> Vec<u8> {
+        let body = match client_api {
+            SupportedAPIsFromClient::AnthropicMessagesAPI(_) => json!({
+                "type": "error",
+                "error": {
+                    "type": anthropic_error_type(self.status),
+                    "message": self.message,
+                },
+            }),
+            SupportedAPIsFromClient::OpenAIChatCompletions(_)
+            | SupportedAPIsFromClient::OpenAIResponsesAPI(_) => json!({
+                "error": {
+                    "message": self.message,
+                    "type": openai_error_type(self.status),
+                    "param": self.param,
+                    "code": self.code.as_ref().or(self.error_type.as_ref()),
+                },
+            }),
+        };
+        serde_json::to_vec(&body).unwrap_or_default()
+    }
+
+    /// Error event ending a stream of the api the client called, for errors that happen
+    /// once the response has started
+    pub fn to_client_sse_event(&self, client_api: &SupportedAPIsFromClient) -> Vec<u8> {
+        let event = match client_api {
+            SupportedAPIsFromClient::AnthropicMessagesAPI(_) => format!(
+                "event: error\ndata: {}\n\n",
+                String::from_utf8_lossy(&self.to_client_body(client_api))
+            ),
+            SupportedAPIsFromClient::OpenAIResponsesAPI(_) => {
+                let data = json!({
+                    "type": "error",
+                    "code": self.code.as_ref().or(self.error_type.as_ref()),
+                    "message": self.message,
+                    "param": self.param,
+                });
+                format!("event: error\ndata: {}\n\n", data)
+            }
+            SupportedAPIsFromClient::OpenAIChatCompletions(_) => format!(
+                "data: {}\n\n",
+                String::from_utf8_lossy(&self.to_client_body(client_api))
+            ),
+        };
+        event.into_bytes()
+    }
+}
+
+/// Longest upstream error kept in a response header
+pub const MAX_ERROR_HEADER_VALUE_LEN: usize = 1024;
+
+/// Upstream error body reduced to a single line of printable ascii, usable as a header value
+pub fn error_header_value(body: &[u8]) -> String {
+    let body = match serde_json::from_slice::<Value>(body) {
+        Ok(value) => value.to_string(),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    body.chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_ERROR_HEADER_VALUE_LEN)
+        .collect()
+}
+
+fn string_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn status_reason(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "upstream error",
+    }
+}
+
+fn openai_error_type(status: u16) -> &'static str {
+    match status {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        429 => "rate_limit_error",
+        400..=499 => "invalid_request_error",
+        _ => "server_error",
+    }
+}
+
+fn anthropic_error_type(status: u16) -> &'static str {
+    match status {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        400..=499 => "invalid_request_error",
+        _ => "api_error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::anthropic::AnthropicApi;
+    use crate::apis::openai::OpenAIApi;
+
+    #[test]
+    fn test_openai_error_to_anthropic_client() {
+        let body = br#"{"error":{"message":"Rate limit reached for gpt-4o","type":"requests","param":null,"code":"rate_limit_exceeded"}}"#;
+        let error = UpstreamError::parse(429, body);
+        assert_eq!(error.message, "Rate limit reached for gpt-4o");
+        assert_eq!(error.code.as_deref(), Some("rate_limit_exceeded"));
+
+        let client_body: Value = serde_json::from_slice(&error.to_client_body(
+            &SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages),
+        ))
+        .unwrap();
+        assert_eq!(
+            client_body,
+            json!({
+                "type": "error",
+                "error": {"type": "rate_limit_error", "message": "Rate limit reached for gpt-4o"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_anthropic_error_to_openai_client() {
+        let body =
+            br#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let error = UpstreamError::parse(529, body);
+        assert_eq!(error.error_type.as_deref(), Some("overloaded_error"));
+
+        let client_body: Value = serde_json::from_slice(&error.to_client_body(
+            &SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
+        ))
+        .unwrap();
+        assert_eq!(client_body["error"]["message"], "Overloaded");
+        assert_eq!(client_body["error"]["type"], "server_error");
+        assert_eq!(client_body["error"]["code"], "overloaded_error");
+    }
+
+    #[test]
+    fn test_error_to_client_sse_event() {
+        let error = UpstreamError::parse(502, b"response too large");
+
+        let event = error.to_client_sse_event(&SupportedAPIsFromClient::AnthropicMessagesAPI(
+            AnthropicApi::Messages,
+        ));
+        let event = String::from_utf8(event).unwrap();
+        let data = event.strip_prefix("event: error\ndata: ").unwrap();
+        assert!(data.ends_with("\n\n"));
+        assert_eq!(
+            serde_json::from_str::<Value>(data).unwrap(),
+            json!({
+                "type": "error",
+                "error": {"type": "api_error", "message": "response too large"}
+            })
+        );
+
+        let event = error.to_client_sse_event(&SupportedAPIsFromClient::OpenAIResponsesAPI(
+            OpenAIApi::Responses,
+        ));
+        let event = String::from_utf8(event).unwrap();
+        let data = event.strip_prefix("event: error\ndata: ").unwrap();
+        let data: Value = serde_json::from_str(data.trim_end()).unwrap();
+        assert_eq!(data["type"], "error");
+        assert_eq!(data["message"], "response too large");
+
+        let event = error.to_client_sse_event(&SupportedAPIsFromClient::OpenAIChatCompletions(
+            OpenAIApi::ChatCompletions,
+        ));
+        let event = String::from_utf8(event).unwrap();
+        assert!(event.starts_with("data: {\"error\":"));
+        assert!(event.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn test_gemini_bedrock_and_plain_errors() {
+        let gemini = br#"[{"error":{"code":400,"message":"API key not valid","status":"INVALID_ARGUMENT"}}]"#;
+        let error = UpstreamError::parse(400, gemini);
+        assert_eq!(error.message, "API key not valid");
+        assert_eq!(error.error_type.as_deref(), Some("INVALID_ARGUMENT"));
+        assert_eq!(error.code.as_deref(), Some("400"));
+
+        let bedrock = br#"{"message":"The security token included in the request is invalid."}"#;
+        assert_eq!(
+            UpstreamError::parse(403, bedrock).message,
+            "The security token included in the request is invalid."
+        );
+
+        assert_eq!(
+            UpstreamError::parse(502, b"upstream connect error").message,
+            "upstream connect error"
+        );
+        assert_eq!(UpstreamError::parse(504, b"").message, "Gateway Timeout");
+    }
+
+    #[test]
+    fn test_error_header_value() {
+        assert_eq!(
+            error_header_value(b"{\n  \"error\": {\"message\": \"bad\"}\n}"),
+            r#"{"error":{"message":"bad"}}"#
+        );
+        assert_eq!(
+            error_header_value(b"line one\r\nline\ttwo"),
+            "line one line two"
+        );
+        assert_eq!(
+            error_header_value(&[b'a'; 2000]).len(),
+            MAX_ERROR_HEADER_VALUE_LEN
+        );
+    }
+}
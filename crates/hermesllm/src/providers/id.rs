@@ -1,4 +1,4 @@
-use crate::apis::{AmazonBedrockApi, AnthropicApi, OpenAIApi};
+use crate::apis::{AmazonBedrockApi, AnthropicApi, GeminiApi, OpenAIApi};
 use crate::clients::endpoints::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
 use std::fmt::Display;
 
@@ -10,6 +10,9 @@ pub enum ProviderId {
     Deepseek,
     Groq,
     Gemini,
+    /// Gemini served through its native generateContent API rather than the OpenAI
+    /// compatible endpoint, selected with `api_interface: native`
+    GeminiNative,
     Anthropic,
     GitHub,
     Arch,
@@ -21,11 +24,26 @@ pub enum ProviderId {
     Zhipu,
     Qwen,
     AmazonBedrock,
+    VertexAI,
+    /// Claude served by Vertex AI through the Anthropic Messages API, selected by the
+    /// model of a `vertex_ai` provider
+    VertexAnthropic,
+    /// The Codex backend of ChatGPT, which takes the OAuth tokens of ChatGPT Plus and Pro
+    /// accounts and only speaks the Responses API
+    ChatGPT,
+    Custom,
 }
 
 impl From<&str> for ProviderId {
     fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
+        ProviderId::from_name(value).unwrap_or_else(|| panic!("Unknown provider: {}", value))
+    }
+}
+
+impl ProviderId {
+    /// Parses a provider name case insensitively, None when the provider is unknown
+    pub fn from_name(value: &str) -> Option<Self> {
+        let provider = match value.to_lowercase().as_str() {
             "openai" => ProviderId::OpenAI,
             "mistral" => ProviderId::Mistral,
             "deepseek" => ProviderId::Deepseek,
@@ -42,12 +60,14 @@ impl From<&str> for ProviderId {
             "zhipu" => ProviderId::Zhipu,
             "qwen" => ProviderId::Qwen, // alias for Qwen
             "amazon_bedrock" => ProviderId::AmazonBedrock,
-            _ => panic!("Unknown provider: {}", value),
-        }
+            "vertex_ai" => ProviderId::VertexAI,
+            "chatgpt" => ProviderId::ChatGPT,
+            "custom" => ProviderId::Custom,
+            _ => return None,
+        };
+        Some(provider)
     }
-}
 
-impl ProviderId {
     /// Given a client API, return the compatible upstream API for this provider
     pub fn compatible_api_for_client(
         &self,
@@ -83,7 +103,8 @@ impl ProviderId {
                 | ProviderId::Ollama
                 | ProviderId::Moonshotai
                 | ProviderId::Zhipu
-                | ProviderId::Qwen,
+                | ProviderId::Qwen
+                | ProviderId::Custom,
                 SupportedAPIsFromClient::AnthropicMessagesAPI(_),
             ) => SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
 
@@ -101,7 +122,8 @@ impl ProviderId {
                 | ProviderId::Ollama
                 | ProviderId::Moonshotai
                 | ProviderId::Zhipu
-                | ProviderId::Qwen,
+                | ProviderId::Qwen
+                | ProviderId::Custom,
                 SupportedAPIsFromClient::OpenAIChatCompletions(_),
             ) => SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions),
 
@@ -139,6 +161,25 @@ impl ProviderId {
                 }
             }
 
+            // Claude on Vertex AI only has the Messages API, whatever the client API is
+            (ProviderId::VertexAnthropic, _) => {
+                SupportedUpstreamAPIs::AnthropicMessagesAPI(AnthropicApi::Messages)
+            }
+
+            // The Codex backend only takes Responses API requests, whatever the client API is
+            (ProviderId::ChatGPT, _) => {
+                SupportedUpstreamAPIs::OpenAIResponsesAPI(OpenAIApi::Responses)
+            }
+
+            // Native Gemini and Vertex AI speak generateContent whatever the client API is
+            (ProviderId::GeminiNative | ProviderId::VertexAI, _) => {
+                if is_streaming {
+                    SupportedUpstreamAPIs::GeminiGenerateContent(GeminiApi::StreamGenerateContent)
+                } else {
+                    SupportedUpstreamAPIs::GeminiGenerateContent(GeminiApi::GenerateContent)
+                }
+            }
+
             // Non-OpenAI providers: if client requested the Responses API, fall back to Chat Completions
             (_, SupportedAPIsFromClient::OpenAIResponsesAPI(_)) => {
                 SupportedUpstreamAPIs::OpenAIChatCompletions(OpenAIApi::ChatCompletions)
@@ -154,7 +195,7 @@ impl Display for ProviderId {
             ProviderId::Mistral => write!(f, "Mistral"),
             ProviderId::Deepseek => write!(f, "Deepseek"),
             ProviderId::Groq => write!(f, "Groq"),
-            ProviderId::Gemini => write!(f, "Gemini"),
+            ProviderId::Gemini | ProviderId::GeminiNative => write!(f, "Gemini"),
             ProviderId::Anthropic => write!(f, "Anthropic"),
             ProviderId::GitHub => write!(f, "GitHub"),
             ProviderId::Arch => write!(f, "Arch"),
@@ -166,6 +207,9 @@ impl Display for ProviderId {
             ProviderId::Zhipu => write!(f, "zhipu"),
             ProviderId::Qwen => write!(f, "qwen"),
             ProviderId::AmazonBedrock => write!(f, "amazon_bedrock"),
+            ProviderId::VertexAI | ProviderId::VertexAnthropic => write!(f, "vertex_ai"),
+            ProviderId::ChatGPT => write!(f, "chatgpt"),
+            ProviderId::Custom => write!(f, "custom"),
         }
     }
 }
@@ -3,12 +3,29 @@
 //! This module contains provider-specific implementations that handle
 //! request/response conversion for different LLM service APIs.
 //!
+pub mod claude_models;
+pub mod context_window;
+pub mod error;
 pub mod id;
+pub mod params;
+pub mod reasoning;
+pub mod repair;
 pub mod request;
 pub mod response;
 pub mod streaming_response;
+pub mod system_prompt;
 
+pub use claude_models::{claude_model_id, is_claude_model};
+pub use context_window::{known_context_window, MaxTokensAdjustment};
+pub use error::UpstreamError;
 pub use id::ProviderId;
+pub use params::{ParamAdjustments, SamplingCapabilities};
+pub use reasoning::{reasoning_effort_for_thinking, thinking_for_reasoning_effort};
+pub use repair::{repair_response_body, RepairAction, RepairedResponse};
 pub use request::{ProviderRequest, ProviderRequestError, ProviderRequestType};
-pub use response::{ProviderResponse, ProviderResponseType, TokenUsage};
+pub use response::{
+    scan_trailing_usage_counts, scan_usage_counts, ProviderResponse, ProviderResponseType,
+    TokenUsage,
+};
 pub use streaming_response::{ProviderStreamResponse, ProviderStreamResponseType};
+pub use system_prompt::SystemPromptMode;
@@ -0,0 +1,287 @@
+//! Sampling parameter mapping per provider
+//!
+//! Providers disagree on which sampling parameters they accept and in what range.
+//! This module keeps a small capability table and applies it to the client request
+//! before it is converted for the upstream, so that unsupported parameters are dropped
+//! (and reported), out of range values are clamped, and requests that can't be honored
+//! are rejected up front.
+
+use crate::apis::anthropic::MessagesRequest;
+use crate::apis::openai::ChatCompletionsRequest;
+use crate::providers::id::ProviderId;
+use crate::providers::request::{ProviderRequestError, ProviderRequestType};
+
+/// What to do with a numeric sampling parameter for a given provider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamAction {
+    /// Forward unchanged
+    Keep,
+    /// Remove from the request, the provider doesn't support it
+    Drop,
+    /// Forward, clamped to the provider's accepted range
+    Clamp { min: f32, max: f32 },
+}
+
+/// Sampling parameter support for a provider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingCapabilities {
+    pub frequency_penalty: ParamAction,
+    pub presence_penalty: ParamAction,
+    pub seed: ParamAction,
+    /// Maximum number of stop sequences, None when unbounded
+    pub max_stop_sequences: Option<usize>,
+}
+
+const OPENAI_PENALTY_RANGE: ParamAction = ParamAction::Clamp {
+    min: -2.0,
+    max: 2.0,
+};
+
+impl SamplingCapabilities {
+    pub fn for_provider(provider_id: ProviderId) -> Self {
+        match provider_id {
+            ProviderId::OpenAI | ProviderId::AzureOpenAI | ProviderId::GitHub => Self {
+                frequency_penalty: OPENAI_PENALTY_RANGE,
+                presence_penalty: OPENAI_PENALTY_RANGE,
+                seed: ParamAction::Keep,
+                max_stop_sequences: Some(4),
+            },
+            ProviderId::Gemini | ProviderId::GeminiNative | ProviderId::VertexAI => Self {
+                frequency_penalty: OPENAI_PENALTY_RANGE,
+                presence_penalty: OPENAI_PENALTY_RANGE,
+                seed: ParamAction::Keep,
+                max_stop_sequences: Some(5),
+            },
+            // The Messages API has no penalties or seed
+            ProviderId::Anthropic | ProviderId::VertexAnthropic => Self {
+                frequency_penalty: ParamAction::Drop,
+                presence_penalty: ParamAction::Drop,
+                seed: ParamAction::Drop,
+                max_stop_sequences: None,
+            },
+            // The Responses API has no penalties, seed or stop sequences
+            ProviderId::ChatGPT => Self {
+                frequency_penalty: ParamAction::Drop,
+                presence_penalty: ParamAction::Drop,
+                seed: ParamAction::Drop,
+                max_stop_sequences: None,
+            },
+            // Converse only exposes temperature, top_p, max_tokens and stop sequences
+            ProviderId::AmazonBedrock => Self {
+                frequency_penalty: ParamAction::Drop,
+                presence_penalty: ParamAction::Drop,
+                seed: ParamAction::Drop,
+                max_stop_sequences: Some(4),
+            },
+            ProviderId::Mistral
+            | ProviderId::Deepseek
+            | ProviderId::Groq
+            | ProviderId::XAI
+            | ProviderId::TogetherAI
+            | ProviderId::Ollama
+            | ProviderId::Moonshotai
+            | ProviderId::Zhipu
+            | ProviderId::Qwen
+            | ProviderId::Arch
+            | ProviderId::Custom => Self {
+                frequency_penalty: OPENAI_PENALTY_RANGE,
+                presence_penalty: OPENAI_PENALTY_RANGE,
+                seed: ParamAction::Keep,
+                max_stop_sequences: None,
+            },
+        }
+    }
+}
+
+/// Parameters that were changed while applying the capability table
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamAdjustments {
+    pub dropped: Vec<&'static str>,
+    pub clamped: Vec<&'static str>,
+}
+
+impl ParamAdjustments {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.clamped.is_empty()
+    }
+
+    fn apply_f32(&mut self, name: &'static str, value: &mut Option<f32>, action: ParamAction) {
+        let Some(current) = *value else {
+            return;
+        };
+        match action {
+            ParamAction::Keep => {}
+            ParamAction::Drop => {
+                *value = None;
+                self.dropped.push(name);
+            }
+            ParamAction::Clamp { min, max } => {
+                let clamped = current.clamp(min, max);
+                if clamped != current {
+                    *value = Some(clamped);
+                    self.clamped.push(name);
+                }
+            }
+        }
+    }
+
+    fn apply_seed(&mut self, value: &mut Option<i32>, action: ParamAction) {
+        if value.is_some() && action == ParamAction::Drop {
+            *value = None;
+            self.dropped.push("seed");
+        }
+    }
+}
+
+fn check_stop_sequences(
+    name: &str,
+    stop: Option<&Vec<String>>,
+    provider_id: ProviderId,
+    capabilities: &SamplingCapabilities,
+) -> Result<(), ProviderRequestError> {
+    match (stop, capabilities.max_stop_sequences) {
+        (Some(stop), Some(max)) if stop.len() > max => Err(ProviderRequestError {
+            message: format!(
+                "{} has {} entries but provider {} accepts at most {}",
+                name,
+                stop.len(),
+                provider_id,
+                max
+            ),
+            source: None,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn apply_to_chat_completions(
+    req: &mut ChatCompletionsRequest,
+    provider_id: ProviderId,
+    capabilities: &SamplingCapabilities,
+) -> Result<ParamAdjustments, ProviderRequestError> {
+    check_stop_sequences("stop", req.stop.as_ref(), provider_id, capabilities)?;
+
+    let mut adjustments = ParamAdjustments::default();
+    adjustments.apply_f32(
+        "frequency_penalty",
+        &mut req.frequency_penalty,
+        capabilities.frequency_penalty,
+    );
+    adjustments.apply_f32(
+        "presence_penalty",
+        &mut req.presence_penalty,
+        capabilities.presence_penalty,
+    );
+    adjustments.apply_seed(&mut req.seed, capabilities.seed);
+    Ok(adjustments)
+}
+
+fn apply_to_messages(
+    req: &MessagesRequest,
+    provider_id: ProviderId,
+    capabilities: &SamplingCapabilities,
+) -> Result<ParamAdjustments, ProviderRequestError> {
+    check_stop_sequences(
+        "stop_sequences",
+        req.stop_sequences.as_ref(),
+        provider_id,
+        capabilities,
+    )?;
+    Ok(ParamAdjustments::default())
+}
+
+impl ProviderRequestType {
+    /// Apply the provider's sampling capability table to the client request.
+    /// Returns the parameters that were dropped or clamped, or an error when the
+    /// request can't be honored by the provider.
+    pub fn apply_sampling_capabilities(
+        &mut self,
+        provider_id: ProviderId,
+    ) -> Result<ParamAdjustments, ProviderRequestError> {
+        let capabilities = SamplingCapabilities::for_provider(provider_id);
+        match self {
+            ProviderRequestType::ChatCompletionsRequest(req) => {
+                apply_to_chat_completions(req, provider_id, &capabilities)
+            }
+            ProviderRequestType::MessagesRequest(req) => {
+                apply_to_messages(req, provider_id, &capabilities)
+            }
+            ProviderRequestType::BedrockConverse(_)
+            | ProviderRequestType::BedrockConverseStream(_)
+            | ProviderRequestType::ResponsesAPIRequest(_)
+            | ProviderRequestType::GeminiGenerateContent(_) => Ok(ParamAdjustments::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_request() -> ChatCompletionsRequest {
+        ChatCompletionsRequest {
+            model: "gpt-4o".to_string(),
+            frequency_penalty: Some(3.5),
+            presence_penalty: Some(0.5),
+            seed: Some(42),
+            stop: Some(vec!["a".into(), "b".into()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_openai_clamps_penalties() {
+        let mut request = ProviderRequestType::ChatCompletionsRequest(chat_request());
+        let adjustments = request
+            .apply_sampling_capabilities(ProviderId::OpenAI)
+            .unwrap();
+
+        assert_eq!(adjustments.clamped, vec!["frequency_penalty"]);
+        assert!(adjustments.dropped.is_empty());
+        if let ProviderRequestType::ChatCompletionsRequest(req) = request {
+            assert_eq!(req.frequency_penalty, Some(2.0));
+            assert_eq!(req.presence_penalty, Some(0.5));
+            assert_eq!(req.seed, Some(42));
+        } else {
+            panic!("Expected ChatCompletionsRequest");
+        }
+    }
+
+    #[test]
+    fn test_anthropic_drops_penalties_and_seed() {
+        let mut request = ProviderRequestType::ChatCompletionsRequest(chat_request());
+        let adjustments = request
+            .apply_sampling_capabilities(ProviderId::Anthropic)
+            .unwrap();
+
+        assert_eq!(
+            adjustments.dropped,
+            vec!["frequency_penalty", "presence_penalty", "seed"]
+        );
+        if let ProviderRequestType::ChatCompletionsRequest(req) = request {
+            assert_eq!(req.frequency_penalty, None);
+            assert_eq!(req.presence_penalty, None);
+            assert_eq!(req.seed, None);
+            assert_eq!(req.stop.map(|s| s.len()), Some(2));
+        } else {
+            panic!("Expected ChatCompletionsRequest");
+        }
+    }
+
+    #[test]
+    fn test_too_many_stop_sequences_rejected() {
+        let mut chat = chat_request();
+        chat.stop = Some((0..5).map(|i| i.to_string()).collect());
+        let mut request = ProviderRequestType::ChatCompletionsRequest(chat);
+
+        let err = request
+            .apply_sampling_capabilities(ProviderId::AmazonBedrock)
+            .unwrap_err();
+        assert!(err.message.contains("at most 4"));
+
+        // Providers without a limit accept them
+        assert!(request
+            .apply_sampling_capabilities(ProviderId::Anthropic)
+            .is_ok());
+    }
+}
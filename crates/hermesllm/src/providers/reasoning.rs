@@ -0,0 +1,177 @@
+//! Reasoning effort and extended thinking
+//!
+//! OpenAI models take a `reasoning_effort` while Claude models take an extended thinking
+//! budget in tokens. This module knows the thinking budgets of Claude models that
+//! support extended thinking and maps between the two when requests are translated.
+
+use crate::apis::anthropic::ThinkingConfig;
+
+/// Smallest thinking budget Anthropic accepts
+pub const MIN_THINKING_BUDGET_TOKENS: u32 = 1_024;
+
+/// Thinking budget of each reasoning effort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThinkingBudgets {
+    pub low: u32,
+    pub medium: u32,
+    pub high: u32,
+}
+
+impl ThinkingBudgets {
+    const fn new(low: u32, medium: u32, high: u32) -> Self {
+        ThinkingBudgets { low, medium, high }
+    }
+}
+
+/// Thinking budgets by model id prefix of the Claude models with extended thinking.
+/// More specific prefixes come first.
+const THINKING_BUDGETS: &[(&str, ThinkingBudgets)] = &[
+    ("claude-opus-4", ThinkingBudgets::new(4_096, 16_384, 32_000)),
+    (
+        "claude-sonnet-4",
+        ThinkingBudgets::new(2_048, 8_192, 24_576),
+    ),
+    ("claude-haiku-4", ThinkingBudgets::new(1_024, 4_096, 16_384)),
+    (
+        "claude-3-7-sonnet",
+        ThinkingBudgets::new(2_048, 8_192, 24_576),
+    ),
+];
+
+/// Budgets used to read the effort of a thinking budget sent to a model without its own
+const DEFAULT_THINKING_BUDGETS: ThinkingBudgets = ThinkingBudgets::new(2_048, 8_192, 24_576);
+
+/// Thinking budgets of a Claude model with extended thinking, matched like
+/// [`known_context_window`](super::known_context_window) on the model id without any
+/// provider or region prefix
+pub fn thinking_budgets(model: &str) -> Option<ThinkingBudgets> {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let candidates =
+        std::iter::once(model).chain(model.match_indices('.').map(|(i, _)| &model[i + 1..]));
+    candidates
+        .filter_map(|candidate| {
+            THINKING_BUDGETS
+                .iter()
+                .find(|(prefix, _)| candidate.starts_with(prefix))
+        })
+        .map(|(_, budgets)| *budgets)
+        .next()
+}
+
+/// Whether an OpenAI model accepts `reasoning_effort` (o-series and gpt-5 models)
+pub fn is_openai_reasoning_model(model: &str) -> bool {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    model.starts_with("gpt-5")
+        || model
+            .strip_prefix('o')
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Extended thinking for a `reasoning_effort` sent to a Claude model. The budget stays
+/// below `max_tokens`, which counts thinking tokens too. None when the model has no
+/// extended thinking, the effort turns reasoning off or `max_tokens` leaves no room for
+/// the smallest budget.
+pub fn thinking_for_reasoning_effort(
+    model: &str,
+    reasoning_effort: &str,
+    max_tokens: u32,
+) -> Option<ThinkingConfig> {
+    let budgets = thinking_budgets(model)?;
+    let budget = match reasoning_effort {
+        "minimal" => MIN_THINKING_BUDGET_TOKENS,
+        "low" => budgets.low,
+        "medium" => budgets.medium,
+        "high" => budgets.high,
+        _ => return None,
+    };
+    let budget = budget.min(max_tokens.saturating_sub(1));
+    if budget < MIN_THINKING_BUDGET_TOKENS {
+        return None;
+    }
+    Some(ThinkingConfig {
+        thinking_type: "enabled".to_string(),
+        budget_tokens: Some(budget),
+    })
+}
+
+/// `reasoning_effort` for extended thinking sent to an OpenAI reasoning model, the
+/// effort whose budget is closest above the thinking budget
+pub fn reasoning_effort_for_thinking(model: &str, thinking: &ThinkingConfig) -> Option<String> {
+    if thinking.thinking_type != "enabled" || !is_openai_reasoning_model(model) {
+        return None;
+    }
+    let budgets = DEFAULT_THINKING_BUDGETS;
+    let effort = match thinking.budget_tokens {
+        Some(budget) if budget <= budgets.low => "low",
+        Some(budget) if budget <= budgets.medium => "medium",
+        Some(_) => "high",
+        None => "medium",
+    };
+    Some(effort.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(budget_tokens: Option<u32>) -> ThinkingConfig {
+        ThinkingConfig {
+            thinking_type: "enabled".to_string(),
+            budget_tokens,
+        }
+    }
+
+    #[test]
+    fn test_thinking_budgets() {
+        assert_eq!(
+            thinking_budgets("claude-sonnet-4-20250514").map(|b| b.medium),
+            Some(8_192)
+        );
+        assert_eq!(
+            thinking_budgets("us.anthropic.claude-opus-4-1-20250805-v1:0").map(|b| b.high),
+            Some(32_000)
+        );
+        assert_eq!(thinking_budgets("anthropic/claude-3-5-sonnet-latest"), None);
+        assert_eq!(thinking_budgets("gpt-4o"), None);
+    }
+
+    #[test]
+    fn test_thinking_for_reasoning_effort() {
+        let thinking = thinking_for_reasoning_effort("claude-sonnet-4-5", "high", 32_000).unwrap();
+        assert_eq!(thinking.thinking_type, "enabled");
+        assert_eq!(thinking.budget_tokens, Some(24_576));
+
+        // the budget stays below max_tokens
+        let thinking = thinking_for_reasoning_effort("claude-sonnet-4-5", "high", 4_096).unwrap();
+        assert_eq!(thinking.budget_tokens, Some(4_095));
+
+        assert!(thinking_for_reasoning_effort("claude-sonnet-4-5", "low", 1_000).is_none());
+        assert!(thinking_for_reasoning_effort("claude-sonnet-4-5", "none", 4_096).is_none());
+        assert!(thinking_for_reasoning_effort("claude-3-5-haiku", "high", 4_096).is_none());
+    }
+
+    #[test]
+    fn test_reasoning_effort_for_thinking() {
+        assert_eq!(
+            reasoning_effort_for_thinking("o3-mini", &enabled(Some(1_024))).as_deref(),
+            Some("low")
+        );
+        assert_eq!(
+            reasoning_effort_for_thinking("openai/gpt-5", &enabled(Some(16_000))).as_deref(),
+            Some("high")
+        );
+        assert_eq!(
+            reasoning_effort_for_thinking("o4-mini", &enabled(None)).as_deref(),
+            Some("medium")
+        );
+        assert_eq!(
+            reasoning_effort_for_thinking("gpt-4o", &enabled(Some(8_000))),
+            None
+        );
+        let disabled = ThinkingConfig {
+            thinking_type: "disabled".to_string(),
+            budget_tokens: None,
+        };
+        assert_eq!(reasoning_effort_for_thinking("o3", &disabled), None);
+    }
+}
@@ -0,0 +1,317 @@
+//! Repair of malformed upstream responses
+//!
+//! Some providers intermittently return bodies that are cut off, contain invalid UTF-8
+//! or leave out fields every client expects. This module turns such bodies into a
+//! response that parses, keeping whatever content the provider did send.
+
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+
+use crate::clients::endpoints::{SupportedAPIsFromClient, SupportedUpstreamAPIs};
+use crate::providers::id::ProviderId;
+
+/// What was done to a response body to make it parse
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepairAction {
+    /// Invalid UTF-8 sequences were replaced with U+FFFD
+    ReplacedInvalidUtf8,
+    /// Unterminated strings, objects and arrays were closed, dropping a partial member
+    CompletedTruncatedJson,
+    /// A required field missing from the response was filled with a default
+    SynthesizedField(&'static str),
+}
+
+impl Display for RepairAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepairAction::ReplacedInvalidUtf8 => write!(f, "replaced_invalid_utf8"),
+            RepairAction::CompletedTruncatedJson => write!(f, "completed_truncated_json"),
+            RepairAction::SynthesizedField(field) => write!(f, "synthesized_{}", field),
+        }
+    }
+}
+
+/// Response body after repair, along with what was repaired
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairedResponse {
+    pub body: Vec<u8>,
+    pub actions: Vec<RepairAction>,
+}
+
+/// Repairs the body of a non streaming upstream response in the api the provider
+/// answers `client_api` with. Returns None when there is nothing to repair or the body
+/// can't be made into json.
+pub fn repair_response_body(
+    body: &[u8],
+    client_api: &SupportedAPIsFromClient,
+    provider_id: &ProviderId,
+) -> Option<RepairedResponse> {
+    let mut actions = Vec::new();
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => text.to_string(),
+        Err(_) => {
+            actions.push(RepairAction::ReplacedInvalidUtf8);
+            String::from_utf8_lossy(body).into_owned()
+        }
+    };
+
+    let mut value = match serde_json::from_str::<Value>(&text) {
+        Ok(value) => value,
+        Err(_) => {
+            let completed = complete_truncated_json(&text)?;
+            actions.push(RepairAction::CompletedTruncatedJson);
+            serde_json::from_str(&completed).ok()?
+        }
+    };
+
+    let upstream_api = provider_id.compatible_api_for_client(client_api, false);
+    let fields = value.as_object_mut()?;
+    match upstream_api {
+        SupportedUpstreamAPIs::OpenAIChatCompletions(_) => {
+            synthesize_chat_completion_fields(fields, &mut actions)
+        }
+        SupportedUpstreamAPIs::AnthropicMessagesAPI(_) => {
+            synthesize_messages_fields(fields, &mut actions)
+        }
+        _ => {}
+    }
+
+    if actions.is_empty() {
+        return None;
+    }
+    Some(RepairedResponse {
+        body: serde_json::to_vec(&value).ok()?,
+        actions,
+    })
+}
+
+/// Closes the open strings, arrays and objects of json that was cut off. Members that
+/// were only partially received are dropped. Returns None for json that isn't truncated.
+pub fn complete_truncated_json(text: &str) -> Option<String> {
+    let mut closers = Vec::new();
+    // places the json can be cut at, with the containers open at that point
+    let mut cut_points: Vec<(usize, Vec<char>)> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                closers.push(if c == '{' { '}' } else { ']' });
+                cut_points.push((i + 1, closers.clone()));
+            }
+            '}' | ']' if closers.last() != Some(&c) => return None,
+            '}' | ']' => {
+                closers.pop();
+            }
+            ',' => cut_points.push((i, closers.clone())),
+            _ => {}
+        }
+    }
+    if closers.is_empty() && !in_string {
+        return None;
+    }
+
+    let close = |prefix: &str, closers: &[char]| {
+        let mut completed = prefix.trim_end().to_string();
+        completed.extend(closers.iter().rev());
+        completed
+    };
+
+    // keep the last member when only its closing quote and brackets are missing
+    let mut prefix = text.to_string();
+    if in_string {
+        if escaped {
+            prefix.pop();
+        }
+        prefix.push('"');
+    }
+    let completed = close(&prefix, &closers);
+    if serde_json::from_str::<Value>(&completed).is_ok() {
+        return Some(completed);
+    }
+
+    cut_points.into_iter().rev().find_map(|(end, closers)| {
+        let completed = close(&text[..end], &closers);
+        serde_json::from_str::<Value>(&completed)
+            .is_ok()
+            .then_some(completed)
+    })
+}
+
+fn synthesize(
+    fields: &mut Map<String, Value>,
+    field: &'static str,
+    default: Value,
+    actions: &mut Vec<RepairAction>,
+) {
+    if fields.get(field).is_none_or(Value::is_null) {
+        fields.insert(field.to_string(), default);
+        actions.push(RepairAction::SynthesizedField(field));
+    }
+}
+
+fn synthesize_chat_completion_fields(
+    fields: &mut Map<String, Value>,
+    actions: &mut Vec<RepairAction>,
+) {
+    synthesize(fields, "id", json!(""), actions);
+    synthesize(fields, "created", json!(0), actions);
+    synthesize(fields, "model", json!(""), actions);
+    synthesize(fields, "choices", json!([]), actions);
+    synthesize(
+        fields,
+        "usage",
+        json!({"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0}),
+        actions,
+    );
+    let choices = fields.get_mut("choices").and_then(Value::as_array_mut);
+    for (index, choice) in choices.into_iter().flatten().enumerate() {
+        if let Some(choice) = choice.as_object_mut() {
+            synthesize(choice, "index", json!(index), actions);
+            synthesize(choice, "message", json!({"role": "assistant"}), actions);
+        }
+    }
+}
+
+fn synthesize_messages_fields(fields: &mut Map<String, Value>, actions: &mut Vec<RepairAction>) {
+    synthesize(fields, "id", json!(""), actions);
+    synthesize(fields, "type", json!("message"), actions);
+    synthesize(fields, "role", json!("assistant"), actions);
+    synthesize(fields, "content", json!([]), actions);
+    synthesize(fields, "model", json!(""), actions);
+    synthesize(fields, "stop_reason", json!("end_turn"), actions);
+    synthesize(
+        fields,
+        "usage",
+        json!({"input_tokens": 0, "output_tokens": 0}),
+        actions,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::endpoints::SupportedAPIsFromClient;
+    use crate::providers::response::ProviderResponseType;
+
+    fn chat_completions() -> SupportedAPIsFromClient {
+        SupportedAPIsFromClient::from_endpoint("/v1/chat/completions").unwrap()
+    }
+
+    #[test]
+    fn test_complete_truncated_json() {
+        assert_eq!(complete_truncated_json(r#"{"a":1}"#), None);
+        assert_eq!(
+            complete_truncated_json(r#"{"a":"hel"#).as_deref(),
+            Some(r#"{"a":"hel"}"#)
+        );
+        assert_eq!(
+            complete_truncated_json(r#"{"a":[1,2"#).as_deref(),
+            Some(r#"{"a":[1,2]}"#)
+        );
+        // partial members are dropped
+        assert_eq!(
+            complete_truncated_json(r#"{"a":1,"b"#).as_deref(),
+            Some(r#"{"a":1}"#)
+        );
+        assert_eq!(
+            complete_truncated_json(r#"{"a":1,"b":tr"#).as_deref(),
+            Some(r#"{"a":1}"#)
+        );
+        assert_eq!(
+            complete_truncated_json(r#"{"a":"x\"#).as_deref(),
+            Some(r#"{"a":"x"}"#)
+        );
+        assert_eq!(complete_truncated_json(r#"{"a":1]"#), None);
+    }
+
+    #[test]
+    fn test_repair_truncated_chat_completion() {
+        let body = br#"{"id":"chatcmpl-1","object":"chat.completion","created":1,"model":"gpt-4o","choices":[{"index":0,"message":{"role":"assistant","content":"The capital of France is Par"#;
+        let repaired =
+            repair_response_body(body, &chat_completions(), &ProviderId::OpenAI).unwrap();
+        assert_eq!(
+            repaired.actions,
+            vec![
+                RepairAction::CompletedTruncatedJson,
+                RepairAction::SynthesizedField("usage")
+            ]
+        );
+        let response = ProviderResponseType::try_from((
+            &repaired.body[..],
+            &chat_completions(),
+            &ProviderId::OpenAI,
+        ))
+        .unwrap();
+        match response {
+            ProviderResponseType::ChatCompletionsResponse(response) => assert_eq!(
+                response.choices[0].message.content.as_deref(),
+                Some("The capital of France is Par")
+            ),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_repair_missing_choices_and_invalid_utf8() {
+        let mut body = br#"{"id":"chatcmpl-1","created":1,"model":"gpt-4o","usage":{"prompt_tokens":1,"completion_tokens":0,"total_tokens":1},"note":""#.to_vec();
+        body.extend_from_slice(&[0xff, 0xfe]);
+        body.extend_from_slice(br#""}"#);
+        let repaired =
+            repair_response_body(&body, &chat_completions(), &ProviderId::OpenAI).unwrap();
+        assert_eq!(
+            repaired.actions,
+            vec![
+                RepairAction::ReplacedInvalidUtf8,
+                RepairAction::SynthesizedField("choices")
+            ]
+        );
+        assert!(ProviderResponseType::try_from((
+            &repaired.body[..],
+            &chat_completions(),
+            &ProviderId::OpenAI,
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_nothing_to_repair() {
+        let body = br#"{"id":"chatcmpl-1","created":1,"model":"gpt-4o","choices":[],"usage":{"prompt_tokens":1,"completion_tokens":0,"total_tokens":1}}"#;
+        assert_eq!(
+            repair_response_body(body, &chat_completions(), &ProviderId::OpenAI),
+            None
+        );
+        assert_eq!(
+            repair_response_body(b"<html>", &chat_completions(), &ProviderId::OpenAI),
+            None
+        );
+    }
+
+    #[test]
+    fn test_repair_messages_response() {
+        let client_api = SupportedAPIsFromClient::from_endpoint("/v1/messages").unwrap();
+        let body = br#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4","content":[{"type":"text","text":"Hi"}],"usage":{"input_tokens":3,"output_tokens":1}}"#;
+        let repaired = repair_response_body(body, &client_api, &ProviderId::Anthropic).unwrap();
+        assert_eq!(
+            repaired.actions,
+            vec![RepairAction::SynthesizedField("stop_reason")]
+        );
+        assert!(ProviderResponseType::try_from((
+            &repaired.body[..],
+            &client_api,
+            &ProviderId::Anthropic,
+        ))
+        .is_ok());
+    }
+}
@@ -2,10 +2,12 @@ use crate::apis::anthropic::MessagesRequest;
 use crate::apis::openai::ChatCompletionsRequest;
 
 use crate::apis::amazon_bedrock::{ConverseRequest, ConverseStreamRequest};
+use crate::apis::gemini::GenerateContentRequest;
 use crate::apis::openai_responses::ResponsesAPIRequest;
 use crate::clients::endpoints::SupportedAPIsFromClient;
 use crate::clients::endpoints::SupportedUpstreamAPIs;
 
+use crate::providers::id::ProviderId;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
@@ -18,6 +20,7 @@ pub enum ProviderRequestType {
     BedrockConverse(ConverseRequest),
     BedrockConverseStream(ConverseStreamRequest),
     ResponsesAPIRequest(ResponsesAPIRequest),
+    GeminiGenerateContent(GenerateContentRequest),
     //add more request types here
 }
 pub trait ProviderRequest: Send + Sync {
@@ -58,6 +61,15 @@ pub trait ProviderRequest: Send + Sync {
     fn set_messages(&mut self, messages: &[crate::apis::openai::Message]);
 }
 
+/// Messages API version of Claude on Vertex AI
+const VERTEX_ANTHROPIC_VERSION: &str = "vertex-2023-10-16";
+
+/// Instructions of Codex backend requests without a system prompt, the backend requires them
+pub const CHATGPT_DEFAULT_INSTRUCTIONS: &str = "You are a helpful assistant.";
+
+/// Metadata key of the end user in Anthropic Messages requests
+const ANTHROPIC_USER_ID_KEY: &str = "user_id";
+
 impl ProviderRequestType {
     /// Set message history from OpenAI Message format
     /// This converts OpenAI messages to the appropriate format for each provider type
@@ -68,6 +80,85 @@ impl ProviderRequestType {
             Self::BedrockConverse(r) => r.set_messages(messages),
             Self::BedrockConverseStream(r) => r.set_messages(messages),
             Self::ResponsesAPIRequest(r) => r.set_messages(messages),
+            Self::GeminiGenerateContent(r) => r.set_messages(messages),
+        }
+    }
+
+    /// End user of the request, OpenAI's `user` or Anthropic's `metadata.user_id`
+    pub fn user(&self) -> Option<&str> {
+        match self {
+            Self::ChatCompletionsRequest(r) => r.user.as_deref(),
+            Self::ResponsesAPIRequest(r) => r.user.as_deref(),
+            Self::MessagesRequest(r) => r
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(ANTHROPIC_USER_ID_KEY))
+                .and_then(Value::as_str),
+            _ => None,
+        }
+    }
+
+    /// Replaces the end user of the request, requests without one are left as they are
+    pub fn set_user(&mut self, user: String) {
+        match self {
+            Self::ChatCompletionsRequest(r) if r.user.is_some() => r.user = Some(user),
+            Self::ResponsesAPIRequest(r) if r.user.is_some() => r.user = Some(user),
+            Self::MessagesRequest(r) => {
+                if let Some(user_id) = r
+                    .metadata
+                    .as_mut()
+                    .and_then(|metadata| metadata.get_mut(ANTHROPIC_USER_ID_KEY))
+                {
+                    *user_id = Value::String(user);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Serializes the request for the provider. Claude on Vertex AI takes the model from
+    /// the path and the API version in the body rather than a header. The Codex backend of
+    /// ChatGPT only streams, keeps nothing and rejects the sampling and token limits.
+    pub fn to_upstream_bytes(
+        &self,
+        provider_id: ProviderId,
+    ) -> Result<Vec<u8>, ProviderRequestError> {
+        match (provider_id, self) {
+            (ProviderId::VertexAnthropic, Self::MessagesRequest(r)) => {
+                let mut body = serde_json::to_value(r).map_err(|e| ProviderRequestError {
+                    message: format!("Failed to serialize MessagesRequest: {}", e),
+                    source: Some(Box::new(e)),
+                })?;
+                if let Some(body) = body.as_object_mut() {
+                    body.remove("model");
+                    body.insert(
+                        "anthropic_version".to_string(),
+                        Value::String(VERTEX_ANTHROPIC_VERSION.to_string()),
+                    );
+                }
+                serde_json::to_vec(&body).map_err(|e| ProviderRequestError {
+                    message: format!("Failed to serialize MessagesRequest: {}", e),
+                    source: Some(Box::new(e)),
+                })
+            }
+            (ProviderId::ChatGPT, Self::ResponsesAPIRequest(r)) => {
+                let mut r = r.clone();
+                r.store = Some(false);
+                r.stream = Some(true);
+                r.instructions
+                    .get_or_insert_with(|| CHATGPT_DEFAULT_INSTRUCTIONS.to_string());
+                r.max_output_tokens = None;
+                r.temperature = None;
+                r.top_p = None;
+                r.metadata = None;
+                r.user = None;
+                r.previous_response_id = None;
+                serde_json::to_vec(&r).map_err(|e| ProviderRequestError {
+                    message: format!("Failed to serialize ResponsesAPIRequest: {}", e),
+                    source: Some(Box::new(e)),
+                })
+            }
+            _ => self.to_bytes(),
         }
     }
 }
@@ -80,6 +171,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.model(),
             Self::BedrockConverseStream(r) => r.model(),
             Self::ResponsesAPIRequest(r) => r.model(),
+            Self::GeminiGenerateContent(r) => r.model(),
         }
     }
 
@@ -90,6 +182,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.set_model(model),
             Self::BedrockConverseStream(r) => r.set_model(model),
             Self::ResponsesAPIRequest(r) => r.set_model(model),
+            Self::GeminiGenerateContent(r) => r.set_model(model),
         }
     }
 
@@ -100,6 +193,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(_) => false,
             Self::BedrockConverseStream(_) => true,
             Self::ResponsesAPIRequest(r) => r.is_streaming(),
+            Self::GeminiGenerateContent(r) => r.is_streaming(),
         }
     }
 
@@ -110,6 +204,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.extract_messages_text(),
             Self::BedrockConverseStream(r) => r.extract_messages_text(),
             Self::ResponsesAPIRequest(r) => r.extract_messages_text(),
+            Self::GeminiGenerateContent(r) => r.extract_messages_text(),
         }
     }
 
@@ -120,6 +215,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.get_recent_user_message(),
             Self::BedrockConverseStream(r) => r.get_recent_user_message(),
             Self::ResponsesAPIRequest(r) => r.get_recent_user_message(),
+            Self::GeminiGenerateContent(r) => r.get_recent_user_message(),
         }
     }
 
@@ -130,6 +226,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.get_tool_names(),
             Self::BedrockConverseStream(r) => r.get_tool_names(),
             Self::ResponsesAPIRequest(r) => r.get_tool_names(),
+            Self::GeminiGenerateContent(r) => r.get_tool_names(),
         }
     }
 
@@ -140,6 +237,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.to_bytes(),
             Self::BedrockConverseStream(r) => r.to_bytes(),
             Self::ResponsesAPIRequest(r) => r.to_bytes(),
+            Self::GeminiGenerateContent(r) => r.to_bytes(),
         }
     }
 
@@ -150,6 +248,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.metadata(),
             Self::BedrockConverseStream(r) => r.metadata(),
             Self::ResponsesAPIRequest(r) => r.metadata(),
+            Self::GeminiGenerateContent(r) => r.metadata(),
         }
     }
 
@@ -160,6 +259,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.remove_metadata_key(key),
             Self::BedrockConverseStream(r) => r.remove_metadata_key(key),
             Self::ResponsesAPIRequest(r) => r.remove_metadata_key(key),
+            Self::GeminiGenerateContent(r) => r.remove_metadata_key(key),
         }
     }
 
@@ -170,6 +270,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.get_temperature(),
             Self::BedrockConverseStream(r) => r.get_temperature(),
             Self::ResponsesAPIRequest(r) => r.get_temperature(),
+            Self::GeminiGenerateContent(r) => r.get_temperature(),
         }
     }
 
@@ -180,6 +281,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.get_messages(),
             Self::BedrockConverseStream(r) => r.get_messages(),
             Self::ResponsesAPIRequest(r) => r.get_messages(),
+            Self::GeminiGenerateContent(r) => r.get_messages(),
         }
     }
 
@@ -190,6 +292,7 @@ impl ProviderRequest for ProviderRequestType {
             Self::BedrockConverse(r) => r.set_messages(messages),
             Self::BedrockConverseStream(r) => r.set_messages(messages),
             Self::ResponsesAPIRequest(r) => r.set_messages(messages),
+            Self::GeminiGenerateContent(r) => r.set_messages(messages),
         }
     }
 }
@@ -281,13 +384,34 @@ impl TryFrom<(ProviderRequestType, &SupportedUpstreamAPIs)> for ProviderRequestT
                 Ok(ProviderRequestType::BedrockConverseStream(bedrock_req))
             }
             (
-                ProviderRequestType::ChatCompletionsRequest(_),
+                ProviderRequestType::ChatCompletionsRequest(chat_req),
+                SupportedUpstreamAPIs::GeminiGenerateContent(_),
+            ) => {
+                let gemini_req = GenerateContentRequest::try_from(chat_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ChatCompletionsRequest to Gemini request: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+                Ok(ProviderRequestType::GeminiGenerateContent(gemini_req))
+            }
+            (
+                ProviderRequestType::ChatCompletionsRequest(chat_req),
                 SupportedUpstreamAPIs::OpenAIResponsesAPI(_),
             ) => {
-                Err(ProviderRequestError {
-                    message: "Conversion from ChatCompletionsRequest to ResponsesAPIRequest is not supported. ResponsesAPI can only be used as a client API, not as an upstream API.".to_string(),
-                    source: None,
-                })
+                let responses_req = ResponsesAPIRequest::try_from(chat_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ChatCompletionsRequest to ResponsesAPIRequest: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+                Ok(ProviderRequestType::ResponsesAPIRequest(responses_req))
             }
 
             // ============================================================================
@@ -341,14 +465,57 @@ impl TryFrom<(ProviderRequestType, &SupportedUpstreamAPIs)> for ProviderRequestT
                 })?;
                 Ok(ProviderRequestType::BedrockConverseStream(bedrock_req))
             }
+            // Messages -> Gemini (via ChatCompletions)
+            (
+                ProviderRequestType::MessagesRequest(messages_req),
+                SupportedUpstreamAPIs::GeminiGenerateContent(_),
+            ) => {
+                let chat_req = ChatCompletionsRequest::try_from(messages_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert MessagesRequest to ChatCompletionsRequest: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+
+                let gemini_req = GenerateContentRequest::try_from(chat_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ChatCompletionsRequest to Gemini request: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+                Ok(ProviderRequestType::GeminiGenerateContent(gemini_req))
+            }
+            // Messages -> ResponsesAPI (via ChatCompletions)
             (
-                ProviderRequestType::MessagesRequest(_),
+                ProviderRequestType::MessagesRequest(messages_req),
                 SupportedUpstreamAPIs::OpenAIResponsesAPI(_),
             ) => {
-                Err(ProviderRequestError {
-                    message: "Conversion from MessagesRequest to ResponsesAPIRequest is not supported. ResponsesAPI can only be used as a client API, not as an upstream API.".to_string(),
-                    source: None,
-                })
+                let chat_req = ChatCompletionsRequest::try_from(messages_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert MessagesRequest to ChatCompletionsRequest: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+
+                let responses_req = ResponsesAPIRequest::try_from(chat_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ChatCompletionsRequest to ResponsesAPIRequest: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+                Ok(ProviderRequestType::ResponsesAPIRequest(responses_req))
             }
 
             // ============================================================================
@@ -460,6 +627,33 @@ impl TryFrom<(ProviderRequestType, &SupportedUpstreamAPIs)> for ProviderRequestT
                 Ok(ProviderRequestType::BedrockConverseStream(bedrock_req))
             }
 
+            // ResponsesAPI -> Gemini (via ChatCompletions)
+            (
+                ProviderRequestType::ResponsesAPIRequest(responses_req),
+                SupportedUpstreamAPIs::GeminiGenerateContent(_),
+            ) => {
+                let chat_req = ChatCompletionsRequest::try_from(responses_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ResponsesAPIRequest to ChatCompletionsRequest: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+
+                let gemini_req = GenerateContentRequest::try_from(chat_req).map_err(|e| {
+                    ProviderRequestError {
+                        message: format!(
+                            "Failed to convert ChatCompletionsRequest to Gemini request: {}",
+                            e
+                        ),
+                        source: Some(Box::new(e)),
+                    }
+                })?;
+                Ok(ProviderRequestType::GeminiGenerateContent(gemini_req))
+            }
+
             // ============================================================================
             // Amazon Bedrock conversions (not supported as client API)
             // ============================================================================
@@ -477,6 +671,13 @@ impl TryFrom<(ProviderRequestType, &SupportedUpstreamAPIs)> for ProviderRequestT
                     source: None,
                 })
             }
+
+            (ProviderRequestType::GeminiGenerateContent(_), _) => {
+                Err(ProviderRequestError {
+                    message: "Gemini generateContent is not supported as a client API. Only OpenAI ChatCompletions, Anthropic Messages, and OpenAI Responses APIs are supported as client APIs.".to_string(),
+                    source: None,
+                })
+            }
         }
     }
 }
@@ -892,9 +1093,10 @@ mod tests {
     }
 
     #[test]
-    fn test_chat_completions_to_responses_api_not_supported() {
+    fn test_chat_completions_to_responses_api() {
         use crate::apis::openai::OpenAIApi::Responses;
         use crate::apis::openai::{Message, MessageContent, Role};
+        use crate::apis::openai_responses::InputParam;
 
         let chat_req = ChatCompletionsRequest {
             model: "gpt-4".to_string(),
@@ -914,17 +1116,21 @@ mod tests {
             &upstream_api,
         ));
 
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err
-            .message
-            .contains("ResponsesAPI can only be used as a client API"));
+        match result.unwrap() {
+            ProviderRequestType::ResponsesAPIRequest(responses_req) => {
+                assert!(
+                    matches!(responses_req.input, InputParam::Items(ref items) if items.len() == 1)
+                );
+            }
+            _ => panic!("Expected ResponsesAPIRequest variant"),
+        }
     }
 
     #[test]
-    fn test_anthropic_messages_to_responses_api_not_supported() {
+    fn test_anthropic_messages_to_responses_api() {
         use crate::apis::anthropic::MessagesRequest as AnthropicMessagesRequest;
         use crate::apis::openai::OpenAIApi::Responses;
+        use crate::apis::openai_responses::InputParam;
 
         let messages_req = AnthropicMessagesRequest {
             model: "claude-3-sonnet".to_string(),
@@ -956,11 +1162,14 @@ mod tests {
             &upstream_api,
         ));
 
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err
-            .message
-            .contains("ResponsesAPI can only be used as a client API"));
+        match result.unwrap() {
+            ProviderRequestType::ResponsesAPIRequest(responses_req) => {
+                assert!(
+                    matches!(responses_req.input, InputParam::Items(ref items) if items.len() == 1)
+                );
+            }
+            _ => panic!("Expected ResponsesAPIRequest variant"),
+        }
     }
 
     #[test]
@@ -1096,4 +1305,92 @@ mod tests {
         assert_eq!(messages[0].role, crate::apis::openai::Role::System);
         assert_eq!(messages[1].role, crate::apis::openai::Role::User);
     }
+
+    #[test]
+    fn test_vertex_anthropic_body() {
+        let req = json!({
+            "model": "claude-sonnet-4@20250514",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "Hello!"}]
+        });
+        let bytes = serde_json::to_vec(&req).unwrap();
+        let endpoint = SupportedAPIsFromClient::AnthropicMessagesAPI(Messages);
+        let request = ProviderRequestType::try_from((bytes.as_slice(), &endpoint)).unwrap();
+
+        let body: Value = serde_json::from_slice(
+            &request
+                .to_upstream_bytes(ProviderId::VertexAnthropic)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(body["anthropic_version"], "vertex-2023-10-16");
+        assert!(body.get("model").is_none());
+
+        let body: Value =
+            serde_json::from_slice(&request.to_upstream_bytes(ProviderId::Anthropic).unwrap())
+                .unwrap();
+        assert_eq!(body["model"], "claude-sonnet-4@20250514");
+        assert!(body.get("anthropic_version").is_none());
+    }
+
+    #[test]
+    fn test_chatgpt_body() {
+        let req = json!({
+            "model": "gpt-5-codex",
+            "max_tokens": 100,
+            "temperature": 0.2,
+            "messages": [{"role": "user", "content": "Hello!"}]
+        });
+        let bytes = serde_json::to_vec(&req).unwrap();
+        let endpoint = SupportedAPIsFromClient::OpenAIChatCompletions(ChatCompletions);
+        let request = ProviderRequestType::try_from((bytes.as_slice(), &endpoint)).unwrap();
+        let upstream_api = ProviderId::ChatGPT.compatible_api_for_client(&endpoint, true);
+        let request = ProviderRequestType::try_from((request, &upstream_api)).unwrap();
+
+        let body: Value =
+            serde_json::from_slice(&request.to_upstream_bytes(ProviderId::ChatGPT).unwrap())
+                .unwrap();
+        assert_eq!(body["store"], false);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["instructions"], CHATGPT_DEFAULT_INSTRUCTIONS);
+        assert_eq!(body["input"][0]["content"], "Hello!");
+        assert!(body.get("max_output_tokens").is_none());
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn test_user_of_openai_and_anthropic_requests() {
+        let req = json!({
+            "model": "gpt-4o",
+            "user": "alice@example.com",
+            "messages": [{"role": "user", "content": "Hello!"}]
+        });
+        let bytes = serde_json::to_vec(&req).unwrap();
+        let endpoint = SupportedAPIsFromClient::OpenAIChatCompletions(ChatCompletions);
+        let mut request = ProviderRequestType::try_from((bytes.as_slice(), &endpoint)).unwrap();
+        assert_eq!(request.user(), Some("alice@example.com"));
+        request.set_user("hashed".to_string());
+        assert_eq!(request.user(), Some("hashed"));
+
+        let req = json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "metadata": {"user_id": "alice@example.com"},
+            "messages": [{"role": "user", "content": "Hello!"}]
+        });
+        let bytes = serde_json::to_vec(&req).unwrap();
+        let endpoint = SupportedAPIsFromClient::AnthropicMessagesAPI(Messages);
+        let mut request = ProviderRequestType::try_from((bytes.as_slice(), &endpoint)).unwrap();
+        assert_eq!(request.user(), Some("alice@example.com"));
+        request.set_user("hashed".to_string());
+        assert_eq!(request.user(), Some("hashed"));
+
+        // requests without a user don't get one
+        let req = json!({"model": "gpt-4o", "messages": []});
+        let bytes = serde_json::to_vec(&req).unwrap();
+        let endpoint = SupportedAPIsFromClient::OpenAIChatCompletions(ChatCompletions);
+        let mut request = ProviderRequestType::try_from((bytes.as_slice(), &endpoint)).unwrap();
+        request.set_user("hashed".to_string());
+        assert_eq!(request.user(), None);
+    }
 }
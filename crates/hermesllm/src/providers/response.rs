@@ -1,11 +1,12 @@
 use crate::apis::amazon_bedrock::ConverseResponse;
 use crate::apis::anthropic::MessagesResponse;
+use crate::apis::gemini::GenerateContentResponse;
 use crate::apis::openai::ChatCompletionsResponse;
 use crate::apis::openai_responses::ResponsesAPIResponse;
 use crate::clients::endpoints::SupportedAPIsFromClient;
 use crate::clients::endpoints::SupportedUpstreamAPIs;
 use crate::providers::id::ProviderId;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
@@ -62,6 +63,59 @@ impl ProviderResponse for ProviderResponseType {
     }
 }
 
+/// Token counts of a response in any of the client apis, the other fields are skipped
+#[derive(Deserialize)]
+struct UsageScan {
+    usage: Option<ScannedUsage>,
+}
+
+#[derive(Deserialize)]
+struct ScannedUsage {
+    #[serde(alias = "input_tokens")]
+    prompt_tokens: Option<usize>,
+    #[serde(alias = "output_tokens")]
+    completion_tokens: Option<usize>,
+    total_tokens: Option<usize>,
+}
+
+/// Reads the token counts of a non streaming response without parsing it into the api
+/// types. The fields other than `usage` are skipped as they are read, so none of the
+/// body is copied however large it is. The whole body is still checked to be valid
+/// json, an error means it has to be repaired or rejected.
+pub fn scan_usage_counts(bytes: &[u8]) -> serde_json::Result<Option<(usize, usize, usize)>> {
+    let scan: UsageScan = serde_json::from_slice(bytes)?;
+    Ok(scan.usage.map(|usage| {
+        let prompt_tokens = usage.prompt_tokens.unwrap_or_default();
+        let completion_tokens = usage.completion_tokens.unwrap_or_default();
+        let total_tokens = usage
+            .total_tokens
+            .unwrap_or(prompt_tokens + completion_tokens);
+        (prompt_tokens, completion_tokens, total_tokens)
+    }))
+}
+
+/// Reads the token counts from the end of a non streaming response, for bodies that are
+/// forwarded as they arrive and never held whole. The last `"usage"` key of the tail is
+/// taken, the apis all put usage after the content. None when the tail has no usage.
+pub fn scan_trailing_usage_counts(tail: &[u8]) -> Option<(usize, usize, usize)> {
+    const USAGE_KEY: &[u8] = b"\"usage\"";
+    let start = tail
+        .windows(USAGE_KEY.len())
+        .rposition(|window| window == USAGE_KEY)?;
+    let rest = tail[start + USAGE_KEY.len()..].trim_ascii_start();
+    let rest = rest.strip_prefix(b":")?;
+    let usage = serde_json::Deserializer::from_slice(rest)
+        .into_iter::<ScannedUsage>()
+        .next()?
+        .ok()?;
+    let prompt_tokens = usage.prompt_tokens.unwrap_or_default();
+    let completion_tokens = usage.completion_tokens.unwrap_or_default();
+    let total_tokens = usage
+        .total_tokens
+        .unwrap_or(prompt_tokens + completion_tokens);
+    Some((prompt_tokens, completion_tokens, total_tokens))
+}
+
 // --- Response transformation logic for client API compatibility ---
 impl TryFrom<(&[u8], &SupportedAPIsFromClient, &ProviderId)> for ProviderResponseType {
     type Error = std::io::Error;
@@ -70,7 +124,23 @@ impl TryFrom<(&[u8], &SupportedAPIsFromClient, &ProviderId)> for ProviderRespons
         (bytes, client_api, provider_id): (&[u8], &SupportedAPIsFromClient, &ProviderId),
     ) -> Result<Self, Self::Error> {
         let upstream_api = provider_id.compatible_api_for_client(client_api, false);
-        match (&upstream_api, client_api) {
+        ProviderResponseType::try_from((bytes, client_api, &upstream_api))
+    }
+}
+
+/// A response of the upstream api converted to the client's api, for upstreams that
+/// aren't providers, e.g. agents
+impl TryFrom<(&[u8], &SupportedAPIsFromClient, &SupportedUpstreamAPIs)> for ProviderResponseType {
+    type Error = std::io::Error;
+
+    fn try_from(
+        (bytes, client_api, upstream_api): (
+            &[u8],
+            &SupportedAPIsFromClient,
+            &SupportedUpstreamAPIs,
+        ),
+    ) -> Result<Self, Self::Error> {
+        match (upstream_api, client_api) {
             (
                 SupportedUpstreamAPIs::OpenAIChatCompletions(_),
                 SupportedAPIsFromClient::OpenAIChatCompletions(_),
@@ -238,6 +308,39 @@ impl TryFrom<(&[u8], &SupportedAPIsFromClient, &ProviderId)> for ProviderRespons
                     response_api,
                 )))
             }
+            // Gemini transformations, every client api goes through ChatCompletions
+            (SupportedUpstreamAPIs::GeminiGenerateContent(_), client_api) => {
+                let gemini_resp: GenerateContentResponse = serde_json::from_slice(bytes)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+                let chat_resp: ChatCompletionsResponse = gemini_resp.try_into().map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Gemini to ChatCompletions transformation error: {}", e),
+                    )
+                })?;
+                let transformation_error = |e: crate::clients::TransformError| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Transformation error: {}", e),
+                    )
+                };
+                match client_api {
+                    SupportedAPIsFromClient::OpenAIChatCompletions(_) => {
+                        Ok(ProviderResponseType::ChatCompletionsResponse(chat_resp))
+                    }
+                    SupportedAPIsFromClient::AnthropicMessagesAPI(_) => {
+                        Ok(ProviderResponseType::MessagesResponse(
+                            chat_resp.try_into().map_err(transformation_error)?,
+                        ))
+                    }
+                    SupportedAPIsFromClient::OpenAIResponsesAPI(_) => {
+                        Ok(ProviderResponseType::ResponsesAPIResponse(Box::new(
+                            chat_resp.try_into().map_err(transformation_error)?,
+                        )))
+                    }
+                }
+            }
             _ => Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Unsupported API combination for response transformation",
@@ -275,6 +378,34 @@ mod tests {
     use crate::providers::id::ProviderId;
     use serde_json::json;
 
+    #[test]
+    fn test_scan_usage_counts() {
+        let chat = br#"{"id":"chatcmpl-1","choices":[{"message":{"content":"a \"quoted\" answer"}}],"usage":{"prompt_tokens":5,"completion_tokens":7,"total_tokens":12}}"#;
+        assert_eq!(scan_usage_counts(chat).unwrap(), Some((5, 7, 12)));
+
+        let messages = br#"{"type":"message","content":[],"usage":{"input_tokens":10,"output_tokens":3,"cache_read_input_tokens":0}}"#;
+        assert_eq!(scan_usage_counts(messages).unwrap(), Some((10, 3, 13)));
+
+        assert_eq!(scan_usage_counts(br#"{"id":"resp-1"}"#).unwrap(), None);
+        assert!(scan_usage_counts(br#"{"id":"chatcmpl-1","choices":[{"#).is_err());
+    }
+
+    #[test]
+    fn test_scan_trailing_usage_counts() {
+        let tail = br#"ing answer"}}],"usage":{"prompt_tokens":5,"completion_tokens":7,"total_tokens":12}}"#;
+        assert_eq!(scan_trailing_usage_counts(tail), Some((5, 7, 12)));
+
+        let tail =
+            br#"ext":"the \"usage\" word"}], "usage" : {"input_tokens":10,"output_tokens":3}}"#;
+        assert_eq!(scan_trailing_usage_counts(tail), Some((10, 3, 13)));
+
+        assert_eq!(
+            scan_trailing_usage_counts(br#"t"}}],"usage":{"prompt_to"#),
+            None
+        );
+        assert_eq!(scan_trailing_usage_counts(br#"t"}}]}"#), None);
+    }
+
     #[test]
     fn test_openai_response_from_bytes() {
         let resp = json!({
@@ -413,4 +544,38 @@ mod tests {
             _ => panic!("Expected ChatCompletionsResponse variant"),
         }
     }
+
+    #[test]
+    fn test_anthropic_response_from_bytes_with_gemini_native_provider() {
+        let resp = json!({
+            "candidates": [
+                {
+                    "content": { "role": "model", "parts": [{ "text": "Hello!" }] },
+                    "finishReason": "STOP",
+                    "index": 0
+                }
+            ],
+            "usageMetadata": {
+                "promptTokenCount": 10,
+                "candidatesTokenCount": 3,
+                "totalTokenCount": 13
+            },
+            "modelVersion": "gemini-2.5-flash",
+            "responseId": "abc"
+        });
+        let bytes = serde_json::to_vec(&resp).unwrap();
+        let result = ProviderResponseType::try_from((
+            bytes.as_slice(),
+            &SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages),
+            &ProviderId::GeminiNative,
+        ));
+        match result.unwrap() {
+            ProviderResponseType::MessagesResponse(r) => {
+                assert_eq!(r.model, "gemini-2.5-flash");
+                assert_eq!(r.usage.input_tokens, 10);
+                assert_eq!(r.usage.output_tokens, 3);
+            }
+            _ => panic!("Expected MessagesResponse variant"),
+        }
+    }
 }
@@ -684,6 +684,7 @@ mod tests {
             usage: None,
             system_fingerprint: None,
             service_tier: None,
+            extensions: Default::default(),
         };
         let provider_type = ProviderStreamResponseType::ChatCompletionsStreamResponse(openai_event);
         assert_eq!(provider_type.event_type(), None);
@@ -0,0 +1,266 @@
+//! System prompt policies
+//!
+//! Operators can add text before or after the system prompt of a request, e.g. a
+//! compliance banner, or replace it altogether. Each api keeps its system prompt in a
+//! different place: a leading system message for chat completions, the `system` field
+//! of Anthropic messages and Bedrock converse requests, `instructions` for the
+//! responses api and `systemInstruction` for Gemini.
+
+use serde::{Deserialize, Serialize};
+
+use crate::apis::amazon_bedrock::{ConverseRequest, SystemContentBlock};
+use crate::apis::anthropic::{MessagesContentBlock, MessagesRequest, MessagesSystemPrompt};
+use crate::apis::gemini::{Content as GeminiContent, GenerateContentRequest, Part as GeminiPart};
+use crate::apis::openai::{ChatCompletionsRequest, ContentPart, Message, MessageContent, Role};
+use crate::providers::request::ProviderRequestType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptMode {
+    /// Text goes before the system prompt of the request
+    Prepend,
+    /// Text goes after the system prompt of the request
+    Append,
+    /// Text is the system prompt, whatever the request sent
+    Replace,
+}
+
+/// Separates the text of the policy from the system prompt of the request
+const SEPARATOR: &str = "\n\n";
+
+fn combine(mode: SystemPromptMode, text: &str, existing: &str) -> String {
+    match mode {
+        SystemPromptMode::Prepend => format!("{}{}{}", text, SEPARATOR, existing),
+        SystemPromptMode::Append => format!("{}{}{}", existing, SEPARATOR, text),
+        SystemPromptMode::Replace => text.to_string(),
+    }
+}
+
+impl ProviderRequestType {
+    /// Applies `text` to the system prompt of the request, adding a system prompt when
+    /// the request has none
+    pub fn apply_system_prompt(&mut self, mode: SystemPromptMode, text: &str) {
+        match self {
+            Self::ChatCompletionsRequest(request) => apply_to_chat(request, mode, text),
+            Self::MessagesRequest(request) => apply_to_messages(request, mode, text),
+            Self::BedrockConverse(request) | Self::BedrockConverseStream(request) => {
+                apply_to_converse(request, mode, text)
+            }
+            Self::ResponsesAPIRequest(request) => {
+                request.instructions = Some(match request.instructions.as_deref() {
+                    Some(existing) if !existing.is_empty() => combine(mode, text, existing),
+                    _ => text.to_string(),
+                });
+            }
+            Self::GeminiGenerateContent(request) => apply_to_gemini(request, mode, text),
+        }
+    }
+}
+
+fn apply_to_chat(request: &mut ChatCompletionsRequest, mode: SystemPromptMode, text: &str) {
+    if mode == SystemPromptMode::Replace {
+        request
+            .messages
+            .retain(|message| message.role != Role::System);
+    }
+    match request.messages.first_mut() {
+        Some(message) if message.role == Role::System => match &mut message.content {
+            MessageContent::Text(existing) => *existing = combine(mode, text, existing),
+            MessageContent::Parts(parts) => {
+                let part = ContentPart::Text {
+                    text: text.to_string(),
+                };
+                match mode {
+                    SystemPromptMode::Prepend => parts.insert(0, part),
+                    _ => parts.push(part),
+                }
+            }
+        },
+        _ => request.messages.insert(
+            0,
+            Message {
+                role: Role::System,
+                content: MessageContent::Text(text.to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            },
+        ),
+    }
+}
+
+fn apply_to_messages(request: &mut MessagesRequest, mode: SystemPromptMode, text: &str) {
+    request.system = Some(match (request.system.take(), mode) {
+        (None, _) | (_, SystemPromptMode::Replace) => {
+            MessagesSystemPrompt::Single(text.to_string())
+        }
+        (Some(MessagesSystemPrompt::Single(existing)), mode) => {
+            MessagesSystemPrompt::Single(combine(mode, text, &existing))
+        }
+        // blocks can carry cache control, the text is added as a block of its own so they
+        // keep their place
+        (Some(MessagesSystemPrompt::Blocks(mut blocks)), mode) => {
+            let block = MessagesContentBlock::Text {
+                text: text.to_string(),
+                cache_control: None,
+            };
+            match mode {
+                SystemPromptMode::Prepend => blocks.insert(0, block),
+                _ => blocks.push(block),
+            }
+            MessagesSystemPrompt::Blocks(blocks)
+        }
+    });
+}
+
+fn apply_to_converse(request: &mut ConverseRequest, mode: SystemPromptMode, text: &str) {
+    let block = SystemContentBlock::Text {
+        text: text.to_string(),
+    };
+    let system = request.system.get_or_insert_with(Vec::new);
+    match mode {
+        SystemPromptMode::Prepend => system.insert(0, block),
+        SystemPromptMode::Append => system.push(block),
+        SystemPromptMode::Replace => *system = vec![block],
+    }
+}
+
+fn apply_to_gemini(request: &mut GenerateContentRequest, mode: SystemPromptMode, text: &str) {
+    let part = GeminiPart::text(text);
+    let instruction = request
+        .system_instruction
+        .get_or_insert_with(|| GeminiContent {
+            role: None,
+            parts: Vec::new(),
+        });
+    match mode {
+        SystemPromptMode::Prepend => instruction.parts.insert(0, part),
+        SystemPromptMode::Append => instruction.parts.push(part),
+        SystemPromptMode::Replace => instruction.parts = vec![part],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::openai_responses::ResponsesAPIRequest;
+    use crate::ProviderRequest;
+    use serde_json::json;
+
+    const BANNER: &str = "Do not share customer data.";
+
+    fn system_prompt(request: &ProviderRequestType) -> serde_json::Value {
+        match request {
+            ProviderRequestType::ChatCompletionsRequest(r) => json!(r.messages[0]),
+            ProviderRequestType::MessagesRequest(r) => json!(r.system),
+            ProviderRequestType::BedrockConverse(r) => json!(r.system),
+            ProviderRequestType::BedrockConverseStream(r) => json!(r.system),
+            ProviderRequestType::ResponsesAPIRequest(r) => json!(r.instructions),
+            ProviderRequestType::GeminiGenerateContent(r) => json!(r.system_instruction),
+        }
+    }
+
+    #[test]
+    fn test_chat_completions() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "hi"}
+            ]
+        }))
+        .unwrap();
+
+        let mut prepended = ProviderRequestType::ChatCompletionsRequest(request.clone());
+        prepended.apply_system_prompt(SystemPromptMode::Prepend, BANNER);
+        let messages = prepended.get_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].content.to_string(),
+            "Do not share customer data.\n\nBe brief."
+        );
+
+        let mut appended = ProviderRequestType::ChatCompletionsRequest(request.clone());
+        appended.apply_system_prompt(SystemPromptMode::Append, BANNER);
+        assert_eq!(
+            appended.get_messages()[0].content.to_string(),
+            "Be brief.\n\nDo not share customer data."
+        );
+
+        let mut replaced = ProviderRequestType::ChatCompletionsRequest(request);
+        replaced.apply_system_prompt(SystemPromptMode::Replace, BANNER);
+        let messages = replaced.get_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].content.to_string(), BANNER);
+    }
+
+    #[test]
+    fn test_chat_completions_without_system_prompt() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let mut request = ProviderRequestType::ChatCompletionsRequest(request);
+        request.apply_system_prompt(SystemPromptMode::Append, BANNER);
+        let messages = request.get_messages();
+        assert_eq!(messages[0].role, Role::System);
+        assert_eq!(messages[0].content.to_string(), BANNER);
+        assert_eq!(messages[1].role, Role::User);
+    }
+
+    #[test]
+    fn test_messages() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "system": [{"type": "text", "text": "Be brief.", "cache_control": {"type": "ephemeral"}}],
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let mut request = ProviderRequestType::MessagesRequest(request);
+        request.apply_system_prompt(SystemPromptMode::Prepend, BANNER);
+        assert_eq!(
+            system_prompt(&request),
+            json!([
+                {"type": "text", "text": BANNER},
+                {"type": "text", "text": "Be brief.", "cache_control": {"type": "ephemeral"}}
+            ])
+        );
+        request.apply_system_prompt(SystemPromptMode::Replace, BANNER);
+        assert_eq!(system_prompt(&request), json!(BANNER));
+    }
+
+    #[test]
+    fn test_converse_and_responses() {
+        let request = ConverseRequest {
+            model_id: "anthropic.claude-sonnet-4".to_string(),
+            system: Some(vec![SystemContentBlock::Text {
+                text: "Be brief.".to_string(),
+            }]),
+            ..Default::default()
+        };
+        let mut request = ProviderRequestType::BedrockConverse(request);
+        request.apply_system_prompt(SystemPromptMode::Append, BANNER);
+        assert_eq!(
+            system_prompt(&request),
+            json!([
+                {"type": "text", "text": "Be brief."},
+                {"type": "text", "text": BANNER}
+            ])
+        );
+
+        let request: ResponsesAPIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "input": "hi",
+            "instructions": "Be brief."
+        }))
+        .unwrap();
+        let mut request = ProviderRequestType::ResponsesAPIRequest(request);
+        request.apply_system_prompt(SystemPromptMode::Prepend, BANNER);
+        assert_eq!(
+            system_prompt(&request),
+            json!("Do not share customer data.\n\nBe brief.")
+        );
+    }
+}
@@ -1,5 +1,9 @@
-use crate::apis::anthropic::{MessagesContentBlock, MessagesImageSource};
-use crate::apis::openai::{ContentPart, FunctionCall, ImageUrl, Message, MessageContent, ToolCall};
+use crate::apis::anthropic::{
+    MessagesContentBlock, MessagesImageSource, MessagesStopReason, ToolResultContent,
+};
+use crate::apis::openai::{
+    ContentPart, FinishReason, FunctionCall, ImageUrl, Message, MessageContent, Role, ToolCall,
+};
 use crate::clients::TransformError;
 use serde_json::Value;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -14,7 +18,12 @@ pub trait ContentUtils<T> {
     fn split_for_openai(&self) -> Result<SplitForOpenAIResult, TransformError>;
 }
 
-pub type SplitForOpenAIResult = (Vec<ContentPart>, Vec<ToolCall>, Vec<(String, String, bool)>);
+/// Content parts, tool calls and tool results (tool use id, content parts, is_error)
+pub type SplitForOpenAIResult = (
+    Vec<ContentPart>,
+    Vec<ToolCall>,
+    Vec<(String, Vec<ContentPart>, bool)>,
+);
 
 /// Helper to create a current unix timestamp
 pub fn current_timestamp() -> u64 {
@@ -57,10 +66,7 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
         })
     }
 
-    fn split_for_openai(
-        &self,
-    ) -> Result<(Vec<ContentPart>, Vec<ToolCall>, Vec<(String, String, bool)>), TransformError>
-    {
+    fn split_for_openai(&self) -> Result<SplitForOpenAIResult, TransformError> {
         let mut content_parts = Vec::new();
         let mut tool_calls = Vec::new();
         let mut tool_results = Vec::new();
@@ -100,10 +106,15 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
                     is_error,
                     ..
                 } => {
-                    let result_text = content.extract_text();
+                    let result_parts = match content {
+                        ToolResultContent::Text(text) => {
+                            vec![ContentPart::Text { text: text.clone() }]
+                        }
+                        ToolResultContent::Blocks(blocks) => convert_tool_result_blocks(blocks),
+                    };
                     tool_results.push((
                         tool_use_id.clone(),
-                        result_text,
+                        result_parts,
                         is_error.unwrap_or(false),
                     ));
                 }
@@ -122,10 +133,9 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
                     content,
                     is_error,
                 } => {
-                    let result_text = content.extract_text();
                     tool_results.push((
                         tool_use_id.clone(),
-                        result_text,
+                        convert_tool_result_blocks(content),
                         is_error.unwrap_or(false),
                     ));
                 }
@@ -140,6 +150,89 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
     }
 }
 
+/// Convert the text and image blocks of a tool result to OpenAI content parts
+fn convert_tool_result_blocks(blocks: &[MessagesContentBlock]) -> Vec<ContentPart> {
+    blocks
+        .iter()
+        .filter_map(|block| match block {
+            MessagesContentBlock::Text { text, .. } => {
+                Some(ContentPart::Text { text: text.clone() })
+            }
+            MessagesContentBlock::Image { source } => Some(ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: convert_image_source_to_url(source),
+                    detail: None,
+                },
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// OpenAI messages for a tool result. Tool messages only take text, so the images a tool
+/// returned follow in a user message.
+pub fn openai_tool_result_messages(tool_call_id: String, parts: Vec<ContentPart>) -> Vec<Message> {
+    let (text_parts, image_parts): (Vec<_>, Vec<_>) = parts
+        .into_iter()
+        .partition(|part| matches!(part, ContentPart::Text { .. }));
+    let mut messages = vec![Message {
+        role: Role::Tool,
+        content: MessageContent::Text(text_parts.extract_text()),
+        name: None,
+        tool_calls: None,
+        tool_call_id: Some(tool_call_id),
+    }];
+    if !image_parts.is_empty() {
+        messages.push(Message {
+            role: Role::User,
+            content: MessageContent::Parts(image_parts),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    messages
+}
+
+/// Anthropic tool result content of an OpenAI tool message, keeping the images of
+/// array content
+pub fn convert_openai_tool_content_to_anthropic(content: &MessageContent) -> ToolResultContent {
+    let blocks = match content {
+        MessageContent::Text(text) => vec![MessagesContentBlock::Text {
+            text: text.clone(),
+            cache_control: None,
+        }],
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => MessagesContentBlock::Text {
+                    text: text.clone(),
+                    cache_control: None,
+                },
+                ContentPart::ImageUrl { image_url } => MessagesContentBlock::Image {
+                    source: convert_image_url_to_source(image_url),
+                },
+            })
+            .collect(),
+    };
+    ToolResultContent::Blocks(blocks)
+}
+
+/// Anthropic stop reason and stop sequence of an OpenAI finish reason. A `stop` that
+/// carries the matched stop sequence ended on that sequence.
+pub fn anthropic_stop_reason(
+    finish_reason: &FinishReason,
+    stop_sequence: Option<&String>,
+) -> (MessagesStopReason, Option<String>) {
+    match (finish_reason, stop_sequence) {
+        (FinishReason::Stop, Some(stop_sequence)) => (
+            MessagesStopReason::StopSequence,
+            Some(stop_sequence.clone()),
+        ),
+        (finish_reason, _) => (finish_reason.clone().into(), None),
+    }
+}
+
 /// Convert image source to URL
 pub fn convert_image_source_to_url(source: &MessagesImageSource) -> String {
     match source {
@@ -1,21 +1,23 @@
 use crate::apis::amazon_bedrock::{
-    AnyChoice, AutoChoice, ContentBlock, ConversationRole, ConverseRequest, ImageBlock,
-    ImageSource, InferenceConfiguration, Message as BedrockMessage, SystemContentBlock,
-    Tool as BedrockTool, ToolChoice as BedrockToolChoice, ToolChoiceSpec, ToolConfiguration,
-    ToolInputSchema, ToolResultBlock, ToolResultContentBlock, ToolResultStatus, ToolSpecDefinition,
-    ToolUseBlock,
+    ContentBlock, ConversationRole, ConverseRequest, ImageBlock, ImageSource,
+    InferenceConfiguration, Message as BedrockMessage, SystemContentBlock, Tool as BedrockTool,
+    ToolConfiguration, ToolInputSchema, ToolResultBlock, ToolResultContentBlock, ToolResultStatus,
+    ToolSpecDefinition, ToolUseBlock,
 };
 use crate::apis::anthropic::{
     MessagesMessage, MessagesMessageContent, MessagesRequest, MessagesRole, MessagesStopReason,
-    MessagesSystemPrompt, MessagesTool, MessagesToolChoice, MessagesToolChoiceType, MessagesUsage,
-    ToolResultContent,
+    MessagesSystemPrompt, MessagesTool, MessagesUsage, ToolResultContent,
 };
 use crate::apis::openai::{
-    ChatCompletionsRequest, ContentPart, FinishReason, Function, FunctionChoice, Message,
-    MessageContent, Role, Tool, ToolCall, ToolChoice, ToolChoiceType, Usage,
+    ChatCompletionsRequest, ContentPart, FinishReason, Function, Message, MessageContent, Role,
+    Tool, ToolCall, Usage,
 };
 use crate::clients::TransformError;
+use crate::providers::reasoning::reasoning_effort_for_thinking;
 use crate::transforms::lib::*;
+use crate::transforms::request::tool_choice::{
+    anthropic_to_bedrock_tool_choice, anthropic_to_openai_tool_choice,
+};
 
 type AnthropicMessagesRequest = MessagesRequest;
 
@@ -40,7 +42,12 @@ impl TryFrom<AnthropicMessagesRequest> for ChatCompletionsRequest {
         // Convert tools and tool choice
         let openai_tools = req.tools.map(convert_anthropic_tools);
         let (openai_tool_choice, parallel_tool_calls) =
-            convert_anthropic_tool_choice(req.tool_choice);
+            anthropic_to_openai_tool_choice(req.tool_choice);
+
+        let reasoning_effort = req
+            .thinking
+            .as_ref()
+            .and_then(|thinking| reasoning_effort_for_thinking(&req.model, thinking));
 
         let mut _chat_completions_req: ChatCompletionsRequest = ChatCompletionsRequest {
             model: req.model,
@@ -53,6 +60,7 @@ impl TryFrom<AnthropicMessagesRequest> for ChatCompletionsRequest {
             tools: openai_tools,
             tool_choice: openai_tool_choice,
             parallel_tool_calls,
+            reasoning_effort,
             ..Default::default()
         };
         _chat_completions_req.suppress_max_tokens_if_o3();
@@ -98,8 +106,9 @@ impl TryFrom<AnthropicMessagesRequest> for ConverseRequest {
 
         // Convert tools and tool choice to ToolConfiguration
         // Only include toolConfig if we have actual tools (Bedrock requires at least 1 tool)
+        let tool_selection = anthropic_to_bedrock_tool_choice(req.tool_choice);
         let tool_config = req.tools.and_then(|anthropic_tools| {
-            if anthropic_tools.is_empty() {
+            if anthropic_tools.is_empty() || !tool_selection.sends_tools(messages.as_deref()) {
                 return None;
             }
 
@@ -116,32 +125,9 @@ impl TryFrom<AnthropicMessagesRequest> for ConverseRequest {
                 })
                 .collect();
 
-            let tool_choice = req.tool_choice.map(|choice| {
-                match choice.kind {
-                    MessagesToolChoiceType::Auto => BedrockToolChoice::Auto {
-                        auto: AutoChoice {},
-                    },
-                    MessagesToolChoiceType::Any => BedrockToolChoice::Any { any: AnyChoice {} },
-                    MessagesToolChoiceType::None => BedrockToolChoice::Auto {
-                        auto: AutoChoice {},
-                    }, // Bedrock doesn't have explicit "none"
-                    MessagesToolChoiceType::Tool => {
-                        if let Some(name) = choice.name {
-                            BedrockToolChoice::Tool {
-                                tool: ToolChoiceSpec { name },
-                            }
-                        } else {
-                            BedrockToolChoice::Auto {
-                                auto: AutoChoice {},
-                            }
-                        }
-                    }
-                }
-            });
-
             Some(ToolConfiguration {
                 tools: Some(tools),
-                tool_choice,
+                tool_choice: tool_selection.into_tool_choice(),
             })
         });
 
@@ -183,14 +169,9 @@ impl TryFrom<MessagesMessage> for Vec<Message> {
             MessagesMessageContent::Blocks(blocks) => {
                 let (content_parts, tool_calls, tool_results) = blocks.split_for_openai()?;
                 // Add tool result messages
-                for (tool_use_id, result_text, _is_error) in tool_results {
-                    result.push(Message {
-                        role: Role::Tool,
-                        content: MessageContent::Text(result_text),
-                        name: None,
-                        tool_calls: None,
-                        tool_call_id: Some(tool_use_id),
-                    });
+                // OpenAI tool messages have no error flag
+                for (tool_use_id, result_parts, _is_error) in tool_results {
+                    result.extend(openai_tool_result_messages(tool_use_id, result_parts));
                 }
 
                 // Only create main message if there's actual content or tool calls
@@ -235,17 +216,22 @@ impl From<FinishReason> for MessagesStopReason {
             FinishReason::ToolCalls => MessagesStopReason::ToolUse,
             FinishReason::ContentFilter => MessagesStopReason::Refusal,
             FinishReason::FunctionCall => MessagesStopReason::ToolUse,
+            FinishReason::Other(_) => MessagesStopReason::EndTurn,
         }
     }
 }
 
 impl From<Usage> for MessagesUsage {
     fn from(val: Usage) -> Self {
+        // Anthropic reports cache reads separately from (and excluded from) input tokens
+        let cache_read_input_tokens = val.cached_prompt_tokens();
         MessagesUsage {
-            input_tokens: val.prompt_tokens,
+            input_tokens: val
+                .prompt_tokens
+                .saturating_sub(cache_read_input_tokens.unwrap_or(0)),
             output_tokens: val.completion_tokens,
             cache_creation_input_tokens: None,
-            cache_read_input_tokens: None,
+            cache_read_input_tokens,
         }
     }
 }
@@ -285,34 +271,6 @@ fn convert_anthropic_tools(tools: Vec<MessagesTool>) -> Vec<Tool> {
         .collect()
 }
 
-/// Convert Anthropic tool choice to OpenAI format
-fn convert_anthropic_tool_choice(
-    tool_choice: Option<MessagesToolChoice>,
-) -> (Option<ToolChoice>, Option<bool>) {
-    match tool_choice {
-        Some(choice) => {
-            let openai_choice = match choice.kind {
-                MessagesToolChoiceType::Auto => ToolChoice::Type(ToolChoiceType::Auto),
-                MessagesToolChoiceType::Any => ToolChoice::Type(ToolChoiceType::Required),
-                MessagesToolChoiceType::None => ToolChoice::Type(ToolChoiceType::None),
-                MessagesToolChoiceType::Tool => {
-                    if let Some(name) = choice.name {
-                        ToolChoice::Function {
-                            choice_type: "function".to_string(),
-                            function: FunctionChoice { name },
-                        }
-                    } else {
-                        ToolChoice::Type(ToolChoiceType::Auto)
-                    }
-                }
-            };
-            let parallel = choice.disable_parallel_tool_use.map(|disable| !disable);
-            (Some(openai_choice), parallel)
-        }
-        None => (None, None),
-    }
-}
-
 /// Build OpenAI message content from parts and tool calls
 fn build_openai_content(
     content_parts: Vec<ContentPart>,
@@ -384,8 +342,19 @@ impl TryFrom<MessagesMessage> for BedrockMessage {
                                 ToolResultContent::Blocks(blocks) => {
                                     let mut result_blocks = Vec::new();
                                     for result_block in blocks {
-                                        if let crate::apis::anthropic::MessagesContentBlock::Text { text, .. } = result_block {
-                                            result_blocks.push(ToolResultContentBlock::Text { text });
+                                        match result_block {
+                                            crate::apis::anthropic::MessagesContentBlock::Text { text, .. } => {
+                                                result_blocks.push(ToolResultContentBlock::Text { text });
+                                            }
+                                            crate::apis::anthropic::MessagesContentBlock::Image {
+                                                source: crate::apis::anthropic::MessagesImageSource::Base64 { media_type, data },
+                                            } => {
+                                                result_blocks.push(ToolResultContentBlock::Image {
+                                                    source: ImageSource::Base64 { media_type, data },
+                                                });
+                                            }
+                                            // Bedrock doesn't support URL-based images
+                                            _ => {}
                                         }
                                     }
                                     result_blocks
@@ -701,4 +670,51 @@ mod tests {
             panic!("Expected text content block");
         }
     }
+
+    #[test]
+    fn test_tool_result_with_image_and_error() {
+        let request: AnthropicMessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "Take a screenshot"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "screenshot", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "is_error": true, "content": [
+                        {"type": "text", "text": "Partial capture"},
+                        {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}}
+                    ]}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let chat_request = ChatCompletionsRequest::try_from(request.clone()).unwrap();
+        let messages = serde_json::to_value(&chat_request.messages).unwrap();
+        assert_eq!(
+            messages[2],
+            json!({"role": "tool", "content": "Partial capture", "tool_call_id": "toolu_1"})
+        );
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(
+            messages[3]["content"][0]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+
+        let bedrock_request = ConverseRequest::try_from(request).unwrap();
+        let messages = serde_json::to_value(bedrock_request.messages.unwrap()).unwrap();
+        assert_eq!(
+            messages[2]["content"][0]["toolResult"],
+            json!({
+                "toolUseId": "toolu_1",
+                "content": [
+                    {"type": "text", "text": "Partial capture"},
+                    {"type": "image", "source": {"type": "base64", "mediaType": "image/png", "data": "iVBORw0KGgo="}}
+                ],
+                "status": "error"
+            })
+        );
+    }
 }
@@ -1,26 +1,39 @@
 use crate::apis::amazon_bedrock::{
-    AnyChoice, AutoChoice, ContentBlock, ConversationRole, ConverseRequest, InferenceConfiguration,
-    Message as BedrockMessage, SystemContentBlock, Tool as BedrockTool,
-    ToolChoice as BedrockToolChoice, ToolChoiceSpec, ToolConfiguration, ToolInputSchema,
-    ToolSpecDefinition,
+    ContentBlock, ConversationRole, ConverseRequest, InferenceConfiguration,
+    Message as BedrockMessage, SystemContentBlock, Tool as BedrockTool, ToolConfiguration,
+    ToolInputSchema, ToolResultContentBlock, ToolSpecDefinition,
 };
 use crate::apis::anthropic::{
     MessagesContentBlock, MessagesMessage, MessagesMessageContent, MessagesRequest, MessagesRole,
-    MessagesSystemPrompt, MessagesTool, MessagesToolChoice, MessagesToolChoiceType,
-    ToolResultContent,
+    MessagesSystemPrompt, MessagesTool,
+};
+use crate::apis::gemini::{
+    gemini_model_name, Blob, Content as GeminiContent, ContentRole, FileData,
+    FunctionCall as GeminiFunctionCall, FunctionDeclaration, FunctionResponse,
+    GenerateContentRequest, GenerationConfig, Part as GeminiPart, ThinkingConfig,
+    Tool as GeminiTool, ToolConfig as GeminiToolConfig,
 };
 use crate::apis::openai::{
-    ChatCompletionsRequest, Message, MessageContent, Role, Tool, ToolChoice, ToolChoiceType,
+    ChatCompletionsRequest, ContentPart, FunctionCall, Message, MessageContent, Role, Tool,
+    ToolCall,
 };
 
 use crate::apis::openai_responses::{
-    InputContent, InputItem, InputParam, MessageRole, Modality, ReasoningEffort,
-    ResponsesAPIRequest, Tool as ResponsesTool, ToolChoice as ResponsesToolChoice,
+    InputContent, InputItem, InputMessage, InputParam, MessageContent as ResponsesMessageContent,
+    MessageRole, Modality, ReasoningEffort, ResponsesAPIRequest, TextConfig, TextFormat,
+    Tool as ResponsesTool,
 };
 use crate::clients::TransformError;
+use crate::providers::reasoning::thinking_for_reasoning_effort;
 use crate::transforms::lib::ExtractText;
 use crate::transforms::lib::*;
+use crate::transforms::request::tool_choice::{
+    openai_to_anthropic_tool_choice, openai_to_bedrock_tool_choice,
+    openai_to_gemini_function_calling, openai_to_responses_tool_choice,
+    responses_to_openai_tool_choice,
+};
 use crate::transforms::*;
+use std::collections::HashMap;
 
 type AnthropicMessagesRequest = MessagesRequest;
 
@@ -59,10 +72,7 @@ impl TryFrom<Message> for MessagesMessage {
                         MessagesContentBlock::ToolResult {
                             tool_use_id: tool_call_id,
                             is_error: None,
-                            content: ToolResultContent::Blocks(vec![MessagesContentBlock::Text {
-                                text: message.content.extract_text(),
-                                cache_control: None,
-                            }]),
+                            content: convert_openai_tool_content_to_anthropic(&message.content),
                             cache_control: None,
                         },
                     ]),
@@ -214,19 +224,14 @@ impl TryFrom<Message> for BedrockMessage {
                     )
                 })?;
 
-                let tool_content = message.content.extract_text();
-
-                // Create ToolResult content block
-                let tool_result_content = if tool_content.is_empty() {
+                let mut tool_result_content =
+                    convert_openai_tool_content_to_bedrock(message.content)?;
+                if tool_result_content.is_empty() {
                     // Even for tool results, we need non-empty content
-                    vec![crate::apis::amazon_bedrock::ToolResultContentBlock::Text {
+                    tool_result_content.push(ToolResultContentBlock::Text {
                         text: " ".to_string(),
-                    }]
-                } else {
-                    vec![crate::apis::amazon_bedrock::ToolResultContentBlock::Text {
-                        text: tool_content,
-                    }]
-                };
+                    });
+                }
 
                 content_blocks.push(ContentBlock::ToolResult {
                     tool_result: crate::apis::amazon_bedrock::ToolResultBlock {
@@ -282,28 +287,32 @@ impl TryFrom<ResponsesAPIRequest> for ChatCompletionsRequest {
 
                 // Convert each input item
                 for item in items {
-                    if let InputItem::Message(input_msg) = item {
-                        let role = match input_msg.role {
-                            MessageRole::User => Role::User,
-                            MessageRole::Assistant => Role::Assistant,
-                            MessageRole::System => Role::System,
-                            MessageRole::Developer => Role::System, // Map developer to system
-                        };
-
-                        // Convert content based on MessageContent type
-                        let content = match &input_msg.content {
-                            crate::apis::openai_responses::MessageContent::Text(text) => {
-                                // Simple text content
-                                MessageContent::Text(text.clone())
-                            }
-                            crate::apis::openai_responses::MessageContent::Items(content_items) => {
-                                // Check if it's a single text item (can use simple text format)
-                                if content_items.len() == 1 {
-                                    if let InputContent::InputText { text } = &content_items[0] {
-                                        MessageContent::Text(text.clone())
-                                    } else {
-                                        // Single non-text item - use parts format
-                                        MessageContent::Parts(
+                    match item {
+                        InputItem::Message(input_msg) => {
+                            let role = match input_msg.role {
+                                MessageRole::User => Role::User,
+                                MessageRole::Assistant => Role::Assistant,
+                                MessageRole::System => Role::System,
+                                MessageRole::Developer => Role::System, // Map developer to system
+                            };
+
+                            // Convert content based on MessageContent type
+                            let content = match &input_msg.content {
+                                crate::apis::openai_responses::MessageContent::Text(text) => {
+                                    // Simple text content
+                                    MessageContent::Text(text.clone())
+                                }
+                                crate::apis::openai_responses::MessageContent::Items(
+                                    content_items,
+                                ) => {
+                                    // Check if it's a single text item (can use simple text format)
+                                    if content_items.len() == 1 {
+                                        if let InputContent::InputText { text } = &content_items[0]
+                                        {
+                                            MessageContent::Text(text.clone())
+                                        } else {
+                                            // Single non-text item - use parts format
+                                            MessageContent::Parts(
                                             content_items.iter()
                                                 .filter_map(|c| match c {
                                                     InputContent::InputText { text } => {
@@ -322,13 +331,14 @@ impl TryFrom<ResponsesAPIRequest> for ChatCompletionsRequest {
                                                 })
                                                 .collect()
                                         )
-                                    }
-                                } else {
-                                    // Multiple content items - convert to parts
-                                    MessageContent::Parts(
-                                        content_items
-                                            .iter()
-                                            .filter_map(|c| match c {
+                                        }
+                                    } else {
+                                        // Multiple content items - convert to parts
+                                        MessageContent::Parts(
+                                            content_items
+                                                .iter()
+                                                .filter_map(|c| {
+                                                    match c {
                                                 InputContent::InputText { text } => {
                                                     Some(crate::apis::openai::ContentPart::Text {
                                                         text: text.clone(),
@@ -344,20 +354,78 @@ impl TryFrom<ResponsesAPIRequest> for ChatCompletionsRequest {
                                                 ),
                                                 InputContent::InputFile { .. } => None, // Skip files for now
                                                 InputContent::InputAudio { .. } => None, // Skip audio for now
-                                            })
-                                            .collect(),
-                                    )
+                                            }
+                                                })
+                                                .collect(),
+                                        )
+                                    }
                                 }
+                            };
+
+                            converted_messages.push(Message {
+                                role,
+                                content,
+                                name: None,
+                                tool_call_id: None,
+                                tool_calls: None,
+                            });
+                        }
+                        InputItem::FunctionCall {
+                            call_id,
+                            name,
+                            arguments,
+                            ..
+                        } => {
+                            let tool_call = ToolCall {
+                                id: call_id,
+                                call_type: "function".to_string(),
+                                function: FunctionCall { name, arguments },
+                            };
+                            // Calls made in the same turn belong to one assistant message
+                            match converted_messages.last_mut() {
+                                Some(last) if last.role == Role::Assistant => {
+                                    last.tool_calls.get_or_insert_with(Vec::new).push(tool_call);
+                                }
+                                _ => converted_messages.push(Message {
+                                    role: Role::Assistant,
+                                    content: MessageContent::Text(String::new()),
+                                    name: None,
+                                    tool_call_id: None,
+                                    tool_calls: Some(vec![tool_call]),
+                                }),
                             }
-                        };
-
-                        converted_messages.push(Message {
-                            role,
-                            content,
-                            name: None,
-                            tool_call_id: None,
-                            tool_calls: None,
-                        });
+                        }
+                        InputItem::FunctionCallOutput {
+                            call_id, output, ..
+                        } => {
+                            let parts = match output {
+                                crate::apis::openai_responses::MessageContent::Text(text) => {
+                                    vec![ContentPart::Text { text }]
+                                }
+                                crate::apis::openai_responses::MessageContent::Items(items) => {
+                                    items
+                                        .into_iter()
+                                        .filter_map(|c| match c {
+                                            InputContent::InputText { text } => {
+                                                Some(ContentPart::Text { text })
+                                            }
+                                            InputContent::InputImage { image_url, detail } => {
+                                                Some(ContentPart::ImageUrl {
+                                                    image_url: crate::apis::openai::ImageUrl {
+                                                        url: image_url,
+                                                        detail,
+                                                    },
+                                                })
+                                            }
+                                            InputContent::InputFile { .. } => None, // Skip files for now
+                                            InputContent::InputAudio { .. } => None, // Skip audio for now
+                                        })
+                                        .collect()
+                                }
+                            };
+                            converted_messages.extend(openai_tool_result_messages(call_id, parts));
+                        }
+                        InputItem::ItemReference { .. } => {}
                     }
                 }
 
@@ -430,32 +498,151 @@ impl TryFrom<ResponsesAPIRequest> for ChatCompletionsRequest {
                     }
                 }).collect::<Result<Vec<_>, _>>()
             }).transpose()?,
-            tool_choice: req.tool_choice.map(|choice| {
-                match choice {
-                    ResponsesToolChoice::String(s) => {
-                        match s.as_str() {
-                            "auto" => ToolChoice::Type(ToolChoiceType::Auto),
-                            "required" => ToolChoice::Type(ToolChoiceType::Required),
-                            "none" => ToolChoice::Type(ToolChoiceType::None),
-                            _ => ToolChoice::Type(ToolChoiceType::Auto), // Default to auto for unknown strings
-                        }
+            tool_choice: req.tool_choice.map(responses_to_openai_tool_choice),
+            parallel_tool_calls: req.parallel_tool_calls,
+            ..Default::default()
+        })
+    }
+}
+
+impl TryFrom<ChatCompletionsRequest> for ResponsesAPIRequest {
+    type Error = TransformError;
+
+    fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
+        if req.n.unwrap_or(1) > 1 {
+            return Err(TransformError::UnsupportedCapability(format!(
+                "n={} is not supported by the Responses API; only a single choice can be generated",
+                req.n.unwrap_or(1)
+            )));
+        }
+
+        // System prompts become the instructions, the rest of the conversation the input
+        let mut instructions = Vec::new();
+        let mut items = Vec::new();
+        for message in req.messages {
+            match message.role {
+                Role::System => instructions.push(message.content.extract_text()),
+                Role::User => items.push(InputItem::Message(InputMessage {
+                    role: MessageRole::User,
+                    content: responses_message_content(message.content),
+                })),
+                Role::Assistant => {
+                    let text = message.content.extract_text();
+                    if !text.is_empty() {
+                        items.push(InputItem::Message(InputMessage {
+                            role: MessageRole::Assistant,
+                            content: ResponsesMessageContent::Text(text),
+                        }));
                     }
-                    ResponsesToolChoice::Named { function, .. } => ToolChoice::Function {
-                        choice_type: "function".to_string(),
-                        function: crate::apis::openai::FunctionChoice { name: function.name }
+                    for tool_call in message.tool_calls.unwrap_or_default() {
+                        items.push(InputItem::FunctionCall {
+                            item_type: "function_call".to_string(),
+                            call_id: tool_call.id,
+                            name: tool_call.function.name,
+                            arguments: tool_call.function.arguments,
+                        });
                     }
                 }
-            }),
+                Role::Tool => {
+                    let call_id = message
+                        .tool_call_id
+                        .ok_or_else(|| TransformError::MissingField("tool_call_id".to_string()))?;
+                    items.push(InputItem::FunctionCallOutput {
+                        item_type: "function_call_output".to_string(),
+                        call_id,
+                        output: responses_message_content(message.content),
+                    });
+                }
+            }
+        }
+
+        let reasoning_effort = match req.reasoning_effort.as_deref() {
+            Some("low") => Some(ReasoningEffort::Low),
+            Some("medium") => Some(ReasoningEffort::Medium),
+            Some("high") => Some(ReasoningEffort::High),
+            _ => None,
+        };
+        // json_object is the only response format with the same shape in both APIs
+        let text = req
+            .response_format
+            .as_ref()
+            .filter(|format| format["type"] == "json_object")
+            .map(|_| TextConfig {
+                format: TextFormat::JsonObject,
+            });
+        let tools = req.tools.map(|tools| {
+            tools
+                .into_iter()
+                .map(|tool| ResponsesTool::Function {
+                    name: tool.function.name,
+                    description: tool.function.description,
+                    parameters: Some(tool.function.parameters),
+                    strict: tool.function.strict,
+                })
+                .collect()
+        });
+
+        Ok(ResponsesAPIRequest {
+            model: req.model,
+            input: InputParam::Items(items),
+            include: None,
             parallel_tool_calls: req.parallel_tool_calls,
-            ..Default::default()
+            store: req.store,
+            instructions: (!instructions.is_empty()).then(|| instructions.join("\n\n")),
+            stream: req.stream,
+            stream_options: None,
+            conversation: None,
+            tools,
+            tool_choice: req.tool_choice.map(openai_to_responses_tool_choice),
+            max_output_tokens: req
+                .max_completion_tokens
+                .or(req.max_tokens)
+                .map(|t| t as i32),
+            temperature: req.temperature,
+            top_p: req.top_p,
+            metadata: req.metadata,
+            previous_response_id: None,
+            modalities: None,
+            audio: None,
+            text,
+            reasoning_effort,
+            truncation: None,
+            user: req.user,
+            max_tool_calls: None,
+            service_tier: req.service_tier,
+            background: None,
+            top_logprobs: req.top_logprobs.map(|t| t as i32),
         })
     }
 }
 
+/// Anthropic and Bedrock return a single choice without token logprobs. Reject requests
+/// that depend on either instead of silently returning less than the client asked for.
+fn ensure_single_choice_without_logprobs(
+    req: &ChatCompletionsRequest,
+    target: &str,
+) -> Result<(), TransformError> {
+    if req.n.unwrap_or(1) > 1 {
+        return Err(TransformError::UnsupportedCapability(format!(
+            "n={} is not supported by {}; only a single choice can be generated",
+            req.n.unwrap_or(1),
+            target
+        )));
+    }
+    if req.logprobs.unwrap_or(false) || req.top_logprobs.is_some() {
+        return Err(TransformError::UnsupportedCapability(format!(
+            "logprobs/top_logprobs are not supported by {}",
+            target
+        )));
+    }
+    Ok(())
+}
+
 impl TryFrom<ChatCompletionsRequest> for AnthropicMessagesRequest {
     type Error = TransformError;
 
     fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
+        ensure_single_choice_without_logprobs(&req, "the Anthropic Messages API")?;
         let mut system_prompt = None;
         let mut messages = Vec::new();
 
@@ -472,24 +659,39 @@ impl TryFrom<ChatCompletionsRequest> for AnthropicMessagesRequest {
         }
 
         // Convert tools and tool choice
+        let has_tools = req.tools.as_ref().is_some_and(|tools| !tools.is_empty());
         let anthropic_tools = req.tools.map(convert_openai_tools);
         let anthropic_tool_choice =
-            convert_openai_tool_choice(req.tool_choice, req.parallel_tool_calls);
+            openai_to_anthropic_tool_choice(req.tool_choice, req.parallel_tool_calls, has_tools);
+
+        let max_tokens = req
+            .max_completion_tokens
+            .or(req.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS);
+        let thinking = req
+            .reasoning_effort
+            .as_deref()
+            .and_then(|effort| thinking_for_reasoning_effort(&req.model, effort, max_tokens));
+        // extended thinking only runs with the default temperature and a top_p of at least 0.95
+        let (temperature, top_p) = match thinking {
+            Some(_) => (
+                req.temperature.filter(|temperature| *temperature == 1.0),
+                req.top_p.filter(|top_p| *top_p >= 0.95),
+            ),
+            None => (req.temperature, req.top_p),
+        };
 
         Ok(AnthropicMessagesRequest {
             model: req.model,
             system: system_prompt,
             messages,
-            max_tokens: req
-                .max_completion_tokens
-                .or(req.max_tokens)
-                .unwrap_or(DEFAULT_MAX_TOKENS),
+            max_tokens,
             container: None,
             mcp_servers: None,
             service_tier: None,
-            thinking: None,
-            temperature: req.temperature,
-            top_p: req.top_p,
+            thinking,
+            temperature,
+            top_p,
             top_k: None, // OpenAI doesn't have top_k
             stream: req.stream,
             stop_sequences: req.stop,
@@ -504,6 +706,7 @@ impl TryFrom<ChatCompletionsRequest> for ConverseRequest {
     type Error = TransformError;
 
     fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
+        ensure_single_choice_without_logprobs(&req, "Amazon Bedrock Converse")?;
         // Separate system messages from user/assistant messages
         let mut system_messages = Vec::new();
         let mut conversation_messages = Vec::new();
@@ -556,9 +759,12 @@ impl TryFrom<ChatCompletionsRequest> for ConverseRequest {
         };
 
         // Convert tools and tool choice to ToolConfiguration
-        let tool_config = if req.tools.is_some() || req.tool_choice.is_some() {
-            let tools = req.tools.map(|openai_tools| {
-                openai_tools
+        let tool_selection = openai_to_bedrock_tool_choice(req.tool_choice);
+        let tool_config = match req.tools {
+            Some(openai_tools)
+                if !openai_tools.is_empty() && tool_selection.sends_tools(messages.as_deref()) =>
+            {
+                let tools = openai_tools
                     .into_iter()
                     .map(|tool| BedrockTool::ToolSpec {
                         tool_spec: ToolSpecDefinition {
@@ -569,45 +775,13 @@ impl TryFrom<ChatCompletionsRequest> for ConverseRequest {
                             },
                         },
                     })
-                    .collect()
-            });
-
-            let tool_choice = req
-                .tool_choice
-                .map(|choice| {
-                    match choice {
-                        ToolChoice::Type(tool_type) => match tool_type {
-                            ToolChoiceType::Auto => BedrockToolChoice::Auto {
-                                auto: AutoChoice {},
-                            },
-                            ToolChoiceType::Required => {
-                                BedrockToolChoice::Any { any: AnyChoice {} }
-                            }
-                            ToolChoiceType::None => BedrockToolChoice::Auto {
-                                auto: AutoChoice {},
-                            }, // Bedrock doesn't have explicit "none"
-                        },
-                        ToolChoice::Function { function, .. } => BedrockToolChoice::Tool {
-                            tool: ToolChoiceSpec {
-                                name: function.name,
-                            },
-                        },
-                    }
+                    .collect();
+                Some(ToolConfiguration {
+                    tools: Some(tools),
+                    tool_choice: tool_selection.into_tool_choice(),
                 })
-                .or_else(|| {
-                    // If tools are present but no tool_choice specified, default to "auto"
-                    if tools.is_some() {
-                        Some(BedrockToolChoice::Auto {
-                            auto: AutoChoice {},
-                        })
-                    } else {
-                        None
-                    }
-                });
-
-            Some(ToolConfiguration { tools, tool_choice })
-        } else {
-            None
+            }
+            _ => None,
         };
 
         Ok(ConverseRequest {
@@ -628,7 +802,231 @@ impl TryFrom<ChatCompletionsRequest> for ConverseRequest {
     }
 }
 
+impl TryFrom<ChatCompletionsRequest> for GenerateContentRequest {
+    type Error = TransformError;
+
+    fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
+        ensure_single_choice_without_logprobs(&req, "Gemini")?;
+        let mut system_parts = Vec::new();
+        let mut contents: Vec<GeminiContent> = Vec::new();
+        // Gemini answers a function call by name, OpenAI tool messages only carry the call id
+        let mut function_names: HashMap<String, String> = HashMap::new();
+
+        for message in req.messages {
+            let (role, parts) = match message.role {
+                Role::System => {
+                    system_parts.push(GeminiPart::text(message.content.extract_text()));
+                    continue;
+                }
+                Role::User => (ContentRole::User, gemini_parts(message.content)?),
+                Role::Assistant => {
+                    let mut parts = Vec::new();
+                    let text = message.content.extract_text();
+                    if !text.is_empty() {
+                        parts.push(GeminiPart::text(text));
+                    }
+                    for tool_call in message.tool_calls.unwrap_or_default() {
+                        let args: serde_json::Value =
+                            serde_json::from_str(&tool_call.function.arguments).map_err(|e| {
+                                TransformError::UnsupportedConversion(format!(
+                                    "Failed to parse tool arguments as JSON: {}. Arguments: {}",
+                                    e, tool_call.function.arguments
+                                ))
+                            })?;
+                        function_names.insert(tool_call.id, tool_call.function.name.clone());
+                        parts.push(GeminiPart {
+                            function_call: Some(GeminiFunctionCall {
+                                id: None,
+                                name: tool_call.function.name,
+                                args: Some(args),
+                            }),
+                            ..Default::default()
+                        });
+                    }
+                    (ContentRole::Model, parts)
+                }
+                Role::Tool => {
+                    let tool_call_id = message.tool_call_id.ok_or_else(|| {
+                        TransformError::MissingField(
+                            "tool_call_id required for Tool messages".to_string(),
+                        )
+                    })?;
+                    let name = function_names
+                        .get(&tool_call_id)
+                        .cloned()
+                        .or(message.name)
+                        .ok_or_else(|| {
+                            TransformError::MissingField(format!(
+                                "function name of tool call {}",
+                                tool_call_id
+                            ))
+                        })?;
+                    let text = message.content.extract_text();
+                    // the function response is an object, other results are wrapped
+                    let response = match serde_json::from_str::<serde_json::Value>(&text) {
+                        Ok(value @ serde_json::Value::Object(_)) => value,
+                        _ => serde_json::json!({ "result": text }),
+                    };
+                    let part = GeminiPart {
+                        function_response: Some(FunctionResponse {
+                            id: None,
+                            name,
+                            response,
+                        }),
+                        ..Default::default()
+                    };
+                    (ContentRole::User, vec![part])
+                }
+            };
+            if parts.is_empty() {
+                continue;
+            }
+            // consecutive turns of one role, like the results of parallel tool calls, are
+            // sent as one content
+            match contents.last_mut() {
+                Some(last) if last.role == Some(role) => last.parts.extend(parts),
+                _ => contents.push(GeminiContent {
+                    role: Some(role),
+                    parts,
+                }),
+            }
+        }
+
+        let system_instruction = (!system_parts.is_empty()).then_some(GeminiContent {
+            role: None,
+            parts: system_parts,
+        });
+
+        let (response_mime_type, response_json_schema) = match &req.response_format {
+            Some(format) => match format.get("type").and_then(|t| t.as_str()) {
+                Some("json_object") => (Some("application/json".to_string()), None),
+                Some("json_schema") => (
+                    Some("application/json".to_string()),
+                    format
+                        .get("json_schema")
+                        .and_then(|schema| schema.get("schema"))
+                        .cloned(),
+                ),
+                _ => (None, None),
+            },
+            None => (None, None),
+        };
+        let thinking_config = match req.reasoning_effort.as_deref() {
+            None | Some("none") => None,
+            Some(_) => Some(ThinkingConfig {
+                include_thoughts: Some(true),
+                thinking_budget: None,
+            }),
+        };
+        let generation_config = GenerationConfig {
+            stop_sequences: req.stop,
+            response_mime_type,
+            response_json_schema,
+            candidate_count: None,
+            max_output_tokens: req.max_completion_tokens.or(req.max_tokens),
+            temperature: req.temperature,
+            top_p: req.top_p,
+            top_k: req.top_k,
+            seed: req.seed,
+            presence_penalty: req.presence_penalty,
+            frequency_penalty: req.frequency_penalty,
+            thinking_config,
+        };
+
+        let tools = req.tools.filter(|tools| !tools.is_empty()).map(|tools| {
+            let declarations = tools
+                .into_iter()
+                .map(|tool| FunctionDeclaration {
+                    name: tool.function.name,
+                    description: tool.function.description,
+                    parameters_json_schema: Some(tool.function.parameters),
+                })
+                .collect();
+            vec![GeminiTool {
+                function_declarations: Some(declarations),
+                ..Default::default()
+            }]
+        });
+        let tool_config =
+            openai_to_gemini_function_calling(req.tool_choice).map(|config| GeminiToolConfig {
+                function_calling_config: Some(config),
+            });
+
+        Ok(GenerateContentRequest {
+            contents,
+            system_instruction,
+            tools,
+            tool_config,
+            generation_config: (generation_config != GenerationConfig::default())
+                .then_some(generation_config),
+            safety_settings: None,
+            cached_content: None,
+            model: gemini_model_name(&req.model).to_string(),
+            stream: req.stream.unwrap_or(false),
+            metadata: req.metadata,
+        })
+    }
+}
+
+/// Gemini parts of an OpenAI user message. Data url images are sent inline, other image
+/// urls as file data.
+fn gemini_parts(content: MessageContent) -> Result<Vec<GeminiPart>, TransformError> {
+    let parts = match content {
+        MessageContent::Text(text) => vec![ContentPart::Text { text }],
+        MessageContent::Parts(parts) => parts,
+    };
+    let mut gemini_parts = Vec::new();
+    for part in parts {
+        match part {
+            ContentPart::Text { text } => {
+                if !text.is_empty() {
+                    gemini_parts.push(GeminiPart::text(text));
+                }
+            }
+            ContentPart::ImageUrl { image_url } if image_url.url.starts_with("data:") => {
+                let (mime_type, data) = parse_data_url(&image_url.url).ok_or_else(|| {
+                    TransformError::UnsupportedConversion(format!(
+                        "Invalid data URL format: {}",
+                        image_url.url
+                    ))
+                })?;
+                gemini_parts.push(GeminiPart {
+                    inline_data: Some(Blob { mime_type, data }),
+                    ..Default::default()
+                });
+            }
+            ContentPart::ImageUrl { image_url } => gemini_parts.push(GeminiPart {
+                file_data: Some(FileData {
+                    mime_type: None,
+                    file_uri: image_url.url,
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+    Ok(gemini_parts)
+}
+
 /// Convert OpenAI tools to Anthropic format
+/// Responses API content of a chat message, text stays a string
+fn responses_message_content(content: MessageContent) -> ResponsesMessageContent {
+    match content {
+        MessageContent::Text(text) => ResponsesMessageContent::Text(text),
+        MessageContent::Parts(parts) => ResponsesMessageContent::Items(
+            parts
+                .into_iter()
+                .map(|part| match part {
+                    ContentPart::Text { text } => InputContent::InputText { text },
+                    ContentPart::ImageUrl { image_url } => InputContent::InputImage {
+                        image_url: image_url.url,
+                        detail: image_url.detail,
+                    },
+                })
+                .collect(),
+        ),
+    }
+}
+
 fn convert_openai_tools(tools: Vec<Tool>) -> Vec<MessagesTool> {
     tools
         .into_iter()
@@ -640,37 +1038,6 @@ fn convert_openai_tools(tools: Vec<Tool>) -> Vec<MessagesTool> {
         .collect()
 }
 
-/// Convert OpenAI tool choice to Anthropic format
-fn convert_openai_tool_choice(
-    tool_choice: Option<ToolChoice>,
-    parallel_tool_calls: Option<bool>,
-) -> Option<MessagesToolChoice> {
-    tool_choice.map(|choice| match choice {
-        ToolChoice::Type(tool_type) => match tool_type {
-            ToolChoiceType::Auto => MessagesToolChoice {
-                kind: MessagesToolChoiceType::Auto,
-                name: None,
-                disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
-            },
-            ToolChoiceType::Required => MessagesToolChoice {
-                kind: MessagesToolChoiceType::Any,
-                name: None,
-                disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
-            },
-            ToolChoiceType::None => MessagesToolChoice {
-                kind: MessagesToolChoiceType::None,
-                name: None,
-                disable_parallel_tool_use: None,
-            },
-        },
-        ToolChoice::Function { function, .. } => MessagesToolChoice {
-            kind: MessagesToolChoiceType::Tool,
-            name: Some(function.name),
-            disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
-        },
-    })
-}
-
 /// Build Anthropic message content from content blocks
 fn build_anthropic_content(content_blocks: Vec<MessagesContentBlock>) -> MessagesMessageContent {
     if content_blocks.len() == 1 {
@@ -685,6 +1052,38 @@ fn build_anthropic_content(content_blocks: Vec<MessagesContentBlock>) -> Message
     }
 }
 
+/// Bedrock tool result content of an OpenAI tool message, keeping the images of array
+/// content
+fn convert_openai_tool_content_to_bedrock(
+    content: MessageContent,
+) -> Result<Vec<ToolResultContentBlock>, TransformError> {
+    let parts = match content {
+        MessageContent::Text(text) => vec![ContentPart::Text { text }],
+        MessageContent::Parts(parts) => parts,
+    };
+    let mut blocks = Vec::new();
+    for part in parts {
+        match part {
+            ContentPart::Text { text } => {
+                if !text.is_empty() {
+                    blocks.push(ToolResultContentBlock::Text { text });
+                }
+            }
+            ContentPart::ImageUrl { image_url } => {
+                let (media_type, data) = parse_data_url(&image_url.url).ok_or_else(|| {
+                    TransformError::UnsupportedConversion(
+                        "Only base64 data URLs are supported for images in Bedrock".to_string(),
+                    )
+                })?;
+                blocks.push(ToolResultContentBlock::Image {
+                    source: crate::apis::amazon_bedrock::ImageSource::Base64 { media_type, data },
+                });
+            }
+        }
+    }
+    Ok(blocks)
+}
+
 /// Parse a data URL into media type and base64 data
 /// Supports format: data:image/jpeg;base64,<data>
 fn parse_data_url(url: &str) -> Option<(String, String)> {
@@ -987,4 +1386,239 @@ mod tests {
             panic!("Expected text content block");
         }
     }
+
+    #[test]
+    fn test_n_choices_and_logprobs_rejected_for_anthropic_and_bedrock() {
+        let base_request = ChatCompletionsRequest {
+            model: "gpt-4".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("Hello".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            ..Default::default()
+        };
+
+        let n_request = ChatCompletionsRequest {
+            n: Some(2),
+            ..base_request.clone()
+        };
+        let err = AnthropicMessagesRequest::try_from(n_request.clone()).unwrap_err();
+        assert!(matches!(err, TransformError::UnsupportedCapability(_)));
+        assert!(ConverseRequest::try_from(n_request).is_err());
+
+        let logprobs_request = ChatCompletionsRequest {
+            logprobs: Some(true),
+            top_logprobs: Some(3),
+            ..base_request.clone()
+        };
+        let err = AnthropicMessagesRequest::try_from(logprobs_request.clone()).unwrap_err();
+        assert!(err.to_string().contains("logprobs"));
+        assert!(ConverseRequest::try_from(logprobs_request).is_err());
+
+        // A single choice without logprobs still converts
+        let single_request = ChatCompletionsRequest {
+            n: Some(1),
+            logprobs: Some(false),
+            ..base_request
+        };
+        assert!(AnthropicMessagesRequest::try_from(single_request.clone()).is_ok());
+        assert!(ConverseRequest::try_from(single_request).is_ok());
+    }
+
+    #[test]
+    fn test_reasoning_effort_to_anthropic_thinking() {
+        let request = ChatCompletionsRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("Prove it".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            reasoning_effort: Some("medium".to_string()),
+            max_completion_tokens: Some(16_000),
+            temperature: Some(0.2),
+            ..Default::default()
+        };
+
+        let anthropic_request = AnthropicMessagesRequest::try_from(request.clone()).unwrap();
+        let thinking = anthropic_request.thinking.unwrap();
+        assert_eq!(thinking.thinking_type, "enabled");
+        assert_eq!(thinking.budget_tokens, Some(8_192));
+        // thinking requires the default temperature
+        assert_eq!(anthropic_request.temperature, None);
+
+        let without_thinking = AnthropicMessagesRequest::try_from(ChatCompletionsRequest {
+            model: "claude-3-5-haiku-latest".to_string(),
+            ..request
+        })
+        .unwrap();
+        assert!(without_thinking.thinking.is_none());
+        assert_eq!(without_thinking.temperature, Some(0.2));
+    }
+
+    #[test]
+    fn test_tool_message_with_image_content() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "messages": [
+                {"role": "user", "content": "Take a screenshot"},
+                {"role": "assistant", "content": "", "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": {"name": "screenshot", "arguments": "{}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": [
+                    {"type": "text", "text": "Captured"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let anthropic_request = AnthropicMessagesRequest::try_from(request.clone()).unwrap();
+        let tool_result = serde_json::to_value(&anthropic_request.messages[2].content).unwrap();
+        assert_eq!(
+            tool_result[0]["content"],
+            json!([
+                {"type": "text", "text": "Captured"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}}
+            ])
+        );
+
+        let bedrock_request = ConverseRequest::try_from(request).unwrap();
+        let messages = serde_json::to_value(bedrock_request.messages.unwrap()).unwrap();
+        assert_eq!(
+            messages[2]["content"][0]["toolResult"]["content"],
+            json!([
+                {"type": "text", "text": "Captured"},
+                {"type": "image", "source": {"type": "base64", "mediaType": "image/png", "data": "iVBORw0KGgo="}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_responses_function_call_output_to_tool_messages() {
+        let request: ResponsesAPIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "input": [
+                {"role": "user", "content": "Take a screenshot"},
+                {"type": "function_call", "call_id": "call_1", "name": "screenshot", "arguments": "{}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": [
+                    {"type": "input_text", "text": "Captured"},
+                    {"type": "input_image", "image_url": "data:image/png;base64,iVBORw0KGgo="}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let chat_request = ChatCompletionsRequest::try_from(request).unwrap();
+        let messages = serde_json::to_value(&chat_request.messages).unwrap();
+        assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            messages[2],
+            json!({"role": "tool", "content": "Captured", "tool_call_id": "call_1"})
+        );
+        // tool messages only take text, the image follows in a user message
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(
+            messages[3]["content"][0]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+    }
+
+    #[test]
+    fn test_openai_to_responses_request() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-5-codex",
+            "stream": true,
+            "max_tokens": 256,
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What's the weather?"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/sky.png"}}
+                ]},
+                {"role": "assistant", "content": "", "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}],
+            "tool_choice": {"type": "function", "function": {"name": "get_weather"}}
+        }))
+        .unwrap();
+
+        let responses_request = ResponsesAPIRequest::try_from(request).unwrap();
+        let body = serde_json::to_value(&responses_request).unwrap();
+        assert_eq!(body["instructions"], "Be brief.");
+        assert_eq!(body["max_output_tokens"], 256);
+        assert_eq!(body["stream"], true);
+        assert_eq!(
+            body["input"],
+            json!([
+                {"role": "user", "content": [
+                    {"type": "input_text", "text": "What's the weather?"},
+                    {"type": "input_image", "image_url": "https://example.com/sky.png", "detail": null}
+                ]},
+                {"type": "function_call", "call_id": "call_1", "name": "get_weather", "arguments": "{\"city\":\"Paris\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "Sunny"}
+            ])
+        );
+        assert_eq!(
+            body["tools"],
+            json!([{"type": "function", "name": "get_weather", "description": null, "parameters": {"type": "object"}, "strict": null}])
+        );
+        assert_eq!(
+            body["tool_choice"],
+            json!({"type": "function", "name": "get_weather"})
+        );
+    }
+
+    #[test]
+    fn test_openai_to_gemini_request() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "models/gemini-2.5-flash",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Weather in Paris and Rome?"},
+                {"role": "assistant", "content": "", "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "{\"temp\": 21}"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "sunny"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}],
+            "tool_choice": "required",
+            "max_tokens": 256
+        }))
+        .unwrap();
+
+        let gemini_request = GenerateContentRequest::try_from(request).unwrap();
+        assert_eq!(gemini_request.model, "gemini-2.5-flash");
+        let body = serde_json::to_value(&gemini_request).unwrap();
+        assert_eq!(
+            body["systemInstruction"],
+            json!({"parts": [{"text": "Be brief."}]})
+        );
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(
+            body["contents"][1]["parts"][1]["functionCall"],
+            json!({"name": "get_weather", "args": {"city": "Rome"}})
+        );
+        // both results go back in one user turn, answered by function name
+        assert_eq!(
+            body["contents"][2]["parts"],
+            json!([
+                {"functionResponse": {"name": "get_weather", "response": {"temp": 21}}},
+                {"functionResponse": {"name": "get_weather", "response": {"result": "sunny"}}}
+            ])
+        );
+        assert_eq!(body["toolConfig"]["functionCallingConfig"]["mode"], "ANY");
+        assert_eq!(body["generationConfig"], json!({"maxOutputTokens": 256}));
+    }
 }
@@ -2,3 +2,4 @@
 
 pub mod from_anthropic;
 pub mod from_openai;
+pub mod tool_choice;
@@ -0,0 +1,402 @@
+//! Tool choice translation between the OpenAI, Responses, Anthropic, Bedrock and Gemini
+//! formats
+//!
+//! | OpenAI                              | Anthropic                              | Bedrock Converse    | Gemini                        |
+//! |-------------------------------------|----------------------------------------|---------------------|-------------------------------|
+//! | `auto`                              | `auto`                                 | `auto`              | `AUTO`                        |
+//! | `required` (`any`)                  | `any`                                  | `any`               | `ANY`                         |
+//! | `none`                              | `none`                                 | tools left out      | `NONE`                        |
+//! | `{"type":"function","function":..}` | `{"type":"tool","name":..}`            | `{"tool":{"name"}}` | `ANY` with the function named |
+//! | `parallel_tool_calls: false`        | `disable_parallel_tool_use: true`      | not supported       | not supported                 |
+
+use crate::apis::amazon_bedrock::{
+    AnyChoice, AutoChoice, ContentBlock, Message as BedrockMessage,
+    ToolChoice as BedrockToolChoice, ToolChoiceSpec,
+};
+use crate::apis::anthropic::{MessagesToolChoice, MessagesToolChoiceType};
+use crate::apis::gemini::{FunctionCallingConfig, FunctionCallingMode};
+use crate::apis::openai::{FunctionChoice, ToolChoice, ToolChoiceType};
+use crate::apis::openai_responses::ToolChoice as ResponsesToolChoice;
+
+/// Anthropic tool choice of an OpenAI request. `parallel_tool_calls: false` without a
+/// tool choice still disables parallel tool use when the request has tools.
+pub fn openai_to_anthropic_tool_choice(
+    tool_choice: Option<ToolChoice>,
+    parallel_tool_calls: Option<bool>,
+    has_tools: bool,
+) -> Option<MessagesToolChoice> {
+    let disable_parallel_tool_use = parallel_tool_calls.map(|parallel| !parallel);
+    let (kind, name) = match tool_choice {
+        Some(ToolChoice::Type(ToolChoiceType::Auto)) => (MessagesToolChoiceType::Auto, None),
+        Some(ToolChoice::Type(ToolChoiceType::Required | ToolChoiceType::Any)) => {
+            (MessagesToolChoiceType::Any, None)
+        }
+        Some(ToolChoice::Type(ToolChoiceType::None)) => {
+            return Some(MessagesToolChoice {
+                kind: MessagesToolChoiceType::None,
+                name: None,
+                disable_parallel_tool_use: None,
+            })
+        }
+        Some(ToolChoice::Function { function, .. }) => {
+            (MessagesToolChoiceType::Tool, Some(function.name))
+        }
+        None if has_tools && disable_parallel_tool_use == Some(true) => {
+            (MessagesToolChoiceType::Auto, None)
+        }
+        None => return None,
+    };
+    Some(MessagesToolChoice {
+        kind,
+        name,
+        disable_parallel_tool_use,
+    })
+}
+
+/// OpenAI tool choice and `parallel_tool_calls` of an Anthropic request. A `tool`
+/// choice without a name lets the model choose.
+pub fn anthropic_to_openai_tool_choice(
+    tool_choice: Option<MessagesToolChoice>,
+) -> (Option<ToolChoice>, Option<bool>) {
+    let Some(choice) = tool_choice else {
+        return (None, None);
+    };
+    let openai_choice = match (choice.kind, choice.name) {
+        (MessagesToolChoiceType::Auto, _) => ToolChoice::Type(ToolChoiceType::Auto),
+        (MessagesToolChoiceType::Any, _) => ToolChoice::Type(ToolChoiceType::Required),
+        (MessagesToolChoiceType::None, _) => ToolChoice::Type(ToolChoiceType::None),
+        (MessagesToolChoiceType::Tool, Some(name)) => ToolChoice::Function {
+            choice_type: "function".to_string(),
+            function: FunctionChoice { name },
+        },
+        (MessagesToolChoiceType::Tool, None) => ToolChoice::Type(ToolChoiceType::Auto),
+    };
+    let parallel_tool_calls = choice.disable_parallel_tool_use.map(|disable| !disable);
+    (Some(openai_choice), parallel_tool_calls)
+}
+
+/// OpenAI tool choice of a Responses API request. Both the flat
+/// `{"type":"function","name":..}` and the chat completions shape name a function.
+pub fn responses_to_openai_tool_choice(tool_choice: ResponsesToolChoice) -> ToolChoice {
+    match tool_choice {
+        ResponsesToolChoice::String(choice) => match choice.as_str() {
+            "required" => ToolChoice::Type(ToolChoiceType::Required),
+            "none" => ToolChoice::Type(ToolChoiceType::None),
+            _ => ToolChoice::Type(ToolChoiceType::Auto),
+        },
+        ResponsesToolChoice::Named { function, .. } => ToolChoice::Function {
+            choice_type: "function".to_string(),
+            function: FunctionChoice {
+                name: function.name,
+            },
+        },
+        ResponsesToolChoice::Function { name, .. } => ToolChoice::Function {
+            choice_type: "function".to_string(),
+            function: FunctionChoice { name },
+        },
+    }
+}
+
+/// Responses API tool choice of an OpenAI request, a function is named in the flat
+/// `{"type":"function","name":..}` shape
+pub fn openai_to_responses_tool_choice(tool_choice: ToolChoice) -> ResponsesToolChoice {
+    match tool_choice {
+        ToolChoice::Type(ToolChoiceType::Auto) => ResponsesToolChoice::String("auto".to_string()),
+        ToolChoice::Type(ToolChoiceType::Required | ToolChoiceType::Any) => {
+            ResponsesToolChoice::String("required".to_string())
+        }
+        ToolChoice::Type(ToolChoiceType::None) => ResponsesToolChoice::String("none".to_string()),
+        ToolChoice::Function { function, .. } => ResponsesToolChoice::Function {
+            tool_type: "function".to_string(),
+            name: function.name,
+        },
+    }
+}
+
+/// Tool choice of a Converse request
+#[derive(Debug, Clone)]
+pub enum BedrockToolSelection {
+    /// Send the tools with this choice
+    Tools(BedrockToolChoice),
+    /// Converse has no `none` choice, the tools are left out instead
+    NoTools,
+}
+
+/// Converse tool choice of an OpenAI request, tools without a tool choice are `auto`
+pub fn openai_to_bedrock_tool_choice(tool_choice: Option<ToolChoice>) -> BedrockToolSelection {
+    let choice = match tool_choice {
+        Some(ToolChoice::Type(ToolChoiceType::None)) => return BedrockToolSelection::NoTools,
+        None | Some(ToolChoice::Type(ToolChoiceType::Auto)) => BedrockToolChoice::Auto {
+            auto: AutoChoice {},
+        },
+        Some(ToolChoice::Type(ToolChoiceType::Required | ToolChoiceType::Any)) => {
+            BedrockToolChoice::Any { any: AnyChoice {} }
+        }
+        Some(ToolChoice::Function { function, .. }) => BedrockToolChoice::Tool {
+            tool: ToolChoiceSpec {
+                name: function.name,
+            },
+        },
+    };
+    BedrockToolSelection::Tools(choice)
+}
+
+/// Converse tool choice of an Anthropic request, tools without a tool choice are `auto`
+pub fn anthropic_to_bedrock_tool_choice(
+    tool_choice: Option<MessagesToolChoice>,
+) -> BedrockToolSelection {
+    let choice = match tool_choice.map(|choice| (choice.kind, choice.name)) {
+        Some((MessagesToolChoiceType::None, _)) => return BedrockToolSelection::NoTools,
+        None
+        | Some((MessagesToolChoiceType::Auto, _))
+        | Some((MessagesToolChoiceType::Tool, None)) => BedrockToolChoice::Auto {
+            auto: AutoChoice {},
+        },
+        Some((MessagesToolChoiceType::Any, _)) => BedrockToolChoice::Any { any: AnyChoice {} },
+        Some((MessagesToolChoiceType::Tool, Some(name))) => BedrockToolChoice::Tool {
+            tool: ToolChoiceSpec { name },
+        },
+    };
+    BedrockToolSelection::Tools(choice)
+}
+
+impl BedrockToolSelection {
+    /// Whether the tools are sent. `none` leaves them out, but conversations that already
+    /// used tools keep them since Converse rejects tool blocks without a tool configuration.
+    pub fn sends_tools(&self, messages: Option<&[BedrockMessage]>) -> bool {
+        match self {
+            BedrockToolSelection::Tools(_) => true,
+            BedrockToolSelection::NoTools => messages.unwrap_or_default().iter().any(|message| {
+                message.content.iter().any(|block| {
+                    matches!(
+                        block,
+                        ContentBlock::ToolUse { .. } | ContentBlock::ToolResult { .. }
+                    )
+                })
+            }),
+        }
+    }
+
+    /// Tool choice of the tool configuration, left out when the tools are only sent for
+    /// the tool blocks of the conversation
+    pub fn into_tool_choice(self) -> Option<BedrockToolChoice> {
+        match self {
+            BedrockToolSelection::Tools(choice) => Some(choice),
+            BedrockToolSelection::NoTools => None,
+        }
+    }
+}
+
+/// Gemini function calling config of an OpenAI request, None leaves the choice to Gemini
+/// (`AUTO`)
+pub fn openai_to_gemini_function_calling(
+    tool_choice: Option<ToolChoice>,
+) -> Option<FunctionCallingConfig> {
+    let (mode, allowed_function_names) = match tool_choice? {
+        ToolChoice::Type(ToolChoiceType::Auto) => (FunctionCallingMode::Auto, None),
+        ToolChoice::Type(ToolChoiceType::Required | ToolChoiceType::Any) => {
+            (FunctionCallingMode::Any, None)
+        }
+        ToolChoice::Type(ToolChoiceType::None) => (FunctionCallingMode::None, None),
+        ToolChoice::Function { function, .. } => {
+            (FunctionCallingMode::Any, Some(vec![function.name]))
+        }
+    };
+    Some(FunctionCallingConfig {
+        mode,
+        allowed_function_names,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::amazon_bedrock::{ConversationRole, ToolUseBlock};
+    use serde_json::json;
+
+    fn openai(value: serde_json::Value) -> ToolChoice {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn anthropic(value: serde_json::Value) -> MessagesToolChoice {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn to_json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_openai_to_anthropic() {
+        let cases = [
+            (json!("auto"), None, json!({"type": "auto"})),
+            (json!("required"), None, json!({"type": "any"})),
+            (json!("none"), Some(false), json!({"type": "none"})),
+            (
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                Some(false),
+                json!({"type": "tool", "name": "get_weather", "disable_parallel_tool_use": true}),
+            ),
+            (
+                json!("auto"),
+                Some(true),
+                json!({"type": "auto", "disable_parallel_tool_use": false}),
+            ),
+        ];
+        for (choice, parallel_tool_calls, expected) in cases {
+            let converted =
+                openai_to_anthropic_tool_choice(Some(openai(choice)), parallel_tool_calls, true);
+            assert_eq!(to_json(&converted), expected);
+        }
+
+        // parallel_tool_calls without a tool choice
+        let converted = openai_to_anthropic_tool_choice(None, Some(false), true);
+        assert_eq!(
+            to_json(&converted),
+            json!({"type": "auto", "disable_parallel_tool_use": true})
+        );
+        assert!(openai_to_anthropic_tool_choice(None, Some(false), false).is_none());
+        assert!(openai_to_anthropic_tool_choice(None, Some(true), true).is_none());
+    }
+
+    #[test]
+    fn test_anthropic_to_openai() {
+        let cases = [
+            (json!({"type": "auto"}), json!("auto"), None),
+            (
+                json!({"type": "any", "disable_parallel_tool_use": true}),
+                json!("required"),
+                Some(false),
+            ),
+            (json!({"type": "none"}), json!("none"), None),
+            (
+                json!({"type": "tool", "name": "get_weather"}),
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                None,
+            ),
+            (json!({"type": "tool"}), json!("auto"), None),
+        ];
+        for (choice, expected, expected_parallel) in cases {
+            let (converted, parallel_tool_calls) =
+                anthropic_to_openai_tool_choice(Some(anthropic(choice)));
+            assert_eq!(to_json(&converted), expected);
+            assert_eq!(parallel_tool_calls, expected_parallel);
+        }
+        assert!(matches!(
+            anthropic_to_openai_tool_choice(None),
+            (None, None)
+        ));
+    }
+
+    #[test]
+    fn test_responses_to_openai() {
+        let cases = [
+            (json!("auto"), json!("auto")),
+            (json!("required"), json!("required")),
+            (json!("none"), json!("none")),
+            (
+                json!({"type": "function", "name": "get_weather"}),
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+            ),
+            (
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+            ),
+        ];
+        for (choice, expected) in cases {
+            let choice: ResponsesToolChoice = serde_json::from_value(choice).unwrap();
+            assert_eq!(to_json(&responses_to_openai_tool_choice(choice)), expected);
+        }
+    }
+
+    #[test]
+    fn test_openai_to_responses() {
+        let cases = [
+            (json!("auto"), json!("auto")),
+            (json!("required"), json!("required")),
+            (json!("none"), json!("none")),
+            (
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                json!({"type": "function", "name": "get_weather"}),
+            ),
+        ];
+        for (choice, expected) in cases {
+            assert_eq!(
+                to_json(&openai_to_responses_tool_choice(openai(choice))),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_openai_to_gemini() {
+        let cases = [
+            (json!("auto"), json!({"mode": "AUTO"})),
+            (json!("required"), json!({"mode": "ANY"})),
+            (json!("none"), json!({"mode": "NONE"})),
+            (
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                json!({"mode": "ANY", "allowedFunctionNames": ["get_weather"]}),
+            ),
+        ];
+        for (choice, expected) in cases {
+            let converted = openai_to_gemini_function_calling(Some(openai(choice)));
+            assert_eq!(to_json(&converted), expected);
+        }
+        assert!(openai_to_gemini_function_calling(None).is_none());
+    }
+
+    #[test]
+    fn test_to_bedrock() {
+        let cases = [
+            (json!("auto"), json!({"type": "auto"}), json!({"auto": {}})),
+            (
+                json!("required"),
+                json!({"type": "any"}),
+                json!({"any": {}}),
+            ),
+            (
+                json!({"type": "function", "function": {"name": "get_weather"}}),
+                json!({"type": "tool", "name": "get_weather"}),
+                json!({"tool": {"name": "get_weather"}}),
+            ),
+        ];
+        for (openai_choice, anthropic_choice, expected) in cases {
+            let from_openai = openai_to_bedrock_tool_choice(Some(openai(openai_choice)));
+            let from_anthropic =
+                anthropic_to_bedrock_tool_choice(Some(anthropic(anthropic_choice)));
+            assert_eq!(to_json(&from_openai.into_tool_choice()), expected);
+            assert_eq!(to_json(&from_anthropic.into_tool_choice()), expected);
+        }
+
+        assert_eq!(
+            to_json(&openai_to_bedrock_tool_choice(None).into_tool_choice()),
+            json!({"auto": {}})
+        );
+        assert_eq!(
+            to_json(&anthropic_to_bedrock_tool_choice(None).into_tool_choice()),
+            json!({"auto": {}})
+        );
+    }
+
+    #[test]
+    fn test_none_leaves_bedrock_tools_out_unless_they_were_used() {
+        let none = openai_to_bedrock_tool_choice(Some(openai(json!("none"))));
+        assert!(matches!(none, BedrockToolSelection::NoTools));
+        assert!(!none.sends_tools(None));
+
+        let used_tools = vec![BedrockMessage {
+            role: ConversationRole::Assistant,
+            content: vec![ContentBlock::ToolUse {
+                tool_use: ToolUseBlock {
+                    tool_use_id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    input: json!({}),
+                },
+            }],
+        }];
+        let none = anthropic_to_bedrock_tool_choice(Some(anthropic(json!({"type": "none"}))));
+        assert!(none.sends_tools(Some(&used_tools)));
+        assert!(none.into_tool_choice().is_none());
+    }
+}
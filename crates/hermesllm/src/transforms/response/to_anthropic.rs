@@ -20,19 +20,31 @@ impl TryFrom<ChatCompletionsResponse> for MessagesResponse {
             .next()
             .ok_or_else(|| TransformError::MissingField("choices".to_string()))?;
 
-        let content = convert_openai_message_to_anthropic_content(&choice.message.to_message())?;
-        let stop_reason = choice
-            .finish_reason
-            .map(|fr| fr.into())
-            .unwrap_or(MessagesStopReason::EndTurn);
-
-        let usage = MessagesUsage {
-            input_tokens: resp.usage.prompt_tokens,
-            output_tokens: resp.usage.completion_tokens,
-            cache_creation_input_tokens: None,
-            cache_read_input_tokens: None,
+        let mut content =
+            convert_openai_message_to_anthropic_content(&choice.message.to_message())?;
+        // Reasoning models on OpenAI compatible APIs return their chain of thought separately;
+        // surface it as a leading thinking block like Anthropic does
+        if let Some(reasoning) = choice.message.reasoning_content.as_ref() {
+            if !reasoning.is_empty() {
+                content.insert(
+                    0,
+                    MessagesContentBlock::Thinking {
+                        thinking: reasoning.clone(),
+                        signature: None,
+                        cache_control: None,
+                    },
+                );
+            }
+        }
+        let (stop_reason, stop_sequence) = match &choice.finish_reason {
+            Some(finish_reason) => {
+                anthropic_stop_reason(finish_reason, choice.stop_sequence.as_ref())
+            }
+            None => (MessagesStopReason::EndTurn, None),
         };
 
+        let usage: MessagesUsage = resp.usage.into();
+
         Ok(MessagesResponse {
             id: resp.id,
             obj_type: "message".to_string(),
@@ -40,7 +52,7 @@ impl TryFrom<ChatCompletionsResponse> for MessagesResponse {
             content,
             model: resp.model,
             stop_reason,
-            stop_sequence: None,
+            stop_sequence,
             usage,
             container: None,
         })
@@ -758,4 +770,55 @@ mod tests {
         // Should use fallback model name
         assert_eq!(anthropic_response_fallback.model, "bedrock-model");
     }
+
+    #[test]
+    fn test_deepseek_reasoning_and_cache_usage_to_anthropic() {
+        let deepseek_response = json!({
+            "id": "chatcmpl-ds",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "deepseek-reasoner",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "The answer is 42",
+                    "reasoning_content": "Thinking it through"
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 100,
+                "completion_tokens": 20,
+                "total_tokens": 120,
+                "prompt_cache_hit_tokens": 64,
+                "prompt_cache_miss_tokens": 36
+            }
+        });
+        let response: ChatCompletionsResponse = serde_json::from_value(deepseek_response).unwrap();
+
+        // Provider extensions survive an OpenAI passthrough round trip
+        let passthrough = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            passthrough["choices"][0]["message"]["reasoning_content"],
+            "Thinking it through"
+        );
+        assert_eq!(passthrough["usage"]["prompt_cache_hit_tokens"], 64);
+        assert_eq!(passthrough["usage"]["prompt_cache_miss_tokens"], 36);
+
+        let anthropic_response: MessagesResponse = response.try_into().unwrap();
+        match &anthropic_response.content[0] {
+            MessagesContentBlock::Thinking { thinking, .. } => {
+                assert_eq!(thinking, "Thinking it through")
+            }
+            other => panic!("Expected thinking block first, got {:?}", other),
+        }
+        match &anthropic_response.content[1] {
+            MessagesContentBlock::Text { text, .. } => assert_eq!(text, "The answer is 42"),
+            other => panic!("Expected text block second, got {:?}", other),
+        }
+        assert_eq!(anthropic_response.usage.input_tokens, 36);
+        assert_eq!(anthropic_response.usage.output_tokens, 20);
+        assert_eq!(anthropic_response.usage.cache_read_input_tokens, Some(64));
+    }
 }
@@ -1,9 +1,16 @@
 use crate::apis::amazon_bedrock::{ConverseOutput, ConverseResponse, StopReason};
-use crate::apis::anthropic::{MessagesContentBlock, MessagesResponse, MessagesUsage};
+use crate::apis::anthropic::{
+    MessagesContentBlock, MessagesResponse, MessagesStopReason, MessagesUsage,
+};
+use crate::apis::gemini::{
+    FinishReason as GeminiFinishReason, FunctionCall as GeminiFunctionCall,
+    GenerateContentResponse, UsageMetadata,
+};
 use crate::apis::openai::{
-    ChatCompletionsResponse, Choice, FinishReason, MessageContent, ResponseMessage, Role, Usage,
+    ChatCompletionsResponse, Choice, CompletionTokensDetails, FinishReason, FunctionCall,
+    MessageContent, PromptTokensDetails, ResponseMessage, Role, ToolCall, Usage,
 };
-use crate::apis::openai_responses::ResponsesAPIResponse;
+use crate::apis::openai_responses::{ResponseUsage, ResponsesAPIResponse};
 use crate::clients::TransformError;
 use crate::transforms::lib::*;
 
@@ -20,6 +27,32 @@ impl From<MessagesUsage> for Usage {
             total_tokens: val.input_tokens + val.output_tokens,
             prompt_tokens_details: None,
             completion_tokens_details: None,
+            provider_extensions: Default::default(),
+        }
+    }
+}
+
+impl From<&ResponseUsage> for Usage {
+    fn from(usage: &ResponseUsage) -> Self {
+        Usage {
+            prompt_tokens: usage.input_tokens as u32,
+            completion_tokens: usage.output_tokens as u32,
+            total_tokens: usage.total_tokens as u32,
+            prompt_tokens_details: usage.input_tokens_details.as_ref().map(|details| {
+                PromptTokensDetails {
+                    cached_tokens: Some(details.cached_tokens as u32),
+                    audio_tokens: None,
+                }
+            }),
+            completion_tokens_details: usage.output_tokens_details.as_ref().map(|details| {
+                CompletionTokensDetails {
+                    reasoning_tokens: Some(details.reasoning_tokens as u32),
+                    audio_tokens: None,
+                    accepted_prediction_tokens: None,
+                    rejected_prediction_tokens: None,
+                }
+            }),
+            provider_extensions: Default::default(),
         }
     }
 }
@@ -204,8 +237,24 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
 
     fn try_from(resp: MessagesResponse) -> Result<Self, Self::Error> {
         let content = convert_anthropic_content_to_openai(&resp.content)?;
+        // the matched stop sequence is kept since finish_reason `stop` can't tell it apart
+        // from the end of the turn
+        let stop_sequence = resp
+            .stop_sequence
+            .clone()
+            .filter(|_| resp.stop_reason == MessagesStopReason::StopSequence);
         let finish_reason: FinishReason = resp.stop_reason.into();
         let tool_calls = resp.content.extract_tool_calls()?;
+        // extended thinking is returned like the chain of thought of reasoning models
+        let reasoning = resp
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                MessagesContentBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
 
         // Convert MessageContent to String for response
         let content_string = match content {
@@ -228,6 +277,7 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
             audio: None,
             function_call: None,
             tool_calls,
+            reasoning_content: Some(reasoning).filter(|reasoning| !reasoning.is_empty()),
         };
 
         let choice = Choice {
@@ -235,6 +285,7 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
             message,
             finish_reason: Some(finish_reason),
             logprobs: None,
+            stop_sequence,
         };
 
         let usage = Usage {
@@ -243,6 +294,7 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
             total_tokens: resp.usage.input_tokens + resp.usage.output_tokens,
             prompt_tokens_details: None,
             completion_tokens_details: None,
+            provider_extensions: Default::default(),
         };
 
         Ok(ChatCompletionsResponse {
@@ -294,6 +346,7 @@ impl TryFrom<ConverseResponse> for ChatCompletionsResponse {
             audio: None,
             function_call: None,
             tool_calls,
+            reasoning_content: None,
         };
 
         // Create choice
@@ -302,6 +355,7 @@ impl TryFrom<ConverseResponse> for ChatCompletionsResponse {
             message: response_message,
             finish_reason: Some(finish_reason),
             logprobs: None,
+            stop_sequence: None,
         };
 
         // Convert token usage
@@ -311,6 +365,7 @@ impl TryFrom<ConverseResponse> for ChatCompletionsResponse {
             total_tokens: resp.usage.total_tokens,
             prompt_tokens_details: None,
             completion_tokens_details: None,
+            provider_extensions: Default::default(),
         };
 
         // Generate a response ID (using timestamp since Bedrock doesn't provide one)
@@ -342,6 +397,155 @@ impl TryFrom<ConverseResponse> for ChatCompletionsResponse {
     }
 }
 
+impl From<&GeminiFinishReason> for FinishReason {
+    fn from(reason: &GeminiFinishReason) -> Self {
+        match reason {
+            GeminiFinishReason::Stop => FinishReason::Stop,
+            GeminiFinishReason::MaxTokens => FinishReason::Length,
+            GeminiFinishReason::Safety
+            | GeminiFinishReason::Recitation
+            | GeminiFinishReason::Blocklist
+            | GeminiFinishReason::ProhibitedContent
+            | GeminiFinishReason::Spii => FinishReason::ContentFilter,
+            GeminiFinishReason::MalformedFunctionCall | GeminiFinishReason::Other(_) => {
+                FinishReason::Stop
+            }
+        }
+    }
+}
+
+impl From<&UsageMetadata> for Usage {
+    fn from(usage: &UsageMetadata) -> Self {
+        Usage {
+            prompt_tokens: usage.prompt_token_count,
+            completion_tokens: usage.candidates_token_count
+                + usage.thoughts_token_count.unwrap_or(0),
+            total_tokens: usage.total_token_count,
+            prompt_tokens_details: usage.cached_content_token_count.map(|cached_tokens| {
+                PromptTokensDetails {
+                    cached_tokens: Some(cached_tokens),
+                    audio_tokens: None,
+                }
+            }),
+            completion_tokens_details: usage.thoughts_token_count.map(|reasoning_tokens| {
+                CompletionTokensDetails {
+                    reasoning_tokens: Some(reasoning_tokens),
+                    audio_tokens: None,
+                    accepted_prediction_tokens: None,
+                    rejected_prediction_tokens: None,
+                }
+            }),
+            provider_extensions: Default::default(),
+        }
+    }
+}
+
+/// Tool call of a Gemini function call. Gemini only sometimes ids its calls, the others
+/// are numbered by their position in the candidate.
+pub(crate) fn gemini_tool_call(call: &GeminiFunctionCall, index: usize) -> ToolCall {
+    ToolCall {
+        id: call
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("call_{}_{}", call.name, index)),
+        call_type: "function".to_string(),
+        function: FunctionCall {
+            name: call.name.clone(),
+            arguments: call
+                .args
+                .as_ref()
+                .map(|args| args.to_string())
+                .unwrap_or_else(|| "{}".to_string()),
+        },
+    }
+}
+
+impl TryFrom<GenerateContentResponse> for ChatCompletionsResponse {
+    type Error = TransformError;
+
+    fn try_from(resp: GenerateContentResponse) -> Result<Self, Self::Error> {
+        let mut choices: Vec<Choice> = resp
+            .candidates
+            .iter()
+            .enumerate()
+            .map(|(position, candidate)| {
+                let parts = candidate
+                    .content
+                    .as_ref()
+                    .map(|content| content.parts.as_slice())
+                    .unwrap_or_default();
+                let content: String = parts.iter().filter_map(|part| part.answer_text()).collect();
+                let reasoning: String = parts
+                    .iter()
+                    .filter_map(|part| part.thought_text())
+                    .collect();
+                let tool_calls: Vec<ToolCall> = parts
+                    .iter()
+                    .filter_map(|part| part.function_call.as_ref())
+                    .enumerate()
+                    .map(|(index, call)| gemini_tool_call(call, index))
+                    .collect();
+
+                // Gemini finishes function calls with STOP
+                let finish_reason = match &candidate.finish_reason {
+                    Some(GeminiFinishReason::Stop) if !tool_calls.is_empty() => {
+                        Some(FinishReason::ToolCalls)
+                    }
+                    Some(reason) => Some(reason.into()),
+                    None => None,
+                };
+                Choice {
+                    index: candidate.index.unwrap_or(position as u32),
+                    message: ResponseMessage {
+                        content: (!content.is_empty()).then_some(content),
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                        ..Default::default()
+                    },
+                    finish_reason,
+                    logprobs: None,
+                    stop_sequence: None,
+                }
+            })
+            .collect();
+
+        // A blocked prompt has no candidates, only prompt feedback
+        if choices.is_empty() {
+            choices.push(Choice {
+                index: 0,
+                message: ResponseMessage::default(),
+                finish_reason: Some(FinishReason::ContentFilter),
+                logprobs: None,
+                stop_sequence: None,
+            });
+        }
+
+        let id = resp.response_id.unwrap_or_else(|| {
+            format!(
+                "gemini-{}",
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos()
+            )
+        });
+
+        Ok(ChatCompletionsResponse {
+            id,
+            object: Some("chat.completion".to_string()),
+            created: current_timestamp(),
+            model: resp.model_version.unwrap_or_default(),
+            choices,
+            usage: resp
+                .usage_metadata
+                .as_ref()
+                .map(Usage::from)
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+}
+
 /// Convert Bedrock Message to OpenAI content and tool calls
 /// This function extracts text content and tool calls from a Bedrock message
 fn convert_bedrock_message_to_openai(
@@ -397,9 +601,6 @@ fn convert_anthropic_content_to_openai(
             MessagesContentBlock::Text { text, .. } => {
                 text_parts.push(text.clone());
             }
-            MessagesContentBlock::Thinking { thinking, .. } => {
-                text_parts.push(format!("thinking: {}", thinking));
-            }
             _ => {
                 // Skip other content types for basic text conversion
                 continue;
@@ -969,9 +1170,11 @@ mod tests {
                     audio: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 },
                 finish_reason: Some(FinishReason::Stop),
                 logprobs: None,
+                stop_sequence: None,
             }],
             usage: Usage {
                 prompt_tokens: 10,
@@ -979,10 +1182,12 @@ mod tests {
                 total_tokens: 30,
                 prompt_tokens_details: None,
                 completion_tokens_details: None,
+                provider_extensions: Default::default(),
             },
             system_fingerprint: None,
             service_tier: Some("default".to_string()),
             metadata: None,
+            extensions: Default::default(),
         };
 
         let responses_api: ResponsesAPIResponse = chat_response.try_into().unwrap();
@@ -1050,9 +1255,11 @@ mod tests {
                             arguments: r#"{"location":"San Francisco"}"#.to_string(),
                         },
                     }]),
+                    reasoning_content: None,
                 },
                 finish_reason: Some(FinishReason::ToolCalls),
                 logprobs: None,
+                stop_sequence: None,
             }],
             usage: Usage {
                 prompt_tokens: 15,
@@ -1060,10 +1267,12 @@ mod tests {
                 total_tokens: 40,
                 prompt_tokens_details: None,
                 completion_tokens_details: None,
+                provider_extensions: Default::default(),
             },
             system_fingerprint: None,
             service_tier: None,
             metadata: None,
+            extensions: Default::default(),
         };
 
         let responses_api: ResponsesAPIResponse = chat_response.try_into().unwrap();
@@ -1123,9 +1332,11 @@ mod tests {
                             arguments: r#"{"location":"San Francisco, CA"}"#.to_string(),
                         },
                     }]),
+                    reasoning_content: None,
                 },
                 finish_reason: Some(FinishReason::ToolCalls),
                 logprobs: None,
+                stop_sequence: None,
             }],
             usage: Usage {
                 prompt_tokens: 84,
@@ -1133,10 +1344,12 @@ mod tests {
                 total_tokens: 101,
                 prompt_tokens_details: None,
                 completion_tokens_details: None,
+                provider_extensions: Default::default(),
             },
             system_fingerprint: Some("fp_7eeb46f068".to_string()),
             service_tier: Some("default".to_string()),
             metadata: None,
+            extensions: Default::default(),
         };
 
         let responses_api: ResponsesAPIResponse = chat_response.try_into().unwrap();
@@ -1165,4 +1378,68 @@ mod tests {
             crate::apis::openai_responses::ResponseStatus::Completed
         ));
     }
+
+    #[test]
+    fn test_anthropic_thinking_to_reasoning_content() {
+        let response: MessagesResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "Two and two make four", "signature": "sig"},
+                {"type": "text", "text": "4"}
+            ],
+            "model": "claude-sonnet-4-20250514",
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 20}
+        }))
+        .unwrap();
+
+        let chat_response = ChatCompletionsResponse::try_from(response).unwrap();
+        let message = &chat_response.choices[0].message;
+        assert_eq!(message.content.as_deref(), Some("4"));
+        assert_eq!(
+            message.reasoning_content.as_deref(),
+            Some("Two and two make four")
+        );
+    }
+
+    #[test]
+    fn test_anthropic_stop_reason_round_trip() {
+        let cases = [
+            ("end_turn", None, "stop"),
+            ("stop_sequence", Some("###"), "stop"),
+            ("max_tokens", None, "length"),
+            ("tool_use", None, "tool_calls"),
+        ];
+        for (stop_reason, stop_sequence, finish_reason) in cases {
+            let response: MessagesResponse = serde_json::from_value(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [{"type": "text", "text": "Done"}],
+                "model": "claude-sonnet-4-20250514",
+                "stop_reason": stop_reason,
+                "stop_sequence": stop_sequence,
+                "usage": {"input_tokens": 10, "output_tokens": 2}
+            }))
+            .unwrap();
+
+            let chat_response = ChatCompletionsResponse::try_from(response).unwrap();
+            let choice = serde_json::to_value(&chat_response.choices[0]).unwrap();
+            assert_eq!(choice["finish_reason"], finish_reason);
+            assert_eq!(
+                choice.get("stop_sequence").and_then(|s| s.as_str()),
+                stop_sequence
+            );
+
+            let anthropic_response = MessagesResponse::try_from(chat_response).unwrap();
+            assert_eq!(
+                serde_json::to_value(&anthropic_response.stop_reason).unwrap(),
+                stop_reason
+            );
+            assert_eq!(anthropic_response.stop_sequence.as_deref(), stop_sequence);
+        }
+    }
 }
@@ -5,6 +5,7 @@ use crate::apis::anthropic::{
 };
 use crate::apis::openai::{ChatCompletionsStreamResponse, ToolCallDelta};
 use crate::clients::TransformError;
+use crate::transforms::lib::anthropic_stop_reason;
 use serde_json::Value;
 
 impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
@@ -21,11 +22,12 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
         let has_usage = resp.usage.is_some();
         if let Some(usage) = resp.usage {
             if let Some(finish_reason) = &choice.finish_reason {
-                let anthropic_stop_reason: MessagesStopReason = finish_reason.clone().into();
+                let (stop_reason, stop_sequence) =
+                    anthropic_stop_reason(finish_reason, choice.stop_sequence.as_ref());
                 return Ok(MessagesStreamEvent::MessageDelta {
                     delta: MessagesMessageDelta {
-                        stop_reason: anthropic_stop_reason,
-                        stop_sequence: None,
+                        stop_reason,
+                        stop_sequence,
                     },
                     usage: usage.into(),
                 });
@@ -50,6 +52,18 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
             }
         }
 
+        // Reasoning models stream their chain of thought in reasoning_content (e.g. DeepSeek)
+        if let Some(reasoning) = &choice.delta.reasoning_content {
+            if !reasoning.is_empty() {
+                return Ok(MessagesStreamEvent::ContentBlockDelta {
+                    index: 0,
+                    delta: MessagesContentDelta::ThinkingDelta {
+                        thinking: reasoning.clone(),
+                    },
+                });
+            }
+        }
+
         // Handle tool calls
         if let Some(tool_calls) = &choice.delta.tool_calls {
             return convert_tool_call_deltas(tool_calls.clone());
@@ -60,11 +74,12 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
             // If we have usage data, it was already handled above
             // If not, we need to generate MessageDelta with default usage
             if !has_usage {
-                let anthropic_stop_reason: MessagesStopReason = finish_reason.clone().into();
+                let (stop_reason, stop_sequence) =
+                    anthropic_stop_reason(finish_reason, choice.stop_sequence.as_ref());
                 return Ok(MessagesStreamEvent::MessageDelta {
                     delta: MessagesMessageDelta {
-                        stop_reason: anthropic_stop_reason,
-                        stop_sequence: None,
+                        stop_reason,
+                        stop_sequence,
                     },
                     usage: MessagesUsage {
                         input_tokens: 0,
@@ -2,14 +2,16 @@ use crate::apis::amazon_bedrock::{ConverseStreamEvent, StopReason};
 use crate::apis::anthropic::{
     MessagesContentBlock, MessagesContentDelta, MessagesStopReason, MessagesStreamEvent,
 };
+use crate::apis::gemini::{FinishReason as GeminiFinishReason, GenerateContentResponse};
 use crate::apis::openai::{
     ChatCompletionsStreamResponse, FinishReason, FunctionCallDelta, MessageDelta, Role,
     StreamChoice, ToolCallDelta, Usage,
 };
-use crate::apis::openai_responses::ResponsesAPIStreamEvent;
+use crate::apis::openai_responses::{OutputItem, ResponseStatus, ResponsesAPIStreamEvent};
 
 use crate::clients::TransformError;
 use crate::transforms::lib::*;
+use crate::transforms::response::to_openai::gemini_tool_call;
 
 // ============================================================================
 // PROVIDER STREAMING TRANSFORMATIONS TO OPENAI FORMAT
@@ -55,6 +57,7 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                     refusal: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 },
                 None,
                 None,
@@ -72,10 +75,13 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
             MessagesStreamEvent::ContentBlockStop { .. } => Ok(create_empty_openai_chunk()),
 
             MessagesStreamEvent::MessageDelta { delta, usage } => {
+                let stop_sequence = delta
+                    .stop_sequence
+                    .filter(|_| delta.stop_reason == MessagesStopReason::StopSequence);
                 let finish_reason: Option<FinishReason> = Some(delta.stop_reason.into());
                 let openai_usage: Option<Usage> = Some(usage.into());
 
-                Ok(create_openai_chunk(
+                let mut chunk = create_openai_chunk(
                     "stream",
                     "unknown",
                     MessageDelta {
@@ -84,10 +90,13 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                         refusal: None,
                         function_call: None,
                         tool_calls: None,
+                        reasoning_content: None,
                     },
                     finish_reason,
                     openai_usage,
-                ))
+                );
+                chunk.choices[0].stop_sequence = stop_sequence;
+                Ok(chunk)
             }
 
             MessagesStreamEvent::MessageStop => Ok(create_openai_chunk(
@@ -99,6 +108,7 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                     refusal: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 },
                 Some(FinishReason::Stop),
                 None,
@@ -113,6 +123,7 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                 usage: None,
                 system_fingerprint: None,
                 service_tier: None,
+                extensions: Default::default(),
             }),
         }
     }
@@ -138,6 +149,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                         refusal: None,
                         function_call: None,
                         tool_calls: None,
+                        reasoning_content: None,
                     },
                     None,
                     None,
@@ -165,6 +177,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                                     arguments: Some("".to_string()),
                                 }),
                             }]),
+                            reasoning_content: None,
                         },
                         None,
                         None,
@@ -185,6 +198,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                             refusal: None,
                             function_call: None,
                             tool_calls: None,
+                            reasoning_content: None,
                         },
                         None,
                         None,
@@ -206,6 +220,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                                     arguments: Some(tool_use.input),
                                 }),
                             }]),
+                            reasoning_content: None,
                         },
                         None,
                         None,
@@ -234,6 +249,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                         refusal: None,
                         function_call: None,
                         tool_calls: None,
+                        reasoning_content: None,
                     },
                     Some(finish_reason),
                     None,
@@ -247,6 +263,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                     total_tokens: metadata_event.usage.total_tokens,
                     prompt_tokens_details: None,
                     completion_tokens_details: None,
+                    provider_extensions: Default::default(),
                 };
 
                 Ok(create_openai_chunk(
@@ -258,6 +275,7 @@ impl TryFrom<ConverseStreamEvent> for ChatCompletionsStreamResponse {
                         refusal: None,
                         function_call: None,
                         tool_calls: None,
+                        reasoning_content: None,
                     },
                     None,
                     Some(usage),
@@ -305,11 +323,14 @@ fn convert_content_block_start(
                             arguments: Some("".to_string()),
                         }),
                     }]),
+                    reasoning_content: None,
                 },
                 None,
                 None,
             ))
         }
+        // Blocks of unknown types are dropped, as are their deltas
+        MessagesContentBlock::Other(_) => Ok(create_empty_openai_chunk()),
         _ => Err(TransformError::UnsupportedContent(
             "Unsupported content block type in stream start".to_string(),
         )),
@@ -331,6 +352,7 @@ fn convert_content_delta(
                 refusal: None,
                 function_call: None,
                 tool_calls: None,
+                reasoning_content: None,
             },
             None,
             None,
@@ -340,10 +362,11 @@ fn convert_content_delta(
             "unknown",
             MessageDelta {
                 role: None,
-                content: Some(format!("thinking: {}", thinking)),
+                content: None,
                 refusal: None,
                 function_call: None,
                 tool_calls: None,
+                reasoning_content: Some(thinking),
             },
             None,
             None,
@@ -365,12 +388,14 @@ fn convert_content_delta(
                         arguments: Some(partial_json),
                     }),
                 }]),
+                reasoning_content: None,
             },
             None,
             None,
         )),
-        MessagesContentDelta::SignatureDelta { signature: _ } => {
-            // Signature delta is cryptographic verification metadata, not content
+        // Signature delta is cryptographic verification metadata, not content, and deltas
+        // of unknown types have nothing the client could use
+        MessagesContentDelta::SignatureDelta { signature: _ } | MessagesContentDelta::Other(_) => {
             // Create an empty delta chunk to maintain stream continuity
             Ok(create_openai_chunk(
                 "stream",
@@ -381,6 +406,7 @@ fn convert_content_delta(
                     refusal: None,
                     function_call: None,
                     tool_calls: None,
+                    reasoning_content: None,
                 },
                 None,
                 None,
@@ -389,6 +415,249 @@ fn convert_content_delta(
     }
 }
 
+impl TryFrom<GenerateContentResponse> for ChatCompletionsStreamResponse {
+    type Error = TransformError;
+
+    fn try_from(chunk: GenerateContentResponse) -> Result<Self, Self::Error> {
+        let choices = chunk
+            .candidates
+            .iter()
+            .enumerate()
+            .map(|(position, candidate)| {
+                let parts = candidate
+                    .content
+                    .as_ref()
+                    .map(|content| content.parts.as_slice())
+                    .unwrap_or_default();
+                let content: String = parts.iter().filter_map(|part| part.answer_text()).collect();
+                let reasoning: String = parts
+                    .iter()
+                    .filter_map(|part| part.thought_text())
+                    .collect();
+                // Gemini streams each function call whole
+                let tool_calls: Vec<ToolCallDelta> = parts
+                    .iter()
+                    .filter_map(|part| part.function_call.as_ref())
+                    .enumerate()
+                    .map(|(index, call)| {
+                        let tool_call = gemini_tool_call(call, index);
+                        ToolCallDelta {
+                            index: index as u32,
+                            id: Some(tool_call.id),
+                            call_type: Some(tool_call.call_type),
+                            function: Some(FunctionCallDelta {
+                                name: Some(tool_call.function.name),
+                                arguments: Some(tool_call.function.arguments),
+                            }),
+                        }
+                    })
+                    .collect();
+                let finish_reason = match &candidate.finish_reason {
+                    Some(GeminiFinishReason::Stop) if !tool_calls.is_empty() => {
+                        Some(FinishReason::ToolCalls)
+                    }
+                    Some(reason) => Some(reason.into()),
+                    None => None,
+                };
+                StreamChoice {
+                    index: candidate.index.unwrap_or(position as u32),
+                    delta: MessageDelta {
+                        role: Some(Role::Assistant),
+                        content: (!content.is_empty()).then_some(content),
+                        refusal: None,
+                        function_call: None,
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                    },
+                    finish_reason,
+                    logprobs: None,
+                    stop_sequence: None,
+                }
+            })
+            .collect();
+
+        // Gemini reports the running usage on every chunk, chat completions only on the
+        // finishing one
+        let finishes = chunk
+            .candidates
+            .iter()
+            .any(|candidate| candidate.finish_reason.is_some());
+        let usage = chunk
+            .usage_metadata
+            .as_ref()
+            .filter(|_| finishes)
+            .map(Usage::from);
+
+        Ok(ChatCompletionsStreamResponse {
+            id: chunk.response_id.unwrap_or_else(|| "stream".to_string()),
+            object: Some("chat.completion.chunk".to_string()),
+            created: current_timestamp(),
+            model: chunk.model_version.unwrap_or_else(|| "unknown".to_string()),
+            choices,
+            usage,
+            system_fingerprint: None,
+            service_tier: None,
+            extensions: Default::default(),
+        })
+    }
+}
+
+impl TryFrom<ResponsesAPIStreamEvent> for ChatCompletionsStreamResponse {
+    type Error = TransformError;
+
+    fn try_from(event: ResponsesAPIStreamEvent) -> Result<Self, Self::Error> {
+        let empty_delta = MessageDelta {
+            role: None,
+            content: None,
+            refusal: None,
+            function_call: None,
+            tool_calls: None,
+            reasoning_content: None,
+        };
+        match event {
+            ResponsesAPIStreamEvent::ResponseCreated { response, .. } => Ok(create_openai_chunk(
+                &response.id,
+                &response.model,
+                MessageDelta {
+                    role: Some(Role::Assistant),
+                    ..empty_delta
+                },
+                None,
+                None,
+            )),
+
+            ResponsesAPIStreamEvent::ResponseOutputTextDelta { delta, .. } => {
+                Ok(create_openai_chunk(
+                    "stream",
+                    "unknown",
+                    MessageDelta {
+                        content: Some(delta),
+                        ..empty_delta
+                    },
+                    None,
+                    None,
+                ))
+            }
+
+            // A function call starts with its item, its arguments follow as deltas. The
+            // output index keeps the calls of a response apart.
+            ResponsesAPIStreamEvent::ResponseOutputItemAdded {
+                output_index,
+                item: OutputItem::FunctionCall { call_id, name, .. },
+                ..
+            } => Ok(create_openai_chunk(
+                "stream",
+                "unknown",
+                MessageDelta {
+                    tool_calls: Some(vec![ToolCallDelta {
+                        index: output_index as u32,
+                        id: Some(call_id),
+                        call_type: Some("function".to_string()),
+                        function: Some(FunctionCallDelta {
+                            name,
+                            arguments: Some("".to_string()),
+                        }),
+                    }]),
+                    ..empty_delta
+                },
+                None,
+                None,
+            )),
+
+            ResponsesAPIStreamEvent::ResponseFunctionCallArgumentsDelta {
+                output_index,
+                delta,
+                ..
+            } => Ok(create_openai_chunk(
+                "stream",
+                "unknown",
+                MessageDelta {
+                    tool_calls: Some(vec![ToolCallDelta {
+                        index: output_index as u32,
+                        id: None,
+                        call_type: None,
+                        function: Some(FunctionCallDelta {
+                            name: None,
+                            arguments: Some(delta),
+                        }),
+                    }]),
+                    ..empty_delta
+                },
+                None,
+                None,
+            )),
+
+            ResponsesAPIStreamEvent::ResponseCompleted { response, .. } => {
+                let finish_reason = if matches!(response.status, ResponseStatus::Incomplete) {
+                    FinishReason::Length
+                } else if response
+                    .output
+                    .iter()
+                    .any(|item| matches!(item, OutputItem::FunctionCall { .. }))
+                {
+                    FinishReason::ToolCalls
+                } else {
+                    FinishReason::Stop
+                };
+                Ok(create_openai_chunk(
+                    &response.id,
+                    &response.model,
+                    empty_delta,
+                    Some(finish_reason),
+                    response.usage.as_ref().map(Usage::from),
+                ))
+            }
+
+            ResponsesAPIStreamEvent::Error { code, message, .. } => Err(
+                TransformError::UnsupportedContent(format!("upstream error {}: {}", code, message)),
+            ),
+
+            // Lifecycle events and the done events repeat what the deltas already carried
+            _ => Err(TransformError::UnsupportedConversion(
+                "Responses API event without a chat completions counterpart".to_string(),
+            )),
+        }
+    }
+}
+
+/// Splits a chunk that carries a delta and the finish reason into a delta chunk and a
+/// finishing chunk with the usage. The chat completions stream translations expect the
+/// two separately, while Gemini sends the last text with the finish reason.
+pub fn split_finishing_chunk(
+    chunk: ChatCompletionsStreamResponse,
+) -> Vec<ChatCompletionsStreamResponse> {
+    let has_delta = |choice: &StreamChoice| {
+        choice.delta.content.is_some()
+            || choice.delta.reasoning_content.is_some()
+            || choice.delta.tool_calls.is_some()
+    };
+    let splits = chunk
+        .choices
+        .iter()
+        .any(|choice| choice.finish_reason.is_some() && has_delta(choice));
+    if !splits {
+        return vec![chunk];
+    }
+
+    let mut delta_chunk = chunk.clone();
+    delta_chunk.usage = None;
+    for choice in &mut delta_chunk.choices {
+        choice.finish_reason = None;
+    }
+    let mut finishing_chunk = chunk;
+    for choice in &mut finishing_chunk.choices {
+        choice.delta = MessageDelta {
+            role: None,
+            content: None,
+            refusal: None,
+            function_call: None,
+            tool_calls: None,
+            reasoning_content: None,
+        };
+    }
+    vec![delta_chunk, finishing_chunk]
+}
+
 /// Helper to create OpenAI streaming chunk
 fn create_openai_chunk(
     id: &str,
@@ -407,10 +676,12 @@ fn create_openai_chunk(
             delta,
             finish_reason,
             logprobs: None,
+            stop_sequence: None,
         }],
         usage,
         system_fingerprint: None,
         service_tier: None,
+        extensions: Default::default(),
     }
 }
 
@@ -425,6 +696,7 @@ fn create_empty_openai_chunk() -> ChatCompletionsStreamResponse {
             refusal: None,
             function_call: None,
             tool_calls: None,
+            reasoning_content: None,
         },
         None,
         None,
@@ -441,6 +713,7 @@ impl From<MessagesStopReason> for FinishReason {
             MessagesStopReason::ToolUse => FinishReason::ToolCalls,
             MessagesStopReason::PauseTurn => FinishReason::Stop,
             MessagesStopReason::Refusal => FinishReason::ContentFilter,
+            MessagesStopReason::Other(_) => FinishReason::Stop,
         }
     }
 }
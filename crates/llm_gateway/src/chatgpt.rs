@@ -0,0 +1,34 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::Value;
+
+/// Claim of ChatGPT OAuth tokens holding the account details
+const OPENAI_AUTH_CLAIM: &str = "https://api.openai.com/auth";
+
+/// ChatGPT account of an OAuth access token, read from its claims. The signature isn't
+/// checked, the Codex backend does that.
+pub fn account_id(access_token: &str) -> Option<String> {
+    let payload = access_token.split('.').nth(1)?;
+    let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    claims[OPENAI_AUTH_CLAIM]["chatgpt_account_id"]
+        .as_str()
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_id() {
+        let claims = URL_SAFE_NO_PAD.encode(
+            r#"{"sub":"user-1","https://api.openai.com/auth":{"chatgpt_account_id":"acct_123","chatgpt_plan_type":"pro"}}"#,
+        );
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.signature", claims);
+        assert_eq!(account_id(&token).as_deref(), Some("acct_123"));
+
+        assert_eq!(account_id("sk-static-key"), None);
+        let other_claims = URL_SAFE_NO_PAD.encode(r#"{"sub":"user-1"}"#);
+        assert_eq!(account_id(&format!("e30.{}.sig", other_claims)), None);
+    }
+}
@@ -1,11 +1,14 @@
 use crate::metrics::Metrics;
+use crate::middleware::{Middleware, MiddlewareRegistry};
 use crate::stream_context::StreamContext;
 use common::configuration::Configuration;
-use common::configuration::Overrides;
+use common::configuration::{Listener, Overrides, RequestRule};
 use common::http::Client;
 use common::llm_providers::LlmProviders;
 use common::ratelimit;
 use common::stats::Gauge;
+use derivative::Derivative;
+use hermesllm::apis::parse_mode::set_parse_mode;
 use log::trace;
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
@@ -17,13 +20,18 @@ use std::time::Duration;
 #[derive(Debug)]
 pub struct CallContext {}
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct FilterContext {
     metrics: Rc<Metrics>,
     // callouts stores token_id to request mapping that we use during #on_http_call_response to match the response to the request.
     callouts: RefCell<HashMap<u32, CallContext>>,
     llm_providers: Option<Rc<LlmProviders>>,
     overrides: Rc<Option<Overrides>>,
+    listeners: Rc<Vec<Listener>>,
+    #[derivative(Debug = "ignore")]
+    middlewares: Rc<Vec<Box<dyn Middleware>>>,
+    request_rules: Rc<Vec<RequestRule>>,
 }
 
 impl FilterContext {
@@ -33,6 +41,9 @@ impl FilterContext {
             metrics: Rc::new(Metrics::new()),
             llm_providers: None,
             overrides: Rc::new(None),
+            listeners: Rc::new(Vec::new()),
+            middlewares: Rc::new(Vec::new()),
+            request_rules: Rc::new(Vec::new()),
         }
     }
 }
@@ -62,7 +73,20 @@ impl RootContext for FilterContext {
         };
 
         ratelimit::ratelimits(Some(config.ratelimits.unwrap_or_default()));
+        set_parse_mode(
+            config
+                .overrides
+                .as_ref()
+                .and_then(|overrides| overrides.response_parsing)
+                .unwrap_or_default(),
+        );
         self.overrides = Rc::new(config.overrides);
+        self.listeners = Rc::new(config.listeners);
+        match MiddlewareRegistry::with_builtins().build(&config.middlewares.unwrap_or_default()) {
+            Ok(middlewares) => self.middlewares = Rc::new(middlewares),
+            Err(err) => panic!("{err}"),
+        }
+        self.request_rules = Rc::new(config.request_rules.unwrap_or_default());
 
         match config.model_providers.try_into() {
             Ok(llm_providers) => self.llm_providers = Some(Rc::new(llm_providers)),
@@ -86,6 +110,9 @@ impl RootContext for FilterContext {
                     .expect("LLM Providers must exist when Streams are being created"),
             ),
             Rc::clone(&self.overrides),
+            Rc::clone(&self.listeners),
+            Rc::clone(&self.middlewares),
+            Rc::clone(&self.request_rules),
         )))
     }
 
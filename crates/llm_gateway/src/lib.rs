@@ -2,9 +2,15 @@ use filter_context::FilterContext;
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 
+mod chatgpt;
 mod filter_context;
 mod metrics;
+pub mod middleware;
+mod raw_body;
+mod request_rules;
+mod sanitizer;
 mod stream_context;
+mod user_hashing;
 
 proxy_wasm::main! {{
     proxy_wasm::set_log_level(LogLevel::Trace);
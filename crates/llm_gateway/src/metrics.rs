@@ -1,15 +1,46 @@
 use common::stats::{Counter, Gauge, Histogram};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Debug)]
 pub struct Metrics {
     pub active_http_calls: Gauge,
     pub ratelimited_rq: Counter,
-    pub time_to_first_token: Histogram,
-    pub time_per_output_token: Histogram,
-    pub tokens_per_second: Histogram,
-    pub request_latency: Histogram,
-    pub output_sequence_length: Histogram,
-    pub input_sequence_length: Histogram,
+    /// Completed llm requests
+    pub requests: MetricFamily<Counter>,
+    pub time_to_first_token: MetricFamily<Histogram>,
+    pub time_per_output_token: MetricFamily<Histogram>,
+    pub tokens_per_second: MetricFamily<Histogram>,
+    pub request_latency: MetricFamily<Histogram>,
+    pub output_sequence_length: MetricFamily<Histogram>,
+    pub input_sequence_length: MetricFamily<Histogram>,
+    pub oversized_requests: Counter,
+    pub oversized_responses: Counter,
+    pub truncated_responses: Counter,
+    pub upstream_queue_time: Histogram,
+    /// Milliseconds of audio transcribed, as reported by the upstream
+    pub audio_duration: Histogram,
+    pub audio_speech_characters: Counter,
+    pub batches_created: Counter,
+    /// Requests that ran past their provider or client deadline
+    pub request_timeouts: Counter,
+    /// Requests sent to a provider that haven't completed yet
+    pub in_flight_requests: MetricFamily<Gauge>,
+    /// Token counters, `rate()` of these gives tokens per minute
+    pub input_tokens: MetricFamily<Counter>,
+    pub output_tokens: MetricFamily<Counter>,
+    /// Milliseconds between two chunks of a streaming response
+    pub stream_chunk_gap: MetricFamily<Histogram>,
+    /// Streaming responses that went quiet for longer than the stall threshold
+    pub stream_stalls: MetricFamily<Counter>,
+    /// Non streaming responses that didn't parse until they were repaired
+    pub repaired_responses: MetricFamily<Counter>,
+    /// Non streaming responses that didn't parse and couldn't be repaired
+    pub malformed_responses: MetricFamily<Counter>,
+    /// Usage per OpenAI organization and project, for providers that set them
+    pub billing_scope_requests: MetricFamily<Counter>,
+    pub billing_scope_output_tokens: MetricFamily<Counter>,
+    pub billing_scope_batches_created: MetricFamily<Counter>,
 }
 
 impl Metrics {
@@ -17,12 +48,183 @@ impl Metrics {
         Metrics {
             active_http_calls: Gauge::new(String::from("active_http_calls")),
             ratelimited_rq: Counter::new(String::from("ratelimited_rq")),
-            time_to_first_token: Histogram::new(String::from("time_to_first_token")),
-            time_per_output_token: Histogram::new(String::from("time_per_output_token")),
-            tokens_per_second: Histogram::new(String::from("tokens_per_second")),
-            request_latency: Histogram::new(String::from("request_latency")),
-            output_sequence_length: Histogram::new(String::from("output_sequence_length")),
-            input_sequence_length: Histogram::new(String::from("input_sequence_length")),
+            requests: MetricFamily::new("llm_requests", Counter::new),
+            time_to_first_token: MetricFamily::new("time_to_first_token", Histogram::new),
+            time_per_output_token: MetricFamily::new("time_per_output_token", Histogram::new),
+            tokens_per_second: MetricFamily::new("tokens_per_second", Histogram::new),
+            request_latency: MetricFamily::new("request_latency", Histogram::new),
+            output_sequence_length: MetricFamily::new("output_sequence_length", Histogram::new),
+            input_sequence_length: MetricFamily::new("input_sequence_length", Histogram::new),
+            oversized_requests: Counter::new(String::from("oversized_requests")),
+            oversized_responses: Counter::new(String::from("oversized_responses")),
+            truncated_responses: Counter::new(String::from("truncated_responses")),
+            upstream_queue_time: Histogram::new(String::from("upstream_queue_time")),
+            audio_duration: Histogram::new(String::from("audio_duration")),
+            audio_speech_characters: Counter::new(String::from("audio_speech_characters")),
+            batches_created: Counter::new(String::from("batches_created")),
+            request_timeouts: Counter::new(String::from("request_timeouts")),
+            in_flight_requests: MetricFamily::new("in_flight_requests", Gauge::new),
+            input_tokens: MetricFamily::new("llm_input_tokens", Counter::new),
+            output_tokens: MetricFamily::new("llm_output_tokens", Counter::new),
+            stream_chunk_gap: MetricFamily::new("stream_chunk_gap", Histogram::new),
+            stream_stalls: MetricFamily::new("stream_stalls", Counter::new),
+            repaired_responses: MetricFamily::new("repaired_responses", Counter::new),
+            malformed_responses: MetricFamily::new("malformed_responses", Counter::new),
+            billing_scope_requests: MetricFamily::new("llm_requests", Counter::new),
+            billing_scope_output_tokens: MetricFamily::new("output_tokens", Counter::new),
+            billing_scope_batches_created: MetricFamily::new("batches_created", Counter::new),
         }
     }
 }
+
+/// Labels of per request metrics. They are encoded in the metric name as `.<label>.<value>`
+/// pairs, which envoy's `stats_tags` turn back into prometheus labels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricLabels {
+    pub provider: String,
+    pub model: String,
+    pub client_api: String,
+    /// `2xx`, `4xx`, `5xx`, or `unknown` before the upstream answered
+    pub status_class: String,
+    /// Traffic split of the model alias, only set for aliases that split traffic
+    pub split: Option<String>,
+}
+
+impl MetricLabels {
+    pub fn metric_name(&self, name: &str) -> String {
+        let name = format!(
+            "{}.provider.{}.model.{}.client_api.{}.status_class.{}",
+            name,
+            label_value(&self.provider),
+            label_value(&self.model),
+            label_value(&self.client_api),
+            label_value(&self.status_class)
+        );
+        match &self.split {
+            Some(split) => format!("{}.split.{}", name, label_value(split)),
+            None => name,
+        }
+    }
+}
+
+/// Dots separate labels in the metric name, so they can't appear in a value
+fn label_value(value: &str) -> String {
+    if value.is_empty() {
+        return "unknown".to_string();
+    }
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// One metric per label set, defined the first time the label set is seen
+#[derive(Debug)]
+pub struct MetricFamily<M> {
+    name: &'static str,
+    define: fn(String) -> M,
+    children: RefCell<HashMap<String, M>>,
+}
+
+impl<M: Copy> MetricFamily<M> {
+    pub fn new(name: &'static str, define: fn(String) -> M) -> Self {
+        MetricFamily {
+            name,
+            define,
+            children: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_labels(&self, labels: &MetricLabels) -> M {
+        self.child(labels.metric_name(self.name))
+    }
+
+    /// For metrics that are only split by provider
+    pub fn with_provider(&self, provider: &str) -> M {
+        self.child(format!("{}.provider.{}", self.name, label_value(provider)))
+    }
+
+    /// For usage split per billing scope, None when the provider has no organization or
+    /// project configured
+    pub fn with_billing_scope(
+        &self,
+        organization: Option<&str>,
+        project: Option<&str>,
+    ) -> Option<M> {
+        billing_scope_metric_name(self.name, organization, project).map(|name| self.child(name))
+    }
+
+    fn child(&self, metric_name: String) -> M {
+        *self
+            .children
+            .borrow_mut()
+            .entry(metric_name.clone())
+            .or_insert_with(|| (self.define)(metric_name))
+    }
+}
+
+/// Name of a metric scoped to an OpenAI organization / project so usage can be split
+/// per billing scope, e.g. `output_tokens.organization.org-1.project.proj-a`
+fn billing_scope_metric_name(
+    name: &str,
+    organization: Option<&str>,
+    project: Option<&str>,
+) -> Option<String> {
+    if organization.is_none() && project.is_none() {
+        return None;
+    }
+    Some(format!(
+        "{}.organization.{}.project.{}",
+        name,
+        label_value(organization.unwrap_or("none")),
+        label_value(project.unwrap_or("none"))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_name_encodes_labels() {
+        let labels = MetricLabels {
+            provider: "openai".to_string(),
+            model: "gpt-4.1-mini".to_string(),
+            client_api: "messages".to_string(),
+            status_class: String::new(),
+            split: None,
+        };
+        assert_eq!(
+            labels.metric_name("request_latency"),
+            "request_latency.provider.openai.model.gpt-4_1-mini.client_api.messages.status_class.unknown"
+        );
+
+        let labels = MetricLabels {
+            split: Some("sonnet".to_string()),
+            ..labels
+        };
+        assert_eq!(
+            labels.metric_name("llm_requests"),
+            "llm_requests.provider.openai.model.gpt-4_1-mini.client_api.messages.status_class.unknown.split.sonnet"
+        );
+    }
+
+    #[test]
+    fn test_billing_scope_metric_name() {
+        assert_eq!(
+            billing_scope_metric_name("output_tokens", Some("org-1"), Some("proj.a b")),
+            Some("output_tokens.organization.org-1.project.proj_a_b".to_string())
+        );
+        assert_eq!(
+            billing_scope_metric_name("llm_requests", None, Some("proj-a")),
+            Some("llm_requests.organization.none.project.proj-a".to_string())
+        );
+        assert_eq!(billing_scope_metric_name("llm_requests", None, None), None);
+    }
+}
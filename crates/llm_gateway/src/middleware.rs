@@ -0,0 +1,359 @@
+use common::configuration::{LlmProvider, MiddlewareConfig};
+use hermesllm::apis::anthropic::MessagesContentBlock;
+use hermesllm::apis::openai_responses::{OutputContent, OutputItem};
+use hermesllm::{ProviderRequestType, ProviderResponseType};
+use http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MiddlewareError {
+    /// The request is answered with `status` instead of being sent upstream, or the
+    /// response is replaced with it
+    #[error("{message}")]
+    Rejected { status: StatusCode, message: String },
+    #[error("unknown middleware {0}")]
+    Unknown(String),
+    #[error("invalid config for middleware {name}: {message}")]
+    InvalidConfig { name: String, message: String },
+}
+
+/// What a middleware knows about the request it runs on
+pub struct MiddlewareContext<'a> {
+    pub request_id: &'a str,
+    /// Model sent upstream
+    pub model: &'a str,
+    pub provider: &'a LlmProvider,
+    /// Headers of the client request, with lowercase names
+    pub request_headers: &'a [(String, String)],
+}
+
+impl MiddlewareContext<'_> {
+    pub fn request_header(&self, name: &str) -> Option<&str> {
+        self.request_headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Custom logic run by llm_gateway on every llm request, before it is translated for
+/// the provider, and on every non streaming response, after it is translated for the
+/// client. Both hooks see the api the client speaks.
+pub trait Middleware {
+    fn name(&self) -> &str;
+
+    fn on_request(
+        &self,
+        _context: &MiddlewareContext,
+        _request: &mut ProviderRequestType,
+    ) -> Result<(), MiddlewareError> {
+        Ok(())
+    }
+
+    fn on_response(
+        &self,
+        _context: &MiddlewareContext,
+        _response: &mut ProviderResponseType,
+    ) -> Result<(), MiddlewareError> {
+        Ok(())
+    }
+}
+
+/// Builds a middleware from the `config` of its entry in `middlewares`
+pub type MiddlewareFactory = fn(Option<&serde_json::Value>) -> Result<Box<dyn Middleware>, String>;
+
+/// Middlewares that can be configured by name. Custom middlewares are compiled in by
+/// registering them in `with_builtins`.
+pub struct MiddlewareRegistry {
+    factories: HashMap<&'static str, MiddlewareFactory>,
+}
+
+impl MiddlewareRegistry {
+    pub fn with_builtins() -> Self {
+        let mut registry = MiddlewareRegistry {
+            factories: HashMap::new(),
+        };
+        registry.register(HeaderAuth::NAME, HeaderAuth::from_config);
+        registry.register(OutputFilter::NAME, OutputFilter::from_config);
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, factory: MiddlewareFactory) {
+        self.factories.insert(name, factory);
+    }
+
+    /// Middlewares of the configuration, in the configured order
+    pub fn build(
+        &self,
+        configs: &[MiddlewareConfig],
+    ) -> Result<Vec<Box<dyn Middleware>>, MiddlewareError> {
+        configs
+            .iter()
+            .map(|config| {
+                let factory = self
+                    .factories
+                    .get(config.name.as_str())
+                    .ok_or_else(|| MiddlewareError::Unknown(config.name.clone()))?;
+                factory(config.config.as_ref()).map_err(|message| MiddlewareError::InvalidConfig {
+                    name: config.name.clone(),
+                    message,
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_config<T: DeserializeOwned>(config: Option<&serde_json::Value>) -> Result<T, String> {
+    serde_json::from_value(config.cloned().unwrap_or_default()).map_err(|err| err.to_string())
+}
+
+/// Text the model generated, across the response apis
+pub fn response_texts_mut(response: &mut ProviderResponseType) -> Vec<&mut String> {
+    match response {
+        ProviderResponseType::ChatCompletionsResponse(response) => response
+            .choices
+            .iter_mut()
+            .filter_map(|choice| choice.message.content.as_mut())
+            .collect(),
+        ProviderResponseType::MessagesResponse(response) => response
+            .content
+            .iter_mut()
+            .filter_map(|block| match block {
+                MessagesContentBlock::Text { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect(),
+        ProviderResponseType::ResponsesAPIResponse(response) => response
+            .output
+            .iter_mut()
+            .flat_map(|item| match item {
+                OutputItem::Message { content, .. } => content.iter_mut().collect::<Vec<_>>(),
+                _ => Vec::new(),
+            })
+            .filter_map(|content| match content {
+                OutputContent::OutputText { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeaderAuthConfig {
+    pub header: String,
+    /// Accepted values of the header
+    pub values: Vec<String>,
+}
+
+/// Rejects requests whose header isn't one of the accepted values, e.g. shared api keys
+pub struct HeaderAuth {
+    config: HeaderAuthConfig,
+}
+
+impl HeaderAuth {
+    pub const NAME: &'static str = "header_auth";
+
+    pub fn from_config(config: Option<&serde_json::Value>) -> Result<Box<dyn Middleware>, String> {
+        Ok(Box::new(HeaderAuth {
+            config: parse_config(config)?,
+        }))
+    }
+}
+
+impl Middleware for HeaderAuth {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn on_request(
+        &self,
+        context: &MiddlewareContext,
+        _request: &mut ProviderRequestType,
+    ) -> Result<(), MiddlewareError> {
+        match context.request_header(&self.config.header) {
+            Some(value) if self.config.values.iter().any(|accepted| accepted == value) => Ok(()),
+            _ => Err(MiddlewareError::Rejected {
+                status: StatusCode::UNAUTHORIZED,
+                message: format!("missing or invalid {} header", self.config.header),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutputFilterConfig {
+    /// Terms removed from completions, matched ignoring ascii case
+    pub blocked_terms: Vec<String>,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+fn default_replacement() -> String {
+    "[FILTERED]".to_string()
+}
+
+/// Replaces blocked terms in the text of responses
+pub struct OutputFilter {
+    config: OutputFilterConfig,
+}
+
+impl OutputFilter {
+    pub const NAME: &'static str = "output_filter";
+
+    pub fn from_config(config: Option<&serde_json::Value>) -> Result<Box<dyn Middleware>, String> {
+        Ok(Box::new(OutputFilter {
+            config: parse_config(config)?,
+        }))
+    }
+
+    fn filter(&self, text: &str) -> String {
+        self.config
+            .blocked_terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .fold(text.to_string(), |text, term| {
+                replace_ignore_ascii_case(&text, term, &self.config.replacement)
+            })
+    }
+}
+
+/// Ascii lowercasing keeps byte offsets, so matches found in the lowercased text are
+/// valid ranges of the original
+fn replace_ignore_ascii_case(text: &str, term: &str, replacement: &str) -> String {
+    let haystack = text.to_ascii_lowercase();
+    let needle = term.to_ascii_lowercase();
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in haystack.match_indices(&needle) {
+        replaced.push_str(&text[last..start]);
+        replaced.push_str(replacement);
+        last = start + needle.len();
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+impl Middleware for OutputFilter {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn on_response(
+        &self,
+        _context: &MiddlewareContext,
+        response: &mut ProviderResponseType,
+    ) -> Result<(), MiddlewareError> {
+        for text in response_texts_mut(response) {
+            *text = self.filter(text);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::openai::{ChatCompletionsRequest, ChatCompletionsResponse};
+
+    fn context<'a>(
+        provider: &'a LlmProvider,
+        headers: &'a [(String, String)],
+    ) -> MiddlewareContext<'a> {
+        MiddlewareContext {
+            request_id: "req-1",
+            model: "gpt-4o",
+            provider,
+            request_headers: headers,
+        }
+    }
+
+    fn registry_build(name: &str, config: serde_json::Value) -> Box<dyn Middleware> {
+        MiddlewareRegistry::with_builtins()
+            .build(&[MiddlewareConfig {
+                name: name.to_string(),
+                config: Some(config),
+            }])
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn test_build_unknown_and_invalid() {
+        let registry = MiddlewareRegistry::with_builtins();
+        let err = registry
+            .build(&[MiddlewareConfig {
+                name: "nope".to_string(),
+                config: None,
+            }])
+            .err()
+            .unwrap();
+        assert!(matches!(err, MiddlewareError::Unknown(name) if name == "nope"));
+
+        let err = registry
+            .build(&[MiddlewareConfig {
+                name: HeaderAuth::NAME.to_string(),
+                config: Some(serde_json::json!({"header": "x-api-key"})),
+            }])
+            .err()
+            .unwrap();
+        assert!(matches!(err, MiddlewareError::InvalidConfig { .. }));
+    }
+
+    #[test]
+    fn test_header_auth() {
+        let middleware = registry_build(
+            HeaderAuth::NAME,
+            serde_json::json!({"header": "x-api-key", "values": ["secret"]}),
+        );
+        let provider = LlmProvider::default();
+        let mut request = ProviderRequestType::ChatCompletionsRequest(ChatCompletionsRequest {
+            model: "gpt-4o".to_string(),
+            ..Default::default()
+        });
+
+        let headers = vec![("X-Api-Key".to_string(), "secret".to_string())];
+        assert!(middleware
+            .on_request(&context(&provider, &headers), &mut request)
+            .is_ok());
+
+        let headers = vec![("x-api-key".to_string(), "guess".to_string())];
+        match middleware.on_request(&context(&provider, &headers), &mut request) {
+            Err(MiddlewareError::Rejected { status, .. }) => {
+                assert_eq!(status, StatusCode::UNAUTHORIZED)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_output_filter() {
+        let middleware = registry_build(
+            OutputFilter::NAME,
+            serde_json::json!({"blocked_terms": ["project falcon"]}),
+        );
+        let response: ChatCompletionsResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "created": 1,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Project Falcon ships in May, as project falcon planned."}}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        }))
+        .unwrap();
+        let mut response = ProviderResponseType::ChatCompletionsResponse(response);
+        let provider = LlmProvider::default();
+        middleware
+            .on_response(&context(&provider, &[]), &mut response)
+            .unwrap();
+        let texts: Vec<String> = response_texts_mut(&mut response)
+            .into_iter()
+            .map(|text| text.to_string())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["[FILTERED] ships in May, as [FILTERED] planned."]
+        );
+    }
+}
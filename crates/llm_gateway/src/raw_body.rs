@@ -0,0 +1,334 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+/// Top level fields of a JSON object body. Values are kept as their raw text in the
+/// body and only parsed when read, fields that aren't changed are written back byte for
+/// byte.
+#[derive(Debug)]
+pub struct RawObject<'a> {
+    fields: Vec<(String, Cow<'a, str>)>,
+}
+
+impl<'a> RawObject<'a> {
+    /// None when the body isn't a JSON object
+    pub fn parse(body: &'a [u8]) -> Option<Self> {
+        let fields: RawFields<'a> = serde_json::from_slice(body).ok()?;
+        Some(RawObject {
+            fields: fields
+                .0
+                .into_iter()
+                .map(|(key, value)| (key, Cow::Borrowed(value.get())))
+                .collect(),
+        })
+    }
+
+    /// The fields complete in the first bytes of an object body that is still arriving,
+    /// up to the first field that isn't
+    pub fn parse_prefix(prefix: &'a [u8]) -> Self {
+        let mut fields = Vec::new();
+        PrefixScanner {
+            bytes: prefix,
+            pos: 0,
+        }
+        .leading_fields(&mut fields);
+        RawObject { fields }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.raw(key).is_some()
+    }
+
+    /// The field parsed as T, None when it's missing or isn't a T
+    pub fn get<'b, T: Deserialize<'b>>(&'b self, key: &str) -> Option<T> {
+        serde_json::from_str(self.raw(key)?).ok()
+    }
+
+    fn raw(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == key)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// Sets the field, keeping its position when it's already there
+    pub fn set(&mut self, key: &str, value: &impl Serialize) -> serde_json::Result<()> {
+        let value = Cow::Owned(serde_json::to_string(value)?);
+        match self.fields.iter_mut().find(|(field, _)| field == key) {
+            Some((_, current)) => *current = value,
+            None => self.fields.push((key.to_string(), value)),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.fields.len();
+        self.fields.retain(|(field, _)| field != key);
+        self.fields.len() != len
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let len = self
+            .fields
+            .iter()
+            .map(|(key, value)| key.len() + value.len() + 4)
+            .sum::<usize>();
+        let mut body = Vec::with_capacity(len + 2);
+        body.push(b'{');
+        for (index, (key, value)) in self.fields.iter().enumerate() {
+            if index > 0 {
+                body.push(b',');
+            }
+            // writing a string to a vec can't fail
+            let _ = serde_json::to_writer(&mut body, key);
+            body.push(b':');
+            body.extend_from_slice(value.as_bytes());
+        }
+        body.push(b'}');
+        body
+    }
+}
+
+/// Top level keys of an object body that arrives in chunks. Keys are reported by the
+/// chunk their closing quote is in, whatever their values hold.
+#[derive(Debug, Default)]
+pub struct TopLevelKeys {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    expects_key: bool,
+    /// Raw bytes of the top level key being read
+    key: Option<Vec<u8>>,
+}
+
+impl TopLevelKeys {
+    /// The keys completed in this chunk of the body
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut keys = Vec::new();
+        for &byte in chunk {
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    if let Some(key) = self.key.take() {
+                        let mut quoted = Vec::with_capacity(key.len() + 2);
+                        quoted.push(b'"');
+                        quoted.extend_from_slice(&key);
+                        quoted.push(b'"');
+                        keys.push(
+                            serde_json::from_slice(&quoted)
+                                .unwrap_or_else(|_| String::from_utf8_lossy(&key).into_owned()),
+                        );
+                    }
+                    continue;
+                }
+                if let Some(key) = self.key.as_mut() {
+                    key.push(byte);
+                }
+                continue;
+            }
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 && self.expects_key {
+                        self.expects_key = false;
+                        self.key = Some(Vec::new());
+                    }
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    if self.depth == 1 {
+                        self.expects_key = byte == b'{';
+                    }
+                }
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                b',' if self.depth == 1 => self.expects_key = true,
+                _ => {}
+            }
+        }
+        keys
+    }
+}
+
+/// Fields of an object in the order of the body, with values borrowed from it
+struct RawFields<'a>(Vec<(String, &'a RawValue)>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawFields<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = RawFields<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::with_capacity(map.size_hint().unwrap_or(16));
+                while let Some((key, value)) = map.next_entry::<String, &'de RawValue>()? {
+                    fields.push((key, value));
+                }
+                Ok(RawFields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+/// Walks the top level of an object without parsing its values, stopping where the
+/// bytes end
+struct PrefixScanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PrefixScanner<'a> {
+    fn leading_fields(&mut self, fields: &mut Vec<(String, Cow<'a, str>)>) -> Option<()> {
+        self.eat(b'{')?;
+        loop {
+            self.skip_whitespace();
+            let key_end = self.string_end(self.pos)?;
+            let key = serde_json::from_slice(&self.bytes[self.pos..key_end]).ok()?;
+            self.pos = key_end;
+            self.eat(b':')?;
+            let value = self.value()?;
+            fields.push((key, Cow::Borrowed(std::str::from_utf8(value).ok()?)));
+            self.eat(b',')?;
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.bytes.get(self.pos), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        (*self.bytes.get(self.pos)? == byte).then(|| self.pos += 1)
+    }
+
+    /// Position past the closing quote of the string starting at `start`
+    fn string_end(&self, start: usize) -> Option<usize> {
+        if *self.bytes.get(start)? != b'"' {
+            return None;
+        }
+        let mut index = start + 1;
+        while index < self.bytes.len() {
+            match self.bytes[index] {
+                b'\\' => index += 2,
+                b'"' => return Some(index + 1),
+                _ => index += 1,
+            }
+        }
+        None
+    }
+
+    /// The value at the current position, None when it isn't complete
+    fn value(&mut self) -> Option<&'a [u8]> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let end = match *self.bytes.get(start)? {
+            b'"' => self.string_end(start)?,
+            b'{' | b'[' => {
+                let mut depth = 0;
+                let mut index = start;
+                loop {
+                    match *self.bytes.get(index)? {
+                        b'"' => {
+                            index = self.string_end(index)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break index + 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                    index += 1;
+                }
+            }
+            // a number at the end of the bytes may still go on
+            _ => {
+                start
+                    + self.bytes[start..].iter().position(|byte| {
+                        matches!(byte, b',' | b'}' | b']' | b' ' | b'\n' | b'\r' | b'\t')
+                    })?
+            }
+        };
+        self.pos = end;
+        Some(&self.bytes[start..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untouched_fields_are_kept_byte_for_byte() {
+        let body =
+            r#"{"model":"gpt-4o", "messages": [ {"role":"user","content":"hi é"} ],"stream":true}"#;
+        let mut object = RawObject::parse(body.as_bytes()).unwrap();
+        assert_eq!(object.get::<&str>("model"), Some("gpt-4o"));
+        assert_eq!(object.get::<bool>("stream"), Some(true));
+        assert_eq!(object.get::<bool>("model"), None);
+        assert!(!object.contains("user"));
+
+        object.set("model", &"gpt-4o-2024-08-06").unwrap();
+        assert!(object.remove("stream"));
+        assert_eq!(
+            String::from_utf8(object.to_vec()).unwrap(),
+            r#"{"model":"gpt-4o-2024-08-06","messages":[ {"role":"user","content":"hi é"} ]}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_what_isnt_an_object() {
+        assert!(RawObject::parse(b"[1, 2]").is_none());
+        assert!(RawObject::parse(b"{\"model\":").is_none());
+        assert_eq!(RawObject::parse(b"{}").unwrap().to_vec(), b"{}");
+    }
+
+    #[test]
+    fn test_parse_prefix_keeps_the_complete_fields() {
+        let prefix = br#"{"model": "gpt-4o", "stream":true, "tools":[{"name":"a]}"}], "messages":[{"role":"us"#;
+        let object = RawObject::parse_prefix(prefix);
+        assert_eq!(object.get::<&str>("model"), Some("gpt-4o"));
+        assert_eq!(object.get::<bool>("stream"), Some(true));
+        assert!(object.contains("tools"));
+        assert!(!object.contains("messages"));
+
+        let object = RawObject::parse_prefix(br#"{"max_tokens": 10"#);
+        assert!(!object.contains("max_tokens"));
+        assert!(!RawObject::parse_prefix(b"[1, 2]").contains("model"));
+    }
+
+    #[test]
+    fn test_top_level_keys_across_chunks() {
+        let mut keys = TopLevelKeys::default();
+        assert_eq!(
+            keys.feed(
+                br#"{"model":"gpt-4o", "messages":[{"role":"user","content":"say \"user\": {"#
+            ),
+            vec!["model", "messages"]
+        );
+        assert!(keys.feed(br#"hi}, \"seed\""}], "us"#).is_empty());
+        assert_eq!(
+            keys.feed(br#"er": "bob", "stop\u0073": ["#),
+            vec!["user", "stops"]
+        );
+        assert!(keys.feed(br#""a"]}"#).is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use common::configuration::{RequestRuleAction, RequestRuleMatch};
+use hermesllm::clients::endpoints::SupportedAPIsFromClient;
+use serde_json::{json, Map, Value};
+
+/// What the rules can match a request on
+pub struct RuleContext<'a> {
+    /// Request path without the query string
+    pub path: &'a str,
+    /// Model the client asked for
+    pub requested_model: &'a str,
+    /// Model sent upstream
+    pub model: &'a str,
+    pub provider: &'a str,
+    /// Headers of the client request, with lowercase names
+    pub headers: &'a [(String, String)],
+}
+
+pub fn matches(rule_match: &RequestRuleMatch, context: &RuleContext) -> bool {
+    let path_matches = rule_match
+        .path
+        .as_deref()
+        .is_none_or(|path| path == context.path);
+    let model_matches = rule_match.models.as_ref().is_none_or(|models| {
+        models.iter().any(|model| {
+            model == context.requested_model || model == context.model || model == context.provider
+        })
+    });
+    let headers_match = rule_match.headers.iter().flatten().all(|(name, value)| {
+        context.headers.iter().any(|(header, header_value)| {
+            header.eq_ignore_ascii_case(name) && header_value == value
+        })
+    });
+    path_matches && model_matches && headers_match
+}
+
+/// Applies the actions to the body of a request in the client's api, returns what they
+/// changed. Actions that don't change anything are left out.
+pub fn apply_actions(
+    actions: &[RequestRuleAction],
+    api: &SupportedAPIsFromClient,
+    body: &mut Value,
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    for action in actions {
+        match action {
+            RequestRuleAction::Set { field, value } => {
+                if get_path(body, field) != Some(value) && set_path(body, field, value.clone()) {
+                    changes.push(format!("set {}={}", field, value));
+                }
+            }
+            RequestRuleAction::Remove { field } => {
+                if remove_path(body, field) {
+                    changes.push(format!("removed {}", field));
+                }
+            }
+            RequestRuleAction::Cap { field, max } => {
+                let current = get_path(body, field).and_then(Value::as_f64);
+                let limit = max.as_f64().unwrap_or(f64::MAX);
+                if let Some(current) = current.filter(|current| *current > limit) {
+                    set_path(body, field, Value::Number(max.clone()));
+                    changes.push(format!("capped {} {} -> {}", field, current, max));
+                }
+            }
+            RequestRuleAction::AddMessage { role, content } => {
+                if add_message(api, body, role, content) {
+                    changes.push(format!("added {} message", role));
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Adds the message where the api keeps messages of its role
+fn add_message(api: &SupportedAPIsFromClient, body: &mut Value, role: &str, content: &str) -> bool {
+    let Some(request) = body.as_object_mut() else {
+        return false;
+    };
+    match (api, role) {
+        (SupportedAPIsFromClient::OpenAIChatCompletions(_), "system") => {
+            let Some(Value::Array(messages)) = request.get_mut("messages") else {
+                return false;
+            };
+            // after the system messages the client sent
+            let position = messages
+                .iter()
+                .take_while(|message| message["role"] == "system")
+                .count();
+            messages.insert(position, json!({"role": "system", "content": content}));
+        }
+        (SupportedAPIsFromClient::AnthropicMessagesAPI(_), "system") => {
+            match request.get_mut("system") {
+                Some(Value::String(system)) if !system.is_empty() => {
+                    system.push_str("\n\n");
+                    system.push_str(content);
+                }
+                Some(Value::Array(blocks)) => blocks.push(json!({"type": "text", "text": content})),
+                _ => {
+                    request.insert("system".to_string(), Value::String(content.to_string()));
+                }
+            }
+        }
+        (SupportedAPIsFromClient::OpenAIResponsesAPI(_), "system") => {
+            match request.get_mut("instructions") {
+                Some(Value::String(instructions)) if !instructions.is_empty() => {
+                    instructions.push_str("\n\n");
+                    instructions.push_str(content);
+                }
+                _ => {
+                    request.insert(
+                        "instructions".to_string(),
+                        Value::String(content.to_string()),
+                    );
+                }
+            }
+        }
+        (SupportedAPIsFromClient::OpenAIResponsesAPI(_), _) => {
+            let message = json!({"role": role, "content": content});
+            match request.get_mut("input") {
+                Some(Value::Array(input)) => input.push(message),
+                Some(Value::String(text)) => {
+                    let text = std::mem::take(text);
+                    request.insert(
+                        "input".to_string(),
+                        json!([{"role": "user", "content": text}, message]),
+                    );
+                }
+                _ => return false,
+            }
+        }
+        (_, _) => {
+            let Some(Value::Array(messages)) = request.get_mut("messages") else {
+                return false;
+            };
+            messages.push(json!({"role": role, "content": content}));
+        }
+    }
+    true
+}
+
+fn get_path<'a>(value: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(value, |value, key| value.as_object()?.get(key))
+}
+
+/// Sets the field, adding the objects along the path that are missing. Returns false
+/// when something along the path isn't an object.
+fn set_path(value: &mut Value, field: &str, new_value: Value) -> bool {
+    let mut keys = field.split('.').peekable();
+    let mut current = value;
+    while let Some(key) = keys.next() {
+        let Some(object) = current.as_object_mut() else {
+            return false;
+        };
+        if keys.peek().is_none() {
+            object.insert(key.to_string(), new_value);
+            return true;
+        }
+        current = object
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    false
+}
+
+fn remove_path(value: &mut Value, field: &str) -> bool {
+    let (parent, key) = match field.rsplit_once('.') {
+        Some((parent, key)) => (
+            parent
+                .split('.')
+                .try_fold(value, |value, key| value.as_object_mut()?.get_mut(key)),
+            key,
+        ),
+        None => (Some(value), field),
+    };
+    parent
+        .and_then(Value::as_object_mut)
+        .is_some_and(|object| object.remove(key).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermesllm::apis::anthropic::AnthropicApi;
+    use hermesllm::apis::openai::OpenAIApi;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_matches() {
+        let headers = vec![("x-team".to_string(), "research".to_string())];
+        let context = RuleContext {
+            path: "/v1/chat/completions",
+            requested_model: "gpt-4o",
+            model: "gpt-4o",
+            provider: "openai/gpt-4o",
+            headers: &headers,
+        };
+        assert!(matches(&RequestRuleMatch::default(), &context));
+        assert!(matches(
+            &RequestRuleMatch {
+                path: Some("/v1/chat/completions".to_string()),
+                models: Some(vec!["openai/gpt-4o".to_string()]),
+                headers: Some(HashMap::from([(
+                    "X-Team".to_string(),
+                    "research".to_string()
+                )])),
+            },
+            &context
+        ));
+        assert!(!matches(
+            &RequestRuleMatch {
+                models: Some(vec!["claude-sonnet-4".to_string()]),
+                ..Default::default()
+            },
+            &context
+        ));
+        assert!(!matches(
+            &RequestRuleMatch {
+                headers: Some(HashMap::from([("x-team".to_string(), "ops".to_string())])),
+                ..Default::default()
+            },
+            &context
+        ));
+    }
+
+    #[test]
+    fn test_apply_actions() {
+        let actions = vec![
+            RequestRuleAction::Set {
+                field: "stream".to_string(),
+                value: json!(true),
+            },
+            RequestRuleAction::Cap {
+                field: "max_tokens".to_string(),
+                max: 4096.into(),
+            },
+            RequestRuleAction::Remove {
+                field: "metadata.user_id".to_string(),
+            },
+            RequestRuleAction::AddMessage {
+                role: "system".to_string(),
+                content: "Answer in English.".to_string(),
+            },
+        ];
+        let api = SupportedAPIsFromClient::OpenAIChatCompletions(OpenAIApi::ChatCompletions);
+        let mut body = json!({
+            "model": "gpt-4o",
+            "max_tokens": 8000,
+            "metadata": {"user_id": "alice"},
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "hi"}
+            ]
+        });
+        let changes = apply_actions(&actions, &api, &mut body);
+        assert_eq!(
+            changes,
+            vec![
+                "set stream=true",
+                "capped max_tokens 8000 -> 4096",
+                "removed metadata.user_id",
+                "added system message"
+            ]
+        );
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 4096);
+        assert_eq!(body["metadata"], json!({}));
+        assert_eq!(body["messages"][1]["content"], "Answer in English.");
+        assert_eq!(body["messages"][2]["content"], "hi");
+
+        // already applied, nothing changes
+        assert!(apply_actions(&actions[..3], &api, &mut body).is_empty());
+    }
+
+    #[test]
+    fn test_add_message_to_system_prompt_of_messages_api() {
+        let api = SupportedAPIsFromClient::AnthropicMessagesAPI(AnthropicApi::Messages);
+        let mut body = json!({"model": "claude-sonnet-4", "system": "Be brief.", "messages": []});
+        assert!(add_message(&api, &mut body, "system", "Answer in English."));
+        assert_eq!(body["system"], "Be brief.\n\nAnswer in English.");
+    }
+}
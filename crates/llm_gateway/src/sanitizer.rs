@@ -0,0 +1,150 @@
+use serde_json::Value;
+
+use crate::raw_body::RawObject;
+
+/// Fields only meant for the gateway, stripped from every upstream request
+pub const GATEWAY_ONLY_FIELDS: &[&str] = &["metadata.archgw_preference_config"];
+
+/// What the sanitizer changed in a request body
+#[derive(Debug, Default, PartialEq)]
+pub struct PayloadDiff {
+    /// Paths of the removed fields, array elements are listed by index
+    pub removed: Vec<String>,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// Removes fields from a JSON request body, as dotted paths into the body. Objects in
+/// arrays along the path are all visited, e.g. `tools.function.strict` strips `strict`
+/// from every tool. Only the top level fields along the paths are parsed, the others are
+/// copied as they are. Returns None when nothing matched, the body is then sent as is,
+/// byte for byte.
+pub fn strip_fields<'a>(
+    body: &[u8],
+    fields: impl IntoIterator<Item = &'a str>,
+) -> Option<(Vec<u8>, PayloadDiff)> {
+    let mut object = RawObject::parse(body)?;
+    let mut removed = Vec::new();
+    for field in fields {
+        let path: Vec<&str> = field.split('.').filter(|key| !key.is_empty()).collect();
+        let Some((key, rest)) = path.split_first() else {
+            continue;
+        };
+        if rest.is_empty() {
+            if object.remove(key) {
+                removed.push(key.to_string());
+            }
+            continue;
+        }
+        let Some(mut value) = object.get::<Value>(key) else {
+            continue;
+        };
+        let removed_before = removed.len();
+        strip_path(&mut value, rest, key.to_string(), &mut removed);
+        if removed.len() > removed_before {
+            object.set(key, &value).ok()?;
+        }
+    }
+    if removed.is_empty() {
+        return None;
+    }
+
+    let sanitized = object.to_vec();
+    let diff = PayloadDiff {
+        removed,
+        bytes_before: body.len(),
+        bytes_after: sanitized.len(),
+    };
+    Some((sanitized, diff))
+}
+
+fn strip_path(value: &mut Value, path: &[&str], prefix: String, removed: &mut Vec<String>) {
+    let Some((key, rest)) = path.split_first() else {
+        return;
+    };
+    match value {
+        Value::Object(object) if rest.is_empty() => {
+            removed.extend(object.remove(*key).map(|_| join(&prefix, key)));
+        }
+        Value::Object(object) => {
+            if let Some(child) = object.get_mut(*key) {
+                strip_path(child, rest, join(&prefix, key), removed);
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                strip_path(item, path, join(&prefix, &index.to_string()), removed);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_strip_top_level_and_nested_fields() {
+        let body = serde_json::to_vec(&json!({
+            "model": "gpt-4o",
+            "user": "u-1",
+            "metadata": {"archgw_preference_config": "routes", "team": "search"},
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let (sanitized, diff) = strip_fields(
+            &body,
+            GATEWAY_ONLY_FIELDS.iter().copied().chain(["user", "seed"]),
+        )
+        .unwrap();
+        let sanitized: Value = serde_json::from_slice(&sanitized).unwrap();
+        assert_eq!(
+            sanitized,
+            json!({
+                "model": "gpt-4o",
+                "metadata": {"team": "search"},
+                "messages": [{"role": "user", "content": "hi"}]
+            })
+        );
+        assert_eq!(
+            diff.removed,
+            vec!["metadata.archgw_preference_config", "user"]
+        );
+        assert_eq!(diff.bytes_before, body.len());
+        assert!(diff.bytes_after < diff.bytes_before);
+    }
+
+    #[test]
+    fn test_strip_fields_in_arrays() {
+        let body = serde_json::to_vec(&json!({
+            "tools": [
+                {"type": "function", "function": {"name": "a", "strict": true}},
+                {"type": "function", "function": {"name": "b"}}
+            ]
+        }))
+        .unwrap();
+
+        let (sanitized, diff) = strip_fields(&body, ["tools.function.strict"]).unwrap();
+        let sanitized: Value = serde_json::from_slice(&sanitized).unwrap();
+        assert!(sanitized["tools"][0]["function"].get("strict").is_none());
+        assert_eq!(diff.removed, vec!["tools.0.function.strict"]);
+    }
+
+    #[test]
+    fn test_untouched_body_is_not_rewritten() {
+        let body = br#"{"model":"gpt-4o","messages":[]}"#;
+        assert_eq!(strip_fields(body, ["metadata", "user"]), None);
+        assert_eq!(strip_fields(b"not json", ["user"]), None);
+    }
+}
@@ -8,31 +8,87 @@ use std::num::NonZero;
 use std::rc::Rc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::metrics::Metrics;
-use common::configuration::{LlmProvider, LlmProviderType, Overrides};
+use crate::chatgpt;
+use crate::metrics::{MetricLabels, Metrics};
+use crate::middleware::{Middleware, MiddlewareContext, MiddlewareError};
+use crate::raw_body::{RawObject, TopLevelKeys};
+use crate::request_rules::{self, RuleContext};
+use crate::sanitizer::{strip_fields, GATEWAY_ONLY_FIELDS};
+use crate::user_hashing;
+use common::compression::{self, ContentEncoding, StreamDecoder, StreamEncoder};
+use common::configuration::{
+    Listener, LlmProvider, LlmProviderType, Overrides, RequestRule, UserHashingConfig,
+};
 use common::consts::{
-    ARCH_IS_STREAMING_HEADER, ARCH_PROVIDER_HINT_HEADER, ARCH_ROUTING_HEADER, HEALTHZ_PATH,
-    RATELIMIT_SELECTOR_HEADER_KEY, REQUEST_ID_HEADER, TRACE_PARENT_HEADER,
+    ARCH_CONNECTION_WARMUP_HEADER, ARCH_IS_STREAMING_HEADER, ARCH_LISTENER_NAME_HEADER,
+    ARCH_MALFORMED_RESPONSE_HEADER, ARCH_MAX_TOKENS_ADJUSTED_HEADER, ARCH_PROVIDER_HINT_HEADER,
+    ARCH_RATELIMIT_CLAIMS_HEADER, ARCH_REQUEST_ID_HEADER, ARCH_REQUEST_TIMEOUT_HEADER,
+    ARCH_ROUTING_HEADER, ARCH_SSE_TAP_HEADER, ARCH_TRAFFIC_SPLIT_HEADER,
+    ARCH_UPSTREAM_ACCESS_TOKEN_HEADER, ARCH_UPSTREAM_ERROR_HEADER, AZURE_CLIENT_REQUEST_ID_HEADER,
+    CHATGPT_ACCOUNT_ID_HEADER, CHATGPT_SESSION_ID_HEADER, DEFAULT_STREAM_STALL_THRESHOLD_MS,
+    ENVOY_UPSTREAM_TIMEOUT_HEADER, HEALTHZ_PATH, LARGE_RESPONSE_BODY_BYTES,
+    OPENAI_CLIENT_REQUEST_ID_HEADER, OPENAI_ORGANIZATION_HEADER, OPENAI_PROJECT_HEADER,
+    RATELIMIT_SELECTOR_HEADER_KEY, REQUEST_ID_HEADER, RESPONSE_BODY_READ_CHUNK_BYTES,
+    RESPONSE_USAGE_TAIL_BYTES, SSE_TAP_UPSTREAM_PREFIX, TRACE_PARENT_HEADER,
+    USER_RATELIMIT_SELECTOR,
 };
 use common::errors::ServerError;
 use common::llm_providers::LlmProviders;
 use common::ratelimit::Header;
+use common::request_id::resolve_request_id;
+use common::routing::ProviderHint;
 use common::stats::{IncrementingMetric, RecordingMetric};
 use common::{ratelimit, routing, tokenizer};
+use hermesllm::apis::anthropic::AnthropicApi;
+use hermesllm::apis::anthropic_batch::{MessageBatch, MessageBatchStatus};
+use hermesllm::apis::openai::{ChatCompletionsResponse, ChatCompletionsStreamResponse};
+use hermesllm::apis::openai_audio::{
+    multipart_model_field, replace_multipart_model_field, AudioApi, SpeechRequest,
+    TranscriptionUsage,
+};
+use hermesllm::apis::openai_batch::{BatchApi, BatchObject};
 use hermesllm::apis::streaming_shapes::amazon_bedrock_binary_frame::BedrockBinaryFrameDecoder;
 use hermesllm::apis::streaming_shapes::sse::{SseEvent, SseStreamBuffer, SseStreamBufferTrait};
 use hermesllm::apis::streaming_shapes::sse_chunk_processor::SseChunkProcessor;
-use hermesllm::clients::endpoints::SupportedAPIsFromClient;
-use hermesllm::providers::response::ProviderResponse;
+use hermesllm::clients::endpoints::{render_path_template, SupportedAPIsFromClient};
+use hermesllm::providers::context_window::{known_context_window, MaxTokensAdjustment};
+use hermesllm::providers::error::{error_header_value, UpstreamError};
+use hermesllm::providers::repair::repair_response_body;
+use hermesllm::providers::response::{
+    scan_trailing_usage_counts, scan_usage_counts, ProviderResponse,
+};
 use hermesllm::providers::streaming_response::ProviderStreamResponse;
 use hermesllm::{
     DecodedFrame, ProviderId, ProviderRequest, ProviderRequestType, ProviderResponseType,
     ProviderStreamResponseType,
 };
 
+/// Request fields that send requests down the full path, sampling params are adjusted to
+/// what the provider supports
+const FAST_PATH_EXCLUDED_FIELDS: &[&str] = &[
+    "frequency_penalty",
+    "presence_penalty",
+    "seed",
+    "stop",
+    "stop_sequences",
+];
+
+/// Request fields that keep a body from being streamed upstream, the gateway has to see
+/// or change them before the request goes out
+fn excludes_body_streaming(field: &str) -> bool {
+    ["user", "metadata"]
+        .iter()
+        .chain(FAST_PATH_EXCLUDED_FIELDS)
+        .any(|excluded| *excluded == field)
+}
+
+/// Bytes of a request body buffered before it may be streamed upstream
+const DEFAULT_STREAMING_MIN_BODY_BYTES: usize = 1024 * 1024;
+
 pub struct StreamContext {
     metrics: Rc<Metrics>,
-    ratelimit_selector: Option<Header>,
+    /// Selector header and the selectors of the end user's JWT claims
+    ratelimit_selectors: Vec<Header>,
     streaming_response: bool,
     response_tokens: usize,
     /// The API that is requested by the client (before compatibility mapping)
@@ -47,7 +103,14 @@ pub struct StreamContext {
     ttft_time: Option<u128>,
     traceparent: Option<String>,
     request_body_sent_time: Option<u128>,
-    _overrides: Rc<Option<Overrides>>,
+    overrides: Rc<Option<Overrides>>,
+    listeners: Rc<Vec<Listener>>,
+    /// Name of the listener the request came in on, set by the envoy listener route
+    listener_name: Option<String>,
+    /// Set for audio requests, which are proxied without API conversion
+    audio_api: Option<AudioApi>,
+    /// Set for batch and file requests, which are proxied without API conversion
+    batch_api: Option<BatchApi>,
     user_message: Option<String>,
     upstream_status_code: Option<StatusCode>,
     binary_frame_decoder: Option<BedrockBinaryFrameDecoder<bytes::BytesMut>>,
@@ -55,6 +118,45 @@ pub struct StreamContext {
     http_protocol: Option<String>,
     sse_buffer: Option<SseStreamBuffer>,
     sse_chunk_processor: Option<SseChunkProcessor>,
+    response_body_bytes: usize,
+    response_truncated: bool,
+    /// End of a large response that is forwarded as it arrives, None when responses are
+    /// buffered
+    large_response_tail: Option<Vec<u8>>,
+    /// Deadline of the upstream request, enforced by envoy
+    request_timeout_ms: Option<u64>,
+    /// Model sent upstream, used as a metric label
+    resolved_model: Option<String>,
+    input_tokens: Option<u64>,
+    /// Provider the in flight gauge was incremented for
+    in_flight_provider: Option<String>,
+    last_chunk_time: Option<SystemTime>,
+    /// Output token limit lowered to fit the context window, reported in a response header
+    max_tokens_adjustment: Option<MaxTokensAdjustment>,
+    /// Traffic split of the model alias the request was assigned to, a metric label
+    traffic_split: Option<String>,
+    middlewares: Rc<Vec<Box<dyn Middleware>>>,
+    request_rules: Rc<Vec<RequestRule>>,
+    /// Client request headers, kept for the middlewares
+    request_headers: Vec<(String, String)>,
+    /// Encoding of the client's request body, decoded before it is parsed
+    request_encoding: Option<ContentEncoding>,
+    client_accept_encoding: Option<String>,
+    /// Decodes a compressed upstream response before it is parsed
+    upstream_decoder: Option<StreamDecoder>,
+    /// Compresses the response for the client when compress_responses is on
+    client_encoder: Option<StreamEncoder>,
+    /// brightstaff records this stream, raw upstream chunks are sent along as SSE comments
+    sse_tap: bool,
+    /// End user of the request before it is hashed, for rate limits
+    end_user: Option<String>,
+    /// Whether the request body is streamed upstream as it arrives, None until enough of
+    /// it arrived to decide
+    streams_request_body: Option<bool>,
+    /// Request body bytes streamed upstream so far
+    streamed_request_bytes: usize,
+    /// Top level keys of a streamed request body, read as the chunks go by
+    streamed_request_keys: TopLevelKeys,
 }
 
 impl StreamContext {
@@ -62,11 +164,19 @@ impl StreamContext {
         metrics: Rc<Metrics>,
         llm_providers: Rc<LlmProviders>,
         overrides: Rc<Option<Overrides>>,
+        listeners: Rc<Vec<Listener>>,
+        middlewares: Rc<Vec<Box<dyn Middleware>>>,
+        request_rules: Rc<Vec<RequestRule>>,
     ) -> Self {
         StreamContext {
             metrics,
-            _overrides: overrides,
-            ratelimit_selector: None,
+            overrides,
+            listeners,
+            listener_name: None,
+            audio_api: None,
+            batch_api: None,
+            ratelimit_selectors: Vec::new(),
+            end_user: None,
             streaming_response: false,
             response_tokens: 0,
             client_api: None,
@@ -86,18 +196,94 @@ impl StreamContext {
             http_protocol: None,
             sse_buffer: None,
             sse_chunk_processor: None,
+            response_body_bytes: 0,
+            response_truncated: false,
+            large_response_tail: None,
+            request_timeout_ms: None,
+            resolved_model: None,
+            input_tokens: None,
+            in_flight_provider: None,
+            last_chunk_time: None,
+            max_tokens_adjustment: None,
+            traffic_split: None,
+            middlewares,
+            request_rules,
+            request_headers: Vec::new(),
+            request_encoding: None,
+            client_accept_encoding: None,
+            upstream_decoder: None,
+            client_encoder: None,
+            sse_tap: false,
+            streams_request_body: None,
+            streamed_request_bytes: 0,
+            streamed_request_keys: TopLevelKeys::default(),
         }
     }
 
-    /// Returns the appropriate request identifier for logging.
-    /// Uses request_id (from x-request-id header) when available, otherwise returns a literal indicating no request ID.
+    /// Returns the request id for logging, resolved when the request headers arrive
     fn request_identifier(&self) -> String {
-        self.request_id
+        self.request_id.clone().unwrap_or_default()
+    }
+
+    /// Resolves the request id from the `x-archgw-request-id` or `x-request-id` headers,
+    /// generating one when both are missing, and forwards it upstream
+    fn resolve_request_id(&mut self) {
+        let request_id = resolve_request_id(
+            self.get_http_request_header(ARCH_REQUEST_ID_HEADER)
+                .as_deref(),
+            self.get_http_request_header(REQUEST_ID_HEADER).as_deref(),
+        );
+        self.set_http_request_header(ARCH_REQUEST_ID_HEADER, Some(&request_id));
+        self.request_id = Some(request_id);
+    }
+    fn compress_responses(&self) -> bool {
+        self.overrides
+            .as_ref()
+            .as_ref()
+            .and_then(|overrides| overrides.compress_responses)
+            .unwrap_or(false)
+    }
+
+    fn user_hashing(&self) -> Option<&UserHashingConfig> {
+        self.overrides
+            .as_ref()
+            .as_ref()
+            .and_then(|overrides| overrides.user_hashing.as_ref())
+    }
+
+    fn max_request_body_bytes(&self) -> Option<usize> {
+        self.overrides
+            .as_ref()
+            .as_ref()
+            .and_then(|overrides| overrides.max_request_body_bytes)
+    }
+
+    fn max_response_body_bytes(&self) -> Option<usize> {
+        self.overrides
+            .as_ref()
             .as_ref()
-            .filter(|id| !id.is_empty())
-            .cloned()
-            .unwrap_or_else(|| "NO_REQUEST_ID".to_string())
+            .and_then(|overrides| overrides.max_response_body_bytes)
     }
+
+    fn reject_oversized_request(&self, body_size: usize, max_bytes: usize) {
+        warn!(
+            "[PLANO_REQ_ID:{}] REQUEST_BODY_TOO_LARGE: size={} limit={}",
+            self.request_identifier(),
+            body_size,
+            max_bytes
+        );
+        self.metrics.oversized_requests.increment(1);
+        self.send_server_error(
+            ServerError::BadRequest {
+                why: format!(
+                    "Request body of {} bytes exceeds the limit of {} bytes",
+                    body_size, max_bytes
+                ),
+            },
+            Some(StatusCode::PAYLOAD_TOO_LARGE),
+        );
+    }
+
     fn llm_provider(&self) -> &LlmProvider {
         self.llm_provider
             .as_ref()
@@ -112,27 +298,164 @@ impl StreamContext {
     fn update_upstream_path(&mut self, request_path: &str) {
         let hermes_provider_id = self.llm_provider().to_provider_id();
         if let Some(api) = &self.client_api {
-            let target_endpoint = api.target_endpoint_for_provider(
-                &hermes_provider_id,
-                request_path,
-                self.llm_provider()
-                    .model
-                    .as_ref()
-                    .unwrap_or(&"".to_string()),
-                self.streaming_response,
-                self.llm_provider().base_url_path_prefix.as_deref(),
-            );
+            let model_id = self
+                .llm_provider()
+                .upstream_model_id(self.llm_provider().model.as_deref().unwrap_or_default())
+                .to_string();
+            let target_endpoint =
+                match (self.llm_provider().chat_path.as_deref(), &self.resolved_api) {
+                    (Some(chat_path), Some(SupportedUpstreamAPIs::OpenAIChatCompletions(_))) => {
+                        render_path_template(
+                            chat_path,
+                            &model_id,
+                            self.llm_provider().base_url_path_prefix.as_deref(),
+                        )
+                    }
+                    _ => api.target_endpoint_for_provider(
+                        &hermes_provider_id,
+                        request_path,
+                        &model_id,
+                        self.streaming_response,
+                        self.llm_provider().base_url_path_prefix.as_deref(),
+                    ),
+                };
             if target_endpoint != request_path {
                 self.set_http_request_header(":path", Some(&target_endpoint));
             }
         }
     }
 
+    /// Routing, auth and header rules for the upstream request. Assumes the provider has been set.
+    /// Routes the request to the cluster of the selected provider
+    fn add_routing_header(&mut self) {
+        if self.llm_provider().endpoint.is_some() {
+            self.add_http_request_header(
+                ARCH_ROUTING_HEADER,
+                &self
+                    .llm_provider()
+                    .cluster_name
+                    .as_ref()
+                    .unwrap()
+                    .to_string(),
+            );
+        } else {
+            self.add_http_request_header(
+                ARCH_ROUTING_HEADER,
+                &self.llm_provider().provider_interface.to_string(),
+            );
+        }
+    }
+
+    /// Sends a warmup request of brightstaff to the provider's cluster as a HEAD of its
+    /// base path, without credentials. Whatever the provider answers, the connection
+    /// stays open in the cluster's pool.
+    fn forward_connection_warmup(&mut self) {
+        self.remove_http_request_header(ARCH_CONNECTION_WARMUP_HEADER);
+        let path = self
+            .llm_provider()
+            .base_url_path_prefix
+            .clone()
+            .unwrap_or_else(|| "/".to_string());
+        self.set_http_request_header(":path", Some(&path));
+        self.set_http_request_header(":method", Some("HEAD"));
+        self.add_routing_header();
+        debug!(
+            "[PLANO_REQ_ID:{}] CONNECTION_WARMUP: provider={}",
+            self.request_identifier(),
+            self.llm_provider().name
+        );
+    }
+
+    fn prepare_upstream_headers(&mut self) {
+        // read before the passthrough rules may drop it
+        let requested_timeout_ms = self
+            .get_http_request_header(ARCH_REQUEST_TIMEOUT_HEADER)
+            .and_then(|value| value.parse::<u64>().ok());
+        self.add_routing_header();
+        self.filter_passthrough_headers();
+        // minted by brightstaff for providers authenticated with a service account
+        let access_token = self.get_http_request_header(ARCH_UPSTREAM_ACCESS_TOKEN_HEADER);
+        self.remove_http_request_header(ARCH_UPSTREAM_ACCESS_TOKEN_HEADER);
+        let passthrough_auth = self
+            .listener()
+            .and_then(|listener| listener.passthrough_auth)
+            .unwrap_or(false);
+        if passthrough_auth {
+            debug!(
+                "[PLANO_REQ_ID:{}] AUTH_PASSTHROUGH: forwarding client credentials",
+                self.request_identifier()
+            );
+        } else if let Err(error) = self.modify_auth_headers(access_token) {
+            // ensure that the provider has an endpoint if the access key is missing else return a bad request
+            if self.llm_provider.as_ref().unwrap().endpoint.is_none()
+                && self.llm_provider.as_ref().unwrap().provider_interface != LlmProviderType::Arch
+            {
+                self.send_server_error(error, Some(StatusCode::BAD_REQUEST));
+            }
+        }
+        self.add_openai_scope_headers();
+        self.add_client_request_id_header();
+        self.add_chatgpt_headers();
+        self.apply_header_rules();
+        self.apply_request_timeout(requested_timeout_ms);
+    }
+
+    /// Sets the upstream deadline from the provider timeout and the client's timeout header.
+    /// Envoy answers with a 504 once it passes.
+    fn apply_request_timeout(&mut self, requested_timeout_ms: Option<u64>) {
+        self.remove_http_request_header(ARCH_REQUEST_TIMEOUT_HEADER);
+        self.request_timeout_ms = self.llm_provider().request_timeout_ms(requested_timeout_ms);
+        if let Some(timeout_ms) = self.request_timeout_ms {
+            debug!(
+                "[PLANO_REQ_ID:{}] REQUEST_TIMEOUT: timeout_ms={}",
+                self.request_identifier(),
+                timeout_ms
+            );
+            self.set_http_request_header(
+                ENVOY_UPSTREAM_TIMEOUT_HEADER,
+                Some(&timeout_ms.to_string()),
+            );
+        }
+    }
+
+    /// Whether a 504 was caused by the deadline of this request rather than by the provider
+    fn request_timed_out(&self, status_code: StatusCode) -> bool {
+        let Some(timeout_ms) = self.request_timeout_ms else {
+            return false;
+        };
+        let elapsed = get_current_time()
+            .ok()
+            .and_then(|now| now.duration_since(self.start_time).ok())
+            .unwrap_or_default();
+        status_code == StatusCode::GATEWAY_TIMEOUT && elapsed.as_millis() >= timeout_ms as u128
+    }
+
+    fn listener(&self) -> Option<&Listener> {
+        let name = self.listener_name.as_ref()?;
+        self.listeners
+            .iter()
+            .find(|listener| &listener.name == name)
+    }
+
     fn select_llm_provider(&mut self) {
-        let provider_hint = self
+        let provider_hint: Option<ProviderHint> = self
             .get_http_request_header(ARCH_PROVIDER_HINT_HEADER)
             .map(|llm_name| llm_name.into());
 
+        // fall back to the listener's default provider when the hint doesn't name a configured one
+        let listener_default = self
+            .listener()
+            .and_then(|listener| listener.default_provider.clone());
+        let provider_hint = match (provider_hint, listener_default) {
+            (Some(ProviderHint::Name(name)), Some(default))
+                if self.llm_providers.get(&name).is_none() =>
+            {
+                Some(ProviderHint::Name(default))
+            }
+            (None, Some(default)) => Some(ProviderHint::Name(default)),
+            (hint, _) => hint,
+        };
+
         // info!("llm_providers: {:?}", self.llm_providers);
         self.llm_provider = Some(routing::get_llm_provider(
             &self.llm_providers,
@@ -148,7 +471,24 @@ impl StreamContext {
         );
     }
 
-    fn modify_auth_headers(&mut self) -> Result<(), ServerError> {
+    fn modify_auth_headers(&mut self, access_token: Option<String>) -> Result<(), ServerError> {
+        // Providers authenticated with a service account take the token minted for this
+        // request as a Bearer token
+        if self.llm_provider().access_token_url().is_some() {
+            let access_token = access_token.ok_or(ServerError::BadRequest {
+                why: format!(
+                    "No access token minted for selected LLM Provider \"{}\"",
+                    self.llm_provider()
+                ),
+            })?;
+            self.remove_http_request_header("x-api-key");
+            self.set_http_request_header(
+                "Authorization",
+                Some(&format!("Bearer {}", access_token)),
+            );
+            return Ok(());
+        }
+
         let llm_provider_api_key_value =
             self.llm_provider()
                 .access_key
@@ -160,8 +500,28 @@ impl StreamContext {
                     ),
                 })?;
 
+        // Providers can override the header used to carry the access key
+        if let Some(auth_header_name) = self.llm_provider().auth_header_name.as_deref() {
+            self.remove_http_request_header("x-api-key");
+            self.remove_http_request_header("Authorization");
+            if auth_header_name.eq_ignore_ascii_case("authorization") {
+                let authorization_header_value = format!("Bearer {}", llm_provider_api_key_value);
+                self.set_http_request_header("Authorization", Some(&authorization_header_value));
+            } else {
+                self.set_http_request_header(auth_header_name, Some(llm_provider_api_key_value));
+            }
+            return Ok(());
+        }
+
         // Set API-specific headers based on the resolved upstream API
         match self.resolved_api.as_ref() {
+            // Vertex AI takes OAuth2 access tokens whatever the API, Gemini or Claude, a
+            // static one can be set as the access key
+            Some(_) if self.llm_provider().provider_interface == LlmProviderType::VertexAI => {
+                self.remove_http_request_header("x-api-key");
+                let authorization_header_value = format!("Bearer {}", llm_provider_api_key_value);
+                self.set_http_request_header("Authorization", Some(&authorization_header_value));
+            }
             Some(SupportedUpstreamAPIs::AnthropicMessagesAPI(_)) => {
                 // Anthropic API requires x-api-key and anthropic-version headers
                 // Remove any existing Authorization header since Anthropic doesn't use it
@@ -169,6 +529,13 @@ impl StreamContext {
                 self.set_http_request_header("x-api-key", Some(llm_provider_api_key_value));
                 self.set_http_request_header("anthropic-version", Some("2023-06-01"));
             }
+            Some(SupportedUpstreamAPIs::GeminiGenerateContent(_)) => {
+                // The native Gemini API takes the key in x-goog-api-key, the OpenAI
+                // compatible endpoint takes it as a Bearer token
+                self.remove_http_request_header("Authorization");
+                self.remove_http_request_header("x-api-key");
+                self.set_http_request_header("x-goog-api-key", Some(llm_provider_api_key_value));
+            }
             Some(
                 SupportedUpstreamAPIs::OpenAIChatCompletions(_)
                 | SupportedUpstreamAPIs::AmazonBedrockConverse(_)
@@ -187,6 +554,140 @@ impl StreamContext {
         Ok(())
     }
 
+    /// Applies provider specific request fixups that the API level conversion can't know about
+    fn sanitize_for_provider(&self, request: &mut ProviderRequestType) {
+        if let (ProviderId::Mistral, ProviderRequestType::ChatCompletionsRequest(chat_req)) =
+            (self.get_provider_id(), request)
+        {
+            chat_req.sanitize_for_mistral(self.llm_provider().safe_prompt);
+        }
+    }
+
+    /// Removes gateway only fields and the fields the provider is configured to strip from
+    /// the upstream body
+    fn strip_request_fields(&self, body: Vec<u8>) -> Vec<u8> {
+        let configured = self.llm_provider().strip_fields.iter().flatten();
+        let fields = GATEWAY_ONLY_FIELDS
+            .iter()
+            .copied()
+            .chain(configured.map(String::as_str));
+        match strip_fields(&body, fields) {
+            Some((sanitized, diff)) => {
+                debug!(
+                    "[PLANO_REQ_ID:{}] PAYLOAD_SANITIZED: provider={} removed={:?} bytes={}->{}",
+                    self.request_identifier(),
+                    self.llm_provider().name,
+                    diff.removed,
+                    diff.bytes_before,
+                    diff.bytes_after
+                );
+                sanitized
+            }
+            None => body,
+        }
+    }
+
+    /// Applies the system prompt policy of the provider in the api the provider speaks
+    fn apply_system_prompt_policy(&self, request: &mut ProviderRequestType) {
+        if let Some(policy) = self.llm_provider().system_prompt_policy.as_ref() {
+            debug!(
+                "[PLANO_REQ_ID:{}] SYSTEM_PROMPT_POLICY: provider={} mode={:?}",
+                self.request_identifier(),
+                self.llm_provider().name,
+                policy.mode
+            );
+            request.apply_system_prompt(policy.mode, &policy.content);
+        }
+    }
+
+    /// Drops client headers that are not on the provider's passthrough allowlist
+    fn filter_passthrough_headers(&mut self) {
+        let rules = match self.llm_provider().headers.clone() {
+            Some(rules) if rules.passthrough.is_some() => rules,
+            _ => return,
+        };
+        for (name, _) in self.get_http_request_headers() {
+            if !rules.allows_passthrough(&name) {
+                debug!(
+                    "[PLANO_REQ_ID:{}] HEADER_DROPPED: name={}",
+                    self.request_identifier(),
+                    name
+                );
+                self.remove_http_request_header(&name);
+            }
+        }
+    }
+
+    /// Adds `OpenAI-Organization` / `OpenAI-Project` when the provider is scoped to them
+    fn add_openai_scope_headers(&mut self) {
+        if let Some(organization) = self.llm_provider().organization.clone() {
+            self.set_http_request_header(OPENAI_ORGANIZATION_HEADER, Some(&organization));
+        }
+        if let Some(project) = self.llm_provider().project.clone() {
+            self.set_http_request_header(OPENAI_PROJECT_HEADER, Some(&project));
+        }
+    }
+
+    /// Sends the request id to providers that log the client's request id with their own
+    fn add_client_request_id_header(&mut self) {
+        let header = match self.llm_provider().provider_interface {
+            LlmProviderType::OpenAI => OPENAI_CLIENT_REQUEST_ID_HEADER,
+            LlmProviderType::AzureOpenAI => AZURE_CLIENT_REQUEST_ID_HEADER,
+            _ => return,
+        };
+        if let Some(request_id) = self.request_id.clone() {
+            self.set_http_request_header(header, Some(&request_id));
+        }
+    }
+
+    /// Headers the Codex backend expects from the Codex CLI: the ChatGPT account of the
+    /// token and the request id as the session
+    fn add_chatgpt_headers(&mut self) {
+        if self.llm_provider().provider_interface != LlmProviderType::ChatGPT {
+            return;
+        }
+        let account_id = self.llm_provider().account_id.clone().or_else(|| {
+            let authorization = self.get_http_request_header("Authorization")?;
+            chatgpt::account_id(authorization.strip_prefix("Bearer ")?)
+        });
+        match account_id {
+            Some(account_id) => {
+                self.set_http_request_header(CHATGPT_ACCOUNT_ID_HEADER, Some(&account_id))
+            }
+            None => warn!(
+                "[PLANO_REQ_ID:{}] CHATGPT_ACCOUNT_UNKNOWN: no account_id configured or in the token of provider {}",
+                self.request_identifier(),
+                self.llm_provider().name
+            ),
+        }
+        self.set_http_request_header("OpenAI-Beta", Some("responses=experimental"));
+        self.set_http_request_header("originator", Some("codex_cli_rs"));
+        if let Some(request_id) = self.request_id.clone() {
+            self.set_http_request_header(CHATGPT_SESSION_ID_HEADER, Some(&request_id));
+        }
+    }
+
+    /// Applies static headers and the provider's `headers.add` / `headers.remove` rules.
+    /// Runs after auth headers are set so that configured values take precedence.
+    fn apply_header_rules(&mut self) {
+        if let Some(static_headers) = self.llm_provider().static_headers.clone() {
+            for (name, value) in static_headers.iter() {
+                self.set_http_request_header(name, Some(value));
+            }
+        }
+
+        let rules = match self.llm_provider().headers.clone() {
+            Some(rules) => rules,
+            None => return,
+        };
+        for (name, value) in rules.add.unwrap_or_default().iter() {
+            self.set_http_request_header(name, Some(value));
+        }
+        for name in rules.remove.unwrap_or_default().iter() {
+            self.remove_http_request_header(name);
+        }
+    }
+
     fn delete_content_length_header(&mut self) {
         // Remove the Content-Length header because further body manipulations in the gateway logic will invalidate it.
         // Server's generally throw away requests whose body length do not match the Content-Length header.
@@ -196,26 +697,560 @@ impl StreamContext {
     }
 
     fn save_ratelimit_header(&mut self) {
-        self.ratelimit_selector = self
+        self.ratelimit_selectors = self
             .get_http_request_header(RATELIMIT_SELECTOR_HEADER_KEY)
             .and_then(|key| {
                 self.get_http_request_header(&key)
                     .map(|value| Header { key, value })
-            });
+            })
+            .into_iter()
+            .collect();
+
+        // set by brightstaff from the validated JWT of the request, not sent upstream
+        if let Some(claims) = self.get_http_request_header(ARCH_RATELIMIT_CLAIMS_HEADER) {
+            self.set_http_request_header(ARCH_RATELIMIT_CLAIMS_HEADER, None);
+            self.ratelimit_selectors
+                .extend(ratelimit::claim_selectors(&claims));
+        }
+    }
+
+    /// Error responses of llm calls are translated to the client api. Batch and audio
+    /// responses are forwarded as they are.
+    fn normalizes_upstream_errors(&self) -> bool {
+        self.client_api.is_some()
+            && self.request_body_sent_time.is_some()
+            && self.batch_api.is_none()
+            && self.audio_api.is_none()
+    }
+
+    /// Rewrites an upstream error into the error schema of the client api. The provider's
+    /// own error is kept in the upstream error header.
+    fn normalize_upstream_error(&mut self, status_code: StatusCode, body: &[u8], body_size: usize) {
+        if !self.normalizes_upstream_errors() {
+            return;
+        }
+        let client_api = self.client_api.clone().unwrap();
+        let upstream_error = if self.request_timed_out(status_code) {
+            warn!(
+                "[PLANO_REQ_ID:{}] REQUEST_TIMEOUT_EXCEEDED: provider={} timeout_ms={}",
+                self.request_identifier(),
+                self.llm_provider().name,
+                self.request_timeout_ms.unwrap()
+            );
+            self.metrics.request_timeouts.increment(1);
+            UpstreamError {
+                status: status_code.as_u16(),
+                message: format!(
+                    "request to {} timed out after {}ms",
+                    self.llm_provider().name,
+                    self.request_timeout_ms.unwrap()
+                ),
+                error_type: Some("timeout".to_string()),
+                code: Some("request_timeout".to_string()),
+                param: None,
+            }
+        } else {
+            UpstreamError::parse(status_code.as_u16(), body)
+        };
+        let header_value = format!("{}: {}", self.get_provider_id(), error_header_value(body));
+        self.set_http_response_header(ARCH_UPSTREAM_ERROR_HEADER, Some(&header_value));
+        self.set_http_response_header("content-type", Some("application/json"));
+        self.set_http_response_body(0, body_size, &upstream_error.to_client_body(&client_api));
+    }
+
+    fn start_in_flight(&mut self) {
+        let provider = self.metric_labels().provider;
+        self.metrics
+            .in_flight_requests
+            .with_provider(&provider)
+            .increment(1);
+        self.in_flight_provider = Some(provider);
+    }
+
+    fn finish_in_flight(&mut self) {
+        if let Some(provider) = self.in_flight_provider.take() {
+            self.metrics
+                .in_flight_requests
+                .with_provider(&provider)
+                .increment(-1);
+        }
+    }
+
+    /// Records the gap since the previous chunk of a streaming response, and counts
+    /// gaps over the stall threshold
+    fn record_stream_chunk_gap(&mut self, current_time: SystemTime) {
+        let previous = self.last_chunk_time.replace(current_time);
+        let Some(gap) = previous.and_then(|previous| current_time.duration_since(previous).ok())
+        else {
+            return;
+        };
+        let gap_ms = gap.as_millis() as u64;
+        let provider = self.metric_labels().provider;
+        self.metrics
+            .stream_chunk_gap
+            .with_provider(&provider)
+            .record(gap_ms);
+
+        let stall_threshold_ms = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.stream_stall_threshold_ms)
+            .unwrap_or(DEFAULT_STREAM_STALL_THRESHOLD_MS);
+        if gap_ms >= stall_threshold_ms {
+            warn!(
+                "[PLANO_REQ_ID:{}] STREAM_STALL: provider={} gap={}ms",
+                self.request_identifier(),
+                provider,
+                gap_ms
+            );
+            self.metrics
+                .stream_stalls
+                .with_provider(&provider)
+                .increment(1);
+        }
+    }
+
+    /// Lowers the output token limit of requests that wouldn't fit in the context window
+    /// of the model next to the prompt, when enabled in the overrides
+    fn adjust_max_tokens_to_context_window(
+        &mut self,
+        request: &mut ProviderRequestType,
+        model: &str,
+    ) {
+        let enabled = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.adjust_max_tokens_to_context_window)
+            .unwrap_or_default();
+        if !enabled {
+            return;
+        }
+        let Some(context_window) = self
+            .llm_provider()
+            .context_window
+            .or_else(|| known_context_window(model))
+        else {
+            debug!(
+                "[PLANO_REQ_ID:{}] MAX_TOKENS_NOT_ADJUSTED: unknown context window for model={}",
+                self.request_identifier(),
+                model
+            );
+            return;
+        };
+        let prompt_tokens = self.input_tokens.unwrap_or_default();
+        if let Some(adjustment) =
+            request.clamp_max_tokens_to_context_window(context_window, prompt_tokens)
+        {
+            info!(
+                "[PLANO_REQ_ID:{}] MAX_TOKENS_ADJUSTED: model={} context_window={} prompt_tokens={} requested={} adjusted={}",
+                self.request_identifier(),
+                model,
+                context_window,
+                prompt_tokens,
+                adjustment.requested,
+                adjustment.adjusted
+            );
+            self.max_tokens_adjustment = Some(adjustment);
+        }
+    }
+
+    /// Whether requests may skip parsing into the api types: the provider speaks the
+    /// client's api and nothing configured changes the body
+    fn request_fast_path_allowed(&self) -> bool {
+        let overrides = (*self.overrides).as_ref();
+        let enabled = overrides
+            .and_then(|overrides| overrides.request_fast_path)
+            .unwrap_or(true);
+        let adjusts_max_tokens = overrides
+            .and_then(|overrides| overrides.adjust_max_tokens_to_context_window)
+            .unwrap_or_default();
+        let same_api = match (self.client_api.as_ref(), self.resolved_api.as_ref()) {
+            (Some(client_api), Some(upstream_api)) => is_same_api(client_api, upstream_api),
+            _ => false,
+        };
+        // providers whose requests get fixups of their own
+        let provider_fixups = matches!(
+            self.get_provider_id(),
+            ProviderId::Mistral | ProviderId::ChatGPT | ProviderId::VertexAnthropic
+        );
+        enabled
+            && same_api
+            && !provider_fixups
+            && !adjusts_max_tokens
+            && self.middlewares.is_empty()
+            && self.request_rules.is_empty()
+            && self.user_hashing().is_none()
+            && self.llm_provider().system_prompt_policy.is_none()
+            && self.ratelimit_selectors.is_empty()
+    }
+
+    /// The upstream body of a request sent without parsing it into the api types. Only
+    /// the model is set, the other fields are copied as they are. None when the request
+    /// has to take the full path.
+    fn request_fast_path(&mut self, body: &[u8]) -> Option<Vec<u8>> {
+        if !self.request_fast_path_allowed() {
+            return None;
+        }
+        let mut request = RawObject::parse(body)?;
+        // sampling params the provider may not support and end users, which are rate
+        // limited, need the parsed request
+        let has_end_user = request.contains("user")
+            || request
+                .get::<serde_json::Value>("metadata")
+                .is_some_and(|metadata| metadata.get("user_id").is_some());
+        if has_end_user
+            || FAST_PATH_EXCLUDED_FIELDS
+                .iter()
+                .any(|field| request.contains(field))
+        {
+            return None;
+        }
+
+        let model_name = self.llm_provider().model.as_deref()?;
+        let resolved_model = self
+            .llm_provider()
+            .upstream_model_id(model_name)
+            .to_string();
+        let model_requested: String = request.get("model").unwrap_or_default();
+        let upstream_body = if model_requested == resolved_model {
+            body.to_vec()
+        } else {
+            request.set("model", &resolved_model).ok()?;
+            request.to_vec()
+        };
+
+        if !self.streaming_response {
+            self.streaming_response = request.get("stream").unwrap_or(false);
+        }
+        info!(
+            "[PLANO_REQ_ID:{}] MODEL_RESOLUTION: req_model='{}' -> resolved_model='{}' provider='{}' streaming={} fast_path=true",
+            self.request_identifier(),
+            model_requested,
+            resolved_model,
+            self.llm_provider().name,
+            self.streaming_response
+        );
+        self.resolved_model = Some(resolved_model);
+        Some(upstream_body)
+    }
+
+    /// Whether the rest of the request body is streamed upstream as it arrives instead of
+    /// being buffered. Decided once `min_body_bytes` arrived: the request has to take the
+    /// fast path, and the fields complete in its first bytes have to name the upstream
+    /// model and hold nothing the full path would change. The keys of the rest are
+    /// checked as it streams, see reject_late_request_field.
+    fn stream_request_body(&mut self, body_size: usize) -> bool {
+        let Some(config) = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.request_body_streaming.as_ref())
+        else {
+            return false;
+        };
+        let min_body_bytes = config
+            .min_body_bytes
+            .unwrap_or(DEFAULT_STREAMING_MIN_BODY_BYTES);
+        if body_size < min_body_bytes {
+            return false;
+        }
+        // a body that has to be buffered isn't looked at again
+        self.streams_request_body = Some(false);
+        let strips_fields = self
+            .llm_provider()
+            .strip_fields
+            .as_ref()
+            .is_some_and(|fields| !fields.is_empty());
+        if self.request_encoding.is_some() || strips_fields || !self.request_fast_path_allowed() {
+            return false;
+        }
+        let Some(prefix) = self.get_http_request_body(0, body_size) else {
+            return false;
+        };
+        let request = RawObject::parse_prefix(&prefix);
+        let mut keys = TopLevelKeys::default();
+        // the field cut off at the end of the prefix counts too
+        if keys
+            .feed(&prefix)
+            .iter()
+            .any(|field| excludes_body_streaming(field))
+        {
+            return false;
+        }
+        let Some(model_name) = self.llm_provider().model.as_deref() else {
+            return false;
+        };
+        let resolved_model = self
+            .llm_provider()
+            .upstream_model_id(model_name)
+            .to_string();
+        // the model can't be rewritten in a body that is already on its way
+        if request.get::<&str>("model") != Some(resolved_model.as_str()) {
+            return false;
+        }
+
+        if !self.streaming_response {
+            self.streaming_response = request.get("stream").unwrap_or(false);
+        }
+        info!(
+            "[PLANO_REQ_ID:{}] REQUEST_BODY_STREAMING: model='{}' provider='{}' buffered_bytes={}",
+            self.request_identifier(),
+            resolved_model,
+            self.llm_provider().name,
+            body_size
+        );
+        self.resolved_model = Some(resolved_model);
+        self.streams_request_body = Some(true);
+        self.streamed_request_bytes = body_size;
+        self.streamed_request_keys = keys;
+        true
+    }
+
+    /// Fails a streamed request whose chunk holds a field the streaming decision relied on
+    /// not being there. The chunk isn't forwarded and the upstream request is reset.
+    fn reject_late_request_field(&mut self, body_size: usize) -> bool {
+        let Some(chunk) = self.get_http_request_body(0, body_size) else {
+            return false;
+        };
+        let Some(field) = self
+            .streamed_request_keys
+            .feed(&chunk)
+            .into_iter()
+            .find(|field| excludes_body_streaming(field) || field == "model" || field == "stream")
+        else {
+            return false;
+        };
+        warn!(
+            "[PLANO_REQ_ID:{}] REQUEST_BODY_STREAMING: field '{}' after {} streamed bytes",
+            self.request_identifier(),
+            field,
+            self.streamed_request_bytes
+        );
+        self.send_server_error(
+            ServerError::BadRequest {
+                why: format!(
+                    "Request field '{}' arrived after the body started streaming upstream, send it ahead of the messages",
+                    field
+                ),
+            },
+            Some(StatusCode::BAD_REQUEST),
+        );
+        true
+    }
+
+    /// Repairs a non streaming response that doesn't parse, unless disabled in the
+    /// overrides
+    fn repair_response(
+        &self,
+        body: &[u8],
+        client_api: &SupportedAPIsFromClient,
+        provider_id: &ProviderId,
+    ) -> Option<ProviderResponseType> {
+        let enabled = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.malformed_responses.as_ref())
+            .and_then(|policy| policy.repair)
+            .unwrap_or(true);
+        if !enabled {
+            return None;
+        }
+        let repaired = repair_response_body(body, client_api, provider_id)?;
+        let response =
+            ProviderResponseType::try_from((&repaired.body[..], client_api, provider_id)).ok()?;
+        info!(
+            "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_REPAIRED: {}",
+            self.request_identifier(),
+            repaired
+                .actions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        self.metrics
+            .repaired_responses
+            .with_labels(&self.metric_labels())
+            .increment(1);
+        Some(response)
+    }
+
+    fn middleware_context(&self) -> MiddlewareContext<'_> {
+        MiddlewareContext {
+            request_id: self.request_id.as_deref().unwrap_or_default(),
+            model: self.resolved_model.as_deref().unwrap_or_default(),
+            provider: self.llm_provider(),
+            request_headers: &self.request_headers,
+        }
+    }
+
+    fn run_request_middlewares(
+        &self,
+        request: &mut ProviderRequestType,
+    ) -> Result<(), MiddlewareError> {
+        let context = self.middleware_context();
+        for middleware in self.middlewares.iter() {
+            debug!(
+                "[PLANO_REQ_ID:{}] MIDDLEWARE_REQUEST: {}",
+                self.request_identifier(),
+                middleware.name()
+            );
+            middleware.on_request(&context, request)?;
+        }
+        Ok(())
+    }
+
+    fn run_response_middlewares(
+        &self,
+        response: &mut ProviderResponseType,
+    ) -> Result<(), MiddlewareError> {
+        let context = self.middleware_context();
+        for middleware in self.middlewares.iter() {
+            debug!(
+                "[PLANO_REQ_ID:{}] MIDDLEWARE_RESPONSE: {}",
+                self.request_identifier(),
+                middleware.name()
+            );
+            middleware.on_response(&context, response)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the request rules matching the request to its body in the client's api.
+    /// Dry run rules only log what they would change.
+    fn apply_request_rules(
+        &self,
+        request: &mut ProviderRequestType,
+        requested_model: &str,
+    ) -> Result<(), String> {
+        let Some(api) = self.client_api.as_ref() else {
+            return Ok(());
+        };
+        let path = self
+            .request_headers
+            .iter()
+            .find(|(name, _)| name == ":path")
+            .and_then(|(_, path)| path.split('?').next())
+            .unwrap_or_default();
+        let model = request.model().to_string();
+        let context = RuleContext {
+            path,
+            requested_model,
+            model: &model,
+            provider: &self.llm_provider().name,
+            headers: &self.request_headers,
+        };
+        let mut rules = self
+            .request_rules
+            .iter()
+            .filter(|rule| request_rules::matches(&rule.matches, &context))
+            .peekable();
+        if rules.peek().is_none() {
+            return Ok(());
+        }
+
+        let body = request.to_bytes().map_err(|err| err.to_string())?;
+        let mut body: serde_json::Value =
+            serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        let mut changed = false;
+        for rule in rules {
+            if rule.dry_run.unwrap_or(false) {
+                let changes = request_rules::apply_actions(&rule.actions, api, &mut body.clone());
+                if !changes.is_empty() {
+                    info!(
+                        "[PLANO_REQ_ID:{}] REQUEST_RULE_DRY_RUN: rule={} would apply {:?}",
+                        self.request_identifier(),
+                        rule.name,
+                        changes
+                    );
+                }
+                continue;
+            }
+            let changes = request_rules::apply_actions(&rule.actions, api, &mut body);
+            if !changes.is_empty() {
+                info!(
+                    "[PLANO_REQ_ID:{}] REQUEST_RULE_APPLIED: rule={} changes={:?}",
+                    self.request_identifier(),
+                    rule.name,
+                    changes
+                );
+                changed = true;
+            }
+        }
+        if changed {
+            let body = serde_json::to_vec(&body).map_err(|err| err.to_string())?;
+            *request =
+                ProviderRequestType::try_from((&body[..], api)).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn send_middleware_error(&self, err: MiddlewareError) {
+        warn!(
+            "[PLANO_REQ_ID:{}] MIDDLEWARE_REJECTED: {}",
+            self.request_identifier(),
+            err
+        );
+        let status = match &err {
+            MiddlewareError::Rejected { status, .. } => *status,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        self.send_server_error(ServerError::LogicError(err.to_string()), Some(status));
+    }
+
+    fn metric_labels(&self) -> MetricLabels {
+        let client_api = match (&self.client_api, &self.audio_api) {
+            (Some(SupportedAPIsFromClient::OpenAIChatCompletions(_)), _) => "chat_completions",
+            (Some(SupportedAPIsFromClient::AnthropicMessagesAPI(_)), _) => "messages",
+            (Some(SupportedAPIsFromClient::OpenAIResponsesAPI(_)), _) => "responses",
+            (None, Some(_)) => "audio",
+            (None, None) => "unknown",
+        };
+        MetricLabels {
+            provider: self
+                .llm_provider
+                .as_ref()
+                .map(|provider| provider.provider_interface.to_string())
+                .unwrap_or_default(),
+            model: self
+                .resolved_model
+                .clone()
+                .or_else(|| self.llm_provider.as_ref().and_then(|p| p.model.clone()))
+                .unwrap_or_default(),
+            client_api: client_api.to_string(),
+            status_class: self
+                .upstream_status_code
+                .map(|status| format!("{}xx", status.as_u16() / 100))
+                .unwrap_or_default(),
+            split: self.traffic_split.clone(),
+        }
     }
 
     fn send_server_error(&self, error: ServerError, override_status_code: Option<StatusCode>) {
-        warn!("server error occurred: {}", error);
-        self.send_http_response(
+        warn!(
+            "[PLANO_REQ_ID:{}] server error occurred: {}",
+            self.request_identifier(),
+            error
+        );
+        self.send_local_response(
             override_status_code
                 .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
                 .as_u16()
                 .into(),
             vec![],
-            Some(format!("{error}").as_bytes()),
+            Some(format!("{error}, request_id={}", self.request_identifier()).as_bytes()),
         );
     }
 
+    /// Responds to the client from the filter, with the request id header upstream
+    /// responses carry as well
+    fn send_local_response(
+        &self,
+        status_code: u32,
+        headers: Vec<(&str, &str)>,
+        body: Option<&[u8]>,
+    ) {
+        let request_id = self.request_identifier();
+        let mut headers: Vec<(&str, &str)> = headers;
+        headers.push((ARCH_REQUEST_ID_HEADER, &request_id));
+        self.send_http_response(status_code, headers, body);
+    }
+
     fn enforce_ratelimits(
         &mut self,
         model: &str,
@@ -231,13 +1266,28 @@ impl StreamContext {
             token_count
         );
 
-        // Record the token count to metrics.
-        self.metrics
-            .input_sequence_length
-            .record(token_count as u64);
+        // Recorded with the other metrics once the response is complete
+        self.input_tokens = Some(token_count as u64);
 
         // Check if rate limiting needs to be applied.
-        if let Some(selector) = self.ratelimit_selector.take() {
+        let mut selectors = std::mem::take(&mut self.ratelimit_selectors);
+        // Limits selecting on the end user see the original value, not its hash
+        if let Some(user) = &self.end_user {
+            selectors.push(Header {
+                key: USER_RATELIMIT_SELECTOR.to_string(),
+                value: user.clone(),
+            });
+        }
+        let Some(tokens) = NonZero::new(token_count as u32).filter(|_| !selectors.is_empty())
+        else {
+            debug!(
+                "[PLANO_REQ_ID:{}] RATELIMIT_SKIP: model='{}' (no selector)",
+                self.request_identifier(),
+                model
+            );
+            return Ok(());
+        };
+        for selector in selectors {
             info!(
                 "[PLANO_REQ_ID:{}] RATELIMIT_CHECK: model='{}' selector='{}:{}'",
                 self.request_identifier(),
@@ -245,17 +1295,7 @@ impl StreamContext {
                 selector.key,
                 selector.value
             );
-            ratelimit::ratelimits(None).read().unwrap().check_limit(
-                model.to_owned(),
-                selector,
-                NonZero::new(token_count as u32).unwrap(),
-            )?;
-        } else {
-            debug!(
-                "[PLANO_REQ_ID:{}] RATELIMIT_SKIP: model='{}' (no selector)",
-                self.request_identifier(),
-                model
-            );
+            ratelimit::check_limit(model.to_owned(), selector, tokens)?;
         }
 
         Ok(())
@@ -276,7 +1316,10 @@ impl StreamContext {
                         duration_ms
                     );
                     self.ttft_duration = Some(duration);
-                    self.metrics.time_to_first_token.record(duration_ms as u64);
+                    self.metrics
+                        .time_to_first_token
+                        .with_labels(&self.metric_labels())
+                        .record(duration_ms as u64);
                 }
                 Err(e) => {
                     warn!(
@@ -289,6 +1332,23 @@ impl StreamContext {
         }
     }
     fn handle_end_of_request_metrics_and_traces(&mut self, current_time: SystemTime) {
+        let labels = self.metric_labels();
+        self.metrics.requests.with_labels(&labels).increment(1);
+        if let Some(input_tokens) = self.input_tokens.take() {
+            self.metrics
+                .input_sequence_length
+                .with_labels(&labels)
+                .record(input_tokens);
+            self.metrics
+                .input_tokens
+                .with_labels(&labels)
+                .increment(input_tokens as i64);
+        }
+        self.metrics
+            .output_tokens
+            .with_labels(&labels)
+            .increment(self.response_tokens as i64);
+        self.finish_in_flight();
         // All streaming responses end with bytes=0 and end_stream=true
         // Record the latency for the request
         match current_time.duration_since(self.start_time) {
@@ -302,33 +1362,161 @@ impl StreamContext {
                     self.response_tokens
                 );
                 // Record the latency to the latency histogram
-                self.metrics.request_latency.record(duration_ms as u64);
+                self.metrics
+                    .request_latency
+                    .with_labels(&labels)
+                    .record(duration_ms as u64);
 
                 if self.response_tokens > 0 {
                     // Compute the time per output token
                     let tpot = duration_ms as u64 / self.response_tokens as u64;
 
-                    // Record the time per output token
-                    self.metrics.time_per_output_token.record(tpot);
+                    // Record the time per output token
+                    self.metrics
+                        .time_per_output_token
+                        .with_labels(&labels)
+                        .record(tpot);
+
+                    info!(
+                        "[PLANO_REQ_ID:{}] TOKEN_THROUGHPUT: time_per_token={}ms tokens_per_second={}",
+                        self.request_identifier(),
+                        tpot,
+                        1000 / tpot
+                    );
+                    // Record the tokens per second
+                    self.metrics
+                        .tokens_per_second
+                        .with_labels(&labels)
+                        .record(1000 / tpot);
+                }
+            }
+            Err(e) => {
+                warn!("SystemTime error: {:?}", e);
+            }
+        }
+        // Record the output sequence length
+        self.metrics
+            .output_sequence_length
+            .with_labels(&labels)
+            .record(self.response_tokens as u64);
+
+        self.record_billing_scope_usage();
+    }
+
+    fn record_billing_scope_usage(&self) {
+        let provider = match self.llm_provider.as_ref() {
+            Some(provider) => provider,
+            None => return,
+        };
+        let organization = provider.organization.as_deref();
+        let project = provider.project.as_deref();
+        let metrics = &self.metrics;
+        if let Some(requests) = metrics
+            .billing_scope_requests
+            .with_billing_scope(organization, project)
+        {
+            requests.increment(1);
+        }
+        if let Some(output_tokens) = metrics
+            .billing_scope_output_tokens
+            .with_billing_scope(organization, project)
+        {
+            output_tokens.increment(self.response_tokens as i64);
+        }
+    }
+
+    /// Compressed upstream responses are decoded before they are parsed. Successful llm
+    /// responses are compressed again for the client when compress_responses is on and
+    /// its Accept-Encoding allows it.
+    fn negotiate_response_encoding(&mut self) {
+        if let Some(value) = self.get_http_response_header("content-encoding") {
+            self.remove_http_response_header("content-encoding");
+            if !ContentEncoding::is_identity(&value) {
+                match ContentEncoding::from_header(&value) {
+                    Some(encoding) => self.upstream_decoder = Some(StreamDecoder::new(encoding)),
+                    None => warn!(
+                        "[PLANO_REQ_ID:{}] UNSUPPORTED_RESPONSE_ENCODING: {}",
+                        self.request_identifier(),
+                        value
+                    ),
+                }
+            }
+        }
+
+        let is_success = self
+            .upstream_status_code
+            .is_some_and(|status| status.is_success());
+        if !is_success
+            || self.client_api.is_none()
+            || self.request_body_sent_time.is_none()
+            || !self.compress_responses()
+        {
+            return;
+        }
+        if let Some(encoding) = self
+            .client_accept_encoding
+            .as_deref()
+            .and_then(ContentEncoding::negotiate)
+        {
+            self.set_http_response_header("content-encoding", Some(encoding.as_str()));
+            self.client_encoder = Some(StreamEncoder::new(encoding));
+        }
+    }
+
+    fn decode_upstream_body(&mut self, body: Vec<u8>) -> Vec<u8> {
+        let Some(decoder) = self.upstream_decoder.as_mut() else {
+            return body;
+        };
+        match decoder.decode(&body) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                let encoding = decoder.encoding();
+                warn!(
+                    "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_DECODE_ERROR: encoding={} {}",
+                    self.request_identifier(),
+                    encoding.as_str(),
+                    err
+                );
+                self.upstream_decoder = None;
+                body
+            }
+        }
+    }
+
+    /// Puts the raw upstream chunk in front of its translation as an SSE comment for tapped
+    /// streams. The translation is made of whole events, so the comment starts a line.
+    fn tap_upstream_chunk(&self, upstream: &[u8], translated: Vec<u8>) -> Vec<u8> {
+        if !self.sse_tap {
+            return translated;
+        }
+        let raw = serde_json::to_string(&String::from_utf8_lossy(upstream)).unwrap_or_default();
+        let mut tapped = format!("{}{}\n", SSE_TAP_UPSTREAM_PREFIX, raw).into_bytes();
+        tapped.extend_from_slice(&translated);
+        tapped
+    }
+
+    /// The encoder is finished with the last chunk of the response
+    fn encode_for_client(&mut self, body: Vec<u8>, end_of_stream: bool) -> Vec<u8> {
+        if end_of_stream {
+            return match self.client_encoder.take() {
+                Some(encoder) => encoder.finish(&body),
+                None => body,
+            };
+        }
+        match self.client_encoder.as_mut() {
+            Some(encoder) => encoder.encode(&body),
+            None => body,
+        }
+    }
 
-                    info!(
-                        "[PLANO_REQ_ID:{}] TOKEN_THROUGHPUT: time_per_token={}ms tokens_per_second={}",
-                        self.request_identifier(),
-                        tpot,
-                        1000 / tpot
-                    );
-                    // Record the tokens per second
-                    self.metrics.tokens_per_second.record(1000 / tpot);
-                }
-            }
-            Err(e) => {
-                warn!("SystemTime error: {:?}", e);
-            }
+    /// Bodies that couldn't be processed are forwarded as received, decoded from the
+    /// upstream encoding and in the client's encoding
+    fn forward_unprocessed_body(&mut self, body: Vec<u8>, body_size: usize, end_of_stream: bool) {
+        if self.upstream_decoder.is_none() && self.client_encoder.is_none() {
+            return;
         }
-        // Record the output sequence length
-        self.metrics
-            .output_sequence_length
-            .record(self.response_tokens as u64);
+        let body = self.encode_for_client(body, end_of_stream);
+        self.set_http_response_body(0, body_size, &body);
     }
 
     fn read_raw_response_body(&mut self, body_size: usize) -> Result<Vec<u8>, Action> {
@@ -369,6 +1557,9 @@ impl StreamContext {
                 self.request_identifier(),
                 body_size
             );
+            if body_size > RESPONSE_BODY_READ_CHUNK_BYTES {
+                return self.read_response_body_in_chunks(body_size);
+            }
             match self.get_http_response_body(0, body_size) {
                 Some(body) => Ok(body),
                 None => {
@@ -379,6 +1570,108 @@ impl StreamContext {
         }
     }
 
+    /// Reads a large non-streaming body from the host in slices of at most
+    /// `RESPONSE_BODY_READ_CHUNK_BYTES`, so no single host call copies more than that.
+    /// This is for bodies that are parsed whole, e.g. to translate them, so they're still
+    /// held whole up to `max_response_body_bytes`. The others are passed on as they arrive
+    /// by `forward_large_response`.
+    fn read_response_body_in_chunks(&self, body_size: usize) -> Result<Vec<u8>, Action> {
+        let mut body = Vec::with_capacity(body_size);
+        while body.len() < body_size {
+            let chunk_size = RESPONSE_BODY_READ_CHUNK_BYTES.min(body_size - body.len());
+            match self.get_http_response_body(body.len(), chunk_size) {
+                Some(chunk) if !chunk.is_empty() => body.extend_from_slice(&chunk),
+                _ => {
+                    warn!(
+                        "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_ERROR: short read at offset={} body_size={}",
+                        self.request_identifier(),
+                        body.len(),
+                        body_size
+                    );
+                    return Err(Action::Continue);
+                }
+            }
+        }
+        debug!(
+            "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_CHUNKED_READ: body_size={} chunk_size={}",
+            self.request_identifier(),
+            body_size,
+            RESPONSE_BODY_READ_CHUNK_BYTES
+        );
+        Ok(body)
+    }
+
+    /// Enforces `max_response_body_bytes`. Oversized non-streaming responses are rejected,
+    /// streaming responses are ended with an error event once the limit is crossed.
+    /// Returns true when the current chunk must not be forwarded.
+    fn enforce_response_body_limit(&mut self, body_size: usize, end_of_stream: bool) -> bool {
+        let max_bytes = match self.max_response_body_bytes() {
+            Some(max_bytes) => max_bytes,
+            None => return false,
+        };
+
+        if self.streaming_response {
+            self.response_body_bytes += body_size;
+            if self.response_body_bytes <= max_bytes {
+                return false;
+            }
+            let mut body = Vec::new();
+            if !self.response_truncated {
+                warn!(
+                    "[PLANO_REQ_ID:{}] RESPONSE_BODY_TRUNCATED: streamed={} limit={}",
+                    self.request_identifier(),
+                    self.response_body_bytes,
+                    max_bytes
+                );
+                self.metrics.truncated_responses.increment(1);
+                self.response_truncated = true;
+                body = self.truncated_stream_error_event(max_bytes);
+            }
+            let body = self.encode_for_client(body, end_of_stream);
+            self.set_http_response_body(0, body_size, &body);
+            return true;
+        }
+
+        // non streaming bodies are buffered, so body_size is the total size seen so far
+        if body_size <= max_bytes {
+            return false;
+        }
+        warn!(
+            "[PLANO_REQ_ID:{}] RESPONSE_BODY_TOO_LARGE: size={} limit={}",
+            self.request_identifier(),
+            body_size,
+            max_bytes
+        );
+        self.metrics.oversized_responses.increment(1);
+        self.send_server_error(
+            ServerError::LogicError(format!(
+                "Upstream response body of {} bytes exceeds the limit of {} bytes",
+                body_size, max_bytes
+            )),
+            Some(StatusCode::BAD_GATEWAY),
+        );
+        true
+    }
+
+    /// Error event in the client's api that ends a stream cut at the size limit, so the
+    /// client sees why it stopped instead of waiting for the rest
+    fn truncated_stream_error_event(&self, max_bytes: usize) -> Vec<u8> {
+        let Some(client_api) = self.client_api.as_ref() else {
+            return Vec::new();
+        };
+        let error = UpstreamError {
+            status: StatusCode::BAD_GATEWAY.as_u16(),
+            message: format!(
+                "Upstream response exceeds the limit of {} bytes and was truncated",
+                max_bytes
+            ),
+            error_type: None,
+            code: Some("response_too_large".to_string()),
+            param: None,
+        };
+        error.to_client_sse_event(client_api)
+    }
+
     fn handle_streaming_response(
         &mut self,
         body: &[u8],
@@ -456,79 +1749,138 @@ impl StreamContext {
                     }
                 };
 
-                // Process each successfully transformed SSE event
-                for transformed_event in transformed_events {
-                    // Extract ProviderStreamResponse for processing (token counting, etc.)
-                    if !transformed_event.is_done() && !transformed_event.is_event_only() {
-                        match transformed_event.provider_response() {
-                            Ok(provider_response) => {
-                                self.record_ttft_if_needed();
+                self.forward_transformed_events(transformed_events)
+            }
+            None => {
+                warn!("Missing client_api for non-streaming response");
+                Err(Action::Continue)
+            }
+        }
+    }
 
-                                if provider_response.is_final() {
-                                    debug!(
-                                        "[PLANO_REQ_ID:{}] STREAMING_FINAL_CHUNK: total_tokens={}",
-                                        self.request_identifier(),
-                                        self.response_tokens
-                                    );
-                                }
+    /// Counts tokens for the transformed events and returns the client bytes accumulated
+    /// in the SSE buffer (which may inject lifecycle events).
+    fn forward_transformed_events(
+        &mut self,
+        transformed_events: Vec<SseEvent>,
+    ) -> Result<Vec<u8>, Action> {
+        // Process each successfully transformed SSE event
+        for transformed_event in transformed_events {
+            if let Some(data) = transformed_event.data.as_deref() {
+                self.record_groq_queue_time(data.as_bytes(), true);
+            }
+            // Extract ProviderStreamResponse for processing (token counting, etc.)
+            if !transformed_event.is_done() && !transformed_event.is_event_only() {
+                match transformed_event.provider_response() {
+                    Ok(provider_response) => {
+                        self.record_ttft_if_needed();
 
-                                if let Some(content) = provider_response.content_delta() {
-                                    let estimated_tokens = content.len() / 4;
-                                    self.response_tokens += estimated_tokens.max(1);
-                                    debug!(
-                                        "[PLANO_REQ_ID:{}] STREAMING_TOKEN_UPDATE: delta_chars={} estimated_tokens={} total_tokens={}",
-                                        self.request_identifier(),
-                                        content.len(),
-                                        estimated_tokens.max(1),
-                                        self.response_tokens
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "[PLANO_REQ_ID:{}] STREAMING_CHUNK_ERROR: {}",
-                                    self.request_identifier(),
-                                    e
-                                );
-                                return Err(Action::Continue);
-                            }
+                        if provider_response.is_final() {
+                            debug!(
+                                "[PLANO_REQ_ID:{}] STREAMING_FINAL_CHUNK: total_tokens={}",
+                                self.request_identifier(),
+                                self.response_tokens
+                            );
                         }
-                    }
-
-                    // Add transformed event to buffer (buffer may inject lifecycle events)
-                    if let Some(buffer) = self.sse_buffer.as_mut() {
-                        buffer.add_transformed_event(transformed_event);
-                    }
-                }
 
-                // Get accumulated bytes from buffer and return
-                match self.sse_buffer.as_mut() {
-                    Some(buffer) => {
-                        let bytes = buffer.to_bytes();
-                        if !bytes.is_empty() {
-                            let content = String::from_utf8_lossy(&bytes);
+                        if let Some(content) = provider_response.content_delta() {
+                            let estimated_tokens = content.len() / 4;
+                            self.response_tokens += estimated_tokens.max(1);
                             debug!(
-                                "[PLANO_REQ_ID:{}] UPSTREAM_TRANSFORMED_CLIENT_RESPONSE: size={} content={}",
+                                "[PLANO_REQ_ID:{}] STREAMING_TOKEN_UPDATE: delta_chars={} estimated_tokens={} total_tokens={}",
                                 self.request_identifier(),
-                                bytes.len(),
-                                content
+                                content.len(),
+                                estimated_tokens.max(1),
+                                self.response_tokens
                             );
                         }
-                        Ok(bytes)
                     }
-                    None => {
-                        warn!("SSE buffer unexpectedly missing after initialization");
-                        Err(Action::Continue)
+                    Err(e) => {
+                        warn!(
+                            "[PLANO_REQ_ID:{}] STREAMING_CHUNK_ERROR: {}",
+                            self.request_identifier(),
+                            e
+                        );
+                        return Err(Action::Continue);
                     }
                 }
             }
+
+            // Add transformed event to buffer (buffer may inject lifecycle events)
+            if let Some(buffer) = self.sse_buffer.as_mut() {
+                buffer.add_transformed_event(transformed_event);
+            }
+        }
+
+        // Get accumulated bytes from buffer and return
+        match self.sse_buffer.as_mut() {
+            Some(buffer) => {
+                let bytes = buffer.to_bytes();
+                if !bytes.is_empty() {
+                    let content = String::from_utf8_lossy(&bytes);
+                    debug!(
+                        "[PLANO_REQ_ID:{}] UPSTREAM_TRANSFORMED_CLIENT_RESPONSE: size={} content={}",
+                        self.request_identifier(),
+                        bytes.len(),
+                        content
+                    );
+                }
+                Ok(bytes)
+            }
             None => {
-                warn!("Missing client_api for non-streaming response");
+                warn!("SSE buffer unexpectedly missing after initialization");
                 Err(Action::Continue)
             }
         }
     }
 
+    /// Records Groq's reported queue time from the raw upstream body (or SSE data line)
+    /// before it is normalized into the client's API shape.
+    fn record_groq_queue_time(&self, raw: &[u8], streaming: bool) {
+        if self.get_provider_id() != ProviderId::Groq {
+            return;
+        }
+        let queue_time = if streaming {
+            serde_json::from_slice::<ChatCompletionsStreamResponse>(raw)
+                .ok()
+                .and_then(|chunk| chunk.queue_time())
+        } else {
+            serde_json::from_slice::<ChatCompletionsResponse>(raw)
+                .ok()
+                .and_then(|response| response.queue_time())
+        };
+        if let Some(queue_time) = queue_time {
+            let queue_time_ms = (queue_time * 1000.0) as u64;
+            debug!(
+                "[PLANO_REQ_ID:{}] UPSTREAM_QUEUE_TIME: provider=groq queue_time={}ms",
+                self.request_identifier(),
+                queue_time_ms
+            );
+            self.metrics.upstream_queue_time.record(queue_time_ms);
+        }
+    }
+
+    /// Drains any event left in the SSE decoder once the upstream stream has ended.
+    fn flush_sse_stream(&mut self) -> Option<Vec<u8>> {
+        let upstream_api = self.resolved_api.clone()?;
+        let client_api = self.client_api.clone()?;
+        let processor = self.sse_chunk_processor.as_mut()?;
+        if !processor.has_buffered_data() {
+            return None;
+        }
+        match processor.flush(&client_api, &upstream_api) {
+            Ok(events) => self.forward_transformed_events(events).ok(),
+            Err(e) => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] SSE_FLUSH_ERROR: {}",
+                    self.request_identifier(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
     fn handle_bedrock_binary_stream(
         &mut self,
         body: &[u8],
@@ -620,29 +1972,328 @@ impl StreamContext {
             }
         }
 
-        // Get accumulated bytes from buffer and return
-        match self.sse_buffer.as_mut() {
-            Some(buffer) => {
-                let bytes = buffer.to_bytes();
-                if !bytes.is_empty() {
-                    let content = String::from_utf8_lossy(&bytes);
-                    debug!(
-                        "[PLANO_REQ_ID:{}] UPSTREAM_TRANSFORMED_CLIENT_RESPONSE: size={} content={}",
-                        self.request_identifier(),
-                        bytes.len(),
-                        content
-                    );
-                }
-                Ok(bytes)
-            }
-            None => {
-                warn!(
-                    "[PLANO_REQ_ID:{}] BEDROCK_BUFFER_MISSING",
-                    self.request_identifier()
-                );
-                Err(Action::Continue)
+        // Get accumulated bytes from buffer and return
+        match self.sse_buffer.as_mut() {
+            Some(buffer) => {
+                let bytes = buffer.to_bytes();
+                if !bytes.is_empty() {
+                    let content = String::from_utf8_lossy(&bytes);
+                    debug!(
+                        "[PLANO_REQ_ID:{}] UPSTREAM_TRANSFORMED_CLIENT_RESPONSE: size={} content={}",
+                        self.request_identifier(),
+                        bytes.len(),
+                        content
+                    );
+                }
+                Ok(bytes)
+            }
+            None => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] BEDROCK_BUFFER_MISSING",
+                    self.request_identifier()
+                );
+                Err(Action::Continue)
+            }
+        }
+    }
+
+    /// File downloads are forwarded as-is. Batch objects are small, they are buffered so
+    /// that batch creation is accounted for and status changes show up in the logs.
+    fn handle_batch_response_body(
+        &mut self,
+        batch_api: BatchApi,
+        body_size: usize,
+        end_of_stream: bool,
+    ) -> Action {
+        let is_success = self
+            .upstream_status_code
+            .map(|status| status.is_success())
+            .unwrap_or(false);
+        if batch_api == BatchApi::Files || !is_success {
+            return Action::Continue;
+        }
+        if !end_of_stream {
+            return Action::Pause;
+        }
+
+        let Some(body) = self.get_http_response_body(0, body_size) else {
+            return Action::Continue;
+        };
+        // a created batch is validating (openai) or in progress (anthropic)
+        let created = if let Some(batch) = BatchObject::from_response(&body) {
+            info!(
+                "[PLANO_REQ_ID:{}] BATCH_STATUS: provider={} batch_id={} status={} request_counts={:?}",
+                self.request_identifier(),
+                self.llm_provider().name,
+                batch.id,
+                batch.status,
+                batch.request_counts
+            );
+            batch.status == "validating"
+        } else if let Some(batch) = MessageBatch::from_response(&body) {
+            info!(
+                "[PLANO_REQ_ID:{}] BATCH_STATUS: provider={} batch_id={} status={:?} request_counts={:?}",
+                self.request_identifier(),
+                self.llm_provider().name,
+                batch.id,
+                batch.processing_status,
+                batch.request_counts
+            );
+            batch.processing_status == MessageBatchStatus::InProgress
+        } else {
+            return Action::Continue;
+        };
+
+        if self.http_method.as_deref() == Some("POST") && created {
+            self.metrics.batches_created.increment(1);
+            let provider = self.llm_provider();
+            if let Some(batches) = self
+                .metrics
+                .billing_scope_batches_created
+                .with_billing_scope(
+                    provider.organization.as_deref(),
+                    provider.project.as_deref(),
+                )
+            {
+                batches.increment(1);
+            }
+        }
+        Action::Continue
+    }
+
+    /// Both bodies get the upstream model. Transcription uploads are multipart, only their
+    /// model field is rewritten. Speech requests are small json bodies whose input
+    /// characters are metered.
+    fn handle_audio_request_body(
+        &mut self,
+        audio_api: AudioApi,
+        body_size: usize,
+        end_of_stream: bool,
+    ) -> Action {
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        let Some(body_bytes) = self.get_http_request_body(0, body_size) else {
+            return Action::Continue;
+        };
+        if audio_api == AudioApi::Transcriptions {
+            self.rewrite_transcription_model(&body_bytes);
+            return Action::Continue;
+        }
+        let mut speech_request: SpeechRequest = match serde_json::from_slice(&body_bytes) {
+            Ok(speech_request) => speech_request,
+            Err(err) => {
+                self.send_server_error(
+                    ServerError::LogicError(format!("Failed to parse speech request: {}", err)),
+                    Some(StatusCode::BAD_REQUEST),
+                );
+                return Action::Pause;
+            }
+        };
+
+        let model_requested = speech_request.model.clone();
+        if let Some(model) = self.llm_provider().model.as_deref() {
+            speech_request.model = self.llm_provider().upstream_model_id(model).to_string();
+        }
+        let input_characters = speech_request.input.chars().count();
+        self.metrics
+            .audio_speech_characters
+            .increment(input_characters as i64);
+        info!(
+            "[PLANO_REQ_ID:{}] AUDIO_SPEECH_REQUEST: model_requested={} model_upstream={} input_characters={}",
+            self.request_identifier(),
+            model_requested,
+            speech_request.model,
+            input_characters
+        );
+
+        match serde_json::to_vec(&speech_request) {
+            Ok(body) => self.set_http_request_body(0, body_size, &body),
+            Err(err) => {
+                self.send_server_error(
+                    ServerError::LogicError(format!("Failed to serialize speech request: {}", err)),
+                    None,
+                );
+                return Action::Pause;
+            }
+        }
+        Action::Continue
+    }
+
+    /// Replaces the model form field of a transcription upload with the upstream model
+    fn rewrite_transcription_model(&mut self, body: &[u8]) {
+        let Some(model) = self.llm_provider().model.as_deref() else {
+            return;
+        };
+        let model_upstream = self.llm_provider().upstream_model_id(model).to_string();
+        let content_type = self
+            .get_http_request_header("content-type")
+            .unwrap_or_default();
+        let model_requested = multipart_model_field(&content_type, body).unwrap_or_default();
+        info!(
+            "[PLANO_REQ_ID:{}] AUDIO_TRANSCRIPTION_REQUEST: model_requested={} model_upstream={}",
+            self.request_identifier(),
+            model_requested,
+            model_upstream
+        );
+        if model_requested == model_upstream {
+            return;
+        }
+        if let Some(rewritten) = replace_multipart_model_field(&content_type, body, &model_upstream)
+        {
+            self.set_http_request_body(0, body.len(), &rewritten);
+        }
+    }
+
+    /// Audio responses are forwarded as-is. Transcription responses are buffered so that
+    /// the transcribed duration and token usage can be recorded.
+    fn handle_audio_response_body(
+        &mut self,
+        audio_api: AudioApi,
+        body_size: usize,
+        end_of_stream: bool,
+    ) -> Action {
+        let is_error = self
+            .upstream_status_code
+            .map(|status| status.is_client_error() || status.is_server_error())
+            .unwrap_or(false);
+        if audio_api == AudioApi::Transcriptions && !is_error {
+            if !end_of_stream {
+                return Action::Pause;
+            }
+            if let Some(body) = self.get_http_response_body(0, body_size) {
+                if let Some(usage) = TranscriptionUsage::from_response(&body) {
+                    if let Some(seconds) = usage.duration_seconds {
+                        self.metrics
+                            .audio_duration
+                            .record((seconds * 1000.0) as u64);
+                    }
+                    if let Some(input_tokens) = usage.input_tokens {
+                        self.input_tokens = Some(input_tokens);
+                    }
+                    self.response_tokens = usage.output_tokens.unwrap_or(0) as usize;
+                    info!(
+                        "[PLANO_REQ_ID:{}] AUDIO_TRANSCRIPTION_USAGE: duration_seconds={:?} input_tokens={:?} output_tokens={:?}",
+                        self.request_identifier(),
+                        usage.duration_seconds,
+                        usage.input_tokens,
+                        usage.output_tokens
+                    );
+                }
+            }
+        }
+
+        if end_of_stream {
+            self.handle_end_of_request_metrics_and_traces(get_current_time().unwrap());
+        }
+        Action::Continue
+    }
+
+    fn record_response_usage(&mut self, usage: Option<(usize, usize, usize)>) {
+        let Some((prompt_tokens, completion_tokens, total_tokens)) = usage else {
+            warn!(
+                "[PLANO_REQ_ID:{}] RESPONSE_USAGE: no usage information found",
+                self.request_identifier()
+            );
+            return;
+        };
+        debug!(
+            "[PLANO_REQ_ID:{}] RESPONSE_USAGE: prompt_tokens={} completion_tokens={} total_tokens={}",
+            self.request_identifier(),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens
+        );
+        self.response_tokens = completion_tokens;
+        // requests sent on the fast path aren't tokenized
+        self.input_tokens.get_or_insert(prompt_tokens as u64);
+    }
+
+    /// Sends a large response that is already in the client's api as it is, reading its
+    /// usage by scanning the body instead of building the response. None when the response
+    /// needs the full path: it's translated, middlewares see it or it doesn't parse.
+    fn scan_large_response(
+        &mut self,
+        body: &[u8],
+        client_api: &SupportedAPIsFromClient,
+        provider_id: ProviderId,
+    ) -> Option<Vec<u8>> {
+        if body.len() < LARGE_RESPONSE_BODY_BYTES || !self.middlewares.is_empty() {
+            return None;
+        }
+        let upstream_api = provider_id.compatible_api_for_client(client_api, false);
+        if !is_same_api(client_api, &upstream_api) {
+            return None;
+        }
+        let usage = scan_usage_counts(body).ok()?;
+        debug!(
+            "[PLANO_REQ_ID:{}] NON_STREAMING_SCAN: body_size={}",
+            self.request_identifier(),
+            body.len()
+        );
+        self.record_response_usage(usage);
+        Some(body.to_vec())
+    }
+
+    /// Whether a non streaming response is forwarded chunk by chunk instead of buffered,
+    /// when it's large and nothing needs it whole: it's in the client's api, no middleware
+    /// sees it and it isn't re-encoded. Responses over the size limit are still buffered
+    /// until they're rejected.
+    fn forwards_large_response(&self, content_length: Option<usize>) -> bool {
+        let Some(content_length) = content_length else {
+            return false;
+        };
+        let is_success = self
+            .upstream_status_code
+            .is_some_and(|status| status.is_success());
+        if self.streaming_response
+            || !is_success
+            || content_length < LARGE_RESPONSE_BODY_BYTES
+            || self
+                .max_response_body_bytes()
+                .is_some_and(|max_bytes| content_length > max_bytes)
+            || self.request_body_sent_time.is_none()
+            || self.batch_api.is_some()
+            || self.audio_api.is_some()
+            || !self.middlewares.is_empty()
+            || self.upstream_decoder.is_some()
+            || self.client_encoder.is_some()
+        {
+            return false;
+        }
+        let Some(client_api) = self.client_api.as_ref() else {
+            return false;
+        };
+        let upstream_api = self
+            .get_provider_id()
+            .compatible_api_for_client(client_api, false);
+        is_same_api(client_api, &upstream_api)
+    }
+
+    /// Forwards each chunk of a large response as it arrives, keeping only the last
+    /// `RESPONSE_USAGE_TAIL_BYTES` to read the usage from once the upstream is done
+    fn forward_large_response(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if body_size > 0 {
+            if let Some(chunk) = self.get_http_response_body(0, body_size) {
+                let tail = self.large_response_tail.get_or_insert_with(Vec::new);
+                tail.extend_from_slice(&chunk);
+                let excess = tail.len().saturating_sub(RESPONSE_USAGE_TAIL_BYTES);
+                tail.drain(..excess);
             }
         }
+        if !end_of_stream {
+            return Action::Continue;
+        }
+
+        let tail = self.large_response_tail.take().unwrap_or_default();
+        debug!(
+            "[PLANO_REQ_ID:{}] NON_STREAMING_FORWARDED: tail_size={}",
+            self.request_identifier(),
+            tail.len()
+        );
+        self.record_response_usage(scan_trailing_usage_counts(&tail));
+        self.handle_end_of_request_metrics_and_traces(get_current_time().unwrap());
+        Action::Continue
     }
 
     fn handle_non_streaming_response(
@@ -657,52 +2308,51 @@ impl StreamContext {
             body.len()
         );
 
-        let response: ProviderResponseType = match self.client_api.as_ref() {
-            Some(client_api) => {
-                match ProviderResponseType::try_from((body, client_api, &provider_id)) {
-                    Ok(response) => response,
-                    Err(e) => {
-                        warn!(
-                            "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_PARSE_ERROR: {} | body: {}",
-                            self.request_identifier(),
-                            e,
-                            String::from_utf8_lossy(body)
-                        );
-                        self.send_server_error(
-                            ServerError::LogicError(format!("Response parsing error: {}", e)),
-                            Some(StatusCode::BAD_REQUEST),
-                        );
-                        return Err(Action::Continue);
-                    }
-                }
-            }
-            None => {
-                warn!(
-                    "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_ERROR: missing client_api",
-                    self.request_identifier()
-                );
-                return Err(Action::Continue);
-            }
-        };
+        self.record_groq_queue_time(body, false);
 
-        // Use provider interface to extract usage information
-        if let Some((prompt_tokens, completion_tokens, total_tokens)) =
-            response.extract_usage_counts()
-        {
-            debug!(
-                "[PLANO_REQ_ID:{}] RESPONSE_USAGE: prompt_tokens={} completion_tokens={} total_tokens={}",
-                self.request_identifier(),
-                prompt_tokens,
-                completion_tokens,
-                total_tokens
-            );
-            self.response_tokens = completion_tokens;
-        } else {
+        let Some(client_api) = self.client_api.clone() else {
             warn!(
-                "[PLANO_REQ_ID:{}] RESPONSE_USAGE: no usage information found",
+                "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_ERROR: missing client_api",
                 self.request_identifier()
             );
+            return Err(Action::Continue);
+        };
+        if let Some(body) = self.scan_large_response(body, &client_api, provider_id) {
+            return Ok(body);
+        }
+        let mut response = match ProviderResponseType::try_from((body, &client_api, &provider_id)) {
+            Ok(response) => response,
+            Err(e) => match self.repair_response(body, &client_api, &provider_id) {
+                Some(response) => response,
+                None => {
+                    warn!(
+                        "[PLANO_REQ_ID:{}] UPSTREAM_RESPONSE_PARSE_ERROR: {} | body: {}",
+                        self.request_identifier(),
+                        e,
+                        String::from_utf8_lossy(body)
+                    );
+                    self.metrics
+                        .malformed_responses
+                        .with_labels(&self.metric_labels())
+                        .increment(1);
+                    // brightstaff retries requests answered with this header
+                    self.send_local_response(
+                        StatusCode::BAD_GATEWAY.as_u16().into(),
+                        vec![(ARCH_MALFORMED_RESPONSE_HEADER, "true")],
+                        Some(format!("Response parsing error: {}", e).as_bytes()),
+                    );
+                    return Err(Action::Continue);
+                }
+            },
+        };
+
+        if let Err(err) = self.run_response_middlewares(&mut response) {
+            self.send_middleware_error(err);
+            return Err(Action::Continue);
         }
+
+        // Use provider interface to extract usage information
+        self.record_response_usage(response.extract_usage_counts());
         // Serialize the normalized response back to JSON bytes
         match serde_json::to_vec(&response) {
             Ok(bytes) => {
@@ -735,6 +2385,23 @@ impl HttpContext for StreamContext {
             self.send_http_response(200, vec![], None);
             return Action::Continue;
         }
+        self.resolve_request_id();
+
+        if let Some(max_bytes) = self.max_request_body_bytes() {
+            if let Some(content_length) = self
+                .get_http_request_header("content-length")
+                .and_then(|value| value.parse::<usize>().ok())
+            {
+                if content_length > max_bytes {
+                    self.reject_oversized_request(content_length, max_bytes);
+                    return Action::Continue;
+                }
+            }
+        }
+
+        if !self.middlewares.is_empty() {
+            self.request_headers = self.get_http_request_headers();
+        }
 
         // Capture HTTP method and protocol for tracing
         self.http_method = self.get_http_request_header(":method");
@@ -747,18 +2414,112 @@ impl HttpContext for StreamContext {
 
         // let routing_header_value = self.get_http_request_header(ARCH_ROUTING_HEADER);
 
+        self.listener_name = self.get_http_request_header(ARCH_LISTENER_NAME_HEADER);
+        self.traffic_split = self.get_http_request_header(ARCH_TRAFFIC_SPLIT_HEADER);
+        if self.traffic_split.is_some() {
+            self.remove_http_request_header(ARCH_TRAFFIC_SPLIT_HEADER);
+        }
+        self.sse_tap = self.get_http_request_header(ARCH_SSE_TAP_HEADER).is_some();
+        if self.sse_tap {
+            self.remove_http_request_header(ARCH_SSE_TAP_HEADER);
+        }
         self.select_llm_provider();
+
+        if self
+            .get_http_request_header(ARCH_CONNECTION_WARMUP_HEADER)
+            .is_some()
+        {
+            self.forward_connection_warmup();
+            return Action::Continue;
+        }
+
+        // Batches and files live with the provider that created them, so clients polling a
+        // batch must keep sending the same provider hint (or rely on the default provider).
+        if let Some(batch_api) = BatchApi::from_endpoint(&request_path) {
+            self.batch_api = Some(batch_api);
+            // message batches authenticate like the messages api
+            if batch_api == BatchApi::MessageBatches {
+                self.resolved_api = Some(SupportedUpstreamAPIs::AnthropicMessagesAPI(
+                    AnthropicApi::Messages,
+                ));
+            }
+            let target_endpoint = batch_api.target_endpoint_for_provider(
+                &self.get_provider_id(),
+                &request_path,
+                self.llm_provider().base_url_path_prefix.as_deref(),
+            );
+            self.set_http_request_header(":path", Some(&target_endpoint));
+            self.prepare_upstream_headers();
+            self.traceparent = self.get_http_request_header(TRACE_PARENT_HEADER);
+            return Action::Continue;
+        }
+
+        if let Some(audio_api) = AudioApi::from_endpoint(&request_path) {
+            self.audio_api = Some(audio_api);
+            let model_id = self
+                .llm_provider()
+                .upstream_model_id(self.llm_provider().model.as_deref().unwrap_or_default())
+                .to_string();
+            let target_endpoint = audio_api.target_endpoint_for_provider(
+                &self.get_provider_id(),
+                &model_id,
+                self.llm_provider().base_url_path_prefix.as_deref(),
+            );
+            self.set_http_request_header(":path", Some(&target_endpoint));
+            self.prepare_upstream_headers();
+            // the body is rewritten with the upstream model
+            self.delete_content_length_header();
+            self.traceparent = self.get_http_request_header(TRACE_PARENT_HEADER);
+            return Action::Continue;
+        }
+
         // Check if this is a supported API endpoint
         if SupportedAPIsFromClient::from_endpoint(&request_path).is_none() {
-            self.send_http_response(404, vec![], Some(b"Unsupported endpoint"));
+            self.send_local_response(404, vec![], Some(b"Unsupported endpoint"));
             return Action::Continue;
         }
 
         // Get the SupportedApi for routing decisions
         let supported_api: Option<SupportedAPIsFromClient> =
             SupportedAPIsFromClient::from_endpoint(&request_path);
+
+        if let (Some(api), Some(listener)) = (supported_api.as_ref(), self.listener()) {
+            if !listener.allows_api(api) {
+                warn!(
+                    "[PLANO_REQ_ID:{}] LISTENER_API_REJECTED: listener={} client_api={:?}",
+                    self.request_identifier(),
+                    listener.name,
+                    api
+                );
+                self.send_local_response(404, vec![], Some(b"Unsupported endpoint"));
+                return Action::Continue;
+            }
+        }
         self.client_api = supported_api;
 
+        // The body is rewritten for the upstream, so it is decoded here and sent uncompressed
+        if let Some(value) = self.get_http_request_header("content-encoding") {
+            if !ContentEncoding::is_identity(&value) {
+                match ContentEncoding::from_header(&value) {
+                    Some(encoding) => {
+                        self.request_encoding = Some(encoding);
+                        self.remove_http_request_header("content-encoding");
+                    }
+                    None => {
+                        self.send_local_response(
+                            415,
+                            vec![],
+                            Some(b"Unsupported request content encoding"),
+                        );
+                        return Action::Continue;
+                    }
+                }
+            }
+        }
+        // Upstreams may compress responses with any encoding the filter can decode
+        self.client_accept_encoding = self.get_http_request_header("accept-encoding");
+        self.set_http_request_header("accept-encoding", Some("gzip, br"));
+
         // Debug: log provider, client API, resolved API, and request path
         if let (Some(api), Some(provider)) = (self.client_api.as_ref(), self.llm_provider.as_ref())
         {
@@ -778,37 +2539,12 @@ impl HttpContext for StreamContext {
             //We need to update the upstream path if there is a variation for a provider like Gemini/Groq, etc.
             self.update_upstream_path(&request_path);
 
-            if self.llm_provider().endpoint.is_some() {
-                self.add_http_request_header(
-                    ARCH_ROUTING_HEADER,
-                    &self
-                        .llm_provider()
-                        .cluster_name
-                        .as_ref()
-                        .unwrap()
-                        .to_string(),
-                );
-            } else {
-                self.add_http_request_header(
-                    ARCH_ROUTING_HEADER,
-                    &self.llm_provider().provider_interface.to_string(),
-                );
-            }
-            if let Err(error) = self.modify_auth_headers() {
-                // ensure that the provider has an endpoint if the access key is missing else return a bad request
-                if self.llm_provider.as_ref().unwrap().endpoint.is_none()
-                    && self.llm_provider.as_ref().unwrap().provider_interface
-                        != LlmProviderType::Arch
-                {
-                    self.send_server_error(error, Some(StatusCode::BAD_REQUEST));
-                }
-            }
+            self.prepare_upstream_headers();
         }
 
         self.delete_content_length_header();
         self.save_ratelimit_header();
 
-        self.request_id = self.get_http_request_header(REQUEST_ID_HEADER);
         self.traceparent = self.get_http_request_header(TRACE_PARENT_HEADER);
 
         Action::Continue
@@ -822,14 +2558,50 @@ impl HttpContext for StreamContext {
             end_of_stream
         );
 
-        // Let the client send the gateway all the data before sending to the LLM_provider.
-        // TODO: consider a streaming API.
+        // The body is buffered until all of it arrived, unless it is large enough to be
+        // streamed upstream unchanged, see stream_request_body
 
         if self.request_body_sent_time.is_none() {
             self.request_body_sent_time = Some(current_time_ns());
+            self.start_in_flight();
+        }
+
+        // once streamed, body_size is the size of each chunk instead of the buffered body
+        if self.streams_request_body == Some(true) {
+            self.streamed_request_bytes += body_size;
+            if let Some(max_bytes) = self.max_request_body_bytes() {
+                if self.streamed_request_bytes > max_bytes {
+                    self.reject_oversized_request(self.streamed_request_bytes, max_bytes);
+                    return Action::Pause;
+                }
+            }
+            if self.reject_late_request_field(body_size) {
+                return Action::Pause;
+            }
+            return Action::Continue;
+        }
+
+        // body_size is the size of the buffered body, so oversized uploads are rejected early
+        if let Some(max_bytes) = self.max_request_body_bytes() {
+            if body_size > max_bytes {
+                self.reject_oversized_request(body_size, max_bytes);
+                return Action::Pause;
+            }
+        }
+
+        // file uploads are streamed through untouched
+        if self.batch_api.is_some() {
+            return Action::Continue;
+        }
+
+        if let Some(audio_api) = self.audio_api {
+            return self.handle_audio_request_body(audio_api, body_size, end_of_stream);
         }
 
         if !end_of_stream {
+            if self.streams_request_body.is_none() && self.stream_request_body(body_size) {
+                return Action::Continue;
+            }
             return Action::Pause;
         }
 
@@ -850,6 +2622,31 @@ impl HttpContext for StreamContext {
                 return Action::Pause;
             }
         };
+        let body_bytes = match self.request_encoding {
+            Some(encoding) => match compression::decode(encoding, &body_bytes) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    self.send_server_error(
+                        ServerError::BadRequest {
+                            why: format!(
+                                "Failed to decode {} request body: {}",
+                                encoding.as_str(),
+                                err
+                            ),
+                        },
+                        Some(StatusCode::BAD_REQUEST),
+                    );
+                    return Action::Pause;
+                }
+            },
+            None => body_bytes,
+        };
+
+        if let Some(upstream_body) = self.request_fast_path(&body_bytes) {
+            let upstream_body = self.strip_request_fields(upstream_body);
+            self.set_http_request_body(0, body_size, &upstream_body);
+            return Action::Continue;
+        }
 
         //We need to deserialize the request body based on the resolved API
         let mut deserialized_client_request: ProviderRequestType = match self.client_api.as_ref() {
@@ -903,7 +2700,10 @@ impl HttpContext for StreamContext {
 
         // Apply model name resolution logic using the trait method
         let resolved_model = match model_name {
-            Some(model_name) => model_name.clone(),
+            Some(model_name) => self
+                .llm_provider()
+                .upstream_model_id(model_name)
+                .to_string(),
             None => {
                 warn!(
                     "[PLANO_REQ_ID:{}] MODEL_RESOLUTION_ERROR: no model specified | req_model='{}' provider='{}' config_model={:?}",
@@ -929,6 +2729,32 @@ impl HttpContext for StreamContext {
 
         // Set the resolved model using the trait method
         deserialized_client_request.set_model(resolved_model.clone());
+        self.resolved_model = Some(resolved_model.clone());
+
+        if let Err(err) = self.run_request_middlewares(&mut deserialized_client_request) {
+            self.send_middleware_error(err);
+            return Action::Continue;
+        }
+
+        if let Err(err) =
+            self.apply_request_rules(&mut deserialized_client_request, &model_requested)
+        {
+            self.send_server_error(
+                ServerError::BadRequest {
+                    why: format!("Request rules produced an invalid request: {}", err),
+                },
+                Some(StatusCode::BAD_REQUEST),
+            );
+            return Action::Continue;
+        }
+        // a rule can send the request with another model of the provider
+        let resolved_model = deserialized_client_request.model().to_string();
+        self.resolved_model = Some(resolved_model.clone());
+
+        self.end_user = deserialized_client_request.user().map(str::to_string);
+        if let (Some(config), Some(user)) = (self.user_hashing(), self.end_user.as_deref()) {
+            deserialized_client_request.set_user(user_hashing::hash_user(&config.secret, user));
+        }
 
         // Extract user message for tracing
         self.user_message = deserialized_client_request.get_recent_user_message();
@@ -947,6 +2773,19 @@ impl HttpContext for StreamContext {
         if !self.streaming_response {
             self.streaming_response = deserialized_client_request.is_streaming();
         }
+        // The Codex backend only streams, and its events aren't collected into a response
+        if self.get_provider_id() == ProviderId::ChatGPT && !self.streaming_response {
+            self.send_server_error(
+                ServerError::BadRequest {
+                    why: format!(
+                        "LLM Provider \"{}\" only serves streaming requests, set \"stream\": true",
+                        self.llm_provider().name
+                    ),
+                },
+                Some(StatusCode::BAD_REQUEST),
+            );
+            return Action::Continue;
+        }
 
         // Use provider interface for text extraction (after potential mutation)
         let input_tokens_str = deserialized_client_request.extract_messages_text();
@@ -960,6 +2799,29 @@ impl HttpContext for StreamContext {
             return Action::Continue;
         }
 
+        // Map, clamp or reject sampling params the provider handles differently
+        match deserialized_client_request.apply_sampling_capabilities(self.get_provider_id()) {
+            Ok(adjustments) if !adjustments.is_empty() => {
+                warn!(
+                    "[PLANO_REQ_ID:{}] SAMPLING_PARAMS_ADJUSTED: provider={} dropped={:?} clamped={:?}",
+                    self.request_identifier(),
+                    self.get_provider_id(),
+                    adjustments.dropped,
+                    adjustments.clamped
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.send_server_error(
+                    ServerError::BadRequest { why: e.message },
+                    Some(StatusCode::BAD_REQUEST),
+                );
+                return Action::Pause;
+            }
+        }
+
+        self.adjust_max_tokens_to_context_window(&mut deserialized_client_request, &resolved_model);
+
         // Convert chat completion request to llm provider specific request using provider interface
         let serialized_body_bytes_upstream =
             match self.resolved_api.as_ref() {
@@ -970,14 +2832,16 @@ impl HttpContext for StreamContext {
                 );
 
                     match ProviderRequestType::try_from((deserialized_client_request, upstream)) {
-                        Ok(request) => {
+                        Ok(mut request) => {
+                            self.sanitize_for_provider(&mut request);
+                            self.apply_system_prompt_policy(&mut request);
                             debug!(
                                 "[PLANO_REQ_ID:{}] UPSTREAM_REQUEST_PAYLOAD: {}",
                                 self.request_identifier(),
                                 String::from_utf8_lossy(&request.to_bytes().unwrap_or_default())
                             );
 
-                            match request.to_bytes() {
+                            match request.to_upstream_bytes(self.get_provider_id()) {
                                 Ok(bytes) => bytes,
                                 Err(e) => {
                                     warn!("Failed to serialize request body: {}", e);
@@ -1012,11 +2876,13 @@ impl HttpContext for StreamContext {
                 }
             };
 
+        let serialized_body_bytes_upstream =
+            self.strip_request_fields(serialized_body_bytes_upstream);
         self.set_http_request_body(0, body_size, &serialized_body_bytes_upstream);
         Action::Continue
     }
 
-    fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
         // Capture the upstream response status code to handle errors appropriately
         if let Some(status_str) = self.get_http_response_header(":status") {
             if let Ok(status_code) = status_str.parse::<u16>() {
@@ -1030,60 +2896,134 @@ impl HttpContext for StreamContext {
             }
         }
 
+        // the stream flag of a streamed request body can come after the bytes it was
+        // decided on
+        if self.streams_request_body == Some(true) && !self.streaming_response {
+            self.streaming_response = self
+                .get_http_response_header("content-type")
+                .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+        }
+
+        let content_length = self
+            .get_http_response_header("content-length")
+            .and_then(|value| value.parse::<usize>().ok());
         self.remove_http_response_header("content-length");
-        self.remove_http_response_header("content-encoding");
+        if let Some(request_id) = self.request_id.clone() {
+            self.set_http_response_header(ARCH_REQUEST_ID_HEADER, Some(&request_id));
+        }
+        // batch and audio bodies are forwarded untouched, in their own encoding
+        if self.batch_api.is_none() && self.audio_api.is_none() {
+            self.negotiate_response_encoding();
+        }
+        if self.forwards_large_response(content_length) {
+            self.large_response_tail = Some(Vec::new());
+        }
+
+        if let Some(adjustment) = self.max_tokens_adjustment {
+            let header_value = format!(
+                "requested={}; adjusted={}",
+                adjustment.requested, adjustment.adjusted
+            );
+            self.set_http_response_header(ARCH_MAX_TOKENS_ADJUSTED_HEADER, Some(&header_value));
+        }
 
         self.set_property(
             vec!["metadata", "filter_metadata", "llm_filter", "user_prompt"],
             Some("hello world from filter".as_bytes()),
         );
 
+        // hold the headers of error responses so the upstream error header can be added
+        // once the body is read
+        let is_error = self
+            .upstream_status_code
+            .is_some_and(|status| status.is_client_error() || status.is_server_error());
+        if is_error && self.normalizes_upstream_errors() {
+            if end_of_stream {
+                // no body to translate
+                let header_value = format!("{}: ", self.get_provider_id());
+                self.set_http_response_header(ARCH_UPSTREAM_ERROR_HEADER, Some(&header_value));
+                return Action::Continue;
+            }
+            return Action::Pause;
+        }
+
         Action::Continue
     }
 
     fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        // batch polling is mostly GET requests, which never reach on_http_request_body
+        if let Some(batch_api) = self.batch_api {
+            return self.handle_batch_response_body(batch_api, body_size, end_of_stream);
+        }
+
         if self.request_body_sent_time.is_none() {
             debug!("on_http_response_body: request body not sent, not doing any processing in llm filter");
             return Action::Continue;
         }
 
-        let current_time = get_current_time().unwrap();
-        if end_of_stream && body_size == 0 {
-            debug!(
-                "[PLANO_REQ_ID:{}] RESPONSE_BODY_COMPLETE: total_bytes={}",
-                self.request_identifier(),
-                body_size
-            );
-            self.handle_end_of_request_metrics_and_traces(current_time);
-            return Action::Continue;
+        if let Some(audio_api) = self.audio_api {
+            return self.handle_audio_response_body(audio_api, body_size, end_of_stream);
         }
 
-        // Check if this is an error response from upstream
-        if let Some(status_code) = &self.upstream_status_code {
+        if let Some(status_code) = self.upstream_status_code {
             if status_code.is_client_error() || status_code.is_server_error() {
+                // error bodies are small, buffer them to translate them whole
+                if !end_of_stream {
+                    return Action::Pause;
+                }
                 info!(
                     "[PLANO_REQ_ID:{}] UPSTREAM_ERROR_RESPONSE: status={} body_size={}",
                     self.request_identifier(),
                     status_code.as_u16(),
                     body_size
                 );
-
-                // For error responses, forward the upstream error directly without parsing
-                if body_size > 0 {
-                    if let Ok(body) = self.read_raw_response_body(body_size) {
-                        debug!(
-                            "[PLANO_REQ_ID:{}] UPSTREAM_ERROR_BODY: {}",
-                            self.request_identifier(),
-                            String::from_utf8_lossy(&body)
-                        );
-                        // Forward the error response as-is
-                        self.set_http_response_body(0, body_size, &body);
-                    }
-                }
+                let body = match body_size {
+                    0 => Vec::new(),
+                    _ => self
+                        .get_http_response_body(0, body_size)
+                        .unwrap_or_default(),
+                };
+                let body = self.decode_upstream_body(body);
+                debug!(
+                    "[PLANO_REQ_ID:{}] UPSTREAM_ERROR_BODY: {}",
+                    self.request_identifier(),
+                    String::from_utf8_lossy(&body)
+                );
+                self.normalize_upstream_error(status_code, &body, body_size);
+                self.metrics
+                    .requests
+                    .with_labels(&self.metric_labels())
+                    .increment(1);
                 return Action::Continue;
             }
         }
 
+        if self.large_response_tail.is_some() {
+            return self.forward_large_response(body_size, end_of_stream);
+        }
+
+        let current_time = get_current_time().unwrap();
+        if end_of_stream && body_size == 0 {
+            debug!(
+                "[PLANO_REQ_ID:{}] RESPONSE_BODY_COMPLETE: total_bytes={}",
+                self.request_identifier(),
+                body_size
+            );
+            // a truncated stream already ended with its error event
+            let mut flushed = match self.streaming_response && !self.response_truncated {
+                true => self.flush_sse_stream().unwrap_or_default(),
+                false => Vec::new(),
+            };
+            if let Some(encoder) = self.client_encoder.take() {
+                flushed = encoder.finish(&flushed);
+            }
+            if !flushed.is_empty() {
+                self.set_http_response_body(0, 0, &flushed);
+            }
+            self.handle_end_of_request_metrics_and_traces(current_time);
+            return Action::Continue;
+        }
+
         match self.client_api {
             Some(SupportedAPIsFromClient::OpenAIChatCompletions(_)) => {}
             Some(SupportedAPIsFromClient::AnthropicMessagesAPI(_)) => {}
@@ -1102,8 +3042,17 @@ impl HttpContext for StreamContext {
             }
         }
 
+        if self.enforce_response_body_limit(body_size, end_of_stream) {
+            return Action::Continue;
+        }
+
+        // Buffer non-streaming bodies until the upstream is done so that they are parsed whole
+        if !self.streaming_response && !end_of_stream {
+            return Action::Pause;
+        }
+
         let body = match self.read_raw_response_body(body_size) {
-            Ok(bytes) => bytes,
+            Ok(bytes) => self.decode_upstream_body(bytes),
             Err(action) => return action,
         };
 
@@ -1116,25 +3065,62 @@ impl HttpContext for StreamContext {
 
         let provider_id = self.get_provider_id();
         if self.streaming_response {
+            self.record_stream_chunk_gap(current_time);
             match self.handle_streaming_response(&body, provider_id) {
                 Ok(serialized_body) => {
+                    let mut serialized_body = self.tap_upstream_chunk(&body, serialized_body);
+                    // the last chunk can leave an event without its terminating blank line
+                    if end_of_stream {
+                        if let Some(flushed) = self.flush_sse_stream() {
+                            serialized_body.extend_from_slice(&flushed);
+                        }
+                    }
+                    let serialized_body = self.encode_for_client(serialized_body, end_of_stream);
                     self.set_http_response_body(0, body_size, &serialized_body);
+                    if end_of_stream {
+                        self.handle_end_of_request_metrics_and_traces(current_time);
+                    }
+                }
+                Err(action) => {
+                    self.forward_unprocessed_body(body, body_size, end_of_stream);
+                    return action;
                 }
-                Err(action) => return action,
             }
         } else {
             match self.handle_non_streaming_response(&body, provider_id) {
                 Ok(serialized_body) => {
+                    let serialized_body = self.encode_for_client(serialized_body, true);
                     self.set_http_response_body(0, body_size, &serialized_body);
                 }
-                Err(action) => return action,
+                Err(action) => {
+                    self.forward_unprocessed_body(body, body_size, true);
+                    return action;
+                }
             }
+            self.handle_end_of_request_metrics_and_traces(current_time);
         }
 
         Action::Continue
     }
 }
 
+/// Whether the upstream api is the api of the client, so bodies need no translation
+fn is_same_api(client_api: &SupportedAPIsFromClient, upstream_api: &SupportedUpstreamAPIs) -> bool {
+    matches!(
+        (client_api, upstream_api),
+        (
+            SupportedAPIsFromClient::OpenAIChatCompletions(_),
+            SupportedUpstreamAPIs::OpenAIChatCompletions(_)
+        ) | (
+            SupportedAPIsFromClient::AnthropicMessagesAPI(_),
+            SupportedUpstreamAPIs::AnthropicMessagesAPI(_)
+        ) | (
+            SupportedAPIsFromClient::OpenAIResponsesAPI(_),
+            SupportedUpstreamAPIs::OpenAIResponsesAPI(_)
+        )
+    )
+}
+
 fn current_time_ns() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -1142,4 +3128,10 @@ fn current_time_ns() -> u128 {
         .as_nanos()
 }
 
-impl Context for StreamContext {}
+impl Context for StreamContext {
+    fn on_done(&mut self) -> bool {
+        // streams reset before the response completed
+        self.finish_in_flight();
+        true
+    }
+}
@@ -0,0 +1,38 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Hex encoded HMAC-SHA256 of the end user, keyed with the gateway secret. The same user
+/// always hashes to the same value, so providers can still tell users apart for abuse
+/// detection without seeing who they are.
+pub fn hash_user(secret: &str, user: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(user.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_user() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hash_user("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hash_user("secret", "alice@example.com"),
+            hash_user("secret", "alice@example.com")
+        );
+        assert_ne!(
+            hash_user("secret", "alice@example.com"),
+            hash_user("rotated", "alice@example.com")
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use common::configuration::PromptTarget;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a conversation can take to answer a clarifying question
+pub const PENDING_CLARIFICATION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Tool call waiting on the user to provide required parameters, kept in shared data
+/// between the turns of a conversation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingClarification {
+    pub prompt_target: String,
+    /// Arguments already extracted for the tool call
+    pub arguments: serde_json::Map<String, serde_json::Value>,
+    pub missing: Vec<String>,
+    pub created_at: u64,
+}
+
+pub fn clarification_key(session_id: &str) -> String {
+    format!("arch.clarification.{}", session_id)
+}
+
+impl PendingClarification {
+    pub fn new(prompt_target: &str, arguments: &str, missing: Vec<String>) -> Self {
+        PendingClarification {
+            prompt_target: prompt_target.to_string(),
+            arguments: serde_json::from_str(arguments).unwrap_or_default(),
+            missing,
+            created_at: now_secs(),
+        }
+    }
+
+    /// Reads a pending clarification from shared data, ignoring expired or cleared entries
+    pub fn from_shared_data(data: &[u8]) -> Option<Self> {
+        let pending: PendingClarification = serde_json::from_slice(data).ok()?;
+        if now_secs() > pending.created_at + PENDING_CLARIFICATION_TTL.as_secs() {
+            return None;
+        }
+        Some(pending)
+    }
+
+    /// Fills the arguments of a new call to the same prompt target with the ones collected
+    /// in previous turns. Arguments of the new call win.
+    pub fn merge_arguments(&self, arguments: &str) -> String {
+        let mut merged = self.arguments.clone();
+        if let Ok(serde_json::Value::Object(arguments)) = serde_json::from_str(arguments) {
+            merged.extend(arguments.into_iter().filter(|(_, value)| !value.is_null()));
+        }
+        serde_json::Value::Object(merged).to_string()
+    }
+}
+
+/// System prompt instructing the llm to ask the user for the missing parameters
+pub fn clarification_prompt(prompt_target: &PromptTarget, missing: &[String]) -> String {
+    let parameters = prompt_target.parameters.clone().unwrap_or_default();
+    let missing_details: Vec<String> = missing
+        .iter()
+        .map(
+            |name| match parameters.iter().find(|parameter| &parameter.name == name) {
+                Some(parameter) => format!("- {}: {}", name, parameter.description),
+                None => format!("- {}", name),
+            },
+        )
+        .collect();
+
+    format!(
+        "You are helping the user with the following task: {}\nThe following information is still needed:\n{}\nAsk the user a short, friendly question to get this information. Do not make up values and do not answer the task yet.",
+        prompt_target.description,
+        missing_details.join("\n")
+    )
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge_arguments() {
+        let pending = PendingClarification::new(
+            "get_weather",
+            r#"{"location": "seattle", "unit": "celsius"}"#,
+            vec!["days".to_string()],
+        );
+
+        let merged: serde_json::Value =
+            serde_json::from_str(&pending.merge_arguments(r#"{"days": 3, "unit": null}"#)).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({"location": "seattle", "unit": "celsius", "days": 3})
+        );
+    }
+
+    #[test]
+    fn test_pending_clarification_expires() {
+        let mut pending = PendingClarification::new("get_weather", "{}", vec![]);
+        let data = serde_json::to_vec(&pending).unwrap();
+        assert_eq!(
+            PendingClarification::from_shared_data(&data),
+            Some(pending.clone())
+        );
+
+        pending.created_at -= PENDING_CLARIFICATION_TTL.as_secs() + 1;
+        let data = serde_json::to_vec(&pending).unwrap();
+        assert_eq!(PendingClarification::from_shared_data(&data), None);
+        assert_eq!(PendingClarification::from_shared_data(b""), None);
+    }
+
+    #[test]
+    fn test_clarification_prompt_lists_missing_parameters() {
+        let prompt_target: PromptTarget = serde_yaml::from_str(
+            r#"
+name: get_weather
+description: Get the weather forecast
+parameters:
+  - name: location
+    type: str
+    description: The city
+    required: true
+"#,
+        )
+        .unwrap();
+
+        let prompt = clarification_prompt(&prompt_target, &["location".to_string()]);
+        assert!(prompt.contains("Get the weather forecast"));
+        assert!(prompt.contains("- location: The city"));
+    }
+}
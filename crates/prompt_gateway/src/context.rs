@@ -3,7 +3,7 @@ use std::str::FromStr;
 use common::errors::ServerError;
 use common::stats::IncrementingMetric;
 use http::StatusCode;
-use log::warn;
+use log::{debug, warn};
 use proxy_wasm::traits::Context;
 
 use crate::stream_context::{ResponseHandlerType, StreamContext};
@@ -23,10 +23,24 @@ impl Context for StreamContext {
             .expect("invalid token_id");
         self.metrics.active_http_calls.increment(-1);
 
+        let is_function_call = matches!(
+            callout_context.response_handler_type,
+            ResponseHandlerType::FunctionCall
+        );
+        if is_function_call && self.tool_calls_failed {
+            debug!("dropping api call response, another tool call failed");
+            return;
+        }
+
         let body = self
             .get_http_call_response_body(0, body_size)
             .unwrap_or_default();
 
+        if let ResponseHandlerType::Guard = callout_context.response_handler_type {
+            // guards apply their own on_exception policy to failed calls
+            return self.guard_response_handler(body, callout_context);
+        }
+
         if let Some(http_status) = self.get_http_call_response_header(":status") {
             match StatusCode::from_str(http_status.as_str()) {
                 Ok(status_code) => {
@@ -38,6 +52,9 @@ impl Context for StreamContext {
                             body: String::from_utf8(body).unwrap(),
                         };
                         warn!("received non 2xx code: {:?}", server_error);
+                        if is_function_call {
+                            self.tool_calls_failed = true;
+                        }
                         return self.send_server_error(
                             server_error,
                             Some(StatusCode::from_str(http_status.as_str()).unwrap()),
@@ -62,6 +79,7 @@ impl Context for StreamContext {
             ResponseHandlerType::ArchFC => self.arch_fc_response_handler(body, callout_context),
             ResponseHandlerType::FunctionCall => self.api_call_response_handler(body, callout_context),
             ResponseHandlerType::DefaultTarget =>self.default_target_handler(body, callout_context),
+            ResponseHandlerType::Guard => self.guard_response_handler(body, callout_context),
         }
     }
 }
@@ -1,9 +1,11 @@
-use crate::metrics::Metrics;
+use crate::metrics::{GuardMetrics, Metrics};
 use crate::stream_context::StreamContext;
+use crate::tool_cache::ToolCache;
 use common::configuration::{
-    Configuration, Endpoint, Overrides, PromptGuards, PromptTarget, Tracing,
+    Configuration, Endpoint, FunctionCallingConfig, Overrides, PromptGuards, PromptTarget, Tracing,
 };
 use common::http::Client;
+use common::prompt_template::{parse_templates, LabeledTemplate};
 use common::stats::Gauge;
 use log::trace;
 use proxy_wasm::traits::*;
@@ -23,9 +25,13 @@ pub struct FilterContext {
     overrides: Rc<Option<Overrides>>,
     system_prompt: Rc<Option<String>>,
     prompt_targets: Rc<HashMap<String, PromptTarget>>,
+    prompt_templates: Rc<HashMap<String, LabeledTemplate>>,
     endpoints: Rc<Option<HashMap<String, Endpoint>>>,
     prompt_guards: Rc<PromptGuards>,
     tracing: Rc<Option<Tracing>>,
+    tool_cache: Rc<RefCell<ToolCache>>,
+    guard_metrics: Rc<HashMap<String, GuardMetrics>>,
+    function_calling: Rc<FunctionCallingConfig>,
 }
 
 impl FilterContext {
@@ -35,10 +41,14 @@ impl FilterContext {
             metrics: Rc::new(Metrics::new()),
             system_prompt: Rc::new(None),
             prompt_targets: Rc::new(HashMap::new()),
+            prompt_templates: Rc::new(HashMap::new()),
             overrides: Rc::new(None),
             prompt_guards: Rc::new(PromptGuards::default()),
             endpoints: Rc::new(None),
             tracing: Rc::new(None),
+            tool_cache: Rc::new(RefCell::new(ToolCache::default())),
+            guard_metrics: Rc::new(HashMap::new()),
+            function_calling: Rc::new(FunctionCallingConfig::default()),
         }
     }
 }
@@ -77,13 +87,27 @@ impl RootContext for FilterContext {
         }
         self.system_prompt = Rc::new(config.system_prompt);
         self.prompt_targets = Rc::new(prompt_targets);
+        if let Some(prompt_templates) = config.prompt_templates.as_ref() {
+            match parse_templates(prompt_templates) {
+                Ok(prompt_templates) => self.prompt_templates = Rc::new(prompt_templates),
+                Err(err) => panic!("Invalid prompt_templates: {}", err),
+            }
+        }
         self.endpoints = Rc::new(config.endpoints);
 
         if let Some(prompt_guards) = config.prompt_guards {
+            let guard_metrics = prompt_guards
+                .guards
+                .iter()
+                .flatten()
+                .map(|guard| (guard.name.clone(), GuardMetrics::new(&guard.name)))
+                .collect();
+            self.guard_metrics = Rc::new(guard_metrics);
             self.prompt_guards = Rc::new(prompt_guards)
         }
 
         self.tracing = Rc::new(config.tracing);
+        self.function_calling = Rc::new(config.function_calling.unwrap_or_default());
 
         true
     }
@@ -99,9 +123,14 @@ impl RootContext for FilterContext {
             Rc::clone(&self.metrics),
             Rc::clone(&self.system_prompt),
             Rc::clone(&self.prompt_targets),
+            Rc::clone(&self.prompt_templates),
             Rc::clone(&self.endpoints),
             Rc::clone(&self.overrides),
             Rc::clone(&self.tracing),
+            Rc::clone(&self.tool_cache),
+            Rc::clone(&self.prompt_guards),
+            Rc::clone(&self.guard_metrics),
+            Rc::clone(&self.function_calling),
         )))
     }
 
@@ -0,0 +1,162 @@
+use common::api::prompt_guard::{GuardRequest, GuardResponse};
+use common::configuration::{GuardAction, GuardConfig, GuardTransport};
+
+/// Request body for a guard, either a plain `GuardRequest` or an MCP `tools/call`
+pub fn guard_request_body(guard: &GuardConfig, input: &str) -> String {
+    let guard_request = GuardRequest {
+        guard: guard.name.clone(),
+        stage: guard.stage,
+        input: input.to_string(),
+    };
+
+    match guard.transport.unwrap_or_default() {
+        GuardTransport::Http => serde_json::to_string(&guard_request).unwrap(),
+        GuardTransport::Mcp => serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {
+                "name": guard.tool.as_deref().unwrap_or(&guard.name),
+                "arguments": guard_request,
+            },
+        })
+        .to_string(),
+    }
+}
+
+/// Verdict of a guard. MCP guards return it as structured content or as json text content,
+/// over plain json or a single server sent event.
+pub fn parse_guard_response(guard: &GuardConfig, body: &[u8]) -> Result<GuardResponse, String> {
+    let body = String::from_utf8_lossy(body);
+    match guard.transport.unwrap_or_default() {
+        GuardTransport::Http => serde_json::from_str(&body).map_err(|e| e.to_string()),
+        GuardTransport::Mcp => {
+            let payload = body
+                .lines()
+                .find_map(|line| line.strip_prefix("data:"))
+                .unwrap_or(&body)
+                .trim();
+            let response: serde_json::Value =
+                serde_json::from_str(payload).map_err(|e| e.to_string())?;
+            if let Some(error) = response.get("error") {
+                return Err(format!("mcp error: {}", error));
+            }
+            let result = response
+                .get("result")
+                .ok_or_else(|| "mcp response has no result".to_string())?;
+            if result.get("isError").and_then(|e| e.as_bool()) == Some(true) {
+                return Err(format!("guard tool failed: {}", result));
+            }
+            if let Some(structured) = result.get("structuredContent") {
+                return serde_json::from_value(structured.clone()).map_err(|e| e.to_string());
+            }
+            let text = result
+                .pointer("/content/0/text")
+                .and_then(|text| text.as_str())
+                .ok_or_else(|| "mcp response has no text content".to_string())?;
+            serde_json::from_str(text).map_err(|e| e.to_string())
+        }
+    }
+}
+
+pub fn guard_action(guard: &GuardConfig) -> GuardAction {
+    guard
+        .on_exception
+        .as_ref()
+        .and_then(|on_exception| on_exception.action)
+        .unwrap_or_default()
+}
+
+pub fn guard_fails_open(guard: &GuardConfig) -> bool {
+    guard
+        .on_exception
+        .as_ref()
+        .and_then(|on_exception| on_exception.fail_open)
+        .unwrap_or(false)
+}
+
+/// Message returned to the client when a guard blocks
+pub fn guard_exception_message(guard: &GuardConfig, reason: Option<&str>) -> String {
+    if let Some(message) = guard
+        .on_exception
+        .as_ref()
+        .and_then(|on_exception| on_exception.message.as_ref())
+    {
+        return message.clone();
+    }
+    match reason {
+        Some(reason) => format!("blocked by {} guard: {}", guard.name, reason),
+        None => format!("blocked by {} guard", guard.name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn guard(transport: &str) -> GuardConfig {
+        serde_yaml::from_str(&format!(
+            r#"
+name: toxicity
+stage: output
+transport: {}
+endpoint:
+  name: guard_service
+  path: /guard
+on_exception:
+  action: log
+"#,
+            transport
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_http_guard_request_and_response() {
+        let guard = guard("http");
+        let body: serde_json::Value =
+            serde_json::from_str(&guard_request_body(&guard, "hello")).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"guard": "toxicity", "stage": "output", "input": "hello"})
+        );
+
+        let response =
+            parse_guard_response(&guard, br#"{"flagged": true, "reason": "insult"}"#).unwrap();
+        assert!(response.flagged);
+        assert_eq!(response.reason.as_deref(), Some("insult"));
+        assert_eq!(guard_action(&guard), GuardAction::Log);
+        assert!(!guard_fails_open(&guard));
+    }
+
+    #[test]
+    fn test_mcp_guard_request_and_response() {
+        let guard = guard("mcp");
+        let body: serde_json::Value =
+            serde_json::from_str(&guard_request_body(&guard, "hello")).unwrap();
+        assert_eq!(body["method"], "tools/call");
+        assert_eq!(body["params"]["name"], "toxicity");
+        assert_eq!(body["params"]["arguments"]["input"], "hello");
+
+        let structured =
+            br#"{"jsonrpc":"2.0","id":1,"result":{"structuredContent":{"flagged":false}}}"#;
+        assert!(!parse_guard_response(&guard, structured).unwrap().flagged);
+
+        let sse = b"event: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"{\\\"flagged\\\":true}\"}]}}\n\n";
+        assert!(parse_guard_response(&guard, sse).unwrap().flagged);
+
+        let failed = br#"{"jsonrpc":"2.0","id":1,"result":{"isError":true,"content":[]}}"#;
+        assert!(parse_guard_response(&guard, failed).is_err());
+    }
+
+    #[test]
+    fn test_guard_exception_message() {
+        let mut guard = guard("http");
+        assert_eq!(
+            guard_exception_message(&guard, Some("insult")),
+            "blocked by toxicity guard: insult"
+        );
+        guard.on_exception.as_mut().unwrap().message = Some("please rephrase".to_string());
+        assert_eq!(guard_exception_message(&guard, None), "please rephrase");
+    }
+}
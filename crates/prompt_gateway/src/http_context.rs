@@ -1,17 +1,17 @@
-use crate::stream_context::{ResponseHandlerType, StreamCallContext, StreamContext};
+use crate::stream_context::StreamContext;
 use common::{
     api::open_ai::{
-        self, ArchState, ChatCompletionStreamResponse, ChatCompletionTool, ChatCompletionsRequest,
+        self, ArchState, ChatCompletionStreamResponse, ChatCompletionsRequest,
+        ChatCompletionsResponse,
     },
+    configuration::GuardStage,
     consts::{
-        ARCH_FC_MODEL_NAME, ARCH_INTERNAL_CLUSTER_NAME, ARCH_ROUTING_HEADER,
-        ARCH_UPSTREAM_HOST_HEADER, ASSISTANT_ROLE, CHAT_COMPLETIONS_PATH, HEALTHZ_PATH,
-        MODEL_SERVER_NAME, MODEL_SERVER_REQUEST_TIMEOUT_MS, REQUEST_ID_HEADER, TOOL_ROLE,
-        TRACE_PARENT_HEADER, USER_ROLE, X_ARCH_API_RESPONSE, X_ARCH_FC_MODEL_RESPONSE,
-        X_ARCH_STATE_HEADER, X_ARCH_TOOL_CALL,
+        ARCH_FC_MODEL_NAME, ARCH_ROUTING_HEADER, ARCH_SESSION_ID_HEADER, ARCH_TOOL_CACHE_BYPASS,
+        ARCH_TOOL_CACHE_HEADER, ASSISTANT_ROLE, CHAT_COMPLETIONS_PATH, HEALTHZ_PATH,
+        IDEMPOTENCY_KEY_HEADER, REQUEST_ID_HEADER, TOOL_ROLE, TRACE_PARENT_HEADER, USER_ROLE,
+        X_ARCH_API_RESPONSE, X_ARCH_FC_MODEL_RESPONSE, X_ARCH_STATE_HEADER, X_ARCH_TOOL_CALL,
     },
     errors::ServerError,
-    http::{CallArgs, Client},
     pii::obfuscate_auth_header,
 };
 use http::StatusCode;
@@ -20,7 +20,7 @@ use proxy_wasm::{traits::HttpContext, types::Action};
 use serde_json::Value;
 use std::{
     collections::HashMap,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 // HttpContext is the trait that allows the Rust code to interact with HTTP objects.
@@ -72,6 +72,11 @@ impl HttpContext for StreamContext {
 
         self.request_id = self.get_http_request_header(REQUEST_ID_HEADER);
         self.traceparent = self.get_http_request_header(TRACE_PARENT_HEADER);
+        self.idempotency_key = self.get_http_request_header(IDEMPOTENCY_KEY_HEADER);
+        self.session_id = self.get_http_request_header(ARCH_SESSION_ID_HEADER);
+        self.tool_cache_bypass = self
+            .get_http_request_header(ARCH_TOOL_CACHE_HEADER)
+            .is_some_and(|value| value.eq_ignore_ascii_case(ARCH_TOOL_CACHE_BYPASS));
 
         Action::Continue
     }
@@ -153,101 +158,12 @@ impl HttpContext for StreamContext {
 
         self.user_prompt = Some(last_user_prompt.clone());
 
-        // convert prompt targets to ChatCompletionTool
-        let tool_calls: Vec<ChatCompletionTool> =
-            self.prompt_targets.values().map(|pt| pt.into()).collect();
-
-        let mut metadata = deserialized_body.metadata.clone();
-
-        if let Some(overrides) = self.overrides.as_ref() {
-            if overrides.optimize_context_window.unwrap_or_default() {
-                if metadata.is_none() {
-                    metadata = Some(HashMap::new());
-                }
-                metadata
-                    .as_mut()
-                    .unwrap()
-                    .insert("optimize_context_window".to_string(), "true".to_string());
-            }
-        }
-
-        if let Some(overrides) = self.overrides.as_ref() {
-            if overrides.use_agent_orchestrator.unwrap_or_default() {
-                if metadata.is_none() {
-                    metadata = Some(HashMap::new());
-                }
-                metadata
-                    .as_mut()
-                    .unwrap()
-                    .insert("use_agent_orchestrator".to_string(), "true".to_string());
-            }
-        }
-
-        let arch_fc_chat_completion_request = ChatCompletionsRequest {
-            messages: deserialized_body.messages.clone(),
-            metadata,
-            stream: deserialized_body.stream,
-            model: deserialized_body.model.clone(),
-            stream_options: deserialized_body.stream_options.clone(),
-            tools: Some(tool_calls),
-        };
-
         self.chat_completions_request = Some(deserialized_body);
+        self.load_pending_clarification();
 
-        let json_data = match serde_json::to_string(&arch_fc_chat_completion_request) {
-            Ok(json_data) => json_data,
-            Err(error) => {
-                self.send_server_error(ServerError::Serialization(error), None);
-                return Action::Pause;
-            }
-        };
-
-        info!("on_http_request_body: sending request to model server");
-        debug!("request body: {}", json_data);
-
-        let timeout_str = MODEL_SERVER_REQUEST_TIMEOUT_MS.to_string();
-
-        let mut headers = vec![
-            (ARCH_UPSTREAM_HOST_HEADER, MODEL_SERVER_NAME),
-            (":method", "POST"),
-            (":path", "/function_calling"),
-            ("content-type", "application/json"),
-            (":authority", MODEL_SERVER_NAME),
-            ("x-envoy-upstream-rq-timeout-ms", timeout_str.as_str()),
-        ];
-
-        if self.request_id.is_some() {
-            headers.push((REQUEST_ID_HEADER, self.request_id.as_ref().unwrap()));
-        }
-
-        if self.traceparent.is_some() {
-            headers.push((TRACE_PARENT_HEADER, self.traceparent.as_ref().unwrap()));
-        }
-
-        let call_args = CallArgs::new(
-            ARCH_INTERNAL_CLUSTER_NAME,
-            "/function_calling",
-            headers,
-            Some(json_data.as_bytes()),
-            vec![],
-            Duration::from_secs(5),
-        );
-
-        if let Some(content) = self.user_prompt.as_ref().unwrap().content.as_ref() {
-            let call_context = StreamCallContext {
-                response_handler_type: ResponseHandlerType::ArchFC,
-                user_message: Some(content.to_string()),
-                prompt_target_name: None,
-                request_body: self.chat_completions_request.as_ref().unwrap().clone(),
-                similarity_scores: None,
-                upstream_cluster: Some(ARCH_INTERNAL_CLUSTER_NAME.to_string()),
-                upstream_cluster_path: Some("/function_calling".to_string()),
-            };
-
-            if let Err(e) = self.http_call(call_args, call_context) {
-                warn!("http_call failed: {:?}", e);
-                self.send_server_error(ServerError::HttpDispatch(e), None);
-            }
+        if self.user_prompt.as_ref().unwrap().content.is_some() {
+            // input guards run first, then the prompt goes to Arch-Function
+            self.run_guards(GuardStage::Input, 0);
         } else {
             warn!("No content in the last user prompt");
             self.send_server_error(
@@ -290,6 +206,13 @@ impl HttpContext for StreamContext {
             );
         }
 
+        let output_guards = !self.streaming_response
+            && !self.prompt_guards.guards_for(GuardStage::Output).is_empty();
+        if output_guards && !end_of_stream {
+            // output guards need the complete response
+            return Action::Pause;
+        }
+
         if end_of_stream && body_size == 0 {
             return Action::Continue;
         }
@@ -333,6 +256,14 @@ impl HttpContext for StreamContext {
                 return Action::Continue;
             }
         };
+        self.response_body_size = body_size;
+        if output_guards {
+            self.guard_output = serde_json::from_str::<ChatCompletionsResponse>(&body_utf8)
+                .ok()
+                .and_then(|response| response.choices.into_iter().next())
+                .and_then(|choice| choice.message.content)
+                .map(|content| content.to_string());
+        }
 
         if self.streaming_response {
             debug!("streaming response");
@@ -392,15 +323,16 @@ impl HttpContext for StreamContext {
                         serde_json::Value::String(tool_call_message_str),
                     );
 
-                    let api_response_message = self.generate_api_response_message();
+                    let api_response_messages = self.generate_api_response_messages();
                     let api_response_message_str =
-                        serde_json::to_string(&api_response_message).unwrap();
+                        serde_json::to_string(&api_response_messages[0]).unwrap();
                     metadata.as_object_mut().unwrap().insert(
                         X_ARCH_API_RESPONSE.to_string(),
                         serde_json::Value::String(api_response_message_str),
                     );
 
-                    let fc_messages = vec![tool_call_message, api_response_message];
+                    let mut fc_messages = vec![tool_call_message];
+                    fc_messages.extend(api_response_messages);
 
                     let fc_messages_str = serde_json::to_string(&fc_messages).unwrap();
                     let arch_state = HashMap::from([("messages".to_string(), fc_messages_str)]);
@@ -421,12 +353,18 @@ impl HttpContext for StreamContext {
                     let data_serialized = serde_json::to_string(&data).unwrap();
                     info!("archgw <= developer: {}", data_serialized);
                     self.set_http_response_body(0, body_size, data_serialized.as_bytes());
+                    self.response_body_size = data_serialized.len();
                 };
             }
         }
 
         debug!("recv [S={}] end_stream={}", self.context_id, end_of_stream);
 
+        if output_guards && self.guard_output.is_some() {
+            self.run_guards(GuardStage::Output, 0);
+            return Action::Pause;
+        }
+
         Action::Continue
     }
 }
@@ -2,11 +2,14 @@ use filter_context::FilterContext;
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 
+mod clarification;
 mod context;
 mod filter_context;
+mod guards;
 mod http_context;
 mod metrics;
 mod stream_context;
+mod tool_cache;
 mod tools;
 
 proxy_wasm::main! {{
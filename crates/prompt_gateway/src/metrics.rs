@@ -1,14 +1,38 @@
-use common::stats::Gauge;
+use common::stats::{Counter, Gauge};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Metrics {
     pub active_http_calls: Gauge,
+    pub tool_cache_hits: Counter,
+    pub tool_cache_misses: Counter,
+    pub direct_answers: Counter,
 }
 
 impl Metrics {
     pub fn new() -> Metrics {
         Metrics {
             active_http_calls: Gauge::new(String::from("active_http_calls")),
+            tool_cache_hits: Counter::new(String::from("tool_cache_hits")),
+            tool_cache_misses: Counter::new(String::from("tool_cache_misses")),
+            direct_answers: Counter::new(String::from("arch_fc_direct_answers")),
+        }
+    }
+}
+
+/// Counters of one configured guard
+#[derive(Copy, Clone, Debug)]
+pub struct GuardMetrics {
+    pub checks: Counter,
+    pub flagged: Counter,
+    pub errors: Counter,
+}
+
+impl GuardMetrics {
+    pub fn new(guard: &str) -> GuardMetrics {
+        GuardMetrics {
+            checks: Counter::new(format!("prompt_guard_{}_checks", guard)),
+            flagged: Counter::new(format!("prompt_guard_{}_flagged", guard)),
+            errors: Counter::new(format!("prompt_guard_{}_errors", guard)),
         }
     }
 }
@@ -1,19 +1,30 @@
-use crate::metrics::Metrics;
-use crate::tools::compute_request_path_body;
+use crate::clarification::{clarification_key, clarification_prompt, PendingClarification};
+use crate::guards::{
+    guard_action, guard_exception_message, guard_fails_open, guard_request_body,
+    parse_guard_response,
+};
+use crate::metrics::{GuardMetrics, Metrics};
+use crate::tool_cache::{cache_key, ToolCache};
+use crate::tools::{compute_request_path_body, validate_tool_params, ToolParamError};
 use common::api::open_ai::{
-    to_server_events, ArchState, ChatCompletionStreamResponse, ChatCompletionsRequest,
-    ChatCompletionsResponse, ContentType, Message, ToolCall,
+    to_server_events, ArchState, ChatCompletionStreamResponse, ChatCompletionTool,
+    ChatCompletionsRequest, ChatCompletionsResponse, ContentType, Message, ToolCall,
+};
+use common::configuration::{
+    DirectAnswerPolicy, Endpoint, FunctionCallingApi, FunctionCallingConfig, GuardAction,
+    GuardConfig, GuardStage, GuardTransport, Overrides, PromptGuards, PromptTarget, Tracing,
 };
-use common::configuration::{Endpoint, Overrides, PromptTarget, Tracing};
 use common::consts::{
     API_REQUEST_TIMEOUT_MS, ARCH_FC_MODEL_NAME, ARCH_INTERNAL_CLUSTER_NAME,
-    ARCH_UPSTREAM_HOST_HEADER, ASSISTANT_ROLE, DEFAULT_TARGET_REQUEST_TIMEOUT_MS, MESSAGES_KEY,
-    REQUEST_ID_HEADER, SYSTEM_ROLE, TOOL_ROLE, TRACE_PARENT_HEADER, USER_ROLE,
-    X_ARCH_FC_MODEL_RESPONSE,
+    ARCH_PROVIDER_HINT_HEADER, ARCH_SESSION_ID_HEADER, ARCH_UPSTREAM_HOST_HEADER, ASSISTANT_ROLE,
+    CHAT_COMPLETIONS_PATH, DEFAULT_TARGET_REQUEST_TIMEOUT_MS, MESSAGES_KEY, MODEL_SERVER_NAME,
+    MODEL_SERVER_REQUEST_TIMEOUT_MS, REQUEST_ID_HEADER, SYSTEM_ROLE, TOOL_ROLE,
+    TRACE_PARENT_HEADER, USER_ROLE, X_ARCH_FC_MODEL_RESPONSE,
 };
 use common::errors::ServerError;
 use common::http::{CallArgs, Client};
-use common::stats::Gauge;
+use common::prompt_template::LabeledTemplate;
+use common::stats::{Gauge, IncrementingMetric};
 use derivative::Derivative;
 use http::StatusCode;
 use log::{debug, info, warn};
@@ -24,11 +35,21 @@ use std::rc::Rc;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+/// Tool calls dispatched at the same time unless `overrides.max_parallel_tool_calls` is set
+pub const DEFAULT_MAX_PARALLEL_TOOL_CALLS: usize = 4;
+
+/// Times Arch-Function is asked to fix invalid tool arguments unless
+/// `overrides.tool_params_validation_retries` is set
+pub const DEFAULT_TOOL_PARAMS_VALIDATION_RETRIES: u32 = 1;
+
+const DIRECT_ANSWER_REPROMPT: &str = "Do not answer the user directly. Respond with a call to one of the available tools, or ask the user for the parameters it still needs.";
+
 #[derive(Debug, Clone)]
 pub enum ResponseHandlerType {
     ArchFC,
     FunctionCall,
     DefaultTarget,
+    Guard,
 }
 
 #[derive(Clone, Derivative)]
@@ -42,11 +63,16 @@ pub struct StreamCallContext {
     pub similarity_scores: Option<Vec<(String, f64)>>,
     pub upstream_cluster: Option<String>,
     pub upstream_cluster_path: Option<String>,
+    pub tool_cache_key: Option<String>,
+    pub tool_call_index: usize,
+    pub guard_stage: Option<GuardStage>,
+    pub guard_index: usize,
 }
 
 pub struct StreamContext {
     system_prompt: Rc<Option<String>>,
     pub prompt_targets: Rc<HashMap<String, PromptTarget>>,
+    pub prompt_templates: Rc<HashMap<String, LabeledTemplate>>,
     pub endpoints: Rc<Option<HashMap<String, Endpoint>>>,
     pub overrides: Rc<Option<Overrides>>,
     pub metrics: Rc<Metrics>,
@@ -54,6 +80,10 @@ pub struct StreamContext {
     pub context_id: u32,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub tool_call_response: Option<String>,
+    /// Endpoint responses in the order of `tool_calls`, filled in as they arrive
+    pub tool_call_results: Vec<Option<String>>,
+    pub next_tool_call: usize,
+    pub tool_calls_failed: bool,
     pub arch_state: Option<Vec<ArchState>>,
     pub request_body_size: usize,
     pub user_prompt: Option<Message>,
@@ -66,28 +96,51 @@ pub struct StreamContext {
     pub traceparent: Option<String>,
     pub _tracing: Rc<Option<Tracing>>,
     pub arch_fc_response: Option<String>,
+    pub direct_answer_reprompted: bool,
+    pub tool_params_validation_attempts: u32,
+    pub session_id: Option<String>,
+    pub pending_clarification: Option<PendingClarification>,
+    pub prompt_guards: Rc<PromptGuards>,
+    pub guard_metrics: Rc<HashMap<String, GuardMetrics>>,
+    /// Model response checked by the output guards
+    pub guard_output: Option<String>,
+    pub response_body_size: usize,
+    pub tool_cache: Rc<RefCell<ToolCache>>,
+    pub tool_cache_bypass: bool,
+    pub idempotency_key: Option<String>,
+    pub function_calling: Rc<FunctionCallingConfig>,
 }
 
 impl StreamContext {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         context_id: u32,
         metrics: Rc<Metrics>,
         system_prompt: Rc<Option<String>>,
         prompt_targets: Rc<HashMap<String, PromptTarget>>,
+        prompt_templates: Rc<HashMap<String, LabeledTemplate>>,
         endpoints: Rc<Option<HashMap<String, Endpoint>>>,
         overrides: Rc<Option<Overrides>>,
         tracing: Rc<Option<Tracing>>,
+        tool_cache: Rc<RefCell<ToolCache>>,
+        prompt_guards: Rc<PromptGuards>,
+        guard_metrics: Rc<HashMap<String, GuardMetrics>>,
+        function_calling: Rc<FunctionCallingConfig>,
     ) -> Self {
         StreamContext {
             context_id,
             metrics,
             system_prompt,
             prompt_targets,
+            prompt_templates,
             endpoints,
             callouts: RefCell::new(HashMap::new()),
             chat_completions_request: None,
             tool_calls: None,
             tool_call_response: None,
+            tool_call_results: Vec::new(),
+            next_tool_call: 0,
+            tool_calls_failed: false,
             arch_state: None,
             request_body_size: 0,
             streaming_response: false,
@@ -100,6 +153,18 @@ impl StreamContext {
             start_upstream_llm_request_time: 0,
             time_to_first_token: None,
             arch_fc_response: None,
+            direct_answer_reprompted: false,
+            tool_params_validation_attempts: 0,
+            session_id: None,
+            pending_clarification: None,
+            prompt_guards,
+            guard_metrics,
+            guard_output: None,
+            response_body_size: 0,
+            tool_cache,
+            tool_cache_bypass: false,
+            idempotency_key: None,
+            function_calling,
         }
     }
 
@@ -124,6 +189,319 @@ impl StreamContext {
         }
     }
 
+    /// Runs the guards of a stage one after another, starting at `index`. The prompt (or the
+    /// response) continues once every guard let it through.
+    pub fn run_guards(&mut self, stage: GuardStage, index: usize) {
+        let Some(guard) = self
+            .prompt_guards
+            .guards_for(stage)
+            .get(index)
+            .cloned()
+            .cloned()
+        else {
+            return self.guards_passed(stage);
+        };
+
+        let input = match stage {
+            GuardStage::Input => self
+                .user_prompt
+                .as_ref()
+                .and_then(|message| message.content.as_ref())
+                .map(|content| content.to_string())
+                .unwrap_or_default(),
+            GuardStage::Output => self.guard_output.clone().unwrap_or_default(),
+        };
+        let body = guard_request_body(&guard, &input);
+        let path = guard
+            .endpoint
+            .path
+            .clone()
+            .unwrap_or_else(|| "/".to_string());
+        let timeout_ms = guard.timeout_ms.unwrap_or(API_REQUEST_TIMEOUT_MS);
+        let timeout_str = timeout_ms.to_string();
+
+        let mut headers = vec![
+            (ARCH_UPSTREAM_HOST_HEADER, guard.endpoint.name.as_str()),
+            (":method", "POST"),
+            (":path", path.as_str()),
+            (":authority", guard.endpoint.name.as_str()),
+            ("content-type", "application/json"),
+            ("x-envoy-upstream-rq-timeout-ms", timeout_str.as_str()),
+        ];
+        if guard.transport.unwrap_or_default() == GuardTransport::Mcp {
+            headers.push(("accept", "application/json, text/event-stream"));
+        }
+        if let Some(request_id) = self.request_id.as_ref() {
+            headers.push((REQUEST_ID_HEADER, request_id));
+        }
+        if let Some(http_headers) = guard.endpoint.http_headers.as_ref() {
+            for (key, value) in http_headers.iter() {
+                headers.push((key.as_str(), value.as_str()));
+            }
+        }
+
+        let call_args = CallArgs::new(
+            ARCH_INTERNAL_CLUSTER_NAME,
+            &path,
+            headers,
+            Some(body.as_bytes()),
+            vec![],
+            Duration::from_millis(timeout_ms),
+        );
+        let call_context = StreamCallContext {
+            response_handler_type: ResponseHandlerType::Guard,
+            user_message: None,
+            prompt_target_name: None,
+            request_body: self.chat_completions_request.as_ref().unwrap().clone(),
+            similarity_scores: None,
+            upstream_cluster: Some(guard.endpoint.name.clone()),
+            upstream_cluster_path: Some(path.clone()),
+            tool_cache_key: None,
+            tool_call_index: 0,
+            guard_stage: Some(stage),
+            guard_index: index,
+        };
+
+        debug!("checking {:?} with guard {}", stage, guard.name);
+        if let Err(e) = self.http_call(call_args, call_context) {
+            warn!("error dispatching request to guard {}: {}", guard.name, e);
+            self.guard_failed(&guard, stage, index, e.to_string());
+        }
+    }
+
+    pub fn guard_response_handler(&mut self, body: Vec<u8>, callout_context: StreamCallContext) {
+        let stage = callout_context.guard_stage.unwrap();
+        let index = callout_context.guard_index;
+        let guard = self.prompt_guards.guards_for(stage)[index].clone();
+        if let Some(metrics) = self.guard_metrics.get(&guard.name) {
+            metrics.checks.increment(1);
+        }
+
+        let http_status = self
+            .get_http_call_response_header(":status")
+            .unwrap_or(StatusCode::OK.as_str().to_string());
+        let verdict = match StatusCode::from_str(&http_status) {
+            Ok(status) if status.is_success() => parse_guard_response(&guard, &body),
+            _ => Err(format!("guard responded with status {}", http_status)),
+        };
+
+        match verdict {
+            Ok(verdict) if verdict.flagged => {
+                if let Some(metrics) = self.guard_metrics.get(&guard.name) {
+                    metrics.flagged.increment(1);
+                }
+                warn!(
+                    "guard {} flagged {:?}: {}",
+                    guard.name,
+                    stage,
+                    verdict.reason.as_deref().unwrap_or_default()
+                );
+                if guard_action(&guard) == GuardAction::Block {
+                    let message = guard_exception_message(&guard, verdict.reason.as_deref());
+                    return self.guard_blocked(&guard.name, stage, message);
+                }
+                self.run_guards(stage, index + 1);
+            }
+            Ok(_) => self.run_guards(stage, index + 1),
+            Err(e) => self.guard_failed(&guard, stage, index, e),
+        }
+    }
+
+    fn guard_failed(
+        &mut self,
+        guard: &GuardConfig,
+        stage: GuardStage,
+        index: usize,
+        error: String,
+    ) {
+        if let Some(metrics) = self.guard_metrics.get(&guard.name) {
+            metrics.errors.increment(1);
+        }
+        warn!("guard {} failed: {}", guard.name, error);
+        if guard_fails_open(guard) {
+            return self.run_guards(stage, index + 1);
+        }
+        self.guard_blocked(
+            &guard.name,
+            stage,
+            format!("{} guard is unavailable", guard.name),
+        );
+    }
+
+    fn guards_passed(&mut self, stage: GuardStage) {
+        match stage {
+            GuardStage::Input => {
+                let messages = self
+                    .chat_completions_request
+                    .as_ref()
+                    .unwrap()
+                    .messages
+                    .clone();
+                let user_message = self
+                    .user_prompt
+                    .as_ref()
+                    .and_then(|message| message.content.as_ref())
+                    .map(|content| content.to_string())
+                    .unwrap_or_default();
+                self.send_arch_fc_request(messages, user_message);
+            }
+            GuardStage::Output => self.resume_http_response(),
+        }
+    }
+
+    /// Rejects the prompt, or replaces the model response, with the guard's message
+    fn guard_blocked(&mut self, guard: &str, stage: GuardStage, message: String) {
+        match stage {
+            GuardStage::Input => self.send_server_error(
+                ServerError::GuardViolation {
+                    guard: guard.to_string(),
+                    message,
+                },
+                Some(StatusCode::BAD_REQUEST),
+            ),
+            GuardStage::Output => {
+                let mut response = ChatCompletionsResponse::new(message);
+                response.choices[0].finish_reason = Some("content_filter".to_string());
+                let body = serde_json::to_string(&response).unwrap();
+                self.set_http_response_body(0, self.response_body_size, body.as_bytes());
+                self.resume_http_response();
+            }
+        }
+    }
+
+    /// Sends the conversation to Arch-Function with the prompt targets as tools
+    pub fn send_arch_fc_request(&mut self, messages: Vec<Message>, user_message: String) {
+        let request_body = self.chat_completions_request.as_ref().unwrap().clone();
+
+        // convert prompt targets to ChatCompletionTool
+        let tool_calls: Vec<ChatCompletionTool> =
+            self.prompt_targets.values().map(|pt| pt.into()).collect();
+
+        let mut metadata = request_body.metadata.clone();
+
+        if let Some(overrides) = self.overrides.as_ref() {
+            if overrides.optimize_context_window.unwrap_or_default() {
+                metadata
+                    .get_or_insert_with(HashMap::new)
+                    .insert("optimize_context_window".to_string(), "true".to_string());
+            }
+            if overrides.use_agent_orchestrator.unwrap_or_default() {
+                metadata
+                    .get_or_insert_with(HashMap::new)
+                    .insert("use_agent_orchestrator".to_string(), "true".to_string());
+            }
+        }
+
+        let function_calling = Rc::clone(&self.function_calling);
+        let api = function_calling.api.unwrap_or_default();
+        let arch_fc_chat_completion_request = match api {
+            FunctionCallingApi::Arch => ChatCompletionsRequest {
+                messages,
+                metadata,
+                stream: request_body.stream,
+                model: request_body.model.clone(),
+                stream_options: request_body.stream_options.clone(),
+                tools: Some(tool_calls),
+                temperature: None,
+                max_tokens: None,
+            },
+            // the response is parsed as a whole, so plain chat completions servers are
+            // never asked to stream
+            FunctionCallingApi::OpenAi => ChatCompletionsRequest {
+                messages,
+                metadata: None,
+                stream: false,
+                model: function_calling
+                    .model
+                    .clone()
+                    .unwrap_or(ARCH_FC_MODEL_NAME.to_string()),
+                stream_options: None,
+                tools: Some(tool_calls),
+                temperature: None,
+                max_tokens: None,
+            },
+        };
+
+        let json_data = match serde_json::to_string(&arch_fc_chat_completion_request) {
+            Ok(json_data) => json_data,
+            Err(error) => {
+                return self.send_server_error(ServerError::Serialization(error), None);
+            }
+        };
+
+        let upstream = function_calling
+            .endpoint
+            .as_deref()
+            .unwrap_or(MODEL_SERVER_NAME);
+        let path = function_calling.path.as_deref().unwrap_or(match api {
+            FunctionCallingApi::Arch => "/function_calling",
+            FunctionCallingApi::OpenAi => CHAT_COMPLETIONS_PATH,
+        });
+        info!(
+            "on_http_request_body: sending request to function calling model at {}{}",
+            upstream, path
+        );
+        debug!("request body: {}", json_data);
+
+        let timeout_ms = function_calling
+            .timeout_ms
+            .unwrap_or(MODEL_SERVER_REQUEST_TIMEOUT_MS);
+        let timeout_str = timeout_ms.to_string();
+        let authorization = function_calling
+            .access_key
+            .as_ref()
+            .map(|access_key| format!("Bearer {}", access_key));
+
+        let mut headers = vec![
+            (ARCH_UPSTREAM_HOST_HEADER, upstream),
+            (":method", "POST"),
+            (":path", path),
+            ("content-type", "application/json"),
+            (":authority", upstream),
+            ("x-envoy-upstream-rq-timeout-ms", timeout_str.as_str()),
+        ];
+
+        if let Some(authorization) = authorization.as_deref() {
+            headers.push(("authorization", authorization));
+        }
+
+        if let Some(request_id) = self.request_id.as_deref() {
+            headers.push((REQUEST_ID_HEADER, request_id));
+        }
+
+        if let Some(traceparent) = self.traceparent.as_deref() {
+            headers.push((TRACE_PARENT_HEADER, traceparent));
+        }
+
+        let call_args = CallArgs::new(
+            ARCH_INTERNAL_CLUSTER_NAME,
+            path,
+            headers,
+            Some(json_data.as_bytes()),
+            vec![],
+            Duration::from_millis(timeout_ms),
+        );
+
+        let call_context = StreamCallContext {
+            response_handler_type: ResponseHandlerType::ArchFC,
+            user_message: Some(user_message),
+            prompt_target_name: None,
+            request_body,
+            similarity_scores: None,
+            upstream_cluster: Some(ARCH_INTERNAL_CLUSTER_NAME.to_string()),
+            upstream_cluster_path: Some(path.to_string()),
+            tool_cache_key: None,
+            tool_call_index: 0,
+            guard_stage: None,
+            guard_index: 0,
+        };
+
+        if let Err(e) = self.http_call(call_args, call_context) {
+            warn!("http_call failed: {:?}", e);
+            self.send_server_error(ServerError::HttpDispatch(e), None);
+        }
+    }
+
     pub fn arch_fc_response_handler(
         &mut self,
         body: Vec<u8>,
@@ -159,53 +537,10 @@ impl StreamContext {
                 .prompt_targets
                 .values()
                 .find(|pt| pt.default.unwrap_or(false))
+                .cloned()
             {
                 info!("default prompt target found, forwarding request to default prompt target");
-                let endpoint = default_prompt_target.endpoint.clone().unwrap();
-                let upstream_path: String = endpoint.path.unwrap_or(String::from("/"));
-
-                let upstream_endpoint = endpoint.name;
-                let mut params = HashMap::new();
-                params.insert(
-                    MESSAGES_KEY.to_string(),
-                    callout_context.request_body.messages.clone(),
-                );
-                let arch_messages_json = serde_json::to_string(&params).unwrap();
-                let timeout_str = DEFAULT_TARGET_REQUEST_TIMEOUT_MS.to_string();
-
-                let mut headers = vec![
-                    (":method", "POST"),
-                    (ARCH_UPSTREAM_HOST_HEADER, &upstream_endpoint),
-                    (":path", &upstream_path),
-                    (":authority", &upstream_endpoint),
-                    ("content-type", "application/json"),
-                    ("x-envoy-max-retries", "3"),
-                    ("x-envoy-upstream-rq-timeout-ms", timeout_str.as_str()),
-                ];
-
-                if self.request_id.is_some() {
-                    headers.push((REQUEST_ID_HEADER, self.request_id.as_ref().unwrap()));
-                }
-
-                let call_args = CallArgs::new(
-                    ARCH_INTERNAL_CLUSTER_NAME,
-                    &upstream_path,
-                    headers,
-                    Some(arch_messages_json.as_bytes()),
-                    vec![],
-                    Duration::from_secs(5),
-                );
-                callout_context.response_handler_type = ResponseHandlerType::DefaultTarget;
-                callout_context.prompt_target_name = Some(default_prompt_target.name.clone());
-
-                if let Err(e) = self.http_call(call_args, callout_context) {
-                    warn!("error dispatching default prompt target request: {}", e);
-                    return self.send_server_error(
-                        ServerError::HttpDispatch(e),
-                        Some(StatusCode::BAD_REQUEST),
-                    );
-                }
-                return;
+                return self.dispatch_default_target(default_prompt_target, callout_context);
             } else {
                 info!("no default prompt target found, forwarding request to upstream llm");
                 let mut messages = Vec::new();
@@ -241,6 +576,8 @@ impl StreamContext {
                     stream: callout_context.request_body.stream,
                     stream_options: callout_context.request_body.stream_options,
                     metadata: None,
+                    temperature: None,
+                    max_tokens: None,
                 };
 
                 let chat_completion_request_json =
@@ -270,15 +607,61 @@ impl StreamContext {
             // Let's send the response back to the user to initialize lightweight dialog for parameter collection
 
             //TODO: add resolver name to the response so the client can send the response back to the correct resolver
+            self.tool_calls = None;
+            self.metrics.direct_answers.increment(1);
 
-            let direct_response_str = if self.streaming_response {
-                let content = model_server_response.choices[0]
-                    .message
-                    .content
-                    .as_ref()
-                    .unwrap()
-                    .clone();
+            let content = model_server_response.choices[0]
+                .message
+                .content
+                .as_ref()
+                .map(|content| content.to_string())
+                .unwrap_or_default();
+
+            let policy = self.direct_answer_policy(&callout_context);
+            info!(
+                "arch-function answered without tool calls, policy: {:?}",
+                policy
+            );
+            match policy {
+                DirectAnswerPolicy::PassThrough => {}
+                DirectAnswerPolicy::Reprompt if self.direct_answer_reprompted => {
+                    info!("arch-function was already reprompted, passing the answer through");
+                }
+                DirectAnswerPolicy::Reprompt => {
+                    self.direct_answer_reprompted = true;
+                    let mut messages = callout_context.request_body.messages.clone();
+                    messages.push(Message {
+                        role: ASSISTANT_ROLE.to_string(),
+                        content: Some(ContentType::Text(content)),
+                        model: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    });
+                    messages.push(Message {
+                        role: SYSTEM_ROLE.to_string(),
+                        content: Some(ContentType::Text(DIRECT_ANSWER_REPROMPT.to_string())),
+                        model: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    });
+                    let user_message = callout_context.user_message.unwrap_or_default();
+                    return self.send_arch_fc_request(messages, user_message);
+                }
+                DirectAnswerPolicy::DefaultTarget => {
+                    if let Some(default_prompt_target) = self
+                        .prompt_targets
+                        .values()
+                        .find(|pt| pt.default.unwrap_or(false))
+                        .cloned()
+                    {
+                        return self
+                            .dispatch_default_target(default_prompt_target, callout_context);
+                    }
+                    warn!("no default prompt target found, passing the answer through");
+                }
+            }
 
+            let direct_response_str = if self.streaming_response {
                 let chunks = vec![
                     ChatCompletionStreamResponse::new(
                         self.arch_fc_response.clone(),
@@ -287,7 +670,7 @@ impl StreamContext {
                         None,
                     ),
                     ChatCompletionStreamResponse::new(
-                        Some(content.to_string()),
+                        Some(content),
                         None,
                         Some(format!("{}-Chat", ARCH_FC_MODEL_NAME.to_owned())),
                         None,
@@ -299,7 +682,6 @@ impl StreamContext {
                 body_str
             };
 
-            self.tool_calls = None;
             return self.send_http_response(
                 StatusCode::OK.as_u16().into(),
                 vec![],
@@ -308,13 +690,6 @@ impl StreamContext {
         }
 
         // At this point, we know tool_calls is not None and not empty
-        if self.tool_calls.as_ref().unwrap().len() > 1 {
-            warn!(
-                "multiple tool calls not supported yet, tool_calls count found: {}",
-                self.tool_calls.as_ref().unwrap().len()
-            );
-        }
-
         // update prompt target name from the tool call response
         callout_context.prompt_target_name =
             Some(self.tool_calls.as_ref().unwrap()[0].function.name.clone());
@@ -354,6 +729,8 @@ impl StreamContext {
                     stream: callout_context.request_body.stream,
                     stream_options: callout_context.request_body.stream_options.clone(),
                     metadata: Some(metadata),
+                    temperature: None,
+                    max_tokens: None,
                 };
 
                 let body_str = serde_json::to_string(&chat_completion_request).unwrap();
@@ -364,15 +741,351 @@ impl StreamContext {
             }
         }
 
-        self.schedule_api_call_request(callout_context);
+        if let Err(errors) = self.validate_tool_calls() {
+            let clarify_missing_params = (*self.overrides)
+                .as_ref()
+                .and_then(|overrides| overrides.clarify_missing_params)
+                .unwrap_or(true);
+            let only_missing_params = errors
+                .iter()
+                .all(|(_, error)| matches!(error, ToolParamError::MissingRequired(_)));
+            if clarify_missing_params && only_missing_params {
+                return self.ask_for_missing_params(errors, callout_context);
+            }
+            return self.tool_params_validation_failed(errors, callout_context);
+        }
+        self.clear_pending_clarification();
+
+        self.schedule_tool_calls(callout_context);
+    }
+
+    /// Validates and coerces the arguments of every tool call against its prompt target
+    /// Arguments collected in earlier turns of the conversation are filled in first.
+    fn validate_tool_calls(&mut self) -> Result<(), Vec<(String, ToolParamError)>> {
+        let mut errors = Vec::new();
+        for tool_call in self.tool_calls.as_mut().unwrap().iter_mut() {
+            let Some(prompt_target) = self.prompt_targets.get(&tool_call.function.name) else {
+                errors.push((tool_call.function.name.clone(), ToolParamError::UnknownTool));
+                continue;
+            };
+            if let Some(pending) = self
+                .pending_clarification
+                .as_ref()
+                .filter(|pending| pending.prompt_target == prompt_target.name)
+            {
+                tool_call.function.arguments =
+                    pending.merge_arguments(&tool_call.function.arguments);
+            }
+            match validate_tool_params(
+                &tool_call.function.arguments,
+                prompt_target.parameters.as_deref().unwrap_or_default(),
+            ) {
+                Ok(arguments) => tool_call.function.arguments = arguments,
+                Err(tool_errors) => errors.extend(
+                    tool_errors
+                        .into_iter()
+                        .map(|error| (tool_call.function.name.clone(), error)),
+                ),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    fn schedule_api_call_request(&mut self, mut callout_context: StreamCallContext) {
+    /// Has the llm ask the user for the required parameters missing from a tool call. The
+    /// collected arguments are kept for the conversation so the call can resume on the reply.
+    fn ask_for_missing_params(
+        &mut self,
+        errors: Vec<(String, ToolParamError)>,
+        callout_context: StreamCallContext,
+    ) {
+        let prompt_target_name = errors[0].0.clone();
+        let missing: Vec<String> = errors
+            .into_iter()
+            .filter(|(tool, _)| *tool == prompt_target_name)
+            .filter_map(|(_, error)| match error {
+                ToolParamError::MissingRequired(param) => Some(param),
+                _ => None,
+            })
+            .collect();
+        let prompt_target = self
+            .prompt_targets
+            .get(&prompt_target_name)
+            .unwrap()
+            .clone();
+        info!(
+            "asking user for missing parameters of prompt target {}: {:?}",
+            prompt_target_name, missing
+        );
+
+        let arguments = self
+            .tool_calls
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|tool_call| tool_call.function.name == prompt_target_name)
+            .map(|tool_call| tool_call.function.arguments.clone())
+            .unwrap_or_default();
+        let pending = PendingClarification::new(&prompt_target_name, &arguments, missing.clone());
+        match self.session_id.as_ref() {
+            Some(session_id) => {
+                let data = serde_json::to_vec(&pending).unwrap();
+                if let Err(status) =
+                    self.set_shared_data(&clarification_key(session_id), Some(&data), None)
+                {
+                    warn!("failed to store pending clarification: {:?}", status);
+                }
+            }
+            None => debug!(
+                "no {} header, arguments collected so far are not kept",
+                ARCH_SESSION_ID_HEADER
+            ),
+        }
+        self.tool_calls = None;
+
+        let mut messages = vec![Message {
+            role: SYSTEM_ROLE.to_string(),
+            content: Some(ContentType::Text(clarification_prompt(
+                &prompt_target,
+                &missing,
+            ))),
+            model: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }];
+        messages.append(
+            &mut self.filter_out_arch_messages(callout_context.request_body.messages.as_ref()),
+        );
+
+        let chat_completion_request = ChatCompletionsRequest {
+            model: callout_context.request_body.model,
+            messages,
+            tools: None,
+            stream: callout_context.request_body.stream,
+            stream_options: callout_context.request_body.stream_options,
+            metadata: None,
+            temperature: None,
+            max_tokens: None,
+        };
+        let llm_request_str = match serde_json::to_string(&chat_completion_request) {
+            Ok(json_string) => json_string,
+            Err(e) => {
+                return self.send_server_error(ServerError::Serialization(e), None);
+            }
+        };
+        debug!("clarification request body: {}", llm_request_str);
+        self.set_http_request_body(0, self.request_body_size, llm_request_str.as_bytes());
+        self.resume_http_request();
+    }
+
+    pub fn load_pending_clarification(&mut self) {
+        let Some(session_id) = self.session_id.as_ref() else {
+            return;
+        };
+        if let (Some(data), _) = self.get_shared_data(&clarification_key(session_id)) {
+            self.pending_clarification = PendingClarification::from_shared_data(&data);
+        }
+    }
+
+    fn clear_pending_clarification(&mut self) {
+        if self.pending_clarification.take().is_none() {
+            return;
+        }
+        if let Some(session_id) = self.session_id.as_ref() {
+            if let Err(status) = self.set_shared_data(&clarification_key(session_id), None, None) {
+                warn!("failed to clear pending clarification: {:?}", status);
+            }
+        }
+    }
+
+    /// Asks Arch-Function to correct invalid tool arguments, up to
+    /// `overrides.tool_params_validation_retries` times, then rejects the request
+    fn tool_params_validation_failed(
+        &mut self,
+        errors: Vec<(String, ToolParamError)>,
+        callout_context: StreamCallContext,
+    ) {
+        let errors: Vec<String> = errors
+            .iter()
+            .map(|(tool, error)| format!("{}: {}", tool, error))
+            .collect();
+        let retries = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.tool_params_validation_retries)
+            .unwrap_or(DEFAULT_TOOL_PARAMS_VALIDATION_RETRIES);
+        warn!(
+            "tool call arguments failed validation (attempt {} of {}): {:?}",
+            self.tool_params_validation_attempts + 1,
+            retries + 1,
+            errors
+        );
+
+        if self.tool_params_validation_attempts >= retries {
+            self.tool_calls = None;
+            return self.send_server_error(
+                ServerError::BadRequest {
+                    why: format!("invalid tool call arguments: {}", errors.join("; ")),
+                },
+                Some(StatusCode::BAD_REQUEST),
+            );
+        }
+        self.tool_params_validation_attempts += 1;
+
+        let mut messages = callout_context.request_body.messages.clone();
+        messages.push(Message {
+            role: SYSTEM_ROLE.to_string(),
+            content: Some(ContentType::Text(format!(
+                "The arguments of your previous tool call were invalid: {}. Call the tool again with corrected arguments.",
+                errors.join("; ")
+            ))),
+            model: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+        self.tool_calls = None;
+        let user_message = callout_context.user_message.unwrap_or_default();
+        self.send_arch_fc_request(messages, user_message);
+    }
+
+    /// Dispatches the tool calls returned by Arch-Function to their endpoints, keeping at most
+    /// `max_parallel_tool_calls` in flight. Results are collected in tool call order.
+    fn schedule_tool_calls(&mut self, callout_context: StreamCallContext) {
+        let tool_calls_count = self
+            .tool_calls
+            .as_ref()
+            .map_or(0, |tool_calls| tool_calls.len());
+        let max_parallel_tool_calls = (*self.overrides)
+            .as_ref()
+            .and_then(|overrides| overrides.max_parallel_tool_calls)
+            .unwrap_or(DEFAULT_MAX_PARALLEL_TOOL_CALLS)
+            .max(1);
+        info!(
+            "dispatching {} tool calls, at most {} at a time",
+            tool_calls_count, max_parallel_tool_calls
+        );
+
+        self.tool_call_results = vec![None; tool_calls_count];
+        self.next_tool_call = 0;
+        for _ in 0..max_parallel_tool_calls.min(tool_calls_count) {
+            self.schedule_next_tool_call(callout_context.clone());
+        }
+    }
+
+    fn schedule_next_tool_call(&mut self, callout_context: StreamCallContext) {
+        let index = self.next_tool_call;
+        if self.tool_calls_failed || index >= self.tool_call_results.len() {
+            return;
+        }
+        self.next_tool_call += 1;
+        self.schedule_api_call_request(callout_context, index);
+    }
+
+    fn fail_tool_calls(&mut self, error: ServerError, override_status_code: Option<StatusCode>) {
+        // responses of the tool calls still in flight are dropped
+        self.tool_calls_failed = true;
+        self.send_server_error(error, override_status_code);
+    }
+
+    /// Policy for a direct answer: the one of the prompt target last called in the
+    /// conversation (or the only prompt target), then the overrides, then pass through
+    fn direct_answer_policy(&self, callout_context: &StreamCallContext) -> DirectAnswerPolicy {
+        let last_called_target = callout_context
+            .request_body
+            .messages
+            .iter()
+            .rev()
+            .filter_map(|message| message.tool_calls.as_ref())
+            .find_map(|tool_calls| {
+                tool_calls
+                    .iter()
+                    .find_map(|tool_call| self.prompt_targets.get(&tool_call.function.name))
+            });
+        let prompt_target = match last_called_target {
+            Some(prompt_target) => Some(prompt_target),
+            None if self.prompt_targets.len() == 1 => self.prompt_targets.values().next(),
+            None => None,
+        };
+
+        prompt_target
+            .and_then(|prompt_target| prompt_target.on_direct_answer)
+            .or_else(|| {
+                (*self.overrides)
+                    .as_ref()
+                    .and_then(|overrides| overrides.on_direct_answer)
+            })
+            .unwrap_or_default()
+    }
+
+    fn dispatch_default_target(
+        &mut self,
+        default_prompt_target: PromptTarget,
+        mut callout_context: StreamCallContext,
+    ) {
+        let endpoint = default_prompt_target.endpoint.clone().unwrap();
+        let upstream_path: String = endpoint.path.unwrap_or(String::from("/"));
+
+        let upstream_endpoint = endpoint.name;
+        let mut params = HashMap::new();
+        params.insert(
+            MESSAGES_KEY.to_string(),
+            callout_context.request_body.messages.clone(),
+        );
+        let arch_messages_json = serde_json::to_string(&params).unwrap();
+        let timeout_str = DEFAULT_TARGET_REQUEST_TIMEOUT_MS.to_string();
+
+        let mut headers = vec![
+            (":method", "POST"),
+            (ARCH_UPSTREAM_HOST_HEADER, &upstream_endpoint),
+            (":path", &upstream_path),
+            (":authority", &upstream_endpoint),
+            ("content-type", "application/json"),
+            ("x-envoy-max-retries", "3"),
+            ("x-envoy-upstream-rq-timeout-ms", timeout_str.as_str()),
+        ];
+
+        if let Some(request_id) = self.request_id.as_deref() {
+            headers.push((REQUEST_ID_HEADER, request_id));
+        }
+
+        let call_args = CallArgs::new(
+            ARCH_INTERNAL_CLUSTER_NAME,
+            &upstream_path,
+            headers,
+            Some(arch_messages_json.as_bytes()),
+            vec![],
+            Duration::from_secs(5),
+        );
+        callout_context.response_handler_type = ResponseHandlerType::DefaultTarget;
+        callout_context.prompt_target_name = Some(default_prompt_target.name.clone());
+
+        if let Err(e) = self.http_call(call_args, callout_context) {
+            warn!("error dispatching default prompt target request: {}", e);
+            self.send_server_error(ServerError::HttpDispatch(e), Some(StatusCode::BAD_REQUEST));
+        }
+    }
+
+    fn schedule_api_call_request(&mut self, mut callout_context: StreamCallContext, index: usize) {
         // Construct messages early to avoid mutable borrow conflicts
 
-        let tools_call_name = self.tool_calls.as_ref().unwrap()[0].function.name.clone();
-        let prompt_target = self.prompt_targets.get(&tools_call_name).unwrap().clone();
-        let tool_params_str = &self.tool_calls.as_ref().unwrap()[0].function.arguments;
+        let tool_call = self.tool_calls.as_ref().unwrap()[index].clone();
+        let prompt_target = match self.prompt_targets.get(&tool_call.function.name) {
+            Some(prompt_target) => prompt_target.clone(),
+            None => {
+                return self.fail_tool_calls(
+                    ServerError::BadRequest {
+                        why: format!("unknown prompt target: {}", tool_call.function.name),
+                    },
+                    Some(StatusCode::BAD_REQUEST),
+                );
+            }
+        };
+        let tool_params_str = &tool_call.function.arguments;
+        callout_context.prompt_target_name = Some(prompt_target.name.clone());
+        callout_context.tool_call_index = index;
 
         // Parse arguments JSON string into HashMap
         // Note: convert from serde_json::Value to serde_yaml::Value for compatibility
@@ -411,7 +1124,7 @@ impl StreamContext {
         ) {
             Ok((path, body)) => (path, body),
             Err(e) => {
-                return self.send_server_error(
+                return self.fail_tool_calls(
                     ServerError::BadRequest {
                         why: format!("error computing api request path or body: {}", e),
                     },
@@ -422,6 +1135,32 @@ impl StreamContext {
 
         debug!("on_http_call_response: api call body {:?}", api_call_body);
 
+        if prompt_target.tool_cache.is_some() {
+            let key = cache_key(
+                &http_method.to_string(),
+                &path,
+                api_call_body.as_deref(),
+                self.idempotency_key.as_deref(),
+            );
+            // a bypassed lookup still refreshes the cached result once the call returns
+            if !self.tool_cache_bypass {
+                let cached =
+                    self.tool_cache
+                        .borrow_mut()
+                        .get(&prompt_target.name, &key, SystemTime::now());
+                if let Some(cached) = cached {
+                    self.metrics.tool_cache_hits.increment(1);
+                    info!(
+                        "on_http_call_response: using cached response for prompt target: {}",
+                        prompt_target.name
+                    );
+                    return self.tool_call_result_received(cached, callout_context);
+                }
+                self.metrics.tool_cache_misses.increment(1);
+            }
+            callout_context.tool_cache_key = Some(key);
+        }
+
         let timeout_str = API_REQUEST_TIMEOUT_MS.to_string();
 
         let http_method_str = http_method.to_string();
@@ -470,11 +1209,19 @@ impl StreamContext {
         callout_context.response_handler_type = ResponseHandlerType::FunctionCall;
 
         if let Err(e) = self.http_call(call_args, callout_context) {
-            self.send_server_error(ServerError::HttpDispatch(e), Some(StatusCode::BAD_REQUEST));
+            self.fail_tool_calls(ServerError::HttpDispatch(e), Some(StatusCode::BAD_REQUEST));
         }
     }
 
-    pub fn api_call_response_handler(&mut self, body: Vec<u8>, callout_context: StreamCallContext) {
+    pub fn api_call_response_handler(
+        &mut self,
+        body: Vec<u8>,
+        mut callout_context: StreamCallContext,
+    ) {
+        if self.tool_calls_failed {
+            debug!("dropping api call response, another tool call failed");
+            return;
+        }
         let http_status = self
             .get_http_call_response_header(":status")
             .unwrap_or(StatusCode::OK.as_str().to_string());
@@ -482,17 +1229,12 @@ impl StreamContext {
             "on_http_call_response: developer api call response received: status code: {}",
             http_status
         );
-        let prompt_target = self
-            .prompt_targets
-            .get(callout_context.prompt_target_name.as_ref().unwrap())
-            .unwrap()
-            .clone();
         if http_status != StatusCode::OK.as_str() {
             warn!(
                 "api server responded with non 2xx status code: {}",
                 http_status
             );
-            return self.send_server_error(
+            return self.fail_tool_calls(
                 ServerError::Upstream {
                     host: callout_context.upstream_cluster.unwrap(),
                     path: callout_context.upstream_cluster_path.unwrap(),
@@ -502,7 +1244,60 @@ impl StreamContext {
                 Some(StatusCode::from_str(http_status.as_str()).unwrap()),
             );
         }
-        self.tool_call_response = Some(String::from_utf8(body).unwrap());
+        let tool_call_response = String::from_utf8(body).unwrap();
+
+        if let Some(key) = callout_context.tool_cache_key.take() {
+            let prompt_target_name = callout_context.prompt_target_name.as_ref().unwrap();
+            if let Some(tool_cache) = self
+                .prompt_targets
+                .get(prompt_target_name)
+                .and_then(|prompt_target| prompt_target.tool_cache.as_ref())
+            {
+                self.tool_cache.borrow_mut().insert(
+                    prompt_target_name,
+                    key,
+                    tool_call_response.clone(),
+                    tool_cache,
+                    SystemTime::now(),
+                );
+            }
+        }
+
+        self.tool_call_result_received(tool_call_response, callout_context);
+    }
+
+    /// Records the endpoint response of a tool call, either fresh or from the tool cache, and
+    /// continues once all tool calls have completed
+    fn tool_call_result_received(
+        &mut self,
+        tool_call_response: String,
+        mut callout_context: StreamCallContext,
+    ) {
+        self.tool_call_results[callout_context.tool_call_index] = Some(tool_call_response);
+        if self.tool_call_results.iter().any(Option::is_none) {
+            return self.schedule_next_tool_call(callout_context);
+        }
+
+        let tool_calls = self.tool_calls.as_ref().unwrap();
+        let results: Vec<String> = self.tool_call_results.iter().flatten().cloned().collect();
+        let tool_call_response = combine_tool_call_responses(tool_calls, &results);
+        // the first tool call decides the system prompt and whether to summarize with the llm
+        callout_context.prompt_target_name = Some(tool_calls[0].function.name.clone());
+        self.tool_call_response_handler(tool_call_response, callout_context);
+    }
+
+    /// Continues with the combined endpoint responses of the tool calls
+    fn tool_call_response_handler(
+        &mut self,
+        tool_call_response: String,
+        callout_context: StreamCallContext,
+    ) {
+        let prompt_target = self
+            .prompt_targets
+            .get(callout_context.prompt_target_name.as_ref().unwrap())
+            .unwrap()
+            .clone();
+        self.tool_call_response = Some(tool_call_response);
         debug!(
             "response body: {}",
             self.tool_call_response.as_ref().unwrap()
@@ -570,13 +1365,16 @@ impl StreamContext {
             }
         });
 
+        let llm = prompt_target.llm.clone().unwrap_or_default();
         let chat_completions_request: ChatCompletionsRequest = ChatCompletionsRequest {
-            model: callout_context.request_body.model,
+            model: llm.model.unwrap_or(callout_context.request_body.model),
             messages,
             tools: None,
             stream: callout_context.request_body.stream,
             stream_options: callout_context.request_body.stream_options,
             metadata: None,
+            temperature: llm.temperature.or(callout_context.request_body.temperature),
+            max_tokens: llm.max_tokens.or(callout_context.request_body.max_tokens),
         };
 
         let llm_request_str = match serde_json::to_string(&chat_completions_request) {
@@ -593,17 +1391,66 @@ impl StreamContext {
             .unwrap()
             .as_nanos();
 
+        // route the summarization to the provider of the prompt target
+        if let Some(provider) = llm.provider.as_deref() {
+            debug!(
+                "routing prompt target response to llm provider: {}",
+                provider
+            );
+            self.set_http_request_header(ARCH_PROVIDER_HINT_HEADER, Some(provider));
+        }
+
         self.set_http_request_body(0, self.request_body_size, &llm_request_str.into_bytes());
         self.resume_http_request();
     }
 
-    fn get_system_prompt(&self, prompt_target: Option<PromptTarget>) -> Option<String> {
+    fn get_system_prompt(
+        &self,
+        prompt_target: Option<PromptTarget>,
+        request: &ChatCompletionsRequest,
+    ) -> Option<String> {
         match prompt_target {
             None => self.system_prompt.as_ref().clone(),
-            Some(prompt_target) => match prompt_target.system_prompt {
-                None => self.system_prompt.as_ref().clone(),
-                Some(system_prompt) => Some(system_prompt),
-            },
+            Some(prompt_target) => self
+                .prompt_target_system_prompt(&prompt_target, request)
+                .or_else(|| self.system_prompt.as_ref().clone()),
+        }
+    }
+
+    /// Rendered prompt template of the prompt target, or its system_prompt when it has
+    /// no template or the template can't be rendered
+    fn prompt_target_system_prompt(
+        &self,
+        prompt_target: &PromptTarget,
+        request: &ChatCompletionsRequest,
+    ) -> Option<String> {
+        let Some(name) = prompt_target.prompt_template.as_ref() else {
+            return prompt_target.system_prompt.clone();
+        };
+        let Some(labeled) = self.prompt_templates.get(name) else {
+            warn!(
+                "prompt target {} uses undefined prompt template {}",
+                prompt_target.name, name
+            );
+            return prompt_target.system_prompt.clone();
+        };
+        let mut variables = request.metadata.clone().unwrap_or_default();
+        variables.insert("model".to_string(), request.model.clone());
+        match labeled.template.render(&variables) {
+            Ok(system_prompt) => {
+                info!(
+                    "prompt target {} using prompt template {}",
+                    prompt_target.name, labeled.label
+                );
+                Some(system_prompt)
+            }
+            Err(err) => {
+                warn!(
+                    "prompt template {} of prompt target {} failed: {}",
+                    labeled.label, prompt_target.name, err
+                );
+                prompt_target.system_prompt.clone()
+            }
         }
     }
 
@@ -625,9 +1472,10 @@ impl StreamContext {
         // add system prompt
         let system_prompt = match callout_context.prompt_target_name.as_ref() {
             None => self.system_prompt.as_ref().clone(),
-            Some(prompt_target_name) => {
-                self.get_system_prompt(self.prompt_targets.get(prompt_target_name).cloned())
-            }
+            Some(prompt_target_name) => self.get_system_prompt(
+                self.prompt_targets.get(prompt_target_name).cloned(),
+                &callout_context.request_body,
+            ),
         };
 
         if let Some(system_prompt_text) = system_prompt {
@@ -670,16 +1518,32 @@ impl StreamContext {
         }
     }
 
-    pub fn generate_api_response_message(&mut self) -> Message {
-        Message {
-            role: TOOL_ROLE.to_string(),
-            content: Some(ContentType::Text(
-                self.tool_call_response.as_ref().unwrap().clone(),
-            )),
-            model: None,
-            tool_calls: None,
-            tool_call_id: Some(self.tool_calls.as_ref().unwrap()[0].id.clone()),
+    /// One tool message per tool call, in tool call order
+    pub fn generate_api_response_messages(&mut self) -> Vec<Message> {
+        let tool_calls = self.tool_calls.as_ref().unwrap();
+        if self.tool_call_results.len() != tool_calls.len() {
+            return vec![Message {
+                role: TOOL_ROLE.to_string(),
+                content: Some(ContentType::Text(
+                    self.tool_call_response.as_ref().unwrap().clone(),
+                )),
+                model: None,
+                tool_calls: None,
+                tool_call_id: Some(tool_calls[0].id.clone()),
+            }];
         }
+
+        tool_calls
+            .iter()
+            .zip(self.tool_call_results.iter())
+            .map(|(tool_call, result)| Message {
+                role: TOOL_ROLE.to_string(),
+                content: Some(ContentType::Text(result.clone().unwrap_or_default())),
+                model: None,
+                tool_calls: None,
+                tool_call_id: Some(tool_call.id.clone()),
+            })
+            .collect()
     }
 
     pub fn default_target_handler(&self, body: Vec<u8>, mut callout_context: StreamCallContext) {
@@ -754,12 +1618,12 @@ impl StreamContext {
 
         let mut messages = Vec::new();
         // add system prompt
-        match prompt_target.system_prompt.as_ref() {
+        match self.prompt_target_system_prompt(&prompt_target, &callout_context.request_body) {
             None => {}
             Some(system_prompt) => {
                 let system_prompt_message = Message {
                     role: SYSTEM_ROLE.to_string(),
-                    content: Some(ContentType::Text(system_prompt.clone())),
+                    content: Some(ContentType::Text(system_prompt)),
                     model: None,
                     tool_calls: None,
                     tool_call_id: None,
@@ -798,6 +1662,8 @@ impl StreamContext {
             stream: callout_context.request_body.stream,
             stream_options: callout_context.request_body.stream_options,
             metadata: None,
+            temperature: None,
+            max_tokens: None,
         };
 
         let json_resp = serde_json::to_string(&chat_completion_request).unwrap();
@@ -807,6 +1673,20 @@ impl StreamContext {
     }
 }
 
+/// Context passed to the llm for the tool call results. A single result is passed as is,
+/// multiple results are labeled with their tool name and kept in tool call order.
+fn combine_tool_call_responses(tool_calls: &[ToolCall], results: &[String]) -> String {
+    if results.len() == 1 {
+        return results[0].clone();
+    }
+    tool_calls
+        .iter()
+        .zip(results)
+        .map(|(tool_call, result)| format!("{}: {}", tool_call.function.name, result))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 fn check_intent_matched(model_server_response: &ChatCompletionsResponse) -> bool {
     let content = model_server_response
         .choices
@@ -841,7 +1721,7 @@ impl Client for StreamContext {
 mod test {
     use common::api::open_ai::{ChatCompletionsResponse, Choice, ContentType, Message, ToolCall};
 
-    use crate::stream_context::check_intent_matched;
+    use crate::stream_context::{check_intent_matched, combine_tool_call_responses};
 
     #[test]
     fn test_intent_matched() {
@@ -909,4 +1789,26 @@ mod test {
 
         assert!(check_intent_matched(&model_server_response));
     }
+
+    #[test]
+    fn test_combine_tool_call_responses_keeps_order() {
+        let tool_call = |id: &str, name: &str| ToolCall {
+            id: id.to_string(),
+            function: common::api::open_ai::FunctionCallDetail {
+                name: name.to_string(),
+                arguments: "{}".to_string(),
+            },
+            tool_type: common::api::open_ai::ToolType::Function,
+        };
+        let tool_calls = vec![tool_call("1", "get_weather"), tool_call("2", "get_time")];
+
+        assert_eq!(
+            combine_tool_call_responses(&tool_calls[..1], &["sunny".to_string()]),
+            "sunny"
+        );
+        assert_eq!(
+            combine_tool_call_responses(&tool_calls, &["sunny".to_string(), "noon".to_string()]),
+            "get_weather: sunny\nget_time: noon"
+        );
+    }
 }
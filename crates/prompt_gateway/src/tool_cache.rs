@@ -0,0 +1,155 @@
+use common::configuration::ToolCacheConfig;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_TOOL_CACHE_MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone)]
+struct CachedToolResult {
+    body: String,
+    inserted_at: SystemTime,
+    expires_at: SystemTime,
+}
+
+/// Responses of prompt target endpoints, kept per prompt target. Shared by all
+/// streams of a worker through the filter context.
+#[derive(Debug, Default)]
+pub struct ToolCache {
+    targets: HashMap<String, HashMap<String, CachedToolResult>>,
+}
+
+/// Identifies a tool call. An explicit idempotency key replaces the request itself,
+/// otherwise the same method, path and body are considered the same call.
+pub fn cache_key(
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+    idempotency_key: Option<&str>,
+) -> String {
+    match idempotency_key {
+        Some(idempotency_key) => format!("idempotency-key:{}", idempotency_key),
+        None => format!("{} {}\n{}", method, path, body.unwrap_or_default()),
+    }
+}
+
+impl ToolCache {
+    pub fn get(&mut self, prompt_target: &str, key: &str, now: SystemTime) -> Option<String> {
+        let entries = self.targets.get_mut(prompt_target)?;
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > now => Some(entry.body.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        prompt_target: &str,
+        key: String,
+        body: String,
+        config: &ToolCacheConfig,
+        now: SystemTime,
+    ) {
+        let entries = self.targets.entry(prompt_target.to_string()).or_default();
+        entries.retain(|_, entry| entry.expires_at > now);
+
+        let max_entries = config
+            .max_entries
+            .unwrap_or(DEFAULT_TOOL_CACHE_MAX_ENTRIES)
+            .max(1);
+        while entries.len() >= max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.inserted_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+
+        entries.insert(
+            key,
+            CachedToolResult {
+                body,
+                inserted_at: now,
+                expires_at: now + Duration::from_secs(config.ttl_seconds),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(ttl_seconds: u64, max_entries: Option<usize>) -> ToolCacheConfig {
+        ToolCacheConfig {
+            ttl_seconds,
+            max_entries,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(
+            cache_key("GET", "/weather?city=seattle", None, None),
+            cache_key("GET", "/weather?city=seattle", None, None)
+        );
+        assert_ne!(
+            cache_key("POST", "/weather", Some(r#"{"city":"seattle"}"#), None),
+            cache_key("POST", "/weather", Some(r#"{"city":"boston"}"#), None)
+        );
+        assert_eq!(
+            cache_key("POST", "/refund", Some(r#"{"a":1}"#), Some("abc")),
+            cache_key("POST", "/refund", Some(r#"{"a":2}"#), Some("abc"))
+        );
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let mut cache = ToolCache::default();
+        let now = SystemTime::now();
+        cache.insert(
+            "weather",
+            "key".to_string(),
+            "sunny".to_string(),
+            &config(10, None),
+            now,
+        );
+
+        assert_eq!(
+            cache.get("weather", "key", now + Duration::from_secs(5)),
+            Some("sunny".to_string())
+        );
+        assert_eq!(cache.get("other_target", "key", now), None);
+        assert_eq!(
+            cache.get("weather", "key", now + Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(cache.get("weather", "key", now), None);
+    }
+
+    #[test]
+    fn test_oldest_entry_is_evicted_when_full() {
+        let mut cache = ToolCache::default();
+        let now = SystemTime::now();
+        let config = config(60, Some(2));
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            cache.insert(
+                "weather",
+                key.to_string(),
+                key.to_string(),
+                &config,
+                now + Duration::from_secs(i as u64),
+            );
+        }
+
+        assert_eq!(cache.get("weather", "a", now), None);
+        assert_eq!(cache.get("weather", "b", now), Some("b".to_string()));
+        assert_eq!(cache.get("weather", "c", now), Some("c".to_string()));
+    }
+}
@@ -1,3 +1,4 @@
+use common::api::open_ai::ParameterType;
 use common::configuration::{HttpMethod, Parameter};
 use std::collections::HashMap;
 
@@ -58,6 +59,141 @@ pub fn compute_request_path_body(
     Ok((path, body))
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ToolParamError {
+    #[error("arguments must be a json object, got: {0}")]
+    InvalidArguments(String),
+    #[error("{0}: missing required parameter")]
+    MissingRequired(String),
+    #[error("{param}: {reason}")]
+    InvalidValue { param: String, reason: String },
+    #[error("unknown tool")]
+    UnknownTool,
+}
+
+/// Validates tool call arguments against the prompt target parameters and coerces them to the
+/// declared types, e.g. `"3"` for an int parameter becomes `3`. Missing parameters get their
+/// default. Returns the coerced arguments, or every problem found.
+pub fn validate_tool_params(
+    arguments: &str,
+    prompt_target_params: &[Parameter],
+) -> Result<String, Vec<ToolParamError>> {
+    let mut tool_params: serde_json::Map<String, serde_json::Value> =
+        match serde_json::from_str(arguments) {
+            Ok(serde_json::Value::Object(tool_params)) => tool_params,
+            Ok(serde_json::Value::Null) => serde_json::Map::new(),
+            Ok(_) | Err(_) => {
+                return Err(vec![ToolParamError::InvalidArguments(
+                    arguments.to_string(),
+                )])
+            }
+        };
+
+    let mut errors = Vec::new();
+    for param in prompt_target_params {
+        let parameter_type = ParameterType::from(
+            param
+                .parameter_type
+                .clone()
+                .unwrap_or_else(|| "str".to_string()),
+        );
+
+        let value = match tool_params.get(&param.name) {
+            Some(value) if !value.is_null() => value.clone(),
+            _ => match param.default.as_ref() {
+                Some(default) => serde_json::Value::String(default.clone()),
+                None => {
+                    tool_params.remove(&param.name);
+                    if param.required.unwrap_or(false) {
+                        errors.push(ToolParamError::MissingRequired(param.name.clone()));
+                    }
+                    continue;
+                }
+            },
+        };
+
+        let value = match coerce_param(&value, &parameter_type) {
+            Some(value) => value,
+            None => {
+                errors.push(ToolParamError::InvalidValue {
+                    param: param.name.clone(),
+                    reason: format!("expected {:?}, got {}", parameter_type, value),
+                });
+                continue;
+            }
+        };
+
+        if let Some(enum_values) = param.enum_values.as_ref() {
+            let value_str = match &value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            if !enum_values.contains(&value_str) {
+                errors.push(ToolParamError::InvalidValue {
+                    param: param.name.clone(),
+                    reason: format!("{} is not one of {:?}", value_str, enum_values),
+                });
+                continue;
+            }
+        }
+
+        tool_params.insert(param.name.clone(), value);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(serde_json::Value::Object(tool_params).to_string())
+}
+
+fn coerce_param(
+    value: &serde_json::Value,
+    parameter_type: &ParameterType,
+) -> Option<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    match (parameter_type, value) {
+        (ParameterType::Int, Json::Number(n)) if n.is_i64() || n.is_u64() => Some(value.clone()),
+        (ParameterType::Int, Json::Number(n)) => n
+            .as_f64()
+            .filter(|f| f.fract() == 0.0)
+            .map(|f| Json::from(f as i64)),
+        (ParameterType::Int, Json::String(s)) => s.trim().parse::<i64>().ok().map(Json::from),
+        (ParameterType::Float, Json::Number(_)) => Some(value.clone()),
+        (ParameterType::Float, Json::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Json::Number),
+        (ParameterType::Bool, Json::Bool(_)) => Some(value.clone()),
+        (ParameterType::Bool, Json::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" => Some(Json::Bool(true)),
+            "false" => Some(Json::Bool(false)),
+            _ => None,
+        },
+        (ParameterType::String, Json::String(_)) => Some(value.clone()),
+        (ParameterType::String, Json::Number(_) | Json::Bool(_)) => {
+            Some(Json::String(value.to_string()))
+        }
+        (ParameterType::List, Json::Array(_)) => Some(value.clone()),
+        (ParameterType::Dict, Json::Object(_)) => Some(value.clone()),
+        // lists and dicts sometimes come back json encoded in a string
+        (ParameterType::List | ParameterType::Dict, Json::String(s)) => {
+            match serde_json::from_str::<Json>(s) {
+                Ok(Json::Array(list)) if *parameter_type == ParameterType::List => {
+                    Some(Json::Array(list))
+                }
+                Ok(Json::Object(dict)) if *parameter_type == ParameterType::Dict => {
+                    Some(Json::Object(dict))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use common::configuration::{HttpMethod, Parameter};
@@ -159,4 +295,68 @@ mod test {
         );
         assert_eq!(body, None);
     }
+
+    #[test]
+    fn test_validate_tool_params_coerces_types() {
+        let params: Vec<Parameter> = serde_yaml::from_str(
+            r#"
+- name: days
+  type: int
+  description: number of days
+  required: true
+- name: unit
+  type: str
+  description: unit
+  enum: [celsius, fahrenheit]
+  default: fahrenheit
+- name: detailed
+  type: bool
+  description: detailed forecast
+"#,
+        )
+        .unwrap();
+
+        let arguments =
+            super::validate_tool_params(r#"{"days": "3", "detailed": "True"}"#, &params).unwrap();
+        let arguments: serde_json::Value = serde_json::from_str(&arguments).unwrap();
+        assert_eq!(
+            arguments,
+            serde_json::json!({"days": 3, "detailed": true, "unit": "fahrenheit"})
+        );
+    }
+
+    #[test]
+    fn test_validate_tool_params_reports_errors() {
+        let params: Vec<Parameter> = serde_yaml::from_str(
+            r#"
+- name: days
+  type: int
+  description: number of days
+- name: city
+  type: str
+  description: city
+  required: true
+- name: unit
+  type: str
+  description: unit
+  enum: [celsius, fahrenheit]
+"#,
+        )
+        .unwrap();
+
+        let errors = super::validate_tool_params(r#"{"days": "three", "unit": "kelvin"}"#, &params)
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].to_string().starts_with("days: expected Int"));
+        assert_eq!(
+            errors[1],
+            super::ToolParamError::MissingRequired("city".to_string())
+        );
+        assert_eq!(errors[1].to_string(), "city: missing required parameter");
+        assert!(errors[2]
+            .to_string()
+            .starts_with("unit: kelvin is not one of"));
+
+        assert!(super::validate_tool_params("[1, 2]", &params).is_err());
+    }
 }
@@ -0,0 +1,25 @@
+-- Agent Session Storage Table
+-- This table stores agent conversations keyed by the x-arch-session-id header
+-- Run this SQL against your PostgreSQL/Supabase database before using agent sessions with postgres state storage
+
+CREATE TABLE IF NOT EXISTS agent_sessions (
+    session_id TEXT PRIMARY KEY,
+    listener TEXT,
+    messages JSONB NOT NULL DEFAULT '[]'::jsonb,
+    summary TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Sessions created before rolling summaries were added
+ALTER TABLE agent_sessions ADD COLUMN IF NOT EXISTS summary TEXT;
+
+-- Expired sessions are filtered on updated_at
+CREATE INDEX IF NOT EXISTS idx_agent_sessions_updated_at
+    ON agent_sessions(updated_at);
+
+COMMENT ON TABLE agent_sessions IS 'Stores agent conversation history across requests';
+COMMENT ON COLUMN agent_sessions.session_id IS 'Session identifier sent in the x-arch-session-id header';
+COMMENT ON COLUMN agent_sessions.messages IS 'JSONB array of chat messages, oldest first';
+COMMENT ON COLUMN agent_sessions.summary IS 'Rolling summary of the messages dropped from the start of the conversation';
+COMMENT ON COLUMN agent_sessions.updated_at IS 'Unix timestamp (seconds) of the last turn, used for TTL expiry';